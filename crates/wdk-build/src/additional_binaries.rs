@@ -0,0 +1,280 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Companion binaries declared in the
+//! `metadata.wdk.package.additional-binaries` section of a driver's
+//! `Cargo.toml`
+//!
+//! Many drivers ship with a user-mode companion (ex. a control service or a
+//! configuration tool) that is built from a sibling `std` crate in the same
+//! workspace. Declaring it as an additional binary builds its executables
+//! alongside the driver and copies them into the driver package:
+//!
+//! ```toml
+//! [package.metadata.wdk.package.additional-binaries]
+//! sample-control-service = {}                   # all binary targets
+//! sample-tools = { bins = ["sample-configure"] } # only the listed targets
+//! ```
+//!
+//! Every copied executable must be installed by a `CopyFiles` directive of the
+//! driver's INF, so that it is covered by the driver package's catalog file.
+
+use std::{collections::BTreeMap, path::PathBuf, process::ExitStatus};
+
+use cargo_metadata::{Metadata, Package};
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Key of the section in `metadata.wdk` that only configures packaging of the
+/// package it is declared in
+pub const PACKAGE_METADATA_KEY: &str = "package";
+
+const ADDITIONAL_BINARIES_METADATA_KEY: &str = "additional-binaries";
+
+/// Errors that could result from building and packaging additional binaries
+#[derive(Debug, Error)]
+pub enum AdditionalBinariesError {
+    /// Error returned when the `metadata.wdk.package.additional-binaries`
+    /// section of a package fails to be deserialized
+    #[error(
+        "failed to deserialize metadata.wdk.package.additional-binaries for {package_name} package"
+    )]
+    MetadataDeserialization {
+        /// Name of the package whose metadata failed to be deserialized
+        package_name: String,
+        /// [`serde_json::Error`] that caused the deserialization to fail
+        #[source]
+        error_source: serde_json::Error,
+    },
+
+    /// Error returned when an additional binary refers to a package that is
+    /// not in the workspace
+    #[error("additional binaries package `{package}` is not a member of the workspace")]
+    UnknownPackage {
+        /// Name of the unknown package
+        package: String,
+    },
+
+    /// Error returned when an additional binary refers to a binary target that
+    /// does not exist in its package
+    #[error(
+        "package `{package}` has no binary target named `{bin}`. Valid binary targets are: \
+         {valid_bins}"
+    )]
+    UnknownBinaryTarget {
+        /// Name of the package
+        package: String,
+        /// Name of the unknown binary target
+        bin: String,
+        /// Comma-separated list of the binary targets of the package
+        valid_bins: String,
+    },
+
+    /// Error returned when `cargo build` fails to build the additional
+    /// binaries
+    #[error("`cargo build` failed to build the additional binaries with {exit_status}")]
+    BuildFailed {
+        /// Exit status of `cargo build`
+        exit_status: ExitStatus,
+    },
+
+    /// Error returned when `cargo build` does not report the executable of a
+    /// binary target that it built
+    #[error("`cargo build` did not produce an executable for binary target `{bin}`")]
+    MissingExecutable {
+        /// Name of the binary target
+        bin: String,
+    },
+
+    /// Error returned when an additional binary is not installed by any
+    /// `CopyFiles` directive of the driver's INF
+    #[error(
+        "{file_name} is copied into the driver package, but is not installed by any CopyFiles \
+         directive in {inf_path}. Add it to a file-list section referenced by a CopyFiles \
+         directive (or to `copy-files` in metadata.wdk.inf)"
+    )]
+    MissingCopyFilesEntry {
+        /// File name of the additional binary
+        file_name: String,
+        /// Path of the INF file
+        inf_path: PathBuf,
+    },
+}
+
+/// Binary targets of a package to build and copy into the driver package
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct AdditionalBinary {
+    /// Names of the binary targets. When `None`, all binary targets of the
+    /// package are included
+    #[serde(default)]
+    pub bins: Option<Vec<String>>,
+}
+
+/// Metadata specified in the `metadata.wdk.package.additional-binaries`
+/// section of a driver's `Cargo.toml`, keyed by package name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct AdditionalBinariesMetadata {
+    /// Additional binaries, keyed by the name of the package they are built
+    /// from
+    pub packages: BTreeMap<String, AdditionalBinary>,
+}
+
+impl AdditionalBinariesMetadata {
+    /// Parses the `metadata.wdk.package.additional-binaries` section of
+    /// `package`. Packages without this section have no additional binaries.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an
+    /// [`AdditionalBinariesError::MetadataDeserialization`] if the section is
+    /// invalid
+    pub fn try_from_package(package: &Package) -> Result<Self, AdditionalBinariesError> {
+        match &package.metadata["wdk"][PACKAGE_METADATA_KEY][ADDITIONAL_BINARIES_METADATA_KEY] {
+            serde_json::Value::Null => Ok(Self::default()),
+            additional_binaries => Self::deserialize(additional_binaries).map_err(|error_source| {
+                AdditionalBinariesError::MetadataDeserialization {
+                    package_name: package.name.clone(),
+                    error_source,
+                }
+            }),
+        }
+    }
+
+    /// Returns whether no additional binaries are declared
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty()
+    }
+
+    /// Resolves the declared additional binaries into the names of the binary
+    /// targets to build, for each package, in the workspace described by
+    /// `cargo_metadata`
+    ///
+    /// # Errors
+    ///
+    /// This function returns:
+    /// - [`AdditionalBinariesError::UnknownPackage`] if a declared package is
+    ///   not a member of the workspace
+    /// - [`AdditionalBinariesError::UnknownBinaryTarget`] if a declared binary
+    ///   target does not exist in its package
+    pub fn resolve(
+        &self,
+        cargo_metadata: &Metadata,
+    ) -> Result<BTreeMap<String, Vec<String>>, AdditionalBinariesError> {
+        self.packages
+            .iter()
+            .map(|(package_name, additional_binary)| {
+                let package = cargo_metadata
+                    .workspace_packages()
+                    .into_iter()
+                    .find(|package| &package.name == package_name)
+                    .ok_or_else(|| AdditionalBinariesError::UnknownPackage {
+                        package: package_name.clone(),
+                    })?;
+                let package_bins = package
+                    .targets
+                    .iter()
+                    .filter(|target| target.is_bin())
+                    .map(|target| target.name.clone())
+                    .collect::<Vec<_>>();
+
+                let bins = match &additional_binary.bins {
+                    None => package_bins,
+                    Some(bins) => {
+                        if let Some(bin) = bins.iter().find(|bin| !package_bins.contains(bin)) {
+                            return Err(AdditionalBinariesError::UnknownBinaryTarget {
+                                package: package_name.clone(),
+                                bin: bin.clone(),
+                                valid_bins: package_bins.join(", "),
+                            });
+                        }
+                        bins.clone()
+                    }
+                };
+                Ok((package_name.clone(), bins))
+            })
+            .collect()
+    }
+}
+
+/// Checks that every file in `file_names` is installed by a `CopyFiles`
+/// directive of the INF at `inf_path`, whose contents are `inf_contents`.
+/// File names are compared case-insensitively.
+///
+/// # Errors
+///
+/// This function returns an [`AdditionalBinariesError::MissingCopyFilesEntry`]
+/// for the first file that is not installed by the INF
+pub fn validate_copy_files_entries<'a>(
+    inf_path: PathBuf,
+    inf_contents: &str,
+    file_names: impl IntoIterator<Item = &'a str>,
+) -> Result<(), AdditionalBinariesError> {
+    let copy_files_entries = crate::inf::copy_files_entries(inf_contents);
+    for file_name in file_names {
+        if !copy_files_entries
+            .iter()
+            .any(|entry| entry.eq_ignore_ascii_case(file_name))
+        {
+            return Err(AdditionalBinariesError::MissingCopyFilesEntry {
+                file_name: file_name.to_string(),
+                inf_path,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INF_PATH: &str = "sample_driver.inf";
+
+    #[test]
+    fn deserialize_additional_binaries_metadata() {
+        let additional_binaries = AdditionalBinariesMetadata::deserialize(serde_json::json!({
+            "sample-control-service": {},
+            "sample-tools": { "bins": ["sample-configure"] },
+        }))
+        .unwrap();
+
+        assert_eq!(
+            additional_binaries.packages,
+            BTreeMap::from([
+                (
+                    "sample-control-service".to_string(),
+                    AdditionalBinary { bins: None }
+                ),
+                (
+                    "sample-tools".to_string(),
+                    AdditionalBinary {
+                        bins: Some(vec!["sample-configure".to_string()])
+                    }
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn deserialize_additional_binaries_metadata_rejects_unknown_fields() {
+        assert!(AdditionalBinariesMetadata::deserialize(serde_json::json!({
+            "sample-control-service": { "bin": "sample-control-service" },
+        }))
+        .is_err());
+    }
+
+    #[test]
+    fn copy_files_entries_are_validated() {
+        let inf =
+            "[SampleInstall.NT]\nCopyFiles=Service_Dir\n\n[Service_Dir]\nSample_Service.exe\n";
+
+        assert!(validate_copy_files_entries(INF_PATH.into(), inf, ["sample_service.exe"]).is_ok());
+        assert!(matches!(
+            validate_copy_files_entries(INF_PATH.into(), inf, ["sample_service.exe", "sample_configure.exe"]),
+            Err(AdditionalBinariesError::MissingCopyFilesEntry { file_name, .. }) if file_name == "sample_configure.exe"
+        ));
+    }
+}
@@ -0,0 +1,267 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Additional driver binaries declared in the
+//! `metadata.wdk.package.additional-drivers` section of a driver's
+//! `Cargo.toml`
+//!
+//! Some driver packages ship more than one driver binary, for example a
+//! primary driver and an export driver (a kernel-mode DLL that the primary
+//! driver imports functions from). Each driver binary is built from its own
+//! `cdylib` crate in the same workspace, with its own `package.metadata.wdk`
+//! section and build script, so that its binary is configured for the WDK like
+//! any other driver. Declaring it as an additional driver of the primary driver
+//! builds it alongside the primary driver and copies it into the primary
+//! driver's package, instead of packaging it on its own:
+//!
+//! ```toml
+//! [package.metadata.wdk.package]
+//! additional-drivers = ["sample-export-driver"]
+//! ```
+//!
+//! Every copied driver binary must be installed by a `CopyFiles` directive of
+//! the primary driver's INF, so that it is covered by the driver package's
+//! catalog file.
+
+use std::{
+    path::{Path, PathBuf},
+    process::ExitStatus,
+};
+
+use cargo_metadata::{Metadata, Package};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::additional_binaries::PACKAGE_METADATA_KEY;
+
+const ADDITIONAL_DRIVERS_METADATA_KEY: &str = "additional-drivers";
+
+/// Errors that could result from building and packaging additional drivers
+#[derive(Debug, Error)]
+pub enum AdditionalDriversError {
+    /// Error returned when the `metadata.wdk.package.additional-drivers`
+    /// section of a package fails to be deserialized
+    #[error(
+        "failed to deserialize metadata.wdk.package.additional-drivers for {package_name} package"
+    )]
+    MetadataDeserialization {
+        /// Name of the package whose metadata failed to be deserialized
+        package_name: String,
+        /// [`serde_json::Error`] that caused the deserialization to fail
+        #[source]
+        error_source: serde_json::Error,
+    },
+
+    /// Error returned when an additional driver refers to a package that is
+    /// not in the workspace
+    #[error("additional driver package `{package}` is not a member of the workspace")]
+    UnknownPackage {
+        /// Name of the unknown package
+        package: String,
+    },
+
+    /// Error returned when an additional driver refers to a package that is
+    /// not a driver (i.e. a `cdylib` with a `package.metadata.wdk` section)
+    #[error(
+        "additional driver package `{package}` is not a driver. Driver packages must have a \
+         cdylib target and a package.metadata.wdk section"
+    )]
+    NotADriver {
+        /// Name of the package
+        package: String,
+    },
+
+    /// Error returned when an additional driver is also the package it is
+    /// declared in
+    #[error("package `{package}` cannot be an additional driver of itself")]
+    SelfReference {
+        /// Name of the package
+        package: String,
+    },
+
+    /// Error returned when `cargo build` fails to build the additional drivers
+    #[error("`cargo build` failed to build the additional drivers with {exit_status}")]
+    BuildFailed {
+        /// Exit status of `cargo build`
+        exit_status: ExitStatus,
+    },
+
+    /// Error returned when `cargo build` does not report the binary of an
+    /// additional driver that it built
+    #[error("`cargo build` did not produce a driver binary for package `{package}`")]
+    MissingDriverBinary {
+        /// Name of the package
+        package: String,
+    },
+}
+
+/// Metadata specified in the `metadata.wdk.package.additional-drivers`
+/// section of a driver's `Cargo.toml`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct AdditionalDriversMetadata {
+    /// Names of the packages that the additional drivers are built from
+    pub packages: Vec<String>,
+}
+
+/// A resolved additional driver
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdditionalDriver {
+    /// Name of the package that the driver is built from
+    pub package: String,
+    /// File stem of the driver binary (i.e. the name of the package's `cdylib`
+    /// target, with `-` replaced by `_`)
+    pub file_stem: String,
+}
+
+impl AdditionalDriversMetadata {
+    /// Parses the `metadata.wdk.package.additional-drivers` section of
+    /// `package`. Packages without this section have no additional drivers.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an
+    /// [`AdditionalDriversError::MetadataDeserialization`] if the section is
+    /// invalid
+    pub fn try_from_package(package: &Package) -> Result<Self, AdditionalDriversError> {
+        match &package.metadata["wdk"][PACKAGE_METADATA_KEY][ADDITIONAL_DRIVERS_METADATA_KEY] {
+            serde_json::Value::Null => Ok(Self::default()),
+            additional_drivers => Self::deserialize(additional_drivers).map_err(|error_source| {
+                AdditionalDriversError::MetadataDeserialization {
+                    package_name: package.name.clone(),
+                    error_source,
+                }
+            }),
+        }
+    }
+
+    /// Returns whether no additional drivers are declared
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.packages.is_empty()
+    }
+
+    /// Resolves the declared additional drivers of `package_name` in the
+    /// workspace described by `cargo_metadata`
+    ///
+    /// # Errors
+    ///
+    /// This function returns:
+    /// - [`AdditionalDriversError::UnknownPackage`] if a declared package is
+    ///   not a member of the workspace
+    /// - [`AdditionalDriversError::NotADriver`] if a declared package is not a
+    ///   driver
+    /// - [`AdditionalDriversError::SelfReference`] if `package_name` declares
+    ///   itself as an additional driver
+    pub fn resolve(
+        &self,
+        package_name: &str,
+        cargo_metadata: &Metadata,
+    ) -> Result<Vec<AdditionalDriver>, AdditionalDriversError> {
+        self.packages
+            .iter()
+            .map(|driver_package_name| {
+                if driver_package_name == package_name {
+                    return Err(AdditionalDriversError::SelfReference {
+                        package: driver_package_name.clone(),
+                    });
+                }
+                let package = cargo_metadata
+                    .workspace_packages()
+                    .into_iter()
+                    .find(|package| &package.name == driver_package_name)
+                    .ok_or_else(|| AdditionalDriversError::UnknownPackage {
+                        package: driver_package_name.clone(),
+                    })?;
+                let cdylib_target = package
+                    .targets
+                    .iter()
+                    .find(|target| target.kind.iter().any(|kind| kind == "cdylib"))
+                    .filter(|_| !package.metadata["wdk"].is_null())
+                    .ok_or_else(|| AdditionalDriversError::NotADriver {
+                        package: driver_package_name.clone(),
+                    })?;
+                Ok(AdditionalDriver {
+                    package: driver_package_name.clone(),
+                    file_stem: cdylib_target.name.replace('-', "_"),
+                })
+            })
+            .collect()
+    }
+}
+
+impl AdditionalDriver {
+    /// Returns the file name of the driver binary in the driver package, with
+    /// the `extension` of the driver model (ex. `sample_export_driver.sys`)
+    #[must_use]
+    pub fn file_name(&self, extension: &str) -> String {
+        format!("{}.{extension}", self.file_stem)
+    }
+
+    /// Returns the path of the driver binary, as it is built by cargo, in
+    /// `directory`
+    #[must_use]
+    pub fn built_binary_path(&self, directory: &Path) -> PathBuf {
+        directory.join(format!("{}.dll", self.file_stem))
+    }
+}
+
+/// Returns whether `package_name` is declared as an additional driver by any
+/// package in the workspace described by `cargo_metadata`
+///
+/// Additional drivers are packaged by the package that declares them instead
+/// of on their own. Packages with invalid
+/// `metadata.wdk.package.additional-drivers` sections are ignored.
+#[must_use]
+pub fn is_additional_driver(package_name: &str, cargo_metadata: &Metadata) -> bool {
+    cargo_metadata
+        .workspace_packages()
+        .into_iter()
+        .any(|package| {
+            AdditionalDriversMetadata::try_from_package(package).is_ok_and(|additional_drivers| {
+                additional_drivers
+                    .packages
+                    .iter()
+                    .any(|driver_package_name| driver_package_name == package_name)
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_additional_drivers_metadata() {
+        let additional_drivers =
+            AdditionalDriversMetadata::deserialize(serde_json::json!(["sample-export-driver"]))
+                .unwrap();
+
+        assert_eq!(additional_drivers.packages, ["sample-export-driver"]);
+    }
+
+    #[test]
+    fn deserialize_additional_drivers_metadata_rejects_tables() {
+        assert!(AdditionalDriversMetadata::deserialize(serde_json::json!({
+            "sample-export-driver": {},
+        }))
+        .is_err());
+    }
+
+    #[test]
+    fn driver_binary_file_names() {
+        let additional_driver = AdditionalDriver {
+            package: "sample-export-driver".to_string(),
+            file_stem: "sample_export_driver".to_string(),
+        };
+
+        assert_eq!(
+            additional_driver.file_name("sys"),
+            "sample_export_driver.sys"
+        );
+        assert_eq!(
+            additional_driver.built_binary_path(Path::new("target")),
+            Path::new("target").join("sample_export_driver.dll")
+        );
+    }
+}
@@ -8,7 +8,22 @@
     Builder,
 };
 
-use crate::{Config, ConfigError};
+use crate::{BindgenConfig, Config, ConfigError};
+
+/// Items blocked from the bindings generated by [`BuilderExt::wdk_default`],
+/// unless they are allowlisted in [`Config::bindgen`]
+const DEFAULT_BLOCKLISTED_ITEMS: &[&str] = &[
+    "ExAllocatePoolWithTag",         // Deprecated
+    "ExAllocatePoolWithQuotaTag",    // Deprecated
+    "ExAllocatePoolWithTagPriority", // Deprecated
+    "ExAllocatePool",                // Deprecated
+    // FIXME: bitfield generated with non-1byte alignment in _MCG_CAP
+    ".*MCG_CAP(?:__bindgen.*)?",
+    ".*WHEA_XPF_MCA_SECTION",
+    ".*WHEA_ARM_BUS_ERROR(?:__bindgen.*)?",
+    ".*WHEA_ARM_PROCESSOR_ERROR",
+    ".*WHEA_ARM_CACHE_ERROR",
+];
 
 /// An extension trait that provides a way to create a [`bindgen::Builder`]
 /// configured for generating bindings to the wdk
@@ -67,18 +82,8 @@ fn wdk_default(config: impl Borrow<Config>) -> Result<Self, ConfigError> {
                     })
                     .chain(Config::wdk_bindgen_compiler_flags()),
             )
-            .blocklist_item("ExAllocatePoolWithTag") // Deprecated
-            .blocklist_item("ExAllocatePoolWithQuotaTag") // Deprecated
-            .blocklist_item("ExAllocatePoolWithTagPriority") // Deprecated
-            .blocklist_item("ExAllocatePool") // Deprecated
             .opaque_type("_KGDTENTRY64") // No definition in WDK
             .opaque_type("_KIDTENTRY64") // No definition in WDK
-            // FIXME: bitfield generated with non-1byte alignment in _MCG_CAP
-            .blocklist_item(".*MCG_CAP(?:__bindgen.*)?")
-            .blocklist_item(".*WHEA_XPF_MCA_SECTION")
-            .blocklist_item(".*WHEA_ARM_BUS_ERROR(?:__bindgen.*)?")
-            .blocklist_item(".*WHEA_ARM_PROCESSOR_ERROR")
-            .blocklist_item(".*WHEA_ARM_CACHE_ERROR")
             // FIXME: arrays with more than 32 entries currently fail to generate a `Default`` impl: https://github.com/rust-lang/rust-bindgen/issues/2803
             .no_default(".*tagMONITORINFOEXA")
             .must_use_type("NTSTATUS")
@@ -90,7 +95,9 @@ fn wdk_default(config: impl Borrow<Config>) -> Result<Self, ConfigError> {
             .parse_callbacks(Box::new(WdkCallbacks::new(config)))
             .formatter(bindgen::Formatter::Prettyplease);
 
-        Ok(builder)
+        Ok(blocklisted_items(&config.bindgen)?
+            .into_iter()
+            .fold(builder, Self::blocklist_item))
     }
 }
 
@@ -120,3 +127,66 @@ fn new(config: &Config) -> Self {
         }
     }
 }
+
+/// Returns the items to block from the generated bindings: the
+/// [`DEFAULT_BLOCKLISTED_ITEMS`] that are not allowlisted in `bindgen_config`,
+/// followed by the items blocklisted in `bindgen_config`
+fn blocklisted_items(bindgen_config: &BindgenConfig) -> Result<Vec<String>, ConfigError> {
+    if let Some(item) = bindgen_config
+        .allowlist
+        .iter()
+        .find(|item| !DEFAULT_BLOCKLISTED_ITEMS.contains(&item.as_str()))
+    {
+        return Err(ConfigError::UnknownAllowlistedBindgenItem {
+            item: item.clone(),
+            blocklisted_items: DEFAULT_BLOCKLISTED_ITEMS.join(", "),
+        });
+    }
+
+    Ok(DEFAULT_BLOCKLISTED_ITEMS
+        .iter()
+        .filter(|item| {
+            !bindgen_config
+                .allowlist
+                .iter()
+                .any(|allowed| allowed == *item)
+        })
+        .map(ToString::to_string)
+        .chain(bindgen_config.blocklist.iter().cloned())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocklisted_items_apply_bindgen_config() {
+        let bindgen_config = BindgenConfig {
+            blocklist: vec![".*_SOME_BROKEN_STRUCT".to_string()],
+            allowlist: vec![".*WHEA_XPF_MCA_SECTION".to_string()],
+        };
+        let items = blocklisted_items(&bindgen_config).unwrap();
+
+        assert_eq!(items.len(), DEFAULT_BLOCKLISTED_ITEMS.len());
+        assert!(!items.contains(&".*WHEA_XPF_MCA_SECTION".to_string()));
+        assert_eq!(items.last().unwrap(), ".*_SOME_BROKEN_STRUCT");
+        assert_eq!(
+            blocklisted_items(&BindgenConfig::default()).unwrap(),
+            DEFAULT_BLOCKLISTED_ITEMS
+        );
+    }
+
+    #[test]
+    fn unknown_allowlisted_item_is_rejected() {
+        let bindgen_config = BindgenConfig {
+            allowlist: vec!["NotBlocked".to_string()],
+            ..BindgenConfig::default()
+        };
+
+        assert!(matches!(
+            blocklisted_items(&bindgen_config),
+            Err(ConfigError::UnknownAllowlistedBindgenItem { item, .. }) if item == "NotBlocked"
+        ));
+    }
+}
@@ -0,0 +1,253 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Parallel generation of the bindings of independent API subsets
+//!
+//! The build time of `wdk-sys` is dominated by the `bindgen` invocations that
+//! generate the bindings of each [`ApiSubset`](crate::ApiSubset). These are
+//! independent of each other, so a [`GenerationCoordinator`] runs them on a
+//! pool of worker threads, bounded by the number of cores available to the
+//! build script, and collects their outcomes in the order they were added.
+//!
+//! When the `WDK_BUILD_BINDGEN_TIMINGS` environment variable is set to `true`,
+//! the time taken by each generation job is reported as a Cargo warning, so
+//! that it is visible in the output of `cargo build`. The timings are always
+//! emitted as `tracing` events.
+
+use std::{
+    collections::VecDeque,
+    env,
+    num::NonZeroUsize,
+    panic,
+    sync::{Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+use tracing::{info, info_span, Span};
+
+/// Environment variable that enables reporting the time taken by each
+/// generation job as a Cargo warning when set to `true`
+pub const WDK_BUILD_BINDGEN_TIMINGS_ENV_VAR: &str = "WDK_BUILD_BINDGEN_TIMINGS";
+
+/// Error returned when a generation job of a [`GenerationCoordinator`] fails
+#[derive(Debug, Error)]
+#[error("{job_name} generation failed")]
+pub struct GenerationError<E> {
+    /// Name of the job that failed
+    pub job_name: String,
+    /// Error returned by the job
+    #[source]
+    pub source: E,
+}
+
+/// Time taken by a generation job of a [`GenerationCoordinator`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenerationTiming {
+    /// Name of the job
+    pub job_name: String,
+    /// Time taken by the job
+    pub duration: Duration,
+}
+
+/// A generation job added to a [`GenerationCoordinator`]
+type GenerationJob<'scope, E> = Box<dyn FnOnce() -> Result<(), E> + Send + 'scope>;
+
+/// Time taken by a generation job and its result, once it has completed
+type GenerationOutcome<E> = Option<(GenerationTiming, Result<(), E>)>;
+
+/// Runs independent generation jobs on a bounded pool of worker threads
+pub struct GenerationCoordinator<'scope, E> {
+    jobs: Vec<(String, GenerationJob<'scope, E>)>,
+    max_threads: NonZeroUsize,
+}
+
+impl<'scope, E: std::error::Error + Send + 'static> GenerationCoordinator<'scope, E> {
+    /// Creates a [`GenerationCoordinator`] whose number of worker threads is
+    /// bounded by the number of cores available to the build script
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            jobs: Vec::new(),
+            max_threads: thread::available_parallelism().unwrap_or(NonZeroUsize::MIN),
+        }
+    }
+
+    /// Sets the maximum number of worker threads
+    #[must_use]
+    pub const fn max_threads(mut self, max_threads: NonZeroUsize) -> Self {
+        self.max_threads = max_threads;
+        self
+    }
+
+    /// Adds a generation job named `job_name` (ex. the name of the generated
+    /// file)
+    pub fn add_job(
+        &mut self,
+        job_name: impl Into<String>,
+        job: impl FnOnce() -> Result<(), E> + Send + 'scope,
+    ) {
+        self.jobs.push((job_name.into(), Box::new(job)));
+    }
+
+    /// Runs all the jobs to completion, and returns the time taken by each of
+    /// them, in the order they were added. Panics of the jobs are forwarded to
+    /// the calling thread.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if any of the jobs fails. When
+    /// multiple jobs fail, the error of the first of them to be added is
+    /// returned.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if a worker thread fails to be spawned, or if
+    /// any of the jobs panics
+    pub fn run(self) -> Result<Vec<GenerationTiming>, GenerationError<E>> {
+        let job_count = self.jobs.len();
+        // Jobs are queued with their index, so that their outcomes are collected in
+        // the order they were added
+        let queue: Mutex<VecDeque<(usize, String, GenerationJob<'scope, E>)>> = Mutex::new(
+            self.jobs
+                .into_iter()
+                .enumerate()
+                .map(|(index, (job_name, job))| (index, job_name, job))
+                .collect(),
+        );
+        let outcomes: Mutex<Vec<GenerationOutcome<E>>> =
+            Mutex::new((0..job_count).map(|_| None).collect());
+        let worker_count = self.max_threads.get().min(job_count);
+        let current_span = Span::current();
+
+        thread::scope(|thread_scope| {
+            let worker_join_handles = (0..worker_count)
+                .map(|worker_index| {
+                    let (queue, outcomes, current_span) = (&queue, &outcomes, &current_span);
+                    thread::Builder::new()
+                        .name(format!("bindgen generation worker {worker_index}"))
+                        .spawn_scoped(thread_scope, move || loop {
+                            let Some((index, job_name, job)) = queue
+                                .lock()
+                                .unwrap_or_else(PoisonError::into_inner)
+                                .pop_front()
+                            else {
+                                break;
+                            };
+
+                            let start = Instant::now();
+                            // Parent span must be manually set since spans do not persist across thread boundaries: https://github.com/tokio-rs/tracing/issues/1391
+                            let result = info_span!(parent: current_span, "worker thread", generated_file_name = job_name.as_str()).in_scope(job);
+                            let timing = GenerationTiming {
+                                job_name,
+                                duration: start.elapsed(),
+                            };
+                            outcomes.lock().unwrap_or_else(PoisonError::into_inner)[index] =
+                                Some((timing, result));
+                        })
+                        .expect("Scoped Thread should spawn successfully")
+                })
+                .collect::<Vec<_>>();
+
+            for join_handle in worker_join_handles {
+                // Forward panics to the calling thread
+                if let Err(panic_payload) = join_handle.join() {
+                    panic::resume_unwind(panic_payload);
+                }
+            }
+        });
+
+        let mut timings = Vec::with_capacity(job_count);
+        for (timing, result) in outcomes
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+            .into_iter()
+            .flatten()
+        {
+            if let Err(source) = result {
+                return Err(GenerationError {
+                    job_name: timing.job_name,
+                    source,
+                });
+            }
+            timings.push(timing);
+        }
+        report_timings(&timings);
+        Ok(timings)
+    }
+}
+
+impl<E: std::error::Error + Send + 'static> Default for GenerationCoordinator<'_, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reports the time taken by each generation job, as Cargo warnings when
+/// [`WDK_BUILD_BINDGEN_TIMINGS_ENV_VAR`] is set to `true`
+fn report_timings(timings: &[GenerationTiming]) {
+    println!("cargo::rerun-if-env-changed={WDK_BUILD_BINDGEN_TIMINGS_ENV_VAR}");
+    let is_reported =
+        env::var(WDK_BUILD_BINDGEN_TIMINGS_ENV_VAR).is_ok_and(|value| value == "true");
+
+    for GenerationTiming { job_name, duration } in timings {
+        info!("Generated {job_name} in {duration:.2?}");
+        if is_reported {
+            println!("cargo::warning={job_name} generated in {duration:.2?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+
+    #[test]
+    fn jobs_are_bounded_by_max_threads() {
+        let running_jobs = AtomicUsize::new(0);
+        let max_running_jobs = AtomicUsize::new(0);
+
+        let mut coordinator = GenerationCoordinator::<io::Error>::new()
+            .max_threads(NonZeroUsize::new(2).expect("2 should be non-zero"));
+        for job_index in 0..8 {
+            let (running_jobs, max_running_jobs) = (&running_jobs, &max_running_jobs);
+            coordinator.add_job(format!("job {job_index}"), move || {
+                let running = running_jobs.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running_jobs.fetch_max(running, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(10));
+                running_jobs.fetch_sub(1, Ordering::SeqCst);
+                Ok(())
+            });
+        }
+
+        let timings = coordinator.run().unwrap();
+        assert_eq!(
+            timings
+                .iter()
+                .map(|timing| timing.job_name.as_str())
+                .collect::<Vec<_>>(),
+            (0..8)
+                .map(|job_index| format!("job {job_index}"))
+                .collect::<Vec<_>>()
+        );
+        assert!(max_running_jobs.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[test]
+    fn first_failed_job_is_reported() {
+        let mut coordinator = GenerationCoordinator::new();
+        coordinator.add_job("types.rs", || Ok(()));
+        coordinator.add_job("wdf.rs", || Err(io::Error::other("wdf.rs failed")));
+        coordinator.add_job("hid.rs", || Err(io::Error::other("hid.rs failed")));
+
+        let error = coordinator.run().unwrap_err();
+        assert_eq!(error.job_name, "wdf.rs");
+        assert_eq!(error.source.to_string(), "wdf.rs failed");
+    }
+}
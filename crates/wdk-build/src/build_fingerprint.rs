@@ -0,0 +1,203 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Fingerprinting of the WDK and WDK configuration used to build a driver
+//!
+//! Build artifacts (ex. the `wdk-sys` bindings) depend on the installed WDK
+//! and on the WDK configuration in the driver's Cargo metadata. When either
+//! changes, cargo does not always rebuild the affected crates, which leaves
+//! stale artifacts in the target directory that cause confusing build errors.
+//! A [`BuildFingerprint`] is stored next to the build output so that such
+//! changes are detected before the next build.
+
+use std::{collections::BTreeMap, fmt, path::PathBuf, process::ExitStatus};
+
+use cargo_metadata::{DependencyKind, Metadata};
+use thiserror::Error;
+
+use crate::metadata;
+
+/// Name of the file that stores the [`BuildFingerprint`] of the build, in the
+/// WDK build output directory
+pub const BUILD_FINGERPRINT_FILE_NAME: &str = "wdk-build-fingerprint";
+
+const WDK_VERSION_FINGERPRINT_KEY: &str = "WDK_VERSION";
+const WDK_METADATA_FINGERPRINT_KEY_PREFIX: &str = "WDK_BUILD_METADATA";
+const WDK_BUILD_PACKAGE_NAME: &str = "wdk-build";
+
+/// Errors that could result from validating the [`BuildFingerprint`] of a
+/// build
+#[derive(Debug, Error)]
+pub enum BuildFingerprintError {
+    /// Error returned when the target directory contains artifacts built with a
+    /// different WDK or WDK configuration
+    #[error(
+        "the build artifacts in {output_directory} were built with a different WDK or WDK \
+         configuration (changed: {changed_entries:?}). Set the WDK_BUILD_AUTO_CLEAN environment \
+         variable to `true` (ex. `cargo make --env WDK_BUILD_AUTO_CLEAN=true`) to automatically \
+         clean the affected crates, or run `cargo clean`"
+    )]
+    StaleBuildArtifacts {
+        /// WDK build output directory containing the stale artifacts
+        output_directory: PathBuf,
+        /// Fingerprint entries that changed since the artifacts were built
+        changed_entries: Vec<String>,
+    },
+
+    /// Error returned when `cargo clean` fails to clean the affected crates
+    #[error(
+        "`cargo clean` failed to clean the crates affected by the WDK configuration change with \
+         {exit_status}"
+    )]
+    CleanFailed {
+        /// Exit status of `cargo clean`
+        exit_status: ExitStatus,
+    },
+}
+
+/// The WDK version and WDK configuration that a build uses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildFingerprint {
+    entries: BTreeMap<String, String>,
+}
+
+impl BuildFingerprint {
+    /// Creates the [`BuildFingerprint`] of a build using the WDK identified by
+    /// `wdk_version`, configured by `wdk_metadata`
+    ///
+    /// # Errors
+    ///
+    /// This function returns a [`metadata::Error`] if `wdk_metadata` fails to
+    /// be serialized
+    pub fn new(wdk_version: &str, wdk_metadata: &metadata::Wdk) -> Result<Self, metadata::Error> {
+        let mut entries = metadata::to_map_with_prefix::<BTreeMap<_, _>>(
+            WDK_METADATA_FINGERPRINT_KEY_PREFIX,
+            wdk_metadata,
+        )?;
+        entries.insert(
+            WDK_VERSION_FINGERPRINT_KEY.to_string(),
+            wdk_version.to_string(),
+        );
+        Ok(Self { entries })
+    }
+
+    /// Parses a [`BuildFingerprint`] from the contents of a fingerprint file.
+    /// Lines that are not `key=value` pairs are ignored.
+    #[must_use]
+    pub fn parse(contents: &str) -> Self {
+        Self {
+            entries: contents
+                .lines()
+                .filter_map(|line| line.split_once('='))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Returns the keys of the entries that differ between `self` and
+    /// `previous`, including entries that are only present in one of them
+    #[must_use]
+    pub fn changed_entries(&self, previous: &Self) -> Vec<String> {
+        let mut changed_entries = self
+            .entries
+            .iter()
+            .filter(|(key, value)| previous.entries.get(*key) != Some(value))
+            .map(|(key, _)| key.clone())
+            .chain(
+                previous
+                    .entries
+                    .keys()
+                    .filter(|key| !self.entries.contains_key(*key))
+                    .cloned(),
+            )
+            .collect::<Vec<_>>();
+        changed_entries.sort();
+        changed_entries
+    }
+}
+
+impl fmt::Display for BuildFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value) in &self.entries {
+            writeln!(f, "{key}={value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the package ID specifications (ex. `wdk-sys@0.3.0`) of the packages
+/// whose build depends on the WDK configuration, i.e. every package with a
+/// build dependency on `wdk-build`
+#[must_use]
+pub fn packages_affected_by_wdk_configuration(cargo_metadata: &Metadata) -> Vec<String> {
+    cargo_metadata
+        .packages
+        .iter()
+        .filter(|package| {
+            package.dependencies.iter().any(|dependency| {
+                dependency.name == WDK_BUILD_PACKAGE_NAME
+                    && dependency.kind == DependencyKind::Build
+            })
+        })
+        .map(|package| format!("{}@{}", package.name, package.version))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprint(entries: &[(&str, &str)]) -> BuildFingerprint {
+        BuildFingerprint {
+            entries: entries
+                .iter()
+                .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let build_fingerprint = fingerprint(&[
+            ("WDK_VERSION", "10.0.26100.0"),
+            ("WDK_BUILD_METADATA-DRIVER_MODEL-DRIVER_TYPE", "KMDF"),
+        ]);
+
+        assert_eq!(
+            BuildFingerprint::parse(&build_fingerprint.to_string()),
+            build_fingerprint
+        );
+    }
+
+    #[test]
+    fn unchanged() {
+        let build_fingerprint = fingerprint(&[("WDK_VERSION", "10.0.26100.0")]);
+
+        assert!(build_fingerprint
+            .changed_entries(&build_fingerprint.clone())
+            .is_empty());
+    }
+
+    #[test]
+    fn changed_entries() {
+        let previous = fingerprint(&[
+            ("WDK_VERSION", "10.0.22621.0"),
+            ("WDK_BUILD_METADATA-DRIVER_MODEL-DRIVER_TYPE", "KMDF"),
+            ("WDK_BUILD_METADATA-TARGET_OS", "Windows10"),
+        ]);
+        let current = fingerprint(&[
+            ("WDK_VERSION", "10.0.26100.0"),
+            ("WDK_BUILD_METADATA-DRIVER_MODEL-DRIVER_TYPE", "KMDF"),
+            ("WDK_BUILD_METADATA-SPECTRE_MITIGATION", "true"),
+        ]);
+
+        assert_eq!(
+            current.changed_entries(&previous),
+            [
+                "WDK_BUILD_METADATA-SPECTRE_MITIGATION",
+                "WDK_BUILD_METADATA-TARGET_OS",
+                "WDK_VERSION",
+            ]
+        );
+    }
+}
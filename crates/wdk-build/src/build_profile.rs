@@ -0,0 +1,455 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Validation of the Cargo profile that a driver is built with
+//!
+//! Kernel-mode drivers cannot unwind, so their profile must set
+//! `panic = "abort"`. Building them with `panic = "unwind"` otherwise fails
+//! late, with linker errors about missing unwinding symbols, so the profile is
+//! checked before anything else is built.
+//!
+//! Drivers can additionally declare requirements for the profiles they are
+//! built with in the `metadata.wdk.profile` section of their `Cargo.toml`:
+//!
+//! ```toml
+//! [package.metadata.wdk.profile]
+//! # Fail the build unless the profile enables thin LTO
+//! lto = "thin"
+//! # Fail the build unless the profile has an opt-level of at least 1
+//! minimum-opt-level = 1
+//! # Fail the build unless rustc emits Control Flow Guard checks, and link the
+//! # driver with /GUARD:CF
+//! control-flow-guard = true
+//! ```
+
+use std::{
+    env,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use cargo_metadata::Package;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::DriverConfig;
+
+/// Key of the section in `package.metadata.wdk` that declares the
+/// requirements of a driver for the profiles it is built with
+pub const PROFILE_METADATA_KEY: &str = "profile";
+
+/// Name of the directory of the `dev` profile in the target directory
+const DEV_PROFILE_DIRECTORY_NAME: &str = "debug";
+
+/// Errors that could result from validating the Cargo profile of a driver
+#[derive(Debug, Error)]
+pub enum BuildProfileError {
+    /// Error returned when the `metadata.wdk.profile` section of a package
+    /// fails to be deserialized
+    #[error("failed to deserialize metadata.wdk.profile for {package_name} package")]
+    MetadataDeserialization {
+        /// Name of the package whose metadata failed to be deserialized
+        package_name: String,
+        /// [`serde_json::Error`] that caused the deserialization to fail
+        #[source]
+        error_source: serde_json::Error,
+    },
+
+    /// Error returned when the workspace manifest, which declares the
+    /// profiles of the workspace, cannot be read
+    #[error("failed to read workspace manifest at {}", .path.display())]
+    ManifestRead {
+        /// Path of the workspace manifest
+        path: PathBuf,
+        /// [`std::io::Error`] that caused the read to fail
+        #[source]
+        error_source: std::io::Error,
+    },
+
+    /// Error returned when the workspace manifest cannot be parsed
+    #[error("failed to parse workspace manifest")]
+    ManifestParse {
+        /// [`toml::de::Error`] that caused the parsing to fail
+        #[source]
+        error_source: toml::de::Error,
+    },
+
+    /// Error returned when a kernel-mode driver is built with a profile that
+    /// unwinds on panic
+    #[error(
+        "the {profile} profile builds with panic = \"unwind\", which kernel-mode drivers do not \
+         support. The recommended solution is to add panic = \"abort\" to the [profile.{profile}] \
+         section of the workspace's Cargo.toml"
+    )]
+    PanicUnwindNotSupported {
+        /// Name of the profile
+        profile: String,
+    },
+
+    /// Error returned when a driver is built with a profile whose `opt-level`
+    /// is below the `minimum-opt-level` required by the driver
+    #[error(
+        "the {profile} profile builds with opt-level = {opt_level}, but metadata.wdk.profile \
+         requires an opt-level of at least {minimum_opt_level}"
+    )]
+    OptLevelTooLow {
+        /// Name of the profile
+        profile: String,
+        /// `opt-level` of the profile
+        opt_level: String,
+        /// Minimum `opt-level` required by the driver
+        minimum_opt_level: u8,
+    },
+
+    /// Error returned when a driver is built with a profile whose `lto`
+    /// differs from the one required by the driver
+    #[error(
+        "the {profile} profile builds with lto = \"{lto}\", but metadata.wdk.profile requires lto \
+         = \"{required_lto}\""
+    )]
+    LtoMismatch {
+        /// Name of the profile
+        profile: String,
+        /// LTO mode of the profile
+        lto: LtoMode,
+        /// LTO mode required by the driver
+        required_lto: LtoMode,
+    },
+}
+
+/// Link-time optimization mode of a Cargo profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LtoMode {
+    /// No LTO across crates (`lto = false` or `lto = "off"`)
+    Off,
+    /// Thin LTO across all crates (`lto = "thin"`)
+    Thin,
+    /// Fat LTO across all crates (`lto = true` or `lto = "fat"`)
+    Fat,
+}
+
+impl LtoMode {
+    /// Parses the `lto` setting of a Cargo profile, or returns `None` if it is
+    /// not a valid setting
+    fn from_profile_setting(value: &toml::Value) -> Option<Self> {
+        match value {
+            toml::Value::Boolean(false) => Some(Self::Off),
+            toml::Value::Boolean(true) => Some(Self::Fat),
+            toml::Value::String(value) => Self::from_profile_string(value),
+            _ => None,
+        }
+    }
+
+    /// Parses the `lto` setting of a Cargo profile, as written in a
+    /// `CARGO_PROFILE_<name>_LTO` environment variable
+    fn from_profile_string(value: &str) -> Option<Self> {
+        match value {
+            "false" | "off" => Some(Self::Off),
+            "thin" => Some(Self::Thin),
+            "true" | "fat" => Some(Self::Fat),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for LtoMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Off => "off",
+            Self::Thin => "thin",
+            Self::Fat => "fat",
+        })
+    }
+}
+
+/// Metadata specified in the `metadata.wdk.profile` section of a driver's
+/// `Cargo.toml`, which declares requirements for the profiles that the
+/// driver is built with
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BuildProfileConfig {
+    /// LTO mode that the profile must enable. Any LTO mode is accepted when
+    /// `None`
+    #[serde(default)]
+    pub lto: Option<LtoMode>,
+    /// Minimum `opt-level` of the profile. The size optimization levels `"s"`
+    /// and `"z"` count as `2`
+    #[serde(default)]
+    pub minimum_opt_level: Option<u8>,
+    /// Whether rustc must emit Control Flow Guard checks
+    /// (`-C control-flow-guard`), in which case the driver is also linked with
+    /// `/GUARD:CF`
+    #[serde(default)]
+    pub control_flow_guard: bool,
+}
+
+impl BuildProfileConfig {
+    /// Parses the `metadata.wdk.profile` section of `package`, or returns
+    /// `None` if the package does not declare it
+    ///
+    /// # Errors
+    ///
+    /// This function returns a [`BuildProfileError::MetadataDeserialization`]
+    /// if the section is invalid
+    pub fn try_from_package(package: &Package) -> Result<Option<Self>, BuildProfileError> {
+        match &package.metadata["wdk"][PROFILE_METADATA_KEY] {
+            serde_json::Value::Null => Ok(None),
+            profile => Self::deserialize(profile)
+                .map(Some)
+                .map_err(|error_source| BuildProfileError::MetadataDeserialization {
+                    package_name: package.name.clone(),
+                    error_source,
+                }),
+        }
+    }
+}
+
+/// The settings of the Cargo profile that a driver is built with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildProfile {
+    /// Name of the profile (ex. `dev` or `release`)
+    pub name: String,
+    /// Whether the profile unwinds on panic, instead of aborting
+    pub panic_unwind: bool,
+    /// `opt-level` of the profile (ex. `0`, `3` or `s`)
+    pub opt_level: String,
+    /// LTO mode of the profile
+    pub lto: LtoMode,
+}
+
+impl BuildProfile {
+    /// Returns the profile of the build script that is running, whose
+    /// workspace manifest is `workspace_manifest_path`. The `lto` of the
+    /// profile is read from the manifest, and can be overridden by a
+    /// `CARGO_PROFILE_<name>_LTO` environment variable.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if the workspace manifest cannot be read
+    /// or parsed
+    ///
+    /// # Panics
+    ///
+    /// Panics if invoked from outside a Cargo build script
+    pub fn from_build_script_env(
+        workspace_manifest_path: &Path,
+    ) -> Result<Self, BuildProfileError> {
+        let out_dir = env::var("OUT_DIR").expect("OUT_DIR should be set by Cargo");
+        let name =
+            profile_name_from_out_dir(Path::new(&out_dir)).unwrap_or_else(|| {
+                match env::var("PROFILE").as_deref() {
+                    Ok("release") => "release".to_string(),
+                    _ => "dev".to_string(),
+                }
+            });
+
+        let manifest_contents =
+            std::fs::read_to_string(workspace_manifest_path).map_err(|error_source| {
+                BuildProfileError::ManifestRead {
+                    path: workspace_manifest_path.to_path_buf(),
+                    error_source,
+                }
+            })?;
+        let manifest = manifest_contents
+            .parse::<toml::Table>()
+            .map_err(|error_source| BuildProfileError::ManifestParse { error_source })?;
+
+        let lto_env_var = format!(
+            "CARGO_PROFILE_{}_LTO",
+            name.to_uppercase().replace('-', "_")
+        );
+        println!("cargo::rerun-if-env-changed={lto_env_var}");
+        let lto = env::var(&lto_env_var)
+            .ok()
+            .and_then(|lto| LtoMode::from_profile_string(&lto))
+            .unwrap_or_else(|| profile_lto(&manifest, &name));
+
+        Ok(Self {
+            panic_unwind: env::var("CARGO_CFG_PANIC").as_deref() == Ok("unwind"),
+            opt_level: env::var("OPT_LEVEL").expect("OPT_LEVEL should be set by Cargo"),
+            lto,
+            name,
+        })
+    }
+
+    /// Checks that this profile can build a driver with `driver_config`, and
+    /// that it satisfies the requirements declared in `config`
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if:
+    /// * the profile unwinds on panic, and the driver is a kernel-mode driver
+    /// * the `opt-level` of the profile is below the
+    ///   [`BuildProfileConfig::minimum_opt_level`]
+    /// * the LTO mode of the profile differs from [`BuildProfileConfig::lto`]
+    pub fn validate(
+        &self,
+        driver_config: &DriverConfig,
+        config: Option<&BuildProfileConfig>,
+    ) -> Result<(), BuildProfileError> {
+        if self.panic_unwind && !matches!(driver_config, DriverConfig::Umdf(_)) {
+            return Err(BuildProfileError::PanicUnwindNotSupported {
+                profile: self.name.clone(),
+            });
+        }
+
+        let Some(config) = config else {
+            return Ok(());
+        };
+
+        if let Some(minimum_opt_level) = config.minimum_opt_level {
+            let opt_level = match self.opt_level.as_str() {
+                "s" | "z" => 2,
+                opt_level => opt_level.parse().unwrap_or(0),
+            };
+            if opt_level < minimum_opt_level {
+                return Err(BuildProfileError::OptLevelTooLow {
+                    profile: self.name.clone(),
+                    opt_level: self.opt_level.clone(),
+                    minimum_opt_level,
+                });
+            }
+        }
+
+        if let Some(required_lto) = config.lto {
+            if self.lto != required_lto {
+                return Err(BuildProfileError::LtoMismatch {
+                    profile: self.name.clone(),
+                    lto: self.lto,
+                    required_lto,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the name of the profile that a build script whose `OUT_DIR` is
+/// `out_dir` runs for, from the profile directory of the target directory
+/// (ex. `target/x86_64-pc-windows-msvc/release/build/<package>/out`)
+fn profile_name_from_out_dir(out_dir: &Path) -> Option<String> {
+    let profile_directory = out_dir.ancestors().nth(3)?.file_name()?.to_str()?;
+    Some(if profile_directory == DEV_PROFILE_DIRECTORY_NAME {
+        "dev".to_string()
+    } else {
+        profile_directory.to_string()
+    })
+}
+
+/// Returns the LTO mode of the profile `name` declared in the workspace
+/// manifest `manifest`, following the profiles it `inherits` from
+fn profile_lto(manifest: &toml::Table, name: &str) -> LtoMode {
+    let mut name = name;
+    // Bound the number of inherited profiles, in case of an inheritance cycle
+    for _ in 0..8 {
+        let profile = manifest
+            .get("profile")
+            .and_then(|profiles| profiles.get(name));
+        if let Some(lto) = profile
+            .and_then(|profile| profile.get("lto"))
+            .and_then(LtoMode::from_profile_setting)
+        {
+            return lto;
+        }
+        match profile
+            .and_then(|profile| profile.get("inherits"))
+            .and_then(toml::Value::as_str)
+        {
+            Some(inherits) => name = inherits,
+            None => break,
+        }
+    }
+    LtoMode::Off
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KmdfConfig;
+
+    fn build_profile(panic_unwind: bool, opt_level: &str, lto: LtoMode) -> BuildProfile {
+        BuildProfile {
+            name: "release".to_string(),
+            panic_unwind,
+            opt_level: opt_level.to_string(),
+            lto,
+        }
+    }
+
+    #[test]
+    fn profile_name_from_out_dir_of_build_script() {
+        assert_eq!(
+            profile_name_from_out_dir(Path::new(
+                "/ws/target/x86_64-pc-windows-msvc/debug/build/driver-0123456789abcdef/out"
+            ))
+            .as_deref(),
+            Some("dev")
+        );
+        assert_eq!(
+            profile_name_from_out_dir(Path::new(
+                "/ws/target/release-lto/build/driver-0123456789abcdef/out"
+            ))
+            .as_deref(),
+            Some("release-lto")
+        );
+    }
+
+    #[test]
+    fn profile_lto_follows_inherited_profiles() {
+        let manifest: toml::Table = r#"
+            [profile.dev]
+            lto = true
+
+            [profile.release]
+            lto = "thin"
+
+            [profile.production]
+            inherits = "release"
+        "#
+        .parse()
+        .unwrap();
+
+        assert_eq!(profile_lto(&manifest, "dev"), LtoMode::Fat);
+        assert_eq!(profile_lto(&manifest, "production"), LtoMode::Thin);
+        assert_eq!(profile_lto(&manifest, "bench"), LtoMode::Off);
+    }
+
+    #[test]
+    fn validate_rejects_panic_unwind_for_kernel_mode_drivers() {
+        let profile = build_profile(true, "3", LtoMode::Fat);
+
+        assert!(matches!(
+            profile.validate(&DriverConfig::Kmdf(KmdfConfig::new()), None),
+            Err(BuildProfileError::PanicUnwindNotSupported { .. })
+        ));
+        assert!(matches!(
+            profile.validate(&DriverConfig::Wdm, None),
+            Err(BuildProfileError::PanicUnwindNotSupported { .. })
+        ));
+        assert!(build_profile(false, "3", LtoMode::Fat)
+            .validate(&DriverConfig::Wdm, None)
+            .is_ok());
+    }
+
+    #[test]
+    fn validate_checks_declared_requirements() {
+        let config = BuildProfileConfig {
+            lto: Some(LtoMode::Thin),
+            minimum_opt_level: Some(2),
+            control_flow_guard: false,
+        };
+
+        assert!(build_profile(false, "s", LtoMode::Thin)
+            .validate(&DriverConfig::Wdm, Some(&config))
+            .is_ok());
+        assert!(matches!(
+            build_profile(false, "1", LtoMode::Thin).validate(&DriverConfig::Wdm, Some(&config)),
+            Err(BuildProfileError::OptLevelTooLow { .. })
+        ));
+        assert!(matches!(
+            build_profile(false, "3", LtoMode::Fat).validate(&DriverConfig::Wdm, Some(&config)),
+            Err(BuildProfileError::LtoMismatch { .. })
+        ));
+    }
+}
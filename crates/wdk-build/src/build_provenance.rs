@@ -0,0 +1,196 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Provenance of the WDK inputs that went into a driver build
+//!
+//! Compliance processes (ex. SBOM generation) need to trace a driver binary
+//! back to the exact WDK it was built with. [`Config::configure_binary_build`]
+//! writes a [`BuildProvenance`] recording the WDK and SDK versions, the linked
+//! libraries and the included headers of the build to a JSON file named
+//! [`BUILD_PROVENANCE_FILE_NAME`] in the `OUT_DIR` of the driver's build
+//! script, and the `package-driver-flow` cargo-make task copies it into the
+//! driver package.
+//!
+//! The headers are only known to the build script of `wdk-sys`, which forwards
+//! them to the build scripts of the crates that directly depend on it via
+//! [`emit_headers_metadata`].
+
+use std::{env, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cargo_directives::{self, CargoDirective},
+    utils,
+    ApiSubset,
+    Config,
+    ConfigError,
+    CpuArchitecture,
+    DriverConfig,
+};
+
+/// Name of the build provenance file written to `OUT_DIR`
+pub const BUILD_PROVENANCE_FILE_NAME: &str = "wdk-build-provenance.json";
+
+/// Key of the `links` metadata with which `wdk-sys` forwards the headers it
+/// includes to the build scripts of its dependents
+const HEADERS_METADATA_KEY: &str = "headers";
+
+/// Environment variable set by Cargo in the build scripts of crates that
+/// directly depend on `wdk-sys` (whose `links` key is `wdk`), containing the
+/// headers forwarded by [`emit_headers_metadata`]
+const HEADERS_METADATA_ENV_VAR: &str = "DEP_WDK_HEADERS";
+
+/// Separator of the headers forwarded by [`emit_headers_metadata`]
+const HEADERS_METADATA_SEPARATOR: char = ';';
+
+/// The WDK inputs of a driver build
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct BuildProvenance {
+    /// Version of `wdk-build` that configured the build
+    pub wdk_build_version: String,
+    /// Version of the WDK whose libraries are linked (ex. `10.0.22621.0`).
+    /// This is the same version detected by the `wdk-build-init` cargo-make
+    /// task
+    pub wdk_version: String,
+    /// Version of the Windows SDK whose headers are included
+    pub sdk_version: String,
+    /// Path to the root of the WDK
+    pub wdk_content_root: PathBuf,
+    /// Build configuration of the driver
+    pub driver_config: DriverConfig,
+    /// CPU architecture targeted by the build
+    pub cpu_architecture: CpuArchitecture,
+    /// Header include paths of the build
+    pub include_paths: Vec<PathBuf>,
+    /// Library search paths of the build
+    pub library_paths: Vec<PathBuf>,
+    /// Libraries linked into the driver binary, in link order
+    pub libraries: Vec<String>,
+    /// Headers included by `wdk-sys`. This is empty when the driver does not
+    /// directly depend on `wdk-sys`
+    pub headers: Vec<String>,
+}
+
+impl BuildProvenance {
+    /// Creates the [`BuildProvenance`] of the build configured by `config`,
+    /// recording the libraries emitted so far by this build script
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the WDK or SDK version cannot be
+    /// detected, or if any of the include or library paths of `config` do not
+    /// exist
+    pub fn new(config: &Config) -> Result<Self, ConfigError> {
+        Ok(Self {
+            wdk_build_version: env!("CARGO_PKG_VERSION").to_string(),
+            wdk_version: utils::get_latest_windows_sdk_version(
+                &config.wdk_content_root.join("Lib"),
+            )?,
+            sdk_version: utils::get_latest_windows_sdk_version(
+                &config.wdk_content_root.join("Include"),
+            )?,
+            wdk_content_root: config.wdk_content_root.clone(),
+            driver_config: config.driver_config.clone(),
+            cpu_architecture: config.cpu_architecture,
+            include_paths: config.include_paths()?.collect(),
+            library_paths: config.library_paths()?.collect(),
+            libraries: linked_libraries(&cargo_directives::emitted_directives()),
+            headers: env::var(HEADERS_METADATA_ENV_VAR)
+                .map(|headers| parse_headers_metadata(&headers))
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Writes this [`BuildProvenance`] to [`BUILD_PROVENANCE_FILE_NAME`] in
+    /// `OUT_DIR`. Nothing is written outside of build scripts, where `OUT_DIR`
+    /// is not set.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the provenance fails to be
+    /// serialized or written
+    pub fn write_to_out_dir(&self) -> Result<(), ConfigError> {
+        let Some(out_dir) = env::var_os("OUT_DIR") else {
+            return Ok(());
+        };
+
+        std::fs::write(
+            PathBuf::from(out_dir).join(BUILD_PROVENANCE_FILE_NAME),
+            serde_json::to_string_pretty(self).map_err(std::io::Error::from)?,
+        )?;
+        Ok(())
+    }
+}
+
+/// Forwards the headers of `api_subsets` to the build scripts of the crates
+/// that directly depend on the crate being built, so that they are recorded in
+/// their [`BuildProvenance`].
+///
+/// This must be called from the build script of `wdk-sys`, since it relies on
+/// its `links` key.
+pub fn emit_headers_metadata(config: &Config, api_subsets: impl IntoIterator<Item = ApiSubset>) {
+    let headers = api_subsets
+        .into_iter()
+        .flat_map(|api_subset| config.headers(api_subset))
+        .collect::<Vec<_>>()
+        .join(&HEADERS_METADATA_SEPARATOR.to_string());
+    println!("cargo::metadata={HEADERS_METADATA_KEY}={headers}");
+}
+
+/// Parses the headers forwarded by [`emit_headers_metadata`]
+fn parse_headers_metadata(headers: &str) -> Vec<String> {
+    headers
+        .split(HEADERS_METADATA_SEPARATOR)
+        .filter(|header| !header.is_empty())
+        .map(std::string::ToString::to_string)
+        .collect()
+}
+
+/// Returns the names of the libraries linked by the `rustc-link-lib`
+/// directives in `directives`, without their link kind
+fn linked_libraries(directives: &[CargoDirective]) -> Vec<String> {
+    directives
+        .iter()
+        .filter_map(|directive| directive.directive.strip_prefix("rustc-link-lib="))
+        .map(|library| {
+            library
+                .rsplit_once('=')
+                .map_or(library, |(_kind, name)| name)
+                .to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linked_libraries_are_extracted_from_directives() {
+        let directive = |directive: &str| CargoDirective {
+            directive: directive.to_string(),
+            provenance: "test".to_string(),
+        };
+
+        assert_eq!(
+            linked_libraries(&[
+                directive("rustc-link-search=C:\\WDK\\Lib"),
+                directive("rustc-link-lib=static=ntoskrnl"),
+                directive("rustc-cdylib-link-arg=/DRIVER"),
+                directive("rustc-link-lib=hal"),
+            ]),
+            ["ntoskrnl", "hal"]
+        );
+    }
+
+    #[test]
+    fn headers_metadata_is_parsed() {
+        assert_eq!(
+            parse_headers_metadata("ntifs.h;ntddk.h;wdf.h"),
+            ["ntifs.h", "ntddk.h", "wdf.h"]
+        );
+        assert!(parse_headers_metadata("").is_empty());
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Emission of Cargo build script directives, with an optional manifest of
+//! every emitted directive
+//!
+//! When the `WDK_BUILD_DIRECTIVE_MANIFEST` environment variable is set to
+//! `true`, every directive emitted by `wdk-build` (ex. `rustc-link-lib`,
+//! `rustc-cdylib-link-arg`, `rustc-cfg`) is also recorded, along with the
+//! [`Config`](crate::Config) decision that produced it, in a JSON file named
+//! [`DIRECTIVE_MANIFEST_FILE_NAME`] in the `OUT_DIR` of the build script. This
+//! makes it possible to audit why a particular directive was emitted, and to
+//! integrate with external build systems.
+
+use std::{
+    env,
+    path::PathBuf,
+    sync::{Mutex, PoisonError},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Environment variable that enables writing the directive manifest when set
+/// to `true`
+pub const WDK_BUILD_DIRECTIVE_MANIFEST_ENV_VAR: &str = "WDK_BUILD_DIRECTIVE_MANIFEST";
+
+/// Name of the directive manifest file written to `OUT_DIR`
+pub const DIRECTIVE_MANIFEST_FILE_NAME: &str = "wdk-build-directives.json";
+
+/// Directives emitted so far by this build script, in emission order
+static EMITTED_DIRECTIVES: Mutex<Vec<CargoDirective>> = Mutex::new(Vec::new());
+
+/// A Cargo build script directive emitted by `wdk-build`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CargoDirective {
+    /// The directive, without the `cargo::` prefix (ex.
+    /// `rustc-link-lib=static=ntoskrnl`)
+    pub directive: String,
+    /// Description of the configuration decision that produced the directive
+    pub provenance: String,
+}
+
+/// Emits `cargo::<directive>` to Cargo, and records it with its
+/// `provenance` for the directive manifest
+pub(crate) fn emit(directive: String, provenance: &str) {
+    println!("cargo::{directive}");
+
+    EMITTED_DIRECTIVES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(CargoDirective {
+            directive,
+            provenance: provenance.to_string(),
+        });
+}
+
+/// Returns every directive emitted so far by this build script, in emission
+/// order
+pub(crate) fn emitted_directives() -> Vec<CargoDirective> {
+    EMITTED_DIRECTIVES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Writes every directive emitted so far to [`DIRECTIVE_MANIFEST_FILE_NAME`]
+/// in `OUT_DIR`, if the `WDK_BUILD_DIRECTIVE_MANIFEST` environment variable
+/// is set to `true`. The manifest is rewritten each time this is called, so
+/// that it always contains every emitted directive.
+///
+/// # Errors
+///
+/// This function will return an error if the manifest fails to be written
+pub(crate) fn write_manifest_if_enabled() -> std::io::Result<()> {
+    println!("cargo::rerun-if-env-changed={WDK_BUILD_DIRECTIVE_MANIFEST_ENV_VAR}");
+
+    if !env::var(WDK_BUILD_DIRECTIVE_MANIFEST_ENV_VAR).is_ok_and(|value| value == "true") {
+        return Ok(());
+    }
+
+    // `OUT_DIR` is only unset outside of build scripts, where there is no build to
+    // audit
+    let Some(out_dir) = env::var_os("OUT_DIR") else {
+        return Ok(());
+    };
+
+    let manifest = serde_json::to_string_pretty(&emitted_directives())?;
+    std::fs::write(
+        PathBuf::from(out_dir).join(DIRECTIVE_MANIFEST_FILE_NAME),
+        manifest,
+    )
+}
+
+/// Emits a Cargo directive with a `format!`-style directive string, recording
+/// its provenance for the directive manifest
+macro_rules! emit_cargo_directive {
+    ($provenance:expr, $($arg:tt)*) => {
+        $crate::cargo_directives::emit(format!($($arg)*), $provenance)
+    };
+}
+pub(crate) use emit_cargo_directive;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emitted_directives_are_recorded_in_order() {
+        emit(
+            "rustc-link-lib=static=ntoskrnl".to_string(),
+            "WDM libraries",
+        );
+        emit_cargo_directive!("test", "rustc-cdylib-link-arg=/{}", "DRIVER");
+
+        let emitted_directives = EMITTED_DIRECTIVES
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let ntoskrnl_index = emitted_directives
+            .iter()
+            .position(|directive| directive.directive == "rustc-link-lib=static=ntoskrnl")
+            .expect("directive should be recorded");
+        assert_eq!(
+            emitted_directives[ntoskrnl_index + 1..]
+                .iter()
+                .find(|directive| directive.provenance == "test"),
+            Some(&CargoDirective {
+                directive: "rustc-cdylib-link-arg=/DRIVER".to_string(),
+                provenance: "test".to_string(),
+            })
+        );
+    }
+}
@@ -13,27 +13,76 @@
 
 use core::{fmt, ops::RangeFrom};
 use std::{
+    collections::BTreeMap,
     env,
+    ffi::OsString,
+    io::BufReader,
     panic::UnwindSafe,
     path::{Path, PathBuf},
-    process::Command,
+    process::{Command, Stdio},
+    time::Instant,
 };
 
 use anyhow::Context;
-use cargo_metadata::{camino::Utf8Path, Metadata, MetadataCommand};
-use clap::{Args, Parser};
+use cargo_metadata::{camino::Utf8Path, Message, Metadata, MetadataCommand};
+use clap::{Args, Parser, ValueEnum};
 use tracing::{instrument, trace};
 
 use crate::{
+    additional_binaries::{self, AdditionalBinariesError, AdditionalBinariesMetadata},
+    additional_drivers::{
+        self,
+        AdditionalDriver,
+        AdditionalDriversError,
+        AdditionalDriversMetadata,
+    },
+    build_fingerprint::{
+        packages_affected_by_wdk_configuration,
+        BuildFingerprint,
+        BuildFingerprintError,
+        BUILD_FINGERPRINT_FILE_NAME,
+    },
+    build_provenance::BUILD_PROVENANCE_FILE_NAME,
+    cli_defaults::CommandLineDefaults,
+    doctor,
+    driver_verifier::{
+        self,
+        DriverVerifierError,
+        DriverVerifierSettings,
+        DRIVER_VERIFIER_ENV_VAR,
+        DRIVER_VERIFIER_REBOOT_ENV_VAR,
+    },
+    inf,
+    inf_policy::{self, InfPolicy, INF_POLICY_ENV_VAR},
+    inx::InfMetadata,
+    junit::{TestCase, TestSuite},
     metadata,
+    package::PackageError,
+    packaging::{self, PackagingMetadata, PackagingStage, TemplateVariables},
+    pregenerated_bindings::GENERATED_BINDINGS_OUT_DIR_ENV_VAR,
+    probe::{self, Component},
+    stack_frame_limits,
+    symbol_surface::{
+        self,
+        SymbolSurfaceError,
+        SYMBOL_SURFACE_BASELINE_DIR_ENV_VAR,
+        SYMBOL_SURFACE_OUT_DIR_ENV_VAR,
+    },
+    target_directory,
     utils::{
-        detect_wdk_content_root,
         get_latest_windows_sdk_version,
         get_wdk_version_number,
+        try_detect_wdk_content_root,
         PathExt,
     },
+    version_info::{DriverVersion, VersionInfoConfig},
+    wdf_coinstaller::{self, WdfCoInstallerVersion},
+    wdk_layout::{self, WdkLayout},
+    wdk_lock::{MismatchPolicy, WdkLock, WdkLockConfig, WdkLockError, WDK_LOCK_FILE_NAME},
     ConfigError,
     CpuArchitecture,
+    DriverConfig,
+    TargetOs,
 };
 
 /// The filename of the main makefile for Rust Windows drivers.
@@ -45,10 +94,56 @@
 /// The environment variable that [`setup_wdk_version`] stores the WDK version
 /// in.
 pub const WDK_VERSION_ENV_VAR: &str = "WDK_BUILD_DETECTED_VERSION";
-/// The first WDK version with the new `InfVerif` behavior.
-const MINIMUM_SAMPLES_FLAG_WDK_VERSION: i32 = 25798;
 const WDK_INF_ADDITIONAL_FLAGS_ENV_VAR: &str = "WDK_BUILD_ADDITIONAL_INFVERIF_FLAGS";
+/// The name of the environment variable containing the `infverif` flags of the
+/// driver model, which are set by the `infverif` task
+const WDK_INF_BASE_FLAGS_ENV_VAR: &str = "WDK_BUILD_BASE_INFVERIF_FLAGS";
 const WDK_BUILD_OUTPUT_DIRECTORY_ENV_VAR: &str = "WDK_BUILD_OUTPUT_DIRECTORY";
+const WDK_BUILD_METADATA_TARGET_OS_ENV_VAR: &str = "WDK_BUILD_METADATA-TARGET_OS";
+/// The name of the environment variable containing the forwarded arguments
+/// that select the build directory (i.e. profile, target and target directory)
+/// for `cargo clean` commands
+const WDK_BUILD_CARGO_CLEAN_FLAGS_ENV_VAR: &str = "WDK_BUILD_CARGO_CLEAN_FLAGS";
+/// The name of the environment variable that enables automatically cleaning
+/// artifacts built with a different WDK or WDK configuration
+const WDK_BUILD_AUTO_CLEAN_ENV_VAR: &str = "WDK_BUILD_AUTO_CLEAN";
+/// Environment variable used to force the removal of installed driver packages
+/// that are still in use by devices
+const WDK_BUILD_UNINSTALL_FORCE_ENV_VAR: &str = "WDK_BUILD_UNINSTALL_FORCE";
+/// The name of the environment variable containing the target triples of the
+/// architectures selected by `--target-arch all`
+const WDK_BUILD_TARGET_TRIPLES_ENV_VAR: &str = "WDK_BUILD_TARGET_TRIPLES";
+/// The name of the environment variable containing the command line arguments
+/// forwarded to the `package-driver-flow` of each architecture selected by
+/// `--target-arch all`
+const WDK_BUILD_MULTI_ARCHITECTURE_ARGS_ENV_VAR: &str = "WDK_BUILD_MULTI_ARCHITECTURE_ARGS";
+/// The name of the environment variable containing the directory of prebuilt
+/// driver binaries selected by `--input`, which are packaged instead of
+/// building the driver
+const WDK_BUILD_PACKAGE_INPUT_DIR_ENV_VAR: &str = "WDK_BUILD_PACKAGE_INPUT_DIR";
+/// The name of the environment variable containing the `;`-separated paths of
+/// the additional driver binaries copied into the driver package, which are
+/// signed along with the driver binary
+const WDK_BUILD_ADDITIONAL_DRIVER_BINARIES_ENV_VAR: &str = "WDK_BUILD_ADDITIONAL_DRIVER_BINARIES";
+/// The name of the environment variable that enables the `signtool verify`
+/// tasks, which is set when the selected INF policy verifies signatures
+const WDK_BUILD_ENABLE_SIGNTOOL_VERIFY_ENV_VAR: &str = "WDK_BUILD_ENABLE_SIGNTOOL_VERIFY";
+/// The name of the environment variable containing the path of the file that
+/// the `signtool-verify` task verifies the signature of
+const WDK_BUILD_SIGNTOOL_VERIFY_INPUT_FILE_ENV_VAR: &str = "WDK_BUILD_SIGNTOOL_VERIFY_INPUT_FILE";
+/// The name of the environment variable containing the directory selected by
+/// `--junit-report-dir`, which the results of the verification steps of the
+/// driver package are written to
+const WDK_BUILD_JUNIT_REPORT_DIR_ENV_VAR: &str = "WDK_BUILD_JUNIT_REPORT_DIR";
+/// The name of the environment variable containing the version that `stampinf`
+/// stamps into the `DriverVer` directive of the INF (`*` to let `stampinf`
+/// generate one)
+const WDK_BUILD_STAMPINF_DRIVER_VERSION_ENV_VAR: &str = "WDK_BUILD_STAMPINF_DRIVER_VERSION";
+/// The name of the environment variable containing the directory of the MSVC
+/// toolset, which is set in a Developer Command Prompt and in the eWDK
+const VC_TOOLS_INSTALL_DIR_ENV_VAR: &str = "VCToolsInstallDir";
+const RUSTC_WRAPPER_ENV_VAR: &str = "RUSTC_WRAPPER";
+const CARGO_TARGET_DIR_ENV_VAR: &str = "CARGO_TARGET_DIR";
 
 /// The name of the environment variable that cargo-make uses during `cargo
 /// build` and `cargo test` commands
@@ -57,13 +152,12 @@
 const CARGO_MAKE_PROFILE_ENV_VAR: &str = "CARGO_MAKE_PROFILE";
 const CARGO_MAKE_CARGO_PROFILE_ENV_VAR: &str = "CARGO_MAKE_CARGO_PROFILE";
 const CARGO_MAKE_CRATE_TARGET_TRIPLE_ENV_VAR: &str = "CARGO_MAKE_CRATE_TARGET_TRIPLE";
-const CARGO_MAKE_CRATE_CUSTOM_TRIPLE_TARGET_DIRECTORY_ENV_VAR: &str =
-    "CARGO_MAKE_CRATE_CUSTOM_TRIPLE_TARGET_DIRECTORY";
 const CARGO_MAKE_RUST_DEFAULT_TOOLCHAIN_ENV_VAR: &str = "CARGO_MAKE_RUST_DEFAULT_TOOLCHAIN";
 const CARGO_MAKE_CRATE_NAME_ENV_VAR: &str = "CARGO_MAKE_CRATE_NAME";
 const CARGO_MAKE_CRATE_FS_NAME_ENV_VAR: &str = "CARGO_MAKE_CRATE_FS_NAME";
 const CARGO_MAKE_WORKSPACE_WORKING_DIRECTORY_ENV_VAR: &str =
     "CARGO_MAKE_WORKSPACE_WORKING_DIRECTORY";
+const CARGO_MAKE_WORKING_DIRECTORY_ENV_VAR: &str = "CARGO_MAKE_WORKING_DIRECTORY";
 const CARGO_MAKE_CURRENT_TASK_NAME_ENV_VAR: &str = "CARGO_MAKE_CURRENT_TASK_NAME";
 
 /// `clap` uses an exit code of 2 for usage errors: <https://github.com/clap-rs/clap/blob/14fd853fb9c5b94e371170bbd0ca2bf28ef3abff/clap_builder/src/util/mod.rs#L30C18-L30C28>
@@ -90,11 +184,20 @@ struct CommandLineInterface {
     #[command(next_help_heading = "Feature Selection")]
     features: clap_cargo::Features,
 
+    #[command(flatten)]
+    build_cache_options: BuildCacheOptions,
+
     #[command(flatten)]
     compilation_options: CompilationOptions,
 
     #[command(flatten)]
     manifest_options: ManifestOptions,
+
+    #[command(flatten)]
+    package_options: PackageOptions,
+
+    #[command(flatten)]
+    deploy_options: DeployOptions,
 }
 
 #[derive(Args, Debug)]
@@ -104,6 +207,22 @@ struct BaseOptions {
 
     #[arg(short, long, action = clap::ArgAction::Count, help = "Use verbose output (-vv very verbose/build.rs output)")]
     verbose: u8,
+
+    #[arg(
+        long,
+        value_name = "KEY=VALUE",
+        value_parser = parse_forwarded_value,
+        help = "Override a configuration value"
+    )]
+    config: Vec<String>,
+
+    #[arg(
+        short = 'Z',
+        value_name = "FLAG",
+        value_parser = parse_forwarded_value,
+        help = "Unstable (nightly-only) flags to Cargo, see 'cargo -Z help' for details"
+    )]
+    unstable_flags: Vec<String>,
 }
 
 #[derive(Args, Debug)]
@@ -132,10 +251,24 @@ struct CompilationOptions {
     )]
     jobs: Option<String>,
 
-    // FIXME: support building multiple targets at once
     #[arg(long, value_name = "TRIPLE", help = "Build for a target triple")]
     target: Option<String>,
 
+    #[arg(
+        long,
+        value_name = "ARCH",
+        conflicts_with = "target",
+        help = "Build for a CPU architecture, or for all supported CPU architectures"
+    )]
+    target_arch: Option<TargetArchitecture>,
+
+    #[arg(
+        long,
+        value_name = "DIRECTORY",
+        help = "Directory for all generated artifacts"
+    )]
+    target_dir: Option<PathBuf>,
+
     #[allow(clippy::option_option)] // This is how clap_derive expects "optional value for optional argument" args
     #[arg(
         long,
@@ -146,6 +279,37 @@ struct CompilationOptions {
     timings: Option<Option<String>>,
 }
 
+#[derive(Args, Debug)]
+#[command(next_help_heading = "Build Cache Options")]
+struct BuildCacheOptions {
+    #[arg(
+        long,
+        value_name = "DIRECTORY",
+        conflicts_with = "target_dir",
+        help = "Directory for all generated artifacts, relative to the workspace root, which is \
+                shared by every package of the workspace"
+    )]
+    shared_target_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "WRAPPER",
+        help = "Run every rustc invocation through a compiler cache wrapper (ex. sccache)"
+    )]
+    rustc_wrapper: Option<String>,
+}
+
+/// CPU architectures accepted by the `--target-arch` argument
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum TargetArchitecture {
+    /// AMD64 CPU architecture
+    Amd64,
+    /// ARM64 CPU architecture
+    Arm64,
+    /// Every CPU architecture in [`CpuArchitecture::ALL`]
+    All,
+}
+
 #[derive(Args, Debug)]
 #[command(next_help_heading = "Manifest Options")]
 struct ManifestOptions {
@@ -159,27 +323,80 @@ struct ManifestOptions {
     offline: bool,
 }
 
+#[derive(Args, Debug)]
+#[command(next_help_heading = "Package Options")]
+struct PackageOptions {
+    #[arg(
+        long,
+        value_name = "DIRECTORY",
+        conflicts_with = "target_arch",
+        help = "Package the prebuilt driver binaries in a directory instead of building them"
+    )]
+    input: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "POLICY",
+        help = "Policy that the driver package is validated with: sample, production, or the path \
+                of a policy TOML file"
+    )]
+    inf_policy: Option<InfPolicy>,
+
+    #[arg(
+        long,
+        value_name = "DIRECTORY",
+        help = "Write the results of the driver package verification steps (signtool verify and \
+                infverif) to JUnit XML reports in a directory"
+    )]
+    junit_report_dir: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+#[command(next_help_heading = "Deploy Options")]
+struct DeployOptions {
+    #[arg(
+        long,
+        value_name = "SETTINGS",
+        help = "Driver Verifier settings to apply to the deployed driver: standard, off, or a \
+                value of Driver Verifier flags"
+    )]
+    verifier: Option<DriverVerifierSettings>,
+}
+
 impl ParseCargoArgs for CommandLineInterface {
     fn parse_cargo_args(&self) {
         let Self {
             base,
             workspace,
             features,
+            build_cache_options,
             compilation_options,
             manifest_options,
+            package_options,
+            deploy_options,
         } = self;
 
         base.parse_cargo_args();
         workspace.parse_cargo_args();
         features.parse_cargo_args();
+        // The build cache options are parsed first, since the shared target directory
+        // selects the directory that the driver package is generated in
+        build_cache_options.parse_cargo_args();
         compilation_options.parse_cargo_args();
         manifest_options.parse_cargo_args();
+        package_options.parse_cargo_args();
+        deploy_options.parse_cargo_args();
     }
 }
 
 impl ParseCargoArgs for BaseOptions {
     fn parse_cargo_args(&self) {
-        let Self { quiet, verbose } = self;
+        let Self {
+            quiet,
+            verbose,
+            config,
+            unstable_flags,
+        } = self;
 
         if *quiet && *verbose > 0 {
             eprintln!("Cannot specify both --quiet and --verbose");
@@ -196,6 +413,20 @@ fn parse_cargo_args(&self) {
                 format!("-{}", "v".repeat((*verbose).into())).as_str(),
             );
         }
+
+        for config_value in config {
+            append_to_space_delimited_env_var(
+                CARGO_MAKE_CARGO_BUILD_TEST_FLAGS_ENV_VAR,
+                format!("--config {config_value}").as_str(),
+            );
+        }
+
+        for unstable_flag in unstable_flags {
+            append_to_space_delimited_env_var(
+                CARGO_MAKE_CARGO_BUILD_TEST_FLAGS_ENV_VAR,
+                format!("-Z {unstable_flag}").as_str(),
+            );
+        }
     }
 }
 
@@ -327,6 +558,8 @@ fn parse_cargo_args(&self) {
             profile,
             jobs,
             target,
+            target_arch,
+            target_dir,
             timings,
         } = self;
         if *release && profile.is_some() {
@@ -389,6 +622,7 @@ fn parse_cargo_args(&self) {
             );
         }
 
+        let target = configure_target_architectures(target.as_ref(), *target_arch);
         if let Some(target) = &target {
             env::set_var(CARGO_MAKE_CRATE_TARGET_TRIPLE_ENV_VAR, target);
             append_to_space_delimited_env_var(
@@ -397,7 +631,16 @@ fn parse_cargo_args(&self) {
             );
         }
 
-        configure_wdf_build_output_dir(target.as_ref(), &cargo_make_cargo_profile);
+        if let Some(target_dir) = &target_dir {
+            configure_target_dir(target_dir);
+        }
+
+        configure_wdf_build_output_dir(
+            target.as_ref(),
+            target_dir.as_deref(),
+            &cargo_make_cargo_profile,
+        );
+        configure_cargo_clean_flags(target.as_ref(), &cargo_make_cargo_profile);
 
         if let Some(timings_option) = &timings {
             timings_option.as_ref().map_or_else(
@@ -418,6 +661,30 @@ fn parse_cargo_args(&self) {
     }
 }
 
+impl ParseCargoArgs for BuildCacheOptions {
+    fn parse_cargo_args(&self) {
+        let Self {
+            shared_target_dir,
+            rustc_wrapper,
+        } = self;
+
+        // Both are passed to cargo through environment variables instead of command
+        // line arguments, so that they are also used by the cargo commands that
+        // tasks run themselves (ex. to build companion binaries while
+        // packaging)
+        if let Some(shared_target_dir) = shared_target_dir {
+            env::set_var(
+                CARGO_TARGET_DIR_ENV_VAR,
+                resolve_shared_target_dir(shared_target_dir),
+            );
+        }
+
+        if let Some(rustc_wrapper) = rustc_wrapper {
+            env::set_var(RUSTC_WRAPPER_ENV_VAR, rustc_wrapper);
+        }
+    }
+}
+
 impl ParseCargoArgs for ManifestOptions {
     fn parse_cargo_args(&self) {
         let Self {
@@ -449,6 +716,89 @@ fn parse_cargo_args(&self) {
     }
 }
 
+impl ParseCargoArgs for PackageOptions {
+    fn parse_cargo_args(&self) {
+        let Self {
+            input,
+            inf_policy,
+            junit_report_dir,
+        } = self;
+
+        if let Some(input) = input {
+            // The directory is resolved here, since tasks run in the directory of each
+            // package rather than the directory cargo-make was invoked from
+            let Some(input) = input
+                .canonicalize()
+                .ok()
+                .filter(|input| input.is_dir())
+                .and_then(|input| input.strip_extended_length_path_prefix().ok())
+            else {
+                eprintln!(
+                    "--input should be an existing directory: {}",
+                    input.display()
+                );
+                std::process::exit(CLAP_USAGE_EXIT_CODE);
+            };
+            env::set_var(WDK_BUILD_PACKAGE_INPUT_DIR_ENV_VAR, input);
+        }
+
+        if let Some(inf_policy) = inf_policy {
+            // Like --input, the path of a policy file is resolved relative to the directory
+            // cargo-make was invoked from
+            let inf_policy = match inf_policy {
+                InfPolicy::Custom(path) => {
+                    let Some(path) = path
+                        .canonicalize()
+                        .ok()
+                        .filter(|path| path.is_file())
+                        .and_then(|path| path.strip_extended_length_path_prefix().ok())
+                    else {
+                        eprintln!(
+                            "--inf-policy should be sample, production, or an existing policy \
+                             file: {}",
+                            path.display()
+                        );
+                        std::process::exit(CLAP_USAGE_EXIT_CODE);
+                    };
+                    InfPolicy::Custom(path)
+                }
+                inf_policy => inf_policy.clone(),
+            };
+            env::set_var(INF_POLICY_ENV_VAR, inf_policy.to_string());
+        }
+
+        if let Some(junit_report_dir) = junit_report_dir {
+            // Like --input, the directory is resolved relative to the directory cargo-make
+            // was invoked from. It is created up front, so that every package writes its
+            // reports to the same directory.
+            let Some(junit_report_dir) = std::fs::create_dir_all(junit_report_dir)
+                .ok()
+                .and_then(|()| junit_report_dir.canonicalize().ok())
+                .and_then(|junit_report_dir| {
+                    junit_report_dir.strip_extended_length_path_prefix().ok()
+                })
+            else {
+                eprintln!(
+                    "--junit-report-dir should be a directory that can be created: {}",
+                    junit_report_dir.display()
+                );
+                std::process::exit(CLAP_USAGE_EXIT_CODE);
+            };
+            env::set_var(WDK_BUILD_JUNIT_REPORT_DIR_ENV_VAR, junit_report_dir);
+        }
+    }
+}
+
+impl ParseCargoArgs for DeployOptions {
+    fn parse_cargo_args(&self) {
+        let Self { verifier } = self;
+
+        if let Some(verifier) = verifier {
+            env::set_var(DRIVER_VERIFIER_ENV_VAR, verifier.to_string());
+        }
+    }
+}
+
 /// Parses the command line arguments, validates that they are supported by
 /// `rust-driver-makefile.toml`, and then returns a list of environment variable
 /// names that were updated.
@@ -487,7 +837,25 @@ pub fn validate_command_line_args() -> impl IntoIterator<Item = String> {
         env::set_var(CARGO_MAKE_RUST_DEFAULT_TOOLCHAIN_ENV_VAR, toolchain);
     }
 
-    CommandLineInterface::parse_from(env_args.iter()).parse_cargo_args();
+    let command_line_defaults = read_command_line_defaults();
+    let cargo_make_profile = env::var(CARGO_MAKE_PROFILE_ENV_VAR)
+        .unwrap_or_else(|_| panic!("{CARGO_MAKE_PROFILE_ENV_VAR} should be set by cargo-make"));
+
+    CommandLineInterface::parse_from(command_line_defaults.apply(env_args, &cargo_make_profile))
+        .parse_cargo_args();
+
+    if env::var_os(WDK_BUILD_TARGET_TRIPLES_ENV_VAR).is_some() {
+        env::set_var(
+            WDK_BUILD_MULTI_ARCHITECTURE_ARGS_ENV_VAR,
+            args_without_target_arch(
+                command_line_defaults
+                    .apply(env::args_os().collect(), &cargo_make_profile)
+                    .into_iter()
+                    .skip(1),
+            )
+            .join(" "),
+        );
+    }
 
     [
         CARGO_MAKE_CARGO_BUILD_TEST_FLAGS_ENV_VAR,
@@ -495,6 +863,15 @@ pub fn validate_command_line_args() -> impl IntoIterator<Item = String> {
         CARGO_MAKE_CRATE_TARGET_TRIPLE_ENV_VAR,
         CARGO_MAKE_RUST_DEFAULT_TOOLCHAIN_ENV_VAR,
         WDK_BUILD_OUTPUT_DIRECTORY_ENV_VAR,
+        WDK_BUILD_CARGO_CLEAN_FLAGS_ENV_VAR,
+        WDK_BUILD_TARGET_TRIPLES_ENV_VAR,
+        WDK_BUILD_MULTI_ARCHITECTURE_ARGS_ENV_VAR,
+        WDK_BUILD_PACKAGE_INPUT_DIR_ENV_VAR,
+        INF_POLICY_ENV_VAR,
+        WDK_BUILD_JUNIT_REPORT_DIR_ENV_VAR,
+        DRIVER_VERIFIER_ENV_VAR,
+        CARGO_TARGET_DIR_ENV_VAR,
+        RUSTC_WRAPPER_ENV_VAR,
     ]
     .into_iter()
     .filter(|env_var_name| env::var_os(env_var_name).is_some())
@@ -505,8 +882,11 @@ pub fn validate_command_line_args() -> impl IntoIterator<Item = String> {
 ///
 /// # Errors
 ///
-/// This function returns a [`ConfigError::WdkContentRootDetectionError`] if the
-/// WDK content root directory could not be found.
+/// This function returns:
+/// - [`ConfigError::WdkContentRootDetectionError`] if the WDK content root
+///   directory could not be found
+/// - [`ConfigError::UnknownWdkLayout`] if the `WDK_BUILD_WDK_LAYOUT`
+///   environment variable does not name a known WDK layout
 ///
 /// # Panics
 ///
@@ -514,37 +894,24 @@ pub fn validate_command_line_args() -> impl IntoIterator<Item = String> {
 /// [`env::consts::ARCH`] or if the PATH variable contains non-UTF8
 /// characters.
 pub fn setup_path() -> Result<impl IntoIterator<Item = String>, ConfigError> {
-    let Some(wdk_content_root) = detect_wdk_content_root() else {
-        return Err(ConfigError::WdkContentRootDetectionError);
-    };
+    let wdk_content_root = try_detect_wdk_content_root()?;
     let version = get_latest_windows_sdk_version(&wdk_content_root.join("Lib"))?;
     let host_arch = CpuArchitecture::try_from_cargo_str(env::consts::ARCH)
         .expect("The rust standard library should always set env::consts::ARCH");
 
-    let wdk_bin_root = wdk_content_root
-        .join(format!("bin/{version}"))
+    let wdk_layout = wdk_layout::select_wdk_layout(&version)?;
+
+    let host_windows_sdk_ver_bin_path = wdk_layout
+        .bin_directory(&wdk_content_root, &version, host_arch.as_windows_str())
         .canonicalize()?
-        .strip_extended_length_path_prefix()?;
-    let host_windows_sdk_ver_bin_path = match host_arch {
-        CpuArchitecture::Amd64 => wdk_bin_root
-            .join(host_arch.as_windows_str())
-            .canonicalize()?
-            .strip_extended_length_path_prefix()?
-            .to_str()
-            .expect("x64 host_windows_sdk_ver_bin_path should only contain valid UTF8")
-            .to_string(),
-        CpuArchitecture::Arm64 => wdk_bin_root
-            .join(host_arch.as_windows_str())
-            .canonicalize()?
-            .strip_extended_length_path_prefix()?
-            .to_str()
-            .expect("ARM64 host_windows_sdk_ver_bin_path should only contain valid UTF8")
-            .to_string(),
-    };
+        .strip_extended_length_path_prefix()?
+        .to_str()
+        .expect("host_windows_sdk_ver_bin_path should only contain valid UTF8")
+        .to_string();
 
     // Some tools (ex. inf2cat) are only available in the x86 folder
-    let x86_windows_sdk_ver_bin_path = wdk_bin_root
-        .join("x86")
+    let x86_windows_sdk_ver_bin_path = wdk_layout
+        .bin_directory(&wdk_content_root, &version, "x86")
         .canonicalize()?
         .strip_extended_length_path_prefix()?
         .to_str()
@@ -557,12 +924,8 @@ pub fn setup_path() -> Result<impl IntoIterator<Item = String>, ConfigError> {
         format!("{host_windows_sdk_ver_bin_path};{x86_windows_sdk_ver_bin_path}",),
     );
 
-    let wdk_tool_root = wdk_content_root
-        .join(format!("Tools/{version}"))
-        .canonicalize()?
-        .strip_extended_length_path_prefix()?;
-    let arch_specific_wdk_tool_root = wdk_tool_root
-        .join(host_arch.as_windows_str())
+    let arch_specific_wdk_tool_root = wdk_layout
+        .tools_directory(&wdk_content_root, &version, host_arch.as_windows_str())
         .canonicalize()?
         .strip_extended_length_path_prefix()?;
     prepend_to_semicolon_delimited_env_var(
@@ -575,6 +938,231 @@ pub fn setup_path() -> Result<impl IntoIterator<Item = String>, ConfigError> {
     Ok([PATH_ENV_VAR].map(std::string::ToString::to_string))
 }
 
+/// Probes the WDK installation for the components run by the packaging tasks
+/// (`inf2cat`, `signtool` and `infverif`).
+///
+/// A missing component is reported along with the installer feature that
+/// installs it, instead of as a failure to spawn its process. Components that
+/// are not installed in the WDK, but are available in the `PATH`, are also
+/// accepted.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::MissingWdkComponent`] if any of the components is not
+///   installed
+/// - any error returned by [`probe::component_exists`]
+pub fn probe_packaging_components() -> Result<(), ConfigError> {
+    for component in [Component::Inf2Cat, Component::SignTool, Component::InfVerif] {
+        if probe::component_exists(component)?.is_none() && !is_in_path(component.executable_name())
+        {
+            return Err(ConfigError::MissingWdkComponent {
+                component,
+                installer_feature: component.installer_feature(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the prerequisites for cross-compiling to each target
+/// architecture different from the host's are installed.
+///
+/// Missing prerequisites otherwise only surface as failures late in the build
+/// (ex. unresolved libraries when linking). For each cross-compilation target,
+/// this checks that:
+/// - the WDK libraries for the target architecture and the configured driver
+///   model are installed
+/// - the Rust target is installed, when the toolchain is managed by `rustup`
+/// - the MSVC libraries for the target architecture are installed, when the
+///   `VCToolsInstallDir` environment variable identifies the MSVC toolset (ex.
+///   in a Developer Command Prompt or the eWDK)
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::MissingCrossCompilationPrerequisite`] if any of the
+///   prerequisites is not installed
+/// - [`ConfigError::WdkContentRootDetectionError`] if the WDK content root
+///   directory could not be found
+/// - [`ConfigError::UnknownWdkLayout`] if the `WDK_BUILD_WDK_LAYOUT`
+///   environment variable does not name a known WDK layout
+/// - [`ConfigError::CargoMetadataError`] or
+///   [`ConfigError::TryFromCargoMetadataError`] if the WDK metadata of the
+///   workspace cannot be parsed
+///
+/// # Panics
+///
+/// This function will panic if the CPU architecture cannot be determined from
+/// [`env::consts::ARCH`]
+pub fn validate_cross_compilation_toolchain() -> Result<(), ConfigError> {
+    let host_arch = CpuArchitecture::try_from_cargo_str(env::consts::ARCH)
+        .expect("The rust standard library should always set env::consts::ARCH");
+    let cross_compilation_targets = cross_compilation_target_architectures(
+        env::var(CARGO_MAKE_CRATE_TARGET_TRIPLE_ENV_VAR)
+            .ok()
+            .into_iter()
+            .chain(
+                env::var(WDK_BUILD_TARGET_TRIPLES_ENV_VAR)
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .map(ToString::to_string),
+            ),
+        host_arch,
+    );
+    if cross_compilation_targets.is_empty() {
+        return Ok(());
+    }
+
+    let driver_config = match metadata::Wdk::try_from(&get_cargo_metadata()?) {
+        Ok(wdk_metadata) => Some(wdk_metadata.driver_model),
+        Err(metadata::TryFromCargoMetadataError::NoWdkConfigurationsDetected) => None,
+        Err(error) => return Err(error.into()),
+    };
+    let wdk_content_root = try_detect_wdk_content_root()?;
+    let sdk_version = get_latest_windows_sdk_version(&wdk_content_root.join("Lib"))?;
+    let wdk_layout = wdk_layout::select_wdk_layout(&sdk_version)?;
+    let installed_rust_targets = installed_rust_targets();
+
+    for cpu_architecture in cross_compilation_targets {
+        let target_triple = cpu_architecture.as_target_triple();
+        let missing_prerequisite = |prerequisite: String, remediation: String| {
+            ConfigError::MissingCrossCompilationPrerequisite {
+                target_triple: target_triple.to_string(),
+                prerequisite,
+                remediation,
+            }
+        };
+
+        if let Some(library_directory) = driver_config.as_ref().and_then(|driver_config| {
+            missing_wdk_library_directory(
+                wdk_layout,
+                &wdk_content_root,
+                &sdk_version,
+                driver_config,
+                cpu_architecture,
+            )
+        }) {
+            return Err(missing_prerequisite(
+                format!(
+                    "the {} WDK libraries are not installed in {}",
+                    cpu_architecture.as_windows_str(),
+                    library_directory.display()
+                ),
+                format!(
+                    "Install the WDK (version {sdk_version}) with its {} libraries",
+                    cpu_architecture.as_windows_str()
+                ),
+            ));
+        }
+
+        if let Some(installed_rust_targets) = &installed_rust_targets {
+            if !installed_rust_targets
+                .iter()
+                .any(|installed_target| installed_target == target_triple)
+            {
+                return Err(missing_prerequisite(
+                    "the Rust target is not installed".to_string(),
+                    format!("Run `rustup target add {target_triple}`"),
+                ));
+            }
+        }
+
+        if let Some(vc_tools_install_dir) = env::var_os(VC_TOOLS_INSTALL_DIR_ENV_VAR) {
+            let msvc_library_directory = Path::new(&vc_tools_install_dir)
+                .join("lib")
+                .join(cpu_architecture.as_windows_str());
+            if !msvc_library_directory.is_dir() {
+                return Err(missing_prerequisite(
+                    format!(
+                        "the MSVC linker cannot link for {}, since its libraries are not \
+                         installed in {}",
+                        cpu_architecture.as_windows_str(),
+                        msvc_library_directory.display()
+                    ),
+                    format!(
+                        "Install the \"MSVC C++ {} build tools\" component of Visual Studio",
+                        cpu_architecture.as_windows_str()
+                    ),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the first WDK library directory required to link for
+/// `driver_config` on `cpu_architecture` that does not exist, if any
+fn missing_wdk_library_directory(
+    wdk_layout: &dyn WdkLayout,
+    wdk_content_root: &Path,
+    sdk_version: &str,
+    driver_config: &DriverConfig,
+    cpu_architecture: CpuArchitecture,
+) -> Option<PathBuf> {
+    [
+        Some(wdk_layout.sdk_library_directory(
+            wdk_content_root,
+            sdk_version,
+            driver_config,
+            cpu_architecture,
+        )),
+        wdk_layout.wdf_library_directory(wdk_content_root, driver_config, cpu_architecture),
+    ]
+    .into_iter()
+    .flatten()
+    .find(|library_directory| !library_directory.is_dir())
+}
+
+/// Returns the architectures of `target_triples` that differ from
+/// `host_architecture`, without duplicates
+fn cross_compilation_target_architectures(
+    target_triples: impl IntoIterator<Item = String>,
+    host_architecture: CpuArchitecture,
+) -> Vec<CpuArchitecture> {
+    let mut cpu_architectures = Vec::new();
+    for cpu_architecture in target_triples.into_iter().filter_map(|target_triple| {
+        CpuArchitecture::ALL
+            .into_iter()
+            .find(|cpu_architecture| cpu_architecture.as_target_triple() == target_triple)
+    }) {
+        if cpu_architecture != host_architecture && !cpu_architectures.contains(&cpu_architecture) {
+            cpu_architectures.push(cpu_architecture);
+        }
+    }
+    cpu_architectures
+}
+
+/// Returns the Rust targets installed for the toolchain used by the build, or
+/// `None` if the toolchain is not managed by `rustup`
+pub(crate) fn installed_rust_targets() -> Option<Vec<String>> {
+    let mut rustup_command = Command::new("rustup");
+    rustup_command.args(["target", "list", "--installed"]);
+    if let Ok(toolchain) = env::var(CARGO_MAKE_RUST_DEFAULT_TOOLCHAIN_ENV_VAR) {
+        rustup_command.args(["--toolchain", &toolchain]);
+    }
+
+    let output = rustup_command.output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|line| line.trim().to_string())
+            .collect(),
+    )
+}
+
+/// Returns whether a file named `file_name` exists in any directory of the
+/// `PATH`
+fn is_in_path(file_name: &str) -> bool {
+    env::var_os(PATH_ENV_VAR).is_some_and(|path| {
+        env::split_paths(&path).any(|directory| directory.join(file_name).is_file())
+    })
+}
+
 /// Forwards the specified environment variables in this process to the parent
 /// cargo-make. This is facilitated by printing to `stdout`, and having the
 /// `rust-env-update` plugin parse the printed output.
@@ -612,13 +1200,15 @@ pub fn forward_printed_env_vars(env_vars: impl IntoIterator<Item = impl AsRef<st
 ///
 /// # Errors
 ///
-/// This function returns a [`ConfigError::WdkContentRootDetectionError`] if the
-/// WDK content root directory could not be found, or if the WDK version is
-/// ill-formed.
+/// This function returns:
+/// - [`ConfigError::WdkContentRootDetectionError`] if the WDK content root
+///   directory could not be found
+/// - [`ConfigError::WdkVersionMismatchError`] if the WDK version set in the
+///   environment does not match the version of the detected WDK
+/// - [`ConfigError::WdkVersionStringFormatError`] if the WDK version is
+///   ill-formed
 pub fn setup_wdk_version() -> Result<impl IntoIterator<Item = String>, ConfigError> {
-    let Some(wdk_content_root) = detect_wdk_content_root() else {
-        return Err(ConfigError::WdkContentRootDetectionError);
-    };
+    let wdk_content_root = try_detect_wdk_content_root()?;
     let detected_sdk_version = get_latest_windows_sdk_version(&wdk_content_root.join("Lib"))?;
 
     if let Ok(existing_version) = std::env::var(WDK_VERSION_ENV_VAR) {
@@ -628,7 +1218,10 @@ pub fn setup_wdk_version() -> Result<impl IntoIterator<Item = String>, ConfigErr
             return Ok([WDK_VERSION_ENV_VAR].map(std::string::ToString::to_string));
         }
         // We have a bad version string set somehow.  Return an error.
-        return Err(ConfigError::WdkContentRootDetectionError);
+        return Err(ConfigError::WdkVersionMismatchError {
+            version: existing_version,
+            detected_version: detected_sdk_version,
+        });
     }
 
     if !crate::utils::validate_wdk_version_format(&detected_sdk_version) {
@@ -659,18 +1252,17 @@ pub fn setup_infverif_for_samples<S: AsRef<str> + ToString + ?Sized>(
 ) -> Result<impl IntoIterator<Item = String>, ConfigError> {
     let validated_version_string = crate::utils::get_wdk_version_number(version)?;
 
-    // Safe to unwrap as we called .parse::<i32>().is_ok() in our call to
-    // validate_wdk_version_format above.
+    // Safe to unwrap as we validated that the build number is a number in our call
+    // to get_wdk_version_number above.
     let version = validated_version_string
-        .parse::<i32>()
+        .parse::<u32>()
         .expect("Unable to parse the build number of the WDK version string as an int!");
-    let sample_flag = if version > MINIMUM_SAMPLES_FLAG_WDK_VERSION {
-        "/samples" // Note: Not currently implemented, so in samples TOML we
-                   // currently skip infverif
-    } else {
-        "/msft"
-    };
-    append_to_space_delimited_env_var(WDK_INF_ADDITIONAL_FLAGS_ENV_VAR, sample_flag);
+    // Note: /samples is not currently implemented, so in samples TOML we currently
+    // skip infverif
+    append_to_space_delimited_env_var(
+        WDK_INF_ADDITIONAL_FLAGS_ENV_VAR,
+        inf_policy::samples_infverif_flag(version),
+    );
 
     Ok([WDK_INF_ADDITIONAL_FLAGS_ENV_VAR].map(std::string::ToString::to_string))
 }
@@ -736,78 +1328,1894 @@ pub fn copy_to_driver_package_folder<P: AsRef<Path>>(path_to_copy: P) -> Result<
     Ok(())
 }
 
-/// Symlinks `rust-driver-makefile.toml` to the `target` folder where it can be
-/// extended from a `Makefile.toml`.
+/// Removes the driver packaging outputs of the current package from the WDK
+/// build output directory.
 ///
-/// This is necessary so that paths in the `rust-driver-makefile.toml` can to be
-/// relative to `CARGO_MAKE_CURRENT_TASK_INITIAL_MAKEFILE_DIRECTORY`
+/// This removes the driver package folder (including the signed `.cat` file and
+/// any files copied into it by custom packaging steps), as well as the `.inf`
+/// and `.sys` files generated while packaging. The build artifacts of the
+/// package itself are left for `cargo clean` to remove. Outputs that do not
+/// exist are skipped.
 ///
 /// # Errors
 ///
-/// This function returns:
-/// - [`ConfigError::CargoMetadataError`] if there is an error executing or
-///   parsing `cargo_metadata`
-/// - [`ConfigError::MultipleWdkBuildCratesDetected`] if there are multiple
-///   versions of the WDK build crate detected
-/// - [`ConfigError::IoError`] if there is an error creating or updating the
-///   symlink to `rust-driver-makefile.toml`
+/// This function returns a [`ConfigError::IoError`] if any of the packaging
+/// outputs exist but cannot be removed
 ///
 /// # Panics
 ///
-/// This function will panic if the `CARGO_MAKE_WORKSPACE_WORKING_DIRECTORY`
-/// environment variable is not set
-pub fn load_rust_driver_makefile() -> Result<(), ConfigError> {
-    load_wdk_build_makefile(RUST_DRIVER_MAKEFILE_NAME)
+/// This function will panic if the `WDK_BUILD_OUTPUT_DIRECTORY` or
+/// `CARGO_MAKE_CRATE_FS_NAME` environment variables are not set
+pub fn clean_driver_package() -> Result<(), ConfigError> {
+    let output_folder_path = get_wdk_build_output_directory();
+    let package_name = get_current_package_name();
+
+    let package_folder_path = output_folder_path.join(format!("{package_name}_package"));
+    if package_folder_path.exists() {
+        std::fs::remove_dir_all(&package_folder_path)?;
+    }
+
+    for generated_file_extension in ["inf", "sys"] {
+        let generated_file_path =
+            output_folder_path.join(format!("{package_name}.{generated_file_extension}"));
+        if generated_file_path.exists() {
+            std::fs::remove_file(&generated_file_path)?;
+        }
+    }
+
+    Ok(())
 }
 
-/// Symlinks `rust-driver-sample-makefile.toml` to the `target` folder where it
-/// can be extended from a `Makefile.toml`.
-///
-/// This is necessary so that paths in the `rust-driver-sample-makefile.toml`
-/// can to be relative to `CARGO_MAKE_CURRENT_TASK_INITIAL_MAKEFILE_DIRECTORY`
+/// Prints a summary of the driver package of the current package after it is
+/// rebuilt by the `watch` task, listing each file in the driver package folder
+/// along with its size.
 ///
 /// # Errors
 ///
-/// This function returns:
-/// - [`ConfigError::CargoMetadataError`] if there is an error executing or
-///   parsing `cargo_metadata`
-/// - [`ConfigError::MultipleWdkBuildCratesDetected`] if there are multiple
-///   versions of the WDK build crate detected
-/// - [`ConfigError::IoError`] if there is an error creating or updating the
-///   symlink to `rust-driver-sample-makefile.toml`
+/// This function returns a [`ConfigError::IoError`] if the driver package
+/// folder cannot be read
 ///
 /// # Panics
 ///
-/// This function will panic if the `CARGO_MAKE_WORKSPACE_WORKING_DIRECTORY`
-/// environment variable is not set
-pub fn load_rust_driver_sample_makefile() -> Result<(), ConfigError> {
-    load_wdk_build_makefile(RUST_DRIVER_SAMPLE_MAKEFILE_NAME)
+/// This function will panic if the `WDK_BUILD_OUTPUT_DIRECTORY` or
+/// `CARGO_MAKE_CRATE_FS_NAME` environment variables are not set
+pub fn print_watch_iteration_summary() -> Result<(), ConfigError> {
+    let package_name = get_current_package_name();
+    let package_folder_path =
+        get_wdk_build_output_directory().join(format!("{package_name}_package"));
+
+    let mut package_files = std::fs::read_dir(&package_folder_path)?
+        .map(|entry| {
+            let entry = entry?;
+            Ok((entry.file_name(), entry.metadata()?.len()))
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    package_files.sort();
+
+    println!(
+        "Rebuilt driver package {package_name} in {}:",
+        package_folder_path.display()
+    );
+    for (file_name, file_size) in package_files {
+        println!("  {} ({file_size} bytes)", file_name.to_string_lossy());
+    }
+
+    Ok(())
 }
 
-/// Symlinks a [`wdk_build`] `cargo-make` makefile to the `target` folder where
-/// it can be extended from a downstream `Makefile.toml`.
+/// Validates that the `WDK_BUILD_GENERATED_BINDINGS_OUT_DIR` environment
+/// variable is set before the `wdk-sys` bindings are regenerated.
 ///
-/// This is necessary so that paths in the [`wdk_build`] makefile can be
-/// relative to `CARGO_MAKE_CURRENT_TASK_INITIAL_MAKEFILE_DIRECTORY`. The
-/// version of `wdk-build` from which the file being symlinked to comes from is
-/// determined by the workding directory of the process that invokes this
-/// function. For example, if this function is ultimately executing in a
-/// `cargo_make` `load_script`, the files will be symlinked from the `wdk-build`
-/// version that is in the `.Cargo.lock` file, and not the `wdk-build` version
-/// specified in the `load_script`.
+/// Without it, the `generate-wdk-sys-bindings` task would silently build
+/// without writing any bindings.
 ///
 /// # Errors
 ///
-/// This function returns:
-/// - [`ConfigError::CargoMetadataError`] if there is an error executing or
-///   parsing `cargo_metadata`
-/// - [`ConfigError::MultipleWdkBuildCratesDetected`] if there are multiple
-///   versions of the WDK build crate detected
-/// - [`ConfigError::IoError`] if there is an error creating or updating the
-///   symlink to the makefile.
-///
-/// # Panics
-///
+/// This function returns a [`ConfigError::GeneratedBindingsOutDirNotSet`] if
+/// the `WDK_BUILD_GENERATED_BINDINGS_OUT_DIR` environment variable is not set
+pub fn validate_generated_bindings_out_dir() -> Result<(), ConfigError> {
+    if env::var_os(GENERATED_BINDINGS_OUT_DIR_ENV_VAR).is_none() {
+        return Err(ConfigError::GeneratedBindingsOutDirNotSet);
+    }
+    Ok(())
+}
+
+/// Validates that the `WDK_BUILD_SYMBOL_SURFACE_OUT_DIR` environment variable
+/// is set before the listings of the items in the `wdk-sys` bindings are
+/// generated.
+///
+/// Without it, the `generate-wdk-sys-symbol-surface` task would silently build
+/// without writing any listings.
+///
+/// # Errors
+///
+/// This function returns a [`ConfigError::SymbolSurfaceError`] if the
+/// `WDK_BUILD_SYMBOL_SURFACE_OUT_DIR` environment variable is not set
+pub fn validate_symbol_surface_out_dir() -> Result<(), ConfigError> {
+    if env::var_os(SYMBOL_SURFACE_OUT_DIR_ENV_VAR).is_none() {
+        return Err(SymbolSurfaceError::DirectoryNotSet {
+            env_var: SYMBOL_SURFACE_OUT_DIR_ENV_VAR,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Compares the listings of the items in the `wdk-sys` bindings in the
+/// `WDK_BUILD_SYMBOL_SURFACE_BASELINE_DIR` directory with the listings in the
+/// `WDK_BUILD_SYMBOL_SURFACE_OUT_DIR` directory.
+///
+/// The items that were added, removed or changed in each configuration are
+/// printed.
+///
+/// # Errors
+///
+/// This function returns a [`ConfigError::SymbolSurfaceError`] if either
+/// environment variable is not set, if the listings cannot be read, or if
+/// items were removed or changed in any configuration
+pub fn compare_symbol_surfaces() -> Result<(), ConfigError> {
+    let directory = |env_var| {
+        env::var_os(env_var)
+            .map(PathBuf::from)
+            .ok_or(SymbolSurfaceError::DirectoryNotSet { env_var })
+    };
+    let baseline_dir = directory(SYMBOL_SURFACE_BASELINE_DIR_ENV_VAR)?;
+    let current_dir = directory(SYMBOL_SURFACE_OUT_DIR_ENV_VAR)?;
+
+    let diffs = symbol_surface::compare_directories(&baseline_dir, &current_dir)?;
+    if diffs.is_empty() {
+        println!(
+            "The listings in {} match the listings in {}",
+            current_dir.display(),
+            baseline_dir.display()
+        );
+        return Ok(());
+    }
+
+    for (relative_path, diff) in &diffs {
+        println!("{}:\n{diff}", relative_path.display());
+    }
+    let count = diffs.iter().filter(|(_, diff)| diff.is_breaking()).count();
+    if count > 0 {
+        return Err(SymbolSurfaceError::BreakingChanges { count }.into());
+    }
+    Ok(())
+}
+
+/// Validates that the directory of prebuilt driver binaries is selected with
+/// `--input` before they are packaged.
+///
+/// Without it, the `package` task would have no driver binaries to package.
+///
+/// # Errors
+///
+/// This function returns a [`ConfigError::PackageInputNotSet`] if `--input`
+/// was not passed
+pub fn validate_package_input() -> Result<(), ConfigError> {
+    if env::var_os(WDK_BUILD_PACKAGE_INPUT_DIR_ENV_VAR).is_none() {
+        return Err(ConfigError::PackageInputNotSet);
+    }
+    Ok(())
+}
+
+/// Copies the prebuilt driver binaries of the current package from the
+/// directory selected by `--input` to the WDK build output directory, where
+/// the packaging tasks expect the outputs of `cargo build`.
+///
+/// The directory must contain the `<package name>.dll` and `<package
+/// name>.pdb` files of the driver. A `<package name>.map` file is copied if it
+/// exists, and a `<package name>.inx` file is used by [`copy_inx_to_output`]
+/// instead of the INX file of the package. This does nothing if `--input` was
+/// not passed.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::PrebuiltBinaryNotFound`] if the driver binary or its PDB is
+///   missing from the directory
+/// - [`ConfigError::IoError`] if the files cannot be copied
+///
+/// # Panics
+///
+/// This function will panic if the `CARGO_MAKE_CRATE_FS_NAME` or
+/// `WDK_BUILD_OUTPUT_DIRECTORY` environment variables are not set
+pub fn copy_prebuilt_binaries_to_output() -> Result<(), ConfigError> {
+    let Some(input_directory) = env::var_os(WDK_BUILD_PACKAGE_INPUT_DIR_ENV_VAR).map(PathBuf::from)
+    else {
+        return Ok(());
+    };
+    let output_directory = get_wdk_build_output_directory();
+    let package_name = get_current_package_name();
+    std::fs::create_dir_all(output_directory.join("deps"))?;
+
+    for file_name in [format!("{package_name}.dll"), format!("{package_name}.pdb")] {
+        let source_file = input_directory.join(&file_name);
+        if !source_file.is_file() {
+            return Err(ConfigError::PrebuiltBinaryNotFound { path: source_file });
+        }
+        std::fs::copy(&source_file, output_directory.join(&file_name))?;
+    }
+
+    let map_file = input_directory.join(format!("{package_name}.map"));
+    if map_file.is_file() {
+        std::fs::copy(
+            &map_file,
+            output_directory.join(format!("deps/{package_name}.map")),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Checks the prerequisites of building and packaging drivers, and prints a
+/// table with the status of each check and how to fix the ones that fail.
+///
+/// The checks are run for the target triple selected by `--target` (or
+/// `--target-arch`), or for the host's architecture if none is selected. See
+/// [`doctor::run_checks`] for the prerequisites that are checked.
+///
+/// # Errors
+///
+/// This function returns [`ConfigError::DoctorChecksFailed`] if any of the
+/// checks fails. Checks that only warn do not cause an error.
+///
+/// # Panics
+///
+/// This function will panic if the CPU architecture cannot be determined from
+/// [`env::consts::ARCH`]
+pub fn doctor() -> Result<(), ConfigError> {
+    let target_triple = env::var(CARGO_MAKE_CRATE_TARGET_TRIPLE_ENV_VAR).unwrap_or_else(|_| {
+        CpuArchitecture::try_from_cargo_str(env::consts::ARCH)
+            .expect("The rust standard library should always set env::consts::ARCH")
+            .as_target_triple()
+            .to_string()
+    });
+
+    let checks = doctor::run_checks(&target_triple);
+    print!("{}", doctor::format_report(&checks));
+
+    let failed_checks = checks
+        .iter()
+        .filter(|check| check.status == doctor::CheckStatus::Fail)
+        .count();
+    if failed_checks > 0 {
+        return Err(ConfigError::DoctorChecksFailed { failed_checks });
+    }
+    Ok(())
+}
+
+/// Prints the cache hit statistics of the compiler cache that rustc
+/// invocations were run through with `--rustc-wrapper` (or the
+/// `RUSTC_WRAPPER` environment variable).
+///
+/// Statistics are only reported for `sccache`, by running
+/// `sccache --show-stats`. Its statistics accumulate across all the builds
+/// served by the same `sccache` server, so running this task after building
+/// every package of a workspace reports the statistics of the whole build.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::CompilerCacheStatsError`] if `sccache --show-stats` fails
+/// - [`ConfigError::IoError`] if `sccache` cannot be run
+pub fn report_compiler_cache_stats() -> Result<(), ConfigError> {
+    let Some(rustc_wrapper) =
+        env::var_os(RUSTC_WRAPPER_ENV_VAR).filter(|rustc_wrapper| !rustc_wrapper.is_empty())
+    else {
+        println!("No compiler cache is used, since {RUSTC_WRAPPER_ENV_VAR} is not set");
+        return Ok(());
+    };
+
+    if Path::new(&rustc_wrapper)
+        .file_stem()
+        .is_none_or(|file_stem| !file_stem.eq_ignore_ascii_case("sccache"))
+    {
+        println!(
+            "Cache statistics are only reported for sccache, not {}",
+            rustc_wrapper.to_string_lossy()
+        );
+        return Ok(());
+    }
+
+    let exit_status = Command::new(&rustc_wrapper).arg("--show-stats").status()?;
+    if !exit_status.success() {
+        return Err(ConfigError::CompilerCacheStatsError {
+            rustc_wrapper: rustc_wrapper.to_string_lossy().into_owned(),
+            exit_status,
+        });
+    }
+    Ok(())
+}
+
+/// Removes the installed driver packages of the current package from the
+/// driver store.
+///
+/// The driver packages are found by parsing the output of `pnputil
+/// /enum-drivers`, and matching the original name of each driver package
+/// against the INF file of the current package. Each matching driver package
+/// (ex. `oem42.inf`) is then deleted with `pnputil /delete-driver /uninstall`,
+/// which also uninstalls it from any devices using it. If the
+/// `WDK_BUILD_UNINSTALL_FORCE` environment variable is set to `true`, `/force`
+/// is passed to `pnputil` to delete driver packages that are still in use by
+/// devices.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::PnpUtilError`] if `pnputil` fails to enumerate or delete
+///   the driver packages
+/// - [`ConfigError::IoError`] if `pnputil` cannot be run
+///
+/// # Panics
+///
+/// This function will panic if the `CARGO_MAKE_CRATE_FS_NAME` environment
+/// variable is not set
+pub fn uninstall_driver_package() -> Result<(), ConfigError> {
+    let inf_file_name = format!("{}.inf", get_current_package_name());
+    let force = env::var(WDK_BUILD_UNINSTALL_FORCE_ENV_VAR).is_ok_and(|value| value == "true");
+
+    let output = Command::new("pnputil").arg("/enum-drivers").output()?;
+    if !output.status.success() {
+        return Err(ConfigError::PnpUtilError {
+            command: "/enum-drivers".to_string(),
+            exit_status: output.status,
+        });
+    }
+
+    let installed_driver_packages =
+        parse_pnputil_enum_drivers(&String::from_utf8_lossy(&output.stdout))
+            .into_iter()
+            .filter(|driver_package| {
+                driver_package
+                    .original_name
+                    .eq_ignore_ascii_case(&inf_file_name)
+            })
+            .collect::<Vec<_>>();
+    if installed_driver_packages.is_empty() {
+        println!("No installed driver packages found for {inf_file_name}");
+        return Ok(());
+    }
+
+    for driver_package in installed_driver_packages {
+        println!(
+            "Uninstalling {} (original name: {inf_file_name}, version: {})",
+            driver_package.published_name, driver_package.driver_version
+        );
+
+        let mut pnputil_command = Command::new("pnputil");
+        pnputil_command.args([
+            "/delete-driver",
+            &driver_package.published_name,
+            "/uninstall",
+        ]);
+        if force {
+            pnputil_command.arg("/force");
+        }
+
+        let exit_status = pnputil_command.status()?;
+        if !exit_status.success() {
+            return Err(ConfigError::PnpUtilError {
+                command: format!("/delete-driver {}", driver_package.published_name),
+                exit_status,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Driver package in the driver store, as listed by `pnputil /enum-drivers`
+#[derive(Debug, PartialEq, Eq)]
+struct InstalledDriverPackage {
+    /// Name of the INF in the driver store (ex. `oem42.inf`)
+    published_name: String,
+    /// Name of the INF the driver package was installed from
+    original_name: String,
+    /// Date and version of the driver package
+    driver_version: String,
+}
+
+/// Parses the driver packages listed in the output of `pnputil /enum-drivers`.
+///
+/// Each driver package is listed as a block of `Name: Value` lines, separated
+/// from the next by an empty line. Blocks without both a published name and an
+/// original name (ex. the `Microsoft PnP Utility` header) are skipped.
+/// Field names are matched against the English output of `pnputil`.
+fn parse_pnputil_enum_drivers(output: &str) -> Vec<InstalledDriverPackage> {
+    let mut installed_driver_packages = Vec::new();
+    let mut fields = BTreeMap::new();
+
+    for line in output.lines().chain([""]) {
+        if let Some((name, value)) = line.split_once(':') {
+            fields.insert(name.trim().to_string(), value.trim().to_string());
+            continue;
+        }
+
+        if line.trim().is_empty() {
+            if let (Some(published_name), Some(original_name)) = (
+                fields.remove("Published Name"),
+                fields.remove("Original Name"),
+            ) {
+                installed_driver_packages.push(InstalledDriverPackage {
+                    published_name,
+                    original_name,
+                    driver_version: fields.remove("Driver Version").unwrap_or_default(),
+                });
+            }
+            fields.clear();
+        }
+    }
+
+    installed_driver_packages
+}
+
+/// Applies the Driver Verifier settings selected by `--verifier` to the
+/// current driver on this machine.
+///
+/// The settings can also be selected with the `WDK_BUILD_DRIVER_VERIFIER`
+/// environment variable. Nothing is done if no settings are selected.
+///
+/// The current settings are queried with `verifier /querysettings`, and the
+/// selected settings are only applied if they are not already, since Driver
+/// Verifier settings only take effect after a reboot. After they are applied,
+/// the machine is rebooted if the `WDK_BUILD_DRIVER_VERIFIER_REBOOT`
+/// environment variable is set to `true`, and a reminder to reboot is printed
+/// otherwise. This must be run from an elevated command prompt.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::DriverVerifierError`] if the selected settings are invalid,
+///   if the driver is a UMDF driver, or if `verifier.exe` or the reboot fails
+/// - [`ConfigError::CargoMetadataError`] if there is an error executing or
+///   parsing `cargo_metadata`
+/// - [`ConfigError::TryFromCargoMetadataError`] if the WDK metadata is invalid
+/// - [`ConfigError::IoError`] if `verifier.exe` or `shutdown.exe` cannot be run
+///
+/// # Panics
+///
+/// This function will panic if the `CARGO_MAKE_CRATE_FS_NAME` environment
+/// variable is not set
+pub fn configure_driver_verifier() -> Result<(), ConfigError> {
+    let Ok(settings) = env::var(DRIVER_VERIFIER_ENV_VAR) else {
+        return Ok(());
+    };
+    let settings = settings.parse::<DriverVerifierSettings>()?;
+
+    let wdk_metadata = metadata::Wdk::try_from(&get_cargo_metadata()?)?;
+    if matches!(wdk_metadata.driver_model, DriverConfig::Umdf(_)) {
+        return Err(DriverVerifierError::UmdfDriver.into());
+    }
+    let driver_binary_name = format!("{}.sys", get_current_package_name());
+
+    let output = Command::new("verifier").arg("/querysettings").output()?;
+    if !output.status.success() {
+        return Err(DriverVerifierError::VerifierCommand {
+            command: "/querysettings".to_string(),
+            exit_status: output.status,
+        }
+        .into());
+    }
+    let verified_drivers =
+        driver_verifier::parse_verified_drivers(&String::from_utf8_lossy(&output.stdout));
+    if settings.is_applied(&verified_drivers, &driver_binary_name) {
+        println!(
+            "Driver Verifier settings ({settings}) are already applied to {driver_binary_name}"
+        );
+        return Ok(());
+    }
+
+    let verifier_args = settings.verifier_args(&driver_binary_name);
+    println!(
+        "Applying Driver Verifier settings: verifier {}",
+        verifier_args.join(" ")
+    );
+    let exit_status = Command::new("verifier").args(&verifier_args).status()?;
+    if !exit_status.success()
+        && exit_status.code() != Some(driver_verifier::REBOOT_REQUIRED_EXIT_CODE)
+    {
+        return Err(DriverVerifierError::VerifierCommand {
+            command: verifier_args.join(" "),
+            exit_status,
+        }
+        .into());
+    }
+
+    if env::var(DRIVER_VERIFIER_REBOOT_ENV_VAR).is_ok_and(|value| value == "true") {
+        println!("Rebooting for the Driver Verifier settings to take effect");
+        let exit_status = Command::new("shutdown").args(["/r", "/t", "0"]).status()?;
+        if !exit_status.success() {
+            return Err(DriverVerifierError::RebootFailed { exit_status }.into());
+        }
+    } else {
+        println!("Reboot this machine for the Driver Verifier settings to take effect");
+    }
+
+    Ok(())
+}
+
+/// Packages the current driver for each of the architectures selected by
+/// `--target-arch all`, and gathers the driver packages into a
+/// multi-architecture driver package.
+///
+/// The `package-driver-flow` task is run in a separate `cargo make` process for
+/// each architecture, with the `--target` of that architecture and the rest of
+/// the forwarded command line arguments. The driver package of each
+/// architecture is then copied into a subdirectory named after the architecture
+/// (ex. `x64` and `ARM64`) of the driver package folder in the WDK build output
+/// directory.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::MultiArchitecturePackagingError`] if `package-driver-flow`
+///   fails for any of the architectures
+/// - [`ConfigError::IoError`] if `cargo make` cannot be run, or if the driver
+///   packages cannot be copied
+///
+/// # Panics
+///
+/// This function will panic if the `WDK_BUILD_TARGET_TRIPLES`,
+/// `WDK_BUILD_OUTPUT_DIRECTORY`, `CARGO_MAKE_PROFILE`,
+/// `CARGO_MAKE_WORKING_DIRECTORY` or `CARGO_MAKE_CRATE_FS_NAME` environment
+/// variables are not set
+pub fn package_driver_for_each_architecture() -> Result<(), ConfigError> {
+    let target_triples = env::var(WDK_BUILD_TARGET_TRIPLES_ENV_VAR).unwrap_or_else(|_| {
+        panic!("{WDK_BUILD_TARGET_TRIPLES_ENV_VAR} should be set by the wdk-build-init task")
+    });
+    let forwarded_args = env::var(WDK_BUILD_MULTI_ARCHITECTURE_ARGS_ENV_VAR).unwrap_or_default();
+    let cargo_make_profile = env::var(CARGO_MAKE_PROFILE_ENV_VAR)
+        .unwrap_or_else(|_| panic!("{CARGO_MAKE_PROFILE_ENV_VAR} should be set by cargo-make"));
+    let working_directory = env::var(CARGO_MAKE_WORKING_DIRECTORY_ENV_VAR).unwrap_or_else(|_| {
+        panic!("{CARGO_MAKE_WORKING_DIRECTORY_ENV_VAR} should be set by cargo-make")
+    });
+
+    let output_directory = get_wdk_build_output_directory();
+    let package_folder_name = format!("{}_package", get_current_package_name());
+    let multi_architecture_package_folder_path = output_directory.join(&package_folder_name);
+    if multi_architecture_package_folder_path.exists() {
+        std::fs::remove_dir_all(&multi_architecture_package_folder_path)?;
+    }
+
+    for target_triple in target_triples.split_whitespace() {
+        let exit_status = package_driver_command(
+            &cargo_make_profile,
+            &forwarded_args,
+            target_triple,
+            Path::new(&working_directory),
+        )
+        .status()?;
+        if !exit_status.success() {
+            return Err(ConfigError::MultiArchitecturePackagingError {
+                target_triple: target_triple.to_string(),
+                exit_status,
+            });
+        }
+
+        let cpu_architecture = target_triple
+            .split('-')
+            .next()
+            .and_then(CpuArchitecture::try_from_cargo_str)
+            .expect(
+                "target triples selected by --target-arch should have a supported architecture",
+            );
+        // Building with `--target` places the build output in a subdirectory named after the target triple: https://doc.rust-lang.org/cargo/guide/build-cache.html#build-cache
+        let architecture_output_directory = output_directory
+            .parent()
+            .expect("WDK build output directory should be in a target directory")
+            .join(target_triple)
+            .join(
+                output_directory
+                    .file_name()
+                    .expect("WDK build output directory should end with the profile directory"),
+            );
+        copy_directory(
+            &architecture_output_directory.join(&package_folder_name),
+            &multi_architecture_package_folder_path.join(cpu_architecture.as_windows_str()),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Returns the `cargo make` command that packages the driver for
+/// `target_triple`.
+///
+/// The variables that select the multi-architecture packaging flow are removed
+/// from the environment of the command, since cargo-make forwards them to it,
+/// which would make its `package-driver-flow` package every architecture again.
+fn package_driver_command(
+    cargo_make_profile: &str,
+    forwarded_args: &str,
+    target_triple: &str,
+    working_directory: &Path,
+) -> Command {
+    let mut command = Command::new("cargo");
+    command
+        .args([
+            "make",
+            "--profile",
+            cargo_make_profile,
+            "package-driver-flow",
+        ])
+        .args(forwarded_args.split_whitespace())
+        .args(["--target", target_triple])
+        .env_remove(WDK_BUILD_TARGET_TRIPLES_ENV_VAR)
+        .env_remove(WDK_BUILD_MULTI_ARCHITECTURE_ARGS_ENV_VAR)
+        .current_dir(working_directory);
+    command
+}
+
+/// Detects whether the target directory contains artifacts built with a
+/// different WDK or WDK configuration than the current build.
+///
+/// The WDK version and the WDK configuration in the Cargo metadata are stored
+/// in a fingerprint file in the WDK build output directory. When they change,
+/// stale artifacts (ex. `wdk-sys` bindings generated for a different driver
+/// model) would otherwise cause confusing build errors. If the
+/// `WDK_BUILD_AUTO_CLEAN` environment variable is set to `true`, the packages
+/// whose build depends on the WDK configuration are cleaned with `cargo clean`
+/// instead of returning an error. Builds without any WDK configuration are not
+/// fingerprinted.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::CargoMetadataError`] if there is an error executing or
+///   parsing `cargo_metadata`
+/// - [`ConfigError::TryFromCargoMetadataError`] if the WDK metadata is invalid
+/// - [`ConfigError::SerdeError`] if the WDK metadata cannot be serialized
+/// - [`ConfigError::BuildFingerprintError`] if the target directory contains
+///   stale artifacts and `WDK_BUILD_AUTO_CLEAN` is not enabled, or if `cargo
+///   clean` fails
+/// - [`ConfigError::IoError`] if the fingerprint file cannot be read or
+///   written, or `cargo clean` cannot be run
+///
+/// # Panics
+///
+/// This function will panic if the `WDK_BUILD_OUTPUT_DIRECTORY` or
+/// `WDK_BUILD_DETECTED_VERSION` environment variables are not set
+pub fn validate_build_fingerprint() -> Result<(), ConfigError> {
+    let cargo_metadata = get_cargo_metadata()?;
+    let wdk_metadata = match metadata::Wdk::try_from(&cargo_metadata) {
+        Ok(wdk_metadata) => wdk_metadata,
+        Err(metadata::TryFromCargoMetadataError::NoWdkConfigurationsDetected) => return Ok(()),
+        Err(error) => return Err(error.into()),
+    };
+    let wdk_version = env::var(WDK_VERSION_ENV_VAR).unwrap_or_else(|_| {
+        panic!("{WDK_VERSION_ENV_VAR} should be set by the wdk-build-init cargo-make task")
+    });
+    let current_fingerprint = BuildFingerprint::new(&wdk_version, &wdk_metadata)?;
+
+    let output_directory = get_wdk_build_output_directory();
+    let fingerprint_path = output_directory.join(BUILD_FINGERPRINT_FILE_NAME);
+    if fingerprint_path.exists() {
+        let previous_fingerprint =
+            BuildFingerprint::parse(&std::fs::read_to_string(&fingerprint_path)?);
+        let changed_entries = current_fingerprint.changed_entries(&previous_fingerprint);
+
+        if !changed_entries.is_empty() {
+            if !env::var(WDK_BUILD_AUTO_CLEAN_ENV_VAR).is_ok_and(|value| value == "true") {
+                return Err(BuildFingerprintError::StaleBuildArtifacts {
+                    output_directory,
+                    changed_entries,
+                }
+                .into());
+            }
+
+            eprintln!(
+                "WDK or WDK configuration changed ({changed_entries:?}). Cleaning affected \
+                 crates..."
+            );
+            clean_packages(&packages_affected_by_wdk_configuration(&cargo_metadata))?;
+        }
+    }
+
+    std::fs::create_dir_all(&output_directory)?;
+    std::fs::write(&fingerprint_path, current_fingerprint.to_string())?;
+
+    Ok(())
+}
+
+/// Validates the detected WDK against the WDK lock file of the workspace, if
+/// the workspace declares a `workspace.metadata.wdk.lock` section.
+///
+/// If the detected WDK or Windows SDK version differs from the recorded one,
+/// the build fails, or only warns if the section sets `mismatch = "warn"`. A
+/// missing lock file is recorded by [`record_wdk_lock`] after the build
+/// succeeds.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::CargoMetadataError`] if there is an error executing or
+///   parsing `cargo_metadata`
+/// - [`ConfigError::WdkLockError`] if the lock configuration or the lock file
+///   is invalid, or if the detected WDK differs from the recorded one and
+///   mismatches are not configured to only warn
+/// - [`ConfigError::WdkContentRootDetectionError`] if the WDK content root
+///   directory could not be found
+/// - [`ConfigError::IoError`] if the lock file cannot be read
+///
+/// # Panics
+///
+/// This function will panic if the `WDK_BUILD_DETECTED_VERSION` environment
+/// variable is not set
+pub fn validate_wdk_lock() -> Result<(), ConfigError> {
+    let cargo_metadata = get_cargo_metadata()?;
+    let Some(wdk_lock_config) =
+        WdkLockConfig::try_from_workspace_metadata(&cargo_metadata.workspace_metadata)?
+    else {
+        return Ok(());
+    };
+
+    let wdk_lock_path = cargo_metadata.workspace_root.join(WDK_LOCK_FILE_NAME);
+    if !wdk_lock_path.exists() {
+        return Ok(());
+    }
+
+    let locked = WdkLock::parse(&std::fs::read_to_string(&wdk_lock_path)?)?;
+    let mismatches = detect_wdk_lock()?.mismatches(&locked);
+    if mismatches.is_empty() {
+        return Ok(());
+    }
+
+    let error = WdkLockError::VersionMismatch { mismatches };
+    match wdk_lock_config.mismatch {
+        MismatchPolicy::Error => Err(error.into()),
+        MismatchPolicy::Warn => {
+            eprintln!("warning: {error}");
+            Ok(())
+        }
+    }
+}
+
+/// Records the detected WDK in the WDK lock file of the workspace, if the
+/// workspace declares a `workspace.metadata.wdk.lock` section and the lock
+/// file does not exist yet.
+///
+/// This runs after the build succeeds, so that only a WDK that the workspace
+/// builds with is recorded.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::CargoMetadataError`] if there is an error executing or
+///   parsing `cargo_metadata`
+/// - [`ConfigError::WdkLockError`] if the lock configuration is invalid
+/// - [`ConfigError::WdkContentRootDetectionError`] if the WDK content root
+///   directory could not be found
+/// - [`ConfigError::IoError`] if the lock file cannot be written
+///
+/// # Panics
+///
+/// This function will panic if the `WDK_BUILD_DETECTED_VERSION` environment
+/// variable is not set
+pub fn record_wdk_lock() -> Result<(), ConfigError> {
+    let cargo_metadata = get_cargo_metadata()?;
+    if WdkLockConfig::try_from_workspace_metadata(&cargo_metadata.workspace_metadata)?.is_none() {
+        return Ok(());
+    }
+
+    let wdk_lock_path = cargo_metadata.workspace_root.join(WDK_LOCK_FILE_NAME);
+    if wdk_lock_path.exists() {
+        return Ok(());
+    }
+
+    std::fs::write(&wdk_lock_path, detect_wdk_lock()?.to_string())?;
+    println!("Recorded the detected WDK in {wdk_lock_path}");
+    Ok(())
+}
+
+/// Returns the [`WdkLock`] of the detected WDK: the version of its libraries
+/// detected by `wdk-build-init`, and the version of its headers
+fn detect_wdk_lock() -> Result<WdkLock, ConfigError> {
+    let wdk_content_root = try_detect_wdk_content_root()?;
+    Ok(WdkLock {
+        wdk_version: env::var(WDK_VERSION_ENV_VAR).unwrap_or_else(|_| {
+            panic!("{WDK_VERSION_ENV_VAR} should be set by the wdk-build-init cargo-make task")
+        }),
+        sdk_version: get_latest_windows_sdk_version(&wdk_content_root.join("Include"))?,
+    })
+}
+
+/// Runs `cargo clean` for each of the `package_specs`, in the build directory
+/// selected by the forwarded command line arguments
+fn clean_packages(package_specs: &[String]) -> Result<(), ConfigError> {
+    if package_specs.is_empty() {
+        return Ok(());
+    }
+
+    let mut cargo_clean_command = Command::new("cargo");
+    cargo_clean_command.arg("clean");
+    for package_spec in package_specs {
+        cargo_clean_command.args(["--package", package_spec]);
+    }
+    cargo_clean_command.args(
+        env::var(WDK_BUILD_CARGO_CLEAN_FLAGS_ENV_VAR)
+            .unwrap_or_default()
+            .split_whitespace(),
+    );
+
+    let exit_status = cargo_clean_command.status()?;
+    if !exit_status.success() {
+        return Err(BuildFingerprintError::CleanFailed { exit_status }.into());
+    }
+
+    Ok(())
+}
+
+/// Validates the `TargetOSVersion` decorations of the models sections in the
+/// INF file generated for the current package by `stampinf`.
+///
+/// Every decoration listed in the `[Manufacturer]` section must target the CPU
+/// architecture the driver is built for, and must have a corresponding
+/// decorated models section. If `target-os` is configured in the WDK metadata,
+/// every models section must also have a decoration that applies to the
+/// targeted OS build. When a decoration is found to be invalid, the
+/// returned error includes a suggested decoration that matches the current
+/// build configuration.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::IoError`] if the INF file cannot be read
+/// - [`ConfigError::TargetOsParseError`] if the configured `target-os` is
+///   invalid
+/// - [`ConfigError::InfTargetOsDecorationError`] if any of the decorations in
+///   the INF file are invalid
+///
+/// # Panics
+///
+/// This function will panic if the `CARGO_MAKE_CRATE_TARGET_TRIPLE`
+/// environment variable is not set, or does not correspond to a supported CPU
+/// architecture
+pub fn validate_inf_target_os_decorations() -> Result<(), ConfigError> {
+    let inf_path =
+        get_wdk_build_output_directory().join(format!("{}.inf", get_current_package_name()));
+
+    let target_triple = env::var(CARGO_MAKE_CRATE_TARGET_TRIPLE_ENV_VAR).unwrap_or_else(|_| {
+        panic!("{CARGO_MAKE_CRATE_TARGET_TRIPLE_ENV_VAR} should be set by cargo-make")
+    });
+    let cpu_architecture = target_triple
+        .split('-')
+        .next()
+        .and_then(CpuArchitecture::try_from_cargo_str)
+        .unwrap_or_else(|| {
+            panic!("{target_triple} should correspond to a supported CPU architecture")
+        });
+
+    // The minimum OS build can only be checked when `target-os` is set to a named
+    // Windows release in the WDK metadata
+    let minimum_os_build_number = env::var(WDK_BUILD_METADATA_TARGET_OS_ENV_VAR)
+        .ok()
+        .map(|target_os| target_os.parse::<TargetOs>())
+        .transpose()?
+        .and_then(|target_os| target_os.build_number());
+
+    inf::validate_target_os_decorations(
+        &inf_path.to_string_lossy(),
+        &inf::read_inf_file(&inf_path)?,
+        cpu_architecture,
+        minimum_os_build_number,
+    )?;
+
+    Ok(())
+}
+
+/// Applies the INF policy selected by `--inf-policy` to the packaging tasks,
+/// and then returns a list of environment variable names that were updated.
+///
+/// The policy's `infverif` flags are appended to the flags of the `infverif`
+/// task, and the `signtool verify` tasks are enabled if the policy verifies
+/// signatures. Nothing is changed when no policy is selected.
+///
+/// These environment variable names should be passed to
+/// [`forward_printed_env_vars`] to forward values to cargo-make.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::WdkVersionStringFormatError`] if the detected WDK version
+///   is invalid
+/// - [`ConfigError::InfPolicyError`] if the policy file fails to be read or
+///   parsed
+///
+/// # Panics
+///
+/// This function will panic if the `WDK_BUILD_DETECTED_VERSION` environment
+/// variable is not set
+pub fn setup_inf_policy() -> Result<impl IntoIterator<Item = String>, ConfigError> {
+    let Some(inf_policy) = read_inf_policy() else {
+        return Ok(Vec::new());
+    };
+    let rules = inf_policy.rules(get_detected_wdk_build_number()?)?;
+
+    for infverif_flag in &rules.infverif_flags {
+        append_to_space_delimited_env_var(WDK_INF_ADDITIONAL_FLAGS_ENV_VAR, infverif_flag);
+    }
+    if rules.verify_signatures {
+        env::set_var(WDK_BUILD_ENABLE_SIGNTOOL_VERIFY_ENV_VAR, "true");
+    }
+
+    Ok([
+        WDK_INF_ADDITIONAL_FLAGS_ENV_VAR,
+        WDK_BUILD_ENABLE_SIGNTOOL_VERIFY_ENV_VAR,
+    ]
+    .into_iter()
+    .filter(|env_var_name| env::var_os(env_var_name).is_some())
+    .map(ToString::to_string)
+    .collect())
+}
+
+/// Validates that the device setup class declared by the current package's
+/// INF is allowed by the INF policy selected by `--inf-policy`. Nothing is
+/// validated when no policy is selected.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::IoError`] if the INF file cannot be read
+/// - [`ConfigError::WdkVersionStringFormatError`] if the detected WDK version
+///   is invalid
+/// - [`ConfigError::InfPolicyError`] if the policy file fails to be read or
+///   parsed, or the INF's device setup class is not allowed
+///
+/// # Panics
+///
+/// This function will panic if the `WDK_BUILD_DETECTED_VERSION` environment
+/// variable is not set
+pub fn validate_inf_policy() -> Result<(), ConfigError> {
+    let Some(inf_policy) = read_inf_policy() else {
+        return Ok(());
+    };
+    let rules = inf_policy.rules(get_detected_wdk_build_number()?)?;
+
+    let inf_path =
+        get_wdk_build_output_directory().join(format!("{}.inf", get_current_package_name()));
+    rules.validate_class(
+        &inf_policy,
+        &inf_path.to_string_lossy(),
+        &inf::read_inf_file(&inf_path)?,
+    )?;
+
+    Ok(())
+}
+
+/// WDK tool run by a verification step of the driver package
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerificationTool {
+    /// `signtool verify`, run on the file selected by the
+    /// `WDK_BUILD_SIGNTOOL_VERIFY_INPUT_FILE` environment variable
+    SignTool,
+    /// `infverif`, run on the INF file of the current package
+    InfVerif,
+}
+
+/// Runs a verification step of the current package's driver package, and
+/// forwards the output of its tool to the console.
+///
+/// When `--junit-report-dir` is passed, the result of the step, along with its
+/// duration and the output of its tool, is also written to a `JUnit` XML report
+/// in that directory (see [`junit`](crate::junit)).
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::IoError`] if the tool cannot be run, or the report cannot
+///   be written
+/// - [`ConfigError::PackageError`] if the tool fails
+///
+/// # Panics
+///
+/// This function will panic if the `WDK_BUILD_SIGNTOOL_VERIFY_INPUT_FILE`,
+/// `WDK_BUILD_OUTPUT_DIRECTORY` or `CARGO_MAKE_CRATE_FS_NAME` environment
+/// variables needed by the step are not set
+pub fn run_package_verification(tool: VerificationTool) -> Result<(), ConfigError> {
+    let (program, mut args, input_path) = match tool {
+        VerificationTool::SignTool => (
+            "signtool",
+            vec!["verify".to_string(), "/v".to_string(), "/pa".to_string()],
+            PathBuf::from(
+                env::var(WDK_BUILD_SIGNTOOL_VERIFY_INPUT_FILE_ENV_VAR).unwrap_or_else(|_| {
+                    panic!(
+                        "{WDK_BUILD_SIGNTOOL_VERIFY_INPUT_FILE_ENV_VAR} should be set by the \
+                         signtool-verify cargo-make task"
+                    )
+                }),
+            ),
+        ),
+        VerificationTool::InfVerif => (
+            "infverif",
+            [WDK_INF_BASE_FLAGS_ENV_VAR, WDK_INF_ADDITIONAL_FLAGS_ENV_VAR]
+                .into_iter()
+                .filter_map(|env_var_name| env::var(env_var_name).ok())
+                .flat_map(|flags| {
+                    flags
+                        .split_whitespace()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                })
+                .collect(),
+            get_wdk_build_output_directory().join(format!("{}.inf", get_current_package_name())),
+        ),
+    };
+    args.push(input_path.display().to_string());
+
+    let mut command = Command::new(program);
+    command.args(&args);
+    trace!(command = ?command);
+
+    let start = Instant::now();
+    let output = command.output()?;
+    let duration = start.elapsed();
+
+    // The output of the tool is captured for the report, so it is forwarded to the
+    // console here
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    print!("{stdout}");
+    eprint!("{stderr}");
+
+    let failure = (!output.status.success()).then(|| PackageError::ToolFailed {
+        tool: program.to_string(),
+        exit_status: output.status,
+    });
+
+    if let Some(junit_report_dir) = env::var_os(WDK_BUILD_JUNIT_REPORT_DIR_ENV_VAR) {
+        let package_name = get_current_package_name();
+        let step = format!(
+            "{} {}",
+            match tool {
+                VerificationTool::SignTool => "signtool verify",
+                VerificationTool::InfVerif => "infverif",
+            },
+            input_path
+                .file_name()
+                .unwrap_or(input_path.as_os_str())
+                .to_string_lossy()
+        );
+        let test_suite = TestSuite {
+            name: package_name.clone(),
+            test_cases: vec![TestCase {
+                name: step.clone(),
+                duration,
+                output: format!("{stdout}{stderr}"),
+                failure: failure.as_ref().map(ToString::to_string),
+            }],
+        };
+        std::fs::write(
+            Path::new(&junit_report_dir).join(TestSuite::file_name(&package_name, &step)),
+            test_suite.to_string(),
+        )?;
+    }
+
+    failure.map_or(Ok(()), |failure| Err(failure.into()))
+}
+
+/// Returns the INF policy selected by `--inf-policy`, if any
+fn read_inf_policy() -> Option<InfPolicy> {
+    env::var(INF_POLICY_ENV_VAR)
+        .ok()
+        .filter(|inf_policy| !inf_policy.is_empty())
+        .map(|inf_policy| {
+            inf_policy
+                .parse()
+                .unwrap_or_else(|infallible| match infallible {})
+        })
+}
+
+/// Returns the build number of the WDK detected by the `wdk-build-init` task
+fn get_detected_wdk_build_number() -> Result<u32, ConfigError> {
+    let wdk_version = env::var(WDK_VERSION_ENV_VAR).unwrap_or_else(|_| {
+        panic!("{WDK_VERSION_ENV_VAR} should always be set by wdk-build-init cargo make task")
+    });
+    Ok(get_wdk_version_number(&wdk_version)?
+        .parse()
+        .unwrap_or_else(|_| {
+            panic!("Couldn't parse WDK version number! Version number: {wdk_version}")
+        }))
+}
+
+/// Validates that the stack frames of the functions annotated with
+/// `#[max_stack(bytes)]` in the current package's driver binary do not exceed
+/// their declared limits.
+///
+/// The map file generated by the linker is used to resolve the names of the
+/// functions that exceed their limits, if it exists.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::IoError`] if the driver binary or map file cannot be read
+/// - [`ConfigError::StackFrameLimitError`] if the driver binary cannot be
+///   analyzed, or if any of the stack frame limits are exceeded
+pub fn validate_stack_frame_limits() -> Result<(), ConfigError> {
+    let output_directory = get_wdk_build_output_directory();
+    let package_name = get_current_package_name();
+
+    let image = std::fs::read(output_directory.join(format!("{package_name}.dll")))?;
+    let map_file_contents =
+        std::fs::read_to_string(output_directory.join(format!("deps/{package_name}.map"))).ok();
+
+    stack_frame_limits::validate(&image, map_file_contents.as_deref())?;
+    Ok(())
+}
+
+/// Runs the custom packaging steps declared for `stage` in the
+/// `metadata.wdk-packaging` section of the current package's `Cargo.toml`, in
+/// declaration order.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::CargoMetadataError`] if there is an error executing or
+///   parsing `cargo_metadata`
+/// - [`ConfigError::PackagingStepError`] if the `metadata.wdk-packaging`
+///   section is invalid, or if any of the steps fail
+///
+/// # Panics
+///
+/// This function will panic if the `CARGO_MAKE_CRATE_NAME` environment variable
+/// is not set, or if the current package is not present in the `cargo_metadata`
+/// output
+pub fn run_custom_packaging_steps(stage: PackagingStage) -> Result<(), ConfigError> {
+    let cargo_metadata = get_cargo_metadata()?;
+    let current_package = find_current_package(&cargo_metadata);
+
+    let packaging_metadata = PackagingMetadata::try_from_package(current_package)?;
+    if packaging_metadata.steps_for_stage(stage).next().is_none() {
+        return Ok(());
+    }
+
+    let package_name = get_current_package_name();
+    let output_dir = get_wdk_build_output_directory();
+    let template_variables = TemplateVariables {
+        manifest_dir: current_package
+            .manifest_path
+            .parent()
+            .expect("The parsed manifest_path should have a valid parent directory")
+            .into(),
+        package_dir: output_dir.join(format!("{package_name}_package")),
+        output_dir,
+        package_name,
+    };
+    trace!(?stage, template_variables = ?template_variables);
+
+    packaging::run_steps(&packaging_metadata, stage, &template_variables)?;
+    Ok(())
+}
+
+/// Builds the additional binaries declared in the
+/// `metadata.wdk.package.additional-binaries` section of the current package's
+/// `Cargo.toml`, and copies their executables into the driver package folder.
+///
+/// The additional binaries are built with the same cargo profile and target
+/// triple as the driver, or are taken from the directory of prebuilt driver
+/// binaries selected by `--input` (as `<bin name>.exe`). Every executable must
+/// be installed by a `CopyFiles` directive of the driver's stamped INF, so that
+/// it is covered by the catalog file of the driver package.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::CargoMetadataError`] if there is an error executing or
+///   parsing `cargo_metadata`
+/// - [`ConfigError::AdditionalBinariesError`] if the
+///   `metadata.wdk.package.additional-binaries` section is invalid, if the
+///   additional binaries fail to build, or if any of them is not installed by
+///   the INF
+/// - [`ConfigError::PrebuiltBinaryNotFound`] if any of the additional binaries
+///   is missing from the directory selected by `--input`
+/// - [`ConfigError::IoError`] if `cargo build` cannot be run, or if the
+///   executables or the INF cannot be read or copied
+///
+/// # Panics
+///
+/// This function will panic if the `CARGO_MAKE_CRATE_NAME`,
+/// `CARGO_MAKE_CARGO_PROFILE`, `CARGO_MAKE_CRATE_TARGET_TRIPLE`,
+/// `CARGO_MAKE_WORKSPACE_WORKING_DIRECTORY`, `CARGO_MAKE_CRATE_FS_NAME` or
+/// `WDK_BUILD_OUTPUT_DIRECTORY` environment variables are not set, or if the
+/// current package is not present in the `cargo_metadata` output
+pub fn package_additional_binaries() -> Result<(), ConfigError> {
+    let cargo_metadata = get_cargo_metadata()?;
+    let additional_binaries =
+        AdditionalBinariesMetadata::try_from_package(find_current_package(&cargo_metadata))?;
+    if additional_binaries.is_empty() {
+        return Ok(());
+    }
+    let bins_by_package = additional_binaries.resolve(&cargo_metadata)?;
+
+    let executables = match env::var_os(WDK_BUILD_PACKAGE_INPUT_DIR_ENV_VAR) {
+        Some(input_directory) => {
+            find_prebuilt_additional_binaries(&bins_by_package, Path::new(&input_directory))?
+        }
+        None => build_additional_binaries(&bins_by_package)?,
+    };
+    for executable in &executables {
+        copy_to_driver_package_folder(executable)?;
+    }
+
+    let inf_path =
+        get_wdk_build_output_directory().join(format!("{}.inf", get_current_package_name()));
+    let inf_contents = inf::read_inf_file(&inf_path)?;
+    additional_binaries::validate_copy_files_entries(
+        inf_path,
+        &inf_contents,
+        executables
+            .iter()
+            .filter_map(|executable| executable.file_name()?.to_str()),
+    )?;
+
+    Ok(())
+}
+
+/// Builds the additional drivers of the current package, and copies their
+/// driver binaries and symbols into the driver package folder
+///
+/// The additional drivers are declared in the
+/// `metadata.wdk.package.additional-drivers` section of the current package's
+/// `Cargo.toml`. Returns a list of environment variable names that were
+/// updated.
+///
+/// The additional drivers are built with the same cargo profile and target
+/// triple as the driver, or are taken from the directory of prebuilt driver
+/// binaries selected by `--input` (as `<file stem>.dll` and `<file
+/// stem>.pdb`). Every driver binary is copied with the extension of the driver
+/// model (ex. `.sys`), and must be installed by a `CopyFiles` directive of the
+/// driver's stamped INF. The paths of the copied driver binaries are stored in
+/// the `WDK_BUILD_ADDITIONAL_DRIVER_BINARIES` environment variable, so that the
+/// `sign-additional-drivers` task signs them.
+///
+/// These environment variable names should be passed to
+/// [`forward_printed_env_vars`] to forward values to cargo-make.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::CargoMetadataError`] if there is an error executing or
+///   parsing `cargo_metadata`
+/// - [`ConfigError::TryFromCargoMetadataError`] if the WDK metadata is invalid
+/// - [`ConfigError::AdditionalDriversError`] if the
+///   `metadata.wdk.package.additional-drivers` section is invalid, or if the
+///   additional drivers fail to build
+/// - [`ConfigError::AdditionalBinariesError`] if any of the driver binaries is
+///   not installed by the INF
+/// - [`ConfigError::PrebuiltBinaryNotFound`] if any of the additional drivers
+///   is missing from the directory selected by `--input`
+/// - [`ConfigError::IoError`] if `cargo build` cannot be run, or if the driver
+///   binaries or the INF cannot be read or copied
+///
+/// # Panics
+///
+/// This function will panic if the `CARGO_MAKE_CRATE_NAME`,
+/// `CARGO_MAKE_CARGO_PROFILE`, `CARGO_MAKE_CRATE_TARGET_TRIPLE`,
+/// `CARGO_MAKE_WORKSPACE_WORKING_DIRECTORY`, `CARGO_MAKE_CRATE_FS_NAME` or
+/// `WDK_BUILD_OUTPUT_DIRECTORY` environment variables are not set, or if the
+/// current package is not present in the `cargo_metadata` output
+pub fn package_additional_drivers() -> Result<impl IntoIterator<Item = String>, ConfigError> {
+    let cargo_metadata = get_cargo_metadata()?;
+    let current_package = find_current_package(&cargo_metadata);
+    let additional_drivers = AdditionalDriversMetadata::try_from_package(current_package)?;
+    if additional_drivers.is_empty() {
+        return Ok(Vec::new());
+    }
+    let drivers = additional_drivers.resolve(&current_package.name, &cargo_metadata)?;
+    let driver_binary_extension = match metadata::Wdk::try_from(&cargo_metadata)?.driver_model {
+        DriverConfig::Wdm | DriverConfig::Kmdf(_) => "sys",
+        DriverConfig::Umdf(_) => "dll",
+    };
+
+    let built_binaries = match env::var_os(WDK_BUILD_PACKAGE_INPUT_DIR_ENV_VAR) {
+        Some(input_directory) => {
+            find_prebuilt_additional_drivers(&drivers, Path::new(&input_directory))?
+        }
+        None => build_additional_drivers(&drivers)?,
+    };
+
+    let package_folder_path =
+        get_wdk_build_output_directory().join(format!("{}_package", get_current_package_name()));
+    std::fs::create_dir_all(&package_folder_path)?;
+    let mut driver_binaries = Vec::with_capacity(drivers.len());
+    for (driver, (binary, pdb)) in drivers.iter().zip(built_binaries) {
+        let driver_binary = package_folder_path.join(driver.file_name(driver_binary_extension));
+        std::fs::copy(&binary, &driver_binary)?;
+        copy_to_driver_package_folder(pdb)?;
+        driver_binaries.push(driver_binary);
+    }
+
+    let inf_path =
+        get_wdk_build_output_directory().join(format!("{}.inf", get_current_package_name()));
+    let inf_contents = inf::read_inf_file(&inf_path)?;
+    additional_binaries::validate_copy_files_entries(
+        inf_path,
+        &inf_contents,
+        driver_binaries
+            .iter()
+            .filter_map(|driver_binary| driver_binary.file_name()?.to_str()),
+    )?;
+
+    env::set_var(
+        WDK_BUILD_ADDITIONAL_DRIVER_BINARIES_ENV_VAR,
+        env::join_paths(&driver_binaries).expect("driver binary paths should not contain `;`"),
+    );
+    Ok(vec![
+        WDK_BUILD_ADDITIONAL_DRIVER_BINARIES_ENV_VAR.to_string()
+    ])
+}
+
+/// Sets the version that `stampinf` stamps into the `DriverVer` directive of
+/// the current package's INF, and then returns a list of environment variable
+/// names that were updated.
+///
+/// Packages that declare a `metadata.wdk.package.version-info` section are
+/// stamped with their package version, which is also the version of the
+/// `VERSIONINFO` resource embedded into their driver binary. Other packages
+/// are stamped with a version generated by `stampinf`.
+///
+/// These environment variable names should be passed to
+/// [`forward_printed_env_vars`] to forward values to cargo-make.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::CargoMetadataError`] if there is an error executing or
+///   parsing `cargo_metadata`
+/// - [`ConfigError::VersionInfoError`] if the `version-info` section is
+///   invalid, or the package version cannot be used as a driver version
+///
+/// # Panics
+///
+/// This function will panic if the `CARGO_MAKE_CRATE_NAME` environment
+/// variable is not set, or if the current package is not present in the
+/// `cargo_metadata` output
+pub fn setup_driver_version() -> Result<impl IntoIterator<Item = String>, ConfigError> {
+    let cargo_metadata = get_cargo_metadata()?;
+    let package = find_current_package(&cargo_metadata);
+
+    let driver_version = if VersionInfoConfig::try_from_package(package)?.is_some() {
+        package
+            .version
+            .to_string()
+            .parse::<DriverVersion>()?
+            .to_string()
+    } else {
+        "*".to_string()
+    };
+    env::set_var(WDK_BUILD_STAMPINF_DRIVER_VERSION_ENV_VAR, driver_version);
+
+    Ok([WDK_BUILD_STAMPINF_DRIVER_VERSION_ENV_VAR.to_string()])
+}
+
+/// Copies the WDF coinstaller declared by the
+/// `metadata.wdk.package.wdf-coinstaller-version` key of the current package's
+/// `Cargo.toml` from the WDK into the driver package folder.
+///
+/// The coinstaller must be installed by a `CopyFiles` directive, and registered
+/// by a `CoInstallers` section, of the driver's stamped INF.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::CargoMetadataError`] if there is an error executing or
+///   parsing `cargo_metadata`
+/// - [`ConfigError::TryFromCargoMetadataError`] if the WDK metadata is invalid
+/// - [`ConfigError::WdkContentRootDetectionError`] if the WDK content root
+///   directory could not be found
+/// - [`ConfigError::WdfCoInstallerError`] if the declared version is invalid
+///   for the driver, if the coinstaller is not present in the WDK, or if it is
+///   not installed and registered by the INF
+/// - [`ConfigError::IoError`] if the coinstaller or the INF cannot be read or
+///   copied
+///
+/// # Panics
+///
+/// This function will panic if the `CARGO_MAKE_CRATE_NAME`,
+/// `CARGO_MAKE_CRATE_TARGET_TRIPLE`, `CARGO_MAKE_CRATE_FS_NAME` or
+/// `WDK_BUILD_OUTPUT_DIRECTORY` environment variables are not set, if the
+/// target triple does not correspond to a supported CPU architecture, or if the
+/// current package is not present in the `cargo_metadata` output
+pub fn package_wdf_coinstaller() -> Result<(), ConfigError> {
+    let cargo_metadata = get_cargo_metadata()?;
+    let Some(coinstaller_version) =
+        WdfCoInstallerVersion::try_from_package(find_current_package(&cargo_metadata))?
+    else {
+        return Ok(());
+    };
+    let wdk_metadata = metadata::Wdk::try_from(&cargo_metadata)?;
+    let file_name = coinstaller_version.file_name(&wdk_metadata.driver_model)?;
+
+    let target_triple = env::var(CARGO_MAKE_CRATE_TARGET_TRIPLE_ENV_VAR).unwrap_or_else(|_| {
+        panic!("{CARGO_MAKE_CRATE_TARGET_TRIPLE_ENV_VAR} should be set by cargo-make")
+    });
+    let cpu_architecture = target_triple
+        .split('-')
+        .next()
+        .and_then(CpuArchitecture::try_from_cargo_str)
+        .unwrap_or_else(|| {
+            panic!("{target_triple} should correspond to a supported CPU architecture")
+        });
+    let wdk_content_root = try_detect_wdk_content_root()?;
+    let sdk_version = get_latest_windows_sdk_version(&wdk_content_root.join("Lib"))?;
+    let wdf_redist_directory = wdk_layout::select_wdk_layout(&sdk_version)?
+        .wdf_redist_directory(&wdk_content_root, cpu_architecture);
+
+    copy_to_driver_package_folder(wdf_coinstaller::find_coinstaller(
+        &wdf_redist_directory,
+        &file_name,
+    )?)?;
+
+    let inf_path =
+        get_wdk_build_output_directory().join(format!("{}.inf", get_current_package_name()));
+    let inf_contents = inf::read_inf_file(&inf_path)?;
+    wdf_coinstaller::validate_inf_entries(inf_path, &inf_contents, &file_name)?;
+
+    Ok(())
+}
+
+/// Copies the [`BuildProvenance`](crate::build_provenance::BuildProvenance)
+/// written by the build script of the current package into the driver package
+/// folder.
+///
+/// Cargo stores the `OUT_DIR` of each build of the build script in a separate
+/// `build/<package name>-<hash>` directory, so the most recently written
+/// provenance file is copied. A warning is printed if there is none (ex. if the
+/// driver's build script does not call `configure_binary_build`).
+///
+/// # Errors
+///
+/// This function returns a [`ConfigError::IoError`] if the build directory
+/// cannot be read, or if the provenance file cannot be copied
+///
+/// # Panics
+///
+/// This function will panic if the `CARGO_MAKE_CRATE_NAME`,
+/// `CARGO_MAKE_CRATE_FS_NAME` or `WDK_BUILD_OUTPUT_DIRECTORY` environment
+/// variables are not set
+pub fn copy_build_provenance_to_package() -> Result<(), ConfigError> {
+    let current_package_name = env::var(CARGO_MAKE_CRATE_NAME_ENV_VAR)
+        .unwrap_or_else(|_| panic!("{CARGO_MAKE_CRATE_NAME_ENV_VAR} should be set by cargo-make"));
+    let build_script_directory_prefix = format!("{current_package_name}-");
+
+    let build_directory = get_wdk_build_output_directory().join("build");
+    let mut latest_provenance_path = None;
+    if build_directory.is_dir() {
+        for entry in std::fs::read_dir(&build_directory)? {
+            let entry = entry?;
+            // The hash suffix distinguishes the current package from packages whose name
+            // it is a prefix of (ex. `driver` and `driver-utils`)
+            if !entry
+                .file_name()
+                .to_string_lossy()
+                .strip_prefix(&build_script_directory_prefix)
+                .is_some_and(|hash| hash.chars().all(|c| c.is_ascii_hexdigit()))
+            {
+                continue;
+            }
+
+            let provenance_path = entry.path().join("out").join(BUILD_PROVENANCE_FILE_NAME);
+            let Ok(modified) = provenance_path
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+            else {
+                continue;
+            };
+            if latest_provenance_path
+                .as_ref()
+                .is_none_or(|(latest_modified, _)| modified > *latest_modified)
+            {
+                latest_provenance_path = Some((modified, provenance_path));
+            }
+        }
+    }
+
+    let Some((_, provenance_path)) = latest_provenance_path else {
+        eprintln!(
+            "No {BUILD_PROVENANCE_FILE_NAME} was written by the build script of \
+             {current_package_name}. The driver package will not contain build provenance."
+        );
+        return Ok(());
+    };
+    copy_to_driver_package_folder(provenance_path)
+}
+
+/// Returns the paths of the prebuilt executables of the binary targets in
+/// `bins_by_package` in `input_directory`
+fn find_prebuilt_additional_binaries(
+    bins_by_package: &BTreeMap<String, Vec<String>>,
+    input_directory: &Path,
+) -> Result<Vec<PathBuf>, ConfigError> {
+    bins_by_package
+        .values()
+        .flatten()
+        .map(|bin| {
+            let executable = input_directory.join(format!("{bin}.exe"));
+            if executable.is_file() {
+                Ok(executable)
+            } else {
+                Err(ConfigError::PrebuiltBinaryNotFound { path: executable })
+            }
+        })
+        .collect()
+}
+
+/// Returns a `cargo build` command that builds with the cargo profile and
+/// target triple of the driver in the workspace directory, and pipes its JSON
+/// messages to stdout
+fn cargo_build_command() -> Command {
+    let cargo_make_cargo_profile =
+        env::var(CARGO_MAKE_CARGO_PROFILE_ENV_VAR).unwrap_or_else(|_| {
+            panic!("{CARGO_MAKE_CARGO_PROFILE_ENV_VAR} should be set by cargo-make")
+        });
+    let target_triple = env::var(CARGO_MAKE_CRATE_TARGET_TRIPLE_ENV_VAR).unwrap_or_else(|_| {
+        panic!("{CARGO_MAKE_CRATE_TARGET_TRIPLE_ENV_VAR} should be set by cargo-make")
+    });
+    let workspace_directory = env::var(CARGO_MAKE_WORKSPACE_WORKING_DIRECTORY_ENV_VAR)
+        .unwrap_or_else(|_| {
+            panic!("{CARGO_MAKE_WORKSPACE_WORKING_DIRECTORY_ENV_VAR} should be set by cargo-make")
+        });
+
+    let mut command = Command::new("cargo");
+    command
+        .args([
+            "build",
+            "--message-format=json-render-diagnostics",
+            "--profile",
+            &cargo_make_cargo_profile,
+            "--target",
+            &target_triple,
+        ])
+        .current_dir(workspace_directory)
+        .stdout(Stdio::piped());
+    command
+}
+
+/// Returns the paths of the prebuilt driver binaries and symbols of
+/// `additional_drivers` in `input_directory`
+fn find_prebuilt_additional_drivers(
+    additional_drivers: &[AdditionalDriver],
+    input_directory: &Path,
+) -> Result<Vec<(PathBuf, PathBuf)>, ConfigError> {
+    additional_drivers
+        .iter()
+        .map(|driver| {
+            let binary = driver.built_binary_path(input_directory);
+            let pdb = binary.with_extension("pdb");
+            if let Some(missing_path) = [&binary, &pdb].into_iter().find(|path| !path.is_file()) {
+                return Err(ConfigError::PrebuiltBinaryNotFound {
+                    path: missing_path.clone(),
+                });
+            }
+            Ok((binary, pdb))
+        })
+        .collect()
+}
+
+/// Builds the `cdylib` targets of `additional_drivers` with a single `cargo
+/// build`, and returns the paths of their driver binaries and symbols
+fn build_additional_drivers(
+    additional_drivers: &[AdditionalDriver],
+) -> Result<Vec<(PathBuf, PathBuf)>, ConfigError> {
+    let mut command = cargo_build_command();
+    for driver in additional_drivers {
+        command.args(["--package", &driver.package, "--lib"]);
+    }
+    trace!(command = ?command);
+
+    let mut child = command.spawn()?;
+    let filenames_by_package_id = Message::parse_stream(BufReader::new(
+        child
+            .stdout
+            .take()
+            .expect("stdout of cargo build should be piped"),
+    ))
+    .filter_map(|message| match message {
+        Ok(Message::CompilerArtifact(artifact))
+            if artifact.target.kind.iter().any(|kind| kind == "cdylib") =>
+        {
+            Some(Ok((
+                artifact.target.name.replace('-', "_"),
+                artifact.filenames,
+            )))
+        }
+        Ok(_) => None,
+        Err(error) => Some(Err(error)),
+    })
+    .collect::<Result<BTreeMap<_, _>, _>>()?;
+
+    let exit_status = child.wait()?;
+    if !exit_status.success() {
+        return Err(AdditionalDriversError::BuildFailed { exit_status }.into());
+    }
+
+    additional_drivers
+        .iter()
+        .map(|driver| {
+            let filenames = filenames_by_package_id.get(&driver.file_stem);
+            let find_filename = |extension: &str| {
+                filenames
+                    .into_iter()
+                    .flatten()
+                    .find(|filename| filename.extension() == Some(extension))
+                    .map(|filename| filename.clone().into_std_path_buf())
+            };
+            find_filename("dll")
+                .zip(find_filename("pdb"))
+                .ok_or_else(|| {
+                    AdditionalDriversError::MissingDriverBinary {
+                        package: driver.package.clone(),
+                    }
+                    .into()
+                })
+        })
+        .collect()
+}
+
+/// Builds the binary targets in `bins_by_package` with a single `cargo build`,
+/// and returns the paths of their executables
+fn build_additional_binaries(
+    bins_by_package: &BTreeMap<String, Vec<String>>,
+) -> Result<Vec<PathBuf>, ConfigError> {
+    let mut command = cargo_build_command();
+    for (package, bins) in bins_by_package {
+        command.args(["--package", package]);
+        for bin in bins {
+            command.args(["--bin", bin]);
+        }
+    }
+    trace!(command = ?command);
+
+    let mut child = command.spawn()?;
+    let executables_by_bin = Message::parse_stream(BufReader::new(
+        child
+            .stdout
+            .take()
+            .expect("stdout of cargo build should be piped"),
+    ))
+    .filter_map(|message| match message {
+        Ok(Message::CompilerArtifact(artifact)) if artifact.target.is_bin() => artifact
+            .executable
+            .map(|executable| Ok((artifact.target.name, executable.into_std_path_buf()))),
+        Ok(_) => None,
+        Err(error) => Some(Err(error)),
+    })
+    .collect::<Result<BTreeMap<_, _>, _>>()?;
+
+    let exit_status = child.wait()?;
+    if !exit_status.success() {
+        return Err(AdditionalBinariesError::BuildFailed { exit_status }.into());
+    }
+
+    bins_by_package
+        .values()
+        .flatten()
+        .map(|bin| {
+            executables_by_bin.get(bin).cloned().ok_or_else(|| {
+                AdditionalBinariesError::MissingExecutable { bin: bin.clone() }.into()
+            })
+        })
+        .collect()
+}
+
+/// Copies the INX file of the current package to the build output directory
+/// as `<package name>.inf`, where it is stamped by `stampinf`.
+///
+/// If the directory of prebuilt driver binaries selected by `--input` has a
+/// `<package name>.inx` file, it is copied. Otherwise, if the current package's
+/// `Cargo.toml` has a `metadata.wdk.inf` section, the INX file is generated
+/// from it. Otherwise, the `<package name>.inx` file next to the package's
+/// `Cargo.toml` is copied.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::CargoMetadataError`] if there is an error executing or
+///   parsing `cargo_metadata`
+/// - [`ConfigError::TryFromCargoMetadataError`] if the WDK metadata is invalid
+/// - [`ConfigError::InxGenerationError`] if the `metadata.wdk.inf` section is
+///   invalid
+/// - [`ConfigError::IoError`] if the INX file cannot be read or written
+///
+/// # Panics
+///
+/// This function will panic if the `CARGO_MAKE_CRATE_NAME` or
+/// `CARGO_MAKE_WORKING_DIRECTORY` environment variables are not set, or if the
+/// current package is not present in the `cargo_metadata` output
+pub fn copy_inx_to_output() -> Result<(), ConfigError> {
+    let output_folder_path = get_wdk_build_output_directory();
+    if !output_folder_path.exists() {
+        std::fs::create_dir_all(&output_folder_path)?;
+    }
+
+    let package_name = get_current_package_name();
+    let destination_file = output_folder_path.join(format!("{package_name}.inf"));
+
+    if let Some(prebuilt_inx_file) = env::var_os(WDK_BUILD_PACKAGE_INPUT_DIR_ENV_VAR)
+        .map(|input_directory| PathBuf::from(input_directory).join(format!("{package_name}.inx")))
+        .filter(|prebuilt_inx_file| prebuilt_inx_file.is_file())
+    {
+        std::fs::copy(&prebuilt_inx_file, &destination_file)?;
+        return Ok(());
+    }
+
+    let cargo_metadata = get_cargo_metadata()?;
+    if let Some(inx_contents) =
+        generate_inx(&cargo_metadata, find_current_package(&cargo_metadata))?
+    {
+        std::fs::write(&destination_file, inx_contents)?;
+        return Ok(());
+    }
+
+    let source_file = PathBuf::from(
+        env::var(CARGO_MAKE_WORKING_DIRECTORY_ENV_VAR).unwrap_or_else(|_| {
+            panic!("{CARGO_MAKE_WORKING_DIRECTORY_ENV_VAR} should be set by cargo-make")
+        }),
+    )
+    .join(format!("{package_name}.inx"));
+    std::fs::copy(&source_file, &destination_file)?;
+
+    Ok(())
+}
+
+/// Regenerates the `<package name>.inx` file next to the current package's
+/// `Cargo.toml` from its `metadata.wdk.inf` section.
+///
+/// This keeps a checked-in INX file in sync with the package's Cargo metadata.
+/// Packages without a `metadata.wdk.inf` section are left untouched.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::CargoMetadataError`] if there is an error executing or
+///   parsing `cargo_metadata`
+/// - [`ConfigError::TryFromCargoMetadataError`] if the WDK metadata is invalid
+/// - [`ConfigError::InxGenerationError`] if the `metadata.wdk.inf` section is
+///   invalid
+/// - [`ConfigError::IoError`] if the INX file cannot be written
+///
+/// # Panics
+///
+/// This function will panic if the `CARGO_MAKE_CRATE_NAME` environment variable
+/// is not set, or if the current package is not present in the `cargo_metadata`
+/// output
+pub fn sync_inf() -> Result<(), ConfigError> {
+    let cargo_metadata = get_cargo_metadata()?;
+    let current_package = find_current_package(&cargo_metadata);
+
+    let Some(inx_contents) = generate_inx(&cargo_metadata, current_package)? else {
+        println!(
+            "Skipping INX generation for {} package, since it has no metadata.wdk.inf section",
+            current_package.name
+        );
+        return Ok(());
+    };
+
+    let inx_path = current_package
+        .manifest_path
+        .parent()
+        .expect("The parsed manifest_path should have a valid parent directory")
+        .join(format!("{}.inx", get_current_package_name()));
+    std::fs::write(&inx_path, inx_contents)?;
+    println!("Generated {inx_path} from metadata.wdk.inf");
+
+    Ok(())
+}
+
+/// Renders the INX file of `package` from its `metadata.wdk.inf` section,
+/// returning `None` if it has no such section
+fn generate_inx(
+    cargo_metadata: &Metadata,
+    package: &cargo_metadata::Package,
+) -> Result<Option<String>, ConfigError> {
+    let Some(inf_metadata) = InfMetadata::try_from_package(package)? else {
+        return Ok(None);
+    };
+
+    let wdk_metadata = metadata::Wdk::try_from(cargo_metadata)?;
+    Ok(Some(inf_metadata.render_inx(
+        &package.name,
+        &package.version.to_string(),
+        &wdk_metadata.driver_model,
+    )?))
+}
+
+/// Returns the package cargo-make is currently processing from the
+/// `cargo_metadata` output
+///
+/// # Panics
+///
+/// This function will panic if the `CARGO_MAKE_CRATE_NAME` environment variable
+/// is not set, or if the current package is not present in the `cargo_metadata`
+/// output
+fn find_current_package(cargo_metadata: &Metadata) -> &cargo_metadata::Package {
+    // Get the current package name via `CARGO_MAKE_CRATE_NAME_ENV_VAR` instead of
+    // `CARGO_MAKE_CRATE_FS_NAME_ENV_VAR`, since `cargo_metadata` output uses the
+    // non-preprocessed name (ie. - instead of _)
+    let current_package_name = env::var(CARGO_MAKE_CRATE_NAME_ENV_VAR).unwrap_or_else(|_| {
+        panic!(
+            "{} should be set by cargo-make",
+            &CARGO_MAKE_CRATE_NAME_ENV_VAR
+        )
+    });
+    cargo_metadata
+        .packages
+        .iter()
+        .find(|package| package.name == current_package_name)
+        .expect("The current package should be present in the cargo metadata output")
+}
+
+/// Symlinks `rust-driver-makefile.toml` to the `target` folder where it can be
+/// extended from a `Makefile.toml`.
+///
+/// This is necessary so that paths in the `rust-driver-makefile.toml` can to be
+/// relative to `CARGO_MAKE_CURRENT_TASK_INITIAL_MAKEFILE_DIRECTORY`
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::CargoMetadataError`] if there is an error executing or
+///   parsing `cargo_metadata`
+/// - [`ConfigError::MultipleWdkBuildCratesDetected`] if there are multiple
+///   versions of the WDK build crate detected
+/// - [`ConfigError::IoError`] if there is an error creating or updating the
+///   symlink to `rust-driver-makefile.toml`
+///
+/// # Panics
+///
+/// This function will panic if the `CARGO_MAKE_WORKSPACE_WORKING_DIRECTORY`
+/// environment variable is not set
+pub fn load_rust_driver_makefile() -> Result<(), ConfigError> {
+    load_wdk_build_makefile(RUST_DRIVER_MAKEFILE_NAME)
+}
+
+/// Symlinks `rust-driver-sample-makefile.toml` to the `target` folder where it
+/// can be extended from a `Makefile.toml`.
+///
+/// This is necessary so that paths in the `rust-driver-sample-makefile.toml`
+/// can to be relative to `CARGO_MAKE_CURRENT_TASK_INITIAL_MAKEFILE_DIRECTORY`
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::CargoMetadataError`] if there is an error executing or
+///   parsing `cargo_metadata`
+/// - [`ConfigError::MultipleWdkBuildCratesDetected`] if there are multiple
+///   versions of the WDK build crate detected
+/// - [`ConfigError::IoError`] if there is an error creating or updating the
+///   symlink to `rust-driver-sample-makefile.toml`
+///
+/// # Panics
+///
+/// This function will panic if the `CARGO_MAKE_WORKSPACE_WORKING_DIRECTORY`
+/// environment variable is not set
+pub fn load_rust_driver_sample_makefile() -> Result<(), ConfigError> {
+    load_wdk_build_makefile(RUST_DRIVER_SAMPLE_MAKEFILE_NAME)
+}
+
+/// Symlinks a [`wdk_build`] `cargo-make` makefile to the `target` folder where
+/// it can be extended from a downstream `Makefile.toml`.
+///
+/// This is necessary so that paths in the [`wdk_build`] makefile can be
+/// relative to `CARGO_MAKE_CURRENT_TASK_INITIAL_MAKEFILE_DIRECTORY`. The
+/// version of `wdk-build` from which the file being symlinked to comes from is
+/// determined by the workding directory of the process that invokes this
+/// function. For example, if this function is ultimately executing in a
+/// `cargo_make` `load_script`, the files will be symlinked from the `wdk-build`
+/// version that is in the `.Cargo.lock` file, and not the `wdk-build` version
+/// specified in the `load_script`.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::CargoMetadataError`] if there is an error executing or
+///   parsing `cargo_metadata`
+/// - [`ConfigError::MultipleWdkBuildCratesDetected`] if there are multiple
+///   versions of the WDK build crate detected
+/// - [`ConfigError::IoError`] if there is an error creating or updating the
+///   symlink to the makefile.
+///
+/// # Panics
+///
 /// This function will panic if the `CARGO_MAKE_WORKSPACE_WORKING_DIRECTORY`
 /// environment variable is not set
 #[instrument(level = "trace")]
@@ -987,6 +3395,15 @@ pub fn package_driver_flow_condition_script() -> anyhow::Result<()> {
                  contain a cdylib target"
             });
         }
+        if additional_drivers::is_additional_driver(&current_package_name, &cargo_metadata) {
+            return Err(anyhow::anyhow!(
+                "{current_package_name} is packaged as an additional driver of another package"
+            ))
+            .with_context(|| {
+                "Skipping package-driver-flow cargo-make task because the current crate is \
+                 packaged by the driver that declares it in metadata.wdk.package.additional-drivers"
+            });
+        }
 
         match metadata::Wdk::try_from(&cargo_metadata) {
             Err(e @ metadata::TryFromCargoMetadataError::NoWdkConfigurationsDetected) => {
@@ -1069,17 +3486,64 @@ pub fn generate_certificate_condition_script() -> anyhow::Result<()> {
     })
 }
 
-fn configure_wdf_build_output_dir(target_arg: Option<&String>, cargo_make_cargo_profile: &str) {
-    let cargo_make_crate_custom_triple_target_directory =
-        env::var(CARGO_MAKE_CRATE_CUSTOM_TRIPLE_TARGET_DIRECTORY_ENV_VAR).unwrap_or_else(|_| {
-            panic!(
-                "{CARGO_MAKE_CRATE_CUSTOM_TRIPLE_TARGET_DIRECTORY_ENV_VAR} should be set by \
-                 cargo-make."
-            )
-        });
+/// Forwards the `--target-dir` argument to cargo through `CARGO_TARGET_DIR`.
+///
+/// Unlike the space-delimited `CARGO_MAKE_CARGO_BUILD_TEST_FLAGS`, this
+/// supports directories containing spaces. It is also seen by build scripts,
+/// for them to find the workspace from their `OUT_DIR` when the target
+/// directory is outside of the workspace.
+fn configure_target_dir(target_dir: &Path) {
+    // Relative `--target-dir` paths are resolved by cargo relative to the directory
+    // it is invoked from, which is the cargo-make working directory
+    let working_directory = env::var(CARGO_MAKE_WORKING_DIRECTORY_ENV_VAR).unwrap_or_else(|_| {
+        panic!("{CARGO_MAKE_WORKING_DIRECTORY_ENV_VAR} should be set by cargo-make")
+    });
+    env::set_var(
+        CARGO_TARGET_DIR_ENV_VAR,
+        Path::new(&working_directory).join(target_dir),
+    );
+}
+
+fn configure_wdf_build_output_dir(
+    target_arg: Option<&String>,
+    target_dir_arg: Option<&Path>,
+    cargo_make_cargo_profile: &str,
+) {
+    let working_directory = PathBuf::from(
+        env::var(CARGO_MAKE_WORKING_DIRECTORY_ENV_VAR).unwrap_or_else(|_| {
+            panic!("{CARGO_MAKE_WORKING_DIRECTORY_ENV_VAR} should be set by cargo-make")
+        }),
+    );
+
+    // The target directory is resolved like cargo does, which accounts for
+    // `CARGO_TARGET_DIR` (ex. set by `--shared-target-dir`) and `build.target-dir`
+    // in `.cargo/config.toml`, unlike the target directory that cargo-make detects
+    let target_directory = target_directory::resolve_target_directory(
+        &working_directory.join("Cargo.toml"),
+        target_dir_arg,
+        &working_directory,
+    )
+    .unwrap_or_else(|error| {
+        panic!(
+            "the target directory should be resolved: {:#}",
+            anyhow::Error::from(error)
+        )
+    });
+    // The output directory is read by the tasks through cargo-make, which only
+    // supports UTF-8 environment variables
+    let target_directory = match target_directory.into_os_string().into_string() {
+        Ok(target_directory) => target_directory,
+        Err(target_directory) => {
+            eprintln!(
+                "the target directory should be a valid UTF-8 path: {}",
+                Path::new(&target_directory).display()
+            );
+            std::process::exit(CLAP_USAGE_EXIT_CODE);
+        }
+    };
 
     let wdk_build_output_directory = {
-        let mut output_dir = cargo_make_crate_custom_triple_target_directory;
+        let mut output_dir = target_directory;
 
         // Providing the "--target" flag causes the build output to go into a subdirectory: https://doc.rust-lang.org/cargo/guide/build-cache.html#build-cache
         if let Some(target) = target_arg {
@@ -1106,6 +3570,153 @@ fn configure_wdf_build_output_dir(target_arg: Option<&String>, cargo_make_cargo_
     );
 }
 
+/// Reads the default command line arguments declared in the
+/// `workspace.metadata.wdk.defaults` section of the workspace's `Cargo.toml`.
+///
+/// If `cargo metadata` fails, no defaults are applied, since the same failure
+/// is reported by the tasks that require the metadata. Invalid defaults are
+/// reported as a usage error.
+fn read_command_line_defaults() -> CommandLineDefaults {
+    let Ok(cargo_metadata) = MetadataCommand::new()
+        .manifest_path(
+            PathBuf::from(
+                env::var(CARGO_MAKE_WORKING_DIRECTORY_ENV_VAR).unwrap_or_else(|_| {
+                    panic!("{CARGO_MAKE_WORKING_DIRECTORY_ENV_VAR} should be set by cargo-make")
+                }),
+            )
+            .join("Cargo.toml"),
+        )
+        .no_deps()
+        .exec()
+    else {
+        return CommandLineDefaults::default();
+    };
+
+    CommandLineDefaults::try_from_workspace_metadata(&cargo_metadata.workspace_metadata)
+        .unwrap_or_else(|error| {
+            eprintln!("{:#}", anyhow::Error::from(error));
+            std::process::exit(CLAP_USAGE_EXIT_CODE);
+        })
+}
+
+/// Resolves the `--target` and `--target-arch` arguments into the target triple
+/// of the build.
+///
+/// When `--target-arch` selects multiple architectures, every target triple is
+/// forwarded to cargo, so that all of them are built by a single `cargo build`,
+/// and they are recorded in the `WDK_BUILD_TARGET_TRIPLES` environment variable
+/// for [`package_driver_for_each_architecture`]. No single target triple is
+/// returned in this case.
+fn configure_target_architectures(
+    target_arg: Option<&String>,
+    target_arch_arg: Option<TargetArchitecture>,
+) -> Option<String> {
+    let cpu_architectures: &[CpuArchitecture] = match target_arch_arg {
+        None => return target_arg.cloned(),
+        Some(TargetArchitecture::Amd64) => &[CpuArchitecture::Amd64],
+        Some(TargetArchitecture::Arm64) => &[CpuArchitecture::Arm64],
+        Some(TargetArchitecture::All) => &CpuArchitecture::ALL,
+    };
+
+    if let [cpu_architecture] = cpu_architectures {
+        return Some(cpu_architecture.as_target_triple().to_string());
+    }
+
+    for cpu_architecture in cpu_architectures {
+        let target_flag = format!("--target {}", cpu_architecture.as_target_triple());
+        append_to_space_delimited_env_var(CARGO_MAKE_CARGO_BUILD_TEST_FLAGS_ENV_VAR, &target_flag);
+        append_to_space_delimited_env_var(WDK_BUILD_CARGO_CLEAN_FLAGS_ENV_VAR, &target_flag);
+        append_to_space_delimited_env_var(
+            WDK_BUILD_TARGET_TRIPLES_ENV_VAR,
+            cpu_architecture.as_target_triple(),
+        );
+    }
+    None
+}
+
+/// Resolves the `--shared-target-dir` argument relative to the workspace root.
+///
+/// cargo-make runs the tasks of each member of a workspace in the member's
+/// directory, so relative paths are resolved against the workspace root
+/// instead, for every member to share the same target directory.
+fn resolve_shared_target_dir(shared_target_dir: &Path) -> PathBuf {
+    if shared_target_dir.is_absolute() {
+        return shared_target_dir.to_path_buf();
+    }
+
+    let workspace_directory = env::var(CARGO_MAKE_WORKSPACE_WORKING_DIRECTORY_ENV_VAR)
+        .or_else(|_| env::var(CARGO_MAKE_WORKING_DIRECTORY_ENV_VAR))
+        .unwrap_or_else(|_| {
+            panic!("{CARGO_MAKE_WORKING_DIRECTORY_ENV_VAR} should be set by cargo-make")
+        });
+    Path::new(&workspace_directory).join(shared_target_dir)
+}
+
+/// Returns `args`, without the `--target-arch` argument and its value
+fn args_without_target_arch(args: impl IntoIterator<Item = OsString>) -> Vec<String> {
+    let mut args = args
+        .into_iter()
+        .map(|arg| arg.to_string_lossy().into_owned());
+    let mut filtered_args = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--target-arch" {
+            args.next();
+        } else if !arg.starts_with("--target-arch=") {
+            filtered_args.push(arg);
+        }
+    }
+    filtered_args
+}
+
+/// Recursively copies the contents of the `source` directory into the
+/// `destination` directory, creating it if it does not exist
+fn copy_directory(source: &Path, destination: &Path) -> Result<(), ConfigError> {
+    std::fs::create_dir_all(destination)?;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let destination_path = destination.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_directory(&entry.path(), &destination_path)?;
+        } else {
+            std::fs::copy(entry.path(), destination_path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Forwards the arguments that select the build directory to `cargo clean`
+/// commands, via the `WDK_BUILD_CARGO_CLEAN_FLAGS` environment variable. Other
+/// forwarded arguments (ex. `--features`) are not accepted by `cargo clean`.
+/// The target directory is read by `cargo clean` from `CARGO_TARGET_DIR`
+/// instead, since it can contain spaces.
+fn configure_cargo_clean_flags(target_arg: Option<&String>, cargo_make_cargo_profile: &str) {
+    append_to_space_delimited_env_var(
+        WDK_BUILD_CARGO_CLEAN_FLAGS_ENV_VAR,
+        format!("--profile {cargo_make_cargo_profile}"),
+    );
+
+    if let Some(target) = target_arg {
+        append_to_space_delimited_env_var(
+            WDK_BUILD_CARGO_CLEAN_FLAGS_ENV_VAR,
+            format!("--target {target}"),
+        );
+    }
+}
+
+/// Parses the value of an argument that is forwarded to cargo through the
+/// space-delimited `CARGO_MAKE_CARGO_BUILD_TEST_FLAGS`, which would split a
+/// value containing whitespace into several arguments
+fn parse_forwarded_value(value: &str) -> Result<String, String> {
+    if value.contains(char::is_whitespace) {
+        return Err(
+            "values forwarded to cargo cannot contain whitespace. Set configuration values \
+             containing whitespace in .cargo/config.toml instead"
+                .to_string(),
+        );
+    }
+    Ok(value.to_string())
+}
+
 fn append_to_space_delimited_env_var<S, T>(env_var_name: S, string_to_append: T)
 where
     S: AsRef<str>,
@@ -1170,7 +3781,9 @@ pub fn driver_sample_infverif_condition_script() -> anyhow::Result<()> {
 
 #[cfg(test)]
 mod tests {
-    use crate::ConfigError;
+    use std::ffi::{OsStr, OsString};
+
+    use crate::{ConfigError, CpuArchitecture};
 
     const WDK_TEST_OLD_INF_VERSION: &str = "10.0.22061.0";
     const WDK_TEST_NEW_INF_VERSION: &str = "10.0.26100.0";
@@ -1194,4 +3807,131 @@ fn check_env_passing() -> Result<(), ConfigError> {
         assert_eq!(env_string.split(' ').next_back(), Some("/samples"));
         Ok(())
     }
+
+    #[test]
+    fn forwarded_values_cannot_contain_whitespace() {
+        assert_eq!(
+            crate::cargo_make::parse_forwarded_value("build.jobs=4"),
+            Ok("build.jobs=4".to_string())
+        );
+        assert!(
+            crate::cargo_make::parse_forwarded_value(r#"build.rustflags=["-C", "x"]"#).is_err()
+        );
+    }
+
+    #[test]
+    fn package_driver_command_does_not_package_every_architecture_again() {
+        let command = crate::cargo_make::package_driver_command(
+            "release",
+            "--features foo",
+            "aarch64-pc-windows-msvc",
+            std::path::Path::new("driver"),
+        );
+
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                "make",
+                "--profile",
+                "release",
+                "package-driver-flow",
+                "--features",
+                "foo",
+                "--target",
+                "aarch64-pc-windows-msvc",
+            ]
+        );
+        let removed_env_vars = command
+            .get_envs()
+            .filter(|(_, value)| value.is_none())
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert!(removed_env_vars.contains(&OsStr::new(
+            crate::cargo_make::WDK_BUILD_TARGET_TRIPLES_ENV_VAR
+        )));
+        assert!(removed_env_vars.contains(&OsStr::new(
+            crate::cargo_make::WDK_BUILD_MULTI_ARCHITECTURE_ARGS_ENV_VAR
+        )));
+    }
+
+    #[test]
+    fn args_without_target_arch() {
+        let args = [
+            "+nightly",
+            "--release",
+            "--target-arch",
+            "all",
+            "--features",
+            "foo",
+        ]
+        .map(OsString::from);
+        assert_eq!(
+            crate::cargo_make::args_without_target_arch(args),
+            ["+nightly", "--release", "--features", "foo"]
+        );
+
+        let args = ["--target-arch=all", "--release"].map(OsString::from);
+        assert_eq!(
+            crate::cargo_make::args_without_target_arch(args),
+            ["--release"]
+        );
+    }
+
+    #[test]
+    fn cross_compilation_target_architectures() {
+        let target_triples = [
+            "x86_64-pc-windows-msvc",
+            "aarch64-pc-windows-msvc",
+            "aarch64-pc-windows-msvc",
+            "x86_64-unknown-linux-gnu",
+        ]
+        .map(ToString::to_string);
+
+        assert_eq!(
+            crate::cargo_make::cross_compilation_target_architectures(
+                target_triples.clone(),
+                CpuArchitecture::Amd64
+            ),
+            [CpuArchitecture::Arm64]
+        );
+        assert_eq!(
+            crate::cargo_make::cross_compilation_target_architectures(
+                target_triples,
+                CpuArchitecture::Arm64
+            ),
+            [CpuArchitecture::Amd64]
+        );
+        assert!(crate::cargo_make::cross_compilation_target_architectures(
+            [],
+            CpuArchitecture::Amd64
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn parse_pnputil_enum_drivers() {
+        let output =
+            "Microsoft PnP Utility\r\n\r\nPublished Name:     oem3.inf\r\nOriginal Name:      \
+             sample_kmdf_driver.inf\r\nProvider Name:      Contoso\r\nClass Name:         \
+             Sample\r\nClass GUID:         {78a1c341-4539-11d3-b88d-00c04fad5171}\r\nDriver \
+             Version:     10/17/2026 1.0.0.0\r\nSigner Name:        \
+             WDRLocalTestCert\r\n\r\nPublished Name:     oem7.inf\r\nOriginal Name:      \
+             netrndis.inf\r\n";
+
+        assert_eq!(
+            crate::cargo_make::parse_pnputil_enum_drivers(output),
+            [
+                crate::cargo_make::InstalledDriverPackage {
+                    published_name: "oem3.inf".to_string(),
+                    original_name: "sample_kmdf_driver.inf".to_string(),
+                    driver_version: "10/17/2026 1.0.0.0".to_string(),
+                },
+                crate::cargo_make::InstalledDriverPackage {
+                    published_name: "oem7.inf".to_string(),
+                    original_name: "netrndis.inf".to_string(),
+                    driver_version: String::new(),
+                },
+            ]
+        );
+    }
 }
@@ -0,0 +1,264 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Default command line arguments declared in the
+//! `workspace.metadata.wdk.defaults` section of a workspace's `Cargo.toml`
+//!
+//! Teams can declare the arguments that every `cargo make` invocation in the
+//! workspace should use, instead of every developer having to remember them:
+//!
+//! ```toml
+//! [workspace.metadata.wdk.defaults]
+//! target-arch = "all"
+//! profile = "release"
+//! shared-target-dir = "target"
+//! rustc-wrapper = "sccache"
+//! ```
+//!
+//! Arguments passed on the command line always take precedence over these
+//! defaults (ex. `--target x86_64-pc-windows-msvc` overrides the default
+//! `target-arch`, and `--release` overrides the default `profile`).
+
+use std::ffi::OsString;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Key of the section in `workspace.metadata.wdk` that declares the default
+/// command line arguments of `cargo make`
+pub const DEFAULTS_METADATA_KEY: &str = "defaults";
+
+/// Errors that could result from reading the default command line arguments
+#[derive(Debug, Error)]
+pub enum CommandLineDefaultsError {
+    /// Error returned when the `workspace.metadata.wdk.defaults` section fails
+    /// to be deserialized
+    #[error("failed to deserialize workspace.metadata.wdk.defaults")]
+    MetadataDeserialization {
+        /// [`serde_json::Error`] that caused the deserialization to fail
+        #[source]
+        error_source: serde_json::Error,
+    },
+}
+
+/// Metadata specified in the `workspace.metadata.wdk.defaults` section of a
+/// workspace's `Cargo.toml`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct CommandLineDefaults {
+    /// Default value of the `--target-arch` argument, used when neither
+    /// `--target-arch` nor `--target` is passed
+    #[serde(default)]
+    pub target_arch: Option<String>,
+    /// Default value of the `--profile` argument, used when neither
+    /// `--profile` nor `--release` is passed
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Default value of the `--shared-target-dir` argument, used when neither
+    /// `--shared-target-dir` nor `--target-dir` is passed
+    #[serde(default)]
+    pub shared_target_dir: Option<String>,
+    /// Default value of the `--rustc-wrapper` argument, used when
+    /// `--rustc-wrapper` is not passed
+    #[serde(default)]
+    pub rustc_wrapper: Option<String>,
+}
+
+impl CommandLineDefaults {
+    /// Parses the defaults declared in the `workspace.metadata.wdk.defaults`
+    /// section of `workspace_metadata`, if any
+    ///
+    /// # Errors
+    ///
+    /// This function will return
+    /// [`CommandLineDefaultsError::MetadataDeserialization`] if the section
+    /// fails to be deserialized
+    pub fn try_from_workspace_metadata(
+        workspace_metadata: &serde_json::Value,
+    ) -> Result<Self, CommandLineDefaultsError> {
+        match &workspace_metadata["wdk"][DEFAULTS_METADATA_KEY] {
+            serde_json::Value::Null => Ok(Self::default()),
+            defaults_metadata => Self::deserialize(defaults_metadata).map_err(|error_source| {
+                CommandLineDefaultsError::MetadataDeserialization { error_source }
+            }),
+        }
+    }
+
+    /// Returns `args` with the default arguments that are not overridden by
+    /// `args` appended to them.
+    ///
+    /// The default `profile` is also not applied when `cargo_make_profile` is
+    /// `release`, since the `release` cargo-make profile already selects the
+    /// `release` cargo profile.
+    #[must_use]
+    pub fn apply(&self, mut args: Vec<OsString>, cargo_make_profile: &str) -> Vec<OsString> {
+        if let Some(target_arch) = &self.target_arch {
+            if !contains_long_arg(&args, "--target") && !contains_long_arg(&args, "--target-arch") {
+                args.extend(["--target-arch".into(), target_arch.into()]);
+            }
+        }
+
+        if let Some(profile) = &self.profile {
+            if cargo_make_profile != "release"
+                && !contains_long_arg(&args, "--profile")
+                && !contains_long_arg(&args, "--release")
+                && !contains_short_flag(&args, 'r')
+            {
+                args.extend(["--profile".into(), profile.into()]);
+            }
+        }
+
+        if let Some(shared_target_dir) = &self.shared_target_dir {
+            if !contains_long_arg(&args, "--shared-target-dir")
+                && !contains_long_arg(&args, "--target-dir")
+            {
+                args.extend(["--shared-target-dir".into(), shared_target_dir.into()]);
+            }
+        }
+
+        if let Some(rustc_wrapper) = &self.rustc_wrapper {
+            if !contains_long_arg(&args, "--rustc-wrapper") {
+                args.extend(["--rustc-wrapper".into(), rustc_wrapper.into()]);
+            }
+        }
+
+        args
+    }
+}
+
+/// Returns whether `args` contains the `name` long argument, either as a
+/// separate argument or with an `=`-separated value
+fn contains_long_arg(args: &[OsString], name: &str) -> bool {
+    args.iter().any(|arg| {
+        let arg = arg.to_string_lossy();
+        arg == name
+            || arg
+                .strip_prefix(name)
+                .is_some_and(|value| value.starts_with('='))
+    })
+}
+
+/// Returns whether `args` contains the `flag` short flag, either by itself or
+/// in a group of short flags that don't take values (ex. `-rv`)
+fn contains_short_flag(args: &[OsString], flag: char) -> bool {
+    args.iter().any(|arg| {
+        let arg = arg.to_string_lossy();
+        arg.strip_prefix('-').is_some_and(|flags| {
+            flags.contains(flag) && flags.chars().all(|c| matches!(c, 'r' | 'v'))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn parse_defaults_from_workspace_metadata() {
+        let workspace_metadata = serde_json::json!({
+            "wdk": {
+                "driver-model": { "driver-type": "WDM" },
+                "defaults": { "target-arch": "all", "profile": "release" },
+            }
+        });
+
+        assert_eq!(
+            CommandLineDefaults::try_from_workspace_metadata(&workspace_metadata).unwrap(),
+            CommandLineDefaults {
+                target_arch: Some("all".to_string()),
+                profile: Some("release".to_string()),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            CommandLineDefaults::try_from_workspace_metadata(&serde_json::Value::Null).unwrap(),
+            CommandLineDefaults::default()
+        );
+        assert!(
+            CommandLineDefaults::try_from_workspace_metadata(&serde_json::json!({
+                "wdk": { "defaults": { "cert-path": "test.cer" } }
+            }))
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn apply_defaults_not_overridden_by_args() {
+        let defaults = CommandLineDefaults {
+            target_arch: Some("all".to_string()),
+            profile: Some("dev-driver".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            defaults.apply(
+                args(&["wdk-build-init", "--features", "foo"]),
+                "development"
+            ),
+            args(&[
+                "wdk-build-init",
+                "--features",
+                "foo",
+                "--target-arch",
+                "all",
+                "--profile",
+                "dev-driver",
+            ])
+        );
+        assert_eq!(
+            defaults.apply(
+                args(&["wdk-build-init", "--target=aarch64-pc-windows-msvc", "-rv"]),
+                "development"
+            ),
+            args(&["wdk-build-init", "--target=aarch64-pc-windows-msvc", "-rv"])
+        );
+        assert_eq!(
+            defaults.apply(
+                args(&["wdk-build-init", "--target-arch", "amd64"]),
+                "release"
+            ),
+            args(&["wdk-build-init", "--target-arch", "amd64"])
+        );
+    }
+
+    #[test]
+    fn apply_build_cache_defaults() {
+        let defaults = CommandLineDefaults {
+            shared_target_dir: Some("target".to_string()),
+            rustc_wrapper: Some("sccache".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            defaults.apply(args(&["wdk-build-init"]), "development"),
+            args(&[
+                "wdk-build-init",
+                "--shared-target-dir",
+                "target",
+                "--rustc-wrapper",
+                "sccache",
+            ])
+        );
+        assert_eq!(
+            defaults.apply(
+                args(&[
+                    "wdk-build-init",
+                    "--target-dir=out",
+                    "--rustc-wrapper",
+                    "cachepot"
+                ]),
+                "development"
+            ),
+            args(&[
+                "wdk-build-init",
+                "--target-dir=out",
+                "--rustc-wrapper",
+                "cachepot"
+            ])
+        );
+    }
+}
@@ -0,0 +1,240 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Structured trace of the detection decisions made while configuring a build
+//!
+//! When the `WDK_BUILD_DIAGNOSTICS` environment variable is set to `true`,
+//! every detection decision made by `wdk-build` (ex. which WDK content root
+//! candidates were considered, which Windows SDK version was selected, and
+//! which include and library directories were checked) is appended, as soon as
+//! it is made, to a JSON Lines file named [`DIAGNOSTICS_FILE_NAME`] in the
+//! `OUT_DIR` of the build script. Since records are written as they are made,
+//! the trace is complete even when configuration fails, which makes it
+//! possible to report why it failed (see [`report`]).
+//!
+//! Every decision is also emitted as a `tracing` event with the
+//! [`DIAGNOSTICS_TRACING_TARGET`] target, so that it can be captured by
+//! subscribers of the build script.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex, PoisonError},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Environment variable that enables writing the diagnostics file when set to
+/// `true`
+pub const WDK_BUILD_DIAGNOSTICS_ENV_VAR: &str = "WDK_BUILD_DIAGNOSTICS";
+
+/// Name of the diagnostics file written to `OUT_DIR`
+pub const DIAGNOSTICS_FILE_NAME: &str = "wdk-build-diagnostics.jsonl";
+
+/// Target of the `tracing` events emitted for each detection decision
+pub const DIAGNOSTICS_TRACING_TARGET: &str = "wdk_build::diagnostics";
+
+/// Diagnostics file of this build script, if diagnostics are enabled. The file
+/// is created (or truncated) when the first decision is recorded.
+static DIAGNOSTICS_FILE: LazyLock<Option<Mutex<File>>> = LazyLock::new(|| {
+    println!("cargo::rerun-if-env-changed={WDK_BUILD_DIAGNOSTICS_ENV_VAR}");
+
+    let diagnostics_file_path = diagnostics_file_path()?;
+    match File::create(&diagnostics_file_path) {
+        Ok(file) => Some(Mutex::new(file)),
+        Err(error) => {
+            tracing::warn!(
+                "Failed to create diagnostics file at {}: {error}",
+                diagnostics_file_path.display()
+            );
+            None
+        }
+    }
+});
+
+/// A detection decision made by `wdk-build` while configuring a build
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DiagnosticRecord {
+    /// Stage of the configuration that made the decision (ex. `WDK content
+    /// root detection`)
+    pub stage: String,
+    /// Description of the decision
+    pub message: String,
+    /// Inputs and outcome of the decision (ex. the path that was checked, and
+    /// whether it exists)
+    pub fields: BTreeMap<String, String>,
+}
+
+impl fmt::Display for DiagnosticRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.stage, self.message)?;
+        for (index, (key, value)) in self.fields.iter().enumerate() {
+            let separator = if index == 0 { " (" } else { ", " };
+            write!(f, "{separator}{key}: {value}")?;
+        }
+        if !self.fields.is_empty() {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the path of the diagnostics file of the current build script, or
+/// `None` if diagnostics are not enabled, or if `OUT_DIR` is not set (i.e.
+/// outside of build scripts)
+#[must_use]
+pub fn diagnostics_file_path() -> Option<PathBuf> {
+    if !env::var(WDK_BUILD_DIAGNOSTICS_ENV_VAR).is_ok_and(|value| value == "true") {
+        return None;
+    }
+
+    env::var_os("OUT_DIR").map(|out_dir| PathBuf::from(out_dir).join(DIAGNOSTICS_FILE_NAME))
+}
+
+/// Reads the records of the diagnostics file at `path`, in the order the
+/// decisions were made
+///
+/// # Errors
+///
+/// This function will return an error if the file cannot be read, or if any of
+/// its lines is not a valid [`DiagnosticRecord`]
+pub fn read_diagnostics(path: &Path) -> std::io::Result<Vec<DiagnosticRecord>> {
+    BufReader::new(File::open(path)?)
+        .lines()
+        .filter(|line| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+/// Returns a human-readable report of the decisions recorded so far by the
+/// current build script, or `None` if diagnostics are not enabled or nothing
+/// was recorded.
+///
+/// This is intended to be attached to configuration errors reported by build
+/// scripts, to explain why configuration failed.
+#[must_use]
+pub fn report() -> Option<String> {
+    let diagnostics_file_path = diagnostics_file_path()?;
+    let records = read_diagnostics(&diagnostics_file_path).ok()?;
+    if records.is_empty() {
+        return None;
+    }
+
+    Some(records.iter().fold(
+        format!(
+            "wdk-build detection decisions (recorded in {}):",
+            diagnostics_file_path.display()
+        ),
+        |mut report, record| {
+            report.push_str("\n  ");
+            report.push_str(&record.to_string());
+            report
+        },
+    ))
+}
+
+/// Records a detection decision made in `stage`, emitting it as a `tracing`
+/// event and appending it to the diagnostics file if diagnostics are enabled.
+///
+/// Diagnostics are best-effort: failures to write the diagnostics file never
+/// fail the build.
+pub(crate) fn record(stage: &str, message: String, fields: &[(&str, String)]) {
+    tracing::debug!(target: DIAGNOSTICS_TRACING_TARGET, stage, ?fields, "{message}");
+
+    let Some(diagnostics_file) = &*DIAGNOSTICS_FILE else {
+        return;
+    };
+
+    let record = DiagnosticRecord {
+        stage: stage.to_string(),
+        message,
+        fields: fields
+            .iter()
+            .map(|(key, value)| ((*key).to_string(), value.clone()))
+            .collect(),
+    };
+    let Ok(mut line) = serde_json::to_string(&record) else {
+        return;
+    };
+    line.push('\n');
+
+    let mut diagnostics_file = diagnostics_file
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Err(error) = diagnostics_file.write_all(line.as_bytes()) {
+        tracing::warn!("Failed to write to diagnostics file: {error}");
+    }
+}
+
+/// Records a detection decision with a `format!`-style message, and
+/// `key = value` fields whose values implement [`std::fmt::Display`]
+macro_rules! record_diagnostic {
+    ($stage:expr, ($($message:tt)*) $(, $key:ident = $value:expr)* $(,)?) => {
+        $crate::diagnostics::record(
+            $stage,
+            format!($($message)*),
+            &[$((stringify!($key), $value.to_string())),*],
+        )
+    };
+}
+pub(crate) use record_diagnostic;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_record() {
+        let record = DiagnosticRecord {
+            stage: "Windows SDK version selection".to_string(),
+            message: "selected latest Windows SDK version".to_string(),
+            fields: BTreeMap::from([
+                (
+                    "candidates".to_string(),
+                    "10.0.22621.0, 10.0.26100.0".to_string(),
+                ),
+                ("selected".to_string(), "10.0.26100.0".to_string()),
+            ]),
+        };
+
+        assert_eq!(
+            record.to_string(),
+            "[Windows SDK version selection] selected latest Windows SDK version (candidates: \
+             10.0.22621.0, 10.0.26100.0, selected: 10.0.26100.0)"
+        );
+    }
+
+    #[test]
+    fn read_records_in_order() {
+        let records = [
+            DiagnosticRecord {
+                stage: "WDK content root detection".to_string(),
+                message: "candidate rejected".to_string(),
+                fields: BTreeMap::from([("source".to_string(), "WDKContentRoot".to_string())]),
+            },
+            DiagnosticRecord {
+                stage: "WDK content root detection".to_string(),
+                message: "candidate selected".to_string(),
+                fields: BTreeMap::new(),
+            },
+        ];
+        let diagnostics_file_path =
+            env::temp_dir().join(format!("{}-{DIAGNOSTICS_FILE_NAME}", std::process::id()));
+        std::fs::write(
+            &diagnostics_file_path,
+            records
+                .iter()
+                .map(|record| serde_json::to_string(record).unwrap() + "\n")
+                .collect::<String>(),
+        )
+        .unwrap();
+
+        let read_records = read_diagnostics(&diagnostics_file_path);
+        std::fs::remove_file(&diagnostics_file_path).unwrap();
+        assert_eq!(read_records.unwrap(), records);
+    }
+}
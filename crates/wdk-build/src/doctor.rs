@@ -0,0 +1,484 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Diagnosis of the environment that drivers are built in
+//!
+//! Most failures to build a driver for the first time are caused by a missing
+//! or misconfigured prerequisite, and surface as errors that do not name it
+//! (ex. a `bindgen` panic when `libclang` is not installed, or a link failure
+//! when the Rust target is not installed). The `doctor` cargo-make task runs
+//! [`run_checks`] to check each prerequisite up front, and prints a
+//! [`format_report`] table with the status of each check and how to fix the
+//! ones that fail:
+//!
+//! ```text
+//! cargo make doctor
+//! ```
+
+use std::{
+    env,
+    fmt::{self, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    cargo_make,
+    pregenerated_bindings::PREGENERATED_BINDINGS_DIR_ENV_VAR,
+    probe::{self, Component},
+    utils::{detect_wdk_content_root, get_latest_windows_sdk_version},
+    wdk_layout,
+};
+
+/// Minimum major version of LLVM whose `libclang` generates the bindings of
+/// `wdk-sys` correctly
+const MINIMUM_CLANG_MAJOR_VERSION: u32 = 17;
+
+/// Major version of LLVM whose `libclang` fails to generate bindings for ARM64
+const ARM64_INCOMPATIBLE_CLANG_MAJOR_VERSION: u32 = 18;
+
+/// Target feature that statically links the C runtime
+const CRT_STATIC_TARGET_FEATURE: &str = "+crt-static";
+
+/// Status of a [`Check`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CheckStatus {
+    /// The prerequisite is installed and configured
+    Pass,
+    /// The prerequisite is missing or misconfigured, but only some drivers or
+    /// tasks require it
+    Warn,
+    /// The prerequisite is missing or misconfigured, and building or
+    /// packaging drivers fails without it
+    Fail,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Pass => "PASS",
+            Self::Warn => "WARN",
+            Self::Fail => "FAIL",
+        })
+    }
+}
+
+/// Result of checking a prerequisite of building drivers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// Name of the prerequisite (ex. `WDK`)
+    pub name: String,
+    /// Status of the prerequisite
+    pub status: CheckStatus,
+    /// What was found (ex. the detected version)
+    pub detail: String,
+    /// How to fix the prerequisite, if it did not pass
+    pub remediation: Option<String>,
+}
+
+impl Check {
+    fn pass(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: CheckStatus::Pass,
+            detail: detail.into(),
+            remediation: None,
+        }
+    }
+
+    fn problem(
+        status: CheckStatus,
+        name: impl Into<String>,
+        detail: impl Into<String>,
+        remediation: impl Into<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+            remediation: Some(remediation.into()),
+        }
+    }
+}
+
+/// Checks the prerequisites of building and packaging drivers for
+/// `target_triple`: the WDK, LLVM, the Rust target, static linking of the C
+/// runtime, and the packaging tools of the WDK
+#[must_use]
+pub fn run_checks(target_triple: &str) -> Vec<Check> {
+    let mut checks = vec![check_wdk()];
+    checks.extend(check_components());
+    checks.push(check_clang(target_triple));
+    checks.push(check_rust_target(target_triple));
+    checks.push(check_crt_static(target_triple));
+    checks
+}
+
+/// Formats `checks` as a table with one row per check, followed by the
+/// remediation of each check that did not pass
+#[must_use]
+pub fn format_report(checks: &[Check]) -> String {
+    let name_width = checks
+        .iter()
+        .map(|check| check.name.len())
+        .chain(["CHECK".len()])
+        .max()
+        .unwrap_or_default();
+
+    let mut report = format!("{:<name_width$}  STATUS  DETAIL\n", "CHECK");
+    for check in checks {
+        let _ = writeln!(
+            report,
+            "{:<name_width$}  {:<6}  {}",
+            check.name, check.status, check.detail
+        );
+    }
+
+    let remediations = checks
+        .iter()
+        .filter_map(|check| Some((&check.name, check.remediation.as_ref()?)))
+        .collect::<Vec<_>>();
+    if !remediations.is_empty() {
+        report.push_str("\nTo fix:\n");
+        for (name, remediation) in remediations {
+            let _ = writeln!(report, "  {name}: {remediation}");
+        }
+    }
+    report
+}
+
+/// Checks that the WDK is installed, and that its layout is known
+fn check_wdk() -> Check {
+    const NAME: &str = "WDK";
+    const REMEDIATION: &str = "Install the WDK: \
+                               https://learn.microsoft.com/en-us/windows-hardware/drivers/download-the-wdk";
+
+    let Some(wdk_content_root) = detect_wdk_content_root() else {
+        return Check::problem(
+            CheckStatus::Fail,
+            NAME,
+            "no WDK installation detected",
+            REMEDIATION,
+        );
+    };
+    let sdk_version = match get_latest_windows_sdk_version(&wdk_content_root.join("Lib")) {
+        Ok(sdk_version) => sdk_version,
+        Err(error) => {
+            return Check::problem(CheckStatus::Fail, NAME, error.to_string(), REMEDIATION)
+        }
+    };
+    if let Err(error) = wdk_layout::select_wdk_layout(&sdk_version) {
+        return Check::problem(
+            CheckStatus::Fail,
+            NAME,
+            error.to_string(),
+            "Set the WDK_BUILD_WDK_LAYOUT environment variable to the name of a known WDK layout",
+        );
+    }
+    Check::pass(
+        NAME,
+        format!("{sdk_version} in {}", wdk_content_root.display()),
+    )
+}
+
+/// Checks that the tools that package drivers are installed
+fn check_components() -> Vec<Check> {
+    Component::ALL
+        .into_iter()
+        .map(|component| match probe::component_exists(component) {
+            Ok(Some(component_path)) => Check::pass(
+                component.to_string(),
+                component_path.path.display().to_string(),
+            ),
+            Ok(None) => Check::problem(
+                // ApiValidator is not run by any cargo-make task
+                if component == Component::ApiValidator {
+                    CheckStatus::Warn
+                } else {
+                    CheckStatus::Fail
+                },
+                component.to_string(),
+                format!("{} is not installed", component.executable_name()),
+                format!(
+                    "Install the \"{}\" feature of the WDK or Windows SDK",
+                    component.installer_feature()
+                ),
+            ),
+            Err(error) => Check::problem(
+                CheckStatus::Fail,
+                component.to_string(),
+                error.to_string(),
+                "Fix the WDK check first",
+            ),
+        })
+        .collect()
+}
+
+/// Checks that LLVM is installed with a version of `libclang` that generates
+/// the bindings of `wdk-sys` for `target_triple`
+fn check_clang(target_triple: &str) -> Check {
+    const NAME: &str = "LLVM";
+    const REMEDIATION: &str = "Install LLVM 17 (ex. `winget install -i LLVM.LLVM --version 17.0.6 \
+                               --force`) and add it to the PATH: \
+                               https://rust-lang.github.io/rust-bindgen/requirements.html";
+
+    let version = Command::new("clang")
+        .arg("--version")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| parse_clang_version(&String::from_utf8_lossy(&output.stdout)));
+    let Some((major, minor, patch)) = version else {
+        if env::var_os(PREGENERATED_BINDINGS_DIR_ENV_VAR).is_some() {
+            return Check::pass(
+                NAME,
+                format!(
+                    "not found, but not required since {PREGENERATED_BINDINGS_DIR_ENV_VAR} is set"
+                ),
+            );
+        }
+        return Check::problem(
+            CheckStatus::Fail,
+            NAME,
+            "clang was not found in the PATH",
+            REMEDIATION,
+        );
+    };
+
+    let detail = format!("clang {major}.{minor}.{patch}");
+    if major < MINIMUM_CLANG_MAJOR_VERSION {
+        return Check::problem(
+            CheckStatus::Warn,
+            NAME,
+            format!("{detail} is older than LLVM {MINIMUM_CLANG_MAJOR_VERSION}"),
+            REMEDIATION,
+        );
+    }
+    if major == ARM64_INCOMPATIBLE_CLANG_MAJOR_VERSION && target_triple.starts_with("aarch64") {
+        return Check::problem(
+            CheckStatus::Warn,
+            NAME,
+            format!("{detail} fails to generate bindings for ARM64"),
+            "Install LLVM 17 or LLVM 19 or later: \
+             https://github.com/rust-lang/rust-bindgen/issues/2842",
+        );
+    }
+    Check::pass(NAME, detail)
+}
+
+/// Parses the version of clang from the output of `clang --version` (ex.
+/// `clang version 17.0.6`)
+fn parse_clang_version(version_output: &str) -> Option<(u32, u32, u32)> {
+    let version = version_output
+        .lines()
+        .find_map(|line| line.split_once("clang version "))?
+        .1
+        .split_whitespace()
+        .next()?;
+    let mut components = version
+        .split(['.', '-'])
+        .map(|component| component.parse::<u32>().ok());
+    Some((
+        components.next()??,
+        components.next().flatten().unwrap_or_default(),
+        components.next().flatten().unwrap_or_default(),
+    ))
+}
+
+/// Checks that the Rust target for `target_triple` is installed
+fn check_rust_target(target_triple: &str) -> Check {
+    const NAME: &str = "Rust target";
+
+    match cargo_make::installed_rust_targets() {
+        None => Check::problem(
+            CheckStatus::Warn,
+            NAME,
+            "rustup was not found, so the installed targets could not be checked",
+            format!("Make sure that the Rust standard library is installed for {target_triple}"),
+        ),
+        Some(installed_targets)
+            if installed_targets
+                .iter()
+                .any(|target| target == target_triple) =>
+        {
+            Check::pass(NAME, format!("{target_triple} is installed"))
+        }
+        Some(_) => Check::problem(
+            CheckStatus::Fail,
+            NAME,
+            format!("{target_triple} is not installed"),
+            format!("Run `rustup target add {target_triple}`"),
+        ),
+    }
+}
+
+/// Checks that the rustflags configured for `target_triple` statically link
+/// the C runtime, which kernel-mode drivers require
+fn check_crt_static(target_triple: &str) -> Check {
+    const NAME: &str = "Static CRT";
+
+    let rustflags = env_rustflags()
+        .into_iter()
+        .chain(
+            cargo_config_paths()
+                .iter()
+                .filter_map(|config_path| std::fs::read_to_string(config_path).ok())
+                .filter_map(|contents| contents.parse::<toml::Table>().ok())
+                .flat_map(|config| cargo_config_rustflags(&config, target_triple)),
+        )
+        .collect::<Vec<_>>();
+
+    if rustflags_enable_crt_static(&rustflags) {
+        Check::pass(NAME, format!("{CRT_STATIC_TARGET_FEATURE} is enabled"))
+    } else {
+        Check::problem(
+            CheckStatus::Warn,
+            NAME,
+            format!(
+                "{CRT_STATIC_TARGET_FEATURE} is not enabled in RUSTFLAGS or .cargo/config.toml, \
+                 which kernel-mode drivers require"
+            ),
+            "Add `rustflags = [\"-C\", \"target-feature=+crt-static\"]` to the [build] section of \
+             .cargo/config.toml: \
+             https://doc.rust-lang.org/reference/linkage.html#static-and-dynamic-c-runtimes",
+        )
+    }
+}
+
+/// Returns the rustflags set by the `CARGO_ENCODED_RUSTFLAGS`, `RUSTFLAGS` and
+/// `CARGO_BUILD_RUSTFLAGS` environment variables
+fn env_rustflags() -> Vec<String> {
+    let encoded_rustflags = env::var("CARGO_ENCODED_RUSTFLAGS").unwrap_or_default();
+    let rustflags = ["RUSTFLAGS", "CARGO_BUILD_RUSTFLAGS"]
+        .into_iter()
+        .filter_map(|env_var| env::var(env_var).ok())
+        .collect::<Vec<_>>();
+
+    encoded_rustflags
+        .split('\x1f')
+        .chain(rustflags.iter().flat_map(|flags| flags.split_whitespace()))
+        .filter(|flag| !flag.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Returns the paths of the cargo configuration files that apply to the
+/// current directory, in the order that cargo searches them
+fn cargo_config_paths() -> Vec<PathBuf> {
+    let current_dir = env::current_dir().unwrap_or_default();
+    let cargo_home = env::var_os("CARGO_HOME").map(PathBuf::from).or_else(|| {
+        env::var_os("USERPROFILE").map(|user_profile| Path::new(&user_profile).join(".cargo"))
+    });
+
+    current_dir
+        .ancestors()
+        .map(|directory| directory.join(".cargo"))
+        .chain(cargo_home)
+        .flat_map(|directory| [directory.join("config.toml"), directory.join("config")])
+        .filter(|config_path| config_path.is_file())
+        .collect()
+}
+
+/// Returns the `build.rustflags` and `target.<target_triple>.rustflags` of a
+/// cargo configuration file. Rustflags can be configured as an array or as a
+/// space-separated string.
+fn cargo_config_rustflags(config: &toml::Table, target_triple: &str) -> Vec<String> {
+    [
+        config.get("build").and_then(|build| build.get("rustflags")),
+        config
+            .get("target")
+            .and_then(|target| target.get(target_triple))
+            .and_then(|target| target.get("rustflags")),
+    ]
+    .into_iter()
+    .flatten()
+    .flat_map(|rustflags| match rustflags {
+        toml::Value::String(rustflags) => rustflags
+            .split_whitespace()
+            .map(ToString::to_string)
+            .collect(),
+        toml::Value::Array(rustflags) => rustflags
+            .iter()
+            .filter_map(|flag| flag.as_str().map(ToString::to_string))
+            .collect(),
+        _ => Vec::new(),
+    })
+    .collect()
+}
+
+/// Returns whether `rustflags` enable the `crt-static` target feature (ex.
+/// `-C target-feature=+crt-static` or `-Ctarget-feature=+crt-static`)
+fn rustflags_enable_crt_static(rustflags: &[String]) -> bool {
+    rustflags.iter().any(|flag| {
+        flag.split_once("target-feature=")
+            .is_some_and(|(_, features)| {
+                features
+                    .split(',')
+                    .any(|feature| feature == CRT_STATIC_TARGET_FEATURE)
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clang_version_is_parsed() {
+        assert_eq!(
+            parse_clang_version(
+                "clang version 17.0.6\nTarget: x86_64-pc-windows-msvc\nThread model: posix\n"
+            ),
+            Some((17, 0, 6))
+        );
+        assert_eq!(
+            parse_clang_version("Ubuntu clang version 19.1.1 (1ubuntu1)\n"),
+            Some((19, 1, 1))
+        );
+        assert_eq!(parse_clang_version("gcc (GCC) 13.2.0\n"), None);
+    }
+
+    #[test]
+    fn crt_static_is_detected_in_cargo_config() {
+        let config = "[build]\nrustflags = [\"-C\", \"target-feature=+crt-static\"]\n"
+            .parse::<toml::Table>()
+            .unwrap();
+        assert!(rustflags_enable_crt_static(&cargo_config_rustflags(
+            &config,
+            "x86_64-pc-windows-msvc"
+        )));
+
+        let config = "[target.aarch64-pc-windows-msvc]\nrustflags = \
+                      \"-Ctarget-feature=+crt-static\"\n"
+            .parse::<toml::Table>()
+            .unwrap();
+        assert!(rustflags_enable_crt_static(&cargo_config_rustflags(
+            &config,
+            "aarch64-pc-windows-msvc"
+        )));
+        assert!(!rustflags_enable_crt_static(&cargo_config_rustflags(
+            &config,
+            "x86_64-pc-windows-msvc"
+        )));
+    }
+
+    #[test]
+    fn report_lists_remediations_of_failed_checks() {
+        let report = format_report(&[
+            Check::pass("WDK", "10.0.26100.0"),
+            Check::problem(
+                CheckStatus::Fail,
+                "Rust target",
+                "aarch64-pc-windows-msvc is not installed",
+                "Run `rustup target add aarch64-pc-windows-msvc`",
+            ),
+        ]);
+
+        assert_eq!(
+            report,
+            "CHECK        STATUS  DETAIL\nWDK          PASS    10.0.26100.0\nRust target  FAIL    \
+             aarch64-pc-windows-msvc is not installed\n\nTo fix:\n  Rust target: Run `rustup \
+             target add aarch64-pc-windows-msvc`\n"
+        );
+    }
+}
@@ -0,0 +1,210 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Driver Verifier settings applied to a driver when it is deployed
+//!
+//! The `--verifier` argument (or the `WDK_BUILD_DRIVER_VERIFIER` environment
+//! variable) selects the [`DriverVerifierSettings`] that the
+//! `configure-driver-verifier` cargo-make task applies to the driver binary
+//! with `verifier.exe`, on the machine that the driver is deployed to:
+//!
+//! * `standard`: the standard Driver Verifier checks (`verifier /standard`)
+//! * a hexadecimal or decimal value (ex. `0x209BB`): the Driver Verifier checks
+//!   selected by those flags (`verifier /flags`)
+//! * `off`: clears all Driver Verifier settings (`verifier /reset`)
+//!
+//! Driver Verifier settings only take effect after a reboot, so they are not
+//! reapplied when the driver is already verified (or, for `off`, when no
+//! driver is verified).
+
+use std::{fmt, process::ExitStatus, str::FromStr};
+
+use thiserror::Error;
+
+/// Name of the environment variable containing the [`DriverVerifierSettings`]
+/// selected by the `--verifier` argument
+pub const DRIVER_VERIFIER_ENV_VAR: &str = "WDK_BUILD_DRIVER_VERIFIER";
+
+/// Name of the environment variable that enables rebooting the machine after
+/// its Driver Verifier settings are changed
+pub const DRIVER_VERIFIER_REBOOT_ENV_VAR: &str = "WDK_BUILD_DRIVER_VERIFIER_REBOOT";
+
+/// Exit code of `verifier.exe` when the new settings require a reboot to take
+/// effect
+pub const REBOOT_REQUIRED_EXIT_CODE: i32 = 2;
+
+/// Errors that could result from configuring Driver Verifier
+#[derive(Debug, Error)]
+pub enum DriverVerifierError {
+    /// Error returned when the `--verifier` argument is not a valid
+    /// [`DriverVerifierSettings`]
+    #[error(
+        "invalid Driver Verifier settings {value:?}: expected `standard`, `off`, or a hexadecimal \
+         or decimal value of Driver Verifier flags"
+    )]
+    InvalidSettings {
+        /// Value that failed to be parsed
+        value: String,
+    },
+
+    /// Error returned when Driver Verifier is configured for a UMDF driver,
+    /// which it cannot verify
+    #[error(
+        "Driver Verifier only verifies kernel-mode drivers, so it cannot be configured for UMDF \
+         drivers"
+    )]
+    UmdfDriver,
+
+    /// Error returned when `verifier.exe` fails
+    #[error("verifier {command} failed with {exit_status}")]
+    VerifierCommand {
+        /// Arguments `verifier.exe` was run with
+        command: String,
+        /// Exit status of the `verifier.exe` process
+        exit_status: ExitStatus,
+    },
+
+    /// Error returned when the machine fails to be rebooted after its Driver
+    /// Verifier settings are changed
+    #[error("shutdown /r failed with {exit_status}")]
+    RebootFailed {
+        /// Exit status of the `shutdown.exe` process
+        exit_status: ExitStatus,
+    },
+}
+
+/// Driver Verifier settings applied to a deployed driver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriverVerifierSettings {
+    /// Verify the driver with the standard Driver Verifier checks
+    Standard,
+    /// Verify the driver with the Driver Verifier checks selected by these
+    /// flags
+    Flags(u32),
+    /// Clear all Driver Verifier settings
+    Off,
+}
+
+impl DriverVerifierSettings {
+    /// Returns the arguments of `verifier.exe` that apply these settings to
+    /// `driver_binary_name` (ex. `sample_kmdf_driver.sys`)
+    #[must_use]
+    pub fn verifier_args(self, driver_binary_name: &str) -> Vec<String> {
+        match self {
+            Self::Standard => vec![
+                "/standard".to_string(),
+                "/driver".to_string(),
+                driver_binary_name.to_string(),
+            ],
+            Self::Flags(flags) => vec![
+                "/flags".to_string(),
+                format!("{flags:#X}"),
+                "/driver".to_string(),
+                driver_binary_name.to_string(),
+            ],
+            Self::Off => vec!["/reset".to_string()],
+        }
+    }
+
+    /// Returns whether these settings are already applied, given the drivers
+    /// that are currently verified
+    #[must_use]
+    pub fn is_applied(self, verified_drivers: &[String], driver_binary_name: &str) -> bool {
+        match self {
+            Self::Standard | Self::Flags(_) => verified_drivers
+                .iter()
+                .any(|driver| driver.eq_ignore_ascii_case(driver_binary_name)),
+            Self::Off => verified_drivers.is_empty(),
+        }
+    }
+}
+
+impl FromStr for DriverVerifierSettings {
+    type Err = DriverVerifierError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid_settings = || DriverVerifierError::InvalidSettings {
+            value: value.to_string(),
+        };
+
+        match value.trim().to_ascii_lowercase().as_str() {
+            "standard" => Ok(Self::Standard),
+            "off" => Ok(Self::Off),
+            flags => flags
+                .strip_prefix("0x")
+                .map_or_else(|| flags.parse(), |hex| u32::from_str_radix(hex, 16))
+                .map(Self::Flags)
+                .map_err(|_| invalid_settings()),
+        }
+    }
+}
+
+impl fmt::Display for DriverVerifierSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Standard => write!(f, "standard"),
+            Self::Flags(flags) => write!(f, "{flags:#X}"),
+            Self::Off => write!(f, "off"),
+        }
+    }
+}
+
+/// Parses the drivers listed under `Verified drivers:` in the output of
+/// `verifier /querysettings`.
+///
+/// Each verified driver is listed on its own line after the `Verified drivers:`
+/// line, and `None` is listed when no driver is verified. The section name is
+/// matched against the English output of `verifier.exe`.
+#[must_use]
+pub fn parse_verified_drivers(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip_while(|line| !line.trim().eq_ignore_ascii_case("Verified drivers:"))
+        .skip(1)
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.eq_ignore_ascii_case("None"))
+        .map(ToString::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_are_parsed() {
+        assert_eq!(
+            "standard".parse::<DriverVerifierSettings>().unwrap(),
+            DriverVerifierSettings::Standard
+        );
+        assert_eq!(
+            "OFF".parse::<DriverVerifierSettings>().unwrap(),
+            DriverVerifierSettings::Off
+        );
+        assert_eq!(
+            "0x209BB".parse::<DriverVerifierSettings>().unwrap(),
+            DriverVerifierSettings::Flags(0x209BB)
+        );
+        assert_eq!(
+            "9".parse::<DriverVerifierSettings>().unwrap(),
+            DriverVerifierSettings::Flags(9)
+        );
+        assert!(matches!(
+            "special-pool".parse::<DriverVerifierSettings>(),
+            Err(DriverVerifierError::InvalidSettings { .. })
+        ));
+    }
+
+    #[test]
+    fn verified_drivers_are_parsed() {
+        let output = "Special pool: Yes\r\nForce IRQL checking: Yes\r\n\r\nVerified \
+                      drivers:\r\n\r\nsample_kmdf_driver.sys\r\nother.sys\r\n";
+        let verified_drivers = parse_verified_drivers(output);
+        assert_eq!(verified_drivers, ["sample_kmdf_driver.sys", "other.sys"]);
+        assert!(DriverVerifierSettings::Standard
+            .is_applied(&verified_drivers, "SAMPLE_KMDF_DRIVER.sys"));
+        assert!(!DriverVerifierSettings::Off.is_applied(&verified_drivers, "other.sys"));
+
+        assert!(parse_verified_drivers("Verified drivers:\r\n\r\nNone\r\n").is_empty());
+    }
+}
@@ -0,0 +1,269 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Capture and replay of the environment that a build is configured from
+//!
+//! The configuration of a build depends on environment variables (ex.
+//! `WDKContentRoot`, `Path` and the `CARGO_CFG_*` variables set by Cargo) and
+//! on the WDK installation detected from the registry. When two machines make
+//! different configuration decisions, comparing their environments is the
+//! first step to find out why:
+//! - setting [`ENV_SNAPSHOT_OUT_ENV_VAR`] to a file path makes the build
+//!   scripts that configure a build write an [`EnvSnapshot`] of these inputs to
+//!   that file. The detected WDK content root is recorded as `WDKContentRoot`,
+//!   even when it was detected from the registry.
+//! - setting [`ENV_SNAPSHOT_REPLAY_ENV_VAR`] to the path of a snapshot makes
+//!   the build scripts replace these inputs with the ones in the snapshot
+//!   before configuring the build, so that they make the same configuration
+//!   decisions as the machine the snapshot was captured on
+//!
+//! Relative paths in either environment variable are resolved against the
+//! directory of the top-level `Cargo.toml`.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{pregenerated_bindings, utils};
+
+/// Environment variable that points to a file that the [`EnvSnapshot`] of the
+/// build is written to
+pub const ENV_SNAPSHOT_OUT_ENV_VAR: &str = "WDK_BUILD_ENV_SNAPSHOT_OUT";
+
+/// Environment variable that points to a file containing an [`EnvSnapshot`]
+/// that is replayed before the build is configured
+pub const ENV_SNAPSHOT_REPLAY_ENV_VAR: &str = "WDK_BUILD_ENV_SNAPSHOT_REPLAY";
+
+/// Environment variables that affect the configuration of a build
+const CAPTURED_ENV_VARS: [&str; 4] = [
+    "WDKContentRoot",
+    "MicrosoftKitRoot",
+    "WDKKitVersion",
+    "Path",
+];
+
+/// Prefixes of the environment variables that affect the configuration of a
+/// build
+const CAPTURED_ENV_VAR_PREFIXES: [&str; 2] = ["CARGO_CFG_", "WDK_BUILD_"];
+
+/// Errors that could result from capturing or replaying an [`EnvSnapshot`]
+#[derive(Debug, Error)]
+pub enum EnvSnapshotError {
+    /// Error returned when a snapshot fails to be read or written
+    #[error("failed to access the environment snapshot in {path}")]
+    IoError {
+        /// Path of the snapshot
+        path: PathBuf,
+        /// [`std::io::Error`] that caused the access to fail
+        #[source]
+        error_source: std::io::Error,
+    },
+
+    /// Error returned when a snapshot fails to be serialized or deserialized
+    #[error("failed to serialize or deserialize the environment snapshot in {path}")]
+    SerdeError {
+        /// Path of the snapshot
+        path: PathBuf,
+        /// [`serde_json::Error`] that caused the serialization or
+        /// deserialization to fail
+        #[source]
+        error_source: serde_json::Error,
+    },
+}
+
+/// Snapshot of the environment variables that affect the configuration of a
+/// build
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvSnapshot {
+    /// Values of the environment variables, or `None` for the variables that
+    /// were not set
+    pub variables: BTreeMap<String, Option<String>>,
+}
+
+impl EnvSnapshot {
+    /// Captures the environment variables of the current process that affect
+    /// the configuration of a build. `WDKContentRoot` is set to the detected
+    /// WDK content root, if any, so that replaying the snapshot does not
+    /// depend on the registry of the machine it is replayed on.
+    #[must_use]
+    pub fn capture() -> Self {
+        let mut snapshot = Self::from_vars(env::vars());
+        if let Some(wdk_content_root) = utils::detect_wdk_content_root() {
+            snapshot.variables.insert(
+                "WDKContentRoot".to_string(),
+                Some(wdk_content_root.to_string_lossy().into_owned()),
+            );
+        }
+        snapshot
+    }
+
+    /// Creates a snapshot of the variables in `vars` that affect the
+    /// configuration of a build. Variables of [`CAPTURED_ENV_VARS`] that are
+    /// not in `vars` are recorded as not set.
+    fn from_vars(vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        let mut variables = CAPTURED_ENV_VARS
+            .iter()
+            .map(|name| ((*name).to_string(), None))
+            .collect::<BTreeMap<_, _>>();
+        for (name, value) in vars {
+            if is_captured(&name) {
+                // Windows environment variables are case-insensitive (ex. `PATH` and `Path`)
+                let name = CAPTURED_ENV_VARS
+                    .iter()
+                    .find(|captured| captured.eq_ignore_ascii_case(&name))
+                    .map_or(name, |captured| (*captured).to_string());
+                variables.insert(name, Some(value));
+            }
+        }
+        Self { variables }
+    }
+
+    /// Replaces the environment variables of the current process that affect
+    /// the configuration of a build with the ones in the snapshot. Variables
+    /// that are not in the snapshot, or are recorded as not set, are removed.
+    pub fn replay(&self) {
+        for (name, _) in env::vars() {
+            if is_captured(&name) && !self.variables.contains_key(&name) {
+                env::remove_var(name);
+            }
+        }
+        for (name, value) in &self.variables {
+            match value {
+                Some(value) => env::set_var(name, value),
+                None => env::remove_var(name),
+            }
+        }
+    }
+
+    /// Reads the snapshot in `path`
+    ///
+    /// # Errors
+    ///
+    /// This function returns:
+    /// - [`EnvSnapshotError::IoError`] if the file cannot be read
+    /// - [`EnvSnapshotError::SerdeError`] if the file is not a valid snapshot
+    pub fn read(path: &Path) -> Result<Self, EnvSnapshotError> {
+        let contents =
+            fs::read_to_string(path).map_err(|error_source| EnvSnapshotError::IoError {
+                path: path.to_path_buf(),
+                error_source,
+            })?;
+        serde_json::from_str(&contents).map_err(|error_source| EnvSnapshotError::SerdeError {
+            path: path.to_path_buf(),
+            error_source,
+        })
+    }
+
+    /// Writes the snapshot to `path`
+    ///
+    /// # Errors
+    ///
+    /// This function returns:
+    /// - [`EnvSnapshotError::IoError`] if the file cannot be written
+    /// - [`EnvSnapshotError::SerdeError`] if the snapshot fails to be
+    ///   serialized
+    pub fn write(&self, path: &Path) -> Result<(), EnvSnapshotError> {
+        let contents = serde_json::to_string_pretty(self).map_err(|error_source| {
+            EnvSnapshotError::SerdeError {
+                path: path.to_path_buf(),
+                error_source,
+            }
+        })?;
+        fs::write(path, contents + "\n").map_err(|error_source| EnvSnapshotError::IoError {
+            path: path.to_path_buf(),
+            error_source,
+        })
+    }
+}
+
+/// Replays the snapshot in [`ENV_SNAPSHOT_REPLAY_ENV_VAR`], then writes a
+/// snapshot of the resulting environment to [`ENV_SNAPSHOT_OUT_ENV_VAR`].
+///
+/// Either step is skipped if its environment variable is not set. This also
+/// emits the `cargo::rerun-if-*` directives required to reconfigure the build
+/// when either environment variable, or the replayed snapshot, change.
+///
+/// This function only works when called from a `build.rs` file.
+///
+/// # Errors
+///
+/// This function returns an [`EnvSnapshotError`] if the snapshot to replay
+/// cannot be read, or if the captured snapshot cannot be written
+pub fn replay_and_capture() -> Result<(), EnvSnapshotError> {
+    println!("cargo::rerun-if-env-changed={ENV_SNAPSHOT_REPLAY_ENV_VAR}");
+    println!("cargo::rerun-if-env-changed={ENV_SNAPSHOT_OUT_ENV_VAR}");
+
+    if let Some(replay_path) = env::var_os(ENV_SNAPSHOT_REPLAY_ENV_VAR) {
+        let replay_path =
+            pregenerated_bindings::resolve_against_top_level_manifest_directory(replay_path);
+        println!("cargo::rerun-if-changed={}", replay_path.display());
+        EnvSnapshot::read(&replay_path)?.replay();
+    }
+
+    if let Some(out_path) = env::var_os(ENV_SNAPSHOT_OUT_ENV_VAR) {
+        EnvSnapshot::capture().write(
+            &pregenerated_bindings::resolve_against_top_level_manifest_directory(out_path),
+        )?;
+    }
+    Ok(())
+}
+
+/// Returns `true` if the environment variable `name` affects the configuration
+/// of a build. The variables that control snapshots are excluded, so that
+/// replaying a snapshot does not change which snapshots are replayed or
+/// captured.
+fn is_captured(name: &str) -> bool {
+    if name == ENV_SNAPSHOT_OUT_ENV_VAR || name == ENV_SNAPSHOT_REPLAY_ENV_VAR {
+        return false;
+    }
+    CAPTURED_ENV_VARS
+        .iter()
+        .any(|captured| captured.eq_ignore_ascii_case(name))
+        || CAPTURED_ENV_VAR_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_only_contains_configuration_inputs() {
+        let snapshot = EnvSnapshot::from_vars(
+            [
+                ("PATH", r"C:\Windows"),
+                ("CARGO_CFG_TARGET_ARCH", "x86_64"),
+                ("WDK_BUILD_WDK_LAYOUT", "windows-kits-10"),
+                ("WDK_BUILD_ENV_SNAPSHOT_OUT", "env.json"),
+                ("WDKContentRoot", r"C:\Program Files (x86)\Windows Kits\10"),
+                ("USERNAME", "developer"),
+            ]
+            .map(|(name, value)| (name.to_string(), value.to_string())),
+        );
+
+        assert_eq!(
+            snapshot.variables,
+            [
+                ("CARGO_CFG_TARGET_ARCH", Some("x86_64")),
+                ("MicrosoftKitRoot", None),
+                ("Path", Some(r"C:\Windows")),
+                (
+                    "WDKContentRoot",
+                    Some(r"C:\Program Files (x86)\Windows Kits\10")
+                ),
+                ("WDKKitVersion", None),
+                ("WDK_BUILD_WDK_LAYOUT", Some("windows-kits-10")),
+            ]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.map(str::to_string)))
+            .collect::<BTreeMap<_, _>>()
+        );
+    }
+}
@@ -0,0 +1,763 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Private module for utility code related to parsing and validating INF files
+//! generated during driver packaging.
+
+use std::{fmt, path::Path};
+
+use thiserror::Error;
+
+use crate::CpuArchitecture;
+
+const MANUFACTURER_SECTION_NAME: &str = "Manufacturer";
+const TARGET_OS_PLATFORM_PREFIX: &str = "NT";
+const COPY_FILES_DIRECTIVE_NAME: &str = "CopyFiles";
+const VERSION_SECTION_NAME: &str = "Version";
+const CLASS_DIRECTIVE_NAME: &str = "Class";
+/// Architectures that can be named in `TargetOSVersion` decorations
+const INF_ARCHITECTURES: [&str; 5] = ["x86", "amd64", "arm", "arm64", "ia64"];
+
+/// Errors that may occur when validating the `TargetOSVersion` decorations of
+/// the models sections in an INF file
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum InfTargetOsDecorationError {
+    /// Error returned when the INF does not contain a `[Manufacturer]` section
+    #[error("{inf_path} does not contain a [Manufacturer] section")]
+    MissingManufacturerSection {
+        /// Path of the INF file
+        inf_path: String,
+    },
+
+    /// Error returned when a models section is listed without any
+    /// `TargetOSVersion` decorations
+    #[error(
+        "models section `{models_section}` in {inf_path} has no TargetOSVersion decoration. \
+         Consider decorating it with `{suggested_decoration}`"
+    )]
+    MissingDecoration {
+        /// Path of the INF file
+        inf_path: String,
+        /// Name of the undecorated models section
+        models_section: String,
+        /// A decoration that matches the driver package configuration
+        suggested_decoration: String,
+    },
+
+    /// Error returned when a `TargetOSVersion` decoration is not well formed
+    #[error(
+        "TargetOSVersion decoration `{decoration}` of models section `{models_section}` in \
+         {inf_path} is invalid: {reason}. Consider using `{suggested_decoration}` instead"
+    )]
+    InvalidDecoration {
+        /// Path of the INF file
+        inf_path: String,
+        /// Name of the models section being decorated
+        models_section: String,
+        /// The invalid decoration
+        decoration: String,
+        /// Description of why the decoration is invalid
+        reason: String,
+        /// A decoration that matches the driver package configuration
+        suggested_decoration: String,
+    },
+
+    /// Error returned when a decorated models section is listed in the
+    /// `[Manufacturer]` section, but is not defined in the INF
+    #[error("models section `[{section}]` is referenced in {inf_path}, but is not defined")]
+    MissingModelsSection {
+        /// Path of the INF file
+        inf_path: String,
+        /// Name of the missing decorated models section
+        section: String,
+    },
+
+    /// Error returned when none of the `TargetOSVersion` decorations of a
+    /// models section apply to the architecture the driver is built for
+    #[error(
+        "none of the TargetOSVersion decorations of models section `{models_section}` in \
+         {inf_path} apply to the `{architecture}` architecture, so the driver package will not \
+         install on it. Consider adding `{suggested_decoration}`"
+    )]
+    ArchitectureNotCovered {
+        /// Path of the INF file
+        inf_path: String,
+        /// Name of the models section being decorated
+        models_section: String,
+        /// Architecture the driver is built for
+        architecture: String,
+        /// A decoration that matches the driver package configuration
+        suggested_decoration: String,
+    },
+
+    /// Error returned when none of the `TargetOSVersion` decorations of a
+    /// models section apply to the minimum OS build targeted by the driver
+    /// package
+    #[error(
+        "none of the TargetOSVersion decorations of models section `{models_section}` in \
+         {inf_path} apply to OS build {minimum_os_build_number}, so the driver package will not \
+         install on its minimum target OS. Consider adding `{suggested_decoration}`"
+    )]
+    MinimumOsBuildNotCovered {
+        /// Path of the INF file
+        inf_path: String,
+        /// Name of the models section being decorated
+        models_section: String,
+        /// The minimum OS build number targeted by the driver package
+        minimum_os_build_number: u32,
+        /// A decoration that matches the driver package configuration
+        suggested_decoration: String,
+    },
+}
+
+/// A parsed `TargetOSVersion` decoration of a models section. See the
+/// [INF Manufacturer Section documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/install/inf-manufacturer-section)
+/// for details on the format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetOsDecoration {
+    /// Architecture of the decoration (ex. `amd64`). Empty if the decoration
+    /// applies to all architectures.
+    pub architecture: String,
+    /// Major OS version of the decoration
+    pub os_major_version: Option<u32>,
+    /// Minor OS version of the decoration
+    pub os_minor_version: Option<u32>,
+    /// Product type of the decoration
+    pub product_type: Option<u32>,
+    /// Suite mask of the decoration
+    pub suite_mask: Option<u32>,
+    /// Minimum OS build number of the decoration
+    pub build_number: Option<u32>,
+}
+
+impl TargetOsDecoration {
+    /// Creates a [`TargetOsDecoration`] targeting Windows 10 or later on
+    /// `cpu_architecture`, optionally restricted to OS builds >=
+    /// `build_number`
+    #[must_use]
+    pub fn new(cpu_architecture: CpuArchitecture, build_number: Option<u32>) -> Self {
+        Self {
+            architecture: inf_architecture_str(cpu_architecture).to_string(),
+            os_major_version: Some(10),
+            os_minor_version: Some(0),
+            product_type: None,
+            suite_mask: None,
+            build_number,
+        }
+    }
+
+    /// Parses a `TargetOSVersion` decoration (ex. `NTamd64.10.0...22000`).
+    /// Returns `None` if `decoration` is not well formed.
+    #[must_use]
+    pub fn parse(decoration: &str) -> Option<Self> {
+        let decoration = decoration.trim();
+        if !decoration
+            .get(..TARGET_OS_PLATFORM_PREFIX.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(TARGET_OS_PLATFORM_PREFIX))
+        {
+            return None;
+        }
+
+        let mut fields = decoration[TARGET_OS_PLATFORM_PREFIX.len()..].split('.');
+        let architecture = fields.next().unwrap_or_default().to_ascii_lowercase();
+        let mut numeric_fields = [None; 5];
+        for numeric_field in &mut numeric_fields {
+            match fields.next() {
+                None | Some("") => {}
+                Some(field) => {
+                    *numeric_field = Some(parse_inf_number(field)?);
+                }
+            }
+        }
+
+        // Decorations can have at most 6 fields
+        if fields.next().is_some() {
+            return None;
+        }
+
+        let [os_major_version, os_minor_version, product_type, suite_mask, build_number] =
+            numeric_fields;
+        Some(Self {
+            architecture,
+            os_major_version,
+            os_minor_version,
+            product_type,
+            suite_mask,
+            build_number,
+        })
+    }
+}
+
+impl fmt::Display for TargetOsDecoration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields = [
+            self.os_major_version,
+            self.os_minor_version,
+            self.product_type,
+            self.suite_mask,
+            self.build_number,
+        ];
+        let significant_field_count = fields
+            .iter()
+            .rposition(Option::is_some)
+            .map_or(0, |index| index + 1);
+
+        write!(f, "{TARGET_OS_PLATFORM_PREFIX}{}", self.architecture)?;
+        for field in &fields[..significant_field_count] {
+            write!(f, ".")?;
+            if let Some(value) = field {
+                write!(f, "{value}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the contents of an INF file. INF files are commonly encoded in UTF-16
+/// LE (with a BOM), so both UTF-16 LE and UTF-8 encodings are supported.
+///
+/// # Errors
+///
+/// Returns an [`std::io::Error`] if the file cannot be read
+pub fn read_inf_file(inf_path: &Path) -> std::io::Result<String> {
+    const UTF16_LE_BOM: [u8; 2] = [0xFF, 0xFE];
+    const UTF8_BOM: [u8; 3] = [0xEF, 0xBB, 0xBF];
+
+    let bytes = std::fs::read(inf_path)?;
+    Ok(bytes.strip_prefix(&UTF16_LE_BOM).map_or_else(
+        || String::from_utf8_lossy(bytes.strip_prefix(&UTF8_BOM).unwrap_or(&bytes)).into_owned(),
+        |utf16_bytes| {
+            String::from_utf16_lossy(
+                &utf16_bytes
+                    .chunks_exact(2)
+                    .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                    .collect::<Vec<_>>(),
+            )
+        },
+    ))
+}
+
+/// Returns an iterator over the names of all sections in the INF contents
+pub fn section_names(inf_contents: &str) -> impl Iterator<Item = &str> {
+    inf_contents.lines().filter_map(|line| {
+        strip_comment(line)
+            .trim()
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+            .map(str::trim)
+    })
+}
+
+/// Returns an iterator over the non-empty, comment-stripped lines in the
+/// section named `section_name` (case-insensitive)
+pub fn section_lines<'a>(
+    inf_contents: &'a str,
+    section_name: &'a str,
+) -> impl Iterator<Item = &'a str> {
+    let mut in_section = false;
+    inf_contents.lines().filter_map(move |line| {
+        let line = strip_comment(line).trim();
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            in_section = name.trim().eq_ignore_ascii_case(section_name);
+            return None;
+        }
+        (in_section && !line.is_empty()).then_some(line)
+    })
+}
+
+/// Returns the names of all files copied by the `CopyFiles` directives of the
+/// INF contents, either directly (ex. `CopyFiles = @driver.sys`) or as entries
+/// of the file-list sections they reference
+pub fn copy_files_entries(inf_contents: &str) -> Vec<String> {
+    let copy_files_values = inf_contents
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = strip_comment(line).split_once('=')?;
+            key.trim()
+                .eq_ignore_ascii_case(COPY_FILES_DIRECTIVE_NAME)
+                .then_some(value)
+        })
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|value| !value.is_empty());
+
+    let mut entries = Vec::new();
+    for value in copy_files_values {
+        if let Some(file_name) = value.strip_prefix('@') {
+            entries.push(file_name.trim().to_string());
+        } else {
+            // The first field of each entry of a file-list section is the destination file
+            // name
+            entries.extend(section_lines(inf_contents, value).filter_map(|line| {
+                line.split(',')
+                    .next()
+                    .map(str::trim)
+                    .filter(|file_name| !file_name.is_empty())
+                    .map(ToString::to_string)
+            }));
+        }
+    }
+    entries
+}
+
+/// Returns the value of the `Class` directive in the `[Version]` section of the
+/// INF contents (ex. `Sample`), without any surrounding quotes
+pub fn class_name(inf_contents: &str) -> Option<&str> {
+    section_lines(inf_contents, VERSION_SECTION_NAME).find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(CLASS_DIRECTIVE_NAME)
+            .then(|| value.trim().trim_matches('"'))
+    })
+}
+
+/// Validates the `TargetOSVersion` decorations of all models sections listed in
+/// the `[Manufacturer]` section of an INF file.
+///
+/// Every decoration must be well formed, and the corresponding decorated models
+/// section must exist. Decorations for other architectures are allowed, since
+/// an INF can support several architectures, but every models section must have
+/// at least one decoration that applies to `cpu_architecture` (either naming it
+/// or omitting the architecture). If `minimum_os_build_number` is provided, one
+/// of those decorations must also apply to that OS build.
+///
+/// # Errors
+///
+/// Returns an [`InfTargetOsDecorationError`] describing the first invalid
+/// decoration found
+pub fn validate_target_os_decorations(
+    inf_path: &str,
+    inf_contents: &str,
+    cpu_architecture: CpuArchitecture,
+    minimum_os_build_number: Option<u32>,
+) -> Result<(), InfTargetOsDecorationError> {
+    let suggested_decoration =
+        TargetOsDecoration::new(cpu_architecture, minimum_os_build_number).to_string();
+
+    if !section_names(inf_contents).any(|name| name.eq_ignore_ascii_case(MANUFACTURER_SECTION_NAME))
+    {
+        return Err(InfTargetOsDecorationError::MissingManufacturerSection {
+            inf_path: inf_path.to_string(),
+        });
+    }
+
+    let defined_sections = section_names(inf_contents)
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>();
+
+    for manufacturer_entry in section_lines(inf_contents, MANUFACTURER_SECTION_NAME) {
+        let Some((_, models)) = manufacturer_entry.split_once('=') else {
+            continue;
+        };
+        let mut models_fields = models.split(',').map(str::trim);
+        let models_section = models_fields.next().unwrap_or_default().to_string();
+        let decorations = models_fields
+            .filter(|decoration| !decoration.is_empty())
+            .collect::<Vec<_>>();
+
+        if decorations.is_empty() {
+            return Err(InfTargetOsDecorationError::MissingDecoration {
+                inf_path: inf_path.to_string(),
+                models_section,
+                suggested_decoration,
+            });
+        }
+
+        let expected_architecture = inf_architecture_str(cpu_architecture);
+        let mut architecture_is_covered = false;
+        let mut minimum_os_build_is_covered = false;
+        for decoration in decorations {
+            let invalid_decoration =
+                |reason: String| InfTargetOsDecorationError::InvalidDecoration {
+                    inf_path: inf_path.to_string(),
+                    models_section: models_section.clone(),
+                    decoration: decoration.to_string(),
+                    reason,
+                    suggested_decoration: suggested_decoration.clone(),
+                };
+
+            let parsed_decoration = TargetOsDecoration::parse(decoration)
+                .ok_or_else(|| invalid_decoration("decoration is not well formed".to_string()))?;
+
+            if !parsed_decoration.architecture.is_empty()
+                && !INF_ARCHITECTURES.contains(&parsed_decoration.architecture.as_str())
+            {
+                return Err(invalid_decoration(format!(
+                    "`{}` is not a valid architecture",
+                    parsed_decoration.architecture
+                )));
+            }
+
+            let decorated_section = format!("{models_section}.{decoration}").to_ascii_lowercase();
+            if !defined_sections.contains(&decorated_section) {
+                return Err(InfTargetOsDecorationError::MissingModelsSection {
+                    inf_path: inf_path.to_string(),
+                    section: format!("{models_section}.{decoration}"),
+                });
+            }
+
+            if !parsed_decoration.architecture.is_empty()
+                && parsed_decoration.architecture != expected_architecture
+            {
+                continue;
+            }
+
+            architecture_is_covered = true;
+            if let Some(minimum_os_build_number) = minimum_os_build_number {
+                minimum_os_build_is_covered |= parsed_decoration
+                    .build_number
+                    .is_none_or(|build_number| build_number <= minimum_os_build_number);
+            }
+        }
+
+        if !architecture_is_covered {
+            return Err(InfTargetOsDecorationError::ArchitectureNotCovered {
+                inf_path: inf_path.to_string(),
+                models_section,
+                architecture: expected_architecture.to_string(),
+                suggested_decoration,
+            });
+        }
+
+        if let Some(minimum_os_build_number) = minimum_os_build_number {
+            if !minimum_os_build_is_covered {
+                return Err(InfTargetOsDecorationError::MinimumOsBuildNotCovered {
+                    inf_path: inf_path.to_string(),
+                    models_section,
+                    minimum_os_build_number,
+                    suggested_decoration,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the architecture string used in INF `TargetOSVersion` decorations
+const fn inf_architecture_str(cpu_architecture: CpuArchitecture) -> &'static str {
+    match cpu_architecture {
+        CpuArchitecture::Amd64 => "amd64",
+        CpuArchitecture::Arm64 => "arm64",
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    line.split_once(';').map_or(line, |(content, _)| content)
+}
+
+fn parse_inf_number(field: &str) -> Option<u32> {
+    field
+        .strip_prefix("0x")
+        .or_else(|| field.strip_prefix("0X"))
+        .map_or_else(
+            || field.parse().ok(),
+            |hex_field| u32::from_str_radix(hex_field, 16).ok(),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write;
+
+    use super::*;
+
+    const INF_PATH: &str = "sample_driver.inf";
+
+    fn inf_with_manufacturer_section(manufacturer_entry: &str, models_sections: &[&str]) -> String {
+        let mut inf = format!(
+            "[Version]\nSignature = \"$WINDOWS NT$\"\n\n; Install \
+             section\n[Manufacturer]\n{manufacturer_entry}\n"
+        );
+        for models_section in models_sections {
+            writeln!(
+                inf,
+                "\n[{models_section}]\n%DeviceDesc%=SampleDevice, root\\SAMPLE_HW_ID"
+            )
+            .expect("writing to a String should never fail");
+        }
+        inf
+    }
+
+    mod target_os_decoration {
+        use super::*;
+
+        #[test]
+        fn parse_full_decoration() {
+            assert_eq!(
+                TargetOsDecoration::parse("NTamd64.10.0...22000"),
+                Some(TargetOsDecoration {
+                    architecture: "amd64".to_string(),
+                    os_major_version: Some(10),
+                    os_minor_version: Some(0),
+                    product_type: None,
+                    suite_mask: None,
+                    build_number: Some(22000),
+                })
+            );
+        }
+
+        #[test]
+        fn parse_architecture_only() {
+            assert_eq!(
+                TargetOsDecoration::parse("ntARM64"),
+                Some(TargetOsDecoration {
+                    architecture: "arm64".to_string(),
+                    os_major_version: None,
+                    os_minor_version: None,
+                    product_type: None,
+                    suite_mask: None,
+                    build_number: None,
+                })
+            );
+        }
+
+        #[test]
+        fn parse_hex_fields() {
+            assert_eq!(
+                TargetOsDecoration::parse("NTamd64.10.0.0x1.0x10")
+                    .map(|decoration| (decoration.product_type, decoration.suite_mask)),
+                Some((Some(1), Some(16)))
+            );
+        }
+
+        #[test]
+        fn parse_malformed_decorations() {
+            assert_eq!(TargetOsDecoration::parse("amd64.10.0"), None);
+            assert_eq!(TargetOsDecoration::parse("NTamd64.ten"), None);
+            assert_eq!(TargetOsDecoration::parse("NTamd64.10.0.1.2.3.4"), None);
+            assert_eq!(TargetOsDecoration::parse("Nü.10.0"), None);
+            assert_eq!(TargetOsDecoration::parse("ü"), None);
+        }
+
+        #[test]
+        fn parse_without_architecture() {
+            assert_eq!(
+                TargetOsDecoration::parse("NT.10.0...16299")
+                    .map(|decoration| decoration.architecture),
+                Some(String::new())
+            );
+        }
+
+        #[test]
+        fn display_round_trips() {
+            for decoration in ["NTamd64.10.0...22000", "NTarm64", "NTamd64.10.0"] {
+                assert_eq!(
+                    TargetOsDecoration::parse(decoration).map(|d| d.to_string()),
+                    Some(decoration.to_string())
+                );
+            }
+        }
+
+        #[test]
+        fn new_decoration() {
+            assert_eq!(
+                TargetOsDecoration::new(CpuArchitecture::Arm64, Some(19041)).to_string(),
+                "NTarm64.10.0...19041"
+            );
+            assert_eq!(
+                TargetOsDecoration::new(CpuArchitecture::Amd64, None).to_string(),
+                "NTamd64.10.0"
+            );
+        }
+    }
+
+    mod validate_target_os_decorations {
+        use super::*;
+
+        #[test]
+        fn valid_decorations() {
+            let inf = inf_with_manufacturer_section(
+                "%StdMfg%=Standard,NTamd64.10.0...16299, NTamd64.10.0...22000 ; comment",
+                &[
+                    "Standard.NTamd64.10.0...16299",
+                    "Standard.NTamd64.10.0...22000",
+                ],
+            );
+
+            assert_eq!(
+                validate_target_os_decorations(INF_PATH, &inf, CpuArchitecture::Amd64, Some(19041)),
+                Ok(())
+            );
+        }
+
+        #[test]
+        fn missing_manufacturer_section() {
+            assert_eq!(
+                validate_target_os_decorations(
+                    INF_PATH,
+                    "[Version]\nSignature = \"$WINDOWS NT$\"\n",
+                    CpuArchitecture::Amd64,
+                    None
+                ),
+                Err(InfTargetOsDecorationError::MissingManufacturerSection {
+                    inf_path: INF_PATH.to_string(),
+                })
+            );
+        }
+
+        #[test]
+        fn missing_decoration() {
+            let inf = inf_with_manufacturer_section("%StdMfg%=Standard", &["Standard"]);
+
+            assert_eq!(
+                validate_target_os_decorations(INF_PATH, &inf, CpuArchitecture::Amd64, None),
+                Err(InfTargetOsDecorationError::MissingDecoration {
+                    inf_path: INF_PATH.to_string(),
+                    models_section: "Standard".to_string(),
+                    suggested_decoration: "NTamd64.10.0".to_string(),
+                })
+            );
+        }
+
+        #[test]
+        fn decorations_for_multiple_architectures() {
+            let inf = inf_with_manufacturer_section(
+                "%StdMfg%=Standard,NTamd64.10.0...16299,NTarm64.10.0...22000",
+                &[
+                    "Standard.NTamd64.10.0...16299",
+                    "Standard.NTarm64.10.0...22000",
+                ],
+            );
+
+            assert_eq!(
+                validate_target_os_decorations(INF_PATH, &inf, CpuArchitecture::Amd64, Some(19041)),
+                Ok(())
+            );
+            assert_eq!(
+                validate_target_os_decorations(INF_PATH, &inf, CpuArchitecture::Arm64, Some(22000)),
+                Ok(())
+            );
+        }
+
+        #[test]
+        fn decoration_without_architecture() {
+            let inf = inf_with_manufacturer_section(
+                "%StdMfg%=Standard,NT.10.0...16299",
+                &["Standard.NT.10.0...16299"],
+            );
+
+            assert_eq!(
+                validate_target_os_decorations(INF_PATH, &inf, CpuArchitecture::Arm64, Some(19041)),
+                Ok(())
+            );
+        }
+
+        #[test]
+        fn architecture_not_covered() {
+            let inf = inf_with_manufacturer_section(
+                "%StdMfg%=Standard,NTamd64.10.0...16299",
+                &["Standard.NTamd64.10.0...16299"],
+            );
+
+            assert_eq!(
+                validate_target_os_decorations(INF_PATH, &inf, CpuArchitecture::Arm64, None),
+                Err(InfTargetOsDecorationError::ArchitectureNotCovered {
+                    inf_path: INF_PATH.to_string(),
+                    models_section: "Standard".to_string(),
+                    architecture: "arm64".to_string(),
+                    suggested_decoration: "NTarm64.10.0".to_string(),
+                })
+            );
+        }
+
+        #[test]
+        fn unstamped_architecture() {
+            let inf = inf_with_manufacturer_section(
+                "%StdMfg%=Standard,NT$ARCH$.10.0...16299",
+                &["Standard.NT$ARCH$.10.0...16299"],
+            );
+
+            assert!(matches!(
+                validate_target_os_decorations(INF_PATH, &inf, CpuArchitecture::Amd64, None),
+                Err(InfTargetOsDecorationError::InvalidDecoration { .. })
+            ));
+        }
+
+        #[test]
+        fn missing_models_section() {
+            let inf = inf_with_manufacturer_section(
+                "%StdMfg%=Standard,NTamd64.10.0...16299",
+                &["Standard.NTamd64"],
+            );
+
+            assert_eq!(
+                validate_target_os_decorations(INF_PATH, &inf, CpuArchitecture::Amd64, None),
+                Err(InfTargetOsDecorationError::MissingModelsSection {
+                    inf_path: INF_PATH.to_string(),
+                    section: "Standard.NTamd64.10.0...16299".to_string(),
+                })
+            );
+        }
+
+        #[test]
+        fn minimum_os_build_not_covered() {
+            let inf = inf_with_manufacturer_section(
+                "%StdMfg%=Standard,NTamd64.10.0...22000,NTarm64.10.0...16299",
+                &[
+                    "Standard.NTamd64.10.0...22000",
+                    "Standard.NTarm64.10.0...16299",
+                ],
+            );
+
+            assert_eq!(
+                validate_target_os_decorations(INF_PATH, &inf, CpuArchitecture::Amd64, Some(19041)),
+                Err(InfTargetOsDecorationError::MinimumOsBuildNotCovered {
+                    inf_path: INF_PATH.to_string(),
+                    models_section: "Standard".to_string(),
+                    minimum_os_build_number: 19041,
+                    suggested_decoration: "NTamd64.10.0...19041".to_string(),
+                })
+            );
+        }
+    }
+
+    mod copy_files_entries {
+        use super::*;
+
+        #[test]
+        fn file_list_sections_and_direct_entries() {
+            let inf = [
+                "[SampleInstall.NT]",
+                "CopyFiles = Drivers_Dir, Service_Dir ; comment",
+                "copyfiles=@sample.dat",
+                "",
+                "[Drivers_Dir]",
+                "sample_driver.sys",
+                "",
+                "[service_dir]",
+                "sample_service.exe,,,0x00004000 ; comment",
+            ]
+            .join("\n");
+
+            assert_eq!(
+                copy_files_entries(&inf),
+                ["sample_driver.sys", "sample_service.exe", "sample.dat"]
+            );
+        }
+
+        #[test]
+        fn no_copy_files_directives() {
+            assert!(copy_files_entries("[Drivers_Dir]\nsample_driver.sys\n").is_empty());
+        }
+    }
+
+    mod class_name {
+        use super::*;
+
+        #[test]
+        fn class_in_version_section() {
+            let inf = "[Version]\nSignature = \"$WINDOWS NT$\"\nclass = \"Sample\" ; \
+                       comment\nClassGuid = {78A1C341-4539-11d3-B88D-00C04FAD5171}\n";
+            assert_eq!(class_name(inf), Some("Sample"));
+        }
+
+        #[test]
+        fn class_outside_version_section() {
+            assert_eq!(class_name("[Version]\n\n[Strings]\nClass = Sample\n"), None);
+        }
+    }
+}
@@ -0,0 +1,381 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Policies that select how a driver package is validated when it is packaged
+//!
+//! The `--inf-policy` argument (or the `WDK_BUILD_INF_POLICY` environment
+//! variable) selects the [`InfPolicy`] that the packaging tasks apply:
+//!
+//! * `sample`: the checks for Windows driver samples (`infverif /samples`)
+//! * `production`: the checks for Hardware Dev Center submission (`infverif
+//!   /h`), signature verification of the signed driver binary and catalog, and
+//!   rejection of INFs that install into the `Sample` device setup class
+//! * the path of a TOML file: the [`InfPolicyRules`] declared in that file,
+//!   which lets organizations check their internal packaging policy into their
+//!   repository:
+//!
+//! ```toml
+//! infverif-flags = ["/h"]
+//! verify-signatures = true
+//! allowed-classes = ["System", "USB"]
+//! ```
+//!
+//! When no policy is selected, the INF is validated with `InfVerif`'s default
+//! checks for the driver model.
+
+use std::{
+    convert::Infallible,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::inf;
+
+/// Name of the environment variable containing the [`InfPolicy`] selected by
+/// the `--inf-policy` argument
+pub const INF_POLICY_ENV_VAR: &str = "WDK_BUILD_INF_POLICY";
+
+/// The first WDK build number whose `InfVerif` supports the `/samples` flag.
+/// Earlier versions use `/msft` to validate samples.
+const MINIMUM_SAMPLES_FLAG_WDK_BUILD_NUMBER: u32 = 25798;
+
+/// Device setup class of sample drivers, which cannot be submitted to the
+/// Hardware Dev Center
+const SAMPLE_CLASS_NAME: &str = "Sample";
+
+/// Errors that could result from applying an [`InfPolicy`]
+#[derive(Debug, Error)]
+pub enum InfPolicyError {
+    /// Error returned when the policy file fails to be read
+    #[error("failed to read INF policy file {}", .path.display())]
+    PolicyFileRead {
+        /// Path of the policy file
+        path: PathBuf,
+        /// [`std::io::Error`] that caused the read to fail
+        #[source]
+        error_source: std::io::Error,
+    },
+
+    /// Error returned when the policy file fails to be deserialized
+    #[error("failed to parse INF policy file {}", .path.display())]
+    PolicyFileParse {
+        /// Path of the policy file
+        path: PathBuf,
+        /// [`toml::de::Error`] that caused the deserialization to fail
+        #[source]
+        error_source: Box<toml::de::Error>,
+    },
+
+    /// Error returned when a flag in the `infverif-flags` of the policy file
+    /// contains whitespace, which would split it into several arguments
+    #[error(
+        "infverif flag `{flag}` in INF policy file {} contains whitespace, which is not supported",
+        .path.display()
+    )]
+    InfverifFlagContainsWhitespace {
+        /// Path of the policy file
+        path: PathBuf,
+        /// Flag containing whitespace
+        flag: String,
+    },
+
+    /// Error returned when the INF declares a device setup class that the
+    /// policy does not allow
+    #[error(
+        "{inf_path} installs into the `{class}` device setup class, which the {policy} INF policy \
+         does not allow"
+    )]
+    DisallowedClass {
+        /// Path of the INF file
+        inf_path: String,
+        /// Device setup class declared by the INF
+        class: String,
+        /// Policy that was applied
+        policy: String,
+    },
+
+    /// Error returned when the policy restricts the allowed device setup
+    /// classes, but the INF does not declare one
+    #[error(
+        "{inf_path} does not declare a device setup class, which the {policy} INF policy requires"
+    )]
+    MissingClass {
+        /// Path of the INF file
+        inf_path: String,
+        /// Policy that was applied
+        policy: String,
+    },
+}
+
+/// Policy that selects how a driver package is validated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InfPolicy {
+    /// Validate the package as a Windows driver sample
+    Sample,
+    /// Validate the package for release
+    Production,
+    /// Validate the package with the rules declared in a TOML file
+    Custom(PathBuf),
+}
+
+/// Rules applied by an [`InfPolicy`], which can be declared in a TOML file
+/// for [`InfPolicy::Custom`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct InfPolicyRules {
+    /// Flags passed to `infverif`, in addition to the flags selected for the
+    /// driver model. Flags cannot contain whitespace.
+    #[serde(default)]
+    pub infverif_flags: Vec<String>,
+    /// Whether the signatures of the driver binary and catalog are verified
+    /// with `signtool verify`
+    #[serde(default)]
+    pub verify_signatures: bool,
+    /// Device setup classes that the INF may install into (case-insensitive).
+    /// Any class is allowed when this is not set.
+    #[serde(default)]
+    pub allowed_classes: Option<Vec<String>>,
+    /// Device setup classes that the INF may not install into
+    /// (case-insensitive)
+    #[serde(default)]
+    pub disallowed_classes: Vec<String>,
+}
+
+impl InfPolicy {
+    /// Returns the rules applied by this policy, when packaging with the WDK
+    /// build number `wdk_build_number`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the policy file of an
+    /// [`InfPolicy::Custom`] fails to be read or parsed
+    pub fn rules(&self, wdk_build_number: u32) -> Result<InfPolicyRules, InfPolicyError> {
+        match self {
+            Self::Sample => Ok(InfPolicyRules {
+                infverif_flags: vec![samples_infverif_flag(wdk_build_number).to_string()],
+                ..InfPolicyRules::default()
+            }),
+            Self::Production => Ok(InfPolicyRules {
+                infverif_flags: vec!["/h".to_string()],
+                verify_signatures: true,
+                allowed_classes: None,
+                disallowed_classes: vec![SAMPLE_CLASS_NAME.to_string()],
+            }),
+            Self::Custom(path) => InfPolicyRules::from_file(path),
+        }
+    }
+}
+
+impl FromStr for InfPolicy {
+    type Err = Infallible;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match value.trim().to_ascii_lowercase().as_str() {
+            "sample" => Self::Sample,
+            "production" => Self::Production,
+            _ => Self::Custom(PathBuf::from(value.trim())),
+        })
+    }
+}
+
+impl fmt::Display for InfPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sample => write!(f, "sample"),
+            Self::Production => write!(f, "production"),
+            Self::Custom(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl InfPolicyRules {
+    /// Reads the rules declared in the TOML file at `path`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file fails to be read or
+    /// parsed, or if one of its `infverif-flags` contains whitespace
+    pub fn from_file(path: &Path) -> Result<Self, InfPolicyError> {
+        let contents = std::fs::read_to_string(path).map_err(|error_source| {
+            InfPolicyError::PolicyFileRead {
+                path: path.to_path_buf(),
+                error_source,
+            }
+        })?;
+        Self::parse(path, &contents)
+    }
+
+    /// Parses the rules declared in `contents`, which were read from the TOML
+    /// file at `path`
+    fn parse(path: &Path, contents: &str) -> Result<Self, InfPolicyError> {
+        let rules: Self =
+            toml::from_str(contents).map_err(|error_source| InfPolicyError::PolicyFileParse {
+                path: path.to_path_buf(),
+                error_source: Box::new(error_source),
+            })?;
+
+        // The flags are forwarded to `infverif` through a space-delimited environment
+        // variable, which would split a flag containing whitespace
+        if let Some(flag) = rules
+            .infverif_flags
+            .iter()
+            .find(|flag| flag.contains(char::is_whitespace))
+        {
+            return Err(InfPolicyError::InfverifFlagContainsWhitespace {
+                path: path.to_path_buf(),
+                flag: flag.clone(),
+            });
+        }
+        Ok(rules)
+    }
+
+    /// Validates that the device setup class declared in the `[Version]`
+    /// section of the INF contents is allowed by these rules
+    ///
+    /// # Errors
+    ///
+    /// This function will return [`InfPolicyError::DisallowedClass`] if the
+    /// class is not allowed, or [`InfPolicyError::MissingClass`] if only some
+    /// classes are allowed and the INF does not declare one
+    pub fn validate_class(
+        &self,
+        policy: &InfPolicy,
+        inf_path: &str,
+        inf_contents: &str,
+    ) -> Result<(), InfPolicyError> {
+        let Some(class) = inf::class_name(inf_contents) else {
+            if self.allowed_classes.is_some() {
+                return Err(InfPolicyError::MissingClass {
+                    inf_path: inf_path.to_string(),
+                    policy: policy.to_string(),
+                });
+            }
+            return Ok(());
+        };
+
+        let is_allowed = self.allowed_classes.as_ref().is_none_or(|allowed_classes| {
+            allowed_classes
+                .iter()
+                .any(|allowed_class| allowed_class.eq_ignore_ascii_case(class))
+        }) && !self
+            .disallowed_classes
+            .iter()
+            .any(|disallowed_class| disallowed_class.eq_ignore_ascii_case(class));
+
+        if is_allowed {
+            Ok(())
+        } else {
+            Err(InfPolicyError::DisallowedClass {
+                inf_path: inf_path.to_string(),
+                class: class.to_string(),
+                policy: policy.to_string(),
+            })
+        }
+    }
+}
+
+/// Returns the `infverif` flag that validates Windows driver samples with the
+/// WDK build number `wdk_build_number`
+#[must_use]
+pub const fn samples_infverif_flag(wdk_build_number: u32) -> &'static str {
+    if wdk_build_number > MINIMUM_SAMPLES_FLAG_WDK_BUILD_NUMBER {
+        "/samples"
+    } else {
+        "/msft"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INF_PATH: &str = "sample_driver.inf";
+
+    #[test]
+    fn policies_are_parsed() {
+        assert_eq!("sample".parse::<InfPolicy>(), Ok(InfPolicy::Sample));
+        assert_eq!("Production".parse::<InfPolicy>(), Ok(InfPolicy::Production));
+        assert_eq!(
+            "policies/contoso.toml".parse::<InfPolicy>(),
+            Ok(InfPolicy::Custom(PathBuf::from("policies/contoso.toml")))
+        );
+    }
+
+    #[test]
+    fn rules_are_deserialized() {
+        let rules: InfPolicyRules = toml::from_str(
+            "infverif-flags = [\"/h\"]\nverify-signatures = true\nallowed-classes = [\"System\"]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            rules,
+            InfPolicyRules {
+                infverif_flags: vec!["/h".to_string()],
+                verify_signatures: true,
+                allowed_classes: Some(vec!["System".to_string()]),
+                disallowed_classes: Vec::new(),
+            }
+        );
+
+        assert!(toml::from_str::<InfPolicyRules>("sample-class = true\n").is_err());
+    }
+
+    #[test]
+    fn infverif_flags_with_whitespace_are_rejected() {
+        let path = Path::new("contoso.toml");
+
+        assert!(InfPolicyRules::parse(path, "infverif-flags = [\"/h\", \"/w\"]\n").is_ok());
+        assert!(matches!(
+            InfPolicyRules::parse(path, "infverif-flags = [\"/h /w\"]\n"),
+            Err(InfPolicyError::InfverifFlagContainsWhitespace { flag, .. }) if flag == "/h /w"
+        ));
+    }
+
+    #[test]
+    fn sample_class_is_rejected_by_production_policy() {
+        let policy = InfPolicy::Production;
+        let rules = policy.rules(26100).unwrap();
+        let inf = "[Version]\nClass = Sample\n";
+
+        assert!(matches!(
+            rules.validate_class(&policy, INF_PATH, inf),
+            Err(InfPolicyError::DisallowedClass { class, .. }) if class == "Sample"
+        ));
+        assert!(InfPolicy::Sample
+            .rules(26100)
+            .unwrap()
+            .validate_class(&InfPolicy::Sample, INF_PATH, inf)
+            .is_ok());
+    }
+
+    #[test]
+    fn allowed_classes_are_enforced() {
+        let policy = InfPolicy::Custom(PathBuf::from("contoso.toml"));
+        let rules = InfPolicyRules {
+            allowed_classes: Some(vec!["System".to_string()]),
+            ..InfPolicyRules::default()
+        };
+
+        assert!(rules
+            .validate_class(&policy, INF_PATH, "[Version]\nClass = system\n")
+            .is_ok());
+        assert!(matches!(
+            rules.validate_class(&policy, INF_PATH, "[Version]\nClass = USB\n"),
+            Err(InfPolicyError::DisallowedClass { .. })
+        ));
+        assert!(matches!(
+            rules.validate_class(&policy, INF_PATH, "[Version]\n"),
+            Err(InfPolicyError::MissingClass { .. })
+        ));
+    }
+
+    #[test]
+    fn samples_flag_depends_on_wdk_build_number() {
+        assert_eq!(samples_infverif_flag(22061), "/msft");
+        assert_eq!(samples_infverif_flag(26100), "/samples");
+    }
+}
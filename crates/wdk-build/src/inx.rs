@@ -0,0 +1,595 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Private module for generating a driver's INX file from the
+//! `metadata.wdk.inf` section of its `Cargo.toml`.
+//!
+//! The generated INX is a template for `stampinf`, in the same format as the
+//! hand-written INX files of the example drivers. Its file names are derived
+//! from the package name, so the INX stays in sync with the crate when it is
+//! renamed.
+
+use std::fmt::Write;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::DriverConfig;
+
+/// Key of the INF metadata in the `metadata.wdk` section of a package's
+/// `Cargo.toml`
+pub const INF_METADATA_KEY: &str = "inf";
+
+/// Minimum OS build that the models section of a generated INX is decorated
+/// with
+const MINIMUM_TARGET_OS_BUILD_NUMBER: u32 = 16299;
+
+/// Separator line used in the header comment of a generated INX
+const SEPARATOR: &str = "===================================================================";
+
+/// Errors that could result from generating an INX file from the
+/// `metadata.wdk.inf` section of a package
+#[derive(Debug, Error)]
+pub enum InxGenerationError {
+    /// Error returned when the `metadata.wdk.inf` section of a package fails
+    /// to be deserialized
+    #[error("failed to deserialize metadata.wdk.inf for {package_name} package")]
+    MetadataDeserialization {
+        /// Name of the package whose metadata failed to be deserialized
+        package_name: String,
+        /// [`serde_json::Error`] that caused the deserialization to fail
+        #[source]
+        error_source: serde_json::Error,
+    },
+
+    /// Error returned when no hardware IDs are specified
+    #[error("metadata.wdk.inf for {package_name} package must specify at least one hardware-id")]
+    MissingHardwareIds {
+        /// Name of the package
+        package_name: String,
+    },
+
+    /// Error returned when a field contains characters that cannot be
+    /// represented in an INF file
+    #[error(
+        "{field} `{value}` in metadata.wdk.inf for {package_name} package is invalid: {reason}"
+    )]
+    InvalidField {
+        /// Name of the package
+        package_name: String,
+        /// Name of the invalid field
+        field: &'static str,
+        /// The invalid value
+        value: String,
+        /// Description of why the value is invalid
+        reason: &'static str,
+    },
+}
+
+/// Metadata specified in the `metadata.wdk.inf` section of a driver's
+/// `Cargo.toml`, from which its INX file is generated
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct InfMetadata {
+    /// Hardware IDs of the devices the driver is installed on (ex.
+    /// `root\MY_DRIVER_HW_ID`)
+    pub hardware_ids: Vec<String>,
+    /// Device setup class of the devices (ex. `System`)
+    pub class: String,
+    /// GUID of the device setup class, including braces (ex.
+    /// `{4d36e97d-e325-11ce-bfc1-08002be10318}`)
+    pub class_guid: String,
+    /// Name of the driver's service. Defaults to the package name.
+    #[serde(default)]
+    pub service_name: Option<String>,
+    /// Additional files copied to the driver store alongside the driver
+    /// binary. These files must be present in the driver package folder
+    /// before the catalog file is generated.
+    #[serde(default)]
+    pub copy_files: Vec<String>,
+    /// Provider of the driver. Defaults to `TODO-Set-Provider`.
+    #[serde(default)]
+    pub provider: Option<String>,
+    /// Description of the device displayed in Device Manager. Defaults to the
+    /// package name.
+    #[serde(default)]
+    pub device_description: Option<String>,
+}
+
+impl InfMetadata {
+    /// Parses the `metadata.wdk.inf` section of `package`, returning `None` if
+    /// the package has no such section
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`InxGenerationError::MetadataDeserialization`] if the
+    /// section is invalid
+    pub fn try_from_package(
+        package: &cargo_metadata::Package,
+    ) -> Result<Option<Self>, InxGenerationError> {
+        match &package.metadata["wdk"][INF_METADATA_KEY] {
+            serde_json::Value::Null => Ok(None),
+            inf_metadata => Self::deserialize(inf_metadata)
+                .map(Some)
+                .map_err(|error_source| InxGenerationError::MetadataDeserialization {
+                    package_name: package.name.clone(),
+                    error_source,
+                }),
+        }
+    }
+
+    /// Renders the INX file for the driver built from the package named
+    /// `package_name` with version `package_version`
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`InxGenerationError`] if any of the fields are invalid
+    pub fn render_inx(
+        &self,
+        package_name: &str,
+        package_version: &str,
+        driver_config: &DriverConfig,
+    ) -> Result<String, InxGenerationError> {
+        self.validate(package_name)?;
+
+        let crate_fs_name = package_name.replace('-', "_");
+        let driver_binary = match driver_config {
+            DriverConfig::Wdm | DriverConfig::Kmdf(_) => format!("{crate_fs_name}.sys"),
+            DriverConfig::Umdf(_) => format!("{crate_fs_name}.dll"),
+        };
+        let files = std::iter::once(driver_binary.as_str())
+            .chain(self.copy_files.iter().map(String::as_str))
+            .collect::<Vec<_>>();
+        // WDM and KMDF install sections are architecture-decorated, while the
+        // installation of the UMDF reflector requires an undecorated install section
+        let install_section = match driver_config {
+            DriverConfig::Wdm | DriverConfig::Kmdf(_) => "DriverInstall.NT$ARCH$",
+            DriverConfig::Umdf(_) => "DriverInstall.NT",
+        };
+
+        let mut inx = String::new();
+        self.write_version_sections(&mut inx, package_name, package_version, &files);
+        self.write_install_sections(&mut inx, install_section, &files);
+        self.write_service_sections(
+            &mut inx,
+            install_section,
+            &crate_fs_name,
+            &driver_binary,
+            driver_config,
+        );
+        self.write_strings_section(&mut inx, package_name);
+        Ok(inx)
+    }
+
+    // `write!` into a `String` is infallible, so the results of the `writeln!`s
+    // below are ignored
+
+    fn write_version_sections(
+        &self,
+        inx: &mut String,
+        package_name: &str,
+        package_version: &str,
+        files: &[&str],
+    ) {
+        let _ = writeln!(inx, ";{SEPARATOR}");
+        let _ = writeln!(inx, "; {package_name} {package_version}");
+        let _ = writeln!(
+            inx,
+            "; Generated from package.metadata.wdk.inf. Run `cargo make sync-inf` to update."
+        );
+        let _ = writeln!(inx, ";{SEPARATOR}");
+        let _ = writeln!(inx);
+        let _ = writeln!(inx, "[Version]");
+        let _ = writeln!(inx, "Signature   = \"$WINDOWS NT$\"");
+        let _ = writeln!(inx, "Class       = {}", self.class);
+        let _ = writeln!(inx, "ClassGuid   = {}", self.class_guid);
+        let _ = writeln!(inx, "Provider    = %ProviderString%");
+        let _ = writeln!(inx, "PnpLockDown = 1");
+        let _ = writeln!(inx);
+        let _ = writeln!(inx, "[DestinationDirs]");
+        let _ = writeln!(inx, "DefaultDestDir = 13");
+        let _ = writeln!(inx);
+        let _ = writeln!(inx, "[SourceDisksNames]");
+        let _ = writeln!(inx, "1 = %DiskId1%,,,\"\"");
+        let _ = writeln!(inx);
+        let _ = writeln!(inx, "[SourceDisksFiles]");
+        for file in files {
+            let _ = writeln!(inx, "{file} = 1,,");
+        }
+        let _ = writeln!(inx);
+    }
+
+    fn write_install_sections(&self, inx: &mut String, install_section: &str, files: &[&str]) {
+        let models_section = format!("Standard.NT$ARCH$.10.0...{MINIMUM_TARGET_OS_BUILD_NUMBER}");
+
+        let _ = writeln!(inx, "; ================= Install section =================");
+        let _ = writeln!(inx);
+        let _ = writeln!(inx, "[Manufacturer]");
+        let _ = writeln!(
+            inx,
+            "%StdMfg%=Standard,NT$ARCH$.10.0...{MINIMUM_TARGET_OS_BUILD_NUMBER}"
+        );
+        let _ = writeln!(inx);
+        let _ = writeln!(inx, "[{models_section}]");
+        for hardware_id in &self.hardware_ids {
+            let _ = writeln!(inx, "%DeviceDesc%=DriverInstall, {hardware_id}");
+        }
+        let _ = writeln!(inx);
+        let _ = writeln!(inx, "[{install_section}]");
+        let _ = writeln!(inx, "CopyFiles=Drivers_Dir");
+        let _ = writeln!(inx);
+        let _ = writeln!(inx, "[Drivers_Dir]");
+        for file in files {
+            let _ = writeln!(inx, "{file}");
+        }
+        let _ = writeln!(inx);
+    }
+
+    fn write_service_sections(
+        &self,
+        inx: &mut String,
+        install_section: &str,
+        crate_fs_name: &str,
+        driver_binary: &str,
+        driver_config: &DriverConfig,
+    ) {
+        let service_name = self.service_name.as_deref().unwrap_or(crate_fs_name);
+
+        let _ = writeln!(
+            inx,
+            "; ================= Service installation ================="
+        );
+        let _ = writeln!(inx, "[{install_section}.Services]");
+        let (service_install_section, service_binary) = match driver_config {
+            DriverConfig::Wdm | DriverConfig::Kmdf(_) => {
+                let _ = writeln!(
+                    inx,
+                    "AddService = {service_name}, %SPSVCINST_ASSOCSERVICE%, Service_Install"
+                );
+                ("Service_Install", format!("%13%\\{driver_binary}"))
+            }
+            // UMDF drivers are hosted by the reflector, which is installed as the
+            // kernel-mode service of the device
+            DriverConfig::Umdf(_) => {
+                let _ = writeln!(
+                    inx,
+                    "AddService = WUDFRd, 0x000001fa, WUDFRD_ServiceInstall"
+                );
+                ("WUDFRD_ServiceInstall", r"%12%\WUDFRd.sys".to_string())
+            }
+        };
+        let _ = writeln!(inx);
+        let _ = writeln!(inx, "[{service_install_section}]");
+        let _ = writeln!(inx, "DisplayName    = %ServiceDesc%");
+        let _ = writeln!(
+            inx,
+            "ServiceType    = 1               ; SERVICE_KERNEL_DRIVER"
+        );
+        let _ = writeln!(
+            inx,
+            "StartType      = 3               ; SERVICE_DEMAND_START"
+        );
+        let _ = writeln!(
+            inx,
+            "ErrorControl   = 1               ; SERVICE_ERROR_NORMAL"
+        );
+        let _ = writeln!(inx, "ServiceBinary  = {service_binary}");
+        let _ = writeln!(inx);
+
+        match driver_config {
+            DriverConfig::Wdm => {}
+            DriverConfig::Kmdf(_) => {
+                let _ = writeln!(
+                    inx,
+                    "; ================= WDF specific section ================="
+                );
+                let _ = writeln!(inx, "[{install_section}.Wdf]");
+                let _ = writeln!(inx, "KmdfService = {service_name}, KmdfService_Install");
+                let _ = writeln!(inx);
+                let _ = writeln!(inx, "[KmdfService_Install]");
+                let _ = writeln!(inx, "KmdfLibraryVersion = $KMDFVERSION$");
+                let _ = writeln!(inx);
+            }
+            DriverConfig::Umdf(_) => {
+                let _ = writeln!(
+                    inx,
+                    "; ================= WDF specific section ================="
+                );
+                let _ = writeln!(inx, "[{install_section}.Wdf]");
+                let _ = writeln!(inx, "UmdfService = {service_name}, UmdfService_Install");
+                let _ = writeln!(inx, "UmdfServiceOrder = {service_name}");
+                let _ = writeln!(inx);
+                let _ = writeln!(inx, "[UmdfService_Install]");
+                let _ = writeln!(inx, "UmdfLibraryVersion = $UMDFVERSION$");
+                let _ = writeln!(inx, "ServiceBinary = %13%\\{driver_binary}");
+                let _ = writeln!(inx);
+            }
+        }
+    }
+
+    fn write_strings_section(&self, inx: &mut String, package_name: &str) {
+        let provider = self.provider.as_deref().unwrap_or("TODO-Set-Provider");
+        let device_description = self.device_description.as_deref().unwrap_or(package_name);
+
+        let _ = writeln!(inx, "; ================= Strings =================");
+        let _ = writeln!(inx, "[Strings]");
+        let _ = writeln!(inx, "SPSVCINST_ASSOCSERVICE = 0x00000002");
+        let _ = writeln!(inx, "ProviderString         = \"{provider}\"");
+        let _ = writeln!(
+            inx,
+            "StdMfg                 = \"(Standard system devices)\""
+        );
+        let _ = writeln!(
+            inx,
+            "DiskId1                = \"{package_name} Installation Disk #1\""
+        );
+        let _ = writeln!(inx, "DeviceDesc             = \"{device_description}\"");
+        let _ = writeln!(
+            inx,
+            "ServiceDesc            = \"{device_description} Service\""
+        );
+    }
+
+    fn validate(&self, package_name: &str) -> Result<(), InxGenerationError> {
+        let invalid_field = |field, value: &str, reason| InxGenerationError::InvalidField {
+            package_name: package_name.to_string(),
+            field,
+            value: value.to_string(),
+            reason,
+        };
+
+        if self.hardware_ids.is_empty() {
+            return Err(InxGenerationError::MissingHardwareIds {
+                package_name: package_name.to_string(),
+            });
+        }
+
+        for hardware_id in &self.hardware_ids {
+            if hardware_id.is_empty() || hardware_id.contains([',', '"', ';', '%', ' ']) {
+                return Err(invalid_field(
+                    "hardware-id",
+                    hardware_id,
+                    "hardware IDs must be non-empty and cannot contain commas, quotes, \
+                     semicolons, percent signs or spaces",
+                ));
+            }
+        }
+
+        if !is_inf_identifier(&self.class) {
+            return Err(invalid_field(
+                "class",
+                &self.class,
+                "class names can only contain ASCII letters, digits and underscores",
+            ));
+        }
+
+        if !is_braced_guid(&self.class_guid) {
+            return Err(invalid_field(
+                "class-guid",
+                &self.class_guid,
+                "class GUIDs must be formatted as {xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx}",
+            ));
+        }
+
+        if let Some(service_name) = &self.service_name {
+            if !is_inf_identifier(service_name) {
+                return Err(invalid_field(
+                    "service-name",
+                    service_name,
+                    "service names can only contain ASCII letters, digits and underscores",
+                ));
+            }
+        }
+
+        for file in &self.copy_files {
+            if file.is_empty() || file.contains(['\\', '/', ',', '"', ';', '%']) {
+                return Err(invalid_field(
+                    "copy-files",
+                    file,
+                    "file names must be non-empty and cannot contain path separators, commas, \
+                     quotes, semicolons or percent signs",
+                ));
+            }
+        }
+
+        for (field, value) in [
+            ("provider", &self.provider),
+            ("device-description", &self.device_description),
+        ] {
+            if let Some(value) = value {
+                if value.contains(['"', '%', '\n']) {
+                    return Err(invalid_field(
+                        field,
+                        value,
+                        "strings cannot contain quotes, percent signs or newlines",
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn is_inf_identifier(value: &str) -> bool {
+    !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_braced_guid(value: &str) -> bool {
+    const GROUP_LENGTHS: [usize; 5] = [8, 4, 4, 4, 12];
+
+    value
+        .strip_prefix('{')
+        .and_then(|value| value.strip_suffix('}'))
+        .is_some_and(|guid| {
+            let groups = guid.split('-').collect::<Vec<_>>();
+            groups.len() == GROUP_LENGTHS.len()
+                && groups.iter().zip(GROUP_LENGTHS).all(|(group, length)| {
+                    group.len() == length && group.chars().all(|c| c.is_ascii_hexdigit())
+                })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{inf, CpuArchitecture, KmdfConfig, UmdfConfig};
+
+    fn inf_metadata() -> InfMetadata {
+        InfMetadata {
+            hardware_ids: vec![r"root\SAMPLE_HW_ID".to_string()],
+            class: "Sample".to_string(),
+            class_guid: "{78A1C341-4539-11d3-B88D-00C04FAD5171}".to_string(),
+            service_name: None,
+            copy_files: vec![],
+            provider: None,
+            device_description: None,
+        }
+    }
+
+    #[test]
+    fn deserialize_inf_metadata() {
+        let inf_metadata = InfMetadata::deserialize(serde_json::json!({
+            "hardware-ids": [r"root\SAMPLE_HW_ID"],
+            "class": "Sample",
+            "class-guid": "{78A1C341-4539-11d3-B88D-00C04FAD5171}",
+            "copy-files": ["sample.dat"],
+        }))
+        .unwrap();
+
+        assert_eq!(inf_metadata.hardware_ids, [r"root\SAMPLE_HW_ID"]);
+        assert_eq!(inf_metadata.copy_files, ["sample.dat"]);
+        assert_eq!(inf_metadata.service_name, None);
+    }
+
+    #[test]
+    fn render_kmdf_inx() {
+        let inx = InfMetadata {
+            hardware_ids: vec![
+                r"root\SAMPLE_HW_ID".to_string(),
+                r"root\SAMPLE_HW_ID_2".to_string(),
+            ],
+            copy_files: vec!["sample.dat".to_string()],
+            ..inf_metadata()
+        }
+        .render_inx(
+            "sample-kmdf-driver",
+            "0.1.0",
+            &DriverConfig::Kmdf(KmdfConfig::new()),
+        )
+        .unwrap();
+
+        assert!(inx.contains("; sample-kmdf-driver 0.1.0\n"));
+        assert!(inx.contains("sample_kmdf_driver.sys = 1,,\nsample.dat = 1,,\n"));
+        assert!(inx.contains(
+            "%DeviceDesc%=DriverInstall, root\\SAMPLE_HW_ID\n%DeviceDesc%=DriverInstall, \
+             root\\SAMPLE_HW_ID_2\n"
+        ));
+        assert!(inx.contains(
+            "[DriverInstall.NT$ARCH$.Services]\nAddService = sample_kmdf_driver, \
+             %SPSVCINST_ASSOCSERVICE%, Service_Install\n"
+        ));
+        assert!(inx.contains("KmdfLibraryVersion = $KMDFVERSION$"));
+    }
+
+    #[test]
+    fn render_umdf_inx() {
+        let inx = InfMetadata {
+            service_name: Some("SampleService".to_string()),
+            ..inf_metadata()
+        }
+        .render_inx(
+            "sample-umdf-driver",
+            "0.1.0",
+            &DriverConfig::Umdf(UmdfConfig::new()),
+        )
+        .unwrap();
+
+        assert!(inx.contains("[Drivers_Dir]\nsample_umdf_driver.dll\n"));
+        assert!(inx.contains("[DriverInstall.NT.Wdf]\nUmdfService = SampleService, "));
+        assert!(inx.contains("ServiceBinary = %13%\\sample_umdf_driver.dll"));
+        assert!(!inx.contains("KmdfService"));
+    }
+
+    #[test]
+    fn render_wdm_inx_has_no_wdf_section() {
+        let inx = inf_metadata()
+            .render_inx("sample-wdm-driver", "0.1.0", &DriverConfig::Wdm)
+            .unwrap();
+
+        assert!(!inx.contains(".Wdf]"));
+        assert!(inx.contains("ServiceBinary  = %13%\\sample_wdm_driver.sys"));
+    }
+
+    #[test]
+    fn rendered_inx_has_valid_target_os_decorations() {
+        let inx = inf_metadata()
+            .render_inx("sample-wdm-driver", "0.1.0", &DriverConfig::Wdm)
+            .unwrap()
+            .replace("$ARCH$", "amd64");
+
+        assert_eq!(
+            inf::validate_target_os_decorations(
+                "sample_wdm_driver.inf",
+                &inx,
+                CpuArchitecture::Amd64,
+                Some(22000),
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn render_inx_fails_without_hardware_ids() {
+        assert!(matches!(
+            InfMetadata {
+                hardware_ids: vec![],
+                ..inf_metadata()
+            }
+            .render_inx("sample-wdm-driver", "0.1.0", &DriverConfig::Wdm),
+            Err(InxGenerationError::MissingHardwareIds { .. })
+        ));
+    }
+
+    #[test]
+    fn render_inx_fails_on_invalid_fields() {
+        for invalid_inf_metadata in [
+            InfMetadata {
+                hardware_ids: vec![r"root\SAMPLE,HW_ID".to_string()],
+                ..inf_metadata()
+            },
+            InfMetadata {
+                class_guid: "78A1C341-4539-11d3-B88D-00C04FAD5171".to_string(),
+                ..inf_metadata()
+            },
+            InfMetadata {
+                class_guid: "{78A1C341-4539-11d3-B88D-00C04FAD517}".to_string(),
+                ..inf_metadata()
+            },
+            InfMetadata {
+                service_name: Some("Sample Service".to_string()),
+                ..inf_metadata()
+            },
+            InfMetadata {
+                copy_files: vec![r"..\sample.dat".to_string()],
+                ..inf_metadata()
+            },
+            InfMetadata {
+                provider: Some("\"Contoso\"".to_string()),
+                ..inf_metadata()
+            },
+        ] {
+            assert!(
+                matches!(
+                    invalid_inf_metadata.render_inx(
+                        "sample-wdm-driver",
+                        "0.1.0",
+                        &DriverConfig::Wdm
+                    ),
+                    Err(InxGenerationError::InvalidField { .. })
+                ),
+                "{invalid_inf_metadata:?} should be invalid"
+            );
+        }
+    }
+}
@@ -0,0 +1,182 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! `JUnit` XML reports of the driver package verification steps
+//!
+//! When `--junit-report-dir` is passed to `cargo make`, each verification step
+//! of a driver package (`signtool verify` and `infverif`) writes its result to
+//! a `TEST-<package>-<step>.xml` file in that directory, so that CI systems
+//! (ex. the `PublishTestResults` task of Azure Pipelines, whose default search
+//! pattern is `**/TEST-*.xml`) can render them as test results. Each report
+//! contains a test suite named after the driver package, with a single test
+//! case for the step.
+
+use core::fmt;
+use std::time::Duration;
+
+/// Result of running one verification step on a driver package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestCase {
+    /// Name of the verification step (ex. `infverif sample_kmdf_driver.inf`)
+    pub name: String,
+    /// Time that the verification step took
+    pub duration: Duration,
+    /// Output of the tool run by the verification step
+    pub output: String,
+    /// Description of the failure, or `None` if the verification step
+    /// succeeded
+    pub failure: Option<String>,
+}
+
+/// `JUnit` XML report of the verification steps run on a driver package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestSuite {
+    /// Name of the driver package
+    pub name: String,
+    /// Verification steps run on the driver package
+    pub test_cases: Vec<TestCase>,
+}
+
+impl TestSuite {
+    /// Returns the name of the report file of the verification step `step` of
+    /// `package_name`, which follows the `TEST-*.xml` naming convention of
+    /// `JUnit` reports
+    #[must_use]
+    pub fn file_name(package_name: &str, step: &str) -> String {
+        let sanitized_step = step
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+        format!("TEST-{package_name}-{sanitized_step}.xml")
+    }
+}
+
+impl fmt::Display for TestSuite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let failures = self
+            .test_cases
+            .iter()
+            .filter(|test_case| test_case.failure.is_some())
+            .count();
+        let duration = self
+            .test_cases
+            .iter()
+            .map(|test_case| test_case.duration)
+            .sum::<Duration>();
+
+        writeln!(f, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            f,
+            r#"<testsuites tests="{tests}" failures="{failures}" time="{time:.3}">"#,
+            tests = self.test_cases.len(),
+            time = duration.as_secs_f64(),
+        )?;
+        writeln!(
+            f,
+            r#"  <testsuite name="{name}" tests="{tests}" failures="{failures}" errors="0" skipped="0" time="{time:.3}">"#,
+            name = Escaped(&self.name),
+            tests = self.test_cases.len(),
+            time = duration.as_secs_f64(),
+        )?;
+        for test_case in &self.test_cases {
+            writeln!(
+                f,
+                r#"    <testcase name="{name}" classname="{class_name}" time="{time:.3}">"#,
+                name = Escaped(&test_case.name),
+                class_name = Escaped(&self.name),
+                time = test_case.duration.as_secs_f64(),
+            )?;
+            if let Some(failure) = &test_case.failure {
+                writeln!(
+                    f,
+                    r#"      <failure message="{message}"/>"#,
+                    message = Escaped(failure)
+                )?;
+            }
+            writeln!(
+                f,
+                "      <system-out>{output}</system-out>",
+                output = Escaped(&test_case.output)
+            )?;
+            writeln!(f, "    </testcase>")?;
+        }
+        writeln!(f, "  </testsuite>")?;
+        writeln!(f, "</testsuites>")
+    }
+}
+
+/// Text escaped for use in XML attribute values and character data
+struct Escaped<'a>(&'a str);
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '&' => f.write_str("&amp;")?,
+                '<' => f.write_str("&lt;")?,
+                '>' => f.write_str("&gt;")?,
+                '"' => f.write_str("&quot;")?,
+                '\'' => f.write_str("&apos;")?,
+                // Control characters other than whitespace are not allowed in XML 1.0 documents
+                '\t' | '\n' | '\r' => write!(f, "{c}")?,
+                c if c.is_control() => {}
+                c => write!(f, "{c}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_contains_one_test_case_per_step() {
+        let test_suite = TestSuite {
+            name: "sample_kmdf_driver".to_string(),
+            test_cases: vec![
+                TestCase {
+                    name: "infverif sample_kmdf_driver.inf".to_string(),
+                    duration: Duration::from_millis(1500),
+                    output: "INF is VALID".to_string(),
+                    failure: None,
+                },
+                TestCase {
+                    name: "signtool verify sample_kmdf_driver.cat".to_string(),
+                    duration: Duration::from_millis(250),
+                    output: "SignTool Error: <no signature>\u{1b}".to_string(),
+                    failure: Some("signtool failed with exit code: 1".to_string()),
+                },
+            ],
+        };
+
+        assert_eq!(
+            test_suite.to_string(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites tests="2" failures="1" time="1.750">
+  <testsuite name="sample_kmdf_driver" tests="2" failures="1" errors="0" skipped="0" time="1.750">
+    <testcase name="infverif sample_kmdf_driver.inf" classname="sample_kmdf_driver" time="1.500">
+      <system-out>INF is VALID</system-out>
+    </testcase>
+    <testcase name="signtool verify sample_kmdf_driver.cat" classname="sample_kmdf_driver" time="0.250">
+      <failure message="signtool failed with exit code: 1"/>
+      <system-out>SignTool Error: &lt;no signature&gt;</system-out>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+
+    #[test]
+    fn file_names_follow_junit_convention() {
+        assert_eq!(
+            TestSuite::file_name(
+                "sample_kmdf_driver",
+                "signtool verify sample_kmdf_driver.cat"
+            ),
+            "TEST-sample_kmdf_driver-signtool_verify_sample_kmdf_driver_cat.xml"
+        );
+    }
+}
@@ -15,16 +15,47 @@
 pub use bindgen::BuilderExt;
 use metadata::TryFromCargoMetadataError;
 
+pub mod additional_binaries;
+pub mod additional_drivers;
+pub mod bindgen_generation;
+pub mod build_profile;
+pub mod build_provenance;
+pub mod cargo_directives;
 pub mod cargo_make;
+pub mod cli_defaults;
+pub mod diagnostics;
+pub mod doctor;
+pub mod driver_verifier;
+pub mod env_snapshot;
+pub mod inf_policy;
+pub mod junit;
 pub mod metadata;
+pub mod package;
+pub mod packaging;
+pub mod pregenerated_bindings;
+pub mod probe;
+pub mod stack_frame_limits;
+pub mod symbol_surface;
+pub mod target_directory;
+pub mod usage_scan;
+pub mod version_info;
+pub mod wdf_coinstaller;
+pub mod wdk_content_root;
+pub mod wdk_layout;
+pub mod wdk_lock;
 
 mod utils;
 
 mod bindgen;
+mod build_fingerprint;
+mod inf;
+mod inx;
 
 use std::{env, path::PathBuf, sync::LazyLock};
 
+use cargo_directives::emit_cargo_directive;
 use cargo_metadata::MetadataCommand;
+use diagnostics::record_diagnostic;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use utils::PathExt;
@@ -39,6 +70,21 @@ pub struct Config {
     cpu_architecture: CpuArchitecture,
     /// Build configuration of driver
     pub driver_config: DriverConfig,
+    /// Minimum version of Windows targeted by the driver. When `None`, the
+    /// defaults of the WDK headers are used
+    pub target_os: Option<TargetOs>,
+    /// Whether to link against the Spectre-mitigated variants of the WDK
+    /// libraries
+    pub spectre_mitigation: bool,
+    /// Opt-in instrumentation for debug and Driver Verifier builds. When
+    /// `None`, no instrumentation is configured
+    pub instrumentation: Option<InstrumentationConfig>,
+    /// Opt-in security-relevant preprocessor definitions. When `None`, none of
+    /// them are defined
+    pub security: Option<SecurityConfig>,
+    /// Extra items to block from, or built-in blocked items to allow in, the
+    /// bindings generated by [`BuilderExt::wdk_default`]
+    pub bindgen: BindgenConfig,
 }
 
 /// The driver type with its associated configuration parameters
@@ -86,6 +132,86 @@ pub enum CpuArchitecture {
     Arm64,
 }
 
+/// The minimum version of Windows targeted by a driver. This determines the
+/// values of the `NTDDI_VERSION`, `_WIN32_WINNT` and `WINVER` definitions used
+/// when processing WDK headers.
+///
+/// In the `metadata.wdk` section of a `Cargo.toml`, this is specified either as
+/// the name of a Windows release (ex. `target-os = "windows11-22h2"`), or as an
+/// explicit `NTDDI_VERSION` value (ex. `target-os = "0x0A00000C"`).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct TargetOs {
+    ntddi_version: u32,
+}
+
+/// Named Windows releases that can be used as a [`TargetOs`], along with their
+/// `NTDDI_VERSION` values (sourced from `sdkddkver.h`) and OS build numbers.
+/// Releases are sorted in ascending order.
+const TARGET_OS_RELEASES: [(&str, u32, u32); 13] = [
+    ("windows10-1507", 0x0A00_0000, 10240),
+    ("windows10-1511", 0x0A00_0001, 10586),
+    ("windows10-1607", 0x0A00_0002, 14393),
+    ("windows10-1703", 0x0A00_0003, 15063),
+    ("windows10-1709", 0x0A00_0004, 16299),
+    ("windows10-1803", 0x0A00_0005, 17134),
+    ("windows10-1809", 0x0A00_0006, 17763),
+    ("windows10-1903", 0x0A00_0007, 18362),
+    ("windows10-2004", 0x0A00_0008, 19041),
+    ("windows-server-2022", 0x0A00_000A, 20348),
+    ("windows11-21h2", 0x0A00_000B, 22000),
+    ("windows11-22h2", 0x0A00_000C, 22621),
+    ("windows11-24h2", 0x0A00_0010, 26100),
+];
+
+/// Aliases for entries in [`TARGET_OS_RELEASES`]
+const TARGET_OS_RELEASE_ALIASES: [(&str, &str); 2] = [
+    ("windows10", "windows10-1507"),
+    ("windows11", "windows11-21h2"),
+];
+
+/// Name of the `cfg` emitted for every named [`TargetOs`] release that is
+/// older than, or the same as, the configured [`TargetOs`]. This allows code to
+/// be gated on a minimum OS (ex. `#[cfg(wdk_target_os_at_least =
+/// "windows11-22h2")]`).
+const TARGET_OS_AT_LEAST_CFG_KEY: &str = "wdk_target_os_at_least";
+
+/// Name of the `cfg` emitted when [`Config::instrumentation`] is configured.
+/// This allows drivers to compile in extra checks for instrumented builds (ex.
+/// `#[cfg(wdk_instrumentation)]`).
+const INSTRUMENTATION_CFG_KEY: &str = "wdk_instrumentation";
+
+/// Name of the `cfg` emitted when [`SecurityConfig::pool_nx_optin`] is enabled
+/// (ex. `#[cfg(wdk_pool_nx_optin)]`)
+const POOL_NX_OPTIN_CFG_KEY: &str = "wdk_pool_nx_optin";
+
+/// Name of the `cfg` emitted when [`SecurityConfig::deprecate_ddk_functions`]
+/// is enabled (ex. `#[cfg(wdk_deprecate_ddk_functions)]`)
+const DEPRECATE_DDK_FUNCTIONS_CFG_KEY: &str = "wdk_deprecate_ddk_functions";
+
+/// Name of the subdirectory of a WDK library directory that contains the
+/// Spectre-mitigated variants of its libraries
+const SPECTRE_MITIGATED_LIBRARY_DIRECTORY_NAME: &str = "spectre";
+
+/// Key in the `metadata.wdk` section of a UMDF driver package's `Cargo.toml`
+/// that skips the check that the C runtime is statically linked.
+///
+/// Kernel-mode drivers must always statically link the C runtime, so this key
+/// has no effect on them.
+pub const SKIP_STATIC_CRT_CHECK_METADATA_KEY: &str = "skip-static-crt-check";
+
+/// Key in the `metadata.wdk` section of a `Cargo.toml` that declares the
+/// [`BindgenConfig`] of the bindings generated for the WDK.
+///
+/// Unlike the rest of the WDK configuration, this key does not need to be
+/// identical across the dependency graph: the items declared by every package
+/// and by the workspace are combined.
+pub const BINDGEN_METADATA_KEY: &str = "bindgen";
+
+/// Flag that makes a Clang-based C compiler emit LLVM bitcode for
+/// cross-language LTO, when [`Config::is_linker_plugin_lto_enabled`]
+pub const LINKER_PLUGIN_LTO_C_COMPILER_FLAG: &str = "-flto=thin";
+
 /// The configuration parameters for KMDF drivers
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(
@@ -116,6 +242,72 @@ pub struct UmdfConfig {
     pub minimum_umdf_version_minor: Option<u8>,
 }
 
+/// The configuration parameters for instrumented driver builds (ex. for
+/// testing under Driver Verifier).
+///
+/// None of these options disable the `/INTEGRITYCHECK` linker option, so
+/// instrumented drivers can still be loaded with forced integrity checking.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(
+    deny_unknown_fields,
+    rename_all(serialize = "SCREAMING_SNAKE_CASE", deserialize = "kebab-case")
+)]
+pub struct InstrumentationConfig {
+    /// Whether to link the driver with Control Flow Guard (`/GUARD:CF`), which
+    /// is required for drivers loaded with kernel CFG enabled. This requires
+    /// the driver to also be compiled with `-C control-flow-guard`
+    #[serde(default)]
+    pub control_flow_guard: bool,
+}
+
+/// Security-relevant preprocessor definitions that are added to the processing
+/// of the WDK headers, both when generating bindings and when compiling the C
+/// code built into `wdk-sys`.
+///
+/// Each enabled definition also enables a `cfg` of the same name, prefixed with
+/// `wdk_` (ex. `#[cfg(wdk_pool_nx_optin)]`), so that code can depend on it.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(
+    deny_unknown_fields,
+    rename_all(serialize = "SCREAMING_SNAKE_CASE", deserialize = "kebab-case")
+)]
+pub struct SecurityConfig {
+    /// Whether to define `POOL_NX_OPTIN=1`, which makes `NonPagedPool`
+    /// allocations in the WDK headers use non-executable pool once the driver
+    /// opts in at runtime
+    #[serde(default)]
+    pub pool_nx_optin: bool,
+    /// Whether to define `DEPRECATE_DDK_FUNCTIONS=1`, which removes deprecated
+    /// DDK functions from the WDK headers, and therefore from the generated
+    /// bindings
+    #[serde(default)]
+    pub deprecate_ddk_functions: bool,
+}
+
+/// Adjustments to the items blocked from the bindings generated for the WDK,
+/// declared in the `metadata.wdk.bindgen` section of a `Cargo.toml`.
+///
+/// This allows working around WDK headers that bindgen fails to generate valid
+/// bindings for, without waiting for a release of `wdk-sys`:
+///
+/// ```toml
+/// [package.metadata.wdk.bindgen]
+/// blocklist = [".*_SOME_BROKEN_STRUCT"]
+/// allowlist = [".*WHEA_XPF_MCA_SECTION"]
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BindgenConfig {
+    /// Regexes of extra items to block from the generated bindings (see
+    /// [`Builder::blocklist_item`](::bindgen::Builder::blocklist_item))
+    #[serde(default)]
+    pub blocklist: Vec<String>,
+    /// Items blocked by default that should be generated anyway. Each entry
+    /// must exactly match one of the regexes blocked by default
+    #[serde(default)]
+    pub allowlist: Vec<String>,
+}
+
 /// Errors that could result from configuring a build via [`wdk-build`]
 #[derive(Debug, Error)]
 pub enum ConfigError {
@@ -130,6 +322,19 @@ pub enum ConfigError {
         directory: String,
     },
 
+    /// Error returned when Spectre mitigation is enabled, but the
+    /// Spectre-mitigated libraries are not installed
+    #[error(
+        "cannot find Spectre-mitigated libraries in {directory}. Ensure that the \
+         Spectre-mitigated libraries are installed, or remove `spectre-mitigation` from the WDK \
+         metadata."
+    )]
+    SpectreMitigatedLibrariesNotFound {
+        /// Path of the directory where the Spectre-mitigated libraries were
+        /// expected
+        directory: String,
+    },
+
     /// Error returned when an
     /// `utils::PathExt::strip_extended_length_path_prefix` operation fails
     #[error(transparent)]
@@ -140,12 +345,22 @@ pub enum ConfigError {
     #[error(transparent)]
     TryFromCargoMetadataError(#[from] metadata::TryFromCargoMetadataError),
 
-    /// Error returned when a [`Config`] fails to be serialized
+    /// Error returned when the WDK content root directory cannot be detected
+    #[error(transparent)]
+    WdkContentRootDetectionError(#[from] wdk_content_root::WdkContentRootDetectionError),
+
+    /// Error returned when the WDK version set in the environment does not
+    /// match the version of the detected WDK
     #[error(
-        "WDKContentRoot should be able to be detected. Ensure that the WDK is installed, or that \
-         the environment setup scripts in the eWDK have been run."
+        "the WDK version set in the environment ({version}) does not match the version of the \
+         detected WDK ({detected_version})"
     )]
-    WdkContentRootDetectionError,
+    WdkVersionMismatchError {
+        /// The WDK version set in the environment
+        version: String,
+        /// The version of the detected WDK
+        detected_version: String,
+    },
 
     /// Error returned when the WDK version string does not match the expected
     /// format
@@ -155,10 +370,88 @@ pub enum ConfigError {
         version: String,
     },
 
+    /// Error returned when the WDK layout named by the `WDK_BUILD_WDK_LAYOUT`
+    /// environment variable is not a known layout
+    #[error("unknown WDK layout `{name}`. Known WDK layouts: {known_layouts}")]
+    UnknownWdkLayout {
+        /// Name of the requested WDK layout
+        name: String,
+        /// Comma-separated names of the known WDK layouts
+        known_layouts: String,
+    },
+
+    /// Error returned when the directory of pre-generated bindings named by the
+    /// `WDK_BUILD_PREGENERATED_BINDINGS_DIR` environment variable does not
+    /// contain bindings for the current WDK configuration
+    #[error(
+        "cannot find pre-generated bindings for the current WDK configuration in {directory}. \
+         Generate them with `cargo make --env WDK_BUILD_GENERATED_BINDINGS_OUT_DIR=<directory> \
+         generate-wdk-sys-bindings`, or unset WDK_BUILD_PREGENERATED_BINDINGS_DIR to generate the \
+         bindings with bindgen"
+    )]
+    PregeneratedBindingsNotFound {
+        /// Path of the directory where the pre-generated bindings were
+        /// expected
+        directory: String,
+    },
+
+    /// Error returned when the pre-generated bindings for the current WDK
+    /// configuration were generated with different `wdk-sys` features or
+    /// bindgen settings than the current build
+    #[error(
+        "pre-generated bindings in {directory} were generated with different wdk-sys features or \
+         bindgen settings than the current build. Regenerate them with `cargo make --env \
+         WDK_BUILD_GENERATED_BINDINGS_OUT_DIR=<directory> generate-wdk-sys-bindings`"
+    )]
+    PregeneratedBindingsMismatch {
+        /// Path of the directory of the pre-generated bindings
+        directory: String,
+    },
+
+    /// Error returned when the manifest of pre-generated bindings cannot be
+    /// parsed
+    #[error("failed to parse pre-generated bindings manifest {path}")]
+    InvalidPregeneratedBindingsManifest {
+        /// Path of the manifest
+        path: String,
+        /// [`serde_json::Error`] that caused the parsing to fail
+        #[source]
+        error_source: serde_json::Error,
+    },
+
+    /// Error returned when the `generate-wdk-sys-bindings` task is run without
+    /// the `WDK_BUILD_GENERATED_BINDINGS_OUT_DIR` environment variable
+    #[error(
+        "WDK_BUILD_GENERATED_BINDINGS_OUT_DIR must be set to the directory that the generated \
+         wdk-sys bindings are written to"
+    )]
+    GeneratedBindingsOutDirNotSet,
+
+    /// Error returned when the `package` task is run without selecting the
+    /// directory of prebuilt driver binaries with `--input`
+    #[error(
+        "the package task requires --input <DIRECTORY> to select the directory of the prebuilt \
+         driver binaries to package"
+    )]
+    PackageInputNotSet,
+
+    /// Error returned when a driver binary is missing from the directory of
+    /// prebuilt driver binaries selected by `--input`
+    #[error("cannot find prebuilt driver binary: {}", .path.display())]
+    PrebuiltBinaryNotFound {
+        /// Path where the driver binary was expected
+        path: PathBuf,
+    },
+
     /// Error returned when `cargo_metadata` execution or parsing fails
     #[error(transparent)]
     CargoMetadataError(#[from] cargo_metadata::Error),
 
+    /// Error returned when scanning source code for referenced identifiers
+    /// fails
+    #[error(transparent)]
+    UsageScanError(#[from] usage_scan::UsageScanError),
+
     /// Error returned when multiple versions of the wdk-build package are
     /// detected
     #[error(
@@ -171,23 +464,238 @@ pub enum ConfigError {
     },
 
     /// Error returned when the c runtime is not configured to be statically
-    /// linked
+    /// linked for a driver binary that requires it
     #[error(
-        "the C runtime is not properly configured to be statically linked. This is required for building WDK drivers. The recommended solution is to add the following snippet to a \
+        "the C runtime is not properly configured to be statically linked for the {package} package (target: {target}). This is required for building kernel-mode WDK drivers. The recommended solution is to add the following snippet to a \
         `.cargo/config.toml` file:
 [build]
 rustflags = [\"-C\", \"target-feature=+crt-static\"]
 
 \
         See https://doc.rust-lang.org/reference/linkage.html#static-and-dynamic-c-runtimes for more ways \
-        to enable static crt linkage"
+        to enable static crt linkage. UMDF drivers can instead opt out of this check by setting \
+        `skip-static-crt-check = true` in the `package.metadata.wdk` section of their `Cargo.toml`"
+    )]
+    StaticCrtNotEnabled {
+        /// Name of the package whose binary is not statically linked to the C
+        /// runtime
+        package: String,
+        /// Target triple that the package is built for
+        target: String,
+    },
+
+    /// Error returned when `control-flow-guard` is enabled in the
+    /// instrumentation or profile metadata, but the driver is not compiled with
+    /// Control Flow Guard
+    #[error(
+        "control-flow-guard is enabled in metadata.wdk.instrumentation or metadata.wdk.profile, \
+         but rustc is not configured to emit Control Flow Guard checks. The recommended solution \
+         is to add [\"-C\", \"control-flow-guard\"] to the rustflags in a `.cargo/config.toml` \
+         file"
+    )]
+    ControlFlowGuardNotEnabled,
+
+    /// Error returned when linker-plugin-based LTO is enabled, but the driver
+    /// is not linked with `lld-link`
+    #[error(
+        "linker-plugin-lto is enabled in rustflags, but the linker is not lld-link. Only lld-link \
+         can perform LTO on the LLVM bitcode emitted with linker-plugin-lto. The recommended \
+         solution is to add [\"-C\", \"linker=lld-link\"] to the rustflags in a \
+         `.cargo/config.toml` file"
+    )]
+    LinkerPluginLtoRequiresLldLink,
+
+    /// Error returned when linker-plugin-based LTO is enabled, but C code
+    /// linked into the driver is not compiled with a Clang-based compiler
+    #[error(
+        "linker-plugin-lto is enabled in rustflags, but the C compiler is not Clang-based, so it \
+         cannot emit LLVM bitcode for cross-language LTO. The recommended solution is to set the \
+         CC environment variable to clang-cl"
     )]
-    StaticCrtNotEnabled,
+    LinkerPluginLtoRequiresClang,
 
     /// Error returned when [`metadata::ser::Serializer`] fails to serialize the
     /// [`metadata::Wdk`]
     #[error(transparent)]
     SerdeError(#[from] metadata::Error),
+
+    /// Error returned when a [`TargetOs`] fails to be parsed
+    #[error(
+        "{target_os} is not a valid target OS. Expected an NTDDI_VERSION value (ex. 0x0A00000C) \
+         or one of: {valid_target_os_names}"
+    )]
+    TargetOsParseError {
+        /// The invalid target OS string
+        target_os: String,
+        /// Comma-separated list of valid target OS names
+        valid_target_os_names: String,
+    },
+
+    /// Error returned when the `TargetOSVersion` decorations of the models
+    /// sections in a driver's INF file are invalid
+    #[error(transparent)]
+    InfTargetOsDecorationError(#[from] inf::InfTargetOsDecorationError),
+
+    /// Error returned when a custom packaging step declared in
+    /// `metadata.wdk-packaging` is invalid or fails
+    #[error(transparent)]
+    PackagingStepError(#[from] packaging::PackagingStepError),
+
+    /// Error returned when a driver's INX file cannot be generated from
+    /// `metadata.wdk.inf`
+    #[error(transparent)]
+    InxGenerationError(#[from] inx::InxGenerationError),
+
+    /// Error returned when the stack frame limits declared with
+    /// `#[max_stack(bytes)]` cannot be validated or are exceeded
+    #[error(transparent)]
+    StackFrameLimitError(#[from] stack_frame_limits::StackFrameLimitError),
+
+    /// Error returned when the target directory contains artifacts built with a
+    /// different WDK or WDK configuration, or they cannot be cleaned
+    #[error(transparent)]
+    BuildFingerprintError(#[from] build_fingerprint::BuildFingerprintError),
+
+    /// Error returned when the WDK lock file or its configuration is invalid,
+    /// or the detected WDK differs from the one recorded in it
+    #[error(transparent)]
+    WdkLockError(#[from] wdk_lock::WdkLockError),
+
+    /// Error returned when a snapshot of the environment of the build cannot be
+    /// replayed or captured
+    #[error(transparent)]
+    EnvSnapshotError(#[from] env_snapshot::EnvSnapshotError),
+
+    /// Error returned when the listing of the items in the generated bindings
+    /// cannot be written, read or compared, or when items were removed or
+    /// changed between two listings
+    #[error(transparent)]
+    SymbolSurfaceError(#[from] symbol_surface::SymbolSurfaceError),
+
+    /// Error returned when the target directory of the build, or the top-level
+    /// `Cargo.toml` of the build, cannot be resolved
+    #[error(transparent)]
+    TargetDirectoryError(#[from] target_directory::TargetDirectoryError),
+
+    /// Error returned when the additional binaries declared in
+    /// `metadata.wdk.package.additional-binaries` are invalid, fail to build,
+    /// or are not installed by the driver's INF
+    #[error(transparent)]
+    AdditionalBinariesError(#[from] additional_binaries::AdditionalBinariesError),
+
+    /// Error returned when the additional drivers declared in
+    /// `metadata.wdk.package.additional-drivers` are invalid, or fail to be
+    /// built
+    #[error(transparent)]
+    AdditionalDriversError(#[from] additional_drivers::AdditionalDriversError),
+
+    /// Error returned when the WDF coinstaller declared in
+    /// `metadata.wdk.package.wdf-coinstaller-version` is invalid, cannot be
+    /// found in the WDK, or is not installed by the driver's INF
+    #[error(transparent)]
+    WdfCoInstallerError(#[from] wdf_coinstaller::WdfCoInstallerError),
+
+    /// Error returned when the `VERSIONINFO` resource declared in
+    /// `metadata.wdk.package.version-info` is invalid, or fails to be compiled
+    #[error(transparent)]
+    VersionInfoError(#[from] version_info::VersionInfoError),
+
+    /// Error returned when the Cargo profile of a driver is not supported for
+    /// its driver model, or does not satisfy the requirements declared in
+    /// `metadata.wdk.profile`
+    #[error(transparent)]
+    BuildProfileError(#[from] build_profile::BuildProfileError),
+
+    /// Error returned when the Driver Verifier settings selected by
+    /// `--verifier` are invalid, or fail to be applied to the deployed driver
+    #[error(transparent)]
+    DriverVerifierError(#[from] driver_verifier::DriverVerifierError),
+
+    /// Error returned when the INF policy selected by `--inf-policy` fails to
+    /// be applied
+    #[error(transparent)]
+    InfPolicyError(#[from] inf_policy::InfPolicyError),
+
+    /// Error returned when packaging a driver for one of the architectures
+    /// selected by `--target-arch all` fails
+    #[error("packaging the driver for {target_triple} failed with {exit_status}")]
+    MultiArchitecturePackagingError {
+        /// Target triple of the architecture that failed to be packaged
+        target_triple: String,
+        /// Exit status of the `cargo make` process that packaged the driver
+        exit_status: std::process::ExitStatus,
+    },
+
+    /// Error returned when an optional component of the WDK installation that
+    /// is required by a task is not installed
+    #[error(
+        "{component} ({}) is not installed. Install the {installer_feature} feature of the WDK \
+         or Windows SDK",
+        .component.executable_name()
+    )]
+    MissingWdkComponent {
+        /// The component that is not installed
+        component: probe::Component,
+        /// Name of the installer feature that installs the component
+        installer_feature: &'static str,
+    },
+
+    /// Error returned when `pnputil` fails to enumerate or delete installed
+    /// driver packages
+    #[error("pnputil {command} failed with {exit_status}")]
+    PnpUtilError {
+        /// Arguments `pnputil` was run with
+        command: String,
+        /// Exit status of the `pnputil` process
+        exit_status: std::process::ExitStatus,
+    },
+
+    /// Error returned when any of the checks of
+    /// [`cargo_make::doctor`] fails
+    #[error("{failed_checks} of the environment checks failed")]
+    DoctorChecksFailed {
+        /// Number of checks that failed
+        failed_checks: usize,
+    },
+
+    /// Error returned when the compiler cache fails to report its cache hit
+    /// statistics
+    #[error("{rustc_wrapper} --show-stats failed with {exit_status}")]
+    CompilerCacheStatsError {
+        /// The `RUSTC_WRAPPER` that failed to report its statistics
+        rustc_wrapper: String,
+        /// Exit status of the `RUSTC_WRAPPER` process
+        exit_status: std::process::ExitStatus,
+    },
+
+    /// Error returned when a WDK tool run by [`package::DriverPackage`] fails
+    #[error(transparent)]
+    PackageError(#[from] package::PackageError),
+
+    /// Error returned when a prerequisite for cross-compiling to another CPU
+    /// architecture than the host's is not installed
+    #[error("cannot build for {target_triple}: {prerequisite}. {remediation}")]
+    MissingCrossCompilationPrerequisite {
+        /// Target triple of the cross-compilation
+        target_triple: String,
+        /// Description of the missing prerequisite
+        prerequisite: String,
+        /// How to install the missing prerequisite
+        remediation: String,
+    },
+
+    /// Error returned when an entry of `metadata.wdk.bindgen.allowlist` does
+    /// not match any of the items blocked by default
+    #[error(
+        "{item} in metadata.wdk.bindgen.allowlist is not blocked by default. Items blocked by \
+         default: {blocklisted_items}"
+    )]
+    UnknownAllowlistedBindgenItem {
+        /// The allowlisted item
+        item: String,
+        /// Comma-separated items blocked by default
+        blocklisted_items: String,
+    },
 }
 
 /// Subset of APIs in the Windows Driver Kit
@@ -201,27 +709,82 @@ pub enum ApiSubset {
     Hid,
     /// API subset for SPB (Serial Peripheral Bus) drivers: <https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/_spb/>
     Spb,
+    /// API subset for Storport miniport drivers: <https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/_storage/>
+    Storport,
+    /// API subset for WSK (Winsock Kernel) clients: <https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wsk/>
+    Wsk,
+    /// API subset for WFP (Windows Filtering Platform) callout drivers: <https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/_netvista/>
+    Wfp,
+    /// API subset for Kernel Streaming (KS) minidrivers (ex. camera and audio
+    /// drivers): <https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/_stream/>
+    KernelStreaming,
+    /// API subset for USB client drivers: <https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/_usbref/>
+    Usb,
+    /// API subset for audio miniport drivers built on the Port Class
+    /// (`PortCls`) system driver: <https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/_audio/>
+    Audio,
+    /// API subset for Hyper-V `VMBus` drivers built on the `VMBus` Kernel Mode
+    /// Client Library (KMCL): <https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/vmbuskernelmodeclientlibapi/>
+    Vmbus,
 }
 
 impl Default for Config {
     #[must_use]
     fn default() -> Self {
-        Self {
-            wdk_content_root: utils::detect_wdk_content_root().expect(
-                "WDKContentRoot should be able to be detected. Ensure that the WDK is installed, \
-                 or that the environment setup scripts in the eWDK have been run.",
-            ),
-            driver_config: DriverConfig::Wdm,
-            cpu_architecture: utils::detect_cpu_architecture_in_build_script(),
-        }
+        Self::new_or_panic()
     }
 }
 
 impl Config {
     /// Create a new [`Config`] with default values
+    ///
+    /// # Panics
+    ///
+    /// Panics if the WDK content root directory cannot be detected. Use
+    /// [`Config::try_new`] to handle this case.
     #[must_use]
     pub fn new() -> Self {
-        Self::default()
+        Self::new_or_panic()
+    }
+
+    /// Create a new [`Config`] with default values
+    ///
+    /// # Errors
+    ///
+    /// This function will return a
+    /// [`ConfigError::WdkContentRootDetectionError`] if the WDK content root
+    /// directory cannot be detected. The error lists the locations searched
+    /// and the environment variables inspected.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `CARGO_CFG_TARGET_ARCH` environment variable is not set,
+    /// or if the cargo architecture is unsupported.
+    pub fn try_new() -> Result<Self, ConfigError> {
+        Ok(Self {
+            wdk_content_root: utils::try_detect_wdk_content_root()?,
+            driver_config: DriverConfig::Wdm,
+            cpu_architecture: utils::detect_cpu_architecture_in_build_script(),
+            target_os: None,
+            spectre_mitigation: false,
+            instrumentation: None,
+            security: None,
+            bindgen: BindgenConfig::default(),
+        })
+    }
+
+    /// Create a new [`Config`] with default values, panicking with the
+    /// locations searched and the environment variables inspected if the WDK
+    /// content root directory cannot be detected
+    ///
+    /// # Panics
+    ///
+    /// Panics if the WDK content root directory cannot be detected, if the
+    /// `CARGO_CFG_TARGET_ARCH` environment variable is not set, or if the
+    /// cargo architecture is unsupported.
+    #[must_use]
+    pub fn new_or_panic() -> Self {
+        Self::try_new().unwrap_or_else(|error| panic!("{error}"))
     }
 
     /// Create a [`Config`] from parsing the top-level Cargo manifest into a
@@ -236,16 +799,23 @@ pub fn new() -> Self {
     /// * the parsing of [`metadata::Wdk`] from any of the Cargo manifests fail
     /// * multiple conflicting [`metadata::Wdk`] configurations are detected
     /// * no [`metadata::Wdk`] configurations are detected
+    /// * the WDK content root directory cannot be detected
+    /// * the environment snapshot selected by
+    ///   [`env_snapshot::ENV_SNAPSHOT_REPLAY_ENV_VAR`] cannot be replayed, or
+    ///   the one selected by [`env_snapshot::ENV_SNAPSHOT_OUT_ENV_VAR`] cannot
+    ///   be captured
     ///
     /// # Panics
     ///
     /// Panics if the resolved top-level Cargo manifest path is not valid UTF-8
     pub fn from_env_auto() -> Result<Self, ConfigError> {
+        env_snapshot::replay_and_capture()?;
         let top_level_manifest = find_top_level_cargo_manifest();
         let cargo_metadata = MetadataCommand::new()
             .manifest_path(&top_level_manifest)
             .exec()?;
         let wdk_metadata = metadata::Wdk::try_from(&cargo_metadata)?;
+        let bindgen = metadata::parse_bindgen_config(&cargo_metadata)?;
 
         // Force rebuilds if any of the manifest files change (ex. if wdk metadata
         // section is modified)
@@ -257,15 +827,52 @@ pub fn from_env_auto() -> Result<Self, ConfigError> {
                     .expect("Path to Cargo manifests should always be valid UTF8"),
             ))
         {
-            println!("cargo:rerun-if-changed={manifest_path}");
+            emit_cargo_directive!(
+                "Cargo manifests containing the WDK configuration",
+                "rerun-if-changed={manifest_path}"
+            );
         }
 
         Ok(Self {
             driver_config: wdk_metadata.driver_model,
-            ..Default::default()
+            target_os: wdk_metadata.target_os,
+            spectre_mitigation: wdk_metadata.spectre_mitigation,
+            instrumentation: wdk_metadata.instrumentation,
+            security: wdk_metadata.security,
+            bindgen,
+            ..Self::try_new()?
         })
     }
 
+    /// Returns the [`CpuArchitecture`] this [`Config`] targets
+    #[must_use]
+    pub const fn cpu_architecture(&self) -> CpuArchitecture {
+        self.cpu_architecture
+    }
+
+    /// Calls `f` with a copy of this [`Config`] targeting each of the
+    /// architectures in [`CpuArchitecture::ALL`], in order. This allows tools
+    /// that build a driver for multiple architectures to derive the
+    /// architecture-specific configuration (ex. [`Config::library_paths`]) of
+    /// each build from a single [`Config`].
+    ///
+    /// # Errors
+    ///
+    /// This function returns the first error returned by `f`. The remaining
+    /// architectures are not visited after an error.
+    pub fn for_each_architecture<F, E>(&self, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(&Self) -> Result<(), E>,
+    {
+        for cpu_architecture in CpuArchitecture::ALL {
+            f(&Self {
+                cpu_architecture,
+                ..self.clone()
+            })?;
+        }
+        Ok(())
+    }
+
     fn emit_check_cfg_settings() {
         for (cfg_key, allowed_values) in EXPORTED_CFG_SETTINGS.iter() {
             let allowed_cfg_value_string =
@@ -292,8 +899,36 @@ fn emit_check_cfg_settings() {
             };
 
             // Emit allowed cfg values
-            println!("cargo::rustc-check-cfg=cfg({cfg_key}, values({allowed_cfg_value_string}))");
+            emit_cargo_directive!(
+                "cfg settings exported by wdk-build",
+                "rustc-check-cfg=cfg({cfg_key}, values({allowed_cfg_value_string}))"
+            );
         }
+
+        let allowed_target_os_values = TARGET_OS_RELEASES
+            .iter()
+            .map(|(name, ..)| format!(r#""{name}""#))
+            .collect::<Vec<_>>()
+            .join(",");
+        emit_cargo_directive!(
+            "cfg settings exported by wdk-build",
+            "rustc-check-cfg=cfg({TARGET_OS_AT_LEAST_CFG_KEY}, values({allowed_target_os_values}))"
+        );
+
+        emit_cargo_directive!(
+            "cfg settings exported by wdk-build",
+            "rustc-check-cfg=cfg({INSTRUMENTATION_CFG_KEY})"
+        );
+
+        emit_cargo_directive!(
+            "cfg settings exported by wdk-build",
+            "rustc-check-cfg=cfg({POOL_NX_OPTIN_CFG_KEY})"
+        );
+
+        emit_cargo_directive!(
+            "cfg settings exported by wdk-build",
+            "rustc-check-cfg=cfg({DEPRECATE_DDK_FUNCTIONS_CFG_KEY})"
+        );
     }
 
     /// Expose `cfg` settings based on this [`Config`] to enable conditional
@@ -305,6 +940,10 @@ fn emit_cfg_settings(&self) -> Result<(), ConfigError> {
         let serialized_wdk_metadata_map =
             metadata::to_map::<std::collections::BTreeMap<_, _>>(&metadata::Wdk {
                 driver_model: self.driver_config.clone(),
+                target_os: self.target_os,
+                spectre_mitigation: self.spectre_mitigation,
+                instrumentation: self.instrumentation,
+                security: self.security,
             })?;
 
         for cfg_key in EXPORTED_CFG_SETTINGS.iter().map(|(key, _)| *key) {
@@ -320,188 +959,111 @@ fn emit_cfg_settings(&self) -> Result<(), ConfigError> {
             };
 
             // Emit cfg
-            println!(r#"cargo::rustc-cfg={cfg_key}="{cfg_value}""#);
+            emit_cargo_directive!("WDK configuration", r#"rustc-cfg={cfg_key}="{cfg_value}""#);
+        }
+
+        if let Some(target_os) = self.target_os {
+            for release_name in target_os.releases_at_or_below() {
+                emit_cargo_directive!(
+                    "target-os",
+                    r#"rustc-cfg={TARGET_OS_AT_LEAST_CFG_KEY}="{release_name}""#
+                );
+            }
+        }
+
+        if self.instrumentation.is_some() {
+            emit_cargo_directive!("instrumentation", "rustc-cfg={INSTRUMENTATION_CFG_KEY}");
+        }
+
+        if let Some(security) = self.security {
+            if security.pool_nx_optin {
+                emit_cargo_directive!(
+                    "security: pool-nx-optin",
+                    "rustc-cfg={POOL_NX_OPTIN_CFG_KEY}"
+                );
+            }
+            if security.deprecate_ddk_functions {
+                emit_cargo_directive!(
+                    "security: deprecate-ddk-functions",
+                    "rustc-cfg={DEPRECATE_DDK_FUNCTIONS_CFG_KEY}"
+                );
+            }
         }
 
         Ok(())
     }
 
     /// Return header include paths required to build and link based off of the
-    /// configuration of `Config`
+    /// configuration of `Config`. The paths are resolved by the
+    /// [`WdkLayout`](wdk_layout::WdkLayout) selected for the installed WDK.
     ///
     /// # Errors
     ///
     /// This function will return an error if any of the required paths do not
-    /// exist.
+    /// exist, or if a WDK layout cannot be selected.
     pub fn include_paths(&self) -> Result<impl Iterator<Item = PathBuf>, ConfigError> {
-        let mut include_paths = vec![];
-
-        let include_directory = self.wdk_content_root.join("Include");
-
-        // Add windows sdk include paths
-        // Based off of logic from WindowsDriver.KernelMode.props &
-        // WindowsDriver.UserMode.props in NI(22H2) WDK
-        let sdk_version = utils::get_latest_windows_sdk_version(include_directory.as_path())?;
-        let windows_sdk_include_path = include_directory.join(sdk_version);
-
-        let crt_include_path = windows_sdk_include_path.join("km/crt");
-        if !crt_include_path.is_dir() {
-            return Err(ConfigError::DirectoryNotFound {
-                directory: crt_include_path.to_string_lossy().into(),
-            });
-        }
-        include_paths.push(
-            crt_include_path
-                .canonicalize()?
-                .strip_extended_length_path_prefix()?,
-        );
-
-        let km_or_um_include_path = windows_sdk_include_path.join(match self.driver_config {
-            DriverConfig::Wdm | DriverConfig::Kmdf(_) => "km",
-            DriverConfig::Umdf(_) => "um",
-        });
-        if !km_or_um_include_path.is_dir() {
-            return Err(ConfigError::DirectoryNotFound {
-                directory: km_or_um_include_path.to_string_lossy().into(),
-            });
-        }
-        include_paths.push(
-            km_or_um_include_path
-                .canonicalize()?
-                .strip_extended_length_path_prefix()?,
-        );
+        let sdk_version =
+            utils::get_latest_windows_sdk_version(&self.wdk_content_root.join("Include"))?;
+        let wdk_layout = wdk_layout::select_wdk_layout(&sdk_version)?;
 
-        let kit_shared_include_path = windows_sdk_include_path.join("shared");
-        if !kit_shared_include_path.is_dir() {
-            return Err(ConfigError::DirectoryNotFound {
-                directory: kit_shared_include_path.to_string_lossy().into(),
-            });
-        }
-        include_paths.push(
-            kit_shared_include_path
-                .canonicalize()?
-                .strip_extended_length_path_prefix()?,
-        );
-
-        // Add other driver type-specific include paths
-        match &self.driver_config {
-            DriverConfig::Wdm => {}
-            DriverConfig::Kmdf(kmdf_config) => {
-                let kmdf_include_path = include_directory.join(format!(
-                    "wdf/kmdf/{}.{}",
-                    kmdf_config.kmdf_version_major, kmdf_config.target_kmdf_version_minor
-                ));
-                if !kmdf_include_path.is_dir() {
-                    return Err(ConfigError::DirectoryNotFound {
-                        directory: kmdf_include_path.to_string_lossy().into(),
-                    });
-                }
-                include_paths.push(
-                    kmdf_include_path
-                        .canonicalize()?
-                        .strip_extended_length_path_prefix()?,
+        wdk_layout
+            .sdk_include_directories(&self.wdk_content_root, &sdk_version, &self.driver_config)
+            .into_iter()
+            .chain(wdk_layout.wdf_include_directory(&self.wdk_content_root, &self.driver_config))
+            .map(|include_path| {
+                record_diagnostic!(
+                    "include path check",
+                    ("checked include directory"),
+                    path = include_path.display(),
+                    exists = include_path.is_dir(),
                 );
-            }
-            DriverConfig::Umdf(umdf_config) => {
-                let umdf_include_path = include_directory.join(format!(
-                    "wdf/umdf/{}.{}",
-                    umdf_config.umdf_version_major, umdf_config.target_umdf_version_minor
-                ));
-                if !umdf_include_path.is_dir() {
+                if !include_path.is_dir() {
                     return Err(ConfigError::DirectoryNotFound {
-                        directory: umdf_include_path.to_string_lossy().into(),
+                        directory: include_path.to_string_lossy().into(),
                     });
                 }
-                include_paths.push(
-                    umdf_include_path
-                        .canonicalize()?
-                        .strip_extended_length_path_prefix()?,
-                );
-            }
-        }
-
-        Ok(include_paths.into_iter())
+                Ok(include_path
+                    .canonicalize()?
+                    .strip_extended_length_path_prefix()?)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(std::iter::IntoIterator::into_iter)
     }
 
     /// Return library include paths required to build and link based off of
-    /// the configuration of [`Config`].
+    /// the configuration of [`Config`]. The paths are resolved by the
+    /// [`WdkLayout`](wdk_layout::WdkLayout) selected for the installed WDK.
     ///
-    /// For UMDF drivers, this assumes a "Windows-Driver" Target Platform.
+    /// For UMDF drivers, this assumes a "Windows-Driver" Target Platform. When
+    /// [`Config::spectre_mitigation`] is enabled, the `spectre` subdirectory of
+    /// each library directory is used instead, which corresponds to the
+    /// `Spectre Mitigation` setting in the `C/C++` property pages in the WDK.
     ///
     /// # Errors
     ///
     /// This function will return an error if any of the required paths do not
-    /// exist.
+    /// exist, if a WDK layout cannot be selected, or if Spectre mitigation is
+    /// enabled and the Spectre-mitigated libraries are not installed.
     pub fn library_paths(&self) -> Result<impl Iterator<Item = PathBuf>, ConfigError> {
-        let mut library_paths = vec![];
-
-        let library_directory = self.wdk_content_root.join("Lib");
-
-        // Add windows sdk library paths
-        // Based off of logic from WindowsDriver.KernelMode.props &
-        // WindowsDriver.UserMode.props in NI(22H2) WDK
-        let sdk_version = utils::get_latest_windows_sdk_version(library_directory.as_path())?;
-        let windows_sdk_library_path =
-            library_directory
-                .join(sdk_version)
-                .join(match self.driver_config {
-                    DriverConfig::Wdm | DriverConfig::Kmdf(_) => {
-                        format!("km/{}", self.cpu_architecture.as_windows_str(),)
-                    }
-                    DriverConfig::Umdf(_) => {
-                        format!("um/{}", self.cpu_architecture.as_windows_str(),)
-                    }
-                });
-        if !windows_sdk_library_path.is_dir() {
-            return Err(ConfigError::DirectoryNotFound {
-                directory: windows_sdk_library_path.to_string_lossy().into(),
-            });
-        }
-        library_paths.push(
-            windows_sdk_library_path
-                .canonicalize()?
-                .strip_extended_length_path_prefix()?,
-        );
+        let sdk_version =
+            utils::get_latest_windows_sdk_version(&self.wdk_content_root.join("Lib"))?;
+        let wdk_layout = wdk_layout::select_wdk_layout(&sdk_version)?;
+
+        let mut library_paths =
+            vec![self.resolve_library_path(wdk_layout.sdk_library_directory(
+                &self.wdk_content_root,
+                &sdk_version,
+                &self.driver_config,
+                self.cpu_architecture,
+            ))?];
 
         // Add other driver type-specific library paths
-        match &self.driver_config {
-            DriverConfig::Wdm => (),
-            DriverConfig::Kmdf(kmdf_config) => {
-                let kmdf_library_path = library_directory.join(format!(
-                    "wdf/kmdf/{}/{}.{}",
-                    self.cpu_architecture.as_windows_str(),
-                    kmdf_config.kmdf_version_major,
-                    kmdf_config.target_kmdf_version_minor
-                ));
-                if !kmdf_library_path.is_dir() {
-                    return Err(ConfigError::DirectoryNotFound {
-                        directory: kmdf_library_path.to_string_lossy().into(),
-                    });
-                }
-                library_paths.push(
-                    kmdf_library_path
-                        .canonicalize()?
-                        .strip_extended_length_path_prefix()?,
-                );
-            }
-            DriverConfig::Umdf(umdf_config) => {
-                let umdf_library_path = library_directory.join(format!(
-                    "wdf/umdf/{}/{}.{}",
-                    self.cpu_architecture.as_windows_str(),
-                    umdf_config.umdf_version_major,
-                    umdf_config.target_umdf_version_minor,
-                ));
-                if !umdf_library_path.is_dir() {
-                    return Err(ConfigError::DirectoryNotFound {
-                        directory: umdf_library_path.to_string_lossy().into(),
-                    });
-                }
-                library_paths.push(
-                    umdf_library_path
-                        .canonicalize()?
-                        .strip_extended_length_path_prefix()?,
-                );
-            }
+        if let Some(wdf_library_path) = wdk_layout.wdf_library_directory(
+            &self.wdk_content_root,
+            &self.driver_config,
+            self.cpu_architecture,
+        ) {
+            library_paths.push(self.resolve_library_path(wdf_library_path)?);
         }
 
         // Reverse order of library paths so that paths pushed later into the vec take
@@ -510,25 +1072,48 @@ pub fn library_paths(&self) -> Result<impl Iterator<Item = PathBuf>, ConfigError
         Ok(library_paths.into_iter())
     }
 
+    /// Resolves the library directory to link against from `library_path`,
+    /// selecting its Spectre-mitigated variant if
+    /// [`Config::spectre_mitigation`] is enabled
+    fn resolve_library_path(&self, library_path: PathBuf) -> Result<PathBuf, ConfigError> {
+        record_diagnostic!(
+            "library path check",
+            ("checked library directory"),
+            path = library_path.display(),
+            exists = library_path.is_dir(),
+        );
+        if !library_path.is_dir() {
+            return Err(ConfigError::DirectoryNotFound {
+                directory: library_path.to_string_lossy().into(),
+            });
+        }
+
+        let library_path = if self.spectre_mitigation {
+            let spectre_library_path = library_path.join(SPECTRE_MITIGATED_LIBRARY_DIRECTORY_NAME);
+            record_diagnostic!(
+                "library path check",
+                ("checked Spectre-mitigated library directory"),
+                path = spectre_library_path.display(),
+                exists = spectre_library_path.is_dir(),
+            );
+            if !spectre_library_path.is_dir() {
+                return Err(ConfigError::SpectreMitigatedLibrariesNotFound {
+                    directory: spectre_library_path.to_string_lossy().into(),
+                });
+            }
+            spectre_library_path
+        } else {
+            library_path
+        };
+
+        Ok(library_path
+            .canonicalize()?
+            .strip_extended_length_path_prefix()?)
+    }
+
     /// Return an iterator of strings that represent compiler definitions
     /// derived from the `Config`
     pub fn preprocessor_definitions(&self) -> impl Iterator<Item = (String, Option<String>)> {
-        // _WIN32_WINNT=$(WIN32_WINNT_VERSION);
-        // WINVER=$(WINVER_VERSION);
-        // WINNT=1;
-        // NTDDI_VERSION=$(NTDDI_VERSION);
-
-        // Definition sourced from: Program Files\Windows
-        // Kits\10\build\10.0.26040.0\WindowsDriver.Shared.Props
-        // vec![ //from driver.os.props //D:\EWDK\rsprerelease\content\Program
-        // Files\Windows Kits\10\build\10.0.26040.0\WindowsDriver.OS.Props
-        // ("_WIN32_WINNT", Some()),CURRENT_WIN32_WINNT_VERSION
-        // ("WINVER", Some()), = CURRENT_WIN32_WINNT_VERSION
-        // ("WINNT", Some(1)),1
-        // ("NTDDI_VERSION", Some()),CURRENT_NTDDI_VERSION
-        // ]
-        // .into_iter()
-        // .map(|(key, value)| (key.to_string(), value.map(|v| v.to_string())))
         match self.cpu_architecture {
             // Definitions sourced from `Program Files\Windows
             // Kits\10\build\10.0.22621.0\WindowsDriver.x64.props`
@@ -603,6 +1188,31 @@ pub fn preprocessor_definitions(&self) -> impl Iterator<Item = (String, Option<S
             .into_iter()
             .map(|(key, value)| (key.to_string(), value.map(|v| v.to_string()))),
         )
+        .chain(self.target_os.into_iter().flat_map(|target_os| {
+            // Definitions sourced from: Program Files\Windows
+            // Kits\10\build\10.0.26040.0\WindowsDriver.OS.Props
+            let win32_winnt_version = format!("0x{:04X}", target_os.win32_winnt_version());
+            [
+                ("_WIN32_WINNT", Some(win32_winnt_version.clone())),
+                ("WINVER", Some(win32_winnt_version)),
+                ("WINNT", Some("1".to_string())),
+                (
+                    "NTDDI_VERSION",
+                    Some(format!("0x{:08X}", target_os.ntddi_version())),
+                ),
+            ]
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+        }))
+        .chain(self.security.into_iter().flat_map(|security| {
+            [
+                ("POOL_NX_OPTIN", security.pool_nx_optin),
+                ("DEPRECATE_DDK_FUNCTIONS", security.deprecate_ddk_functions),
+            ]
+            .into_iter()
+            .filter(|(_, is_enabled)| *is_enabled)
+            .map(|(key, _)| (key.to_string(), Some("1".to_string())))
+        }))
     }
 
     /// Return an iterator of strings that represent compiler flags (i.e.
@@ -648,7 +1258,7 @@ pub fn headers(&self, api_subset: ApiSubset) -> impl Iterator<Item = String> {
                     vec!["ntifs.h", "ntddk.h", "ntstrsafe.h"]
                 }
                 DriverConfig::Umdf(_) => {
-                    vec!["windows.h"]
+                    vec!["windows.h", "swdevice.h"]
                 }
             },
             ApiSubset::Wdf => {
@@ -684,11 +1294,88 @@ pub fn headers(&self, api_subset: ApiSubset) -> impl Iterator<Item = String> {
 
                 spb_headers
             }
+            ApiSubset::Storport => {
+                if let DriverConfig::Wdm | DriverConfig::Kmdf(_) = self.driver_config {
+                    vec!["storport.h"]
+                } else {
+                    vec![]
+                }
+            }
+            ApiSubset::Wsk => {
+                if let DriverConfig::Wdm | DriverConfig::Kmdf(_) = self.driver_config {
+                    vec!["wsk.h"]
+                } else {
+                    vec![]
+                }
+            }
+            ApiSubset::Wfp => {
+                if let DriverConfig::Wdm | DriverConfig::Kmdf(_) = self.driver_config {
+                    vec!["ndis.h", "fwpsk.h", "fwpmk.h"]
+                } else {
+                    vec![]
+                }
+            }
+            ApiSubset::KernelStreaming => {
+                if let DriverConfig::Wdm | DriverConfig::Kmdf(_) = self.driver_config {
+                    vec!["ks.h", "ksmedia.h"]
+                } else {
+                    vec![]
+                }
+            }
+            ApiSubset::Usb => {
+                let mut usb_headers = vec!["usb.h"];
+
+                if let DriverConfig::Wdm | DriverConfig::Kmdf(_) = self.driver_config {
+                    usb_headers.extend(["usbdlib.h"]);
+                }
+
+                if let DriverConfig::Kmdf(_) | DriverConfig::Umdf(_) = self.driver_config {
+                    usb_headers.extend(["wdfusb.h"]);
+                }
+
+                usb_headers
+            }
+            ApiSubset::Audio => {
+                if let DriverConfig::Wdm | DriverConfig::Kmdf(_) = self.driver_config {
+                    vec!["ks.h", "ksmedia.h", "portcls.h", "drmk.h"]
+                } else {
+                    vec![]
+                }
+            }
+            ApiSubset::Vmbus => {
+                // The KMCL is built on KMDF (ex. channels are WDF objects parented to a
+                // `WDFDEVICE`)
+                if let DriverConfig::Kmdf(_) = self.driver_config {
+                    vec!["vmbuskernelmodeclientlibapi.h"]
+                } else {
+                    vec![]
+                }
+            }
         }
         .into_iter()
         .map(std::string::ToString::to_string)
     }
 
+    /// Returns the preprocessor definitions that must precede the headers of
+    /// a given [`ApiSubset`]
+    const fn header_definitions(
+        &self,
+        api_subset: ApiSubset,
+    ) -> &'static [(&'static str, &'static str)] {
+        match api_subset {
+            // `ndis.h` requires the NDIS version that the driver targets to be defined
+            ApiSubset::Wfp
+                if matches!(
+                    self.driver_config,
+                    DriverConfig::Wdm | DriverConfig::Kmdf(_)
+                ) =>
+            {
+                &[("NDIS630", "1")]
+            }
+            _ => &[],
+        }
+    }
+
     /// Returns a [`String`] containing the contents of a header file designed
     /// for [`bindgen`](https://docs.rs/bindgen) to process
     ///
@@ -702,8 +1389,13 @@ pub fn bindgen_header_contents(
         api_subsets
             .into_iter()
             .flat_map(|api_subset| {
-                self.headers(api_subset)
-                    .map(|header| format!("#include \"{header}\"\n"))
+                self.header_definitions(api_subset)
+                    .iter()
+                    .map(|(name, value)| format!("#define {name} {value}\n"))
+                    .chain(
+                        self.headers(api_subset)
+                            .map(|header| format!("#include \"{header}\"\n")),
+                    )
             })
             .collect::<String>()
     }
@@ -716,7 +1408,9 @@ pub fn bindgen_header_contents(
     /// This function will return an error if the [`Config`] fails to be
     /// serialized
     pub fn configure_library_build(&self) -> Result<(), ConfigError> {
-        self.emit_cfg_settings()
+        self.emit_cfg_settings()?;
+        cargo_directives::write_manifest_if_enabled()?;
+        Ok(())
     }
 
     /// Compute the name of the `WdfFunctions` symbol used for WDF function
@@ -742,8 +1436,10 @@ pub fn compute_wdffunctions_symbol_name(&self) -> Option<String> {
     /// Configure a Cargo build of a binary that depends on the WDK. This
     /// emits specially formatted prints to Cargo based on this [`Config`].
     ///
-    /// This consists mainly of linker setting configuration. This must be
-    /// called from a Cargo build script of the binary being built
+    /// This consists mainly of linker setting configuration. The
+    /// [`BuildProvenance`](build_provenance::BuildProvenance) of the build is
+    /// also written to `OUT_DIR`. This must be called from a Cargo build script
+    /// of the binary being built
     ///
     /// # Errors
     ///
@@ -751,128 +1447,313 @@ pub fn compute_wdffunctions_symbol_name(&self) -> Option<String> {
     /// * any of the required WDK paths do not exist
     /// * the C runtime is not configured to be statically linked for a
     ///   kernel-mode driver
+    /// * the Cargo profile of the build is not supported for the driver model
+    ///   (ex. `panic = "unwind"` for a kernel-mode driver), or does not satisfy
+    ///   the requirements of the package's `metadata.wdk.profile` section
+    /// * Control Flow Guard is enabled in [`Config::instrumentation`] or in the
+    ///   package's `metadata.wdk.profile` section, but the driver is not
+    ///   compiled with `-C control-flow-guard`
+    /// * the [`BuildProvenance`](build_provenance::BuildProvenance) of the
+    ///   build fails to be written
+    /// * the `VERSIONINFO` resource declared by the package's
+    ///   `metadata.wdk.package.version-info` section is invalid, or fails to be
+    ///   compiled
     ///
     /// # Panics
     ///
     /// Panics if the invoked from outside a Cargo build environment
     pub fn configure_binary_build(&self) -> Result<(), ConfigError> {
-        if !Self::is_crt_static_linked() {
-            cfg_if::cfg_if! {
-                if #[cfg(all(wdk_build_unstable, skip_umdf_static_crt_check))] {
-                    if !matches!(self.driver_config, DriverConfig::Umdf(_)) {
-                        return Err(ConfigError::StaticCrtNotEnabled);
-                    }
-                } else {
-                    return Err(ConfigError::StaticCrtNotEnabled);
-                }
-            };
+        if !Self::is_crt_static_linked() && self.requires_static_crt()? {
+            return Err(ConfigError::StaticCrtNotEnabled {
+                package: env::var("CARGO_PKG_NAME").expect("CARGO_PKG_NAME should be set by Cargo"),
+                target: env::var("TARGET").expect("TARGET should be set by Cargo"),
+            });
         }
 
+        // Validate the profile before anything is linked, since unsupported profiles
+        // otherwise fail late with confusing linker errors
+        let (package, workspace_manifest_path) = Self::current_package_and_workspace_manifest()?;
+        let build_profile_config = package
+            .as_ref()
+            .map(build_profile::BuildProfileConfig::try_from_package)
+            .transpose()?
+            .flatten();
+        build_profile::BuildProfile::from_build_script_env(&workspace_manifest_path)?
+            .validate(&self.driver_config, build_profile_config.as_ref())?;
+
         // Emit linker search paths
         for path in self.library_paths()? {
-            println!("cargo::rustc-link-search={}", path.display());
+            emit_cargo_directive!(
+                "library paths of the WDK configuration",
+                "rustc-link-search={}",
+                path.display()
+            );
         }
 
         match &self.driver_config {
             DriverConfig::Wdm => {
+                let provenance = "driver model: WDM";
+
                 // Emit WDM-specific libraries to link to
-                println!("cargo::rustc-link-lib=static=BufferOverflowFastFailK");
-                println!("cargo::rustc-link-lib=static=ntoskrnl");
-                println!("cargo::rustc-link-lib=static=hal");
-                println!("cargo::rustc-link-lib=static=wmilib");
+                emit_cargo_directive!(provenance, "rustc-link-lib=static=BufferOverflowFastFailK");
+                emit_cargo_directive!(provenance, "rustc-link-lib=static=ntoskrnl");
+                emit_cargo_directive!(provenance, "rustc-link-lib=static=hal");
+                emit_cargo_directive!(provenance, "rustc-link-lib=static=wmilib");
 
                 // Emit ARM64-specific libraries to link to derived from
                 // WindowsDriver.arm64.props
                 if self.cpu_architecture == CpuArchitecture::Arm64 {
-                    println!("cargo::rustc-link-lib=static=arm64rt");
+                    emit_cargo_directive!(
+                        "cpu architecture: ARM64",
+                        "rustc-link-lib=static=arm64rt"
+                    );
                 }
 
                 // Linker arguments derived from WindowsDriver.KernelMode.props in Ni(22H2) WDK
-                println!("cargo::rustc-cdylib-link-arg=/DRIVER");
-                println!("cargo::rustc-cdylib-link-arg=/NODEFAULTLIB");
-                println!("cargo::rustc-cdylib-link-arg=/SUBSYSTEM:NATIVE");
-                println!("cargo::rustc-cdylib-link-arg=/KERNEL");
+                emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/DRIVER");
+                emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/NODEFAULTLIB");
+                emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/SUBSYSTEM:NATIVE");
+                emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/KERNEL");
 
                 // Linker arguments derived from WindowsDriver.KernelMode.WDM.props in Ni(22H2)
                 // WDK
-                println!("cargo::rustc-cdylib-link-arg=/ENTRY:DriverEntry");
+                emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/ENTRY:DriverEntry");
 
                 // Ignore `LNK4257: object file was not compiled for kernel mode; the image
                 // might not run` since `rustc` has no support for `/KERNEL`
-                println!("cargo::rustc-cdylib-link-arg=/IGNORE:4257");
+                emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/IGNORE:4257");
 
                 // Ignore `LNK4216: Exported entry point DriverEntry` since Rust currently
                 // provides no way to set a symbol's name without also exporting the symbol:
                 // https://github.com/rust-lang/rust/issues/67399
-                println!("cargo::rustc-cdylib-link-arg=/IGNORE:4216");
+                emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/IGNORE:4216");
             }
             DriverConfig::Kmdf(_) => {
+                let provenance = "driver model: KMDF";
+
                 // Emit KMDF-specific libraries to link to
-                println!("cargo::rustc-link-lib=static=BufferOverflowFastFailK");
-                println!("cargo::rustc-link-lib=static=ntoskrnl");
-                println!("cargo::rustc-link-lib=static=hal");
-                println!("cargo::rustc-link-lib=static=wmilib");
-                println!("cargo::rustc-link-lib=static=WdfLdr");
-                println!("cargo::rustc-link-lib=static=WdfDriverEntry");
+                emit_cargo_directive!(provenance, "rustc-link-lib=static=BufferOverflowFastFailK");
+                emit_cargo_directive!(provenance, "rustc-link-lib=static=ntoskrnl");
+                emit_cargo_directive!(provenance, "rustc-link-lib=static=hal");
+                emit_cargo_directive!(provenance, "rustc-link-lib=static=wmilib");
+                emit_cargo_directive!(provenance, "rustc-link-lib=static=WdfLdr");
+                emit_cargo_directive!(provenance, "rustc-link-lib=static=WdfDriverEntry");
 
                 // Emit ARM64-specific libraries to link to derived from
                 // WindowsDriver.arm64.props
                 if self.cpu_architecture == CpuArchitecture::Arm64 {
-                    println!("cargo::rustc-link-lib=static=arm64rt");
+                    emit_cargo_directive!(
+                        "cpu architecture: ARM64",
+                        "rustc-link-lib=static=arm64rt"
+                    );
                 }
 
                 // Linker arguments derived from WindowsDriver.KernelMode.props in Ni(22H2) WDK
-                println!("cargo::rustc-cdylib-link-arg=/DRIVER");
-                println!("cargo::rustc-cdylib-link-arg=/NODEFAULTLIB");
-                println!("cargo::rustc-cdylib-link-arg=/SUBSYSTEM:NATIVE");
-                println!("cargo::rustc-cdylib-link-arg=/KERNEL");
+                emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/DRIVER");
+                emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/NODEFAULTLIB");
+                emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/SUBSYSTEM:NATIVE");
+                emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/KERNEL");
 
                 // Linker arguments derived from WindowsDriver.KernelMode.KMDF.props in
                 // Ni(22H2) WDK
-                println!("cargo::rustc-cdylib-link-arg=/ENTRY:FxDriverEntry");
+                emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/ENTRY:FxDriverEntry");
 
                 // Ignore `LNK4257: object file was not compiled for kernel mode; the image
                 // might not run` since `rustc` has no support for `/KERNEL`
-                println!("cargo::rustc-cdylib-link-arg=/IGNORE:4257");
+                emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/IGNORE:4257");
             }
             DriverConfig::Umdf(umdf_config) => {
+                let provenance = "driver model: UMDF";
+
                 // Emit UMDF-specific libraries to link to
                 if umdf_config.umdf_version_major >= 2 {
-                    println!("cargo::rustc-link-lib=static=WdfDriverStubUm");
-                    println!("cargo::rustc-link-lib=static=ntdll");
+                    emit_cargo_directive!(provenance, "rustc-link-lib=static=WdfDriverStubUm");
+                    emit_cargo_directive!(provenance, "rustc-link-lib=static=ntdll");
                 }
 
-                println!("cargo::rustc-cdylib-link-arg=/NODEFAULTLIB:kernel32.lib");
-                println!("cargo::rustc-cdylib-link-arg=/NODEFAULTLIB:user32.lib");
-                println!("cargo::rustc-link-lib=static=OneCoreUAP");
+                emit_cargo_directive!(
+                    provenance,
+                    "rustc-cdylib-link-arg=/NODEFAULTLIB:kernel32.lib"
+                );
+                emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/NODEFAULTLIB:user32.lib");
+                emit_cargo_directive!(provenance, "rustc-link-lib=static=OneCoreUAP");
 
                 // Linker arguments derived from WindowsDriver.UserMode.props in Ni(22H2) WDK
-                println!("cargo::rustc-cdylib-link-arg=/SUBSYSTEM:WINDOWS");
+                emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/SUBSYSTEM:WINDOWS");
             }
         }
 
         // Emit linker arguments common to all configs
         {
+            let provenance = "linker arguments common to all drivers";
+
             // Linker arguments derived from Microsoft.Link.Common.props in Ni(22H2) WDK
-            println!("cargo::rustc-cdylib-link-arg=/NXCOMPAT");
-            println!("cargo::rustc-cdylib-link-arg=/DYNAMICBASE");
+            emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/NXCOMPAT");
+            emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/DYNAMICBASE");
 
             // Always generate Map file with Exports
-            println!("cargo::rustc-cdylib-link-arg=/MAP");
-            println!("cargo::rustc-cdylib-link-arg=/MAPINFO:EXPORTS");
+            emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/MAP");
+            emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/MAPINFO:EXPORTS");
 
             // Force Linker Optimizations
-            println!("cargo::rustc-cdylib-link-arg=/OPT:REF,ICF");
+            emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/OPT:REF,ICF");
 
             // Enable "Forced Integrity Checking" to prevent non-signed binaries from
             // loading
-            println!("cargo::rustc-cdylib-link-arg=/INTEGRITYCHECK");
+            emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/INTEGRITYCHECK");
 
             // Disable Manifest File Generation
-            println!("cargo::rustc-cdylib-link-arg=/MANIFEST:NO");
+            emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/MANIFEST:NO");
         }
 
-        self.emit_cfg_settings()
+        self.configure_control_flow_guard(build_profile_config.as_ref())?;
+
+        // Embed the opt-in VERSIONINFO resource of the driver
+        if let Some(package) = &package {
+            version_info::embed_version_resource(package, &self.driver_config)?;
+        }
+
+        Self::validate_linker_plugin_lto_linker()?;
+
+        self.emit_cfg_settings()?;
+        cargo_directives::write_manifest_if_enabled()?;
+        build_provenance::BuildProvenance::new(self)?.write_to_out_dir()?;
+        Ok(())
+    }
+
+    /// Emits the linker arguments of Control Flow Guard, when it is enabled in
+    /// [`Config::instrumentation`] or in the package's `metadata.wdk.profile`
+    /// section
+    fn configure_control_flow_guard(
+        &self,
+        build_profile_config: Option<&build_profile::BuildProfileConfig>,
+    ) -> Result<(), ConfigError> {
+        let control_flow_guard_provenance = if self
+            .instrumentation
+            .is_some_and(|instrumentation| instrumentation.control_flow_guard)
+        {
+            Some("instrumentation: control-flow-guard")
+        } else if build_profile_config
+            .is_some_and(|build_profile_config| build_profile_config.control_flow_guard)
+        {
+            Some("profile: control-flow-guard")
+        } else {
+            None
+        };
+        if let Some(provenance) = control_flow_guard_provenance {
+            // Linking with `/GUARD:CF` without compiling with Control Flow Guard would
+            // produce a binary that claims to be CFG-compatible, but has no checks
+            if !Self::is_control_flow_guard_enabled() {
+                return Err(ConfigError::ControlFlowGuardNotEnabled);
+            }
+            emit_cargo_directive!(provenance, "rustc-cdylib-link-arg=/GUARD:CF");
+        }
+        Ok(())
+    }
+
+    /// Returns whether linker-plugin-based LTO (`-Clinker-plugin-lto`) is
+    /// enabled in the rustflags of the crate being built. When it is, C code
+    /// linked into the driver (ex. `cc`-built shims) must be compiled with a
+    /// Clang-based compiler and [`LINKER_PLUGIN_LTO_C_COMPILER_FLAG`], so that
+    /// `lld-link` can optimize it together with the Rust code.
+    #[must_use]
+    pub fn is_linker_plugin_lto_enabled() -> bool {
+        rustflags_enable_linker_plugin_lto(&env::var("CARGO_ENCODED_RUSTFLAGS").unwrap_or_default())
+    }
+
+    /// Checks that the driver is linked with `lld-link` when
+    /// linker-plugin-based LTO is enabled, since it emits LLVM bitcode
+    /// instead of object files, which only `lld-link` can link
+    fn validate_linker_plugin_lto_linker() -> Result<(), ConfigError> {
+        if !Self::is_linker_plugin_lto_enabled() {
+            return Ok(());
+        }
+
+        println!("cargo::rerun-if-env-changed=RUSTC_LINKER");
+
+        let encoded_rustflags = env::var("CARGO_ENCODED_RUSTFLAGS").unwrap_or_default();
+        let linker = rustflags_codegen_option(&encoded_rustflags, "linker")
+            .map(ToString::to_string)
+            .or_else(|| env::var("RUSTC_LINKER").ok());
+        if !linker.as_deref().is_some_and(is_lld_link) {
+            return Err(ConfigError::LinkerPluginLtoRequiresLldLink);
+        }
+        Ok(())
+    }
+
+    /// Returns whether the binary of the package whose build script is running
+    /// must statically link the C runtime.
+    ///
+    /// Kernel-mode drivers always must, while UMDF drivers can opt out with
+    /// [`SKIP_STATIC_CRT_CHECK_METADATA_KEY`] in their package metadata.
+    fn requires_static_crt(&self) -> Result<bool, ConfigError> {
+        if !matches!(self.driver_config, DriverConfig::Umdf(_)) {
+            return Ok(true);
+        }
+
+        if cfg!(all(wdk_build_unstable, skip_umdf_static_crt_check)) {
+            return Ok(false);
+        }
+
+        let Some(package) = Self::current_package()? else {
+            println!(
+                "cargo::warning=package.metadata.wdk.{SKIP_STATIC_CRT_CHECK_METADATA_KEY} was not \
+                 read, since the package was not found in the cargo metadata output"
+            );
+            return Ok(true);
+        };
+
+        let skip_static_crt_check = match &package.metadata["wdk"]
+            [SKIP_STATIC_CRT_CHECK_METADATA_KEY]
+        {
+            serde_json::Value::Null => false,
+            value => bool::deserialize(value).map_err(|error_source| {
+                TryFromCargoMetadataError::WdkMetadataDeserialization {
+                    metadata_source: format!(
+                        "package.metadata.wdk.{SKIP_STATIC_CRT_CHECK_METADATA_KEY} for {} package",
+                        package.name
+                    ),
+                    error_source,
+                }
+            })?,
+        };
+        Ok(!skip_static_crt_check)
+    }
+
+    /// Returns the `cargo_metadata` of the package whose build script is
+    /// running, or `None` if it is not found in the `cargo_metadata` output
+    fn current_package() -> Result<Option<cargo_metadata::Package>, ConfigError> {
+        Ok(Self::current_package_and_workspace_manifest()?.0)
+    }
+
+    /// Returns the `cargo_metadata` of the package whose build script is
+    /// running (or `None` if it is not found in the `cargo_metadata` output),
+    /// along with the path of the manifest of its workspace, which declares
+    /// the profiles of the build
+    fn current_package_and_workspace_manifest(
+    ) -> Result<(Option<cargo_metadata::Package>, PathBuf), ConfigError> {
+        let manifest_path = PathBuf::from(
+            env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR should be set by Cargo"),
+        )
+        .join("Cargo.toml");
+        let package_name =
+            env::var("CARGO_PKG_NAME").expect("CARGO_PKG_NAME should be set by Cargo");
+        let cargo_metadata = MetadataCommand::new()
+            .manifest_path(&manifest_path)
+            .no_deps()
+            .exec()?;
+        let workspace_manifest_path = cargo_metadata
+            .workspace_root
+            .join("Cargo.toml")
+            .into_std_path_buf();
+        let package = cargo_metadata
+            .packages
+            .into_iter()
+            // The package is selected by name, since its manifest path may be spelled
+            // differently than `CARGO_MANIFEST_DIR` on Windows (ex. in case, or with a
+            // `\\?\` prefix). Package names are unique within a workspace.
+            .find(|package| package.name.as_str() == package_name);
+        Ok((package, workspace_manifest_path))
     }
 
     fn is_crt_static_linked() -> bool {
@@ -883,6 +1764,12 @@ fn is_crt_static_linked() -> bool {
 
         enabled_cpu_target_features.contains(STATICALLY_LINKED_C_RUNTIME_FEATURE_NAME)
     }
+
+    fn is_control_flow_guard_enabled() -> bool {
+        rustflags_enable_control_flow_guard(
+            &env::var("CARGO_ENCODED_RUSTFLAGS").unwrap_or_default(),
+        )
+    }
 }
 
 impl From<DeserializableDriverConfig> for DriverConfig {
@@ -927,34 +1814,151 @@ fn default() -> Self {
     }
 }
 
-impl UmdfConfig {
-    /// Creates a new [`UmdfConfig`] with default values
-    #[must_use]
-    pub fn new() -> Self {
-        Self::default()
+impl UmdfConfig {
+    /// Creates a new [`UmdfConfig`] with default values
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CpuArchitecture {
+    /// Every [`CpuArchitecture`] that drivers can be built for
+    pub const ALL: [Self; 2] = [Self::Amd64, Self::Arm64];
+
+    /// Converts [`CpuArchitecture`] to the string corresponding to what the
+    /// architecture is typically referred to in Windows
+    #[must_use]
+    pub const fn as_windows_str(&self) -> &str {
+        match self {
+            Self::Amd64 => "x64",
+            Self::Arm64 => "ARM64",
+        }
+    }
+
+    /// Converts from a cargo-provided [`std::str`] to a [`CpuArchitecture`].
+    #[must_use]
+    pub fn try_from_cargo_str<S: AsRef<str>>(cargo_str: S) -> Option<Self> {
+        // Specifically not using the [`std::convert::TryFrom`] trait to be more
+        // explicit in function name, since only arch strings from cargo are handled.
+        match cargo_str.as_ref() {
+            "x86_64" => Some(Self::Amd64),
+            "aarch64" => Some(Self::Arm64),
+            _ => None,
+        }
+    }
+
+    /// Converts [`CpuArchitecture`] to the target triple that drivers for the
+    /// architecture are built with
+    #[must_use]
+    pub const fn as_target_triple(&self) -> &'static str {
+        match self {
+            Self::Amd64 => "x86_64-pc-windows-msvc",
+            Self::Arm64 => "aarch64-pc-windows-msvc",
+        }
+    }
+}
+
+impl TargetOs {
+    /// Creates a [`TargetOs`] from an explicit `NTDDI_VERSION` value
+    #[must_use]
+    pub const fn from_ntddi_version(ntddi_version: u32) -> Self {
+        Self { ntddi_version }
+    }
+
+    /// Returns the `NTDDI_VERSION` value of the [`TargetOs`]
+    #[must_use]
+    pub const fn ntddi_version(&self) -> u32 {
+        self.ntddi_version
+    }
+
+    /// Returns the `_WIN32_WINNT` (and `WINVER`) value of the [`TargetOs`]
+    #[must_use]
+    pub const fn win32_winnt_version(&self) -> u32 {
+        self.ntddi_version >> 16
+    }
+
+    /// Returns the name of the Windows release corresponding to the
+    /// [`TargetOs`], or `None` if it does not correspond to a named release
+    #[must_use]
+    pub fn release_name(&self) -> Option<&'static str> {
+        TARGET_OS_RELEASES
+            .iter()
+            .find(|(_, ntddi_version, _)| *ntddi_version == self.ntddi_version)
+            .map(|(name, ..)| *name)
+    }
+
+    /// Returns the OS build number of the Windows release corresponding to the
+    /// [`TargetOs`], or `None` if it does not correspond to a named release
+    #[must_use]
+    pub fn build_number(&self) -> Option<u32> {
+        TARGET_OS_RELEASES
+            .iter()
+            .find(|(_, ntddi_version, _)| *ntddi_version == self.ntddi_version)
+            .map(|(.., build_number)| *build_number)
+    }
+
+    /// Returns an iterator over the names of all the Windows releases that are
+    /// older than, or the same as, the [`TargetOs`]
+    fn releases_at_or_below(self) -> impl Iterator<Item = &'static str> {
+        TARGET_OS_RELEASES
+            .iter()
+            .filter(move |(_, ntddi_version, _)| *ntddi_version <= self.ntddi_version)
+            .map(|(name, ..)| *name)
+    }
+}
+
+impl std::str::FromStr for TargetOs {
+    type Err = ConfigError;
+
+    fn from_str(target_os: &str) -> Result<Self, Self::Err> {
+        let normalized_target_os = target_os.trim().to_ascii_lowercase();
+
+        if let Some(hex_ntddi_version) = normalized_target_os.strip_prefix("0x") {
+            if let Ok(ntddi_version) = u32::from_str_radix(hex_ntddi_version, 16) {
+                return Ok(Self::from_ntddi_version(ntddi_version));
+            }
+        }
+
+        let release_name = TARGET_OS_RELEASE_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == normalized_target_os)
+            .map_or(normalized_target_os.as_str(), |(_, name)| name);
+        TARGET_OS_RELEASES
+            .iter()
+            .find(|(name, ..)| *name == release_name)
+            .map(|(_, ntddi_version, _)| Self::from_ntddi_version(*ntddi_version))
+            .ok_or_else(|| ConfigError::TargetOsParseError {
+                target_os: target_os.to_string(),
+                valid_target_os_names: TARGET_OS_RELEASE_ALIASES
+                    .iter()
+                    .map(|(alias, _)| *alias)
+                    .chain(TARGET_OS_RELEASES.iter().map(|(name, ..)| *name))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            })
+    }
+}
+
+impl TryFrom<String> for TargetOs {
+    type Error = ConfigError;
+
+    fn try_from(target_os: String) -> Result<Self, Self::Error> {
+        target_os.parse()
     }
 }
 
-impl CpuArchitecture {
-    /// Converts [`CpuArchitecture`] to the string corresponding to what the
-    /// architecture is typically referred to in Windows
-    #[must_use]
-    pub const fn as_windows_str(&self) -> &str {
-        match self {
-            Self::Amd64 => "x64",
-            Self::Arm64 => "ARM64",
-        }
+impl From<TargetOs> for String {
+    fn from(target_os: TargetOs) -> Self {
+        target_os.to_string()
     }
+}
 
-    /// Converts from a cargo-provided [`std::str`] to a [`CpuArchitecture`].
-    #[must_use]
-    pub fn try_from_cargo_str<S: AsRef<str>>(cargo_str: S) -> Option<Self> {
-        // Specifically not using the [`std::convert::TryFrom`] trait to be more
-        // explicit in function name, since only arch strings from cargo are handled.
-        match cargo_str.as_ref() {
-            "x86_64" => Some(Self::Amd64),
-            "aarch64" => Some(Self::Arm64),
-            _ => None,
+impl std::fmt::Display for TargetOs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.release_name() {
+            Some(release_name) => write!(f, "{release_name}"),
+            None => write!(f, "0x{:08X}", self.ntddi_version),
         }
     }
 }
@@ -969,26 +1973,20 @@ pub fn try_from_cargo_str<S: AsRef<str>>(cargo_str: S) -> Option<Self> {
 ///    1 or 2
 ///
 /// The returned path should be a manifest in the same directory of the
-/// lockfile. This does not support invocations that use non-default target
-/// directories (ex. via `--target-dir`). This function only works when called
-/// from a `build.rs` file
+/// lockfile. When the target directory is outside of the workspace (ex. set
+/// with `CARGO_TARGET_DIR`), the workspace whose target directory contains
+/// `OUT_DIR` is searched for instead. See
+/// [`target_directory::try_find_top_level_cargo_manifest`] for details. This
+/// function only works when called from a `build.rs` file
 ///
 /// # Panics
 ///
-/// Panics if a `Cargo.lock` file cannot be found in any of the ancestors of
-/// `OUT_DIR` or if this function was called outside of a `build.rs` file
+/// Panics if the top-level `Cargo.toml` cannot be found or if this function
+/// was called outside of a `build.rs` file
 #[must_use]
 pub fn find_top_level_cargo_manifest() -> PathBuf {
-    let out_dir =
-        PathBuf::from(std::env::var("OUT_DIR").expect(
-            "Cargo should have set the OUT_DIR environment variable when executing build.rs",
-        ));
-
-    out_dir
-        .ancestors()
-        .find(|path| path.join("Cargo.lock").exists())
-        .expect("a Cargo.lock file should exist in the same directory as the top-level Cargo.toml")
-        .join("Cargo.toml")
+    target_directory::try_find_top_level_cargo_manifest()
+        .unwrap_or_else(|error| panic!("the top-level Cargo.toml should be found: {error}"))
 }
 
 /// Configure a Cargo build of a library that depends on the WDK.
@@ -1021,6 +2019,7 @@ pub fn configure_wdk_library_build() -> Result<(), ConfigError> {
             // check_cfg must be emitted even if no WDK configurations are detected, so that
             // cfg options are still checked
             Config::emit_check_cfg_settings();
+            cargo_directives::write_manifest_if_enabled()?;
             Ok(())
         }
 
@@ -1064,6 +2063,7 @@ pub fn configure_wdk_library_build_and_then<F, E>(mut f: F) -> Result<(), E>
             // check_cfg must be emitted even if no WDK configurations are detected, so that
             // cfg options are still checked
             Config::emit_check_cfg_settings();
+            cargo_directives::write_manifest_if_enabled().map_err(ConfigError::from)?;
             Ok(())
         }
 
@@ -1087,6 +2087,66 @@ pub fn configure_wdk_binary_build() -> Result<(), ConfigError> {
     Config::from_env_auto()?.configure_binary_build()
 }
 
+/// Returns the value of the last `codegen_option_name` codegen option (ex.
+/// `-Clinker=lld-link` or `-C linker=lld-link`) in the
+/// `CARGO_ENCODED_RUSTFLAGS` passed to a build script. Options specified
+/// without a value (ex. `-Clinker-plugin-lto`) have an empty value.
+fn rustflags_codegen_option<'a>(
+    encoded_rustflags: &'a str,
+    codegen_option_name: &str,
+) -> Option<&'a str> {
+    let mut codegen_option_value = None;
+    let mut previous_flag = "";
+    for flag in encoded_rustflags.split('\x1f') {
+        let codegen_option = flag
+            .strip_prefix("-C")
+            .or_else(|| (previous_flag == "-C").then_some(flag))
+            .unwrap_or_default();
+        if let Some(value) = codegen_option.strip_prefix(codegen_option_name) {
+            // Later flags override earlier ones
+            if value.is_empty() {
+                codegen_option_value = Some(value);
+            } else if let Some(value) = value.strip_prefix('=') {
+                codegen_option_value = Some(value);
+            }
+        }
+        previous_flag = flag;
+    }
+    codegen_option_value
+}
+
+/// Returns whether the value of a boolean codegen option enables it. Boolean
+/// codegen options specified without a value are enabled.
+fn is_codegen_option_enabled(value: &str) -> bool {
+    !matches!(value, "no" | "n" | "off" | "false")
+}
+
+/// Returns whether the `CARGO_ENCODED_RUSTFLAGS` passed to a build script
+/// enable Control Flow Guard (ex. `-Ccontrol-flow-guard` or `-C
+/// control-flow-guard=checks`)
+fn rustflags_enable_control_flow_guard(encoded_rustflags: &str) -> bool {
+    const CONTROL_FLOW_GUARD_CODEGEN_OPTION: &str = "control-flow-guard";
+
+    rustflags_codegen_option(encoded_rustflags, CONTROL_FLOW_GUARD_CODEGEN_OPTION)
+        .is_some_and(is_codegen_option_enabled)
+}
+
+/// Returns whether the `CARGO_ENCODED_RUSTFLAGS` passed to a build script
+/// enable linker-plugin-based LTO (ex. `-Clinker-plugin-lto`), which defers
+/// LTO to the linker so that it can be performed across Rust and C code
+fn rustflags_enable_linker_plugin_lto(encoded_rustflags: &str) -> bool {
+    const LINKER_PLUGIN_LTO_CODEGEN_OPTION: &str = "linker-plugin-lto";
+
+    rustflags_codegen_option(encoded_rustflags, LINKER_PLUGIN_LTO_CODEGEN_OPTION)
+        .is_some_and(is_codegen_option_enabled)
+}
+
+/// Returns whether `linker` is a path to (or the name of) LLVM's `lld-link`
+fn is_lld_link(linker: &str) -> bool {
+    let file_name = linker.rsplit(['\\', '/']).next().unwrap_or(linker);
+    file_name.eq_ignore_ascii_case("lld-link") || file_name.eq_ignore_ascii_case("lld-link.exe")
+}
+
 /// This currently only exports the driver type, but may export more metadata in
 /// the future. `EXPORTED_CFG_SETTINGS` is a mapping of cfg key to allowed cfg
 /// values
@@ -1273,6 +2333,152 @@ fn test_try_from_cargo_str() {
         assert_eq!(CpuArchitecture::try_from_cargo_str("arm"), None);
     }
 
+    #[test]
+    fn for_each_architecture() {
+        let config = with_env(&[("CARGO_CFG_TARGET_ARCH", "x86_64")], || Config {
+            driver_config: DriverConfig::Kmdf(KmdfConfig::new()),
+            ..Config::default()
+        });
+
+        let mut visited_configs = Vec::new();
+        config
+            .for_each_architecture(|config| {
+                visited_configs.push(config.clone());
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+
+        assert_eq!(
+            visited_configs
+                .iter()
+                .map(Config::cpu_architecture)
+                .collect::<Vec<_>>(),
+            CpuArchitecture::ALL
+        );
+        assert!(visited_configs
+            .iter()
+            .all(|visited_config| visited_config.driver_config == config.driver_config));
+    }
+
+    mod target_os {
+        use super::*;
+
+        #[test]
+        fn parse_release_name() {
+            assert_eq!(
+                "windows11-22h2".parse::<TargetOs>().unwrap(),
+                TargetOs::from_ntddi_version(0x0A00_000C)
+            );
+        }
+
+        #[test]
+        fn parse_release_alias() {
+            assert_eq!(
+                "Windows11".parse::<TargetOs>().unwrap(),
+                "windows11-21h2".parse::<TargetOs>().unwrap()
+            );
+        }
+
+        #[test]
+        fn parse_ntddi_version() {
+            let target_os = "0x0A000008".parse::<TargetOs>().unwrap();
+
+            assert_eq!(target_os.ntddi_version(), 0x0A00_0008);
+            assert_eq!(target_os.win32_winnt_version(), 0x0A00);
+            assert_eq!(target_os.release_name(), Some("windows10-2004"));
+            assert_eq!(target_os.build_number(), Some(19041));
+        }
+
+        #[test]
+        fn parse_invalid() {
+            assert!(matches!(
+                "windows12".parse::<TargetOs>(),
+                Err(ConfigError::TargetOsParseError { .. })
+            ));
+            assert!(matches!(
+                "0xWIN10".parse::<TargetOs>(),
+                Err(ConfigError::TargetOsParseError { .. })
+            ));
+        }
+
+        #[test]
+        fn display() {
+            assert_eq!(
+                TargetOs::from_ntddi_version(0x0A00_000C).to_string(),
+                "windows11-22h2"
+            );
+            assert_eq!(
+                TargetOs::from_ntddi_version(0x0A00_000D).to_string(),
+                "0x0A00000D"
+            );
+        }
+
+        #[test]
+        fn releases_at_or_below() {
+            let target_os = "windows10-1903".parse::<TargetOs>().unwrap();
+
+            assert_eq!(
+                target_os.releases_at_or_below().collect::<Vec<_>>(),
+                vec![
+                    "windows10-1507",
+                    "windows10-1511",
+                    "windows10-1607",
+                    "windows10-1703",
+                    "windows10-1709",
+                    "windows10-1803",
+                    "windows10-1809",
+                    "windows10-1903",
+                ]
+            );
+        }
+
+        #[test]
+        fn preprocessor_definitions() {
+            let config = with_env(&[("CARGO_CFG_TARGET_ARCH", "x86_64")], || Config {
+                target_os: Some("windows11-22h2".parse().unwrap()),
+                ..Config::default()
+            });
+
+            let definitions = config.preprocessor_definitions().collect::<Vec<_>>();
+
+            for (key, value) in [
+                ("_WIN32_WINNT", "0x0A00"),
+                ("WINVER", "0x0A00"),
+                ("WINNT", "1"),
+                ("NTDDI_VERSION", "0x0A00000C"),
+            ] {
+                assert!(definitions.contains(&(key.to_string(), Some(value.to_string()))));
+            }
+        }
+
+        #[test]
+        fn security_preprocessor_definitions() {
+            let config = with_env(&[("CARGO_CFG_TARGET_ARCH", "x86_64")], || Config {
+                security: Some(SecurityConfig {
+                    pool_nx_optin: true,
+                    deprecate_ddk_functions: false,
+                }),
+                ..Config::default()
+            });
+
+            let definitions = config.preprocessor_definitions().collect::<Vec<_>>();
+
+            assert!(definitions.contains(&("POOL_NX_OPTIN".to_string(), Some("1".to_string()))));
+            assert!(!definitions
+                .iter()
+                .any(|(key, _)| key == "DEPRECATE_DDK_FUNCTIONS"));
+        }
+
+        #[test]
+        fn no_preprocessor_definitions_by_default() {
+            let config = with_env(&[("CARGO_CFG_TARGET_ARCH", "x86_64")], Config::new);
+
+            assert!(!config
+                .preprocessor_definitions()
+                .any(|(key, _)| key == "NTDDI_VERSION"));
+        }
+    }
+
     mod bindgen_header_contents {
         use super::*;
         use crate::{KmdfConfig, UmdfConfig};
@@ -1328,10 +2534,194 @@ fn umdf() {
             assert_eq!(
                 config.bindgen_header_contents([ApiSubset::Base, ApiSubset::Wdf]),
                 r#"#include "windows.h"
+#include "swdevice.h"
 #include "wdf.h"
 "#,
             );
         }
+
+        #[test]
+        fn storport() {
+            let wdm_config = with_env(&[("CARGO_CFG_TARGET_ARCH", "x86_64")], || Config {
+                driver_config: DriverConfig::Wdm,
+                ..Default::default()
+            });
+            let umdf_config = with_env(&[("CARGO_CFG_TARGET_ARCH", "x86_64")], || Config {
+                driver_config: DriverConfig::Umdf(UmdfConfig::new()),
+                ..Default::default()
+            });
+
+            assert_eq!(
+                wdm_config.bindgen_header_contents([ApiSubset::Base, ApiSubset::Storport]),
+                r#"#include "ntifs.h"
+#include "ntddk.h"
+#include "ntstrsafe.h"
+#include "storport.h"
+"#,
+            );
+            assert_eq!(
+                umdf_config.bindgen_header_contents([ApiSubset::Storport]),
+                "",
+            );
+        }
+
+        #[test]
+        fn wsk() {
+            let wdm_config = with_env(&[("CARGO_CFG_TARGET_ARCH", "x86_64")], || Config {
+                driver_config: DriverConfig::Wdm,
+                ..Default::default()
+            });
+            let umdf_config = with_env(&[("CARGO_CFG_TARGET_ARCH", "x86_64")], || Config {
+                driver_config: DriverConfig::Umdf(UmdfConfig::new()),
+                ..Default::default()
+            });
+
+            assert_eq!(
+                wdm_config.bindgen_header_contents([ApiSubset::Base, ApiSubset::Wsk]),
+                r#"#include "ntifs.h"
+#include "ntddk.h"
+#include "ntstrsafe.h"
+#include "wsk.h"
+"#,
+            );
+            assert_eq!(umdf_config.bindgen_header_contents([ApiSubset::Wsk]), "",);
+        }
+
+        #[test]
+        fn wfp() {
+            let kmdf_config = with_env(&[("CARGO_CFG_TARGET_ARCH", "x86_64")], || Config {
+                driver_config: DriverConfig::Kmdf(KmdfConfig::new()),
+                ..Default::default()
+            });
+            let umdf_config = with_env(&[("CARGO_CFG_TARGET_ARCH", "x86_64")], || Config {
+                driver_config: DriverConfig::Umdf(UmdfConfig::new()),
+                ..Default::default()
+            });
+
+            assert_eq!(
+                kmdf_config.bindgen_header_contents([ApiSubset::Base, ApiSubset::Wfp]),
+                r#"#include "ntifs.h"
+#include "ntddk.h"
+#include "ntstrsafe.h"
+#define NDIS630 1
+#include "ndis.h"
+#include "fwpsk.h"
+#include "fwpmk.h"
+"#,
+            );
+            assert_eq!(umdf_config.bindgen_header_contents([ApiSubset::Wfp]), "",);
+        }
+
+        #[test]
+        fn kernel_streaming() {
+            let kmdf_config = with_env(&[("CARGO_CFG_TARGET_ARCH", "x86_64")], || Config {
+                driver_config: DriverConfig::Kmdf(KmdfConfig::new()),
+                ..Default::default()
+            });
+            let umdf_config = with_env(&[("CARGO_CFG_TARGET_ARCH", "x86_64")], || Config {
+                driver_config: DriverConfig::Umdf(UmdfConfig::new()),
+                ..Default::default()
+            });
+
+            assert_eq!(
+                kmdf_config.bindgen_header_contents([ApiSubset::Base, ApiSubset::KernelStreaming]),
+                r#"#include "ntifs.h"
+#include "ntddk.h"
+#include "ntstrsafe.h"
+#include "ks.h"
+#include "ksmedia.h"
+"#,
+            );
+            assert_eq!(
+                umdf_config.bindgen_header_contents([ApiSubset::KernelStreaming]),
+                "",
+            );
+        }
+
+        #[test]
+        fn audio() {
+            let wdm_config = with_env(&[("CARGO_CFG_TARGET_ARCH", "x86_64")], || Config {
+                driver_config: DriverConfig::Wdm,
+                ..Default::default()
+            });
+            let umdf_config = with_env(&[("CARGO_CFG_TARGET_ARCH", "x86_64")], || Config {
+                driver_config: DriverConfig::Umdf(UmdfConfig::new()),
+                ..Default::default()
+            });
+
+            assert_eq!(
+                wdm_config.bindgen_header_contents([ApiSubset::Base, ApiSubset::Audio]),
+                r#"#include "ntifs.h"
+#include "ntddk.h"
+#include "ntstrsafe.h"
+#include "ks.h"
+#include "ksmedia.h"
+#include "portcls.h"
+#include "drmk.h"
+"#,
+            );
+            assert_eq!(umdf_config.bindgen_header_contents([ApiSubset::Audio]), "",);
+        }
+
+        #[test]
+        fn usb() {
+            let wdm_config = with_env(&[("CARGO_CFG_TARGET_ARCH", "x86_64")], || Config {
+                driver_config: DriverConfig::Wdm,
+                ..Default::default()
+            });
+            let kmdf_config = with_env(&[("CARGO_CFG_TARGET_ARCH", "x86_64")], || Config {
+                driver_config: DriverConfig::Kmdf(KmdfConfig::new()),
+                ..Default::default()
+            });
+            let umdf_config = with_env(&[("CARGO_CFG_TARGET_ARCH", "x86_64")], || Config {
+                driver_config: DriverConfig::Umdf(UmdfConfig::new()),
+                ..Default::default()
+            });
+
+            assert_eq!(
+                wdm_config.bindgen_header_contents([ApiSubset::Usb]),
+                r#"#include "usb.h"
+#include "usbdlib.h"
+"#,
+            );
+            assert_eq!(
+                kmdf_config.bindgen_header_contents([ApiSubset::Usb]),
+                r#"#include "usb.h"
+#include "usbdlib.h"
+#include "wdfusb.h"
+"#,
+            );
+            assert_eq!(
+                umdf_config.bindgen_header_contents([ApiSubset::Usb]),
+                r#"#include "usb.h"
+#include "wdfusb.h"
+"#,
+            );
+        }
+
+        #[test]
+        fn vmbus() {
+            let wdm_config = with_env(&[("CARGO_CFG_TARGET_ARCH", "x86_64")], || Config {
+                driver_config: DriverConfig::Wdm,
+                ..Default::default()
+            });
+            let kmdf_config = with_env(&[("CARGO_CFG_TARGET_ARCH", "x86_64")], || Config {
+                driver_config: DriverConfig::Kmdf(KmdfConfig::new()),
+                ..Default::default()
+            });
+            let umdf_config = with_env(&[("CARGO_CFG_TARGET_ARCH", "x86_64")], || Config {
+                driver_config: DriverConfig::Umdf(UmdfConfig::new()),
+                ..Default::default()
+            });
+
+            assert_eq!(
+                kmdf_config.bindgen_header_contents([ApiSubset::Vmbus]),
+                r#"#include "vmbuskernelmodeclientlibapi.h"
+"#,
+            );
+            assert_eq!(wdm_config.bindgen_header_contents([ApiSubset::Vmbus]), "",);
+            assert_eq!(umdf_config.bindgen_header_contents([ApiSubset::Vmbus]), "",);
+        }
     }
     mod compute_wdffunctions_symbol_name {
         use super::*;
@@ -1381,4 +2771,86 @@ fn wdm() {
             assert_eq!(result, None);
         }
     }
+
+    mod linker_plugin_lto_rustflags {
+        use super::*;
+
+        #[test]
+        fn enabled() {
+            for encoded_rustflags in [
+                "-Clinker-plugin-lto",
+                "-C\x1flinker-plugin-lto",
+                "-C\x1ftarget-feature=+crt-static\x1f-Clinker-plugin-lto=yes",
+            ] {
+                assert!(
+                    rustflags_enable_linker_plugin_lto(encoded_rustflags),
+                    "{encoded_rustflags:?} should enable linker-plugin-lto"
+                );
+            }
+        }
+
+        #[test]
+        fn disabled() {
+            for encoded_rustflags in [
+                "",
+                "-Clinker=lld-link",
+                "-Clinker-plugin-lto=off",
+                "-Clinker-plugin-lto\x1f-C\x1flinker-plugin-lto=no",
+            ] {
+                assert!(
+                    !rustflags_enable_linker_plugin_lto(encoded_rustflags),
+                    "{encoded_rustflags:?} should not enable linker-plugin-lto"
+                );
+            }
+        }
+
+        #[test]
+        fn linker() {
+            assert_eq!(
+                rustflags_codegen_option("-Clinker-plugin-lto\x1f-C\x1flinker=lld-link", "linker"),
+                Some("lld-link")
+            );
+            assert_eq!(
+                rustflags_codegen_option("-Clinker-plugin-lto", "linker"),
+                None
+            );
+            assert!(is_lld_link("lld-link"));
+            assert!(is_lld_link(r"C:\Program Files\LLVM\bin\LLD-LINK.EXE"));
+            assert!(!is_lld_link("link.exe"));
+        }
+    }
+
+    mod control_flow_guard_rustflags {
+        use super::*;
+
+        #[test]
+        fn enabled() {
+            for encoded_rustflags in [
+                "-Ccontrol-flow-guard",
+                "-C\x1fcontrol-flow-guard",
+                "-C\x1ftarget-feature=+crt-static\x1f-Ccontrol-flow-guard=checks",
+                "-Ccontrol-flow-guard=no\x1f-Ccontrol-flow-guard=yes",
+            ] {
+                assert!(
+                    rustflags_enable_control_flow_guard(encoded_rustflags),
+                    "{encoded_rustflags:?} should enable control flow guard"
+                );
+            }
+        }
+
+        #[test]
+        fn disabled() {
+            for encoded_rustflags in [
+                "",
+                "-C\x1ftarget-feature=+crt-static",
+                "-Ccontrol-flow-guard=no",
+                "-Ccontrol-flow-guard\x1f-C\x1fcontrol-flow-guard=off",
+            ] {
+                assert!(
+                    !rustflags_enable_control_flow_guard(encoded_rustflags),
+                    "{encoded_rustflags:?} should not enable control flow guard"
+                );
+            }
+        }
+    }
 }
@@ -18,14 +18,21 @@
 mod error;
 mod map;
 
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 
 use camino::Utf8PathBuf;
 use cargo_metadata::Metadata;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::DriverConfig;
+use crate::{
+    BindgenConfig,
+    DriverConfig,
+    InstrumentationConfig,
+    SecurityConfig,
+    TargetOs,
+    BINDGEN_METADATA_KEY,
+};
 
 /// Metadata specified in the `metadata.wdk` section of the `Cargo.toml`
 /// of a crate that depends on the WDK, or in a cargo workspace.
@@ -40,6 +47,24 @@
 pub struct Wdk {
     /// Metadata corresponding to the `Driver Model` property page in the WDK
     pub driver_model: DriverConfig,
+    /// Minimum version of Windows targeted by the driver. This corresponds to
+    /// the `Target OS Version` setting in the `General` property page in the
+    /// WDK
+    #[serde(default)]
+    pub target_os: Option<TargetOs>,
+    /// Whether to link against the Spectre-mitigated variants of the WDK
+    /// libraries. This corresponds to the `Spectre Mitigation` setting in the
+    /// `C/C++` property pages in the WDK
+    #[serde(default)]
+    pub spectre_mitigation: bool,
+    /// Opt-in instrumentation for debug and Driver Verifier builds. When
+    /// present, the `wdk_instrumentation` cfg is also enabled
+    #[serde(default)]
+    pub instrumentation: Option<InstrumentationConfig>,
+    /// Opt-in security-relevant preprocessor definitions. Each enabled
+    /// definition also enables a corresponding `wdk_*` cfg
+    #[serde(default)]
+    pub security: Option<SecurityConfig>,
 }
 
 /// Errors that could result from trying to construct a
@@ -120,38 +145,123 @@ fn parse_packages_wdk_metadata(
 ) -> std::result::Result<HashSet<Wdk>, TryFromCargoMetadataError> {
     let wdk_metadata_configurations = packages
         .iter()
-        .filter_map(|package| match &package.metadata["wdk"] {
-            serde_json::Value::Null => None,
-            // When wdk section is empty, treat it as if it wasn't there. This is to allow for using
-            // empty wdk metadata sections to mark the package as a driver (ex. for detection in
-            // `package_driver_flow_condition_script`)
-            serde_json::Value::Object(map) if map.is_empty() => None,
-            wdk_metadata => Some(Wdk::deserialize(wdk_metadata).map_err(|err| {
-                TryFromCargoMetadataError::WdkMetadataDeserialization {
-                    metadata_source: format!(
-                        "{} for {} package",
-                        stringify!(package.metadata["wdk"]),
-                        package.name
-                    ),
-                    error_source: err,
-                }
-            })),
-        })
+        .filter_map(
+            |package| match without_package_only_keys(&package.metadata["wdk"]) {
+                serde_json::Value::Null => None,
+                // When wdk section is empty, treat it as if it wasn't there. This is to allow for
+                // using empty wdk metadata sections to mark the package as a driver
+                // (ex. for detection in `package_driver_flow_condition_script`)
+                serde_json::Value::Object(map) if map.is_empty() => None,
+                wdk_metadata => Some(Wdk::deserialize(&wdk_metadata).map_err(|err| {
+                    TryFromCargoMetadataError::WdkMetadataDeserialization {
+                        metadata_source: format!(
+                            "{} for {} package",
+                            stringify!(package.metadata["wdk"]),
+                            package.name
+                        ),
+                        error_source: err,
+                    }
+                })),
+            },
+        )
         .collect::<std::result::Result<HashSet<_>, _>>()?;
     Ok(wdk_metadata_configurations)
 }
 
+/// Returns a copy of a package's `metadata.wdk` section without the keys that
+/// only configure that package (ex. `metadata.wdk.inf` and
+/// `metadata.wdk.package`), since those are not
+/// part of the [`Wdk`] configuration that must be identical across the
+/// dependency graph
+fn without_package_only_keys(wdk_metadata: &serde_json::Value) -> serde_json::Value {
+    let mut wdk_metadata = wdk_metadata.clone();
+    if let serde_json::Value::Object(map) = &mut wdk_metadata {
+        map.remove(crate::inx::INF_METADATA_KEY);
+        map.remove(crate::additional_binaries::PACKAGE_METADATA_KEY);
+        map.remove(crate::SKIP_STATIC_CRT_CHECK_METADATA_KEY);
+        map.remove(crate::build_profile::PROFILE_METADATA_KEY);
+        map.remove(BINDGEN_METADATA_KEY);
+    }
+    wdk_metadata
+}
+
+/// Returns a copy of a workspace's `metadata.wdk` section without the keys that
+/// only configure the workspace's tooling (ex. `metadata.wdk.defaults` and
+/// `metadata.wdk.lock`), since those are not part of the [`Wdk`] configuration
+fn without_workspace_only_keys(wdk_metadata: &serde_json::Value) -> serde_json::Value {
+    let mut wdk_metadata = wdk_metadata.clone();
+    if let serde_json::Value::Object(map) = &mut wdk_metadata {
+        map.remove(crate::cli_defaults::DEFAULTS_METADATA_KEY);
+        map.remove(crate::wdk_lock::WDK_LOCK_METADATA_KEY);
+        map.remove(BINDGEN_METADATA_KEY);
+    }
+    wdk_metadata
+}
+
 fn parse_workspace_wdk_metadata(
     workspace_metadata: &serde_json::Value,
 ) -> std::result::Result<Option<Wdk>, TryFromCargoMetadataError> {
-    Ok(match &workspace_metadata["wdk"] {
-        serde_json::Value::Null => None,
-        wdk_metadata => Some(Wdk::deserialize(wdk_metadata).map_err(|err| {
+    Ok(
+        match without_workspace_only_keys(&workspace_metadata["wdk"]) {
+            serde_json::Value::Null => None,
+            // A workspace wdk section that only declares workspace-only keys (ex. the default
+            // command line arguments) does not configure the WDK
+            serde_json::Value::Object(map) if map.is_empty() => None,
+            wdk_metadata => Some(Wdk::deserialize(&wdk_metadata).map_err(|err| {
+                TryFromCargoMetadataError::WdkMetadataDeserialization {
+                    metadata_source: stringify!(workspace_metadata["wdk"]).to_string(),
+                    error_source: err,
+                }
+            })?),
+        },
+    )
+}
+
+/// Parses the `metadata.wdk.bindgen` sections of all packages and of the
+/// workspace, and combines them into a single [`BindgenConfig`]. Duplicate
+/// entries are removed, and entries are sorted so that the result does not
+/// depend on the order of packages in the dependency graph.
+pub(crate) fn parse_bindgen_config(
+    metadata: &Metadata,
+) -> std::result::Result<BindgenConfig, TryFromCargoMetadataError> {
+    let mut blocklist = BTreeSet::new();
+    let mut allowlist = BTreeSet::new();
+
+    let bindgen_metadata_sources = metadata
+        .packages
+        .iter()
+        .map(|package| {
+            (
+                &package.metadata["wdk"][BINDGEN_METADATA_KEY],
+                format!(
+                    "package.metadata.wdk.{BINDGEN_METADATA_KEY} for {} package",
+                    package.name
+                ),
+            )
+        })
+        .chain(std::iter::once((
+            &metadata.workspace_metadata["wdk"][BINDGEN_METADATA_KEY],
+            format!("workspace.metadata.wdk.{BINDGEN_METADATA_KEY}"),
+        )));
+
+    for (bindgen_metadata, metadata_source) in bindgen_metadata_sources {
+        if bindgen_metadata.is_null() {
+            continue;
+        }
+
+        let bindgen_config = BindgenConfig::deserialize(bindgen_metadata).map_err(|err| {
             TryFromCargoMetadataError::WdkMetadataDeserialization {
-                metadata_source: stringify!(workspace_metadata["wdk"]).to_string(),
+                metadata_source,
                 error_source: err,
             }
-        })?),
+        })?;
+        blocklist.extend(bindgen_config.blocklist);
+        allowlist.extend(bindgen_config.allowlist);
+    }
+
+    Ok(BindgenConfig {
+        blocklist: blocklist.into_iter().collect(),
+        allowlist: allowlist.into_iter().collect(),
     })
 }
 
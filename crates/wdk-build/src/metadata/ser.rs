@@ -42,6 +42,10 @@
 ///         target_kmdf_version_minor: 23,
 ///         minimum_kmdf_version_minor: None,
 ///     }),
+///     target_os: None,
+///     spectre_mitigation: false,
+///     instrumentation: None,
+///     security: None,
 /// };
 ///
 /// let output = to_map::<BTreeMap<_, _>>(&wdk_metadata).unwrap();
@@ -89,6 +93,10 @@ pub fn to_map<M>(value: &impl Serialize) -> Result<M>
 ///         target_kmdf_version_minor: 33,
 ///         minimum_kmdf_version_minor: Some(31),
 ///     }),
+///     target_os: None,
+///     spectre_mitigation: false,
+///     instrumentation: None,
+///     security: None,
 /// };
 ///
 /// let output = to_map_with_prefix::<BTreeMap<_, _>>("WDK_BUILD_METADATA", &wdk_metadata).unwrap();
@@ -587,7 +595,14 @@ mod tests {
     };
 
     use super::*;
-    use crate::{metadata, DriverConfig, KmdfConfig, UmdfConfig};
+    use crate::{
+        metadata,
+        DriverConfig,
+        InstrumentationConfig,
+        KmdfConfig,
+        SecurityConfig,
+        UmdfConfig,
+    };
 
     #[test]
     fn test_kmdf() {
@@ -597,6 +612,10 @@ fn test_kmdf() {
                 target_kmdf_version_minor: 23,
                 minimum_kmdf_version_minor: Some(21),
             }),
+            target_os: None,
+            spectre_mitigation: false,
+            instrumentation: None,
+            security: None,
         };
 
         let output = to_map::<BTreeMap<_, _>>(&wdk_metadata).unwrap();
@@ -615,6 +634,10 @@ fn test_kmdf_no_minimum() {
                 target_kmdf_version_minor: 23,
                 minimum_kmdf_version_minor: None,
             }),
+            target_os: None,
+            spectre_mitigation: false,
+            instrumentation: None,
+            security: None,
         };
 
         let output = to_map::<BTreeMap<_, _>>(&wdk_metadata).unwrap();
@@ -635,6 +658,10 @@ fn test_kmdf_with_prefix() {
                 target_kmdf_version_minor: 33,
                 minimum_kmdf_version_minor: Some(31),
             }),
+            target_os: None,
+            spectre_mitigation: false,
+            instrumentation: None,
+            security: None,
         };
 
         let output =
@@ -666,6 +693,10 @@ fn test_kmdf_with_hashmap() {
                 target_kmdf_version_minor: 33,
                 minimum_kmdf_version_minor: Some(31),
             }),
+            target_os: None,
+            spectre_mitigation: false,
+            instrumentation: None,
+            security: None,
         };
 
         let output = to_map::<HashMap<_, _>>(&wdk_metadata).unwrap();
@@ -684,6 +715,10 @@ fn test_umdf() {
                 target_umdf_version_minor: 23,
                 minimum_umdf_version_minor: Some(21),
             }),
+            target_os: None,
+            spectre_mitigation: false,
+            instrumentation: None,
+            security: None,
         };
 
         let output = to_map::<BTreeMap<_, _>>(&wdk_metadata).unwrap();
@@ -702,6 +737,10 @@ fn test_umdf_no_minimum() {
                 target_umdf_version_minor: 23,
                 minimum_umdf_version_minor: None,
             }),
+            target_os: None,
+            spectre_mitigation: false,
+            instrumentation: None,
+            security: None,
         };
 
         let output = to_map::<BTreeMap<_, _>>(&wdk_metadata).unwrap();
@@ -718,11 +757,83 @@ fn test_umdf_no_minimum() {
     fn test_wdm() {
         let wdk_metadata = metadata::Wdk {
             driver_model: DriverConfig::Wdm,
+            target_os: None,
+            spectre_mitigation: false,
+            instrumentation: None,
+            security: None,
+        };
+
+        let output = to_map::<BTreeMap<_, _>>(&wdk_metadata).unwrap();
+
+        assert_eq!(output["DRIVER_MODEL-DRIVER_TYPE"], "WDM");
+    }
+
+    #[test]
+    fn test_target_os() {
+        let wdk_metadata = metadata::Wdk {
+            driver_model: DriverConfig::Wdm,
+            target_os: Some("windows11-22h2".parse().unwrap()),
+            spectre_mitigation: false,
+            instrumentation: None,
+            security: None,
+        };
+
+        let output = to_map::<BTreeMap<_, _>>(&wdk_metadata).unwrap();
+
+        assert_eq!(output["DRIVER_MODEL-DRIVER_TYPE"], "WDM");
+        assert_eq!(output["TARGET_OS"], "windows11-22h2");
+    }
+
+    #[test]
+    fn test_spectre_mitigation() {
+        let wdk_metadata = metadata::Wdk {
+            driver_model: DriverConfig::Wdm,
+            target_os: None,
+            spectre_mitigation: true,
+            instrumentation: None,
+            security: None,
         };
 
         let output = to_map::<BTreeMap<_, _>>(&wdk_metadata).unwrap();
 
         assert_eq!(output["DRIVER_MODEL-DRIVER_TYPE"], "WDM");
+        assert_eq!(output["SPECTRE_MITIGATION"], "true");
+    }
+
+    #[test]
+    fn test_instrumentation() {
+        let wdk_metadata = metadata::Wdk {
+            driver_model: DriverConfig::Wdm,
+            target_os: None,
+            spectre_mitigation: false,
+            instrumentation: Some(InstrumentationConfig {
+                control_flow_guard: true,
+            }),
+            security: None,
+        };
+
+        let output = to_map::<BTreeMap<_, _>>(&wdk_metadata).unwrap();
+
+        assert_eq!(output["INSTRUMENTATION-CONTROL_FLOW_GUARD"], "true");
+    }
+
+    #[test]
+    fn test_security() {
+        let wdk_metadata = metadata::Wdk {
+            driver_model: DriverConfig::Wdm,
+            target_os: None,
+            spectre_mitigation: false,
+            instrumentation: None,
+            security: Some(SecurityConfig {
+                pool_nx_optin: true,
+                deprecate_ddk_functions: false,
+            }),
+        };
+
+        let output = to_map::<BTreeMap<_, _>>(&wdk_metadata).unwrap();
+
+        assert_eq!(output["SECURITY-POOL_NX_OPTIN"], "true");
+        assert_eq!(output["SECURITY-DEPRECATE_DDK_FUNCTIONS"], "false");
     }
 
     #[test]
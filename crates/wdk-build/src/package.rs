@@ -0,0 +1,356 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Packaging primitives for build tools other than cargo-make
+//!
+//! The cargo-make tasks in `rust-driver-makefile.toml` read their inputs from
+//! environment variables that cargo-make sets. Teams that package drivers from
+//! their own build scripts (ex. an `xtask`) can use [`DriverPackage`] instead,
+//! which takes the same inputs as arguments, and runs the WDK tools with the
+//! same arguments as the cargo-make tasks:
+//!
+//! ```rust, ignore
+//! use wdk_build::{package::DriverPackage, CpuArchitecture, DriverConfig, KmdfConfig};
+//!
+//! let driver_package = DriverPackage {
+//!     driver_name: "sample_kmdf_driver".to_string(),
+//!     output_directory: "target/x86_64-pc-windows-msvc/release".into(),
+//!     driver_config: DriverConfig::Kmdf(KmdfConfig::default()),
+//!     cpu_architecture: CpuArchitecture::Amd64,
+//!     driver_version: Some("1.2.3".parse()?),
+//! };
+//!
+//! driver_package.copy_driver_binary()?;
+//! driver_package.copy_to_package(driver_package.output_directory.join("sample_kmdf_driver.pdb"))?;
+//! let inf_path = driver_package.stamp_inf("sample-kmdf-driver.inx")?;
+//! driver_package.copy_to_package(inf_path)?;
+//! driver_package.run_inf2cat()?;
+//! ```
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+};
+
+use thiserror::Error;
+use tracing::trace;
+
+use crate::{
+    probe,
+    utils::{detect_wdk_content_root, get_latest_windows_sdk_version},
+    version_info::DriverVersion,
+    wdk_layout,
+    ConfigError,
+    CpuArchitecture,
+    DriverConfig,
+};
+
+/// Errors that could result from running the WDK tools that package a driver
+#[derive(Debug, Error)]
+pub enum PackageError {
+    /// Error returned when a WDK tool exits with a failure
+    #[error("{tool} failed with {exit_status}")]
+    ToolFailed {
+        /// Name of the tool
+        tool: String,
+        /// Exit status of the tool
+        exit_status: ExitStatus,
+    },
+}
+
+/// A driver package, and the inputs that it is built from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DriverPackage {
+    /// File stem of the driver's binaries and INF file (i.e. the name of the
+    /// driver's `cdylib` target, with `-` replaced by `_`)
+    pub driver_name: String,
+    /// Directory that the driver was built into (ex.
+    /// `target/x86_64-pc-windows-msvc/release`). The driver package is created
+    /// in this directory.
+    pub output_directory: PathBuf,
+    /// Driver model of the driver
+    pub driver_config: DriverConfig,
+    /// CPU architecture that the driver was built for
+    pub cpu_architecture: CpuArchitecture,
+    /// Version stamped into the `DriverVer` directive of the INF file. When
+    /// `None`, `stampinf` generates one.
+    pub driver_version: Option<DriverVersion>,
+}
+
+impl DriverPackage {
+    /// Returns the path of the driver package folder (ex.
+    /// `target/x86_64-pc-windows-msvc/release/sample_kmdf_driver_package`)
+    #[must_use]
+    pub fn package_directory(&self) -> PathBuf {
+        self.output_directory
+            .join(format!("{}_package", self.driver_name))
+    }
+
+    /// Returns the extension of the driver binary in the driver package (`sys`
+    /// for kernel-mode drivers, and `dll` for user-mode drivers)
+    #[must_use]
+    pub const fn driver_binary_extension(&self) -> &'static str {
+        match self.driver_config {
+            DriverConfig::Wdm | DriverConfig::Kmdf(_) => "sys",
+            DriverConfig::Umdf(_) => "dll",
+        }
+    }
+
+    /// Copies the driver binary built by cargo (`<driver name>.dll` in the
+    /// output directory) into the driver package folder, with the extension of
+    /// the driver model, and returns its path in the driver package folder
+    ///
+    /// # Errors
+    ///
+    /// This function returns a [`ConfigError::IoError`] if the driver binary
+    /// cannot be copied, or the driver package folder cannot be created
+    pub fn copy_driver_binary(&self) -> Result<PathBuf, ConfigError> {
+        let package_directory = self.package_directory();
+        std::fs::create_dir_all(&package_directory)?;
+
+        let destination_path = package_directory.join(format!(
+            "{}.{}",
+            self.driver_name,
+            self.driver_binary_extension()
+        ));
+        std::fs::copy(
+            self.output_directory
+                .join(format!("{}.dll", self.driver_name)),
+            &destination_path,
+        )?;
+        Ok(destination_path)
+    }
+
+    /// Copies the file at `path` into the driver package folder, and returns
+    /// its path in the driver package folder
+    ///
+    /// # Errors
+    ///
+    /// This function returns a [`ConfigError::IoError`] if the file cannot be
+    /// copied, or the driver package folder cannot be created
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `path` does not end with a file name
+    pub fn copy_to_package<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, ConfigError> {
+        let path = path.as_ref();
+        let package_directory = self.package_directory();
+        std::fs::create_dir_all(&package_directory)?;
+
+        let destination_path = package_directory.join(
+            path.file_name()
+                .expect("path should always end with a valid file name"),
+        );
+        std::fs::copy(path, &destination_path)?;
+        Ok(destination_path)
+    }
+
+    /// Copies the INX file at `inx_path` to `<driver name>.inf` in the output
+    /// directory, stamps it with `stampinf`, and returns the path of the INF
+    /// file
+    ///
+    /// # Errors
+    ///
+    /// This function returns:
+    /// - [`ConfigError::IoError`] if the INX file cannot be copied, or
+    ///   `stampinf` cannot be run
+    /// - [`ConfigError::PackageError`] if `stampinf` fails
+    pub fn stamp_inf<P: AsRef<Path>>(&self, inx_path: P) -> Result<PathBuf, ConfigError> {
+        let inf_path = self
+            .output_directory
+            .join(format!("{}.inf", self.driver_name));
+        std::fs::copy(inx_path, &inf_path)?;
+
+        run_tool("stampinf", self.stampinf_args(&inf_path))?;
+        Ok(inf_path)
+    }
+
+    /// Returns the arguments that `stampinf` is run with to stamp the INF file
+    /// at `inf_path`
+    #[must_use]
+    pub fn stampinf_args(&self, inf_path: &Path) -> Vec<String> {
+        let mut args = vec![
+            "-f".to_string(),
+            inf_path.display().to_string(),
+            "-d".to_string(),
+            "*".to_string(),
+            "-a".to_string(),
+            match self.cpu_architecture {
+                CpuArchitecture::Amd64 => "amd64",
+                CpuArchitecture::Arm64 => "arm64",
+            }
+            .to_string(),
+            "-c".to_string(),
+            format!("{}.cat", self.driver_name),
+            "-v".to_string(),
+            self.driver_version.map_or_else(
+                || "*".to_string(),
+                |driver_version| driver_version.to_string(),
+            ),
+        ];
+        match &self.driver_config {
+            DriverConfig::Wdm => {}
+            DriverConfig::Kmdf(kmdf_config) => args.extend([
+                "-k".to_string(),
+                format!(
+                    "{}.{}",
+                    kmdf_config.kmdf_version_major, kmdf_config.target_kmdf_version_minor
+                ),
+            ]),
+            DriverConfig::Umdf(umdf_config) => args.extend([
+                "-u".to_string(),
+                format!(
+                    "{}.{}.0",
+                    umdf_config.umdf_version_major, umdf_config.target_umdf_version_minor
+                ),
+            ]),
+        }
+        args
+    }
+
+    /// Generates the catalog file of the driver package folder with `inf2cat`
+    ///
+    /// # Errors
+    ///
+    /// This function returns:
+    /// - [`ConfigError::IoError`] if `inf2cat` cannot be run
+    /// - [`ConfigError::PackageError`] if `inf2cat` fails
+    pub fn run_inf2cat(&self) -> Result<(), ConfigError> {
+        run_tool("inf2cat", self.inf2cat_args())
+    }
+
+    /// Returns the arguments that `inf2cat` is run with to generate the catalog
+    /// file of the driver package folder
+    #[must_use]
+    pub fn inf2cat_args(&self) -> Vec<String> {
+        vec![
+            format!("/driver:{}", self.package_directory().display()),
+            format!(
+                "/os:{}",
+                match self.cpu_architecture {
+                    CpuArchitecture::Amd64 => "10_x64",
+                    CpuArchitecture::Arm64 => "Server10_arm64",
+                }
+            ),
+            "/uselocaltime".to_string(),
+        ]
+    }
+}
+
+/// Returns the path of the WDK tool `tool` (ex. `stampinf`) in the latest
+/// installed WDK
+///
+/// If the tool is not found there, `tool` itself is returned, so that it is
+/// resolved from the `PATH` (ex. in an eWDK build environment).
+///
+/// # Panics
+///
+/// This function will panic if the CPU architecture cannot be determined from
+/// [`env::consts::ARCH`]
+#[must_use]
+pub fn find_tool(tool: &str) -> PathBuf {
+    let host_arch = CpuArchitecture::try_from_cargo_str(env::consts::ARCH)
+        .expect("The rust standard library should always set env::consts::ARCH");
+    let executable_name = format!("{tool}.exe");
+
+    detect_wdk_content_root()
+        .and_then(|wdk_content_root| {
+            let sdk_version = get_latest_windows_sdk_version(&wdk_content_root.join("Lib")).ok()?;
+            let wdk_layout = wdk_layout::select_wdk_layout(&sdk_version).ok()?;
+            probe::component_directories(wdk_layout, &wdk_content_root, &sdk_version, host_arch)
+                .into_iter()
+                .map(|directory| directory.join(&executable_name))
+                .find(|path| path.is_file())
+        })
+        .unwrap_or_else(|| PathBuf::from(tool))
+}
+
+/// Runs the WDK tool `tool` with `args`, and returns an error if it fails
+fn run_tool(tool: &str, args: Vec<String>) -> Result<(), ConfigError> {
+    let mut command = Command::new(find_tool(tool));
+    command.args(args);
+    trace!(command = ?command);
+
+    let exit_status = command.status()?;
+    if !exit_status.success() {
+        return Err(PackageError::ToolFailed {
+            tool: tool.to_string(),
+            exit_status,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{KmdfConfig, UmdfConfig};
+
+    fn driver_package(driver_config: DriverConfig) -> DriverPackage {
+        DriverPackage {
+            driver_name: "sample_driver".to_string(),
+            output_directory: PathBuf::from("target"),
+            driver_config,
+            cpu_architecture: CpuArchitecture::Arm64,
+            driver_version: None,
+        }
+    }
+
+    #[test]
+    fn stampinf_args_match_driver_model() {
+        let inf_path = Path::new("sample_driver.inf");
+
+        assert_eq!(
+            driver_package(DriverConfig::Wdm).stampinf_args(inf_path),
+            [
+                "-f",
+                "sample_driver.inf",
+                "-d",
+                "*",
+                "-a",
+                "arm64",
+                "-c",
+                "sample_driver.cat",
+                "-v",
+                "*"
+            ]
+        );
+        assert!(DriverPackage {
+            driver_version: Some(DriverVersion {
+                major: 1,
+                minor: 2,
+                patch: 3,
+                build: 0,
+            }),
+            ..driver_package(DriverConfig::Wdm)
+        }
+        .stampinf_args(inf_path)
+        .ends_with(&["-v".to_string(), "1.2.3.0".to_string()]));
+        assert!(driver_package(DriverConfig::Kmdf(KmdfConfig::default()))
+            .stampinf_args(inf_path)
+            .ends_with(&["-k".to_string(), "1.33".to_string()]));
+        assert!(driver_package(DriverConfig::Umdf(UmdfConfig::default()))
+            .stampinf_args(inf_path)
+            .ends_with(&["-u".to_string(), "2.33.0".to_string()]));
+    }
+
+    #[test]
+    fn driver_binary_and_catalog_paths() {
+        let driver_package = driver_package(DriverConfig::Umdf(UmdfConfig::default()));
+
+        assert_eq!(driver_package.driver_binary_extension(), "dll");
+        assert_eq!(
+            driver_package.inf2cat_args(),
+            [
+                format!(
+                    "/driver:{}",
+                    Path::new("target").join("sample_driver_package").display()
+                ),
+                "/os:Server10_arm64".to_string(),
+                "/uselocaltime".to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,528 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Custom packaging steps declared in the `metadata.wdk-packaging` section of a
+//! driver's `Cargo.toml`
+//!
+//! Custom packaging steps are external commands that run at a fixed
+//! [`PackagingStage`] of the driver packaging flow in
+//! `rust-driver-makefile.toml`. This allows projects to extend the packaging
+//! flow (ex. to post-process the signed driver binary) without needing to
+//! override any of the built-in `cargo-make` tasks:
+//!
+//! ```toml
+//! [[package.metadata.wdk-packaging.steps]]
+//! name = "dual-sign"
+//! stage = "after-sign"
+//! command = "my-signing-tool"
+//! args = ["--append", "{package_dir}/{package_name}.sys"]
+//! inputs = ["{package_dir}/{package_name}.sys"]
+//! ```
+//!
+//! The `command`, `args`, `inputs` and `outputs` of a step are templates, where
+//! `{variable}` is replaced with the value of the corresponding variable (see
+//! [`TemplateVariables`]), and `{{` and `}}` are replaced with literal braces.
+
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    process::{Command, ExitStatus},
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+const PACKAGING_METADATA_KEY: &str = "wdk-packaging";
+
+/// Errors that could result from parsing or running custom packaging steps
+#[derive(Debug, Error)]
+pub enum PackagingStepError {
+    /// Error returned when the `metadata.wdk-packaging` section of a package
+    /// fails to be deserialized
+    #[error("failed to deserialize metadata.wdk-packaging for {package_name} package")]
+    MetadataDeserialization {
+        /// Name of the package whose metadata failed to be deserialized
+        package_name: String,
+        /// [`serde_json::Error`] that caused the deserialization to fail
+        #[source]
+        error_source: serde_json::Error,
+    },
+
+    /// Error returned when a template contains an unmatched brace
+    #[error("template `{template}` of custom packaging step `{step}` contains an unmatched brace")]
+    InvalidTemplate {
+        /// Name of the custom packaging step
+        step: String,
+        /// The invalid template
+        template: String,
+    },
+
+    /// Error returned when a template references a variable that does not
+    /// exist
+    #[error(
+        "template `{template}` of custom packaging step `{step}` references unknown variable \
+         `{variable}`. Valid variables are: {valid_variables}"
+    )]
+    UnknownTemplateVariable {
+        /// Name of the custom packaging step
+        step: String,
+        /// The template referencing the unknown variable
+        template: String,
+        /// The unknown variable
+        variable: String,
+        /// Comma-separated list of valid variables
+        valid_variables: String,
+    },
+
+    /// Error returned when a declared input of a custom packaging step does
+    /// not exist before the step runs
+    #[error("input {path} of custom packaging step `{step}` does not exist")]
+    MissingInput {
+        /// Name of the custom packaging step
+        step: String,
+        /// Path of the missing input
+        path: PathBuf,
+    },
+
+    /// Error returned when a declared output of a custom packaging step does
+    /// not exist after the step runs
+    #[error("custom packaging step `{step}` did not produce output {path}")]
+    MissingOutput {
+        /// Name of the custom packaging step
+        step: String,
+        /// Path of the missing output
+        path: PathBuf,
+    },
+
+    /// Error returned when the command of a custom packaging step fails to be
+    /// spawned
+    #[error("failed to run command `{command}` of custom packaging step `{step}`")]
+    CommandSpawnError {
+        /// Name of the custom packaging step
+        step: String,
+        /// The command that failed to be spawned
+        command: String,
+        /// [`std::io::Error`] that caused the command to fail to spawn
+        #[source]
+        error_source: std::io::Error,
+    },
+
+    /// Error returned when the command of a custom packaging step exits
+    /// unsuccessfully
+    #[error("command `{command}` of custom packaging step `{step}` failed with {exit_status}")]
+    CommandFailed {
+        /// Name of the custom packaging step
+        step: String,
+        /// The command that failed
+        command: String,
+        /// Exit status of the command
+        exit_status: ExitStatus,
+    },
+}
+
+/// The point in the driver packaging flow at which a custom packaging step
+/// runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PackagingStage {
+    /// After all files are copied to the driver package folder, but before the
+    /// catalog file is generated and any files are signed
+    AfterCopy,
+    /// After the driver binary and catalog file are signed, but before their
+    /// signatures are verified
+    AfterSign,
+    /// After all the built-in packaging steps have completed
+    AfterPackage,
+    /// After a driver package is rebuilt by the `watch` task, to deploy it to a
+    /// test target. These steps do not run in any other packaging flow
+    Deploy,
+}
+
+/// A custom packaging step declared in `metadata.wdk-packaging.steps`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct PackagingStep {
+    /// Name of the step, used in logging and errors
+    pub name: String,
+    /// Stage of the packaging flow at which the step runs
+    pub stage: PackagingStage,
+    /// Template of the command to run
+    pub command: String,
+    /// Templates of the arguments passed to `command`
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Templates of the paths that must exist before the step runs
+    #[serde(default)]
+    pub inputs: Vec<String>,
+    /// Templates of the paths that must exist after the step runs
+    #[serde(default)]
+    pub outputs: Vec<String>,
+}
+
+/// Metadata specified in the `metadata.wdk-packaging` section of a package's
+/// `Cargo.toml`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct PackagingMetadata {
+    /// Custom packaging steps, in the order they should run within each
+    /// [`PackagingStage`]
+    #[serde(default)]
+    pub steps: Vec<PackagingStep>,
+}
+
+/// Variables available to the templates of a [`PackagingStep`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateVariables {
+    /// `{package_name}`: name of the package, with `-` replaced by `_`
+    pub package_name: String,
+    /// `{manifest_dir}`: directory containing the package's `Cargo.toml`. The
+    /// command of each step runs in this directory, and relative `inputs` and
+    /// `outputs` are resolved against it
+    pub manifest_dir: PathBuf,
+    /// `{output_dir}`: directory that the driver binary is built to
+    pub output_dir: PathBuf,
+    /// `{package_dir}`: driver package folder
+    pub package_dir: PathBuf,
+}
+
+impl PackagingMetadata {
+    /// Parses the `metadata.wdk-packaging` section of `package`. Packages
+    /// without this section have no custom packaging steps.
+    ///
+    /// # Errors
+    ///
+    /// This function returns a [`PackagingStepError::MetadataDeserialization`]
+    /// if the section is invalid
+    pub fn try_from_package(package: &cargo_metadata::Package) -> Result<Self, PackagingStepError> {
+        match &package.metadata[PACKAGING_METADATA_KEY] {
+            serde_json::Value::Null => Ok(Self::default()),
+            packaging_metadata => Self::deserialize(packaging_metadata).map_err(|error_source| {
+                PackagingStepError::MetadataDeserialization {
+                    package_name: package.name.clone(),
+                    error_source,
+                }
+            }),
+        }
+    }
+
+    /// Returns the steps that run at `stage`, in declaration order
+    pub fn steps_for_stage(&self, stage: PackagingStage) -> impl Iterator<Item = &PackagingStep> {
+        self.steps.iter().filter(move |step| step.stage == stage)
+    }
+}
+
+impl TemplateVariables {
+    fn to_map(&self) -> BTreeMap<&'static str, String> {
+        BTreeMap::from([
+            ("package_name", self.package_name.clone()),
+            (
+                "manifest_dir",
+                self.manifest_dir.to_string_lossy().into_owned(),
+            ),
+            ("output_dir", self.output_dir.to_string_lossy().into_owned()),
+            (
+                "package_dir",
+                self.package_dir.to_string_lossy().into_owned(),
+            ),
+        ])
+    }
+}
+
+impl PackagingStep {
+    /// Runs the step's command, checking that its declared `inputs` exist
+    /// before it runs and that its declared `outputs` exist after it runs
+    ///
+    /// # Errors
+    ///
+    /// This function returns:
+    /// - [`PackagingStepError::InvalidTemplate`] or
+    ///   [`PackagingStepError::UnknownTemplateVariable`] if any of the step's
+    ///   templates are invalid
+    /// - [`PackagingStepError::MissingInput`] if any of the declared inputs do
+    ///   not exist
+    /// - [`PackagingStepError::CommandSpawnError`] or
+    ///   [`PackagingStepError::CommandFailed`] if the command fails
+    /// - [`PackagingStepError::MissingOutput`] if any of the declared outputs
+    ///   were not produced
+    pub fn run(&self, variables: &TemplateVariables) -> Result<(), PackagingStepError> {
+        let variables_map = variables.to_map();
+        let expand = |template: &String| self.expand_template(template, &variables_map);
+        let resolve = |template: &String| -> Result<PathBuf, PackagingStepError> {
+            Ok(variables.manifest_dir.join(expand(template)?))
+        };
+
+        let command = expand(&self.command)?;
+        let args = self
+            .args
+            .iter()
+            .map(expand)
+            .collect::<Result<Vec<_>, _>>()?;
+        let inputs = self
+            .inputs
+            .iter()
+            .map(resolve)
+            .collect::<Result<Vec<_>, _>>()?;
+        let outputs = self
+            .outputs
+            .iter()
+            .map(resolve)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if let Some(path) = inputs.into_iter().find(|path| !path.exists()) {
+            return Err(PackagingStepError::MissingInput {
+                step: self.name.clone(),
+                path,
+            });
+        }
+
+        println!(
+            "Running custom packaging step `{}`: {command} {}",
+            self.name,
+            args.join(" ")
+        );
+        let exit_status = Command::new(&command)
+            .args(&args)
+            .current_dir(&variables.manifest_dir)
+            .status()
+            .map_err(|error_source| PackagingStepError::CommandSpawnError {
+                step: self.name.clone(),
+                command: command.clone(),
+                error_source,
+            })?;
+        if !exit_status.success() {
+            return Err(PackagingStepError::CommandFailed {
+                step: self.name.clone(),
+                command,
+                exit_status,
+            });
+        }
+
+        if let Some(path) = outputs.into_iter().find(|path| !path.exists()) {
+            return Err(PackagingStepError::MissingOutput {
+                step: self.name.clone(),
+                path,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn expand_template(
+        &self,
+        template: &str,
+        variables: &BTreeMap<&'static str, String>,
+    ) -> Result<String, PackagingStepError> {
+        let invalid_template_error = || PackagingStepError::InvalidTemplate {
+            step: self.name.clone(),
+            template: template.to_string(),
+        };
+
+        let mut expanded = String::with_capacity(template.len());
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' => {
+                    let mut variable = String::new();
+                    loop {
+                        match chars.next() {
+                            // `{{` is an escaped `{`
+                            Some('{') if variable.is_empty() => {
+                                expanded.push('{');
+                                break;
+                            }
+                            Some('}') => {
+                                let value = variables.get(variable.as_str()).ok_or_else(|| {
+                                    PackagingStepError::UnknownTemplateVariable {
+                                        step: self.name.clone(),
+                                        template: template.to_string(),
+                                        variable: variable.clone(),
+                                        valid_variables: variables
+                                            .keys()
+                                            .copied()
+                                            .collect::<Vec<_>>()
+                                            .join(", "),
+                                    }
+                                })?;
+                                expanded.push_str(value);
+                                break;
+                            }
+                            Some(c) if c != '{' => variable.push(c),
+                            _ => return Err(invalid_template_error()),
+                        }
+                    }
+                }
+                // `}}` is an escaped `}`
+                '}' => match chars.next() {
+                    Some('}') => expanded.push('}'),
+                    _ => return Err(invalid_template_error()),
+                },
+                c => expanded.push(c),
+            }
+        }
+
+        Ok(expanded)
+    }
+}
+
+/// Runs all the steps in `packaging_metadata` that run at `stage`, in
+/// declaration order, stopping at the first step that fails
+///
+/// # Errors
+///
+/// This function returns the [`PackagingStepError`] of the first step that
+/// fails
+pub fn run_steps(
+    packaging_metadata: &PackagingMetadata,
+    stage: PackagingStage,
+    variables: &TemplateVariables,
+) -> Result<(), PackagingStepError> {
+    for step in packaging_metadata.steps_for_stage(stage) {
+        step.run(variables)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(stage: PackagingStage) -> PackagingStep {
+        PackagingStep {
+            name: "test-step".to_string(),
+            stage,
+            command: "tool".to_string(),
+            args: vec![],
+            inputs: vec![],
+            outputs: vec![],
+        }
+    }
+
+    fn variables() -> BTreeMap<&'static str, String> {
+        TemplateVariables {
+            package_name: "sample_driver".to_string(),
+            manifest_dir: PathBuf::from("manifest"),
+            output_dir: PathBuf::from("output"),
+            package_dir: PathBuf::from("package"),
+        }
+        .to_map()
+    }
+
+    #[test]
+    fn deserialize_packaging_metadata() {
+        let packaging_metadata = PackagingMetadata::deserialize(serde_json::json!({
+            "steps": [
+                {
+                    "name": "dual-sign",
+                    "stage": "after-sign",
+                    "command": "my-signing-tool",
+                    "args": ["--append", "{package_dir}/{package_name}.sys"],
+                    "inputs": ["{package_dir}/{package_name}.sys"],
+                },
+                {
+                    "name": "archive",
+                    "stage": "after-package",
+                    "command": "tar",
+                    "outputs": ["{output_dir}/{package_name}.tar"],
+                },
+                {
+                    "name": "copy-to-test-machine",
+                    "stage": "deploy",
+                    "command": "robocopy",
+                    "args": ["{package_dir}", "\\\\test-machine\\drivers"],
+                },
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(packaging_metadata.steps.len(), 3);
+        assert_eq!(packaging_metadata.steps[0].stage, PackagingStage::AfterSign);
+        assert!(packaging_metadata.steps[1].args.is_empty());
+        assert_eq!(
+            packaging_metadata
+                .steps_for_stage(PackagingStage::AfterPackage)
+                .map(|step| step.name.as_str())
+                .collect::<Vec<_>>(),
+            ["archive"]
+        );
+        assert_eq!(
+            packaging_metadata
+                .steps_for_stage(PackagingStage::Deploy)
+                .map(|step| step.name.as_str())
+                .collect::<Vec<_>>(),
+            ["copy-to-test-machine"]
+        );
+        assert_eq!(
+            packaging_metadata
+                .steps_for_stage(PackagingStage::AfterCopy)
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn deserialize_packaging_metadata_rejects_unknown_stage() {
+        assert!(PackagingMetadata::deserialize(serde_json::json!({
+            "steps": [{ "name": "step", "stage": "before-build", "command": "tool" }]
+        }))
+        .is_err());
+    }
+
+    #[test]
+    fn expand_template_variables() {
+        assert_eq!(
+            step(PackagingStage::AfterCopy)
+                .expand_template("{package_dir}/{package_name}.sys", &variables())
+                .unwrap(),
+            "package/sample_driver.sys"
+        );
+    }
+
+    #[test]
+    fn expand_template_escaped_braces() {
+        assert_eq!(
+            step(PackagingStage::AfterCopy)
+                .expand_template("{{\"name\": \"{package_name}\"}}", &variables())
+                .unwrap(),
+            "{\"name\": \"sample_driver\"}"
+        );
+    }
+
+    #[test]
+    fn expand_template_unknown_variable() {
+        assert!(matches!(
+            step(PackagingStage::AfterCopy).expand_template("{unknown}", &variables()),
+            Err(PackagingStepError::UnknownTemplateVariable { variable, .. }) if variable == "unknown"
+        ));
+    }
+
+    #[test]
+    fn expand_template_unmatched_braces() {
+        for template in ["{package_name", "package_name}", "{package_{name}}"] {
+            assert!(
+                matches!(
+                    step(PackagingStage::AfterCopy).expand_template(template, &variables()),
+                    Err(PackagingStepError::InvalidTemplate { .. })
+                ),
+                "{template} should be invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn run_fails_on_missing_input() {
+        let step = PackagingStep {
+            inputs: vec!["{package_dir}/does-not-exist.sys".to_string()],
+            ..step(PackagingStage::AfterSign)
+        };
+
+        assert!(matches!(
+            step.run(&TemplateVariables {
+                package_name: "sample_driver".to_string(),
+                manifest_dir: std::env::temp_dir(),
+                output_dir: PathBuf::from("output"),
+                package_dir: PathBuf::from("package"),
+            }),
+            Err(PackagingStepError::MissingInput { .. })
+        ));
+    }
+}
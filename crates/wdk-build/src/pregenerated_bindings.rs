@@ -0,0 +1,472 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Support for building `wdk-sys` from pre-generated bindings, instead of
+//! generating them with bindgen (and therefore LLVM) in every build.
+//!
+//! Pre-generated bindings are stored in a directory tree keyed by the WDK
+//! version, the CPU architecture and the driver model that they were generated
+//! for (ex. `10.0.26100.0/x64/kmdf-1.33`), so that bindings for several
+//! configurations can be checked into source control side by side:
+//! - setting [`GENERATED_BINDINGS_OUT_DIR_ENV_VAR`] makes `wdk-sys` generate
+//!   its bindings with bindgen as usual, and then copy them into the
+//!   subdirectory of that directory for the current configuration
+//! - setting [`PREGENERATED_BINDINGS_DIR_ENV_VAR`] makes `wdk-sys` copy its
+//!   bindings from the subdirectory of that directory for the current
+//!   configuration, without running bindgen
+//!
+//! Relative paths in either environment variable are resolved against the
+//! directory of the top-level `Cargo.toml`.
+//!
+//! The bindings also depend on the enabled `wdk-sys` features and bindgen
+//! settings, which are recorded in a manifest next to the exported bindings.
+//! Pre-generated bindings are only used if their manifest matches the current
+//! build.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    find_top_level_cargo_manifest,
+    utils,
+    BindgenConfig,
+    Config,
+    ConfigError,
+    CpuArchitecture,
+    DriverConfig,
+};
+
+/// Name of the manifest written next to exported bindings
+const BINDINGS_MANIFEST_FILE_NAME: &str = "bindings-manifest.json";
+
+/// Environment variable that points to a directory of pre-generated bindings
+/// that `wdk-sys` is built from, instead of running bindgen
+pub const PREGENERATED_BINDINGS_DIR_ENV_VAR: &str = "WDK_BUILD_PREGENERATED_BINDINGS_DIR";
+
+/// Environment variable that points to a directory that the bindings generated
+/// by `wdk-sys` are copied into, to be consumed later via
+/// [`PREGENERATED_BINDINGS_DIR_ENV_VAR`]
+pub const GENERATED_BINDINGS_OUT_DIR_ENV_VAR: &str = "WDK_BUILD_GENERATED_BINDINGS_OUT_DIR";
+
+/// Settings of a `wdk-sys` build that change its bindings, in addition to the
+/// [`Config`]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BindingsSettings {
+    /// Enabled `wdk-sys` features that generate bindings for an API subset
+    /// (ex. `hid`)
+    pub features: Vec<String>,
+    /// Regex of the items the bindings are pruned to, if
+    /// `prune-unused-bindings` is enabled and the usage scan succeeded
+    pub pruned_items_allowlist: Option<String>,
+}
+
+/// Manifest written next to exported bindings, describing how they were
+/// generated
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct BindingsManifest {
+    settings: BindingsSettings,
+    bindgen: BindgenConfig,
+    /// Names of the exported files
+    files: Vec<String>,
+}
+
+/// Where the bindings to the WDK come from in a build of `wdk-sys`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindingsSource {
+    /// Generate the bindings with bindgen
+    Bindgen {
+        /// Directory to copy the generated bindings into, if any
+        out_dir: Option<PathBuf>,
+    },
+    /// Copy the bindings from a directory of pre-generated bindings
+    Pregenerated {
+        /// Directory containing the pre-generated bindings for the current
+        /// configuration
+        dir: PathBuf,
+    },
+}
+
+impl Config {
+    /// Returns where the bindings to the WDK for this [`Config`] come from,
+    /// based on the [`PREGENERATED_BINDINGS_DIR_ENV_VAR`] and
+    /// [`GENERATED_BINDINGS_OUT_DIR_ENV_VAR`] environment variables. When both
+    /// are set, the bindings are generated with bindgen, so that pre-generated
+    /// bindings can be refreshed without unsetting
+    /// [`PREGENERATED_BINDINGS_DIR_ENV_VAR`]. This also emits the
+    /// `cargo::rerun-if-*` directives required to rebuild when either
+    /// environment variable, or the pre-generated bindings, change.
+    ///
+    /// This function only works when called from a `build.rs` file.
+    ///
+    /// # Errors
+    ///
+    /// This function returns:
+    /// - [`ConfigError::PregeneratedBindingsNotFound`] if
+    ///   [`PREGENERATED_BINDINGS_DIR_ENV_VAR`] is set, but there are no
+    ///   pre-generated bindings for this [`Config`]
+    /// - [`ConfigError::DirectoryNotFound`] if the WDK libraries cannot be
+    ///   found to detect the WDK version
+    pub fn bindings_source(&self) -> Result<BindingsSource, ConfigError> {
+        println!("cargo::rerun-if-env-changed={PREGENERATED_BINDINGS_DIR_ENV_VAR}");
+        println!("cargo::rerun-if-env-changed={GENERATED_BINDINGS_OUT_DIR_ENV_VAR}");
+
+        if let Some(generated_bindings_out_dir) = env::var_os(GENERATED_BINDINGS_OUT_DIR_ENV_VAR) {
+            return Ok(BindingsSource::Bindgen {
+                out_dir: Some(
+                    resolve_against_top_level_manifest_directory(generated_bindings_out_dir)
+                        .join(self.bindings_key()?),
+                ),
+            });
+        }
+
+        if let Some(pregenerated_bindings_dir) = env::var_os(PREGENERATED_BINDINGS_DIR_ENV_VAR) {
+            let dir = resolve_against_top_level_manifest_directory(pregenerated_bindings_dir)
+                .join(self.bindings_key()?);
+            if !dir.is_dir() {
+                return Err(ConfigError::PregeneratedBindingsNotFound {
+                    directory: dir.to_string_lossy().into(),
+                });
+            }
+
+            println!("cargo::rerun-if-changed={}", dir.display());
+            return Ok(BindingsSource::Pregenerated { dir });
+        }
+
+        Ok(BindingsSource::Bindgen { out_dir: None })
+    }
+
+    /// Returns the relative path of the pre-generated bindings for this
+    /// [`Config`] (ex. `10.0.26100.0/x64/kmdf-1.33`)
+    pub(crate) fn bindings_key(&self) -> Result<PathBuf, ConfigError> {
+        let sdk_version =
+            utils::get_latest_windows_sdk_version(&self.wdk_content_root.join("Lib"))?;
+        Ok(bindings_key(
+            &sdk_version,
+            self.cpu_architecture,
+            &self.driver_config,
+        ))
+    }
+}
+
+/// Copies each of the files named `file_names` that exists in `out_dir` to
+/// `destination_dir`, creating `destination_dir` if it does not exist.
+///
+/// A manifest of the copied files, `bindgen_config` and `settings` is written
+/// next to them, so that [`import_bindings`] can check that they match the
+/// build importing them.
+///
+/// # Errors
+///
+/// This function returns a [`ConfigError::IoError`] if `destination_dir` cannot
+/// be created, or a file fails to be copied or written
+pub fn export_bindings(
+    out_dir: &Path,
+    destination_dir: &Path,
+    file_names: &[&str],
+    bindgen_config: &BindgenConfig,
+    settings: &BindingsSettings,
+) -> Result<(), ConfigError> {
+    std::fs::create_dir_all(destination_dir)?;
+
+    let mut files = Vec::new();
+    for file_name in file_names {
+        let source_path = out_dir.join(file_name);
+        if source_path.is_file() {
+            std::fs::copy(&source_path, destination_dir.join(file_name))?;
+            files.push((*file_name).to_string());
+        }
+    }
+
+    let manifest = BindingsManifest {
+        settings: settings.clone(),
+        bindgen: bindgen_config.clone(),
+        files,
+    };
+    serde_json::to_writer_pretty(
+        std::fs::File::create(destination_dir.join(BINDINGS_MANIFEST_FILE_NAME))?,
+        &manifest,
+    )
+    .map_err(std::io::Error::from)?;
+    Ok(())
+}
+
+/// Copies the pre-generated bindings in `source_dir` to `out_dir`, after
+/// checking that the manifest next to them matches `bindgen_config` and
+/// `settings`.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::PregeneratedBindingsNotFound`] if the manifest, or a file
+///   listed in it, does not exist
+/// - [`ConfigError::InvalidPregeneratedBindingsManifest`] if the manifest
+///   cannot be parsed
+/// - [`ConfigError::PregeneratedBindingsMismatch`] if the bindings were
+///   generated with different `wdk-sys` features or bindgen settings
+/// - [`ConfigError::IoError`] if a file fails to be read or copied
+pub fn import_bindings(
+    source_dir: &Path,
+    out_dir: &Path,
+    bindgen_config: &BindgenConfig,
+    settings: &BindingsSettings,
+) -> Result<(), ConfigError> {
+    let manifest_path = source_dir.join(BINDINGS_MANIFEST_FILE_NAME);
+    if !manifest_path.is_file() {
+        return Err(ConfigError::PregeneratedBindingsNotFound {
+            directory: manifest_path.to_string_lossy().into(),
+        });
+    }
+
+    let manifest: BindingsManifest =
+        serde_json::from_str(&std::fs::read_to_string(&manifest_path)?).map_err(|error| {
+            ConfigError::InvalidPregeneratedBindingsManifest {
+                path: manifest_path.to_string_lossy().into(),
+                error_source: error,
+            }
+        })?;
+    if manifest.settings != *settings || manifest.bindgen != *bindgen_config {
+        return Err(ConfigError::PregeneratedBindingsMismatch {
+            directory: source_dir.to_string_lossy().into(),
+        });
+    }
+
+    std::fs::create_dir_all(out_dir)?;
+    for file_name in &manifest.files {
+        let source_path = source_dir.join(file_name);
+        if !source_path.is_file() {
+            return Err(ConfigError::PregeneratedBindingsNotFound {
+                directory: source_path.to_string_lossy().into(),
+            });
+        }
+        std::fs::copy(&source_path, out_dir.join(file_name))?;
+    }
+    Ok(())
+}
+
+/// Returns the relative path of the bindings for the WDK with the Windows SDK
+/// version `sdk_version`, `cpu_architecture` and `driver_config`
+fn bindings_key(
+    sdk_version: &str,
+    cpu_architecture: CpuArchitecture,
+    driver_config: &DriverConfig,
+) -> PathBuf {
+    let driver_model = match driver_config {
+        DriverConfig::Wdm => "wdm".to_string(),
+        DriverConfig::Kmdf(kmdf_config) => format!(
+            "kmdf-{}.{}",
+            kmdf_config.kmdf_version_major, kmdf_config.target_kmdf_version_minor
+        ),
+        DriverConfig::Umdf(umdf_config) => format!(
+            "umdf-{}.{}",
+            umdf_config.umdf_version_major, umdf_config.target_umdf_version_minor
+        ),
+    };
+
+    [
+        sdk_version,
+        &cpu_architecture.as_windows_str().to_ascii_lowercase(),
+        &driver_model,
+    ]
+    .iter()
+    .collect()
+}
+
+/// Resolves `path` against the directory of the top-level `Cargo.toml`, since
+/// build scripts run in the directory of their own package
+pub(crate) fn resolve_against_top_level_manifest_directory(path: impl Into<PathBuf>) -> PathBuf {
+    let path = path.into();
+    if path.is_absolute() {
+        return path;
+    }
+
+    find_top_level_cargo_manifest()
+        .parent()
+        .expect("the top-level Cargo.toml should have a parent directory")
+        .join(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{KmdfConfig, UmdfConfig};
+
+    #[test]
+    fn bindings_keys() {
+        assert_eq!(
+            bindings_key("10.0.26100.0", CpuArchitecture::Amd64, &DriverConfig::Wdm),
+            Path::new("10.0.26100.0").join("x64").join("wdm")
+        );
+        assert_eq!(
+            bindings_key(
+                "10.0.26100.0",
+                CpuArchitecture::Arm64,
+                &DriverConfig::Kmdf(KmdfConfig::new())
+            ),
+            Path::new("10.0.26100.0").join("arm64").join("kmdf-1.33")
+        );
+        assert_eq!(
+            bindings_key(
+                "10.0.22621.0",
+                CpuArchitecture::Amd64,
+                &DriverConfig::Umdf(UmdfConfig::new())
+            ),
+            Path::new("10.0.22621.0").join("x64").join("umdf-2.33")
+        );
+    }
+
+    mod export_and_import_bindings {
+        use super::*;
+
+        fn test_dir(name: &str) -> PathBuf {
+            let dir = env::temp_dir().join(format!(
+                "wdk-build-pregenerated-bindings-test-{}-{name}",
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            dir
+        }
+
+        /// Exports bindings generated with `settings` to a new directory, and
+        /// returns it
+        fn export_test_bindings(name: &str, settings: &BindingsSettings) -> PathBuf {
+            let out_dir = test_dir(&format!("{name}-out"));
+            std::fs::create_dir_all(&out_dir).unwrap();
+            std::fs::write(out_dir.join("types.rs"), "// types").unwrap();
+            std::fs::write(out_dir.join("hid.rs"), "// hid").unwrap();
+
+            let bindings_dir = test_dir(name);
+            export_bindings(
+                &out_dir,
+                &bindings_dir,
+                &["types.rs", "wdf.rs", "hid.rs"],
+                &BindgenConfig::default(),
+                settings,
+            )
+            .unwrap();
+            std::fs::remove_dir_all(&out_dir).unwrap();
+            bindings_dir
+        }
+
+        fn hid_settings() -> BindingsSettings {
+            BindingsSettings {
+                features: vec!["hid".to_string()],
+                pruned_items_allowlist: None,
+            }
+        }
+
+        #[test]
+        fn matching_bindings_are_imported() {
+            let bindings_dir = export_test_bindings("matching", &hid_settings());
+            let out_dir = test_dir("matching-import");
+
+            let result = import_bindings(
+                &bindings_dir,
+                &out_dir,
+                &BindgenConfig::default(),
+                &hid_settings(),
+            );
+            let hid_bindings = std::fs::read_to_string(out_dir.join("hid.rs"));
+            let wdf_bindings_exist = out_dir.join("wdf.rs").exists();
+            std::fs::remove_dir_all(&bindings_dir).unwrap();
+            std::fs::remove_dir_all(&out_dir).unwrap();
+
+            assert!(result.is_ok());
+            assert_eq!(hid_bindings.unwrap(), "// hid");
+            assert!(!wdf_bindings_exist);
+        }
+
+        #[test]
+        fn bindings_with_different_settings_are_rejected() {
+            let bindings_dir = export_test_bindings("mismatched", &hid_settings());
+            let out_dir = test_dir("mismatched-import");
+
+            let different_features = import_bindings(
+                &bindings_dir,
+                &out_dir,
+                &BindgenConfig::default(),
+                &BindingsSettings {
+                    features: vec!["hid".to_string(), "wsk".to_string()],
+                    pruned_items_allowlist: None,
+                },
+            );
+            let different_pruning = import_bindings(
+                &bindings_dir,
+                &out_dir,
+                &BindgenConfig::default(),
+                &BindingsSettings {
+                    pruned_items_allowlist: Some("^(WdfDriverCreate)$".to_string()),
+                    ..hid_settings()
+                },
+            );
+            let different_bindgen_config = import_bindings(
+                &bindings_dir,
+                &out_dir,
+                &BindgenConfig {
+                    blocklist: vec![".*_SOME_BROKEN_STRUCT".to_string()],
+                    allowlist: Vec::new(),
+                },
+                &hid_settings(),
+            );
+            std::fs::remove_dir_all(&bindings_dir).unwrap();
+
+            for result in [
+                different_features,
+                different_pruning,
+                different_bindgen_config,
+            ] {
+                assert!(matches!(
+                    result,
+                    Err(ConfigError::PregeneratedBindingsMismatch { .. })
+                ));
+            }
+        }
+
+        #[test]
+        fn missing_bindings_file_is_rejected() {
+            let bindings_dir = export_test_bindings("missing-file", &hid_settings());
+            std::fs::remove_file(bindings_dir.join("hid.rs")).unwrap();
+            let out_dir = test_dir("missing-file-import");
+
+            let result = import_bindings(
+                &bindings_dir,
+                &out_dir,
+                &BindgenConfig::default(),
+                &hid_settings(),
+            );
+            std::fs::remove_dir_all(&bindings_dir).unwrap();
+            let _ = std::fs::remove_dir_all(&out_dir);
+
+            assert!(matches!(
+                result,
+                Err(ConfigError::PregeneratedBindingsNotFound { directory })
+                    if directory.ends_with("hid.rs")
+            ));
+        }
+
+        #[test]
+        fn bindings_without_manifest_are_rejected() {
+            let bindings_dir = test_dir("no-manifest");
+            std::fs::create_dir_all(&bindings_dir).unwrap();
+            std::fs::write(bindings_dir.join("types.rs"), "// types").unwrap();
+
+            let result = import_bindings(
+                &bindings_dir,
+                &test_dir("no-manifest-import"),
+                &BindgenConfig::default(),
+                &BindingsSettings::default(),
+            );
+            std::fs::remove_dir_all(&bindings_dir).unwrap();
+
+            assert!(matches!(
+                result,
+                Err(ConfigError::PregeneratedBindingsNotFound { .. })
+            ));
+        }
+    }
+}
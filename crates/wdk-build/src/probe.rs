@@ -0,0 +1,222 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Probing of the optional components of a WDK installation
+//!
+//! Some tools used to package drivers (ex. `inf2cat` and `signtool`) are
+//! installed by optional components of the WDK or the Windows SDK. Probing for
+//! them before they are run makes it possible to report which component needs
+//! to be installed, instead of a generic failure to spawn a process.
+
+use std::{
+    env,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    utils::{get_latest_windows_sdk_version, try_detect_wdk_content_root},
+    wdk_layout::{self, WdkLayout},
+    ConfigError,
+    CpuArchitecture,
+};
+
+/// Optional component of a WDK installation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Component {
+    /// `inf2cat`, which generates the catalog file of a driver package
+    Inf2Cat,
+    /// `signtool`, which signs driver binaries and catalog files
+    SignTool,
+    /// `infverif`, which validates INF files
+    InfVerif,
+    /// `ApiValidator`, which validates that a driver only calls APIs allowed
+    /// for its target platform
+    ApiValidator,
+}
+
+impl Component {
+    /// All of the components that can be probed
+    pub const ALL: [Self; 4] = [
+        Self::Inf2Cat,
+        Self::SignTool,
+        Self::InfVerif,
+        Self::ApiValidator,
+    ];
+
+    /// Returns the file name of the component's executable
+    #[must_use]
+    pub const fn executable_name(self) -> &'static str {
+        match self {
+            Self::Inf2Cat => "inf2cat.exe",
+            Self::SignTool => "signtool.exe",
+            Self::InfVerif => "infverif.exe",
+            Self::ApiValidator => "ApiValidator.exe",
+        }
+    }
+
+    /// Returns the name of the installer feature that installs the component
+    #[must_use]
+    pub const fn installer_feature(self) -> &'static str {
+        match self {
+            Self::Inf2Cat | Self::InfVerif | Self::ApiValidator => "Windows Driver Kit",
+            Self::SignTool => "Windows SDK Signing Tools for Desktop Apps",
+        }
+    }
+}
+
+impl fmt::Display for Component {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Inf2Cat => "Inf2Cat",
+            Self::SignTool => "SignTool",
+            Self::InfVerif => "InfVerif",
+            Self::ApiValidator => "ApiValidator",
+        })
+    }
+}
+
+/// Location of a [`Component`] found in a WDK installation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentPath {
+    /// The component that was found
+    pub component: Component,
+    /// Version of the Windows SDK the component was found for (ex.
+    /// `10.0.26100.0`)
+    pub sdk_version: String,
+    /// Path of the component's executable
+    pub path: PathBuf,
+}
+
+/// Probes the WDK installation for `component`, returning the path of its
+/// executable for the latest installed Windows SDK version, or `None` if it is
+/// not installed.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::WdkContentRootDetectionError`] if the WDK content root
+///   directory could not be found
+/// - [`ConfigError::UnknownWdkLayout`] if the `WDK_BUILD_WDK_LAYOUT`
+///   environment variable does not name a known WDK layout
+///
+/// # Panics
+///
+/// This function will panic if the CPU architecture cannot be determined from
+/// [`env::consts::ARCH`]
+pub fn component_exists(component: Component) -> Result<Option<ComponentPath>, ConfigError> {
+    let wdk_content_root = try_detect_wdk_content_root()?;
+    let sdk_version = get_latest_windows_sdk_version(&wdk_content_root.join("Lib"))?;
+    let host_arch = CpuArchitecture::try_from_cargo_str(env::consts::ARCH)
+        .expect("The rust standard library should always set env::consts::ARCH");
+
+    Ok(find_component(
+        component,
+        wdk_layout::select_wdk_layout(&sdk_version)?,
+        &wdk_content_root,
+        &sdk_version,
+        host_arch,
+    ))
+}
+
+/// Probes the WDK installation for `component`, returning an error naming the
+/// installer feature to install if it is not installed.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::MissingWdkComponent`] if `component` is not installed
+/// - any error returned by [`component_exists`]
+pub fn require_component(component: Component) -> Result<ComponentPath, ConfigError> {
+    component_exists(component)?.ok_or(ConfigError::MissingWdkComponent {
+        component,
+        installer_feature: component.installer_feature(),
+    })
+}
+
+/// Returns the directories of a WDK installation that `component` is searched
+/// in, in order of preference. These are the same directories that are added to
+/// the `PATH` of `cargo make` tasks.
+#[must_use]
+pub fn component_directories(
+    wdk_layout: &dyn WdkLayout,
+    wdk_content_root: &Path,
+    sdk_version: &str,
+    host_architecture: CpuArchitecture,
+) -> [PathBuf; 3] {
+    [
+        wdk_layout.tools_directory(
+            wdk_content_root,
+            sdk_version,
+            host_architecture.as_windows_str(),
+        ),
+        wdk_layout.bin_directory(
+            wdk_content_root,
+            sdk_version,
+            host_architecture.as_windows_str(),
+        ),
+        // Some tools (ex. inf2cat) are only available in the x86 folder
+        wdk_layout.bin_directory(wdk_content_root, sdk_version, "x86"),
+    ]
+}
+
+fn find_component(
+    component: Component,
+    wdk_layout: &dyn WdkLayout,
+    wdk_content_root: &Path,
+    sdk_version: &str,
+    host_architecture: CpuArchitecture,
+) -> Option<ComponentPath> {
+    component_directories(wdk_layout, wdk_content_root, sdk_version, host_architecture)
+        .into_iter()
+        .map(|directory| directory.join(component.executable_name()))
+        .find(|path| path.is_file())
+        .map(|path| ComponentPath {
+            component,
+            sdk_version: sdk_version.to_string(),
+            path,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wdk_layout::WindowsKits10Layout;
+
+    const SDK_VERSION: &str = "10.0.26100.0";
+
+    #[test]
+    fn find_components_in_bin_directories() {
+        let wdk_content_root =
+            env::temp_dir().join(format!("wdk-build-probe-test-{}", std::process::id()));
+        let x86_bin_directory = wdk_content_root.join("bin").join(SDK_VERSION).join("x86");
+        std::fs::create_dir_all(&x86_bin_directory).unwrap();
+        std::fs::write(x86_bin_directory.join("inf2cat.exe"), []).unwrap();
+
+        let inf2cat = find_component(
+            Component::Inf2Cat,
+            &WindowsKits10Layout,
+            &wdk_content_root,
+            SDK_VERSION,
+            CpuArchitecture::Amd64,
+        );
+        let signtool = find_component(
+            Component::SignTool,
+            &WindowsKits10Layout,
+            &wdk_content_root,
+            SDK_VERSION,
+            CpuArchitecture::Amd64,
+        );
+        std::fs::remove_dir_all(&wdk_content_root).unwrap();
+
+        assert_eq!(
+            inf2cat,
+            Some(ComponentPath {
+                component: Component::Inf2Cat,
+                sdk_version: SDK_VERSION.to_string(),
+                path: x86_bin_directory.join("inf2cat.exe"),
+            })
+        );
+        assert_eq!(signtool, None);
+    }
+}
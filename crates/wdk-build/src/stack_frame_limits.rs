@@ -0,0 +1,584 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Post-build validation of the stack frame limits declared with the
+//! `#[max_stack(bytes)]` attribute from the `wdk` crate.
+//!
+//! Every function annotated with `#[max_stack(bytes)]` records its address and
+//! its maximum stack frame size in the [`STACK_FRAME_LIMITS_SECTION_NAME`]
+//! section of the driver binary. After the driver is linked, the size of each
+//! annotated function's stack frame is computed from its x64 unwind
+//! information (the `.pdata` section), and compared against its declared
+//! limit. The map file generated by the linker is used to resolve the names of
+//! the functions that exceed their limits.
+
+use std::{collections::HashMap, fmt};
+
+use thiserror::Error;
+
+/// Name of the section of the driver binary that contains the stack frame
+/// limits recorded by the `#[max_stack(bytes)]` attribute. This must match the
+/// `link_section` emitted by `wdk_macros::max_stack`.
+pub const STACK_FRAME_LIMITS_SECTION_NAME: &str = ".wdkstk";
+
+const PE_SIGNATURE: &[u8] = b"PE\0\0";
+const PE32_PLUS_MAGIC: u16 = 0x20B;
+const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+const IMAGE_DIRECTORY_ENTRY_EXCEPTION: usize = 3;
+const SECTION_HEADER_SIZE: usize = 40;
+const RUNTIME_FUNCTION_SIZE: usize = 12;
+const STACK_FRAME_LIMIT_RECORD_SIZE: usize = 16;
+
+/// Errors that could result from validating the stack frame limits of a driver
+/// binary
+#[derive(Debug, Error)]
+pub enum StackFrameLimitError {
+    /// Error returned when the driver binary is not a valid PE32+ image
+    #[error("driver binary is not a valid PE32+ image: {reason}")]
+    InvalidImage {
+        /// Description of why the image is invalid
+        reason: &'static str,
+    },
+
+    /// Error returned when stack frame limits are declared in a driver binary
+    /// built for an architecture whose unwind information is not supported
+    #[error(
+        "stack frame limits can only be validated for x64 driver binaries, but the driver binary \
+         targets machine type {machine:#06x}"
+    )]
+    UnsupportedMachine {
+        /// `Machine` field of the COFF file header of the driver binary
+        machine: u16,
+    },
+
+    /// Error returned when the unwind information of an annotated function
+    /// cannot be parsed
+    #[error("unwind information for function at RVA {function_rva:#x} is invalid: {reason}")]
+    InvalidUnwindInfo {
+        /// RVA of the function whose unwind information is invalid
+        function_rva: u32,
+        /// Description of why the unwind information is invalid
+        reason: &'static str,
+    },
+
+    /// Error returned when the stack frames of annotated functions exceed
+    /// their declared limits
+    #[error(
+        "stack frames exceed the limits declared with #[max_stack]:\n{}",
+        violations.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n")
+    )]
+    LimitsExceeded {
+        /// Functions whose stack frames exceed their declared limits
+        violations: Vec<StackFrameLimitViolation>,
+    },
+}
+
+/// A function whose stack frame exceeds the limit declared with
+/// `#[max_stack(bytes)]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackFrameLimitViolation {
+    /// Name of the function, as resolved from the map file, or its RVA if it
+    /// cannot be resolved
+    pub function: String,
+    /// Size of the function's stack frame in bytes, excluding its return
+    /// address
+    pub frame_size: u64,
+    /// Maximum size of the function's stack frame declared with
+    /// `#[max_stack(bytes)]`
+    pub max_frame_size: u64,
+}
+
+impl fmt::Display for StackFrameLimitViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "  {}: stack frame is {} bytes, but #[max_stack] allows at most {} bytes",
+            self.function, self.frame_size, self.max_frame_size
+        )
+    }
+}
+
+/// A section of a PE image
+struct Section<'a> {
+    name: &'a [u8],
+    virtual_address: u32,
+    data: &'a [u8],
+}
+
+/// The parts of a PE32+ image needed to validate stack frame limits
+struct PeImage<'a> {
+    machine: u16,
+    image_base: u64,
+    exception_directory: (u32, u32),
+    sections: Vec<Section<'a>>,
+}
+
+/// Validates that the stack frames of the functions annotated with
+/// `#[max_stack(bytes)]` in the driver binary `image` do not exceed their
+/// declared limits.
+///
+/// `map_file_contents` is the contents of the map file generated by the
+/// linker, which is used to resolve the names of functions in error messages.
+///
+/// Driver binaries without any annotated functions are always valid.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`StackFrameLimitError::InvalidImage`] if `image` is not a valid PE32+
+///   image
+/// - [`StackFrameLimitError::UnsupportedMachine`] if `image` contains annotated
+///   functions but does not target x64
+/// - [`StackFrameLimitError::InvalidUnwindInfo`] if the unwind information of
+///   an annotated function cannot be parsed
+/// - [`StackFrameLimitError::LimitsExceeded`] if the stack frames of any
+///   annotated functions exceed their declared limits
+pub fn validate(image: &[u8], map_file_contents: Option<&str>) -> Result<(), StackFrameLimitError> {
+    let pe_image = PeImage::parse(image)?;
+
+    let Some(limits_section) = pe_image
+        .sections
+        .iter()
+        .find(|section| section.name == STACK_FRAME_LIMITS_SECTION_NAME.as_bytes())
+    else {
+        return Ok(());
+    };
+
+    // Sections can be padded with zeros between the contributions of different
+    // object files, so records without a function address are skipped
+    let limits = limits_section
+        .data
+        .chunks_exact(STACK_FRAME_LIMIT_RECORD_SIZE)
+        .map(|record| {
+            (
+                read_u64(record, 0).unwrap_or_default(),
+                read_u64(record, 8).unwrap_or_default(),
+            )
+        })
+        .filter(|(function_address, _)| *function_address != 0)
+        .collect::<Vec<_>>();
+    if limits.is_empty() {
+        return Ok(());
+    }
+
+    if pe_image.machine != IMAGE_FILE_MACHINE_AMD64 {
+        return Err(StackFrameLimitError::UnsupportedMachine {
+            machine: pe_image.machine,
+        });
+    }
+
+    let function_names = map_file_contents
+        .map(parse_map_file_function_names)
+        .unwrap_or_default();
+
+    let mut violations = vec![];
+    for (function_address, max_frame_size) in limits {
+        let function_rva = function_address
+            .checked_sub(pe_image.image_base)
+            .and_then(|rva| u32::try_from(rva).ok())
+            .ok_or(StackFrameLimitError::InvalidImage {
+                reason: "stack frame limit refers to an address outside of the image",
+            })?;
+
+        let frame_size = pe_image.frame_size(function_rva)?;
+        if frame_size > max_frame_size {
+            violations.push(StackFrameLimitViolation {
+                function: function_names
+                    .get(&function_address)
+                    .cloned()
+                    .unwrap_or_else(|| format!("function at RVA {function_rva:#x}")),
+                frame_size,
+                max_frame_size,
+            });
+        }
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(StackFrameLimitError::LimitsExceeded { violations })
+    }
+}
+
+impl<'a> PeImage<'a> {
+    fn parse(image: &'a [u8]) -> Result<Self, StackFrameLimitError> {
+        let invalid_image = |reason| StackFrameLimitError::InvalidImage { reason };
+
+        let pe_header_offset =
+            read_u32(image, 0x3C).ok_or_else(|| invalid_image("missing DOS header"))? as usize;
+        if image.get(pe_header_offset..pe_header_offset + PE_SIGNATURE.len()) != Some(PE_SIGNATURE)
+        {
+            return Err(invalid_image("missing PE signature"));
+        }
+
+        let coff_header_offset = pe_header_offset + PE_SIGNATURE.len();
+        let machine =
+            read_u16(image, coff_header_offset).ok_or_else(|| invalid_image("truncated header"))?;
+        let number_of_sections = read_u16(image, coff_header_offset + 2)
+            .ok_or_else(|| invalid_image("truncated header"))?;
+        let size_of_optional_header = read_u16(image, coff_header_offset + 16)
+            .ok_or_else(|| invalid_image("truncated header"))?;
+
+        let optional_header_offset = coff_header_offset + 20;
+        if read_u16(image, optional_header_offset) != Some(PE32_PLUS_MAGIC) {
+            return Err(invalid_image("optional header is not a PE32+ header"));
+        }
+        let image_base = read_u64(image, optional_header_offset + 24)
+            .ok_or_else(|| invalid_image("truncated optional header"))?;
+        let exception_directory_offset =
+            optional_header_offset + 112 + IMAGE_DIRECTORY_ENTRY_EXCEPTION * 8;
+        let exception_directory = (
+            read_u32(image, exception_directory_offset).unwrap_or_default(),
+            read_u32(image, exception_directory_offset + 4).unwrap_or_default(),
+        );
+
+        let section_headers_offset = optional_header_offset + usize::from(size_of_optional_header);
+        let sections = (0..usize::from(number_of_sections))
+            .map(|index| {
+                let header_offset = section_headers_offset + index * SECTION_HEADER_SIZE;
+                let header = image
+                    .get(header_offset..header_offset + SECTION_HEADER_SIZE)
+                    .ok_or_else(|| invalid_image("truncated section headers"))?;
+                let name = &header[..8];
+                let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+                let virtual_size = read_u32(header, 8).unwrap_or_default() as usize;
+                let virtual_address = read_u32(header, 12).unwrap_or_default();
+                let size_of_raw_data = read_u32(header, 16).unwrap_or_default() as usize;
+                let pointer_to_raw_data = read_u32(header, 20).unwrap_or_default() as usize;
+
+                // The raw data of a section is padded to the file alignment, so only its
+                // virtual size is meaningful
+                let data = image
+                    .get(
+                        pointer_to_raw_data
+                            ..pointer_to_raw_data + virtual_size.min(size_of_raw_data),
+                    )
+                    .ok_or_else(|| invalid_image("section data is outside of the image"))?;
+
+                Ok(Section {
+                    name,
+                    virtual_address,
+                    data,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            machine,
+            image_base,
+            exception_directory,
+            sections,
+        })
+    }
+
+    /// Returns the data of the image starting at `rva`
+    fn data_at_rva(&self, rva: u32) -> Option<&'a [u8]> {
+        self.sections.iter().find_map(|section| {
+            let offset = rva.checked_sub(section.virtual_address)? as usize;
+            section.data.get(offset..)
+        })
+    }
+
+    /// Computes the size of the stack frame allocated by the prologue of the
+    /// function at `function_rva` from its unwind information. Functions
+    /// without unwind information are leaf functions, which do not allocate a
+    /// stack frame.
+    fn frame_size(&self, function_rva: u32) -> Result<u64, StackFrameLimitError> {
+        let (exception_directory_rva, exception_directory_size) = self.exception_directory;
+        let Some(runtime_functions) = self.data_at_rva(exception_directory_rva) else {
+            return Ok(0);
+        };
+
+        let unwind_info_rva = runtime_functions
+            .get(..(exception_directory_size as usize).min(runtime_functions.len()))
+            .unwrap_or_default()
+            .chunks_exact(RUNTIME_FUNCTION_SIZE)
+            .find(|runtime_function| read_u32(runtime_function, 0) == Some(function_rva))
+            .and_then(|runtime_function| read_u32(runtime_function, 8));
+        let Some(unwind_info_rva) = unwind_info_rva else {
+            return Ok(0);
+        };
+
+        let unwind_info =
+            self.data_at_rva(unwind_info_rva)
+                .ok_or(StackFrameLimitError::InvalidUnwindInfo {
+                    function_rva,
+                    reason: "unwind information is outside of the image",
+                })?;
+        unwind_info_frame_size(unwind_info).map_err(|reason| {
+            StackFrameLimitError::InvalidUnwindInfo {
+                function_rva,
+                reason,
+            }
+        })
+    }
+}
+
+/// Computes the size of the stack frame described by the unwind codes of an
+/// x64 `UNWIND_INFO` structure: <https://learn.microsoft.com/en-us/cpp/build/exception-handling-x64#struct-unwind_info>
+fn unwind_info_frame_size(unwind_info: &[u8]) -> Result<u64, &'static str> {
+    const UWOP_PUSH_NONVOL: u8 = 0;
+    const UWOP_ALLOC_LARGE: u8 = 1;
+    const UWOP_ALLOC_SMALL: u8 = 2;
+    const UWOP_SET_FPREG: u8 = 3;
+    const UWOP_SAVE_NONVOL: u8 = 4;
+    const UWOP_SAVE_NONVOL_FAR: u8 = 5;
+    const UWOP_EPILOG: u8 = 6;
+    const UWOP_SPARE_CODE: u8 = 7;
+    const UWOP_SAVE_XMM128: u8 = 8;
+    const UWOP_SAVE_XMM128_FAR: u8 = 9;
+    const UWOP_PUSH_MACHFRAME: u8 = 10;
+
+    let count_of_codes = usize::from(*unwind_info.get(2).ok_or("truncated header")?);
+    let unwind_codes = unwind_info
+        .get(4..4 + count_of_codes * 2)
+        .ok_or("truncated unwind codes")?;
+    let slot = |index: usize| read_u16(unwind_codes, index * 2).ok_or("truncated unwind code");
+
+    let mut frame_size = 0;
+    let mut index = 0;
+    while index < count_of_codes {
+        let unwind_op_and_info = unwind_codes[index * 2 + 1];
+        let (unwind_op, op_info) = (unwind_op_and_info & 0xF, unwind_op_and_info >> 4);
+        index += match unwind_op {
+            UWOP_PUSH_NONVOL => {
+                frame_size += 8;
+                1
+            }
+            UWOP_ALLOC_LARGE if op_info == 0 => {
+                frame_size += u64::from(slot(index + 1)?) * 8;
+                2
+            }
+            UWOP_ALLOC_LARGE => {
+                frame_size += u64::from(slot(index + 1)?) | (u64::from(slot(index + 2)?) << 16);
+                3
+            }
+            UWOP_ALLOC_SMALL => {
+                frame_size += u64::from(op_info) * 8 + 8;
+                1
+            }
+            UWOP_SET_FPREG => 1,
+            UWOP_SAVE_NONVOL | UWOP_EPILOG | UWOP_SAVE_XMM128 => 2,
+            UWOP_SAVE_NONVOL_FAR | UWOP_SPARE_CODE | UWOP_SAVE_XMM128_FAR => 3,
+            UWOP_PUSH_MACHFRAME => {
+                frame_size += if op_info == 0 { 40 } else { 48 };
+                1
+            }
+            _ => return Err("unknown unwind operation"),
+        };
+    }
+
+    Ok(frame_size)
+}
+
+/// Parses the public symbols listed in a map file generated by the MSVC linker
+/// into a map from their virtual addresses to their names
+fn parse_map_file_function_names(map_file_contents: &str) -> HashMap<u64, String> {
+    // Public symbols are listed in the following format:
+    // ` 0001:00000000       symbol_name       0000000140001000 f   object.o`
+    map_file_contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let address = fields.next()?;
+            let name = fields.next()?;
+            let rva_plus_base = fields.next()?;
+            address.contains(':').then_some(())?;
+            Some((
+                u64::from_str_radix(rva_plus_base, 16).ok()?,
+                name.to_string(),
+            ))
+        })
+        .collect()
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMAGE_BASE: u64 = 0x1_4000_0000;
+    const TEXT_RVA: u32 = 0x1000;
+    const PDATA_RVA: u32 = 0x2000;
+    const XDATA_RVA: u32 = 0x3000;
+    const LIMITS_RVA: u32 = 0x4000;
+
+    /// Builds a minimal x64 PE32+ image with a single function at `TEXT_RVA`
+    /// whose unwind codes are `unwind_codes`, and whose stack frame limit is
+    /// `max_frame_size`
+    fn build_image(unwind_codes: &[u16], max_frame_size: u64) -> Vec<u8> {
+        const PE_HEADER_OFFSET: usize = 0x40;
+        const SIZE_OF_OPTIONAL_HEADER: u16 = 240;
+        const SECTIONS_DATA_OFFSET: usize = 0x400;
+        const SECTION_FILE_SIZE: usize = 0x200;
+
+        let mut unwind_info = vec![1, 0, u8::try_from(unwind_codes.len()).unwrap(), 0];
+        for unwind_code in unwind_codes {
+            unwind_info.extend_from_slice(&unwind_code.to_le_bytes());
+        }
+
+        let mut runtime_function = vec![];
+        for value in [TEXT_RVA, TEXT_RVA + 0x10, XDATA_RVA] {
+            runtime_function.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut limits = vec![];
+        limits.extend_from_slice(&(IMAGE_BASE + u64::from(TEXT_RVA)).to_le_bytes());
+        limits.extend_from_slice(&max_frame_size.to_le_bytes());
+
+        let sections: [(&str, u32, Vec<u8>); 4] = [
+            (".text", TEXT_RVA, vec![0xC3; 0x10]),
+            (".pdata", PDATA_RVA, runtime_function),
+            (".xdata", XDATA_RVA, unwind_info),
+            (STACK_FRAME_LIMITS_SECTION_NAME, LIMITS_RVA, limits),
+        ];
+
+        let mut image = vec![0; SECTIONS_DATA_OFFSET + sections.len() * SECTION_FILE_SIZE];
+        image[0..2].copy_from_slice(b"MZ");
+        image[0x3C..0x40].copy_from_slice(&u32::try_from(PE_HEADER_OFFSET).unwrap().to_le_bytes());
+        image[PE_HEADER_OFFSET..PE_HEADER_OFFSET + 4].copy_from_slice(PE_SIGNATURE);
+
+        let coff_header_offset = PE_HEADER_OFFSET + 4;
+        image[coff_header_offset..coff_header_offset + 2]
+            .copy_from_slice(&IMAGE_FILE_MACHINE_AMD64.to_le_bytes());
+        image[coff_header_offset + 2..coff_header_offset + 4]
+            .copy_from_slice(&u16::try_from(sections.len()).unwrap().to_le_bytes());
+        image[coff_header_offset + 16..coff_header_offset + 18]
+            .copy_from_slice(&SIZE_OF_OPTIONAL_HEADER.to_le_bytes());
+
+        let optional_header_offset = coff_header_offset + 20;
+        image[optional_header_offset..optional_header_offset + 2]
+            .copy_from_slice(&PE32_PLUS_MAGIC.to_le_bytes());
+        image[optional_header_offset + 24..optional_header_offset + 32]
+            .copy_from_slice(&IMAGE_BASE.to_le_bytes());
+        let exception_directory_offset =
+            optional_header_offset + 112 + IMAGE_DIRECTORY_ENTRY_EXCEPTION * 8;
+        image[exception_directory_offset..exception_directory_offset + 4]
+            .copy_from_slice(&PDATA_RVA.to_le_bytes());
+        image[exception_directory_offset + 4..exception_directory_offset + 8]
+            .copy_from_slice(&u32::try_from(RUNTIME_FUNCTION_SIZE).unwrap().to_le_bytes());
+
+        let section_headers_offset = optional_header_offset + usize::from(SIZE_OF_OPTIONAL_HEADER);
+        for (index, (name, virtual_address, data)) in sections.iter().enumerate() {
+            let header_offset = section_headers_offset + index * SECTION_HEADER_SIZE;
+            let data_offset = SECTIONS_DATA_OFFSET + index * SECTION_FILE_SIZE;
+            let header = &mut image[header_offset..header_offset + SECTION_HEADER_SIZE];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[8..12].copy_from_slice(&u32::try_from(data.len()).unwrap().to_le_bytes());
+            header[12..16].copy_from_slice(&virtual_address.to_le_bytes());
+            header[16..20]
+                .copy_from_slice(&u32::try_from(SECTION_FILE_SIZE).unwrap().to_le_bytes());
+            header[20..24].copy_from_slice(&u32::try_from(data_offset).unwrap().to_le_bytes());
+            image[data_offset..data_offset + data.len()].copy_from_slice(data);
+        }
+
+        image
+    }
+
+    /// Encodes an x64 unwind code from its prologue offset, operation and
+    /// operation info
+    const fn unwind_code(code_offset: u8, unwind_op: u8, op_info: u8) -> u16 {
+        u16::from_le_bytes([code_offset, unwind_op | (op_info << 4)])
+    }
+
+    // Unwind codes are listed in reverse order of the prologue: `sub rsp, 0x28`
+    // followed by `push rbx`
+    const SMALL_FRAME_UNWIND_CODES: [u16; 2] = [unwind_code(5, 2, 4), unwind_code(1, 0, 3)];
+
+    #[test]
+    fn unwind_info_frame_size_small_and_push() {
+        let mut unwind_info = vec![1, 5, 2, 0];
+        for unwind_code in SMALL_FRAME_UNWIND_CODES {
+            unwind_info.extend_from_slice(&unwind_code.to_le_bytes());
+        }
+
+        assert_eq!(unwind_info_frame_size(&unwind_info), Ok(0x28 + 8));
+    }
+
+    #[test]
+    fn unwind_info_frame_size_large() {
+        // `sub rsp, 0x2000` (scaled) and `sub rsp, 0x123456` (unscaled)
+        let mut unwind_info = vec![1, 0, 5, 0];
+        for unwind_code in [
+            unwind_code(7, 1, 0),
+            0x2000 / 8,
+            unwind_code(14, 1, 1),
+            0x3456,
+            0x12,
+        ] {
+            unwind_info.extend_from_slice(&unwind_code.to_le_bytes());
+        }
+
+        assert_eq!(unwind_info_frame_size(&unwind_info), Ok(0x2000 + 0x12_3456));
+    }
+
+    #[test]
+    fn unwind_info_frame_size_truncated() {
+        assert!(unwind_info_frame_size(&[1, 0, 2, 0, 0x01]).is_err());
+    }
+
+    #[test]
+    fn validate_within_limit() {
+        let image = build_image(&SMALL_FRAME_UNWIND_CODES, 0x30);
+
+        assert!(validate(&image, None).is_ok());
+    }
+
+    #[test]
+    fn validate_exceeding_limit() {
+        let image = build_image(&SMALL_FRAME_UNWIND_CODES, 0x20);
+        let map_file_contents = format!(
+            " Address         Publics by Value              Rva+Base               Lib:Object\n\n \
+             0001:00000000       my_driver::dispatch        {:016x} f   my_driver.o\n",
+            IMAGE_BASE + u64::from(TEXT_RVA)
+        );
+
+        let Err(StackFrameLimitError::LimitsExceeded { violations }) =
+            validate(&image, Some(&map_file_contents))
+        else {
+            panic!("stack frame limit should be exceeded");
+        };
+        assert_eq!(
+            violations,
+            [StackFrameLimitViolation {
+                function: "my_driver::dispatch".to_string(),
+                frame_size: 0x30,
+                max_frame_size: 0x20,
+            }]
+        );
+    }
+
+    #[test]
+    fn validate_skips_zero_padding_records() {
+        let mut image = build_image(&SMALL_FRAME_UNWIND_CODES, 0x20);
+        let limits_data_offset = 0x400 + 3 * 0x200;
+        image[limits_data_offset..limits_data_offset + STACK_FRAME_LIMIT_RECORD_SIZE].fill(0);
+
+        assert!(validate(&image, None).is_ok());
+    }
+
+    #[test]
+    fn validate_invalid_image() {
+        assert!(matches!(
+            validate(b"not a PE image", None),
+            Err(StackFrameLimitError::InvalidImage { .. })
+        ));
+    }
+}
@@ -0,0 +1,609 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Listings of the items in the bindings generated by `wdk-sys`, to detect
+//! accidental binding regressions (ex. when moving to a newer WDK)
+//!
+//! When the [`SYMBOL_SURFACE_OUT_DIR_ENV_VAR`] environment variable is set,
+//! `wdk-sys` writes a [`SymbolSurface`] listing every function, static, type
+//! and constant of its bindings to a [`SYMBOL_SURFACE_FILE_NAME`] file, in the
+//! subdirectory of that directory for the current configuration (ex.
+//! `10.0.26100.0/x64/kmdf-1.33`). The listing records the `wdk-sys` features
+//! that were enabled, and the module of each item, so the items of each
+//! feature can be told apart.
+//!
+//! Listings are compared with [`compare_directories`], which reports the items
+//! that were added, removed or changed in every configuration. Removed and
+//! changed items are breaking changes to the users of `wdk-sys`.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use quote::ToTokens;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{pregenerated_bindings, Config, ConfigError};
+
+/// Environment variable that points to a directory that the listings of the
+/// items in the bindings generated by `wdk-sys` are written to
+pub const SYMBOL_SURFACE_OUT_DIR_ENV_VAR: &str = "WDK_BUILD_SYMBOL_SURFACE_OUT_DIR";
+
+/// Environment variable that points to a directory of listings that the
+/// listings in [`SYMBOL_SURFACE_OUT_DIR_ENV_VAR`] are compared to
+pub const SYMBOL_SURFACE_BASELINE_DIR_ENV_VAR: &str = "WDK_BUILD_SYMBOL_SURFACE_BASELINE_DIR";
+
+/// Name of the file that a [`SymbolSurface`] is written to
+pub const SYMBOL_SURFACE_FILE_NAME: &str = "symbols.json";
+
+/// Prefix of the environment variables that Cargo sets for each enabled
+/// feature of the package of a build script
+const CARGO_FEATURE_ENV_VAR_PREFIX: &str = "CARGO_FEATURE_";
+
+/// Errors that could result from writing, reading or comparing listings of the
+/// items in the bindings generated by `wdk-sys`
+#[derive(Debug, Error)]
+pub enum SymbolSurfaceError {
+    /// Error returned when a file or directory fails to be read or written
+    #[error("failed to access {path}")]
+    IoError {
+        /// Path of the file or directory that failed to be accessed
+        path: PathBuf,
+        /// [`std::io::Error`] that caused the access to fail
+        #[source]
+        error_source: std::io::Error,
+    },
+
+    /// Error returned when a file of generated bindings fails to be parsed
+    #[error("failed to parse the bindings in {path}")]
+    ParseError {
+        /// Path of the file of bindings
+        path: PathBuf,
+        /// [`syn::Error`] that caused the parsing to fail
+        #[source]
+        error_source: syn::Error,
+    },
+
+    /// Error returned when a listing fails to be serialized or deserialized
+    #[error("failed to serialize or deserialize the listing in {path}")]
+    SerdeError {
+        /// Path of the listing
+        path: PathBuf,
+        /// [`serde_json::Error`] that caused the serialization or
+        /// deserialization to fail
+        #[source]
+        error_source: serde_json::Error,
+    },
+
+    /// Error returned when an environment variable that names a directory of
+    /// listings is not set
+    #[error("the {env_var} environment variable must be set to a directory of listings")]
+    DirectoryNotSet {
+        /// Name of the environment variable
+        env_var: &'static str,
+    },
+
+    /// Error returned when items were removed or changed between the baseline
+    /// listings and the current listings
+    #[error("{count} listing(s) have removed or changed items")]
+    BreakingChanges {
+        /// Number of listings with removed or changed items
+        count: usize,
+    },
+}
+
+/// Kind of an item in the generated bindings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ItemKind {
+    /// A `const` item
+    Constant,
+    /// A function declared in an `extern` block
+    Function,
+    /// A `static` declared in an `extern` block
+    Static,
+    /// A struct, union, enum or type alias
+    Type,
+}
+
+impl fmt::Display for ItemKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Constant => "constant",
+            Self::Function => "function",
+            Self::Static => "static",
+            Self::Type => "type",
+        })
+    }
+}
+
+/// An item in the generated bindings
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Item {
+    /// Module of the item, named after the file of bindings that it is
+    /// generated in (ex. `ntddk`)
+    pub module: String,
+    /// Kind of the item
+    pub kind: ItemKind,
+    /// Name of the item
+    pub name: String,
+    /// Declaration of the item, without its doc comments
+    pub signature: String,
+}
+
+impl Item {
+    /// Returns the key that identifies the item across listings
+    fn key(&self) -> (&str, ItemKind, &str) {
+        (&self.module, self.kind, &self.name)
+    }
+}
+
+/// Listing of the items in the bindings generated by `wdk-sys` for a
+/// configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SymbolSurface {
+    /// `wdk-sys` features that were enabled when the bindings were generated
+    pub features: Vec<String>,
+    /// Items in the bindings, sorted by module, kind and name
+    pub items: Vec<Item>,
+}
+
+impl SymbolSurface {
+    /// Adds the items of the bindings in `source` to the listing, under
+    /// `module`
+    ///
+    /// # Errors
+    ///
+    /// This function returns a [`syn::Error`] if `source` is not valid Rust
+    pub fn add_bindings(&mut self, module: &str, source: &str) -> syn::Result<()> {
+        let file = syn::parse_file(source)?;
+        collect_items(module, &file.items, &mut self.items);
+        self.items.sort();
+        Ok(())
+    }
+
+    /// Reads the listing in `path`
+    ///
+    /// # Errors
+    ///
+    /// This function returns:
+    /// - [`SymbolSurfaceError::IoError`] if the file cannot be read
+    /// - [`SymbolSurfaceError::SerdeError`] if the file is not a valid listing
+    pub fn read(path: &Path) -> Result<Self, SymbolSurfaceError> {
+        let contents =
+            fs::read_to_string(path).map_err(|error_source| SymbolSurfaceError::IoError {
+                path: path.to_path_buf(),
+                error_source,
+            })?;
+        serde_json::from_str(&contents).map_err(|error_source| SymbolSurfaceError::SerdeError {
+            path: path.to_path_buf(),
+            error_source,
+        })
+    }
+
+    /// Writes the listing to `path`, creating its parent directory if it does
+    /// not exist
+    ///
+    /// # Errors
+    ///
+    /// This function returns:
+    /// - [`SymbolSurfaceError::IoError`] if the file cannot be written
+    /// - [`SymbolSurfaceError::SerdeError`] if the listing fails to be
+    ///   serialized
+    pub fn write(&self, path: &Path) -> Result<(), SymbolSurfaceError> {
+        let io_error = |error_source| SymbolSurfaceError::IoError {
+            path: path.to_path_buf(),
+            error_source,
+        };
+        let contents = serde_json::to_string_pretty(self).map_err(|error_source| {
+            SymbolSurfaceError::SerdeError {
+                path: path.to_path_buf(),
+                error_source,
+            }
+        })?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(io_error)?;
+        }
+        fs::write(path, contents + "\n").map_err(io_error)
+    }
+}
+
+/// Differences between two listings
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolSurfaceDiff {
+    /// Items that are only in the current listing
+    pub added: Vec<Item>,
+    /// Items that are only in the baseline listing
+    pub removed: Vec<Item>,
+    /// Items whose declaration differs, as pairs of the baseline item and the
+    /// current item
+    pub changed: Vec<(Item, Item)>,
+}
+
+impl SymbolSurfaceDiff {
+    /// Returns `true` if the listings have the same items
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Returns `true` if items were removed or changed, which breaks users of
+    /// the bindings
+    #[must_use]
+    pub const fn is_breaking(&self) -> bool {
+        !self.removed.is_empty() || !self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for SymbolSurfaceDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for item in &self.removed {
+            writeln!(f, "- {} {}::{}", item.kind, item.module, item.name)?;
+        }
+        for (baseline, current) in &self.changed {
+            writeln!(f, "~ {} {}::{}", current.kind, current.module, current.name)?;
+            writeln!(f, "    - {}", baseline.signature)?;
+            writeln!(f, "    + {}", current.signature)?;
+        }
+        for item in &self.added {
+            writeln!(f, "+ {} {}::{}", item.kind, item.module, item.name)?;
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Writes the listing of the items in the bindings named `file_names` in
+    /// `bindings_dir` to the subdirectory for this [`Config`] of the directory
+    /// in the [`SYMBOL_SURFACE_OUT_DIR_ENV_VAR`] environment variable, if it is
+    /// set. This also emits the `cargo::rerun-if-env-changed` directive
+    /// required to write the listing when the environment variable changes.
+    ///
+    /// This function only works when called from a `build.rs` file.
+    ///
+    /// # Errors
+    ///
+    /// This function returns:
+    /// - [`ConfigError::SymbolSurfaceError`] if the bindings cannot be read or
+    ///   parsed, or if the listing cannot be written
+    /// - [`ConfigError::DirectoryNotFound`] if the WDK libraries cannot be
+    ///   found to detect the WDK version
+    pub fn write_symbol_surface(
+        &self,
+        bindings_dir: &Path,
+        file_names: &[&str],
+    ) -> Result<(), ConfigError> {
+        println!("cargo::rerun-if-env-changed={SYMBOL_SURFACE_OUT_DIR_ENV_VAR}");
+
+        let Some(symbol_surface_out_dir) = env::var_os(SYMBOL_SURFACE_OUT_DIR_ENV_VAR) else {
+            return Ok(());
+        };
+
+        let mut symbol_surface = SymbolSurface {
+            features: enabled_features(),
+            items: Vec::new(),
+        };
+        for file_name in file_names {
+            let path = bindings_dir.join(file_name);
+            let Some(module) = file_name.strip_suffix(".rs") else {
+                continue;
+            };
+            if !path.is_file() {
+                continue;
+            }
+
+            let source =
+                fs::read_to_string(&path).map_err(|error_source| SymbolSurfaceError::IoError {
+                    path: path.clone(),
+                    error_source,
+                })?;
+            symbol_surface
+                .add_bindings(module, &source)
+                .map_err(|error_source| SymbolSurfaceError::ParseError {
+                    path: path.clone(),
+                    error_source,
+                })?;
+        }
+
+        symbol_surface.write(
+            &pregenerated_bindings::resolve_against_top_level_manifest_directory(
+                symbol_surface_out_dir,
+            )
+            .join(self.bindings_key()?)
+            .join(SYMBOL_SURFACE_FILE_NAME),
+        )?;
+        Ok(())
+    }
+}
+
+/// Compares every listing in `baseline_dir` with the listing at the same
+/// relative path in `current_dir`, and returns the differences of the listings
+/// that differ.
+///
+/// A listing that is missing from `current_dir` is compared with an empty
+/// listing, so all of its items are reported as removed.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`SymbolSurfaceError::IoError`] if a directory or a listing cannot be read
+/// - [`SymbolSurfaceError::SerdeError`] if a file is not a valid listing
+pub fn compare_directories(
+    baseline_dir: &Path,
+    current_dir: &Path,
+) -> Result<Vec<(PathBuf, SymbolSurfaceDiff)>, SymbolSurfaceError> {
+    let mut relative_paths = Vec::new();
+    find_listings(baseline_dir, Path::new(""), &mut relative_paths)?;
+    relative_paths.sort();
+
+    let mut diffs = Vec::new();
+    for relative_path in relative_paths {
+        let baseline = SymbolSurface::read(&baseline_dir.join(&relative_path))?;
+        let current_path = current_dir.join(&relative_path);
+        let current = if current_path.is_file() {
+            SymbolSurface::read(&current_path)?
+        } else {
+            SymbolSurface::default()
+        };
+
+        let diff = compare(&baseline, &current);
+        if !diff.is_empty() {
+            diffs.push((relative_path, diff));
+        }
+    }
+    Ok(diffs)
+}
+
+/// Returns the differences between the `baseline` and `current` listings
+#[must_use]
+pub fn compare(baseline: &SymbolSurface, current: &SymbolSurface) -> SymbolSurfaceDiff {
+    let baseline_items = baseline
+        .items
+        .iter()
+        .map(|item| (item.key(), item))
+        .collect::<BTreeMap<_, _>>();
+    let current_items = current
+        .items
+        .iter()
+        .map(|item| (item.key(), item))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut diff = SymbolSurfaceDiff::default();
+    for (key, baseline_item) in &baseline_items {
+        match current_items.get(key) {
+            None => diff.removed.push((*baseline_item).clone()),
+            Some(current_item) if current_item.signature != baseline_item.signature => diff
+                .changed
+                .push(((*baseline_item).clone(), (*current_item).clone())),
+            Some(_) => {}
+        }
+    }
+    diff.added = current_items
+        .iter()
+        .filter(|(key, _)| !baseline_items.contains_key(*key))
+        .map(|(_, item)| (*item).clone())
+        .collect();
+    diff
+}
+
+/// Adds the relative paths (from `dir`) of the listings in `dir` and its
+/// subdirectories to `relative_paths`
+fn find_listings(
+    dir: &Path,
+    relative_dir: &Path,
+    relative_paths: &mut Vec<PathBuf>,
+) -> Result<(), SymbolSurfaceError> {
+    let io_error = |error_source| SymbolSurfaceError::IoError {
+        path: dir.join(relative_dir),
+        error_source,
+    };
+    for entry in fs::read_dir(dir.join(relative_dir)).map_err(io_error)? {
+        let entry = entry.map_err(io_error)?;
+        let relative_path = relative_dir.join(entry.file_name());
+        if entry.file_type().map_err(io_error)?.is_dir() {
+            find_listings(dir, &relative_path, relative_paths)?;
+        } else if entry.file_name() == SYMBOL_SURFACE_FILE_NAME {
+            relative_paths.push(relative_path);
+        }
+    }
+    Ok(())
+}
+
+/// Adds the public items in `items` to `listing`, recursing into inline
+/// modules
+fn collect_items(module: &str, items: &[syn::Item], listing: &mut Vec<Item>) {
+    let mut push = |kind, name: String, signature: String| {
+        listing.push(Item {
+            module: module.to_string(),
+            kind,
+            name,
+            signature,
+        });
+    };
+    let mut inline_modules = Vec::new();
+
+    for item in items {
+        match item {
+            syn::Item::Const(item) if is_public(&item.vis) => push(
+                ItemKind::Constant,
+                item.ident.to_string(),
+                signature(item, &item.attrs),
+            ),
+            syn::Item::Struct(item) if is_public(&item.vis) => push(
+                ItemKind::Type,
+                item.ident.to_string(),
+                signature(item, &item.attrs),
+            ),
+            syn::Item::Union(item) if is_public(&item.vis) => push(
+                ItemKind::Type,
+                item.ident.to_string(),
+                signature(item, &item.attrs),
+            ),
+            syn::Item::Enum(item) if is_public(&item.vis) => push(
+                ItemKind::Type,
+                item.ident.to_string(),
+                signature(item, &item.attrs),
+            ),
+            syn::Item::Type(item) if is_public(&item.vis) => push(
+                ItemKind::Type,
+                item.ident.to_string(),
+                signature(item, &item.attrs),
+            ),
+            syn::Item::ForeignMod(foreign_mod) => {
+                for foreign_item in &foreign_mod.items {
+                    match foreign_item {
+                        syn::ForeignItem::Fn(item) if is_public(&item.vis) => push(
+                            ItemKind::Function,
+                            item.sig.ident.to_string(),
+                            signature(item, &item.attrs),
+                        ),
+                        syn::ForeignItem::Static(item) if is_public(&item.vis) => push(
+                            ItemKind::Static,
+                            item.ident.to_string(),
+                            signature(item, &item.attrs),
+                        ),
+                        _ => {}
+                    }
+                }
+            }
+            syn::Item::Mod(item) if is_public(&item.vis) => {
+                if let Some((_, items)) = &item.content {
+                    inline_modules.push((format!("{module}::{}", item.ident), items));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (module, items) in inline_modules {
+        collect_items(&module, items, listing);
+    }
+}
+
+/// Returns `true` if `vis` is `pub`
+const fn is_public(vis: &syn::Visibility) -> bool {
+    matches!(vis, syn::Visibility::Public(_))
+}
+
+/// Returns the declaration of `item`, without the doc comments in `attrs`
+fn signature(item: &impl ToTokens, attrs: &[syn::Attribute]) -> String {
+    let tokens = item.to_token_stream().to_string();
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .fold(tokens, |tokens, attr| {
+            tokens.replacen(&attr.to_token_stream().to_string(), "", 1)
+        })
+        .trim()
+        .to_string()
+}
+
+/// Returns the features of the package of the build script, from the
+/// `CARGO_FEATURE_*` environment variables that Cargo sets
+fn enabled_features() -> Vec<String> {
+    let mut features = env::vars()
+        .filter_map(|(name, _)| {
+            name.strip_prefix(CARGO_FEATURE_ENV_VAR_PREFIX)
+                .map(|feature| feature.to_ascii_lowercase().replace('_', "-"))
+        })
+        .collect::<Vec<_>>();
+    features.sort();
+    features
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASELINE_BINDINGS: &str = r#"
+        /// Maximum length of a path
+        pub const MAX_PATH: u32 = 260;
+        pub type NTSTATUS = i32;
+        #[repr(C)]
+        pub struct _UNICODE_STRING {
+            pub Length: u16,
+            pub Buffer: *mut u16,
+        }
+        extern "C" {
+            /// Frees a pool allocation
+            pub fn ExFreePool(P: *mut core::ffi::c_void);
+            pub static mut KeNumberProcessors: u8;
+        }
+        impl Default for _UNICODE_STRING {
+            fn default() -> Self {
+                unsafe { core::mem::zeroed() }
+            }
+        }
+    "#;
+
+    fn listing(source: &str) -> SymbolSurface {
+        let mut symbol_surface = SymbolSurface::default();
+        symbol_surface
+            .add_bindings("ntddk", source)
+            .expect("bindings should be valid Rust");
+        symbol_surface
+    }
+
+    #[test]
+    fn listing_contains_public_items_without_doc_comments() {
+        let symbol_surface = listing(BASELINE_BINDINGS);
+
+        assert_eq!(
+            symbol_surface
+                .items
+                .iter()
+                .map(|item| (item.kind, item.name.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (ItemKind::Constant, "MAX_PATH"),
+                (ItemKind::Function, "ExFreePool"),
+                (ItemKind::Static, "KeNumberProcessors"),
+                (ItemKind::Type, "NTSTATUS"),
+                (ItemKind::Type, "_UNICODE_STRING"),
+            ]
+        );
+        assert_eq!(
+            symbol_surface.items[0].signature,
+            "pub const MAX_PATH : u32 = 260 ;"
+        );
+    }
+
+    #[test]
+    fn compare_reports_added_removed_and_changed_items() {
+        let baseline = listing(BASELINE_BINDINGS);
+        let current = listing(
+            r#"
+            /// Maximum length of a path, in characters
+            pub const MAX_PATH: u32 = 260;
+            pub type NTSTATUS = u32;
+            #[repr(C)]
+            pub struct _UNICODE_STRING {
+                pub Length: u16,
+                pub Buffer: *mut u16,
+            }
+            extern "C" {
+                pub fn ExFreePool(P: *mut core::ffi::c_void);
+                pub fn ExFreePool2(P: *mut core::ffi::c_void, Tag: u32);
+            }
+            "#,
+        );
+
+        let diff = compare(&baseline, &current);
+
+        assert!(diff.is_breaking());
+        assert_eq!(
+            diff.to_string(),
+            "- static ntddk::KeNumberProcessors
+~ type ntddk::NTSTATUS
+    - pub type NTSTATUS = i32 ;
+    + pub type NTSTATUS = u32 ;
++ function ntddk::ExFreePool2
+"
+        );
+    }
+}
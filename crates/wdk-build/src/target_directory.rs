@@ -0,0 +1,231 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Resolution of the Cargo target directory of a build
+//!
+//! Cargo places the artifacts of a build in its target directory, which is
+//! `target` in the workspace root by default, but can be moved with the
+//! `--target-dir` argument, the `CARGO_TARGET_DIR` environment variable or the
+//! `build.target-dir` setting of a `.cargo/config.toml`.
+//! [`resolve_target_directory`] applies all of these the same way Cargo does,
+//! so that the packaging tasks find the artifacts of the build wherever they
+//! are.
+//!
+//! Build scripts find the top-level `Cargo.toml` of the build from the
+//! location of `OUT_DIR` in the target directory. When the target directory
+//! is outside of the workspace, [`try_find_top_level_cargo_manifest`] searches
+//! the ancestors of the package being built (and the working directories of
+//! cargo-make) for the workspace whose target directory contains `OUT_DIR`.
+
+use std::{
+    env,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use cargo_metadata::MetadataCommand;
+use thiserror::Error;
+
+/// Environment variables containing the directories that a build may have
+/// been started from by cargo-make
+const CARGO_MAKE_WORKING_DIRECTORY_ENV_VARS: [&str; 2] = [
+    "CARGO_MAKE_WORKING_DIRECTORY",
+    "CARGO_MAKE_WORKSPACE_WORKING_DIRECTORY",
+];
+
+/// Errors that could result from resolving the target directory of a build
+#[derive(Debug, Error)]
+pub enum TargetDirectoryError {
+    /// Error returned when `cargo metadata` fails for a manifest
+    #[error("failed to read the target directory of {manifest_path} from cargo metadata")]
+    CargoMetadataError {
+        /// Path of the manifest that `cargo metadata` was run for
+        manifest_path: PathBuf,
+        /// [`cargo_metadata::Error`] that caused `cargo metadata` to fail
+        #[source]
+        error_source: cargo_metadata::Error,
+    },
+
+    /// Error returned when no workspace whose target directory contains
+    /// `OUT_DIR` is found
+    #[error(transparent)]
+    TopLevelManifestNotFound(#[from] TopLevelManifestNotFoundError),
+}
+
+/// Error returned when the top-level `Cargo.toml` of a build cannot be found
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub struct TopLevelManifestNotFoundError {
+    /// `OUT_DIR` of the build script
+    pub out_dir: PathBuf,
+    /// Manifests that were searched, along with their target directory, or
+    /// `None` if it could not be read
+    pub searched_manifests: Vec<(PathBuf, Option<PathBuf>)>,
+}
+
+impl fmt::Display for TopLevelManifestNotFoundError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "no Cargo.lock exists in the ancestors of OUT_DIR ({}), and no workspace whose target \
+             directory contains it was found",
+            self.out_dir.display()
+        )?;
+        writeln!(f, "Manifests searched:")?;
+        for (manifest_path, target_directory) in &self.searched_manifests {
+            match target_directory {
+                Some(target_directory) => writeln!(
+                    f,
+                    "  {} (target directory: {})",
+                    manifest_path.display(),
+                    target_directory.display()
+                )?,
+                None => writeln!(
+                    f,
+                    "  {} (target directory could not be read)",
+                    manifest_path.display()
+                )?,
+            }
+        }
+        write!(
+            f,
+            "Set the target directory with the CARGO_TARGET_DIR environment variable or \
+             build.target-dir in .cargo/config.toml instead of --target-dir, so that it is \
+             visible to build scripts"
+        )
+    }
+}
+
+/// Resolves the target directory of a build of the workspace of
+/// `manifest_path`.
+///
+/// `target_dir_arg` is the value of the `--target-dir` argument, if any, which
+/// is resolved relative to `working_directory` like Cargo does. Otherwise, the
+/// target directory reported by `cargo metadata` is returned, which accounts
+/// for `CARGO_TARGET_DIR` and `build.target-dir`.
+///
+/// # Errors
+///
+/// This function returns a [`TargetDirectoryError::CargoMetadataError`] if
+/// `target_dir_arg` is `None` and `cargo metadata` fails
+pub fn resolve_target_directory(
+    manifest_path: &Path,
+    target_dir_arg: Option<&Path>,
+    working_directory: &Path,
+) -> Result<PathBuf, TargetDirectoryError> {
+    if let Some(target_dir) = target_dir_arg {
+        return Ok(working_directory.join(target_dir));
+    }
+
+    let cargo_metadata = MetadataCommand::new()
+        .manifest_path(manifest_path)
+        .no_deps()
+        .exec()
+        .map_err(|error_source| TargetDirectoryError::CargoMetadataError {
+            manifest_path: manifest_path.to_path_buf(),
+            error_source,
+        })?;
+    Ok(cargo_metadata.target_directory.into_std_path_buf())
+}
+
+/// Finds the path of the top-level Cargo manifest of the currently executing
+/// Cargo subcommand, like [`crate::find_top_level_cargo_manifest`].
+///
+/// The manifest is searched for in the ancestors of `OUT_DIR` first. When the
+/// target directory is outside of the workspace, the manifests next to a
+/// `Cargo.lock` in the ancestors of the package being built, and of the
+/// working directories of cargo-make, are searched for one whose target
+/// directory contains `OUT_DIR`. This function only works when called from a
+/// `build.rs` file.
+///
+/// # Errors
+///
+/// This function returns a [`TargetDirectoryError::TopLevelManifestNotFound`]
+/// if none of the searched manifests is the top-level manifest
+///
+/// # Panics
+///
+/// Panics if this function was called outside of a `build.rs` file
+pub fn try_find_top_level_cargo_manifest() -> Result<PathBuf, TargetDirectoryError> {
+    let out_dir =
+        PathBuf::from(env::var("OUT_DIR").expect(
+            "Cargo should have set the OUT_DIR environment variable when executing build.rs",
+        ));
+
+    if let Some(directory) = out_dir
+        .ancestors()
+        .find(|path| path.join("Cargo.lock").exists())
+    {
+        return Ok(directory.join("Cargo.toml"));
+    }
+
+    let search_roots = env::var_os("CARGO_MANIFEST_DIR").into_iter().chain(
+        CARGO_MAKE_WORKING_DIRECTORY_ENV_VARS
+            .iter()
+            .filter_map(env::var_os),
+    );
+    let mut searched_manifests: Vec<(PathBuf, Option<PathBuf>)> = Vec::new();
+    for search_root in search_roots {
+        for directory in Path::new(&search_root).ancestors() {
+            let manifest_path = directory.join("Cargo.toml");
+            if !directory.join("Cargo.lock").exists()
+                || searched_manifests
+                    .iter()
+                    .any(|(searched_manifest, _)| *searched_manifest == manifest_path)
+            {
+                continue;
+            }
+
+            let target_directory = resolve_target_directory(&manifest_path, None, directory).ok();
+            if target_directory
+                .as_deref()
+                .is_some_and(|target_directory| is_within(&out_dir, target_directory))
+            {
+                return Ok(manifest_path);
+            }
+            searched_manifests.push((manifest_path, target_directory));
+        }
+    }
+
+    Err(TopLevelManifestNotFoundError {
+        out_dir,
+        searched_manifests,
+    }
+    .into())
+}
+
+/// Returns `true` if `path` is `directory` or one of its descendants,
+/// comparing canonical paths when they exist
+fn is_within(path: &Path, directory: &Path) -> bool {
+    let canonicalize = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    canonicalize(path).starts_with(canonicalize(directory))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_dir_arg_is_resolved_against_working_directory() {
+        let working_directory = Path::new("/workspace/driver");
+        assert_eq!(
+            resolve_target_directory(
+                Path::new("/workspace/Cargo.toml"),
+                Some(Path::new("../out")),
+                working_directory,
+            )
+            .unwrap(),
+            working_directory.join("../out")
+        );
+
+        let absolute_target_dir = env::temp_dir().join("out");
+        assert_eq!(
+            resolve_target_directory(
+                Path::new("/workspace/Cargo.toml"),
+                Some(&absolute_target_dir),
+                working_directory,
+            )
+            .unwrap(),
+            absolute_target_dir
+        );
+    }
+}
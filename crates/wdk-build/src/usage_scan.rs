@@ -0,0 +1,229 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Scanning of Rust source code for identifiers that may refer to WDK APIs
+//!
+//! This is used to narrow the bindings generated by `wdk-sys` down to the
+//! items that are actually referenced by the crates in the build graph that
+//! depend on it. Since the scan is purely lexical (every identifier in every
+//! source file is collected, including identifiers in macro invocations), the
+//! resulting set of identifiers is a superset of the WDK APIs used.
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use cargo_metadata::MetadataCommand;
+use proc_macro2::{TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::ext::IdentExt;
+use thiserror::Error;
+
+use crate::find_top_level_cargo_manifest;
+
+const WDK_SYS_PACKAGE_NAME: &str = "wdk-sys";
+
+/// Errors that could result from scanning source code for referenced
+/// identifiers
+#[derive(Debug, Error)]
+pub enum UsageScanError {
+    /// Error returned when `cargo_metadata` execution or parsing fails
+    #[error(transparent)]
+    CargoMetadataError(#[from] cargo_metadata::Error),
+
+    /// Error returned when a source file or directory fails to be read
+    #[error("failed to read {path}")]
+    IoError {
+        /// Path of the file or directory that failed to be read
+        path: PathBuf,
+        /// [`std::io::Error`] that caused the read to fail
+        #[source]
+        error_source: std::io::Error,
+    },
+
+    /// Error returned when a source file fails to be parsed
+    #[error("failed to parse {path}")]
+    ParseError {
+        /// Path of the file that failed to be parsed
+        path: PathBuf,
+        /// [`syn::Error`] that caused the parsing to fail
+        #[source]
+        error_source: syn::Error,
+    },
+}
+
+/// Returns all the identifiers referenced in the sources of `wdk-sys` and of
+/// every package in the build graph that directly depends on `wdk-sys`.
+///
+/// This also emits `cargo::rerun-if-changed` directives for every scanned
+/// source file, so that the scan is redone when any of the sources change. This
+/// function only works when called from a `build.rs` file.
+///
+/// # Errors
+///
+/// This function will return an error if:
+/// * the execution of `cargo metadata` fails
+/// * any of the source files fail to be read or parsed
+///
+/// # Panics
+///
+/// Panics if this function was called outside of a `build.rs` file
+pub fn scan_wdk_sys_dependents() -> Result<BTreeSet<String>, UsageScanError> {
+    let cargo_metadata = MetadataCommand::new()
+        .manifest_path(find_top_level_cargo_manifest())
+        .exec()?;
+
+    let mut identifiers = BTreeSet::new();
+    for package in cargo_metadata.packages.iter().filter(|package| {
+        package.name == WDK_SYS_PACKAGE_NAME
+            || package
+                .dependencies
+                .iter()
+                .any(|dependency| dependency.name == WDK_SYS_PACKAGE_NAME)
+    }) {
+        let package_directory = package
+            .manifest_path
+            .parent()
+            .expect("manifest path should always have a parent directory");
+
+        scan_directory(package_directory.as_std_path(), &mut identifiers)?;
+    }
+
+    Ok(identifiers)
+}
+
+/// Inserts all the identifiers referenced in the Rust source files in
+/// `directory` (recursively) into `identifiers`.
+///
+/// Hidden directories and `target` directories are skipped. A
+/// `cargo::rerun-if-changed` directive is emitted for every scanned file.
+///
+/// # Errors
+///
+/// This function will return an error if any of the source files fail to be
+/// read or parsed
+pub fn scan_directory(
+    directory: &Path,
+    identifiers: &mut BTreeSet<String>,
+) -> Result<(), UsageScanError> {
+    let read_dir_error = |error_source| UsageScanError::IoError {
+        path: directory.to_path_buf(),
+        error_source,
+    };
+
+    for entry in std::fs::read_dir(directory).map_err(read_dir_error)? {
+        let path = entry.map_err(read_dir_error)?.path();
+
+        if path.is_dir() {
+            let is_skipped_directory = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_none_or(|name| name == "target" || name.starts_with('.'));
+            if !is_skipped_directory {
+                scan_directory(&path, identifiers)?;
+            }
+        } else if path.extension().is_some_and(|extension| extension == "rs") {
+            crate::cargo_directives::emit_cargo_directive!(
+                "source files scanned for WDK API usage",
+                "rerun-if-changed={}",
+                path.display()
+            );
+            let source =
+                std::fs::read_to_string(&path).map_err(|error_source| UsageScanError::IoError {
+                    path: path.clone(),
+                    error_source,
+                })?;
+            identifiers.extend(scan_source(&source).map_err(|error_source| {
+                UsageScanError::ParseError {
+                    path: path.clone(),
+                    error_source,
+                }
+            })?);
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns all the identifiers referenced in a Rust source file
+///
+/// # Errors
+///
+/// This function will return an error if `source` is not a valid Rust source
+/// file
+pub fn scan_source(source: &str) -> syn::Result<BTreeSet<String>> {
+    let mut identifiers = BTreeSet::new();
+    collect_identifiers(
+        syn::parse_file(source)?.into_token_stream(),
+        &mut identifiers,
+    );
+    Ok(identifiers)
+}
+
+fn collect_identifiers(token_stream: TokenStream, identifiers: &mut BTreeSet<String>) {
+    for token_tree in token_stream {
+        match token_tree {
+            TokenTree::Ident(ident) => {
+                identifiers.insert(ident.unraw().to_string());
+            }
+            // Recurse into groups so that identifiers in macro invocations (ex.
+            // `call_unsafe_wdf_function_binding!(WdfDriverCreate, ...)`) are also collected
+            TokenTree::Group(group) => collect_identifiers(group.stream(), identifiers),
+            TokenTree::Punct(_) | TokenTree::Literal(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_source_collects_identifiers() {
+        let identifiers = scan_source(
+            r#"
+            use wdk_sys::{NTSTATUS, STATUS_SUCCESS};
+
+            fn driver_entry() -> NTSTATUS {
+                unsafe { wdk_sys::ntddk::DbgPrint(c"Hello".as_ptr()) };
+                STATUS_SUCCESS
+            }
+            "#,
+        )
+        .unwrap();
+
+        for identifier in ["wdk_sys", "NTSTATUS", "STATUS_SUCCESS", "ntddk", "DbgPrint"] {
+            assert!(identifiers.contains(identifier), "{identifier} not found");
+        }
+    }
+
+    #[test]
+    fn scan_source_collects_identifiers_in_macro_invocations() {
+        let identifiers = scan_source(
+            r"
+            fn create_driver() {
+                unsafe {
+                    call_unsafe_wdf_function_binding!(WdfDriverCreate, driver, WDF_NO_HANDLE);
+                }
+            }
+            ",
+        )
+        .unwrap();
+
+        assert!(identifiers.contains("WdfDriverCreate"));
+        assert!(identifiers.contains("WDF_NO_HANDLE"));
+    }
+
+    #[test]
+    fn scan_source_unraws_identifiers() {
+        let identifiers = scan_source("fn r#type() {}").unwrap();
+
+        assert!(identifiers.contains("type"));
+    }
+
+    #[test]
+    fn scan_source_fails_on_invalid_source() {
+        assert!(scan_source("fn {").is_err());
+    }
+}
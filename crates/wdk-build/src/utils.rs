@@ -24,7 +24,12 @@
     },
 };
 
-use crate::{ConfigError, CpuArchitecture};
+use crate::{
+    diagnostics::record_diagnostic,
+    wdk_content_root::{Candidate, WdkContentRootDetectionError},
+    ConfigError,
+    CpuArchitecture,
+};
 
 /// Errors that may occur when stripping the extended path prefix from a path
 #[derive(Debug, Error, PartialEq, Eq)]
@@ -81,73 +86,165 @@ fn strip_extended_length_path_prefix(&self) -> Result<PathBuf, Self::Error> {
     }
 }
 
+/// Name of the diagnostics stage of [`try_detect_wdk_content_root`]
+const WDK_CONTENT_ROOT_DETECTION_STAGE: &str = "WDK content root detection";
+
+/// Environment variables inspected by [`try_detect_wdk_content_root`]
+const WDK_CONTENT_ROOT_ENV_VARS: [&str; 3] =
+    ["WDKContentRoot", "MicrosoftKitRoot", "WDKKitVersion"];
+
 /// Detect `WDKContentRoot` Directory. Logic is based off of Toolset.props in
 /// NI(22H2) WDK
 #[must_use]
 pub fn detect_wdk_content_root() -> Option<PathBuf> {
+    try_detect_wdk_content_root().ok()
+}
+
+/// Detect `WDKContentRoot` Directory. Logic is based off of Toolset.props in
+/// NI(22H2) WDK
+///
+/// # Errors
+///
+/// Returns a [`WdkContentRootDetectionError`] listing the locations searched
+/// and the environment variables inspected if no valid `WDKContentRoot`
+/// directory is found
+pub fn try_detect_wdk_content_root() -> Result<PathBuf, WdkContentRootDetectionError> {
+    let mut error = WdkContentRootDetectionError {
+        candidates: Vec::new(),
+        environment_variables: WDK_CONTENT_ROOT_ENV_VARS
+            .iter()
+            .map(|variable| ((*variable).to_string(), env::var(variable).ok()))
+            .collect(),
+    };
+
     // If WDKContentRoot is present in environment(ex. running in an eWDK prompt),
     // use it
     if let Ok(wdk_content_root) = env::var("WDKContentRoot") {
         let path = Path::new(wdk_content_root.as_str());
+        record_diagnostic!(
+            WDK_CONTENT_ROOT_DETECTION_STAGE,
+            ("considered WDKContentRoot environment variable"),
+            path = path.display(),
+            is_dir = path.is_dir(),
+        );
         if path.is_dir() {
-            return Some(path.to_path_buf());
+            return Ok(path.to_path_buf());
         }
         eprintln!(
             "WDKContentRoot was detected to be {}, but does not exist or is not a valid directory.",
             path.display()
         );
+        error.candidates.push(Candidate {
+            source: "WDKContentRoot environment variable".to_string(),
+            path: Some(path.to_path_buf()),
+            reason: "does not exist or is not a directory".to_string(),
+        });
     }
 
     // If MicrosoftKitRoot environment variable is set, use it to set WDKContentRoot
-    if let Ok(microsoft_kit_root) = env::var("MicrosoftKitRoot") {
-        let path = Path::new(microsoft_kit_root.as_str());
-
-        if !path.is_absolute() {
-            eprintln!(
-                "MicrosoftKitRoot({}) was found in environment, but is not an absolute path.",
-                path.display()
-            );
-        } else if !path.is_dir() {
-            eprintln!(
-                "MicrosoftKitRoot({}) was found in environment, but does not exist or is not a \
-                 valid directory.",
-                path.display()
-            );
-        } else {
-            let wdk_kit_version =
-                env::var("WDKKitVersion").map_or("10.0".to_string(), |version| version);
-            let path = path.join("Windows Kits").join(wdk_kit_version);
-            if path.is_dir() {
-                return Some(path);
-            }
-            eprintln!(
-                "WDKContentRoot was detected to be {}, but does not exist or is not a valid \
-                 directory.",
-                path.display()
-            );
-        }
+    if let Some(path) = detect_wdk_content_root_from_microsoft_kit_root(&mut error.candidates) {
+        return Ok(path);
     }
 
     // Check HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows Kits\Installed
-    // Roots@KitsRoot10 registry key
-    if let Some(path) = read_registry_key_string_value(
-        HKEY_LOCAL_MACHINE,
-        s!(r"SOFTWARE\Microsoft\Windows Kits\Installed Roots"),
-        s!(r"KitsRoot10"),
-    ) {
-        return Some(Path::new(path.as_str()).to_path_buf());
+    // Roots@KitsRoot10 registry key, then its
+    // HKEY_LOCAL_MACHINE\SOFTWARE\Wow6432Node\Microsoft\Windows Kits\Installed
+    // Roots@KitsRoot10 counterpart
+    for (key, sub_key) in [
+        (
+            r"HKLM\SOFTWARE\Microsoft\Windows Kits\Installed Roots",
+            s!(r"SOFTWARE\Microsoft\Windows Kits\Installed Roots"),
+        ),
+        (
+            r"HKLM\SOFTWARE\Wow6432Node\Microsoft\Windows Kits\Installed Roots",
+            s!(r"SOFTWARE\Wow6432Node\Microsoft\Windows Kits\Installed Roots"),
+        ),
+    ] {
+        let kits_root_10 =
+            read_registry_key_string_value(HKEY_LOCAL_MACHINE, sub_key, s!(r"KitsRoot10"));
+        record_diagnostic!(
+            WDK_CONTENT_ROOT_DETECTION_STAGE,
+            ("considered KitsRoot10 registry value"),
+            key = key,
+            value = kits_root_10.as_deref().unwrap_or("<not found>"),
+        );
+        if let Some(path) = kits_root_10 {
+            return Ok(Path::new(path.as_str()).to_path_buf());
+        }
+        error.candidates.push(Candidate {
+            source: format!(r"KitsRoot10 value of {key}"),
+            path: None,
+            reason: "not found".to_string(),
+        });
     }
 
-    // Check HKEY_LOCAL_MACHINE\SOFTWARE\Wow6432Node\Microsoft\Windows
-    // Kits\Installed Roots@KitsRoot10 registry key
-    if let Some(path) = read_registry_key_string_value(
-        HKEY_LOCAL_MACHINE,
-        s!(r"SOFTWARE\Wow6432Node\Microsoft\Windows Kits\Installed Roots"),
-        s!(r"KitsRoot10"),
-    ) {
-        return Some(Path::new(path.as_str()).to_path_buf());
-    }
+    record_diagnostic!(
+        WDK_CONTENT_ROOT_DETECTION_STAGE,
+        ("no WDK content root candidate was found")
+    );
+    Err(error)
+}
 
+/// Detects the `WDKContentRoot` directory from the `MicrosoftKitRoot` and
+/// `WDKKitVersion` environment variables, adding the rejected locations to
+/// `candidates`
+fn detect_wdk_content_root_from_microsoft_kit_root(
+    candidates: &mut Vec<Candidate>,
+) -> Option<PathBuf> {
+    let microsoft_kit_root = env::var("MicrosoftKitRoot").ok()?;
+    let path = Path::new(microsoft_kit_root.as_str());
+    record_diagnostic!(
+        WDK_CONTENT_ROOT_DETECTION_STAGE,
+        ("considered MicrosoftKitRoot environment variable"),
+        path = path.display(),
+        is_absolute = path.is_absolute(),
+        is_dir = path.is_dir(),
+    );
+
+    if !path.is_absolute() {
+        eprintln!(
+            "MicrosoftKitRoot({}) was found in environment, but is not an absolute path.",
+            path.display()
+        );
+        candidates.push(Candidate {
+            source: "MicrosoftKitRoot environment variable".to_string(),
+            path: Some(path.to_path_buf()),
+            reason: "is not an absolute path".to_string(),
+        });
+    } else if !path.is_dir() {
+        eprintln!(
+            "MicrosoftKitRoot({}) was found in environment, but does not exist or is not a valid \
+             directory.",
+            path.display()
+        );
+        candidates.push(Candidate {
+            source: "MicrosoftKitRoot environment variable".to_string(),
+            path: Some(path.to_path_buf()),
+            reason: "does not exist or is not a directory".to_string(),
+        });
+    } else {
+        let wdk_kit_version =
+            env::var("WDKKitVersion").map_or("10.0".to_string(), |version| version);
+        let path = path.join("Windows Kits").join(wdk_kit_version);
+        record_diagnostic!(
+            WDK_CONTENT_ROOT_DETECTION_STAGE,
+            ("considered Windows Kits directory of MicrosoftKitRoot"),
+            path = path.display(),
+            is_dir = path.is_dir(),
+        );
+        if path.is_dir() {
+            return Some(path);
+        }
+        eprintln!(
+            "WDKContentRoot was detected to be {}, but does not exist or is not a valid directory.",
+            path.display()
+        );
+        candidates.push(Candidate {
+            source: "Windows Kits directory of MicrosoftKitRoot".to_string(),
+            path: Some(path),
+            reason: "does not exist or is not a directory".to_string(),
+        });
+    }
     None
 }
 
@@ -163,7 +260,7 @@ pub fn detect_wdk_content_root() -> Option<PathBuf> {
 ///
 /// Panics if the path provided is not valid Unicode.
 pub fn get_latest_windows_sdk_version(path_to_search: &Path) -> Result<String, ConfigError> {
-    Ok(path_to_search
+    let sdk_version_directories = path_to_search
         .read_dir()?
         .filter_map(std::result::Result::ok)
         .map(|valid_directory_entry| valid_directory_entry.path())
@@ -175,7 +272,24 @@ pub fn get_latest_windows_sdk_version(path_to_search: &Path) -> Result<String, C
                         .is_some_and(|directory_name| directory_name.starts_with("10."))
                 })
         })
-        .max() // Get the latest SDK folder in case there are multiple installed
+        .collect::<Vec<_>>();
+    // Get the latest SDK folder in case there are multiple installed
+    let latest_sdk_version_directory = sdk_version_directories.iter().max();
+    record_diagnostic!(
+        "Windows SDK version selection",
+        ("searched for Windows SDK versions"),
+        directory = path_to_search.display(),
+        candidates = sdk_version_directories
+            .iter()
+            .filter_map(|path| path.file_name()?.to_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+        selected = latest_sdk_version_directory
+            .and_then(|path| path.file_name()?.to_str())
+            .unwrap_or("<none>"),
+    );
+
+    Ok(latest_sdk_version_directory
         .ok_or(ConfigError::DirectoryNotFound {
             directory: format!(
                 "Windows SDK Directory in {}",
@@ -0,0 +1,400 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! `VERSIONINFO` resource declared by the `version-info` key in the
+//! `metadata.wdk.package` section of a driver's `Cargo.toml`
+//!
+//! Declaring it embeds a `VERSIONINFO` resource into the driver binary, whose
+//! file and product versions are the version of the package. `cargo make` also
+//! stamps the same version into the `DriverVer` directive of the driver's INF,
+//! so that bumping the package version is enough to keep all of them
+//! consistent:
+//!
+//! ```toml
+//! [package.metadata.wdk.package.version-info]
+//! company-name = "Contoso"
+//! legal-copyright = "Copyright (c) Contoso. All rights reserved."
+//! ```
+//!
+//! Windows versions have four components, so the package version `1.2.3`
+//! becomes the driver version `1.2.3.0`. Pre-release and build metadata of the
+//! package version are ignored.
+
+use std::{
+    env,
+    fmt::{self, Write},
+    path::PathBuf,
+    process::{Command, ExitStatus},
+    str::FromStr,
+};
+
+use cargo_metadata::Package;
+use serde::Deserialize;
+use thiserror::Error;
+use tracing::trace;
+
+use crate::{
+    additional_binaries::PACKAGE_METADATA_KEY,
+    cargo_directives::emit_cargo_directive,
+    package::find_tool,
+    ConfigError,
+    DriverConfig,
+};
+
+const VERSION_INFO_METADATA_KEY: &str = "version-info";
+
+/// Errors that could result from generating the `VERSIONINFO` resource of a
+/// driver
+#[derive(Debug, Error)]
+pub enum VersionInfoError {
+    /// Error returned when the `metadata.wdk.package.version-info` section of
+    /// a package fails to be deserialized
+    #[error("failed to deserialize metadata.wdk.package.version-info for {package_name} package")]
+    MetadataDeserialization {
+        /// Name of the package whose metadata failed to be deserialized
+        package_name: String,
+        /// [`serde_json::Error`] that caused the deserialization to fail
+        #[source]
+        error_source: serde_json::Error,
+    },
+
+    /// Error returned when a package version cannot be represented as a
+    /// driver version
+    #[error(
+        "version {version} cannot be used as a driver version. Driver versions must have numeric \
+         major, minor and patch components that are at most 65535"
+    )]
+    InvalidVersion {
+        /// The package version
+        version: String,
+    },
+
+    /// Error returned when the resource compiler fails to compile the
+    /// `VERSIONINFO` resource
+    #[error("rc failed to compile the VERSIONINFO resource with {exit_status}")]
+    ResourceCompilerFailed {
+        /// Exit status of `rc`
+        exit_status: ExitStatus,
+    },
+}
+
+/// Metadata specified in the `metadata.wdk.package.version-info` section of a
+/// driver's `Cargo.toml`. Each field sets the corresponding string of the
+/// `VERSIONINFO` resource.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct VersionInfoConfig {
+    /// `CompanyName` of the driver. Omitted when `None`.
+    #[serde(default)]
+    pub company_name: Option<String>,
+    /// `FileDescription` of the driver. Defaults to the description of the
+    /// package, or its name if it has no description.
+    #[serde(default)]
+    pub file_description: Option<String>,
+    /// `ProductName` of the driver. Defaults to the name of the package.
+    #[serde(default)]
+    pub product_name: Option<String>,
+    /// `LegalCopyright` of the driver. Omitted when `None`.
+    #[serde(default)]
+    pub legal_copyright: Option<String>,
+}
+
+impl VersionInfoConfig {
+    /// Parses the `metadata.wdk.package.version-info` section of `package`.
+    /// Packages without this section do not get a `VERSIONINFO` resource.
+    ///
+    /// # Errors
+    ///
+    /// This function returns a [`VersionInfoError::MetadataDeserialization`]
+    /// if the section is invalid
+    pub fn try_from_package(package: &Package) -> Result<Option<Self>, VersionInfoError> {
+        match &package.metadata["wdk"][PACKAGE_METADATA_KEY][VERSION_INFO_METADATA_KEY] {
+            serde_json::Value::Null => Ok(None),
+            version_info => Self::deserialize(version_info)
+                .map(Some)
+                .map_err(|error_source| VersionInfoError::MetadataDeserialization {
+                    package_name: package.name.clone(),
+                    error_source,
+                }),
+        }
+    }
+
+    /// Returns the contents of a resource script that defines the
+    /// `VERSIONINFO` resource of the binary of `package`, whose driver model
+    /// is `driver_config`
+    ///
+    /// # Errors
+    ///
+    /// This function returns a [`VersionInfoError::InvalidVersion`] if the
+    /// version of `package` cannot be represented as a [`DriverVersion`]
+    pub fn resource_script(
+        &self,
+        package: &Package,
+        driver_config: &DriverConfig,
+    ) -> Result<String, VersionInfoError> {
+        let version = package.version.to_string().parse::<DriverVersion>()?;
+        let binary_name = package.name.replace('-', "_");
+
+        // Numeric values of the `VFT_*` and `VFT2_*` constants of `winver.h`, which
+        // is not available to kernel-mode drivers
+        let (file_type, file_subtype, extension) = match driver_config {
+            // VFT_DRV, VFT2_DRV_SYSTEM
+            DriverConfig::Wdm | DriverConfig::Kmdf(_) => ("0x3L", "0x7L", "sys"),
+            // VFT_DLL, VFT2_UNKNOWN
+            DriverConfig::Umdf(_) => ("0x2L", "0x0L", "dll"),
+        };
+
+        let strings = [
+            ("CompanyName", self.company_name.clone()),
+            (
+                "FileDescription",
+                Some(self.file_description.clone().unwrap_or_else(|| {
+                    package
+                        .description
+                        .clone()
+                        .unwrap_or_else(|| package.name.clone())
+                })),
+            ),
+            ("FileVersion", Some(version.to_string())),
+            ("InternalName", Some(binary_name.clone())),
+            ("LegalCopyright", self.legal_copyright.clone()),
+            (
+                "OriginalFilename",
+                Some(format!("{binary_name}.{extension}")),
+            ),
+            (
+                "ProductName",
+                Some(
+                    self.product_name
+                        .clone()
+                        .unwrap_or_else(|| package.name.clone()),
+                ),
+            ),
+            ("ProductVersion", Some(version.to_string())),
+        ];
+
+        // Writing to a `String` cannot fail
+        let mut resource_script = String::new();
+        let _ = writeln!(resource_script, "1 VERSIONINFO");
+        let _ = writeln!(resource_script, "FILEVERSION {}", version.as_rc_version());
+        let _ = writeln!(
+            resource_script,
+            "PRODUCTVERSION {}",
+            version.as_rc_version()
+        );
+        // VS_FFI_FILEFLAGSMASK
+        let _ = writeln!(resource_script, "FILEFLAGSMASK 0x3FL");
+        let _ = writeln!(resource_script, "FILEFLAGS 0x0L");
+        // VOS_NT_WINDOWS32
+        let _ = writeln!(resource_script, "FILEOS 0x40004L");
+        let _ = writeln!(resource_script, "FILETYPE {file_type}");
+        let _ = writeln!(resource_script, "FILESUBTYPE {file_subtype}");
+        let _ = writeln!(resource_script, "BEGIN");
+        let _ = writeln!(resource_script, "    BLOCK \"StringFileInfo\"");
+        let _ = writeln!(resource_script, "    BEGIN");
+        // U.S. English, Unicode
+        let _ = writeln!(resource_script, "        BLOCK \"040904B0\"");
+        let _ = writeln!(resource_script, "        BEGIN");
+        for (name, value) in strings {
+            if let Some(value) = value {
+                let _ = writeln!(
+                    resource_script,
+                    "            VALUE \"{name}\", \"{}\"",
+                    value.replace('"', "\"\"")
+                );
+            }
+        }
+        let _ = writeln!(resource_script, "        END");
+        let _ = writeln!(resource_script, "    END");
+        let _ = writeln!(resource_script, "    BLOCK \"VarFileInfo\"");
+        let _ = writeln!(resource_script, "    BEGIN");
+        let _ = writeln!(
+            resource_script,
+            "        VALUE \"Translation\", 0x409, 1200"
+        );
+        let _ = writeln!(resource_script, "    END");
+        let _ = writeln!(resource_script, "END");
+        Ok(resource_script)
+    }
+}
+
+/// A four-component Windows version (ex. `1.2.3.0`), as used by the
+/// `VERSIONINFO` resource and the `DriverVer` directive of INF files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DriverVersion {
+    /// Major version
+    pub major: u16,
+    /// Minor version
+    pub minor: u16,
+    /// Patch version
+    pub patch: u16,
+    /// Build number
+    pub build: u16,
+}
+
+impl DriverVersion {
+    /// Returns the version formatted for the `FILEVERSION` and
+    /// `PRODUCTVERSION` statements of a resource script (ex. `1,2,3,0`)
+    #[must_use]
+    pub fn as_rc_version(&self) -> String {
+        format!(
+            "{},{},{},{}",
+            self.major, self.minor, self.patch, self.build
+        )
+    }
+}
+
+impl FromStr for DriverVersion {
+    type Err = VersionInfoError;
+
+    /// Parses a package version (ex. `1.2.3` or `1.2.3-beta.1`) into a
+    /// [`DriverVersion`] with a build number of 0
+    fn from_str(version: &str) -> Result<Self, Self::Err> {
+        let invalid_version = || VersionInfoError::InvalidVersion {
+            version: version.to_string(),
+        };
+
+        let core_version = version
+            .split(['-', '+'])
+            .next()
+            .expect("split should always return at least one item");
+        let components = core_version
+            .split('.')
+            .map(str::parse::<u16>)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid_version())?;
+        let [major, minor, patch] = components[..] else {
+            return Err(invalid_version());
+        };
+
+        Ok(Self {
+            major,
+            minor,
+            patch,
+            build: 0,
+        })
+    }
+}
+
+impl fmt::Display for DriverVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}.{}",
+            self.major, self.minor, self.patch, self.build
+        )
+    }
+}
+
+/// Compiles the `VERSIONINFO` resource declared by `package` in `OUT_DIR`,
+/// and links it into the driver binary. Nothing is done if `package` does not
+/// declare a `metadata.wdk.package.version-info` section.
+pub(crate) fn embed_version_resource(
+    package: &Package,
+    driver_config: &DriverConfig,
+) -> Result<(), ConfigError> {
+    let Some(version_info_config) = VersionInfoConfig::try_from_package(package)? else {
+        return Ok(());
+    };
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR should be set by Cargo"));
+    let resource_script_path = out_dir.join("version-info.rc");
+    let compiled_resource_path = out_dir.join("version-info.res");
+    std::fs::write(
+        &resource_script_path,
+        version_info_config.resource_script(package, driver_config)?,
+    )?;
+
+    let mut command = Command::new(find_tool("rc"));
+    command
+        .arg("/nologo")
+        .arg("/fo")
+        .arg(&compiled_resource_path)
+        .arg(&resource_script_path);
+    trace!(command = ?command);
+
+    let exit_status = command.status()?;
+    if !exit_status.success() {
+        return Err(VersionInfoError::ResourceCompilerFailed { exit_status }.into());
+    }
+
+    emit_cargo_directive!(
+        "metadata.wdk.package.version-info",
+        "rustc-cdylib-link-arg={}",
+        compiled_resource_path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UmdfConfig;
+
+    fn package(version: &str, version_info: &serde_json::Value) -> Package {
+        serde_json::from_value(serde_json::json!({
+            "name": "sample-driver",
+            "version": version,
+            "id": "sample-driver 0.1.0",
+            "dependencies": [],
+            "targets": [],
+            "features": {},
+            "manifest_path": "Cargo.toml",
+            "metadata": { "wdk": { "package": { "version-info": version_info } } },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn driver_version_from_package_version() {
+        assert_eq!(
+            "1.2.3".parse::<DriverVersion>().unwrap().to_string(),
+            "1.2.3.0"
+        );
+        assert_eq!(
+            "1.2.3-beta.1+build.5"
+                .parse::<DriverVersion>()
+                .unwrap()
+                .as_rc_version(),
+            "1,2,3,0"
+        );
+        assert!("1.2.65536".parse::<DriverVersion>().is_err());
+        assert!("1.2".parse::<DriverVersion>().is_err());
+    }
+
+    #[test]
+    fn resource_script_strings() {
+        let package = package(
+            "1.2.3",
+            &serde_json::json!({ "legal-copyright": "Copyright (c) \"Contoso\"" }),
+        );
+        let version_info_config = VersionInfoConfig::try_from_package(&package)
+            .unwrap()
+            .unwrap();
+
+        let resource_script = version_info_config
+            .resource_script(&package, &DriverConfig::Umdf(UmdfConfig::default()))
+            .unwrap();
+        assert!(resource_script.contains("FILEVERSION 1,2,3,0\n"));
+        assert!(resource_script.contains("FILETYPE 0x2L\n"));
+        assert!(resource_script.contains("VALUE \"OriginalFilename\", \"sample_driver.dll\"\n"));
+        assert!(resource_script.contains("VALUE \"ProductVersion\", \"1.2.3.0\"\n"));
+        assert!(resource_script
+            .contains("VALUE \"LegalCopyright\", \"Copyright (c) \"\"Contoso\"\"\"\n"));
+        assert!(!resource_script.contains("CompanyName"));
+    }
+
+    #[test]
+    fn invalid_version_info_metadata() {
+        assert!(VersionInfoConfig::try_from_package(&package(
+            "1.2.3",
+            &serde_json::json!({ "company": "Contoso" })
+        ))
+        .is_err());
+        assert_eq!(
+            VersionInfoConfig::try_from_package(&package("1.2.3", &serde_json::Value::Null))
+                .unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,385 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! WDF coinstaller declared by the `wdf-coinstaller-version` key in the
+//! `metadata.wdk.package` section of a driver's `Cargo.toml`
+//!
+//! Drivers that target OS versions older than the framework version they are
+//! built against must redistribute the WDF coinstaller (ex.
+//! `WdfCoInstaller01011.dll` for KMDF 1.11), which installs the matching
+//! framework during driver installation:
+//!
+//! ```toml
+//! [package.metadata.wdk.package]
+//! wdf-coinstaller-version = "1.11"
+//! ```
+//!
+//! The coinstaller is copied from the `Redist` directory of the WDK into the
+//! driver package. The driver's INF must install it with a `CopyFiles`
+//! directive and register it as `CoInstallers32` in a `CoInstallers` section.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use cargo_metadata::Package;
+use serde::{Deserialize, Deserializer};
+use thiserror::Error;
+
+use crate::{additional_binaries::PACKAGE_METADATA_KEY, inf, DriverConfig};
+
+const WDF_COINSTALLER_VERSION_METADATA_KEY: &str = "wdf-coinstaller-version";
+const COINSTALLERS_SECTION_SUFFIX: &str = ".CoInstallers";
+const ADD_REG_DIRECTIVE_NAME: &str = "AddReg";
+const COINSTALLERS32_VALUE_NAME: &str = "CoInstallers32";
+
+/// Errors that could result from packaging the WDF coinstaller
+#[derive(Debug, Error)]
+pub enum WdfCoInstallerError {
+    /// Error returned when the `metadata.wdk.package.wdf-coinstaller-version`
+    /// key of a package fails to be deserialized
+    #[error(
+        "failed to deserialize metadata.wdk.package.wdf-coinstaller-version for {package_name} \
+         package"
+    )]
+    MetadataDeserialization {
+        /// Name of the package whose metadata failed to be deserialized
+        package_name: String,
+        /// [`serde_json::Error`] that caused the deserialization to fail
+        #[source]
+        error_source: serde_json::Error,
+    },
+
+    /// Error returned when a WDF coinstaller is declared for a WDM driver
+    #[error("WDM drivers do not use WDF, so they cannot be packaged with a WDF coinstaller")]
+    WdmDriver,
+
+    /// Error returned when the coinstaller cannot install the framework
+    /// version that the driver is built against
+    #[error(
+        "WDF coinstaller version {coinstaller_version} cannot install the framework version \
+         {framework_version} targeted by the driver"
+    )]
+    IncompatibleVersion {
+        /// Version of the declared coinstaller
+        coinstaller_version: WdfCoInstallerVersion,
+        /// Framework version targeted by the driver
+        framework_version: WdfCoInstallerVersion,
+    },
+
+    /// Error returned when the coinstaller is not present in the WDK
+    #[error(
+        "{coinstaller_path} does not exist. WDF coinstallers are only redistributed by WDKs that \
+         support targeting the OS versions that require them"
+    )]
+    NotFound {
+        /// Expected path of the coinstaller
+        coinstaller_path: PathBuf,
+    },
+
+    /// Error returned when the coinstaller is not installed by any `CopyFiles`
+    /// directive of the driver's INF
+    #[error(
+        "{file_name} is copied into the driver package, but is not installed by any CopyFiles \
+         directive in {inf_path}"
+    )]
+    MissingCopyFilesEntry {
+        /// File name of the coinstaller
+        file_name: String,
+        /// Path of the INF file
+        inf_path: PathBuf,
+    },
+
+    /// Error returned when the coinstaller is not registered as
+    /// `CoInstallers32` by an `AddReg` directive of a `CoInstallers` section
+    /// of the driver's INF
+    #[error(
+        "{file_name} is not registered as CoInstallers32 by an AddReg directive of a \
+         [<install-section>.CoInstallers] section in {inf_path}"
+    )]
+    MissingCoInstallersRegistration {
+        /// File name of the coinstaller
+        file_name: String,
+        /// Path of the INF file
+        inf_path: PathBuf,
+    },
+}
+
+/// Version of the framework installed by a WDF coinstaller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WdfCoInstallerVersion {
+    /// Major version of the framework (ex. `1` in `1.11`)
+    pub major: u8,
+    /// Minor version of the framework (ex. `11` in `1.11`)
+    pub minor: u8,
+}
+
+impl FromStr for WdfCoInstallerVersion {
+    type Err = String;
+
+    fn from_str(version: &str) -> Result<Self, Self::Err> {
+        let invalid_version =
+            || format!("`{version}` is not a valid WDF version. Expected `<major>.<minor>`");
+        let (major, minor) = version.split_once('.').ok_or_else(invalid_version)?;
+        Ok(Self {
+            major: major.parse().map_err(|_| invalid_version())?,
+            minor: minor.parse().map_err(|_| invalid_version())?,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for WdfCoInstallerVersion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Display for WdfCoInstallerVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+impl WdfCoInstallerVersion {
+    /// Parses the `metadata.wdk.package.wdf-coinstaller-version` key of
+    /// `package`. Packages without this key are not packaged with a
+    /// coinstaller.
+    ///
+    /// # Errors
+    ///
+    /// This function returns a [`WdfCoInstallerError::MetadataDeserialization`]
+    /// if the key is not a valid version
+    pub fn try_from_package(package: &Package) -> Result<Option<Self>, WdfCoInstallerError> {
+        match &package.metadata["wdk"][PACKAGE_METADATA_KEY][WDF_COINSTALLER_VERSION_METADATA_KEY] {
+            serde_json::Value::Null => Ok(None),
+            version => Self::deserialize(version)
+                .map(Some)
+                .map_err(
+                    |error_source| WdfCoInstallerError::MetadataDeserialization {
+                        package_name: package.name.clone(),
+                        error_source,
+                    },
+                ),
+        }
+    }
+
+    /// Returns the file name of the coinstaller of this version for
+    /// `driver_config` (ex. `WdfCoInstaller01011.dll` for KMDF 1.11, or
+    /// `WUDFUpdate_01011.dll` for UMDF 1.11)
+    ///
+    /// # Errors
+    ///
+    /// This function returns:
+    /// - [`WdfCoInstallerError::WdmDriver`] if `driver_config` is WDM
+    /// - [`WdfCoInstallerError::IncompatibleVersion`] if this version has a
+    ///   different major version than the framework targeted by
+    ///   `driver_config`, or an older minor version
+    pub fn file_name(&self, driver_config: &DriverConfig) -> Result<String, WdfCoInstallerError> {
+        let (file_name_prefix, framework_version) = match driver_config {
+            DriverConfig::Wdm => return Err(WdfCoInstallerError::WdmDriver),
+            DriverConfig::Kmdf(kmdf_config) => (
+                "WdfCoInstaller",
+                Self {
+                    major: kmdf_config.kmdf_version_major,
+                    minor: kmdf_config.target_kmdf_version_minor,
+                },
+            ),
+            DriverConfig::Umdf(umdf_config) => (
+                "WUDFUpdate_",
+                Self {
+                    major: umdf_config.umdf_version_major,
+                    minor: umdf_config.target_umdf_version_minor,
+                },
+            ),
+        };
+
+        if self.major != framework_version.major || self.minor < framework_version.minor {
+            return Err(WdfCoInstallerError::IncompatibleVersion {
+                coinstaller_version: *self,
+                framework_version,
+            });
+        }
+
+        Ok(format!(
+            "{file_name_prefix}{:02}{:03}.dll",
+            self.major, self.minor
+        ))
+    }
+}
+
+/// Returns the path of the coinstaller named `file_name` in
+/// `wdf_redist_directory`
+///
+/// # Errors
+///
+/// This function returns a [`WdfCoInstallerError::NotFound`] if the
+/// coinstaller does not exist
+pub fn find_coinstaller(
+    wdf_redist_directory: &Path,
+    file_name: &str,
+) -> Result<PathBuf, WdfCoInstallerError> {
+    let coinstaller_path = wdf_redist_directory.join(file_name);
+    if coinstaller_path.is_file() {
+        Ok(coinstaller_path)
+    } else {
+        Err(WdfCoInstallerError::NotFound { coinstaller_path })
+    }
+}
+
+/// Checks that the coinstaller named `file_name` is installed and registered by
+/// the INF at `inf_path`, whose contents are `inf_contents`.
+///
+/// The coinstaller must be installed by a `CopyFiles` directive, and registered
+/// as `CoInstallers32` by an `AddReg` directive of a `CoInstallers` section.
+/// File names are compared case-insensitively.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`WdfCoInstallerError::MissingCopyFilesEntry`] if the coinstaller is not
+///   installed by the INF
+/// - [`WdfCoInstallerError::MissingCoInstallersRegistration`] if the
+///   coinstaller is not registered by the INF
+pub fn validate_inf_entries(
+    inf_path: PathBuf,
+    inf_contents: &str,
+    file_name: &str,
+) -> Result<(), WdfCoInstallerError> {
+    if !inf::copy_files_entries(inf_contents)
+        .iter()
+        .any(|entry| entry.eq_ignore_ascii_case(file_name))
+    {
+        return Err(WdfCoInstallerError::MissingCopyFilesEntry {
+            file_name: file_name.to_string(),
+            inf_path,
+        });
+    }
+
+    let file_name = file_name.to_ascii_lowercase();
+    let is_registered = inf::section_names(inf_contents)
+        .filter(|section_name| {
+            section_name
+                .to_ascii_lowercase()
+                .ends_with(&COINSTALLERS_SECTION_SUFFIX.to_ascii_lowercase())
+        })
+        .flat_map(|section_name| inf::section_lines(inf_contents, section_name))
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            key.trim()
+                .eq_ignore_ascii_case(ADD_REG_DIRECTIVE_NAME)
+                .then_some(value)
+        })
+        .flat_map(|value| value.split(',').map(str::trim))
+        .flat_map(|add_reg_section| inf::section_lines(inf_contents, add_reg_section))
+        .map(str::to_ascii_lowercase)
+        .any(|line| {
+            line.contains(&COINSTALLERS32_VALUE_NAME.to_ascii_lowercase())
+                && line.contains(&file_name)
+        });
+    if !is_registered {
+        return Err(WdfCoInstallerError::MissingCoInstallersRegistration {
+            file_name,
+            inf_path,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{KmdfConfig, UmdfConfig};
+
+    const INF_PATH: &str = "sample_driver.inf";
+    const COINSTALLER_INF: &str = r#"
+[Sample_Device.NT.CoInstallers]
+AddReg=Sample_Device_CoInstaller_AddReg
+CopyFiles=Sample_Device_CoInstaller_CopyFiles
+
+[Sample_Device_CoInstaller_AddReg]
+HKR,,CoInstallers32,0x00010000, "WdfCoInstaller01011.dll,WdfCoInstaller"
+
+[Sample_Device_CoInstaller_CopyFiles]
+WdfCoInstaller01011.dll
+"#;
+
+    const fn kmdf_config(target_kmdf_version_minor: u8) -> DriverConfig {
+        DriverConfig::Kmdf(KmdfConfig {
+            kmdf_version_major: 1,
+            target_kmdf_version_minor,
+            minimum_kmdf_version_minor: None,
+        })
+    }
+
+    #[test]
+    fn parse_versions() {
+        assert_eq!(
+            "1.11".parse::<WdfCoInstallerVersion>(),
+            Ok(WdfCoInstallerVersion {
+                major: 1,
+                minor: 11
+            })
+        );
+        assert!("1".parse::<WdfCoInstallerVersion>().is_err());
+        assert!("1.x".parse::<WdfCoInstallerVersion>().is_err());
+        assert!(WdfCoInstallerVersion::deserialize(serde_json::json!(1.11)).is_err());
+    }
+
+    #[test]
+    fn coinstaller_file_names() {
+        let version = WdfCoInstallerVersion {
+            major: 1,
+            minor: 11,
+        };
+
+        assert_eq!(
+            version.file_name(&kmdf_config(9)).unwrap(),
+            "WdfCoInstaller01011.dll"
+        );
+        assert_eq!(
+            version
+                .file_name(&DriverConfig::Umdf(UmdfConfig {
+                    umdf_version_major: 1,
+                    target_umdf_version_minor: 11,
+                    minimum_umdf_version_minor: None,
+                }))
+                .unwrap(),
+            "WUDFUpdate_01011.dll"
+        );
+        assert!(matches!(
+            version.file_name(&kmdf_config(15)),
+            Err(WdfCoInstallerError::IncompatibleVersion { .. })
+        ));
+        assert!(matches!(
+            version.file_name(&DriverConfig::Wdm),
+            Err(WdfCoInstallerError::WdmDriver)
+        ));
+    }
+
+    #[test]
+    fn inf_entries_are_validated() {
+        assert!(
+            validate_inf_entries(INF_PATH.into(), COINSTALLER_INF, "wdfcoinstaller01011.dll")
+                .is_ok()
+        );
+        assert!(matches!(
+            validate_inf_entries(INF_PATH.into(), COINSTALLER_INF, "WdfCoInstaller01009.dll"),
+            Err(WdfCoInstallerError::MissingCopyFilesEntry { .. })
+        ));
+
+        let unregistered_inf = COINSTALLER_INF.replace("CoInstallers32", "CoInstallers");
+        assert!(matches!(
+            validate_inf_entries(
+                INF_PATH.into(),
+                &unregistered_inf,
+                "WdfCoInstaller01011.dll"
+            ),
+            Err(WdfCoInstallerError::MissingCoInstallersRegistration { .. })
+        ));
+    }
+}
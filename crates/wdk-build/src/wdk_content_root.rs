@@ -0,0 +1,152 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Errors of the detection of the `WDKContentRoot` directory
+//!
+//! The WDK content root is detected, in order, from the `WDKContentRoot`
+//! environment variable (set in eWDK prompts), the `MicrosoftKitRoot` and
+//! `WDKKitVersion` environment variables, and the `KitsRoot10` values of the
+//! `Installed Roots` registry keys of the Windows Kits. When none of them
+//! yields a valid directory, a [`WdkContentRootDetectionError`] lists every
+//! location that was searched and every environment variable that was
+//! inspected, along with suggestions to fix the installation.
+
+use std::{fmt, path::PathBuf};
+
+use thiserror::Error;
+
+/// Location that was searched for the WDK content root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// Description of where the location comes from (ex. `WDKContentRoot
+    /// environment variable`)
+    pub source: String,
+    /// Path of the location, or `None` if the source did not yield one
+    pub path: Option<PathBuf>,
+    /// Reason why the location was rejected
+    pub reason: String,
+}
+
+/// Error returned when the WDK content root directory cannot be detected
+#[derive(Debug, Clone, Default, Error, PartialEq, Eq)]
+pub struct WdkContentRootDetectionError {
+    /// Locations that were searched, in the order they were searched
+    pub candidates: Vec<Candidate>,
+    /// Environment variables that were inspected, along with their values,
+    /// or `None` if they were not set
+    pub environment_variables: Vec<(String, Option<String>)>,
+}
+
+impl WdkContentRootDetectionError {
+    /// Returns suggestions to fix the detection of the WDK content root, based
+    /// on the locations that were searched
+    #[must_use]
+    pub fn suggestions(&self) -> Vec<String> {
+        let mut suggestions = Vec::new();
+        let is_set = |name: &str| {
+            self.environment_variables
+                .iter()
+                .any(|(variable, value)| variable == name && value.is_some())
+        };
+
+        if is_set("WDKContentRoot") || is_set("MicrosoftKitRoot") {
+            suggestions.push(
+                "Unset WDKContentRoot and MicrosoftKitRoot, or point them to an existing WDK \
+                 installation"
+                    .to_string(),
+            );
+        }
+        suggestions.push(
+            "Install the WDK: https://learn.microsoft.com/en-us/windows-hardware/drivers/download-the-wdk"
+                .to_string(),
+        );
+        suggestions.push(
+            "Or, when using the eWDK, run LaunchBuildEnv.cmd from its root directory before \
+             building"
+                .to_string(),
+        );
+        suggestions
+    }
+}
+
+impl fmt::Display for WdkContentRootDetectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "WDKContentRoot could not be detected")?;
+
+        writeln!(f, "Locations searched:")?;
+        for candidate in &self.candidates {
+            match &candidate.path {
+                Some(path) => writeln!(
+                    f,
+                    "  - {} ({}): {}",
+                    candidate.source,
+                    path.display(),
+                    candidate.reason
+                )?,
+                None => writeln!(f, "  - {}: {}", candidate.source, candidate.reason)?,
+            }
+        }
+
+        writeln!(f, "Environment variables inspected:")?;
+        for (variable, value) in &self.environment_variables {
+            writeln!(
+                f,
+                "  - {variable}={}",
+                value.as_deref().unwrap_or("<not set>")
+            )?;
+        }
+
+        write!(f, "Suggestions:")?;
+        for suggestion in self.suggestions() {
+            write!(f, "\n  - {suggestion}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_lists_searched_locations_and_suggestions() {
+        let error = WdkContentRootDetectionError {
+            candidates: vec![
+                Candidate {
+                    source: "WDKContentRoot environment variable".to_string(),
+                    path: Some(PathBuf::from(r"D:\missing")),
+                    reason: "does not exist or is not a directory".to_string(),
+                },
+                Candidate {
+                    source:
+                        r"KitsRoot10 value of HKLM\SOFTWARE\Microsoft\Windows Kits\Installed Roots"
+                            .to_string(),
+                    path: None,
+                    reason: "not found".to_string(),
+                },
+            ],
+            environment_variables: vec![
+                (
+                    "WDKContentRoot".to_string(),
+                    Some(r"D:\missing".to_string()),
+                ),
+                ("MicrosoftKitRoot".to_string(), None),
+            ],
+        };
+
+        assert_eq!(
+            error.to_string(),
+            r"WDKContentRoot could not be detected
+Locations searched:
+  - WDKContentRoot environment variable (D:\missing): does not exist or is not a directory
+  - KitsRoot10 value of HKLM\SOFTWARE\Microsoft\Windows Kits\Installed Roots: not found
+Environment variables inspected:
+  - WDKContentRoot=D:\missing
+  - MicrosoftKitRoot=<not set>
+Suggestions:
+  - Unset WDKContentRoot and MicrosoftKitRoot, or point them to an existing WDK installation
+  - Install the WDK: https://learn.microsoft.com/en-us/windows-hardware/drivers/download-the-wdk
+  - Or, when using the eWDK, run LaunchBuildEnv.cmd from its root directory before building"
+        );
+    }
+}
@@ -0,0 +1,420 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Resolution of the paths of headers, libraries and tools within a WDK
+//! installation.
+//!
+//! The directory layout of the WDK occasionally changes between releases. Each
+//! known layout is implemented as a [`WdkLayout`] strategy, and the strategy
+//! used for an installed WDK is selected from its build number by
+//! [`select_wdk_layout`]. Supporting a WDK release with a new layout is done by
+//! adding a strategy to the list of known layouts, instead of changing every
+//! place that builds a path into the WDK.
+//!
+//! Early adopters of a WDK release whose layout is not yet detected correctly
+//! can override the selection with either:
+//! - the [`WDK_LAYOUT_ENV_VAR`] environment variable, set to the
+//!   [`WdkLayout::name`] of one of the known layouts
+//! - [`set_wdk_layout_override`], called from a build script before any paths
+//!   are resolved, with a custom [`WdkLayout`] implementation
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use crate::{diagnostics::record_diagnostic, utils, ConfigError, CpuArchitecture, DriverConfig};
+
+/// Environment variable that overrides the [`WdkLayout`] selected from the
+/// build number of the WDK. Its value must be the [`WdkLayout::name`] of one of
+/// the known layouts.
+pub const WDK_LAYOUT_ENV_VAR: &str = "WDK_BUILD_WDK_LAYOUT";
+
+/// Known WDK layouts, along with the first WDK build number each layout applies
+/// to, ordered by build number
+const WDK_LAYOUTS: &[(u32, &dyn WdkLayout)] = &[(0, &WindowsKits10Layout)];
+
+/// Name of the diagnostics stage of [`select_wdk_layout`]
+const WDK_LAYOUT_SELECTION_STAGE: &str = "WDK layout selection";
+
+static WDK_LAYOUT_OVERRIDE: OnceLock<&'static dyn WdkLayout> = OnceLock::new();
+
+/// Strategy for resolving the paths of directories within a WDK installation.
+///
+/// All paths are resolved relative to the WDK content root (ex. `C:\Program
+/// Files (x86)\Windows Kits\10`), for the Windows SDK version installed in it
+/// (ex. `10.0.26100.0`). Implementations only build paths; checking that the
+/// directories exist is left to the caller.
+pub trait WdkLayout: fmt::Debug + Send + Sync {
+    /// Name of the layout, used to select it with [`WDK_LAYOUT_ENV_VAR`]
+    fn name(&self) -> &'static str;
+
+    /// Returns the Windows SDK include directories required to build for
+    /// `driver_config`, in order of precedence
+    fn sdk_include_directories(
+        &self,
+        wdk_content_root: &Path,
+        sdk_version: &str,
+        driver_config: &DriverConfig,
+    ) -> Vec<PathBuf>;
+
+    /// Returns the include directory of the WDF headers for `driver_config`,
+    /// or `None` if `driver_config` does not use WDF
+    fn wdf_include_directory(
+        &self,
+        wdk_content_root: &Path,
+        driver_config: &DriverConfig,
+    ) -> Option<PathBuf>;
+
+    /// Returns the Windows SDK library directory required to link for
+    /// `driver_config` on `cpu_architecture`
+    fn sdk_library_directory(
+        &self,
+        wdk_content_root: &Path,
+        sdk_version: &str,
+        driver_config: &DriverConfig,
+        cpu_architecture: CpuArchitecture,
+    ) -> PathBuf;
+
+    /// Returns the library directory of the WDF libraries for `driver_config`
+    /// on `cpu_architecture`, or `None` if `driver_config` does not use WDF
+    fn wdf_library_directory(
+        &self,
+        wdk_content_root: &Path,
+        driver_config: &DriverConfig,
+        cpu_architecture: CpuArchitecture,
+    ) -> Option<PathBuf>;
+
+    /// Returns the directory containing the redistributable WDF coinstallers
+    /// for `cpu_architecture`
+    fn wdf_redist_directory(
+        &self,
+        wdk_content_root: &Path,
+        cpu_architecture: CpuArchitecture,
+    ) -> PathBuf;
+
+    /// Returns the directory containing the WDK build tools (ex. `stampinf`)
+    /// for `host_architecture`
+    fn bin_directory(
+        &self,
+        wdk_content_root: &Path,
+        sdk_version: &str,
+        host_architecture: &str,
+    ) -> PathBuf;
+
+    /// Returns the directory containing the WDK tools that are not part of the
+    /// build (ex. `infverif`) for `host_architecture`
+    fn tools_directory(
+        &self,
+        wdk_content_root: &Path,
+        sdk_version: &str,
+        host_architecture: &str,
+    ) -> PathBuf;
+}
+
+/// Layout of the WDK installed into `Windows Kits\10`, with headers and
+/// libraries in subdirectories named after the Windows SDK version.
+///
+/// This is based off of `WindowsDriver.KernelMode.props` and
+/// `WindowsDriver.UserMode.props` in the NI(22H2) WDK.
+#[derive(Debug, Clone, Copy)]
+pub struct WindowsKits10Layout;
+
+impl WdkLayout for WindowsKits10Layout {
+    fn name(&self) -> &'static str {
+        "windows-kits-10"
+    }
+
+    fn sdk_include_directories(
+        &self,
+        wdk_content_root: &Path,
+        sdk_version: &str,
+        driver_config: &DriverConfig,
+    ) -> Vec<PathBuf> {
+        let windows_sdk_include_path = wdk_content_root.join("Include").join(sdk_version);
+
+        vec![
+            windows_sdk_include_path.join("km/crt"),
+            windows_sdk_include_path.join(match driver_config {
+                DriverConfig::Wdm | DriverConfig::Kmdf(_) => "km",
+                DriverConfig::Umdf(_) => "um",
+            }),
+            windows_sdk_include_path.join("shared"),
+        ]
+    }
+
+    fn wdf_include_directory(
+        &self,
+        wdk_content_root: &Path,
+        driver_config: &DriverConfig,
+    ) -> Option<PathBuf> {
+        let include_directory = wdk_content_root.join("Include");
+
+        match driver_config {
+            DriverConfig::Wdm => None,
+            DriverConfig::Kmdf(kmdf_config) => Some(include_directory.join(format!(
+                "wdf/kmdf/{}.{}",
+                kmdf_config.kmdf_version_major, kmdf_config.target_kmdf_version_minor
+            ))),
+            DriverConfig::Umdf(umdf_config) => Some(include_directory.join(format!(
+                "wdf/umdf/{}.{}",
+                umdf_config.umdf_version_major, umdf_config.target_umdf_version_minor
+            ))),
+        }
+    }
+
+    fn sdk_library_directory(
+        &self,
+        wdk_content_root: &Path,
+        sdk_version: &str,
+        driver_config: &DriverConfig,
+        cpu_architecture: CpuArchitecture,
+    ) -> PathBuf {
+        wdk_content_root
+            .join("Lib")
+            .join(sdk_version)
+            .join(match driver_config {
+                DriverConfig::Wdm | DriverConfig::Kmdf(_) => {
+                    format!("km/{}", cpu_architecture.as_windows_str())
+                }
+                DriverConfig::Umdf(_) => format!("um/{}", cpu_architecture.as_windows_str()),
+            })
+    }
+
+    fn wdf_library_directory(
+        &self,
+        wdk_content_root: &Path,
+        driver_config: &DriverConfig,
+        cpu_architecture: CpuArchitecture,
+    ) -> Option<PathBuf> {
+        let library_directory = wdk_content_root.join("Lib");
+
+        match driver_config {
+            DriverConfig::Wdm => None,
+            DriverConfig::Kmdf(kmdf_config) => Some(library_directory.join(format!(
+                "wdf/kmdf/{}/{}.{}",
+                cpu_architecture.as_windows_str(),
+                kmdf_config.kmdf_version_major,
+                kmdf_config.target_kmdf_version_minor
+            ))),
+            DriverConfig::Umdf(umdf_config) => Some(library_directory.join(format!(
+                "wdf/umdf/{}/{}.{}",
+                cpu_architecture.as_windows_str(),
+                umdf_config.umdf_version_major,
+                umdf_config.target_umdf_version_minor,
+            ))),
+        }
+    }
+
+    fn wdf_redist_directory(
+        &self,
+        wdk_content_root: &Path,
+        cpu_architecture: CpuArchitecture,
+    ) -> PathBuf {
+        wdk_content_root
+            .join("Redist/wdf")
+            .join(cpu_architecture.as_windows_str())
+    }
+
+    fn bin_directory(
+        &self,
+        wdk_content_root: &Path,
+        sdk_version: &str,
+        host_architecture: &str,
+    ) -> PathBuf {
+        wdk_content_root
+            .join(format!("bin/{sdk_version}"))
+            .join(host_architecture)
+    }
+
+    fn tools_directory(
+        &self,
+        wdk_content_root: &Path,
+        sdk_version: &str,
+        host_architecture: &str,
+    ) -> PathBuf {
+        wdk_content_root
+            .join(format!("Tools/{sdk_version}"))
+            .join(host_architecture)
+    }
+}
+
+/// Overrides the [`WdkLayout`] used to resolve paths within the WDK for the
+/// rest of the current process.
+///
+/// This takes precedence over both
+/// [`WDK_LAYOUT_ENV_VAR`] and the layout selected from the WDK build number.
+///
+/// # Errors
+///
+/// This function returns the layout that was already set if it is called more
+/// than once
+pub fn set_wdk_layout_override(
+    wdk_layout: &'static dyn WdkLayout,
+) -> Result<(), &'static dyn WdkLayout> {
+    WDK_LAYOUT_OVERRIDE.set(wdk_layout)
+}
+
+/// Selects the [`WdkLayout`] for the WDK with the Windows SDK version
+/// `sdk_version` (ex. `10.0.26100.0`).
+///
+/// The layout set by [`set_wdk_layout_override`] or named by
+/// [`WDK_LAYOUT_ENV_VAR`] is used if present. Otherwise, the latest known
+/// layout that applies to the build number of `sdk_version` is selected, so
+/// WDK releases newer than all known layouts use the latest layout.
+///
+/// # Errors
+///
+/// This function returns:
+/// - [`ConfigError::UnknownWdkLayout`] if [`WDK_LAYOUT_ENV_VAR`] does not name
+///   a known layout
+/// - [`ConfigError::WdkVersionStringFormatError`] if `sdk_version` is
+///   ill-formed
+pub fn select_wdk_layout(sdk_version: &str) -> Result<&'static dyn WdkLayout, ConfigError> {
+    if let Some(wdk_layout) = WDK_LAYOUT_OVERRIDE.get() {
+        record_diagnostic!(
+            WDK_LAYOUT_SELECTION_STAGE,
+            ("selected WDK layout set by set_wdk_layout_override"),
+            layout = wdk_layout.name(),
+        );
+        return Ok(*wdk_layout);
+    }
+
+    if let Ok(wdk_layout_name) = std::env::var(WDK_LAYOUT_ENV_VAR) {
+        record_diagnostic!(
+            WDK_LAYOUT_SELECTION_STAGE,
+            ("selected WDK layout named by {WDK_LAYOUT_ENV_VAR}"),
+            layout = wdk_layout_name,
+        );
+        return find_wdk_layout(&wdk_layout_name);
+    }
+
+    let build_number = utils::get_wdk_version_number(sdk_version)?
+        .parse::<u32>()
+        .map_err(|_| ConfigError::WdkVersionStringFormatError {
+            version: sdk_version.to_string(),
+        })?;
+    let wdk_layout = wdk_layout_for_build_number(build_number);
+    record_diagnostic!(
+        WDK_LAYOUT_SELECTION_STAGE,
+        ("selected WDK layout from the WDK build number"),
+        build_number = build_number,
+        layout = wdk_layout.name(),
+    );
+    Ok(wdk_layout)
+}
+
+/// Returns the known [`WdkLayout`] named `wdk_layout_name`
+fn find_wdk_layout(wdk_layout_name: &str) -> Result<&'static dyn WdkLayout, ConfigError> {
+    WDK_LAYOUTS
+        .iter()
+        .map(|(_, wdk_layout)| *wdk_layout)
+        .find(|wdk_layout| wdk_layout.name() == wdk_layout_name)
+        .ok_or_else(|| ConfigError::UnknownWdkLayout {
+            name: wdk_layout_name.to_string(),
+            known_layouts: WDK_LAYOUTS
+                .iter()
+                .map(|(_, wdk_layout)| wdk_layout.name())
+                .collect::<Vec<_>>()
+                .join(", "),
+        })
+}
+
+/// Returns the latest known [`WdkLayout`] that applies to `build_number`
+fn wdk_layout_for_build_number(build_number: u32) -> &'static dyn WdkLayout {
+    WDK_LAYOUTS
+        .iter()
+        .rev()
+        .find(|(first_build_number, _)| *first_build_number <= build_number)
+        .map(|(_, wdk_layout)| *wdk_layout)
+        .expect("the first known WDK layout should apply to all build numbers")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{KmdfConfig, UmdfConfig};
+
+    const WDK_CONTENT_ROOT: &str = "Windows Kits/10";
+    const SDK_VERSION: &str = "10.0.26100.0";
+
+    #[test]
+    fn build_numbers_select_latest_applicable_layout() {
+        assert_eq!(wdk_layout_for_build_number(22621).name(), "windows-kits-10");
+        assert_eq!(
+            wdk_layout_for_build_number(u32::MAX).name(),
+            "windows-kits-10"
+        );
+    }
+
+    #[test]
+    fn find_known_layout() {
+        assert_eq!(
+            find_wdk_layout("windows-kits-10")
+                .expect("windows-kits-10 should be a known layout")
+                .name(),
+            "windows-kits-10"
+        );
+        assert!(matches!(
+            find_wdk_layout("unknown"),
+            Err(ConfigError::UnknownWdkLayout { name, .. }) if name == "unknown"
+        ));
+    }
+
+    #[test]
+    fn windows_kits_10_include_directories() {
+        let wdk_content_root = Path::new(WDK_CONTENT_ROOT);
+        let sdk_include_root = wdk_content_root.join("Include").join(SDK_VERSION);
+
+        assert_eq!(
+            WindowsKits10Layout.sdk_include_directories(
+                wdk_content_root,
+                SDK_VERSION,
+                &DriverConfig::Umdf(UmdfConfig::new())
+            ),
+            [
+                sdk_include_root.join("km/crt"),
+                sdk_include_root.join("um"),
+                sdk_include_root.join("shared"),
+            ]
+        );
+        assert_eq!(
+            WindowsKits10Layout.wdf_include_directory(wdk_content_root, &DriverConfig::Wdm),
+            None
+        );
+        assert_eq!(
+            WindowsKits10Layout
+                .wdf_include_directory(wdk_content_root, &DriverConfig::Kmdf(KmdfConfig::new())),
+            Some(wdk_content_root.join("Include").join("wdf/kmdf/1.33"))
+        );
+    }
+
+    #[test]
+    fn windows_kits_10_library_directories() {
+        let wdk_content_root = Path::new(WDK_CONTENT_ROOT);
+
+        assert_eq!(
+            WindowsKits10Layout.sdk_library_directory(
+                wdk_content_root,
+                SDK_VERSION,
+                &DriverConfig::Wdm,
+                CpuArchitecture::Arm64
+            ),
+            wdk_content_root
+                .join("Lib")
+                .join(SDK_VERSION)
+                .join("km/ARM64")
+        );
+        assert_eq!(
+            WindowsKits10Layout.wdf_library_directory(
+                wdk_content_root,
+                &DriverConfig::Umdf(UmdfConfig::new()),
+                CpuArchitecture::Amd64
+            ),
+            Some(wdk_content_root.join("Lib").join("wdf/umdf/x64/2.33"))
+        );
+    }
+}
@@ -0,0 +1,222 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Pinning of the WDK that a workspace is built with
+//!
+//! Workspaces that declare a `workspace.metadata.wdk.lock` section record the
+//! WDK and Windows SDK versions of their first successful build in a
+//! [`WDK_LOCK_FILE_NAME`] file next to `Cargo.lock`. Once the file is
+//! committed, builds on machines (or CI agents) with a different WDK or
+//! Windows SDK fail before building, instead of silently producing drivers
+//! built against different headers and libraries:
+//!
+//! ```toml
+//! [workspace.metadata.wdk.lock]
+//! # Only warn about a different WDK, instead of failing the build
+//! mismatch = "warn"
+//! ```
+//!
+//! To move the workspace to a different WDK, delete the file and rebuild.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Key of the section in `workspace.metadata.wdk` that enables the WDK lock
+/// file
+pub const WDK_LOCK_METADATA_KEY: &str = "lock";
+
+/// Name of the file that records the WDK and Windows SDK versions of the
+/// workspace, in the root directory of the workspace
+pub const WDK_LOCK_FILE_NAME: &str = "wdk.lock";
+
+/// Comment at the top of the WDK lock file
+const WDK_LOCK_FILE_HEADER: &str =
+    "# This file is generated by wdk-build. It records the WDK and Windows SDK versions that the \
+     workspace is built with.\n# Delete it and rebuild to move the workspace to a different WDK.\n";
+
+/// Errors that could result from reading or validating the WDK lock file
+#[derive(Debug, Error)]
+pub enum WdkLockError {
+    /// Error returned when the `workspace.metadata.wdk.lock` section fails to
+    /// be deserialized
+    #[error("failed to deserialize workspace.metadata.wdk.lock")]
+    MetadataDeserialization {
+        /// [`serde_json::Error`] that caused the deserialization to fail
+        #[source]
+        error_source: serde_json::Error,
+    },
+
+    /// Error returned when the WDK lock file fails to be parsed
+    #[error("failed to parse {WDK_LOCK_FILE_NAME}")]
+    LockFileParse {
+        /// [`toml::de::Error`] that caused the parsing to fail
+        #[source]
+        error_source: toml::de::Error,
+    },
+
+    /// Error returned when the detected WDK or Windows SDK differs from the
+    /// one recorded in the WDK lock file
+    #[error(
+        "the detected WDK differs from the one recorded in {WDK_LOCK_FILE_NAME} ({}). Install the \
+         recorded WDK, or delete {WDK_LOCK_FILE_NAME} to record the detected one",
+        .mismatches.join(", ")
+    )]
+    VersionMismatch {
+        /// Descriptions of the versions that differ
+        mismatches: Vec<String>,
+    },
+}
+
+/// What happens when the detected WDK differs from the one recorded in the
+/// WDK lock file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MismatchPolicy {
+    /// The build fails
+    #[default]
+    Error,
+    /// A warning is printed, and the build continues
+    Warn,
+}
+
+/// Metadata specified in the `workspace.metadata.wdk.lock` section of a
+/// workspace's `Cargo.toml`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct WdkLockConfig {
+    /// What happens when the detected WDK differs from the recorded one
+    #[serde(default)]
+    pub mismatch: MismatchPolicy,
+}
+
+impl WdkLockConfig {
+    /// Parses the `workspace.metadata.wdk.lock` section of
+    /// `workspace_metadata`, or returns `None` if the workspace does not
+    /// declare it
+    ///
+    /// # Errors
+    ///
+    /// This function will return [`WdkLockError::MetadataDeserialization`] if
+    /// the section fails to be deserialized
+    pub fn try_from_workspace_metadata(
+        workspace_metadata: &serde_json::Value,
+    ) -> Result<Option<Self>, WdkLockError> {
+        match &workspace_metadata["wdk"][WDK_LOCK_METADATA_KEY] {
+            serde_json::Value::Null => Ok(None),
+            lock_metadata => Self::deserialize(lock_metadata)
+                .map(Some)
+                .map_err(|error_source| WdkLockError::MetadataDeserialization { error_source }),
+        }
+    }
+}
+
+/// The WDK and Windows SDK versions recorded in the WDK lock file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct WdkLock {
+    /// Version of the WDK, detected from its libraries (ex. `10.0.26100.0`)
+    pub wdk_version: String,
+    /// Version of the Windows SDK, detected from its headers (ex.
+    /// `10.0.26100.0`)
+    pub sdk_version: String,
+}
+
+impl WdkLock {
+    /// Parses the contents of a WDK lock file
+    ///
+    /// # Errors
+    ///
+    /// This function will return [`WdkLockError::LockFileParse`] if `contents`
+    /// is not a valid WDK lock file
+    pub fn parse(contents: &str) -> Result<Self, WdkLockError> {
+        toml::from_str(contents)
+            .map_err(|error_source| WdkLockError::LockFileParse { error_source })
+    }
+
+    /// Returns a description of each version that differs between `self` (the
+    /// detected versions) and `locked` (the recorded versions)
+    #[must_use]
+    pub fn mismatches(&self, locked: &Self) -> Vec<String> {
+        [
+            ("wdk-version", &self.wdk_version, &locked.wdk_version),
+            ("sdk-version", &self.sdk_version, &locked.sdk_version),
+        ]
+        .into_iter()
+        .filter(|(_, detected, locked)| detected != locked)
+        .map(|(key, detected, locked)| format!("{key}: recorded {locked}, detected {detected}"))
+        .collect()
+    }
+}
+
+impl fmt::Display for WdkLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(WDK_LOCK_FILE_HEADER)?;
+        f.write_str(&toml::to_string(self).map_err(|_| fmt::Error)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wdk_lock(wdk_version: &str, sdk_version: &str) -> WdkLock {
+        WdkLock {
+            wdk_version: wdk_version.to_string(),
+            sdk_version: sdk_version.to_string(),
+        }
+    }
+
+    #[test]
+    fn lock_file_round_trip() {
+        let lock = wdk_lock("10.0.26100.0", "10.0.26100.0");
+
+        let contents = lock.to_string();
+        assert!(contents.starts_with("# This file is generated by wdk-build"));
+        assert!(contents.contains("wdk-version = \"10.0.26100.0\""));
+        assert_eq!(WdkLock::parse(&contents).unwrap(), lock);
+        assert!(WdkLock::parse("wdk-version = \"10.0.26100.0\"").is_err());
+    }
+
+    #[test]
+    fn mismatches_describe_changed_versions() {
+        let locked = wdk_lock("10.0.22621.0", "10.0.26100.0");
+
+        assert!(locked.mismatches(&locked).is_empty());
+        assert_eq!(
+            wdk_lock("10.0.26100.0", "10.0.26100.0").mismatches(&locked),
+            ["wdk-version: recorded 10.0.22621.0, detected 10.0.26100.0"]
+        );
+    }
+
+    #[test]
+    fn parse_config_from_workspace_metadata() {
+        assert_eq!(
+            WdkLockConfig::try_from_workspace_metadata(&serde_json::json!({
+                "wdk": { "lock": { "mismatch": "warn" } }
+            }))
+            .unwrap(),
+            Some(WdkLockConfig {
+                mismatch: MismatchPolicy::Warn,
+            })
+        );
+        assert_eq!(
+            WdkLockConfig::try_from_workspace_metadata(&serde_json::json!({
+                "wdk": { "lock": {} }
+            }))
+            .unwrap(),
+            Some(WdkLockConfig::default())
+        );
+        assert_eq!(
+            WdkLockConfig::try_from_workspace_metadata(&serde_json::Value::Null).unwrap(),
+            None
+        );
+        assert!(
+            WdkLockConfig::try_from_workspace_metadata(&serde_json::json!({
+                "wdk": { "lock": { "mismatch": "ignore" } }
+            }))
+            .is_err()
+        );
+    }
+}
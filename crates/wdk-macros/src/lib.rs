@@ -8,9 +8,10 @@
 
 use itertools::Itertools;
 use proc_macro::TokenStream;
-use proc_macro2::{Span, TokenStream as TokenStream2};
+use proc_macro2::{Literal, Span, TokenStream as TokenStream2};
 use quote::{format_ident, quote};
 use syn::{
+    ext::IdentExt,
     parse::{Parse, ParseStream},
     parse2,
     parse_file,
@@ -26,7 +27,9 @@
     GenericArgument,
     Ident,
     Item,
+    ItemFn,
     ItemType,
+    LitInt,
     LitStr,
     Path,
     PathArguments,
@@ -52,6 +55,91 @@ pub fn call_unsafe_wdf_function_binding(input_tokens: TokenStream) -> TokenStrea
     call_unsafe_wdf_function_binding_impl(TokenStream2::from(input_tokens)).into()
 }
 
+/// An attribute that declares the maximum size, in bytes, of the stack frame of
+/// a function.
+///
+/// This macro is only intended to be used via the `wdk` crate, as
+/// `wdk::max_stack`. The limit is recorded in the driver binary, where it is
+/// enforced by the `validate-stack-frame-limits` task in
+/// `rust-driver-makefile.toml` after the driver is linked. When the
+/// `stack-instrumentation` feature of the `wdk` crate is enabled, the remaining
+/// stack is also checked every time the function is entered.
+///
+/// This attribute can only be applied to free functions that are not generic,
+/// `const` or `async`.
+#[proc_macro_attribute]
+pub fn max_stack(attribute_tokens: TokenStream, item_tokens: TokenStream) -> TokenStream {
+    max_stack_impl(
+        TokenStream2::from(attribute_tokens),
+        TokenStream2::from(item_tokens),
+    )
+    .into()
+}
+
+/// A procedural macro that writes a `TraceLogging` event to a
+/// `wdk::trace_logging::TraceLoggingProvider`.
+///
+/// This macro is only intended to be used via the `wdk` crate, as
+/// `wdk::trace_logging_write`. It takes the provider, the name of the event,
+/// and then any number of `level: ..`, `keyword: ..` and `opcode: ..` options
+/// and `field_name = value` fields:
+///
+/// ```rust, ignore
+/// trace_logging_write!(PROVIDER, "DeviceAdded", level: Level::Information, device_index = 3_u32);
+/// ```
+///
+/// The self-describing event metadata (the event name, and the name and type
+/// of every field) is generated at compile time. The field values are only
+/// evaluated when the provider is enabled for the event's level and keyword.
+#[proc_macro]
+pub fn trace_logging_write(input_tokens: TokenStream) -> TokenStream {
+    trace_logging_write_impl(TokenStream2::from(input_tokens)).into()
+}
+
+/// An attribute that generates the `extern "C"` trampoline of a WDF event
+/// callback for a function.
+///
+/// This macro is only intended to be used via the `wdk` crate, as
+/// `wdk::callback`. It takes the name of the callback's typedef in `wdk-sys`
+/// (ex. `EVT_WDF_DRIVER_DEVICE_ADD`), whose parameter and return types are used
+/// for the signature of the trampoline:
+///
+/// ```rust, ignore
+/// #[wdk::callback(EVT_WDF_IO_QUEUE_IO_READ)]
+/// fn evt_io_read(queue: WDFQUEUE, request: Request, length: usize) {
+///     request.complete(STATUS_SUCCESS);
+/// }
+/// ```
+///
+/// The trampoline takes the name and visibility of the function, and can be
+/// registered wherever the typedef is expected (ex. `Some(evt_io_read)`). Each
+/// of its arguments is converted to the type of the corresponding parameter of
+/// the function with `wdk::wdf::FromRawCallbackArg`, and the return value of
+/// the function is converted back with `wdk::wdf::IntoRawCallbackReturn`.
+///
+/// This attribute can only be applied to safe free functions that are not
+/// generic, `const`, `async` or `extern`.
+#[proc_macro_attribute]
+pub fn callback(attribute_tokens: TokenStream, item_tokens: TokenStream) -> TokenStream {
+    callback_impl(
+        TokenStream2::from(attribute_tokens),
+        TokenStream2::from(item_tokens),
+    )
+    .into()
+}
+
+/// A procedural macro that generates the trampoline of a function annotated
+/// with `wdk::callback`.
+///
+/// This macro is only intended to be used in the `wdk-sys` crate, by the macro
+/// that `wdk::callback` expands to. That macro passes in the generated types
+/// from `wdk-sys` as the first argument to this macro, followed by the name of
+/// the callback typedef and the annotated function.
+#[proc_macro]
+pub fn wdf_callback_trampoline(input_tokens: TokenStream) -> TokenStream {
+    wdf_callback_trampoline_impl(TokenStream2::from(input_tokens)).into()
+}
+
 /// A trait to provide additional functionality to the [`String`] type
 trait StringExt {
     /// Convert a string to `snake_case`
@@ -334,6 +422,525 @@ fn call_unsafe_wdf_function_binding_impl(input_tokens: TokenStream2) -> TokenStr
         .assemble_final_output()
 }
 
+/// Name of the section of the driver binary that stack frame limits are
+/// recorded in. This must match
+/// `wdk_build::stack_frame_limits::STACK_FRAME_LIMITS_SECTION_NAME`.
+const STACK_FRAME_LIMITS_SECTION_NAME: &str = ".wdkstk";
+
+fn max_stack_impl(attribute_tokens: TokenStream2, item_tokens: TokenStream2) -> TokenStream2 {
+    let max_frame_size = match parse2::<LitInt>(attribute_tokens).and_then(|max_frame_size| {
+        max_frame_size
+            .base10_parse::<usize>()
+            .map(|_| max_frame_size)
+    }) {
+        Ok(max_frame_size) => max_frame_size,
+        Err(err) => {
+            return Error::new(
+                err.span(),
+                "expected the maximum stack frame size in bytes (ex. `#[max_stack(512)]`)",
+            )
+            .to_compile_error();
+        }
+    };
+
+    let item_fn = match parse2::<ItemFn>(item_tokens) {
+        Ok(item_fn) => item_fn,
+        Err(err) => return err.to_compile_error(),
+    };
+    if let Err(err) = validate_max_stack_fn_signature(&item_fn.sig) {
+        return err.to_compile_error();
+    }
+
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = item_fn;
+    let fn_ident = &sig.ident;
+
+    quote! {
+        #(#attrs)*
+        #vis #sig {
+            #[used]
+            #[link_section = #STACK_FRAME_LIMITS_SECTION_NAME]
+            static STACK_FRAME_LIMIT: ::wdk::stack::StackFrameLimit =
+                ::wdk::stack::StackFrameLimit::new(#fn_ident as *const (), #max_frame_size);
+
+            ::wdk::stack::check_remaining_stack(
+                ::core::concat!(::core::module_path!(), "::", ::core::stringify!(#fn_ident)),
+                #max_frame_size,
+            );
+
+            #block
+        }
+    }
+}
+
+/// Validate that the address of a function with the given [`Signature`] can be
+/// recorded in a `static` inside of its body
+fn validate_max_stack_fn_signature(signature: &Signature) -> Result<()> {
+    if let Some(constness) = &signature.constness {
+        return Err(Error::new(
+            constness.span,
+            "#[max_stack] cannot be applied to const functions",
+        ));
+    }
+    if let Some(asyncness) = &signature.asyncness {
+        return Err(Error::new(
+            asyncness.span,
+            "#[max_stack] cannot be applied to async functions",
+        ));
+    }
+    if !signature.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &signature.generics,
+            "#[max_stack] cannot be applied to generic functions",
+        ));
+    }
+    if let Some(receiver) = signature.receiver() {
+        return Err(Error::new_spanned(
+            receiver,
+            "#[max_stack] can only be applied to free functions",
+        ));
+    }
+    Ok(())
+}
+
+/// Struct storing the input tokens directly parsed from calls to
+/// `trace_logging_write` macro
+struct TraceLoggingWriteInputs {
+    /// Expression evaluating to the provider to write the event to
+    provider: Expr,
+    /// Name of the event
+    event_name: LitStr,
+    /// Level of the event. Defaults to `Level::Verbose`
+    level: Option<Expr>,
+    /// Keyword bitmask of the event. Defaults to `0`
+    keyword: Option<Expr>,
+    /// Opcode of the event. Defaults to `0`
+    opcode: Option<Expr>,
+    /// Names and values of the fields of the event, in order
+    fields: Vec<(Ident, Expr)>,
+}
+
+impl Parse for TraceLoggingWriteInputs {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let provider = input.parse::<Expr>()?;
+        input.parse::<Token![,]>()?;
+        let event_name = input.parse::<LitStr>()?;
+
+        let mut inputs = Self {
+            provider,
+            event_name,
+            level: None,
+            keyword: None,
+            opcode: None,
+            fields: Vec::new(),
+        };
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+
+            let name = input.call(Ident::parse_any)?;
+            if input.peek(Token![:]) {
+                input.parse::<Token![:]>()?;
+                let option = match name.to_string().as_str() {
+                    "level" => &mut inputs.level,
+                    "keyword" => &mut inputs.keyword,
+                    "opcode" => &mut inputs.opcode,
+                    _ => {
+                        return Err(Error::new(
+                            name.span(),
+                            "expected one of the `level`, `keyword` or `opcode` options",
+                        ));
+                    }
+                };
+                if option.is_some() {
+                    return Err(Error::new(
+                        name.span(),
+                        format!("duplicate `{name}` option"),
+                    ));
+                }
+                *option = Some(input.parse::<Expr>()?);
+            } else {
+                input.parse::<Token![=]>()?;
+                inputs.fields.push((name, input.parse::<Expr>()?));
+            }
+        }
+        Ok(inputs)
+    }
+}
+
+fn trace_logging_write_impl(input_tokens: TokenStream2) -> TokenStream2 {
+    const IN_TYPE_CHAIN_FLAG: u8 = 0x80;
+
+    let inputs = match parse2::<TraceLoggingWriteInputs>(input_tokens) {
+        Ok(inputs) => inputs,
+        Err(err) => return err.to_compile_error(),
+    };
+
+    // Event metadata: its total size as a little-endian `u16`, an empty tag byte,
+    // the nul-terminated event name, and then the nul-terminated name, input type
+    // and output type of every field. Every element of `metadata_bytes` is a single
+    // byte.
+    let mut metadata_bytes = vec![quote! { 0 }, quote! { 0 }, quote! { 0 }];
+    match nul_terminated_name_bytes(&inputs.event_name.value(), inputs.event_name.span()) {
+        Ok(name_bytes) => metadata_bytes.extend(name_bytes),
+        Err(err) => return err.to_compile_error(),
+    }
+
+    let type_params = (0..inputs.fields.len())
+        .map(|index| format_ident!("T{index}"))
+        .collect::<Vec<_>>();
+    for ((name, _), type_param) in inputs.fields.iter().zip(&type_params) {
+        match nul_terminated_name_bytes(&name.unraw().to_string(), name.span()) {
+            Ok(name_bytes) => metadata_bytes.extend(name_bytes),
+            Err(err) => return err.to_compile_error(),
+        }
+        metadata_bytes.push(quote! {
+            <#type_param as ::wdk::trace_logging::TraceLoggingField>::IN_TYPE | #IN_TYPE_CHAIN_FLAG
+        });
+        metadata_bytes.push(quote! {
+            <#type_param as ::wdk::trace_logging::TraceLoggingField>::OUT_TYPE
+        });
+    }
+
+    let metadata_size = metadata_bytes.len();
+    let Ok(metadata_size_u16) = u16::try_from(metadata_size) else {
+        return Error::new(
+            inputs.event_name.span(),
+            "TraceLogging event metadata cannot be larger than 65535 bytes",
+        )
+        .to_compile_error();
+    };
+    let [size_low_byte, size_high_byte] =
+        metadata_size_u16.to_le_bytes().map(Literal::u8_unsuffixed);
+    metadata_bytes[0] = quote! { #size_low_byte };
+    metadata_bytes[1] = quote! { #size_high_byte };
+    let metadata_size = Literal::usize_unsuffixed(metadata_size);
+
+    let TraceLoggingWriteInputs {
+        provider,
+        level,
+        keyword,
+        opcode,
+        fields,
+        ..
+    } = inputs;
+    let level = level.map_or_else(
+        || quote! { ::wdk::trace_logging::Level::Verbose },
+        |level| quote! { #level },
+    );
+    let keyword = keyword.map_or_else(|| quote! { 0 }, |keyword| quote! { #keyword });
+    let opcode = opcode.map_or_else(|| quote! { 0 }, |opcode| quote! { #opcode });
+    let values = fields.iter().map(|(_, value)| value);
+
+    // Local variables use mixed-site hygiene so that they cannot shadow variables
+    // used by the field values
+    let provider_ident = Ident::new("provider", Span::mixed_site());
+    let level_ident = Ident::new("level", Span::mixed_site());
+    let keyword_ident = Ident::new("keyword", Span::mixed_site());
+    let metadata_ident = Ident::new("metadata", Span::mixed_site());
+    let value_idents = (0..fields.len())
+        .map(|index| Ident::new(&format!("value_{index}"), Span::mixed_site()))
+        .collect::<Vec<_>>();
+    let field_data_idents = (0..fields.len())
+        .map(|index| Ident::new(&format!("field_data_{index}"), Span::mixed_site()))
+        .collect::<Vec<_>>();
+
+    quote! {
+        {
+            let #provider_ident: &::wdk::trace_logging::TraceLoggingProvider = &#provider;
+            let #level_ident: ::wdk::trace_logging::Level = #level;
+            let #keyword_ident: u64 = #keyword;
+            if #provider_ident.is_enabled(#level_ident, #keyword_ident) {
+                fn event_metadata<
+                    #(#type_params: ::wdk::trace_logging::TraceLoggingField + ?::core::marker::Sized),*
+                >(#(_: &#type_params),*) -> [u8; #metadata_size] {
+                    const { [#(#metadata_bytes),*] }
+                }
+
+                #(let #value_idents = &(#values);)*
+                let #metadata_ident = event_metadata(#(#value_idents),*);
+                #(
+                    let #field_data_idents =
+                        ::wdk::trace_logging::TraceLoggingField::field_data(#value_idents);
+                )*
+                #provider_ident.__write(
+                    #level_ident,
+                    #opcode,
+                    #keyword_ident,
+                    &[
+                        #provider_ident.__metadata_descriptor(),
+                        ::wdk::trace_logging::EventDataDescriptor::event_metadata(&#metadata_ident),
+                        #(
+                            #field_data_idents.__length_prefix_descriptor(),
+                            #field_data_idents.__data_descriptor(),
+                        )*
+                    ],
+                );
+            }
+        }
+    }
+}
+
+/// Generate the bytes of a nul-terminated name in `TraceLogging` metadata
+fn nul_terminated_name_bytes(name: &str, span: Span) -> Result<Vec<TokenStream2>> {
+    if name.contains('\0') {
+        return Err(Error::new(
+            span,
+            "TraceLogging names cannot contain nul characters",
+        ));
+    }
+    Ok(name
+        .bytes()
+        .chain([0])
+        .map(|byte| {
+            let byte = Literal::u8_unsuffixed(byte);
+            quote! { #byte }
+        })
+        .collect())
+}
+
+fn callback_impl(attribute_tokens: TokenStream2, item_tokens: TokenStream2) -> TokenStream2 {
+    let callback_type = match parse2::<Ident>(attribute_tokens) {
+        Ok(callback_type) => callback_type,
+        Err(err) => {
+            return Error::new(
+                err.span(),
+                "expected the name of a WDF callback typedef (ex. \
+                 `#[callback(EVT_WDF_DRIVER_DEVICE_ADD)]`)",
+            )
+            .to_compile_error();
+        }
+    };
+
+    let item_fn = match parse2::<ItemFn>(item_tokens) {
+        Ok(item_fn) => item_fn,
+        Err(err) => return err.to_compile_error(),
+    };
+    if let Err(err) = validate_callback_fn_signature(&item_fn.sig) {
+        return err.to_compile_error();
+    }
+
+    quote! {
+        ::wdk::wdf::__wdf_callback! { #callback_type, #item_fn }
+    }
+}
+
+/// Validate that a function with the given [`Signature`] can be called by a
+/// generated callback trampoline
+fn validate_callback_fn_signature(signature: &Signature) -> Result<()> {
+    if let Some(constness) = &signature.constness {
+        return Err(Error::new(
+            constness.span,
+            "#[callback] cannot be applied to const functions",
+        ));
+    }
+    if let Some(asyncness) = &signature.asyncness {
+        return Err(Error::new(
+            asyncness.span,
+            "#[callback] cannot be applied to async functions",
+        ));
+    }
+    if let Some(unsafety) = &signature.unsafety {
+        return Err(Error::new(
+            unsafety.span,
+            "#[callback] cannot be applied to unsafe functions, since the generated trampoline is \
+             already unsafe",
+        ));
+    }
+    if let Some(abi) = &signature.abi {
+        return Err(Error::new_spanned(
+            abi,
+            "#[callback] cannot be applied to extern functions, since the generated trampoline is \
+             already extern \"C\"",
+        ));
+    }
+    if !signature.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &signature.generics,
+            "#[callback] cannot be applied to generic functions",
+        ));
+    }
+    if let Some(receiver) = signature.receiver() {
+        return Err(Error::new_spanned(
+            receiver,
+            "#[callback] can only be applied to free functions",
+        ));
+    }
+    Ok(())
+}
+
+/// Struct storing the input tokens directly parsed from calls to
+/// `wdf_callback_trampoline` macro
+struct CallbackTrampolineInputs {
+    /// Path to file where generated type information resides.
+    types_path: LitStr,
+    /// Name of the typedef of the callback
+    callback_type: Ident,
+    /// Function called by the trampoline
+    item_fn: ItemFn,
+}
+
+impl Parse for CallbackTrampolineInputs {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let types_path = input.parse::<LitStr>()?;
+        input.parse::<Token![,]>()?;
+        let callback_type = input.parse::<Ident>()?;
+        input.parse::<Token![,]>()?;
+        let item_fn = input.parse::<ItemFn>()?;
+
+        Ok(Self {
+            types_path,
+            callback_type,
+            item_fn,
+        })
+    }
+}
+
+fn wdf_callback_trampoline_impl(input_tokens: TokenStream2) -> TokenStream2 {
+    let CallbackTrampolineInputs {
+        types_path,
+        callback_type,
+        item_fn,
+    } = match parse2::<CallbackTrampolineInputs>(input_tokens) {
+        Ok(inputs) => inputs,
+        Err(err) => return err.to_compile_error(),
+    };
+
+    parse_types_ast(&types_path)
+        .and_then(|types_ast| generate_callback_trampoline(&types_ast, &callback_type, item_fn))
+        .unwrap_or_else(|err| err.to_compile_error())
+}
+
+/// Generate the trampoline of `item_fn`, whose signature is that of the
+/// `callback_type` typedef found in bindgen-generated types information.
+///
+/// The annotated function is renamed, and the trampoline takes its place in a
+/// private module (so that the parameter types of the typedef can be glob
+/// imported from `wdk_sys`), from which it is re-exported with the visibility
+/// of the annotated function.
+fn generate_callback_trampoline(
+    types_ast: &File,
+    callback_type: &Ident,
+    item_fn: ItemFn,
+) -> Result<TokenStream2> {
+    let bare_fn_type = find_callback_bare_fn_type(types_ast, callback_type)?;
+    if bare_fn_type.inputs.len() != item_fn.sig.inputs.len() {
+        return Err(Error::new_spanned(
+            &item_fn.sig,
+            format!(
+                "{callback_type} takes {} parameters, but {} takes {}",
+                bare_fn_type.inputs.len(),
+                item_fn.sig.ident,
+                item_fn.sig.inputs.len()
+            ),
+        ));
+    }
+    let parameters = bare_fn_type
+        .inputs
+        .iter()
+        .map(to_snake_case_parameter)
+        .collect::<Punctuated<BareFnArg, Token![,]>>();
+    let parameter_identifiers = parameters
+        .iter()
+        .map(|parameter| {
+            parameter
+                .name
+                .as_ref()
+                .map(|(ident, _)| ident)
+                .ok_or_else(|| {
+                    Error::new(
+                        callback_type.span(),
+                        format!("Expected every parameter of {callback_type} to be named"),
+                    )
+                })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let return_type = compute_return_type(bare_fn_type);
+
+    let ItemFn {
+        attrs,
+        vis,
+        mut sig,
+        block,
+    } = item_fn;
+    let fn_ident = sig.ident.clone();
+    let callback_fn_ident = format_ident!("__{fn_ident}_callback");
+    let trampoline_module_ident = format_ident!("__{fn_ident}_trampoline");
+    sig.ident = callback_fn_ident.clone();
+
+    // Documentation belongs to the trampoline, which takes the place of the
+    // annotated function, while other attributes (ex. lints) apply to the body
+    // of the annotated function. `cfg` attributes apply to everything that is
+    // generated.
+    let (doc_attrs, attrs): (Vec<_>, Vec<_>) = attrs
+        .into_iter()
+        .partition(|attr| attr.path().is_ident("doc"));
+    let cfg_attrs = attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("cfg"))
+        .collect::<Vec<_>>();
+
+    Ok(quote! {
+        #(#attrs)*
+        #sig #block
+
+        #(#cfg_attrs)*
+        mod #trampoline_module_ident {
+            // Glob import types from wdk_sys, since bindgen generates the parameter types of the
+            // typedef without paths
+            use wdk_sys::*;
+
+            #(#doc_attrs)*
+            pub unsafe extern "C" fn #fn_ident(#parameters) #return_type {
+                ::wdk::wdf::IntoRawCallbackReturn::into_raw_callback_return(
+                    super::#callback_fn_ident(
+                        #(
+                            // SAFETY: The arguments are passed by WDF to the callback
+                            unsafe {
+                                ::wdk::wdf::FromRawCallbackArg::from_raw_callback_arg(
+                                    #parameter_identifiers,
+                                )
+                            }
+                        ),*
+                    ),
+                )
+            }
+        }
+
+        #(#cfg_attrs)*
+        #vis use #trampoline_module_ident::#fn_ident;
+
+        // Validate that the trampoline can be registered as the callback
+        #(#cfg_attrs)*
+        const _: wdk_sys::#callback_type = ::core::option::Option::Some(#fn_ident);
+    })
+}
+
+/// Find the [`TypeBareFn`] of the `callback_type` typedef in bindgen-generated
+/// types information. `PFN_` typedefs of callbacks are aliases of their `EVT_`
+/// typedefs, so aliases are followed.
+fn find_callback_bare_fn_type<'a>(
+    types_ast: &'a File,
+    callback_type: &Ident,
+) -> Result<&'a TypeBareFn> {
+    let mut type_alias = find_type_alias_definition(types_ast, callback_type)?;
+    while let Type::Path(TypePath { qself: None, path }) = type_alias.ty.as_ref() {
+        let Some(aliased_type) = path.get_ident() else {
+            break;
+        };
+        type_alias = find_type_alias_definition(types_ast, aliased_type)?;
+    }
+
+    let fn_pointer_definition = extract_fn_pointer_definition(type_alias, callback_type.span())?;
+    extract_bare_fn_type(fn_pointer_definition, callback_type.span())
+}
+
 fn parse_types_ast(path: &LitStr) -> Result<File> {
     let types_path = PathBuf::from(path.value());
     let types_path = match types_path.canonicalize() {
@@ -681,26 +1288,28 @@ fn compute_fn_parameters(
         .inputs
         .iter()
         .skip(1)
-        // transform argument names to snake_case with trailing underscores to lessen likelihood
-        // of shadowing issues
-        .map(|fn_arg| {
-            let arg_name = fn_arg.name.as_ref().map(|(ident, colon_token)| {
-                let modified_name = {
-                    let mut name = ident.to_string().to_snake_case();
-                    name.push_str("__");
-                    name
-                };
-                (Ident::new(&modified_name, ident.span()), *colon_token)
-            });
-
-            BareFnArg {
-                name: arg_name,
-                ..fn_arg.clone()
-            }
-        })
+        .map(to_snake_case_parameter)
         .collect())
 }
 
+/// Transform the name of a function parameter to `snake_case` with trailing
+/// underscores to lessen likelihood of shadowing issues
+fn to_snake_case_parameter(fn_arg: &BareFnArg) -> BareFnArg {
+    let arg_name = fn_arg.name.as_ref().map(|(ident, colon_token)| {
+        let modified_name = {
+            let mut name = ident.to_string().to_snake_case();
+            name.push_str("__");
+            name
+        };
+        (Ident::new(&modified_name, ident.span()), *colon_token)
+    });
+
+    BareFnArg {
+        name: arg_name,
+        ..fn_arg.clone()
+    }
+}
+
 /// Compute the return type based on the function defintion
 ///
 /// # Examples
@@ -823,6 +1432,292 @@ fn snake_case_with_leading_underscore() {
         }
     }
 
+    mod max_stack {
+        use super::*;
+
+        #[test]
+        fn valid_input() {
+            let attribute_tokens = quote! { 512 };
+            let item_tokens = quote! {
+                /// Dispatch routine
+                pub extern "C" fn dispatch(irp: PIRP) -> NTSTATUS {
+                    process(irp)
+                }
+            };
+            let expected = quote! {
+                /// Dispatch routine
+                pub extern "C" fn dispatch(irp: PIRP) -> NTSTATUS {
+                    #[used]
+                    #[link_section = ".wdkstk"]
+                    static STACK_FRAME_LIMIT: ::wdk::stack::StackFrameLimit =
+                        ::wdk::stack::StackFrameLimit::new(dispatch as *const (), 512);
+
+                    ::wdk::stack::check_remaining_stack(
+                        ::core::concat!(::core::module_path!(), "::", ::core::stringify!(dispatch)),
+                        512,
+                    );
+
+                    {
+                        process(irp)
+                    }
+                }
+            };
+
+            pretty_assert_eq!(
+                max_stack_impl(attribute_tokens, item_tokens).to_string(),
+                expected.to_string()
+            );
+        }
+
+        #[test]
+        fn invalid_max_frame_size() {
+            let attribute_tokens = quote! { "512" };
+            let item_tokens = quote! { fn dispatch() {} };
+
+            assert!(max_stack_impl(attribute_tokens, item_tokens)
+                .to_string()
+                .contains("expected the maximum stack frame size in bytes"));
+        }
+
+        #[test]
+        fn generic_function() {
+            let attribute_tokens = quote! { 512 };
+            let item_tokens = quote! { fn dispatch<T>(value: T) {} };
+
+            assert!(max_stack_impl(attribute_tokens, item_tokens)
+                .to_string()
+                .contains("cannot be applied to generic functions"));
+        }
+
+        #[test]
+        fn method() {
+            let attribute_tokens = quote! { 512 };
+            let item_tokens = quote! { fn dispatch(&self) {} };
+
+            assert!(max_stack_impl(attribute_tokens, item_tokens)
+                .to_string()
+                .contains("can only be applied to free functions"));
+        }
+    }
+
+    mod callback {
+        use super::*;
+
+        /// Snippet of a bindgen-generated file containing the types information
+        /// of a WDF callback
+        fn types_ast() -> File {
+            parse_quote! {
+                pub type EVT_WDF_DRIVER_DEVICE_ADD = ::core::option::Option<
+                    unsafe extern "C" fn(Driver: WDFDRIVER, DeviceInit: PWDFDEVICE_INIT) -> NTSTATUS,
+                >;
+                pub type PFN_WDF_DRIVER_DEVICE_ADD = EVT_WDF_DRIVER_DEVICE_ADD;
+            }
+        }
+
+        #[test]
+        fn valid_input() {
+            let attribute_tokens = quote! { EVT_WDF_DRIVER_DEVICE_ADD };
+            let item_tokens = quote! {
+                fn evt_device_add(driver: WDFDRIVER, device_init: PWDFDEVICE_INIT) -> NTSTATUS {
+                    STATUS_SUCCESS
+                }
+            };
+            let expected = quote! {
+                ::wdk::wdf::__wdf_callback! {
+                    EVT_WDF_DRIVER_DEVICE_ADD,
+                    fn evt_device_add(driver: WDFDRIVER, device_init: PWDFDEVICE_INIT) -> NTSTATUS {
+                        STATUS_SUCCESS
+                    }
+                }
+            };
+
+            pretty_assert_eq!(
+                callback_impl(attribute_tokens, item_tokens).to_string(),
+                expected.to_string()
+            );
+        }
+
+        #[test]
+        fn invalid_callback_type() {
+            let attribute_tokens = quote! { "EVT_WDF_DRIVER_DEVICE_ADD" };
+            let item_tokens = quote! { fn evt_device_add() {} };
+
+            assert!(callback_impl(attribute_tokens, item_tokens)
+                .to_string()
+                .contains("expected the name of a WDF callback typedef"));
+        }
+
+        #[test]
+        fn unsafe_function() {
+            let attribute_tokens = quote! { EVT_WDF_DRIVER_DEVICE_ADD };
+            let item_tokens = quote! { unsafe fn evt_device_add() {} };
+
+            assert!(callback_impl(attribute_tokens, item_tokens)
+                .to_string()
+                .contains("cannot be applied to unsafe functions"));
+        }
+
+        #[test]
+        fn trampoline() {
+            let item_fn = parse_quote! {
+                /// Device add callback
+                #[allow(unused_variables)]
+                pub fn evt_device_add(
+                    driver: WDFDRIVER,
+                    device_init: PWDFDEVICE_INIT,
+                ) -> Result<(), NTSTATUS> {
+                    Ok(())
+                }
+            };
+            let expected = quote! {
+                #[allow(unused_variables)]
+                fn __evt_device_add_callback(
+                    driver: WDFDRIVER,
+                    device_init: PWDFDEVICE_INIT,
+                ) -> Result<(), NTSTATUS> {
+                    Ok(())
+                }
+
+                mod __evt_device_add_trampoline {
+                    use wdk_sys::*;
+
+                    /// Device add callback
+                    pub unsafe extern "C" fn evt_device_add(
+                        driver__: WDFDRIVER,
+                        device_init__: PWDFDEVICE_INIT
+                    ) -> NTSTATUS {
+                        ::wdk::wdf::IntoRawCallbackReturn::into_raw_callback_return(
+                            super::__evt_device_add_callback(
+                                unsafe {
+                                    ::wdk::wdf::FromRawCallbackArg::from_raw_callback_arg(driver__,)
+                                },
+                                unsafe {
+                                    ::wdk::wdf::FromRawCallbackArg::from_raw_callback_arg(
+                                        device_init__,
+                                    )
+                                }
+                            ),
+                        )
+                    }
+                }
+
+                pub use __evt_device_add_trampoline::evt_device_add;
+
+                const _: wdk_sys::PFN_WDF_DRIVER_DEVICE_ADD =
+                    ::core::option::Option::Some(evt_device_add);
+            };
+
+            pretty_assert_eq!(
+                generate_callback_trampoline(
+                    &types_ast(),
+                    &format_ident!("PFN_WDF_DRIVER_DEVICE_ADD"),
+                    item_fn
+                )
+                .unwrap()
+                .to_string(),
+                expected.to_string()
+            );
+        }
+
+        #[test]
+        fn parameter_count_mismatch() {
+            let item_fn = parse_quote! {
+                fn evt_device_add(driver: WDFDRIVER) -> NTSTATUS {
+                    STATUS_SUCCESS
+                }
+            };
+
+            pretty_assert_eq!(
+                generate_callback_trampoline(
+                    &types_ast(),
+                    &format_ident!("EVT_WDF_DRIVER_DEVICE_ADD"),
+                    item_fn
+                )
+                .unwrap_err()
+                .to_string(),
+                "EVT_WDF_DRIVER_DEVICE_ADD takes 2 parameters, but evt_device_add takes 1"
+            );
+        }
+    }
+
+    mod trace_logging_write {
+        use super::*;
+
+        #[test]
+        fn valid_input() {
+            let input_tokens = quote! {
+                PROVIDER, "Ev", level: Level::Information, r#id = 3_u32,
+            };
+            let expected_metadata_bytes = quote! {
+                11, 0, 0, 69, 118, 0, 105, 100, 0,
+                <T0 as ::wdk::trace_logging::TraceLoggingField>::IN_TYPE | 128u8,
+                <T0 as ::wdk::trace_logging::TraceLoggingField>::OUT_TYPE
+            };
+            let expected = quote! {
+                {
+                    let provider: &::wdk::trace_logging::TraceLoggingProvider = &PROVIDER;
+                    let level: ::wdk::trace_logging::Level = Level::Information;
+                    let keyword: u64 = 0;
+                    if provider.is_enabled(level, keyword) {
+                        fn event_metadata<
+                            T0: ::wdk::trace_logging::TraceLoggingField + ?::core::marker::Sized
+                        >(_: &T0) -> [u8; 11] {
+                            const { [#expected_metadata_bytes] }
+                        }
+
+                        let value_0 = &(3_u32);
+                        let metadata = event_metadata(value_0);
+                        let field_data_0 =
+                            ::wdk::trace_logging::TraceLoggingField::field_data(value_0);
+                        provider.__write(
+                            level,
+                            0,
+                            keyword,
+                            &[
+                                provider.__metadata_descriptor(),
+                                ::wdk::trace_logging::EventDataDescriptor::event_metadata(&metadata),
+                                field_data_0.__length_prefix_descriptor(),
+                                field_data_0.__data_descriptor(),
+                            ],
+                        );
+                    }
+                }
+            };
+
+            pretty_assert_eq!(
+                trace_logging_write_impl(input_tokens).to_string(),
+                expected.to_string()
+            );
+        }
+
+        #[test]
+        fn unknown_option() {
+            let input_tokens = quote! { PROVIDER, "Event", channel: 11 };
+
+            assert!(trace_logging_write_impl(input_tokens)
+                .to_string()
+                .contains("expected one of the `level`, `keyword` or `opcode` options"));
+        }
+
+        #[test]
+        fn duplicate_option() {
+            let input_tokens = quote! { PROVIDER, "Event", keyword: 1, keyword: 2 };
+
+            assert!(trace_logging_write_impl(input_tokens)
+                .to_string()
+                .contains("duplicate `keyword` option"));
+        }
+
+        #[test]
+        fn nul_in_event_name() {
+            let input_tokens = quote! { PROVIDER, "Ev\0ent" };
+
+            assert!(trace_logging_write_impl(input_tokens)
+                .to_string()
+                .contains("names cannot contain nul characters"));
+        }
+    }
+
     mod inputs {
         use super::*;
 
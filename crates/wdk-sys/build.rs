@@ -7,7 +7,9 @@
 //! and generates the relevant bindings to WDK APIs.
 
 use std::{
+    collections::HashSet,
     env,
+    fmt::Write as _,
     fs::File,
     io::Write,
     panic,
@@ -17,6 +19,8 @@
 };
 
 use anyhow::Context;
+#[cfg(feature = "spb")]
+use bindgen::callbacks::{ItemInfo, ParseCallbacks};
 use bindgen::CodegenConfig;
 use tracing::{info, info_span, trace, Span};
 use tracing_subscriber::{
@@ -24,7 +28,11 @@
     EnvFilter,
 };
 use wdk_build::{
+    bindgen_generation::GenerationCoordinator,
+    build_provenance,
     configure_wdk_library_build_and_then,
+    diagnostics,
+    pregenerated_bindings::{export_bindings, import_bindings, BindingsSettings, BindingsSource},
     ApiSubset,
     BuilderExt,
     Config,
@@ -32,6 +40,7 @@
     DriverConfig,
     KmdfConfig,
     UmdfConfig,
+    LINKER_PLUGIN_LTO_C_COMPILER_FLAG,
 };
 
 const OUT_DIR_PLACEHOLDER: &str =
@@ -41,6 +50,59 @@
 const WDF_FUNCTION_COUNT_PLACEHOLDER: &str =
     "<PLACEHOLDER FOR EXPRESSION FOR NUMBER OF WDF FUNCTIONS IN `wdk_sys::WdfFunctions`";
 
+/// Prefix of the `static const` variables that the values of IOCTLs are
+/// assigned to, so that bindgen can generate constants for them
+#[cfg(feature = "spb")]
+const IOCTL_CONSTANT_PREFIX: &str = "WDK_IOCTL_";
+
+/// SPB IOCTLs that constants are generated for
+#[cfg(feature = "spb")]
+const SPB_IOCTL_NAMES: &[&str] = &[
+    "IOCTL_SPB_LOCK_CONTROLLER",
+    "IOCTL_SPB_UNLOCK_CONTROLLER",
+    "IOCTL_SPB_LOCK_CONNECTION",
+    "IOCTL_SPB_UNLOCK_CONNECTION",
+    "IOCTL_SPB_EXECUTE_SEQUENCE",
+    "IOCTL_SPB_FULL_DUPLEX",
+];
+
+/// Value of a `GUID` as its `Data1`, `Data2`, `Data3` and `Data4` fields
+type GuidValue = (u32, u16, u16, [u8; 8]);
+
+/// Header whose `DEFINE_DEVPROPKEY` device property keys are generated as
+/// constants in the `devprop` module
+const DEVPROPKEY_HEADER: &str = "devpkey.h";
+
+/// Header that defines the symbolic names and messages of `NTSTATUS` values
+const NTSTATUS_HEADER: &str = "ntstatus.h";
+
+/// Symbolic name, value and message of an `NTSTATUS` definition in
+/// [`NTSTATUS_HEADER`]
+type NtStatusDefinition = (String, i32, Option<String>);
+
+/// Headers whose `DEFINE_GUID` GUIDs are generated as constants in the base
+/// module (`ntddk` or `windows`). Headers that are not in the include paths of
+/// the driver model are skipped.
+const BASE_GUID_HEADERS: &[&str] = &[
+    "wdmguid.h",
+    "devguid.h",
+    "usbiodef.h",
+    "ntddkbd.h",
+    "ntddmou.h",
+    "ntddser.h",
+    "ntddstor.h",
+];
+
+/// Headers whose `DEFINE_GUID` GUIDs are generated as constants in the `hid`
+/// module
+#[cfg(feature = "hid")]
+const HID_GUID_HEADERS: &[&str] = &["hidclass.h"];
+
+/// File stem of the C file that bindgen generates with wrappers for the
+/// `FORCEINLINE` SpbCx functions, and of the library it is compiled into
+#[cfg(feature = "spb")]
+const SPBCX_STATIC_FNS_FILE_STEM: &str = "spbcx_static_fns";
+
 const WDF_FUNCTION_COUNT_DECLARATION_EXTERNAL_SYMBOL: &str =
     "// SAFETY: `crate::WdfFunctionCount` is generated as a mutable static, but is not supposed \
      to be ever mutated by WDF.
@@ -109,6 +171,20 @@ macro_rules! call_unsafe_wdf_function_binding {{
             $($tt)*
         )
     }}
+}}
+
+/// Generates the `extern "C"` trampoline of a function annotated with
+/// `wdk::callback`. This is an implementation detail of `wdk::callback`, and
+/// should not be used directly.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __wdf_callback {{
+    ( $($tt:tt)* ) => {{
+        $crate::__proc_macros::wdf_callback_trampoline! {{
+            r"{OUT_DIR_PLACEHOLDER}",
+            $($tt)*
+        }}
+    }}
 }}"#
     )
 });
@@ -124,6 +200,217 @@ macro_rules! call_unsafe_wdf_function_binding {{
 ",
     )
 });
+
+/// Items that must always be generated when the `prune-unused-bindings`
+/// feature is enabled, since they are referenced by code generated by this
+/// build script
+#[cfg(feature = "prune-unused-bindings")]
+const PRUNED_BINDINGS_REQUIRED_ITEMS: &[&str] = &[
+    "WDFFUNC",
+    "_WDFFUNCENUM",
+    "WdfFunctionCount",
+    "WdfFunctions_.*",
+    "WdfDriverGlobals",
+    "WdfMinimumVersionRequired",
+    "GUID",
+    // Referenced by the `resources` module
+    "CmResourceType.*",
+    "CM_RESOURCE_INTERRUPT_.*",
+    "CM_RESOURCE_MEMORY_LARGE_.*",
+    // Referenced by the `pci` module
+    "PCI_COMMON_HEADER",
+    "PCI_CARDBUS_BRIDGE_TYPE",
+    "PCI_MULTIFUNCTION",
+    "PCI_STATUS_CAPABILITIES_LIST",
+    "PCI_WHICHSPACE_.*",
+    "PCI_CAPABILITY_ID_.*",
+];
+
+/// Regex matching all the items referenced by crates that depend on `wdk-sys`,
+/// or `None` if the usage scan failed and full bindings should be generated
+#[cfg(feature = "prune-unused-bindings")]
+static PRUNED_BINDINGS_ALLOWLIST: LazyLock<Option<String>> = LazyLock::new(|| {
+    match wdk_build::usage_scan::scan_wdk_sys_dependents() {
+        Ok(identifiers) => {
+            info!(
+                "Pruning bindings to the {} identifiers referenced by crates depending on wdk-sys",
+                identifiers.len()
+            );
+            Some(
+                identifiers
+                    .iter()
+                    .flat_map(|identifier| {
+                        // WDF functions are called via the `call_unsafe_wdf_function_binding`
+                        // macro, which requires the function pointer type of the WDF function
+                        identifier
+                            .starts_with("Wdf")
+                            .then(|| format!("PFN_{}", identifier.to_uppercase()))
+                            .into_iter()
+                            .chain(std::iter::once(identifier.clone()))
+                    })
+                    .chain(
+                        PRUNED_BINDINGS_REQUIRED_ITEMS
+                            .iter()
+                            .map(ToString::to_string),
+                    )
+                    .chain(
+                        LAYOUT_ASSERTIONS
+                            .iter()
+                            .map(|layout_assertion| layout_assertion.type_name.to_string()),
+                    )
+                    .collect::<Vec<_>>()
+                    .join("|"),
+            )
+        }
+        Err(error) => {
+            tracing::warn!(
+                "Failed to scan crates depending on wdk-sys for referenced identifiers. Falling \
+                 back to generating full bindings: {error:?}"
+            );
+            None
+        }
+    }
+});
+
+/// Prefix of the constants generated from the WDK headers by
+/// [`generate_layout_assertions`]
+const LAYOUT_CONSTANT_PREFIX: &str = "WDK_LAYOUT_";
+
+/// A struct whose size, alignment and field offsets are asserted at compile
+/// time to match the values computed from the WDK headers
+struct LayoutAssertion {
+    type_name: &'static str,
+    field_names: &'static [&'static str],
+    is_available: fn(&DriverConfig) -> bool,
+}
+
+/// Frequently used structs whose generated bindings are checked against the
+/// WDK headers, so that layout mismatches (ex. from bindgen or architecture
+/// changes) fail the build instead of corrupting memory at runtime
+const LAYOUT_ASSERTIONS: &[LayoutAssertion] = &[
+    LayoutAssertion {
+        type_name: "UNICODE_STRING",
+        field_names: &["Length", "MaximumLength", "Buffer"],
+        is_available: |_| true,
+    },
+    LayoutAssertion {
+        type_name: "IRP",
+        field_names: &[
+            "MdlAddress",
+            "Flags",
+            "AssociatedIrp",
+            "IoStatus",
+            "RequestorMode",
+            "Cancel",
+            "Tail",
+        ],
+        is_available: |driver_config| {
+            matches!(driver_config, DriverConfig::Wdm | DriverConfig::Kmdf(_))
+        },
+    },
+    LayoutAssertion {
+        type_name: "DEVICE_OBJECT",
+        field_names: &[
+            "DriverObject",
+            "NextDevice",
+            "CurrentIrp",
+            "Flags",
+            "DeviceExtension",
+            "DeviceType",
+            "StackSize",
+        ],
+        is_available: |driver_config| {
+            matches!(driver_config, DriverConfig::Wdm | DriverConfig::Kmdf(_))
+        },
+    },
+    LayoutAssertion {
+        type_name: "DRIVER_OBJECT",
+        field_names: &[
+            "DeviceObject",
+            "DriverExtension",
+            "DriverInit",
+            "DriverUnload",
+            "MajorFunction",
+        ],
+        is_available: |driver_config| {
+            matches!(driver_config, DriverConfig::Wdm | DriverConfig::Kmdf(_))
+        },
+    },
+    LayoutAssertion {
+        type_name: "CM_PARTIAL_RESOURCE_DESCRIPTOR",
+        field_names: &["Type", "ShareDisposition", "Flags", "u"],
+        is_available: |_| true,
+    },
+    LayoutAssertion {
+        type_name: "IO_RESOURCE_DESCRIPTOR",
+        field_names: &["Option", "Type", "ShareDisposition", "Flags", "u"],
+        is_available: |driver_config| {
+            matches!(driver_config, DriverConfig::Wdm | DriverConfig::Kmdf(_))
+        },
+    },
+    LayoutAssertion {
+        type_name: "PCI_COMMON_CONFIG",
+        field_names: &[
+            "VendorID",
+            "DeviceID",
+            "Command",
+            "Status",
+            "HeaderType",
+            "u",
+            "DeviceSpecific",
+        ],
+        is_available: |driver_config| {
+            matches!(driver_config, DriverConfig::Wdm | DriverConfig::Kmdf(_))
+        },
+    },
+    LayoutAssertion {
+        type_name: "BUS_INTERFACE_STANDARD",
+        field_names: &[
+            "Size",
+            "Version",
+            "Context",
+            "InterfaceReference",
+            "InterfaceDereference",
+            "TranslateBusAddress",
+            "GetDmaAdapter",
+            "SetBusData",
+            "GetBusData",
+        ],
+        is_available: |driver_config| {
+            matches!(driver_config, DriverConfig::Wdm | DriverConfig::Kmdf(_))
+        },
+    },
+    LayoutAssertion {
+        type_name: "WDF_DRIVER_CONFIG",
+        field_names: &[
+            "Size",
+            "EvtDriverDeviceAdd",
+            "EvtDriverUnload",
+            "DriverInitFlags",
+            "DriverPoolTag",
+        ],
+        is_available: |driver_config| {
+            matches!(driver_config, DriverConfig::Kmdf(_) | DriverConfig::Umdf(_))
+        },
+    },
+    LayoutAssertion {
+        type_name: "WDF_OBJECT_ATTRIBUTES",
+        field_names: &[
+            "Size",
+            "EvtCleanupCallback",
+            "EvtDestroyCallback",
+            "ExecutionLevel",
+            "SynchronizationScope",
+            "ParentObject",
+            "ContextSizeOverride",
+            "ContextTypeInfo",
+        ],
+        is_available: |driver_config| {
+            matches!(driver_config, DriverConfig::Kmdf(_) | DriverConfig::Umdf(_))
+        },
+    },
+];
+
 type GenerateFn = fn(&Path, &Config) -> Result<(), ConfigError>;
 
 const BINDGEN_FILE_GENERATORS_TUPLES: &[(&str, GenerateFn)] = &[
@@ -133,8 +420,78 @@ macro_rules! call_unsafe_wdf_function_binding {{
     ("wdf.rs", generate_wdf),
     ("hid.rs", generate_hid),
     ("spb.rs", generate_spb),
+    ("storport.rs", generate_storport),
+    ("wsk.rs", generate_wsk),
+    ("wfp.rs", generate_wfp),
+    ("kernel_streaming.rs", generate_kernel_streaming),
+    ("audio.rs", generate_audio),
+    ("usb.rs", generate_usb),
+    ("vmbus.rs", generate_vmbus),
+    ("layout_assertions.rs", generate_layout_assertions),
+];
+
+/// Files in `OUT_DIR` that are generated with bindgen, which are exported when
+/// [`BindingsSource::Bindgen`] has an output directory. Only the files for the
+/// enabled features and the driver model exist.
+const BINDGEN_GENERATED_FILE_NAMES: &[&str] = &[
+    "constants.rs",
+    "types.rs",
+    "ntddk.rs",
+    "windows.rs",
+    "wdf.rs",
+    "hid.rs",
+    "spb.rs",
+    "spb_ioctls.rs",
+    "spb-input.h",
+    "spbcx_static_fns.c",
+    "storport.rs",
+    "wsk.rs",
+    "wfp.rs",
+    "kernel_streaming.rs",
+    "audio.rs",
+    "usb.rs",
+    "vmbus.rs",
+    "layout_assertions.rs",
 ];
 
+/// Returns the settings of this build that change its bindings, which are
+/// recorded with exported bindings and checked against pre-generated bindings
+fn bindings_settings() -> BindingsSettings {
+    let features: &[&str] = &[
+        #[cfg(feature = "hid")]
+        "hid",
+        #[cfg(feature = "spb")]
+        "spb",
+        #[cfg(feature = "storport")]
+        "storport",
+        #[cfg(feature = "wsk")]
+        "wsk",
+        #[cfg(feature = "wfp")]
+        "wfp",
+        #[cfg(feature = "kernel-streaming")]
+        "kernel-streaming",
+        #[cfg(feature = "audio")]
+        "audio",
+        #[cfg(feature = "usb")]
+        "usb",
+        #[cfg(feature = "vmbus")]
+        "vmbus",
+    ];
+
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "prune-unused-bindings")] {
+            let pruned_items_allowlist = PRUNED_BINDINGS_ALLOWLIST.clone();
+        } else {
+            let pruned_items_allowlist = None;
+        }
+    }
+
+    BindingsSettings {
+        features: features.iter().map(ToString::to_string).collect(),
+        pruned_items_allowlist,
+    }
+}
+
 fn initialize_tracing() -> Result<(), ParseError> {
     let tracing_filter = EnvFilter::default()
         // Show up to INFO level by default
@@ -189,6 +546,66 @@ fn initialize_tracing() -> Result<(), ParseError> {
     Ok(())
 }
 
+/// Narrows the items generated by `builder` down to the items referenced by
+/// crates depending on `wdk-sys`, when the `prune-unused-bindings` feature is
+/// enabled. If the usage scan fails, `builder` is returned unmodified so that
+/// full bindings are generated.
+///
+/// When `include_file_allowlisted_api_subsets` is set, all items from the
+/// headers of file-allowlisted [`ApiSubset`]s (ex. `wdf.rs`) are kept as well,
+/// since bindings for those [`ApiSubset`]s are never pruned and may reference
+/// any of their types.
+fn allowlist_referenced_items(
+    builder: bindgen::Builder,
+    config: &Config,
+    include_file_allowlisted_api_subsets: bool,
+) -> bindgen::Builder {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "prune-unused-bindings")] {
+            let Some(allowlist) = PRUNED_BINDINGS_ALLOWLIST.as_deref() else {
+                return builder;
+            };
+
+            let mut builder = builder.allowlist_item(allowlist);
+            if include_file_allowlisted_api_subsets {
+                if let DriverConfig::Kmdf(_) | DriverConfig::Umdf(_) = config.driver_config {
+                    builder = builder.allowlist_file("(?i).*wdf.*");
+                }
+                for header_file in [
+                    #[cfg(feature = "hid")]
+                    ApiSubset::Hid,
+                    #[cfg(feature = "spb")]
+                    ApiSubset::Spb,
+                    #[cfg(feature = "storport")]
+                    ApiSubset::Storport,
+                    #[cfg(feature = "wsk")]
+                    ApiSubset::Wsk,
+                    #[cfg(feature = "wfp")]
+                    ApiSubset::Wfp,
+                    #[cfg(feature = "kernel-streaming")]
+                    ApiSubset::KernelStreaming,
+                    #[cfg(feature = "audio")]
+                    ApiSubset::Audio,
+                    #[cfg(feature = "usb")]
+                    ApiSubset::Usb,
+                    #[cfg(feature = "vmbus")]
+                    ApiSubset::Vmbus,
+                ]
+                .into_iter()
+                .flat_map(|api_subset| config.headers(api_subset))
+                {
+                    builder = builder.allowlist_file(format!("(?i).*{header_file}.*"));
+                }
+            }
+            builder
+        } else {
+            let _ = (config, include_file_allowlisted_api_subsets); // Silence unused variable warnings when prune-unused-bindings feature is not enabled
+
+            builder
+        }
+    }
+}
+
 fn generate_constants(out_path: &Path, config: &Config) -> Result<(), ConfigError> {
     info!("Generating bindings to WDK: constants.rs");
 
@@ -199,12 +616,30 @@ fn generate_constants(out_path: &Path, config: &Config) -> Result<(), ConfigErro
         ApiSubset::Hid,
         #[cfg(feature = "spb")]
         ApiSubset::Spb,
+        #[cfg(feature = "storport")]
+        ApiSubset::Storport,
+        #[cfg(feature = "wsk")]
+        ApiSubset::Wsk,
+        #[cfg(feature = "wfp")]
+        ApiSubset::Wfp,
+        #[cfg(feature = "kernel-streaming")]
+        ApiSubset::KernelStreaming,
+        #[cfg(feature = "audio")]
+        ApiSubset::Audio,
+        #[cfg(feature = "usb")]
+        ApiSubset::Usb,
+        #[cfg(feature = "vmbus")]
+        ApiSubset::Vmbus,
     ]);
     trace!(header_contents = ?header_contents);
 
-    let bindgen_builder = bindgen::Builder::wdk_default(config)?
-        .with_codegen_config(CodegenConfig::VARS)
-        .header_contents("constants-input.h", &header_contents);
+    let bindgen_builder = allowlist_referenced_items(
+        bindgen::Builder::wdk_default(config)?
+            .with_codegen_config(CodegenConfig::VARS)
+            .header_contents("constants-input.h", &header_contents),
+        config,
+        false,
+    );
     trace!(bindgen_builder = ?bindgen_builder);
 
     Ok(bindgen_builder
@@ -223,12 +658,30 @@ fn generate_types(out_path: &Path, config: &Config) -> Result<(), ConfigError> {
         ApiSubset::Hid,
         #[cfg(feature = "spb")]
         ApiSubset::Spb,
+        #[cfg(feature = "storport")]
+        ApiSubset::Storport,
+        #[cfg(feature = "wsk")]
+        ApiSubset::Wsk,
+        #[cfg(feature = "wfp")]
+        ApiSubset::Wfp,
+        #[cfg(feature = "kernel-streaming")]
+        ApiSubset::KernelStreaming,
+        #[cfg(feature = "audio")]
+        ApiSubset::Audio,
+        #[cfg(feature = "usb")]
+        ApiSubset::Usb,
+        #[cfg(feature = "vmbus")]
+        ApiSubset::Vmbus,
     ]);
     trace!(header_contents = ?header_contents);
 
-    let bindgen_builder = bindgen::Builder::wdk_default(config)?
-        .with_codegen_config(CodegenConfig::TYPES)
-        .header_contents("types-input.h", &header_contents);
+    let bindgen_builder = allowlist_referenced_items(
+        bindgen::Builder::wdk_default(config)?
+            .with_codegen_config(CodegenConfig::TYPES)
+            .header_contents("types-input.h", &header_contents),
+        config,
+        true,
+    );
     trace!(bindgen_builder = ?bindgen_builder);
 
     Ok(bindgen_builder
@@ -247,9 +700,13 @@ fn generate_base(out_path: &Path, config: &Config) -> Result<(), ConfigError> {
     let header_contents = config.bindgen_header_contents([ApiSubset::Base]);
     trace!(header_contents = ?header_contents);
 
-    let bindgen_builder = bindgen::Builder::wdk_default(config)?
-        .with_codegen_config((CodegenConfig::TYPES | CodegenConfig::VARS).complement())
-        .header_contents(&format!("{outfile_name}-input.h"), &header_contents);
+    let bindgen_builder = allowlist_referenced_items(
+        bindgen::Builder::wdk_default(config)?
+            .with_codegen_config((CodegenConfig::TYPES | CodegenConfig::VARS).complement())
+            .header_contents(&format!("{outfile_name}-input.h"), &header_contents),
+        config,
+        false,
+    );
     trace!(bindgen_builder = ?bindgen_builder);
 
     Ok(bindgen_builder
@@ -330,24 +787,46 @@ fn generate_spb(out_path: &Path, config: &Config) -> Result<(), ConfigError> {
             let header_contents = config.bindgen_header_contents([ApiSubset::Base, ApiSubset::Wdf, ApiSubset::Spb]);
             trace!(header_contents = ?header_contents);
 
+            // The input header is written to disk, instead of being passed to bindgen as
+            // `header_contents`, so that the C file with the wrappers of the SpbCx inline
+            // functions can include it
+            let spb_input_header_path = out_path.join("spb-input.h");
+            std::fs::write(&spb_input_header_path, &header_contents)?;
+
             let bindgen_builder = {
                  let mut builder = bindgen::Builder::wdk_default(config)?
                 .with_codegen_config((CodegenConfig::TYPES | CodegenConfig::VARS).complement())
-                .header_contents("spb-input.h", &header_contents);
+                .header(spb_input_header_path.to_string_lossy());
 
                 // Only allowlist files in the spb-specific files to avoid duplicate definitions
                 for header_file in config.headers(ApiSubset::Spb)
                 {
                     builder = builder.allowlist_file(format!("(?i).*{header_file}.*"));
                 }
+
+                // The SpbCx functions (ex. `SpbDeviceInitConfig`) are `FORCEINLINE` functions
+                // that dispatch through the SpbCx function table, so they have no symbols to
+                // link against. bindgen generates C wrappers for them, which are compiled below.
+                if let DriverConfig::Kmdf(_) = config.driver_config {
+                    builder = builder
+                        .wrap_static_fns(true)
+                        .wrap_static_fns_path(out_path.join(SPBCX_STATIC_FNS_FILE_STEM));
+                }
                 builder
             };
             trace!(bindgen_builder = ?bindgen_builder);
 
-            Ok(bindgen_builder
+            bindgen_builder
                 .generate()
                 .expect("Bindings should succeed to generate")
-                .write_to_file(out_path.join("spb.rs"))?)
+                .write_to_file(out_path.join("spb.rs"))?;
+
+            if let DriverConfig::Kmdf(_) = config.driver_config {
+                compile_spbcx_static_fns(out_path, config)?;
+                link_spbcx_stubs(config)?;
+            }
+
+            generate_spb_ioctls(out_path, config, &header_contents)
         } else {
             let _ = (out_path, config); // Silence unused variable warnings when spb feature is not enabled
 
@@ -358,11 +837,416 @@ fn generate_spb(out_path: &Path, config: &Config) -> Result<(), ConfigError> {
     }
 }
 
+fn generate_storport(out_path: &Path, config: &Config) -> Result<(), ConfigError> {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "storport")] {
+            if let DriverConfig::Wdm | DriverConfig::Kmdf(_) = config.driver_config {
+                info!("Generating bindings to WDK: storport.rs");
+
+                let header_contents = config.bindgen_header_contents([ApiSubset::Base, ApiSubset::Wdf, ApiSubset::Storport]);
+                trace!(header_contents = ?header_contents);
+
+                let bindgen_builder = {
+                     let mut builder = bindgen::Builder::wdk_default(config)?
+                    .with_codegen_config((CodegenConfig::TYPES | CodegenConfig::VARS).complement())
+                    .header_contents("storport-input.h", &header_contents);
+
+                    // Only allowlist files in the storport-specific files to avoid duplicate definitions
+                    for header_file in config.headers(ApiSubset::Storport)
+                    {
+                        builder = builder.allowlist_file(format!("(?i).*{header_file}.*"));
+                    }
+                    builder
+                };
+                trace!(bindgen_builder = ?bindgen_builder);
+
+                Ok(bindgen_builder
+                    .generate()
+                    .expect("Bindings should succeed to generate")
+                    .write_to_file(out_path.join("storport.rs"))?)
+            } else {
+                let _ = out_path; // Silence unused variable warnings when storport.rs is not generated
+
+                info!(
+                    "Skipping storport.rs generation since driver_config is {:#?}",
+                    config.driver_config
+                );
+                Ok(())
+            }
+        } else {
+            let _ = (out_path, config); // Silence unused variable warnings when storport feature is not enabled
+
+            info!(
+            "Skipping storport.rs generation since storport feature is not enabled");
+            Ok(())
+        }
+    }
+}
+
+fn generate_wsk(out_path: &Path, config: &Config) -> Result<(), ConfigError> {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "wsk")] {
+            if let DriverConfig::Wdm | DriverConfig::Kmdf(_) = config.driver_config {
+                info!("Generating bindings to WDK: wsk.rs");
+
+                let header_contents = config.bindgen_header_contents([ApiSubset::Base, ApiSubset::Wdf, ApiSubset::Wsk]);
+                trace!(header_contents = ?header_contents);
+
+                let bindgen_builder = {
+                     let mut builder = bindgen::Builder::wdk_default(config)?
+                    .with_codegen_config((CodegenConfig::TYPES | CodegenConfig::VARS).complement())
+                    .header_contents("wsk-input.h", &header_contents);
+
+                    // Only allowlist files in the wsk-specific files to avoid duplicate definitions
+                    for header_file in config.headers(ApiSubset::Wsk)
+                    {
+                        builder = builder.allowlist_file(format!("(?i).*{header_file}.*"));
+                    }
+                    builder
+                };
+                trace!(bindgen_builder = ?bindgen_builder);
+
+                Ok(bindgen_builder
+                    .generate()
+                    .expect("Bindings should succeed to generate")
+                    .write_to_file(out_path.join("wsk.rs"))?)
+            } else {
+                let _ = out_path; // Silence unused variable warnings when wsk.rs is not generated
+
+                info!(
+                    "Skipping wsk.rs generation since driver_config is {:#?}",
+                    config.driver_config
+                );
+                Ok(())
+            }
+        } else {
+            let _ = (out_path, config); // Silence unused variable warnings when wsk feature is not enabled
+
+            info!(
+            "Skipping wsk.rs generation since wsk feature is not enabled");
+            Ok(())
+        }
+    }
+}
+
+fn generate_wfp(out_path: &Path, config: &Config) -> Result<(), ConfigError> {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "wfp")] {
+            if let DriverConfig::Wdm | DriverConfig::Kmdf(_) = config.driver_config {
+                info!("Generating bindings to WDK: wfp.rs");
+
+                let header_contents = config.bindgen_header_contents([ApiSubset::Base, ApiSubset::Wdf, ApiSubset::Wfp]);
+                trace!(header_contents = ?header_contents);
+
+                let bindgen_builder = {
+                     let mut builder = bindgen::Builder::wdk_default(config)?
+                    .with_codegen_config((CodegenConfig::TYPES | CodegenConfig::VARS).complement())
+                    .header_contents("wfp-input.h", &header_contents);
+
+                    // Only allowlist files in the wfp-specific files to avoid duplicate definitions
+                    for header_file in config.headers(ApiSubset::Wfp)
+                    {
+                        builder = builder.allowlist_file(format!("(?i).*{header_file}.*"));
+                    }
+                    builder
+                };
+                trace!(bindgen_builder = ?bindgen_builder);
+
+                Ok(bindgen_builder
+                    .generate()
+                    .expect("Bindings should succeed to generate")
+                    .write_to_file(out_path.join("wfp.rs"))?)
+            } else {
+                let _ = out_path; // Silence unused variable warnings when wfp.rs is not generated
+
+                info!(
+                    "Skipping wfp.rs generation since driver_config is {:#?}",
+                    config.driver_config
+                );
+                Ok(())
+            }
+        } else {
+            let _ = (out_path, config); // Silence unused variable warnings when wfp feature is not enabled
+
+            info!(
+            "Skipping wfp.rs generation since wfp feature is not enabled");
+            Ok(())
+        }
+    }
+}
+
+fn generate_kernel_streaming(out_path: &Path, config: &Config) -> Result<(), ConfigError> {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "kernel-streaming")] {
+            if let DriverConfig::Wdm | DriverConfig::Kmdf(_) = config.driver_config {
+                info!("Generating bindings to WDK: kernel_streaming.rs");
+
+                let header_contents = config.bindgen_header_contents([ApiSubset::Base, ApiSubset::Wdf, ApiSubset::KernelStreaming]);
+                trace!(header_contents = ?header_contents);
+
+                let bindgen_builder = {
+                     let mut builder = bindgen::Builder::wdk_default(config)?
+                    .with_codegen_config((CodegenConfig::TYPES | CodegenConfig::VARS).complement())
+                    .header_contents("kernel-streaming-input.h", &header_contents);
+
+                    // Only allowlist files in the kernel-streaming-specific files to avoid duplicate definitions
+                    for header_file in config.headers(ApiSubset::KernelStreaming)
+                    {
+                        builder = builder.allowlist_file(format!("(?i).*{header_file}.*"));
+                    }
+                    builder
+                };
+                trace!(bindgen_builder = ?bindgen_builder);
+
+                Ok(bindgen_builder
+                    .generate()
+                    .expect("Bindings should succeed to generate")
+                    .write_to_file(out_path.join("kernel_streaming.rs"))?)
+            } else {
+                let _ = out_path; // Silence unused variable warnings when kernel_streaming.rs is not generated
+
+                info!(
+                    "Skipping kernel_streaming.rs generation since driver_config is {:#?}",
+                    config.driver_config
+                );
+                Ok(())
+            }
+        } else {
+            let _ = (out_path, config); // Silence unused variable warnings when kernel-streaming feature is not enabled
+
+            info!(
+            "Skipping kernel_streaming.rs generation since kernel-streaming feature is not enabled");
+            Ok(())
+        }
+    }
+}
+
+fn generate_audio(out_path: &Path, config: &Config) -> Result<(), ConfigError> {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "audio")] {
+            if let DriverConfig::Wdm | DriverConfig::Kmdf(_) = config.driver_config {
+                info!("Generating bindings to WDK: audio.rs");
+
+                let header_contents = config.bindgen_header_contents([ApiSubset::Base, ApiSubset::Wdf, ApiSubset::Audio]);
+                trace!(header_contents = ?header_contents);
+
+                let bindgen_builder = {
+                     let mut builder = bindgen::Builder::wdk_default(config)?
+                    .with_codegen_config((CodegenConfig::TYPES | CodegenConfig::VARS).complement())
+                    .header_contents("audio-input.h", &header_contents);
+
+                    // Only allowlist files in the audio-specific files to avoid duplicate definitions.
+                    // The Kernel Streaming headers that the audio headers depend on are covered by the
+                    // kernel-streaming feature instead.
+                    let kernel_streaming_headers = config.headers(ApiSubset::KernelStreaming).collect::<Vec<_>>();
+                    for header_file in config
+                        .headers(ApiSubset::Audio)
+                        .filter(|header_file| !kernel_streaming_headers.contains(header_file))
+                    {
+                        builder = builder.allowlist_file(format!("(?i).*{header_file}.*"));
+                    }
+                    builder
+                };
+                trace!(bindgen_builder = ?bindgen_builder);
+
+                Ok(bindgen_builder
+                    .generate()
+                    .expect("Bindings should succeed to generate")
+                    .write_to_file(out_path.join("audio.rs"))?)
+            } else {
+                let _ = out_path; // Silence unused variable warnings when audio.rs is not generated
+
+                info!(
+                    "Skipping audio.rs generation since driver_config is {:#?}",
+                    config.driver_config
+                );
+                Ok(())
+            }
+        } else {
+            let _ = (out_path, config); // Silence unused variable warnings when audio feature is not enabled
+
+            info!(
+            "Skipping audio.rs generation since audio feature is not enabled");
+            Ok(())
+        }
+    }
+}
+
+fn generate_usb(out_path: &Path, config: &Config) -> Result<(), ConfigError> {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "usb")] {
+            info!("Generating bindings to WDK: usb.rs");
+
+            let header_contents = config.bindgen_header_contents([ApiSubset::Base, ApiSubset::Wdf, ApiSubset::Usb]);
+            trace!(header_contents = ?header_contents);
+
+            let bindgen_builder = {
+                 let mut builder = bindgen::Builder::wdk_default(config)?
+                .with_codegen_config((CodegenConfig::TYPES | CodegenConfig::VARS).complement())
+                .header_contents("usb-input.h", &header_contents);
+
+                // Only allowlist files in the usb-specific files to avoid duplicate definitions
+                for header_file in config.headers(ApiSubset::Usb)
+                {
+                    builder = builder.allowlist_file(format!("(?i).*{header_file}.*"));
+                }
+                builder
+            };
+            trace!(bindgen_builder = ?bindgen_builder);
+
+            Ok(bindgen_builder
+                .generate()
+                .expect("Bindings should succeed to generate")
+                .write_to_file(out_path.join("usb.rs"))?)
+        } else {
+            let _ = (out_path, config); // Silence unused variable warnings when usb feature is not enabled
+
+            info!(
+            "Skipping usb.rs generation since usb feature is not enabled");
+            Ok(())
+        }
+    }
+}
+
+fn generate_vmbus(out_path: &Path, config: &Config) -> Result<(), ConfigError> {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "vmbus")] {
+            if let DriverConfig::Kmdf(_) = config.driver_config {
+                info!("Generating bindings to WDK: vmbus.rs");
+
+                let header_contents = config.bindgen_header_contents([ApiSubset::Base, ApiSubset::Wdf, ApiSubset::Vmbus]);
+                trace!(header_contents = ?header_contents);
+
+                let bindgen_builder = {
+                     let mut builder = bindgen::Builder::wdk_default(config)?
+                    .with_codegen_config((CodegenConfig::TYPES | CodegenConfig::VARS).complement())
+                    .header_contents("vmbus-input.h", &header_contents);
+
+                    // Only allowlist files in the vmbus-specific files to avoid duplicate definitions
+                    for header_file in config.headers(ApiSubset::Vmbus)
+                    {
+                        builder = builder.allowlist_file(format!("(?i).*{header_file}.*"));
+                    }
+                    builder
+                };
+                trace!(bindgen_builder = ?bindgen_builder);
+
+                Ok(bindgen_builder
+                    .generate()
+                    .expect("Bindings should succeed to generate")
+                    .write_to_file(out_path.join("vmbus.rs"))?)
+            } else {
+                let _ = out_path; // Silence unused variable warnings when vmbus.rs is not generated
+
+                info!(
+                    "Skipping vmbus.rs generation since driver_config is {:#?}",
+                    config.driver_config
+                );
+                Ok(())
+            }
+        } else {
+            let _ = (out_path, config); // Silence unused variable warnings when vmbus feature is not enabled
+
+            info!(
+            "Skipping vmbus.rs generation since vmbus feature is not enabled");
+            Ok(())
+        }
+    }
+}
+
 /// Generates a `wdf_function_count.rs` file in `OUT_DIR` which contains the
 /// definition of the function `get_wdf_function_count()`. This is required to
 /// be generated here since the size of the table is derived from either a
 /// global symbol that newer WDF versions expose, or an enum that older versions
 /// use.
+fn generate_layout_assertions(out_path: &Path, config: &Config) -> Result<(), ConfigError> {
+    info!("Generating layout assertions: layout_assertions.rs");
+
+    let layout_assertions = LAYOUT_ASSERTIONS
+        .iter()
+        .filter(|layout_assertion| (layout_assertion.is_available)(&config.driver_config))
+        .collect::<Vec<_>>();
+
+    // Let clang compute the layout of each struct from the WDK headers, as
+    // constants that bindgen can evaluate
+    let mut header_contents = config.bindgen_header_contents([ApiSubset::Base, ApiSubset::Wdf]);
+    for LayoutAssertion {
+        type_name,
+        field_names,
+        ..
+    } in &layout_assertions
+    {
+        let _ = writeln!(
+            header_contents,
+            "static const unsigned long long {LAYOUT_CONSTANT_PREFIX}SIZE_OF_{type_name} = \
+             sizeof({type_name});"
+        );
+        let _ = writeln!(
+            header_contents,
+            "static const unsigned long long {LAYOUT_CONSTANT_PREFIX}ALIGN_OF_{type_name} = \
+             __alignof({type_name});"
+        );
+        for field_name in *field_names {
+            let _ = writeln!(
+                header_contents,
+                "static const unsigned long long \
+                 {LAYOUT_CONSTANT_PREFIX}OFFSET_OF_{type_name}__{field_name} = \
+                 __builtin_offsetof({type_name}, {field_name});"
+            );
+        }
+    }
+    trace!(header_contents = ?header_contents);
+
+    let bindgen_builder = bindgen::Builder::wdk_default(config)?
+        .with_codegen_config(CodegenConfig::VARS)
+        .header_contents("layout-assertions-input.h", &header_contents)
+        .allowlist_var(format!("{LAYOUT_CONSTANT_PREFIX}.*"));
+    trace!(bindgen_builder = ?bindgen_builder);
+
+    let mut layout_assertions_rs = bindgen_builder
+        .generate()
+        .expect("Bindings should succeed to generate")
+        .to_string();
+
+    // Compare the layout of the generated bindings against the constants computed
+    // from the WDK headers. Writing to a `String` cannot fail.
+    let _ = writeln!(layout_assertions_rs, "const _: () = {{");
+    for LayoutAssertion {
+        type_name,
+        field_names,
+        ..
+    } in &layout_assertions
+    {
+        let rust_type = format!("crate::types::{type_name}");
+        let _ = writeln!(
+            layout_assertions_rs,
+            "assert!(core::mem::size_of::<{rust_type}>() as u64 == \
+             {LAYOUT_CONSTANT_PREFIX}SIZE_OF_{type_name}, \"size of {type_name} does not match \
+             the WDK headers\");"
+        );
+        let _ = writeln!(
+            layout_assertions_rs,
+            "assert!(core::mem::align_of::<{rust_type}>() as u64 == \
+             {LAYOUT_CONSTANT_PREFIX}ALIGN_OF_{type_name}, \"alignment of {type_name} does not \
+             match the WDK headers\");"
+        );
+        for field_name in *field_names {
+            let _ = writeln!(
+                layout_assertions_rs,
+                "assert!(core::mem::offset_of!({rust_type}, {field_name}) as u64 == \
+                 {LAYOUT_CONSTANT_PREFIX}OFFSET_OF_{type_name}__{field_name}, \"offset of \
+                 {type_name}::{field_name} does not match the WDK headers\");"
+            );
+        }
+    }
+    let _ = writeln!(layout_assertions_rs, "}};");
+
+    Ok(std::fs::write(
+        out_path.join("layout_assertions.rs"),
+        layout_assertions_rs,
+    )?)
+}
+
 fn generate_wdf_function_count(out_path: &Path, config: &Config) -> std::io::Result<()> {
     const MINIMUM_MINOR_VERSION_TO_GENERATE_WDF_FUNCTION_COUNT: u8 = 25;
 
@@ -418,10 +1302,12 @@ fn generate_wdf_function_count(out_path: &Path, config: &Config) -> std::io::Res
 
 /// Generates a `macros.rs` file in `OUT_DIR` which contains a
 /// `call_unsafe_wdf_function_binding!` macro that redirects to the
-/// `wdk_macros::call_unsafe_wdf_function_binding` `proc_macro` . This is
-/// required in order to add an additional argument with the path to the file
-/// containing generated types. There is currently no other way to pass
-/// `OUT_DIR` of `wdk-sys` to the `proc_macro`.
+/// `wdk_macros::call_unsafe_wdf_function_binding` `proc_macro`, and a
+/// `__wdf_callback!` macro that redirects to the
+/// `wdk_macros::wdf_callback_trampoline` `proc_macro`. This is required in
+/// order to add an additional argument with the path to the file containing
+/// generated types. There is currently no other way to pass `OUT_DIR` of
+/// `wdk-sys` to the `proc_macro`.
 fn generate_call_unsafe_wdf_function_binding_macro(out_path: &Path) -> std::io::Result<()> {
     let generated_file_path = out_path.join("call_unsafe_wdf_function_binding.rs");
     let mut generated_file = std::fs::File::create(generated_file_path)?;
@@ -460,6 +1346,529 @@ fn generate_test_stubs(out_path: &Path, config: &Config) -> std::io::Result<()>
     Ok(())
 }
 
+/// Generates the `guids.rs` file (and `hid_guids.rs` when the `hid` feature is
+/// enabled) in `OUT_DIR`, which contain a `GUID` constant for each
+/// `DEFINE_GUID` in the headers of the corresponding [`ApiSubset`].
+///
+/// bindgen cannot generate these, since `DEFINE_GUID` either declares an
+/// `extern` variable, or defines one with an initializer that bindgen does not
+/// evaluate. The headers are parsed directly instead, which does not require
+/// bindgen, so these files are generated even when building from pre-generated
+/// bindings.
+fn generate_guids(out_path: &Path, config: &Config) -> Result<(), ConfigError> {
+    let include_paths = config.include_paths()?.collect::<Vec<_>>();
+
+    write_guids_file(
+        &out_path.join("guids.rs"),
+        &include_paths,
+        BASE_GUID_HEADERS,
+    )?;
+    #[cfg(feature = "hid")]
+    write_guids_file(
+        &out_path.join("hid_guids.rs"),
+        &include_paths,
+        HID_GUID_HEADERS,
+    )?;
+    Ok(())
+}
+
+/// Writes a `GUID` constant for each `DEFINE_GUID` in `headers` to
+/// `file_path`. Only the first definition of each GUID is written, since some
+/// headers define GUIDs in several conditionally compiled blocks.
+fn write_guids_file(
+    file_path: &Path,
+    include_paths: &[PathBuf],
+    headers: &[&str],
+) -> std::io::Result<()> {
+    let mut guids_rs = String::new();
+    let mut generated_guid_names = HashSet::new();
+
+    for header in headers {
+        let Some(header_path) = include_paths
+            .iter()
+            .map(|include_path| include_path.join(header))
+            .find(|header_path| header_path.is_file())
+        else {
+            info!("Skipping GUIDs in {header} since it is not in the include paths");
+            continue;
+        };
+
+        for (name, (data1, data2, data3, data4)) in
+            parse_define_guids(&std::fs::read_to_string(&header_path)?)
+        {
+            if !generated_guid_names.insert(name.clone()) {
+                continue;
+            }
+
+            let _ = writeln!(guids_rs, "/// `{name}` in `{header}`");
+            let data4 = data4
+                .iter()
+                .map(|byte| format!("{byte:#04X}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let _ = writeln!(
+                guids_rs,
+                "pub const {name}: crate::types::GUID = crate::types::GUID {{ Data1: \
+                 {data1:#010X}, Data2: {data2:#06X}, Data3: {data3:#06X}, Data4: [{data4}] }};"
+            );
+        }
+    }
+
+    std::fs::write(file_path, guids_rs)
+}
+
+/// Returns the name and value of each `DEFINE_GUID(name, l, w1, w2, b1, b2,
+/// b3, b4, b5, b6, b7, b8)` in `header_contents`. Definitions whose values are
+/// not all integer literals are skipped.
+fn parse_define_guids(header_contents: &str) -> Vec<(String, GuidValue)> {
+    parse_macro_invocations(header_contents, "DEFINE_GUID")
+        .into_iter()
+        .filter_map(|arguments| {
+            let [name, data1, data2, data3, data4 @ ..] = arguments.as_slice() else {
+                return None;
+            };
+            if data4.len() != 8 {
+                return None;
+            }
+
+            let data4 = data4.iter().map(String::as_str).collect::<Vec<_>>();
+            let guid = parse_guid_value(data1, data2, data3, &data4);
+            if guid.is_none() {
+                trace!(
+                    "Skipping DEFINE_GUID of {name} since its value is not all integer literals"
+                );
+            }
+            Some((name.clone(), guid?))
+        })
+        .collect()
+}
+
+/// Returns the name, `GUID` and property identifier of each
+/// `DEFINE_DEVPROPKEY(name, l, w1, w2, b1, b2, b3, b4, b5, b6, b7, b8, pid)` in
+/// `header_contents`. Definitions whose values are not all integer literals are
+/// skipped.
+fn parse_define_devpropkeys(header_contents: &str) -> Vec<(String, GuidValue, u32)> {
+    parse_macro_invocations(header_contents, "DEFINE_DEVPROPKEY")
+        .into_iter()
+        .filter_map(|arguments| {
+            let [name, data1, data2, data3, data4 @ .., pid] = arguments.as_slice() else {
+                return None;
+            };
+            if data4.len() != 8 {
+                return None;
+            }
+
+            let data4 = data4.iter().map(String::as_str).collect::<Vec<_>>();
+            let devpropkey = parse_guid_value(data1, data2, data3, &data4)
+                .zip(parse_c_integer_literal(pid).and_then(|pid| u32::try_from(pid).ok()));
+            if devpropkey.is_none() {
+                trace!(
+                    "Skipping DEFINE_DEVPROPKEY of {name} since its value is not all integer \
+                     literals"
+                );
+            }
+            let (guid, pid) = devpropkey?;
+            Some((name.clone(), guid, pid))
+        })
+        .collect()
+}
+
+/// Returns the trimmed arguments of each invocation of the function-like macro
+/// `macro_name` in `header_contents` whose first argument is an identifier.
+/// Comments and line continuations are ignored.
+fn parse_macro_invocations(header_contents: &str, macro_name: &str) -> Vec<Vec<String>> {
+    let header_contents = strip_c_comments(&header_contents.replace("\\\n", " "));
+    let is_identifier_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut invocations = Vec::new();
+
+    for (index, _) in header_contents.match_indices(macro_name) {
+        // Skip macros whose name ends with `macro_name`
+        if header_contents[..index]
+            .chars()
+            .next_back()
+            .is_some_and(is_identifier_char)
+        {
+            continue;
+        }
+
+        let Some(arguments) = header_contents[index + macro_name.len()..]
+            .trim_start()
+            .strip_prefix('(')
+            .and_then(|rest| rest.split_once(')'))
+            .map(|(arguments, _)| {
+                arguments
+                    .split(',')
+                    .map(|argument| argument.trim().to_string())
+                    .collect::<Vec<_>>()
+            })
+        else {
+            continue;
+        };
+
+        if arguments
+            .first()
+            .is_some_and(|name| !name.is_empty() && name.chars().all(is_identifier_char))
+        {
+            invocations.push(arguments);
+        }
+    }
+    invocations
+}
+
+/// Parses the value of a `GUID` from the integer literals of the `DEFINE_GUID`
+/// arguments that define it
+fn parse_guid_value(data1: &str, data2: &str, data3: &str, data4: &[&str]) -> Option<GuidValue> {
+    let mut bytes = [0; 8];
+    for (byte, literal) in bytes.iter_mut().zip(data4) {
+        *byte = u8::try_from(parse_c_integer_literal(literal)?).ok()?;
+    }
+    Some((
+        u32::try_from(parse_c_integer_literal(data1)?).ok()?,
+        u16::try_from(parse_c_integer_literal(data2)?).ok()?,
+        u16::try_from(parse_c_integer_literal(data3)?).ok()?,
+        bytes,
+    ))
+}
+
+/// Parses a C integer literal (ex. `0x4D1E55B2L` or `17`), ignoring any
+/// integer suffixes
+fn parse_c_integer_literal(literal: &str) -> Option<u64> {
+    let literal = literal.trim_end_matches(['u', 'U', 'l', 'L']);
+    match literal
+        .strip_prefix("0x")
+        .or_else(|| literal.strip_prefix("0X"))
+    {
+        Some(hex_digits) => u64::from_str_radix(hex_digits, 16).ok(),
+        None => literal.parse().ok(),
+    }
+}
+
+/// Returns `contents` with all C comments replaced by spaces
+fn strip_c_comments(contents: &str) -> String {
+    let mut stripped = String::with_capacity(contents.len());
+    let mut rest = contents;
+    loop {
+        let Some(comment_start) = [rest.find("//"), rest.find("/*")]
+            .into_iter()
+            .flatten()
+            .min()
+        else {
+            break;
+        };
+        let comment_end = if rest[comment_start..].starts_with("//") {
+            rest[comment_start..]
+                .find('\n')
+                .map_or(rest.len(), |end| comment_start + end)
+        } else {
+            rest[comment_start + 2..]
+                .find("*/")
+                .map_or(rest.len(), |end| comment_start + 2 + end + 2)
+        };
+        stripped.push_str(&rest[..comment_start]);
+        stripped.push(' ');
+        rest = &rest[comment_end..];
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// Generates `devpropkeys.rs`, which contains a `DEVPROPKEY` constant for each
+/// `DEFINE_DEVPROPKEY` in [`DEVPROPKEY_HEADER`] (ex.
+/// `DEVPKEY_Device_FriendlyName`).
+///
+/// bindgen cannot generate these for the same reason as the `GUID`s of
+/// [`generate_guids`], so the header is parsed directly as well.
+fn generate_devpropkeys(out_path: &Path, config: &Config) -> Result<(), ConfigError> {
+    let mut devpropkeys_rs = String::new();
+
+    if let Some(header_path) = config
+        .include_paths()?
+        .map(|include_path| include_path.join(DEVPROPKEY_HEADER))
+        .find(|header_path| header_path.is_file())
+    {
+        let mut generated_devpropkey_names = HashSet::new();
+        for (name, (data1, data2, data3, data4), pid) in
+            parse_define_devpropkeys(&std::fs::read_to_string(header_path)?)
+        {
+            if !generated_devpropkey_names.insert(name.clone()) {
+                continue;
+            }
+
+            let _ = writeln!(devpropkeys_rs, "/// `{name}` in `{DEVPROPKEY_HEADER}`");
+            let data4 = data4
+                .iter()
+                .map(|byte| format!("{byte:#04X}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let _ = writeln!(
+                devpropkeys_rs,
+                "pub const {name}: crate::types::DEVPROPKEY = crate::types::DEVPROPKEY {{ fmtid: \
+                 crate::types::GUID {{ Data1: {data1:#010X}, Data2: {data2:#06X}, Data3: \
+                 {data3:#06X}, Data4: [{data4}] }}, pid: {pid} }};"
+            );
+        }
+    } else {
+        info!(
+            "Skipping device property keys since {DEVPROPKEY_HEADER} is not in the include paths"
+        );
+    }
+
+    std::fs::write(out_path.join("devpropkeys.rs"), devpropkeys_rs)?;
+    Ok(())
+}
+
+/// Generates `ntstatus_names.rs`, which maps `NTSTATUS` values to their
+/// symbolic names and messages.
+///
+/// The table is sorted by value, so that it can be binary searched. When
+/// several names are defined for the same value (ex. `STATUS_SUCCESS` and
+/// `STATUS_WAIT_0`), the first definition in `ntstatus.h` is used. Like
+/// [`generate_guids`], the header is parsed directly, so the table is generated
+/// even when building from pre-generated bindings.
+fn generate_ntstatus_names(out_path: &Path, config: &Config) -> Result<(), ConfigError> {
+    let mut ntstatus_names_rs =
+        String::from("pub(super) const NT_STATUS_NAMES: &[(NTSTATUS, &str, Option<&str>)] = &[\n");
+
+    if let Some(header_path) = config
+        .include_paths()?
+        .map(|include_path| include_path.join(NTSTATUS_HEADER))
+        .find(|header_path| header_path.is_file())
+    {
+        let mut definitions = parse_ntstatus_definitions(&std::fs::read_to_string(header_path)?);
+        // `sort_by_key` is stable, so the first definition of each value is kept
+        definitions.sort_by_key(|(_, value, _)| *value);
+        definitions.dedup_by_key(|(_, value, _)| *value);
+
+        for (name, value, message) in definitions {
+            let _ = writeln!(
+                ntstatus_names_rs,
+                "    ({value}_i32, {name:?}, {message:?}),"
+            );
+        }
+    } else {
+        info!("Skipping NTSTATUS names since {NTSTATUS_HEADER} is not in the include paths");
+    }
+
+    ntstatus_names_rs.push_str("];\n");
+    std::fs::write(out_path.join("ntstatus_names.rs"), ntstatus_names_rs)?;
+    Ok(())
+}
+
+/// Returns the symbolic name, value and message of each `#define
+/// STATUS_<NAME> ((NTSTATUS)0x<VALUE>L)` in `header_contents`.
+///
+/// Messages are read from the `MessageText` of the `MessageId` comment block
+/// that precedes each definition. Messages that only repeat the symbolic name
+/// are omitted.
+fn parse_ntstatus_definitions(header_contents: &str) -> Vec<NtStatusDefinition> {
+    const NTSTATUS_CAST_PREFIX: &str = "((NTSTATUS)";
+
+    let mut definitions = Vec::new();
+    let mut message_id = None;
+    let mut message_lines: Option<Vec<&str>> = None;
+    let mut is_reading_message = false;
+
+    for line in header_contents.lines().map(str::trim) {
+        if let Some(comment) = line.strip_prefix("//") {
+            let comment = comment.trim();
+            if let Some(id) = comment.strip_prefix("MessageId:") {
+                message_id = Some(id.trim());
+                message_lines = None;
+                is_reading_message = false;
+            } else if comment == "MessageText:" {
+                message_lines = Some(Vec::new());
+            } else if let Some(message_lines) = &mut message_lines {
+                // The message is delimited by empty comment lines
+                if comment.is_empty() {
+                    is_reading_message = message_lines.is_empty() && !is_reading_message;
+                } else if is_reading_message {
+                    message_lines.push(comment);
+                }
+            }
+            continue;
+        }
+
+        let Some((name, value)) = line
+            .strip_prefix("#define")
+            .and_then(|definition| definition.trim().split_once(char::is_whitespace))
+        else {
+            continue;
+        };
+        let Some(value) = value
+            .trim()
+            .strip_prefix(NTSTATUS_CAST_PREFIX)
+            .and_then(|value| value.split_once(')'))
+            .and_then(|(value, _)| parse_c_integer_literal(value.trim()))
+            .and_then(|value| u32::try_from(value).ok())
+        else {
+            continue;
+        };
+        if !name.starts_with("STATUS_") {
+            continue;
+        }
+
+        let message = message_lines
+            .take()
+            .filter(|_| message_id == Some(name))
+            .map(|message_lines| message_lines.join(" "))
+            .filter(|message| !message.is_empty() && message != name);
+        // NTSTATUS values are written as unsigned hexadecimal literals, but NTSTATUS is
+        // signed
+        definitions.push((
+            name.to_string(),
+            i32::from_ne_bytes(value.to_ne_bytes()),
+            message,
+        ));
+        message_id = None;
+        is_reading_message = false;
+    }
+    definitions
+}
+
+/// Generates constants for the SPB IOCTLs (ex. `IOCTL_SPB_EXECUTE_SEQUENCE`).
+///
+/// The IOCTLs are defined with the `CTL_CODE` function-like macro, which
+/// bindgen does not expand, so each of them is assigned to a `static const`
+/// that clang evaluates instead. IOCTLs that are not defined by the WDK are
+/// skipped.
+#[cfg(feature = "spb")]
+fn generate_spb_ioctls(
+    out_path: &Path,
+    config: &Config,
+    spb_header_contents: &str,
+) -> Result<(), ConfigError> {
+    info!("Generating bindings to WDK: spb_ioctls.rs");
+
+    let mut header_contents = spb_header_contents.to_string();
+    for ioctl_name in SPB_IOCTL_NAMES {
+        let _ = writeln!(header_contents, "#ifdef {ioctl_name}");
+        let _ = writeln!(
+            header_contents,
+            "static const ULONG {IOCTL_CONSTANT_PREFIX}{ioctl_name} = {ioctl_name};"
+        );
+        let _ = writeln!(header_contents, "#endif");
+    }
+    trace!(header_contents = ?header_contents);
+
+    let bindgen_builder = bindgen::Builder::wdk_default(config)?
+        .with_codegen_config(CodegenConfig::VARS)
+        .header_contents("spb-ioctls-input.h", &header_contents)
+        .allowlist_var(format!("{IOCTL_CONSTANT_PREFIX}.*"))
+        .parse_callbacks(Box::new(StripIoctlConstantPrefix));
+    trace!(bindgen_builder = ?bindgen_builder);
+
+    Ok(bindgen_builder
+        .generate()
+        .expect("Bindings should succeed to generate")
+        .write_to_file(out_path.join("spb_ioctls.rs"))?)
+}
+
+/// Names the constants generated for IOCTLs after the IOCTLs themselves
+#[cfg(feature = "spb")]
+#[derive(Debug)]
+struct StripIoctlConstantPrefix;
+
+#[cfg(feature = "spb")]
+impl ParseCallbacks for StripIoctlConstantPrefix {
+    fn generated_name_override(&self, item_info: ItemInfo) -> Option<String> {
+        item_info
+            .name
+            .strip_prefix(IOCTL_CONSTANT_PREFIX)
+            .map(ToString::to_string)
+    }
+}
+
+/// Compiles the C wrappers that bindgen generates for the `FORCEINLINE` SpbCx
+/// functions
+#[cfg(feature = "spb")]
+fn compile_spbcx_static_fns(out_path: &Path, config: &Config) -> Result<(), ConfigError> {
+    info!("Compiling {SPBCX_STATIC_FNS_FILE_STEM}.c");
+
+    new_cc_builder(config)?
+        .includes(config.include_paths()?)
+        .include(out_path)
+        .file(out_path.join(format!("{SPBCX_STATIC_FNS_FILE_STEM}.c")))
+        .compile(SPBCX_STATIC_FNS_FILE_STEM);
+    Ok(())
+}
+
+/// Replaces `from` with `to` in the C file with the wrappers of the SpbCx
+/// inline functions in `dir`, if it exists. bindgen includes `spb-input.h` in
+/// that file by its absolute path in `OUT_DIR`, which is replaced with
+/// [`OUT_DIR_PLACEHOLDER`] in pre-generated bindings.
+#[cfg(feature = "spb")]
+fn replace_in_spbcx_static_fns(dir: &Path, from: &str, to: &str) -> std::io::Result<()> {
+    let spbcx_static_fns_path = dir.join(format!("{SPBCX_STATIC_FNS_FILE_STEM}.c"));
+    if spbcx_static_fns_path.is_file() {
+        let contents = std::fs::read_to_string(&spbcx_static_fns_path)?;
+        std::fs::write(&spbcx_static_fns_path, contents.replace(from, to))?;
+    }
+    Ok(())
+}
+
+/// Links the SpbCx stub library, which provides the SpbCx function table and
+/// driver globals that the SpbCx functions dispatch through.
+///
+/// Depending on the WDK version, the library is either in the kernel-mode
+/// library directory, or in its versioned `spb` subdirectory.
+#[cfg(feature = "spb")]
+fn link_spbcx_stubs(config: &Config) -> Result<(), ConfigError> {
+    const SPBCX_STUBS_LIBRARY_NAME: &str = "SpbCxStubs";
+
+    if let Some(spbcx_stubs_directory) = config
+        .library_paths()?
+        .flat_map(|library_path| [library_path.join("spb").join("1.1"), library_path])
+        .find(|directory| {
+            directory
+                .join(format!("{SPBCX_STUBS_LIBRARY_NAME}.lib"))
+                .is_file()
+        })
+    {
+        println!(
+            "cargo::rustc-link-search={}",
+            spbcx_stubs_directory.display()
+        );
+    }
+    println!("cargo::rustc-link-lib=static={SPBCX_STUBS_LIBRARY_NAME}");
+    Ok(())
+}
+
+/// Creates a [`cc::Build`] for C code linked into `wdk-sys`, configured with
+/// the preprocessor definitions of `config`.
+///
+/// When linker-plugin-based LTO is enabled, the C code is compiled to LLVM
+/// bitcode so that it is optimized together with the Rust code by `lld-link`.
+fn new_cc_builder(config: &Config) -> Result<cc::Build, ConfigError> {
+    let mut cc_builder = cc::Build::new();
+    for (key, value) in config.preprocessor_definitions() {
+        cc_builder.define(&key, value.as_deref());
+    }
+
+    if Config::is_linker_plugin_lto_enabled() {
+        let compiler = cc_builder.get_compiler();
+        if !(compiler.is_like_clang() || compiler.is_like_clang_cl()) {
+            return Err(ConfigError::LinkerPluginLtoRequiresClang);
+        }
+        cc_builder.flag(LINKER_PLUGIN_LTO_C_COMPILER_FLAG);
+    }
+    Ok(cc_builder)
+}
+
+/// Links the VC++ runtime library matching the CRT linkage of the driver, so
+/// that C++ exception handling symbols (ex. `__CxxFrameHandler3`) are resolved
+/// without the `cxx-frame-handler-stub` feature
+#[cfg(not(feature = "cxx-frame-handler-stub"))]
+fn link_vc_runtime() {
+    let is_crt_static = env::var("CARGO_CFG_TARGET_FEATURE")
+        .is_ok_and(|target_features| target_features.split(',').any(|f| f == "crt-static"));
+
+    if is_crt_static {
+        println!("cargo::rustc-link-lib=static=libvcruntime");
+    } else {
+        println!("cargo::rustc-link-lib=dylib=vcruntime");
+    }
+}
+
 fn main() -> anyhow::Result<()> {
     initialize_tracing()?;
 
@@ -467,28 +1876,83 @@ fn main() -> anyhow::Result<()> {
         let out_path = PathBuf::from(
             env::var("OUT_DIR").expect("OUT_DIR should be exist in Cargo build environment"),
         );
+        let bindings_source = config.bindings_source()?;
+
+        build_provenance::emit_headers_metadata(
+            &config,
+            [
+                ApiSubset::Base,
+                ApiSubset::Wdf,
+                #[cfg(feature = "hid")]
+                ApiSubset::Hid,
+                #[cfg(feature = "spb")]
+                ApiSubset::Spb,
+                #[cfg(feature = "storport")]
+                ApiSubset::Storport,
+                #[cfg(feature = "wsk")]
+                ApiSubset::Wsk,
+                #[cfg(feature = "wfp")]
+                ApiSubset::Wfp,
+                #[cfg(feature = "kernel-streaming")]
+                ApiSubset::KernelStreaming,
+                #[cfg(feature = "audio")]
+                ApiSubset::Audio,
+                #[cfg(feature = "usb")]
+                ApiSubset::Usb,
+                #[cfg(feature = "vmbus")]
+                ApiSubset::Vmbus,
+            ],
+        );
 
         thread::scope(|thread_scope| {
             let mut thread_join_handles = Vec::new();
 
-            info_span!("bindgen generation").in_scope(|| {
+            if let BindingsSource::Pregenerated { dir } = &bindings_source {
+                info_span!("pregenerated bindings").in_scope(|| {
+                    info!("Copying pre-generated bindings from {}", dir.display());
+                    import_bindings(dir, &out_path, &config.bindgen, &bindings_settings())?;
+
+                    #[cfg(feature = "spb")]
+                    if let DriverConfig::Kmdf(_) = config.driver_config {
+                        replace_in_spbcx_static_fns(
+                            &out_path,
+                            OUT_DIR_PLACEHOLDER,
+                            out_path
+                                .to_str()
+                                .expect("OUT_DIR should be a valid UTF-8 path"),
+                        )?;
+                        compile_spbcx_static_fns(&out_path, &config)?;
+                        link_spbcx_stubs(&config)?;
+                    }
+                    Ok::<(), ConfigError>(())
+                })?;
+            } else {
+                let current_span = Span::current();
                 let out_path = &out_path;
                 let config = &config;
 
-                for (file_name, generate_function) in BINDGEN_FILE_GENERATORS_TUPLES {
-                    let current_span = Span::current();
-
-                    thread_join_handles.push(
-                        thread::Builder::new()
-                            .name(format!("bindgen {file_name} generator"))
-                            .spawn_scoped(thread_scope, move || {
-                                // Parent span must be manually set since spans do not persist across thread boundaries: https://github.com/tokio-rs/tracing/issues/1391
-                                info_span!(parent: &current_span, "worker thread", generated_file_name = file_name).in_scope(|| generate_function(out_path, config))
+                // The bindgen generators are independent of each other, so they are run in
+                // parallel, bounded by the number of available cores
+                thread_join_handles.push(
+                    thread::Builder::new()
+                        .name("bindgen generation".to_string())
+                        .spawn_scoped(thread_scope, move || {
+                            // Parent span must be manually set since spans do not persist across thread boundaries: https://github.com/tokio-rs/tracing/issues/1391
+                            info_span!(parent: current_span, "bindgen generation").in_scope(|| {
+                                let mut generation_coordinator = GenerationCoordinator::new();
+                                for (file_name, generate_function) in BINDGEN_FILE_GENERATORS_TUPLES
+                                {
+                                    generation_coordinator.add_job(*file_name, move || {
+                                        generate_function(out_path, config)
+                                    });
+                                }
+                                generation_coordinator.run()?;
+                                Ok::<(), anyhow::Error>(())
                             })
-                            .expect("Scoped Thread should spawn successfully"),
-                    );
-                }
-            });
+                        })
+                        .expect("Scoped Thread should spawn successfully"),
+                );
+            }
 
             if let DriverConfig::Kmdf(_) | DriverConfig::Umdf(_) = config.driver_config {
                 let current_span = Span::current();
@@ -519,6 +1983,20 @@ fn main() -> anyhow::Result<()> {
                                                 ApiSubset::Hid,
                                                 #[cfg(feature = "spb")]
                                                 ApiSubset::Spb,
+                                                #[cfg(feature = "storport")]
+                                                ApiSubset::Storport,
+                                                #[cfg(feature = "wsk")]
+                                                ApiSubset::Wsk,
+                                                #[cfg(feature = "wfp")]
+                                                ApiSubset::Wfp,
+                                                #[cfg(feature = "kernel-streaming")]
+                                                ApiSubset::KernelStreaming,
+                                                #[cfg(feature = "audio")]
+                                                ApiSubset::Audio,
+                                                #[cfg(feature = "usb")]
+                                                ApiSubset::Usb,
+                                                #[cfg(feature = "vmbus")]
+                                                ApiSubset::Vmbus,
                                             ])
                                             .as_bytes(),
                                     )?;
@@ -528,16 +2006,11 @@ fn main() -> anyhow::Result<()> {
                                     wdf_c_file.sync_all()?;
                                 }
 
-                                let mut cc_builder = cc::Build::new();
-                                for (key, value) in config.preprocessor_definitions() {
-                                    cc_builder.define(&key, value.as_deref());
-                                }
-
-                                cc_builder
+                                new_cc_builder(config)?
                                     .includes(config.include_paths()?)
                                     .file(wdf_c_file_path)
                                     .compile("wdf");
-                                Ok::<(), ConfigError>(())
+                                Ok::<(), anyhow::Error>(())
                             })
                         })
                         .expect("Scoped Thread should spawn successfully"),
@@ -559,6 +2032,68 @@ fn main() -> anyhow::Result<()> {
                 })?;
             }
 
+            info_span!("guids.rs generation").in_scope(|| generate_guids(&out_path, &config))?;
+
+            info_span!("devpropkeys.rs generation")
+                .in_scope(|| generate_devpropkeys(&out_path, &config))?;
+
+            info_span!("ntstatus_names.rs generation")
+                .in_scope(|| generate_ntstatus_names(&out_path, &config))?;
+
+            // Storport miniport drivers call into the Storport port driver via storport.lib
+            #[cfg(feature = "storport")]
+            if let DriverConfig::Wdm | DriverConfig::Kmdf(_) = config.driver_config {
+                println!("cargo::rustc-link-lib=static=storport");
+            }
+
+            // WSK clients register with the WSK subsystem via netio.lib
+            #[cfg(feature = "wsk")]
+            if let DriverConfig::Wdm | DriverConfig::Kmdf(_) = config.driver_config {
+                println!("cargo::rustc-link-lib=static=netio");
+            }
+
+            // WFP callout drivers call into the filter engine via fwpkclnt.lib, and into
+            // NDIS via ndis.lib to access the `NET_BUFFER_LIST`s of classified packets
+            #[cfg(feature = "wfp")]
+            if let DriverConfig::Wdm | DriverConfig::Kmdf(_) = config.driver_config {
+                println!("cargo::rustc-link-lib=static=fwpkclnt");
+                println!("cargo::rustc-link-lib=static=ndis");
+            }
+
+            // The `USBD_*` routines of `usbdlib.h` are implemented in usbd.lib
+            #[cfg(feature = "usb")]
+            if let DriverConfig::Wdm | DriverConfig::Kmdf(_) = config.driver_config {
+                println!("cargo::rustc-link-lib=static=usbd");
+            }
+
+            // AVStream minidrivers call into the Kernel Streaming class driver via ks.lib
+            #[cfg(feature = "kernel-streaming")]
+            if let DriverConfig::Wdm | DriverConfig::Kmdf(_) = config.driver_config {
+                println!("cargo::rustc-link-lib=static=ks");
+            }
+
+            // Audio miniport drivers register with the Port Class system driver via portcls.lib
+            #[cfg(feature = "audio")]
+            if let DriverConfig::Wdm | DriverConfig::Kmdf(_) = config.driver_config {
+                println!("cargo::rustc-link-lib=static=portcls");
+            }
+
+            // VMBus drivers open and use channels through the Kernel Mode Client Library in
+            // vmbkmcl.lib
+            #[cfg(feature = "vmbus")]
+            if let DriverConfig::Kmdf(_) = config.driver_config {
+                println!("cargo::rustc-link-lib=static=vmbkmcl");
+            }
+
+            // Without the `__CxxFrameHandler3` stub, user-mode drivers get the C++
+            // exception handling symbols from the VC++ runtime. Kernel-mode
+            // drivers have no such runtime in the WDK, so the library that
+            // replaces the stub must provide them.
+            #[cfg(not(feature = "cxx-frame-handler-stub"))]
+            if let DriverConfig::Umdf(_) = config.driver_config {
+                link_vc_runtime();
+            }
+
             for join_handle in thread_join_handles {
                 let thread_name = join_handle.thread().name().unwrap_or("UNNAMED").to_string();
 
@@ -578,7 +2113,44 @@ fn main() -> anyhow::Result<()> {
             Ok::<(), anyhow::Error>(())
         })?;
 
+        info_span!("symbol surface listing")
+            .in_scope(|| config.write_symbol_surface(&out_path, BINDGEN_GENERATED_FILE_NAMES))?;
+
+        if let BindingsSource::Bindgen {
+            out_dir: Some(generated_bindings_out_dir),
+        } = &bindings_source
+        {
+            info_span!("generated bindings export").in_scope(|| {
+                info!(
+                    "Copying generated bindings to {}",
+                    generated_bindings_out_dir.display()
+                );
+                export_bindings(
+                    &out_path,
+                    generated_bindings_out_dir,
+                    BINDGEN_GENERATED_FILE_NAMES,
+                    &config.bindgen,
+                    &bindings_settings(),
+                )?;
+
+                #[cfg(feature = "spb")]
+                replace_in_spbcx_static_fns(
+                    generated_bindings_out_dir,
+                    out_path
+                        .to_str()
+                        .expect("OUT_DIR should be a valid UTF-8 path"),
+                    OUT_DIR_PLACEHOLDER,
+                )?;
+                Ok::<(), ConfigError>(())
+            })?;
+        }
+
         Ok::<(), anyhow::Error>(())
+    })
+    // Explain why configuration failed when `WDK_BUILD_DIAGNOSTICS` is enabled
+    .map_err(|error| match diagnostics::report() {
+        Some(report) => error.context(report),
+        None => error,
     })?;
 
     Ok(())
@@ -0,0 +1,33 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Direct FFI bindings to audio (Port Class) APIs from the Windows Driver Kit
+//! (WDK)
+//!
+//! This module contains all bindings to functions, constants, methods,
+//! constructors and destructors in the following headers: `portcls.h`,
+//! `drmk.h`. Types are not included in this module, but are available in the
+//! top-level `wdk_sys` module.
+//!
+//! Enabling the `audio` feature also links `portcls.lib`, so that the `Pc*`
+//! functions can be called by audio miniport drivers. The Kernel Streaming
+//! APIs that the audio headers depend on are available in the
+//! `kernel_streaming` module, when the `kernel-streaming` feature is enabled.
+
+#[allow(
+    missing_docs,
+    reason = "most items in the WDK headers have no inline documentation, so bindgen is unable to \
+              generate documentation for their bindings"
+)]
+mod bindings {
+    #[allow(
+        clippy::wildcard_imports,
+        reason = "the underlying c code relies on all type definitions being in scope, which \
+                  results in the bindgen generated code relying on the generated types being in \
+                  scope as well"
+    )]
+    use crate::types::*;
+
+    include!(concat!(env!("OUT_DIR"), "/audio.rs"));
+}
+pub use bindings::*;
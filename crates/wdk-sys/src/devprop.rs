@@ -0,0 +1,24 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Device property keys defined in `devpkey.h`
+//!
+//! The keys are defined with the `DEFINE_DEVPROPKEY` macro, which bindgen
+//! cannot evaluate, so they are generated from the header as [`DEVPROPKEY`]
+//! constants (ex. `DEVPKEY_Device_FriendlyName`) by the build script instead.
+//!
+//! [`DEVPROPKEY`]: crate::DEVPROPKEY
+
+pub use devpropkeys::*;
+
+#[allow(
+    non_upper_case_globals,
+    reason = "device property keys are generated with the names of the WDK headers"
+)]
+#[allow(
+    clippy::unreadable_literal,
+    reason = "GUID values are generated in the same format as in the WDK headers"
+)]
+mod devpropkeys {
+    include!(concat!(env!("OUT_DIR"), "/devpropkeys.rs"));
+}
@@ -8,6 +8,9 @@
 //! `hidport.h`, `HidSpiCx/1.0/hidspicx.h`, `kbdmou.h`, `ntdd8042.h`,
 //! `hidclass.h`, `hidsdi.h`, `hidpi.h`, `vhf.h`. Types are not included in this
 //! module, but are available in the top-level `wdk_sys` module.
+//!
+//! The `GUID`s defined with `DEFINE_GUID` in `hidclass.h` are available as
+//! `GUID` constants (ex. `GUID_DEVINTERFACE_HID`).
 
 #[allow(
     missing_docs,
@@ -26,3 +29,12 @@ mod bindings {
     include!(concat!(env!("OUT_DIR"), "/hid.rs"));
 }
 pub use bindings::*;
+pub use guids::*;
+
+#[allow(
+    clippy::unreadable_literal,
+    reason = "GUID values are generated in the same format as in the WDK headers"
+)]
+mod guids {
+    include!(concat!(env!("OUT_DIR"), "/hid_guids.rs"));
+}
@@ -0,0 +1,267 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Implementations of the C runtime intrinsics that LLVM emits calls to in
+//! kernel-mode drivers (ex. `memcpy` for large copies, or `__chkstk` for large
+//! stack frames), for drivers that do not link a library that provides them.
+//!
+//! The memory functions only use volatile accesses, so that LLVM cannot
+//! recognize their loops as the intrinsics themselves and compile them into
+//! infinitely recursive calls.
+
+use core::ffi::c_int;
+
+const WORD_SIZE: usize = core::mem::size_of::<usize>();
+
+/// Copies `n` bytes from `src` to `dest`, and returns `dest`.
+///
+/// # Safety
+///
+/// `src` must be valid for reads of `n` bytes, `dest` must be valid for writes
+/// of `n` bytes, and the two regions must not overlap.
+#[no_mangle]
+pub unsafe extern "C" fn memcpy(dest: *mut u8, src: *const u8, n: usize) -> *mut u8 {
+    // SAFETY: The caller guarantees that `src` and `dest` are valid for `n` bytes
+    unsafe {
+        copy_forward(dest, src, n);
+    }
+    dest
+}
+
+/// Copies `n` bytes from `src` to `dest`, where the two regions may overlap,
+/// and returns `dest`.
+///
+/// # Safety
+///
+/// `src` must be valid for reads of `n` bytes, and `dest` must be valid for
+/// writes of `n` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn memmove(dest: *mut u8, src: *const u8, n: usize) -> *mut u8 {
+    // A forward copy only overwrites bytes of `src` before they are read when
+    // `dest` starts inside of `src`
+    if (dest as usize).wrapping_sub(src as usize) >= n {
+        // SAFETY: The caller guarantees that `src` and `dest` are valid for `n` bytes
+        unsafe {
+            copy_forward(dest, src, n);
+        }
+    } else {
+        // SAFETY: The caller guarantees that `src` and `dest` are valid for `n` bytes
+        unsafe {
+            copy_backward(dest, src, n);
+        }
+    }
+    dest
+}
+
+/// Sets `n` bytes of `dest` to `c` converted to a byte, and returns `dest`.
+///
+/// # Safety
+///
+/// `dest` must be valid for writes of `n` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn memset(dest: *mut u8, c: c_int, n: usize) -> *mut u8 {
+    // memset only uses the low byte of `c`
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let byte = c as u8;
+    let word = usize::from_ne_bytes([byte; WORD_SIZE]);
+
+    let mut i = 0;
+    while i < n && !(dest as usize).wrapping_add(i).is_multiple_of(WORD_SIZE) {
+        // SAFETY: The caller guarantees that `dest` is valid for writes of `n` bytes
+        unsafe {
+            dest.wrapping_add(i).write_volatile(byte);
+        }
+        i += 1;
+    }
+    while n - i >= WORD_SIZE {
+        #[allow(clippy::cast_ptr_alignment)]
+        // SAFETY: The caller guarantees that `dest` is valid for writes of `n` bytes,
+        // and `dest + i` is aligned to a word by the loop above
+        unsafe {
+            dest.wrapping_add(i).cast::<usize>().write_volatile(word);
+        }
+        i += WORD_SIZE;
+    }
+    while i < n {
+        // SAFETY: The caller guarantees that `dest` is valid for writes of `n` bytes
+        unsafe {
+            dest.wrapping_add(i).write_volatile(byte);
+        }
+        i += 1;
+    }
+    dest
+}
+
+/// Compares the first `n` bytes of `s1` and `s2`, and returns the difference
+/// between the first pair of bytes that differ, or 0 if all bytes are equal.
+///
+/// # Safety
+///
+/// `s1` and `s2` must both be valid for reads of `n` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn memcmp(s1: *const u8, s2: *const u8, n: usize) -> c_int {
+    for i in 0..n {
+        // SAFETY: The caller guarantees that `s1` is valid for reads of `n` bytes
+        let byte1 = unsafe { s1.wrapping_add(i).read_volatile() };
+        // SAFETY: The caller guarantees that `s2` is valid for reads of `n` bytes
+        let byte2 = unsafe { s2.wrapping_add(i).read_volatile() };
+        if byte1 != byte2 {
+            return c_int::from(byte1) - c_int::from(byte2);
+        }
+    }
+    0
+}
+
+/// Copies `n` bytes from `src` to `dest`, from the lowest address to the
+/// highest address. Whole words are copied when `src` and `dest` are equally
+/// aligned.
+///
+/// # Safety
+///
+/// `src` must be valid for reads of `n` bytes, and `dest` must be valid for
+/// writes of `n` bytes.
+unsafe fn copy_forward(dest: *mut u8, src: *const u8, n: usize) {
+    let mut i = 0;
+    if (dest as usize ^ src as usize).is_multiple_of(WORD_SIZE) {
+        while i < n && !(dest as usize).wrapping_add(i).is_multiple_of(WORD_SIZE) {
+            // SAFETY: The caller guarantees that `src` and `dest` are valid for `n` bytes
+            unsafe {
+                copy_byte(dest, src, i);
+            }
+            i += 1;
+        }
+        while n - i >= WORD_SIZE {
+            // SAFETY: The caller guarantees that `src` and `dest` are valid for `n` bytes,
+            // and both `src + i` and `dest + i` are aligned to a word by the loop above
+            unsafe {
+                copy_word(dest, src, i);
+            }
+            i += WORD_SIZE;
+        }
+    }
+    while i < n {
+        // SAFETY: The caller guarantees that `src` and `dest` are valid for `n` bytes
+        unsafe {
+            copy_byte(dest, src, i);
+        }
+        i += 1;
+    }
+}
+
+/// Copies `n` bytes from `src` to `dest`, from the highest address to the
+/// lowest address. Whole words are copied when `src` and `dest` are equally
+/// aligned.
+///
+/// # Safety
+///
+/// `src` must be valid for reads of `n` bytes, and `dest` must be valid for
+/// writes of `n` bytes.
+unsafe fn copy_backward(dest: *mut u8, src: *const u8, n: usize) {
+    let mut i = n;
+    if (dest as usize ^ src as usize).is_multiple_of(WORD_SIZE) {
+        while i > 0 && !(dest as usize).wrapping_add(i).is_multiple_of(WORD_SIZE) {
+            i -= 1;
+            // SAFETY: The caller guarantees that `src` and `dest` are valid for `n` bytes
+            unsafe {
+                copy_byte(dest, src, i);
+            }
+        }
+        while i >= WORD_SIZE {
+            i -= WORD_SIZE;
+            // SAFETY: The caller guarantees that `src` and `dest` are valid for `n` bytes,
+            // and both `src + i` and `dest + i` are aligned to a word by the loop above
+            unsafe {
+                copy_word(dest, src, i);
+            }
+        }
+    }
+    while i > 0 {
+        i -= 1;
+        // SAFETY: The caller guarantees that `src` and `dest` are valid for `n` bytes
+        unsafe {
+            copy_byte(dest, src, i);
+        }
+    }
+}
+
+/// Copies the byte at `offset` from `src` to `dest`
+///
+/// # Safety
+///
+/// `src + offset` must be valid for reads, and `dest + offset` must be valid
+/// for writes.
+unsafe fn copy_byte(dest: *mut u8, src: *const u8, offset: usize) {
+    // SAFETY: The caller guarantees that `src + offset` is valid for reads
+    let byte = unsafe { src.wrapping_add(offset).read_volatile() };
+    // SAFETY: The caller guarantees that `dest + offset` is valid for writes
+    unsafe {
+        dest.wrapping_add(offset).write_volatile(byte);
+    }
+}
+
+/// Copies the word at `offset` from `src` to `dest`
+///
+/// # Safety
+///
+/// `src + offset` must be valid for reads of a word, `dest + offset` must be
+/// valid for writes of a word, and both must be aligned to a word.
+#[allow(clippy::cast_ptr_alignment)]
+unsafe fn copy_word(dest: *mut u8, src: *const u8, offset: usize) {
+    // SAFETY: The caller guarantees that `src + offset` is aligned and valid for
+    // reads of a word
+    let word = unsafe { src.wrapping_add(offset).cast::<usize>().read_volatile() };
+    // SAFETY: The caller guarantees that `dest + offset` is aligned and valid for
+    // writes of a word
+    unsafe {
+        dest.wrapping_add(offset)
+            .cast::<usize>()
+            .write_volatile(word);
+    }
+}
+
+// `__chkstk` is called in the prologue of functions whose stack frame is larger
+// than a page, with the size of the stack frame in `rax`. It touches each page
+// of the new stack frame in order, so that a stack overflow faults on the guard
+// page below the stack instead of skipping over it. It must not modify any
+// registers other than `r10`, `r11` and the flags. This is the same
+// implementation as `__chkstk` in LLVM's compiler-rt.
+#[cfg(target_arch = "x86_64")]
+core::arch::global_asm!(
+    ".globl __chkstk",
+    "__chkstk:",
+    "push %rcx",
+    "push %rax",
+    "cmp $0x1000, %rax",
+    "lea 24(%rsp), %rcx",
+    "jb 1f",
+    "2:",
+    "sub $0x1000, %rcx",
+    "test %rcx, (%rcx)",
+    "sub $0x1000, %rax",
+    "cmp $0x1000, %rax",
+    "ja 2b",
+    "1:",
+    "sub %rax, %rcx",
+    "test %rcx, (%rcx)",
+    "pop %rax",
+    "pop %rcx",
+    "ret",
+    options(att_syntax)
+);
+
+// On ARM64, `__chkstk` is called with the size of the stack frame divided by 16
+// in `x15`, and must not modify any registers other than `x16` and `x17`. This
+// is the same implementation as `__chkstk` in LLVM's compiler-rt.
+#[cfg(target_arch = "aarch64")]
+core::arch::global_asm!(
+    ".globl __chkstk",
+    "__chkstk:",
+    "lsl x16, x15, #4",
+    "mov x17, sp",
+    "1:",
+    "sub x17, x17, #4096",
+    "subs x16, x16, #4096",
+    "ldr xzr, [x17]",
+    "b.gt 1b",
+    "ret",
+);
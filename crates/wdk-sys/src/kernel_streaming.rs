@@ -0,0 +1,34 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Direct FFI bindings to Kernel Streaming (KS) APIs from the Windows Driver
+//! Kit (WDK)
+//!
+//! This module contains all bindings to functions, constants, methods,
+//! constructors and destructors in the following headers: `ks.h`,
+//! `ksmedia.h`. Types are not included in this module, but are available in
+//! the top-level `wdk_sys` module.
+//!
+//! Enabling the `kernel-streaming` feature also links `ks.lib`, so that the
+//! `Ks*` functions can be called by AVStream minidrivers (ex. camera and
+//! audio drivers). AVStream minidrivers must still call `KsInitializeDriver`
+//! from their `DriverEntry` to register their `KSDEVICE_DESCRIPTOR` with the
+//! Kernel Streaming class driver.
+
+#[allow(
+    missing_docs,
+    reason = "most items in the WDK headers have no inline documentation, so bindgen is unable to \
+              generate documentation for their bindings"
+)]
+mod bindings {
+    #[allow(
+        clippy::wildcard_imports,
+        reason = "the underlying c code relies on all type definitions being in scope, which \
+                  results in the bindgen generated code relying on the generated types being in \
+                  scope as well"
+    )]
+    use crate::types::*;
+
+    include!(concat!(env!("OUT_DIR"), "/kernel_streaming.rs"));
+}
+pub use bindings::*;
@@ -0,0 +1,18 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Compile-time assertions that the layout of frequently used structs in the
+//! generated bindings matches the layout computed from the WDK headers.
+//!
+//! The sizes, alignments and field offsets of these structs are computed by
+//! clang from the WDK headers when the bindings are generated, and compared
+//! against the Rust definitions in `const` assertions. A mismatch (ex. caused
+//! by a `bindgen` or architecture change) fails the build instead of corrupting
+//! memory at runtime.
+
+#![allow(
+    non_upper_case_globals,
+    reason = "the generated constants contain the names of struct fields"
+)]
+
+include!(concat!(env!("OUT_DIR"), "/layout_assertions.rs"));
@@ -29,6 +29,30 @@
 #[cfg(driver_model__driver_type = "UMDF")]
 pub mod windows;
 
+#[cfg(any(
+    driver_model__driver_type = "WDM",
+    driver_model__driver_type = "KMDF",
+    driver_model__driver_type = "UMDF"
+))]
+pub mod resources;
+
+#[cfg(any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"))]
+pub mod pci;
+
+#[cfg(any(
+    driver_model__driver_type = "WDM",
+    driver_model__driver_type = "KMDF",
+    driver_model__driver_type = "UMDF"
+))]
+pub mod ntstatus;
+
+#[cfg(any(
+    driver_model__driver_type = "WDM",
+    driver_model__driver_type = "KMDF",
+    driver_model__driver_type = "UMDF"
+))]
+pub mod devprop;
+
 #[cfg(all(
     any(
         driver_model__driver_type = "WDM",
@@ -49,9 +73,58 @@
 ))]
 pub mod spb;
 
+#[cfg(all(
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"),
+    feature = "kernel-streaming"
+))]
+pub mod kernel_streaming;
+
+#[cfg(all(
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"),
+    feature = "audio"
+))]
+pub mod audio;
+
+#[cfg(all(
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"),
+    feature = "storport"
+))]
+pub mod storport;
+
+#[cfg(all(
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"),
+    feature = "wsk"
+))]
+pub mod wsk;
+
+#[cfg(all(
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"),
+    feature = "wfp"
+))]
+pub mod wfp;
+
+#[cfg(all(
+    any(
+        driver_model__driver_type = "WDM",
+        driver_model__driver_type = "KMDF",
+        driver_model__driver_type = "UMDF"
+    ),
+    feature = "usb"
+))]
+pub mod usb;
+
+#[cfg(all(driver_model__driver_type = "KMDF", feature = "vmbus"))]
+pub mod vmbus;
+
 #[cfg(feature = "test-stubs")]
 pub mod test_stubs;
 
+#[cfg(all(
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"),
+    feature = "intrinsics"
+))]
+mod intrinsics;
+
 #[cfg(any(
     driver_model__driver_type = "WDM",
     driver_model__driver_type = "KMDF",
@@ -72,16 +145,29 @@
 ))]
 mod macros;
 
+#[cfg(any(
+    driver_model__driver_type = "WDM",
+    driver_model__driver_type = "KMDF",
+    driver_model__driver_type = "UMDF"
+))]
+mod layout_assertions;
+
 // This is fine because we don't actually have any floating point instruction in
 // our binary, thanks to our target defining soft-floats. fltused symbol is
 // necessary due to LLVM being too eager to set it: it checks the LLVM IR for
-// floating point instructions - even if soft-float is enabled!
+// floating point instructions - even if soft-float is enabled! It can be
+// disabled with the `fltused-stub` feature if another library linked into the
+// driver already defines it.
+#[cfg(feature = "fltused-stub")]
 #[allow(missing_docs)]
 #[no_mangle]
 pub static _fltused: () = ();
 
 // FIXME: Is there any way to avoid this stub? See https://github.com/rust-lang/rust/issues/101134
-#[cfg(panic = "abort")]
+// It can be disabled with the `cxx-frame-handler-stub` feature if another
+// library linked into the driver (ex. a C++ static library) provides the real
+// `__CxxFrameHandler3`.
+#[cfg(all(panic = "abort", feature = "cxx-frame-handler-stub"))]
 #[allow(missing_docs)]
 #[allow(clippy::missing_const_for_fn)] // const extern is not yet supported: https://github.com/rust-lang/rust/issues/64926
 #[no_mangle]
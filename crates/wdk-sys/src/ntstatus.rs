@@ -0,0 +1,126 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Symbolic names and messages of `NTSTATUS` values
+//!
+//! The names and messages are generated from `ntstatus.h` of the WDK, so that
+//! a status can be logged as `STATUS_INSUFFICIENT_RESOURCES` instead of
+//! `0xC000009A`. [`NtStatus`] wraps an [`NTSTATUS`] to display it by name:
+//!
+//! ```rust, ignore
+//! use wdk_sys::ntstatus::NtStatus;
+//!
+//! // Prints "IoCreateDevice failed: STATUS_INSUFFICIENT_RESOURCES"
+//! println!("IoCreateDevice failed: {}", NtStatus(nt_status));
+//! ```
+
+use core::fmt;
+
+use crate::{NTSTATUS, NT_ERROR, NT_INFORMATION, NT_SUCCESS, NT_WARNING};
+
+/// An [`NTSTATUS`] value, which is displayed by its symbolic name (ex.
+/// `STATUS_INSUFFICIENT_RESOURCES`).
+///
+/// Values that are not defined in `ntstatus.h` are displayed in hexadecimal
+/// (ex. `0xE0000001`), which is how `NTSTATUS` values are conventionally
+/// written.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NtStatus(pub NTSTATUS);
+
+impl NtStatus {
+    /// Returns the symbolic name of the status (ex.
+    /// `STATUS_INSUFFICIENT_RESOURCES`), or `None` if it is not defined in
+    /// `ntstatus.h`
+    #[must_use]
+    pub fn name(self) -> Option<&'static str> {
+        find(self.0).map(|(_, name, _)| name)
+    }
+
+    /// Returns the message of the status in `ntstatus.h` (ex. `Insufficient
+    /// system resources exist to complete the API.`), or `None` if it is not
+    /// defined or has no message. Messages may contain `%` placeholders for
+    /// arguments that are not available here.
+    #[must_use]
+    pub fn message(self) -> Option<&'static str> {
+        find(self.0).and_then(|(_, _, message)| message)
+    }
+
+    /// Returns whether the status is a success type (0 − 0x3FFFFFFF) or an
+    /// informational type (0x40000000 − 0x7FFFFFFF). See [`NT_SUCCESS`].
+    #[must_use]
+    pub const fn is_success(self) -> bool {
+        NT_SUCCESS(self.0)
+    }
+
+    /// Returns whether the status is an informational type (0x40000000 −
+    /// 0x7FFFFFFF). See [`NT_INFORMATION`].
+    #[must_use]
+    pub const fn is_information(self) -> bool {
+        NT_INFORMATION(self.0)
+    }
+
+    /// Returns whether the status is a warning type (0x80000000 −
+    /// 0xBFFFFFFF). See [`NT_WARNING`].
+    #[must_use]
+    pub const fn is_warning(self) -> bool {
+        NT_WARNING(self.0)
+    }
+
+    /// Returns whether the status is an error type (0xC0000000 - 0xFFFFFFFF).
+    /// See [`NT_ERROR`].
+    #[must_use]
+    pub const fn is_error(self) -> bool {
+        NT_ERROR(self.0)
+    }
+}
+
+impl From<NTSTATUS> for NtStatus {
+    fn from(nt_status: NTSTATUS) -> Self {
+        Self(nt_status)
+    }
+}
+
+impl From<NtStatus> for NTSTATUS {
+    fn from(nt_status: NtStatus) -> Self {
+        nt_status.0
+    }
+}
+
+impl fmt::Display for NtStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            // Hexadecimal formatting of signed integers uses their two's complement
+            // representation (ex. 0xC000009A)
+            None => write!(f, "{:#010X}", self.0),
+        }
+    }
+}
+
+impl fmt::Debug for NtStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{name} ({:#010X})", self.0),
+            None => write!(f, "{:#010X}", self.0),
+        }
+    }
+}
+
+/// Returns the entry of `nt_status` in the generated table of `NTSTATUS`
+/// values, which is sorted by value
+fn find(nt_status: NTSTATUS) -> Option<(NTSTATUS, &'static str, Option<&'static str>)> {
+    names::NT_STATUS_NAMES
+        .binary_search_by_key(&nt_status, |(value, ..)| *value)
+        .ok()
+        .map(|index| names::NT_STATUS_NAMES[index])
+}
+
+#[allow(
+    clippy::unreadable_literal,
+    reason = "NTSTATUS values are generated as decimal literals"
+)]
+mod names {
+    use crate::NTSTATUS;
+
+    include!(concat!(env!("OUT_DIR"), "/ntstatus_names.rs"));
+}
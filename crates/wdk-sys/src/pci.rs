@@ -0,0 +1,313 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Access to the configuration space of PCI devices
+//!
+//! Drivers of PCI devices read and write the configuration space of their
+//! device through the [`BUS_INTERFACE_STANDARD`] of the PCI bus driver, which
+//! is queried with `IRP_MN_QUERY_INTERFACE` (or `WdfFdoQueryForInterface` in
+//! KMDF drivers) for `GUID_BUS_INTERFACE_STANDARD`. This module contains the
+//! offsets of the registers of the common configuration header, which are
+//! checked at compile time against the [`PCI_COMMON_HEADER`] of the bindings,
+//! and functions that access the configuration space through the interface:
+//!
+//! ```rust, ignore
+//! use wdk_sys::pci;
+//!
+//! let mut interrupt_pin = [0u8; 1];
+//! // SAFETY: `bus_interface` was queried from the PCI bus driver, and is
+//! // referenced until the device is removed
+//! unsafe { pci::read_config(&bus_interface, pci::INTERRUPT_PIN_OFFSET, &mut interrupt_pin) };
+//! ```
+//!
+//! Registers of type 0 headers (ex. [`BASE_ADDRESSES_OFFSET`]) are only valid
+//! for devices whose [`HEADER_TYPE_OFFSET`] register, without the
+//! `PCI_MULTIFUNCTION` bit, is `PCI_DEVICE_TYPE`.
+
+use core::mem::{offset_of, size_of, MaybeUninit};
+
+use crate::{
+    BUS_INTERFACE_STANDARD,
+    PCI_CARDBUS_BRIDGE_TYPE,
+    PCI_COMMON_CONFIG,
+    PCI_COMMON_HEADER,
+    PCI_MULTIFUNCTION,
+    PCI_STATUS_CAPABILITIES_LIST,
+    PCI_WHICHSPACE_CONFIG,
+    ULONG,
+};
+
+/// Offset of the vendor ID register (`USHORT`)
+pub const VENDOR_ID_OFFSET: ULONG = 0x00;
+/// Offset of the device ID register (`USHORT`)
+pub const DEVICE_ID_OFFSET: ULONG = 0x02;
+/// Offset of the command register (`USHORT`)
+pub const COMMAND_OFFSET: ULONG = 0x04;
+/// Offset of the status register (`USHORT`)
+pub const STATUS_OFFSET: ULONG = 0x06;
+/// Offset of the revision ID register (`UCHAR`)
+pub const REVISION_ID_OFFSET: ULONG = 0x08;
+/// Offset of the programming interface register (`UCHAR`)
+pub const PROG_IF_OFFSET: ULONG = 0x09;
+/// Offset of the sub-class code register (`UCHAR`)
+pub const SUB_CLASS_OFFSET: ULONG = 0x0A;
+/// Offset of the base class code register (`UCHAR`)
+pub const BASE_CLASS_OFFSET: ULONG = 0x0B;
+/// Offset of the cache line size register (`UCHAR`)
+pub const CACHE_LINE_SIZE_OFFSET: ULONG = 0x0C;
+/// Offset of the latency timer register (`UCHAR`)
+pub const LATENCY_TIMER_OFFSET: ULONG = 0x0D;
+/// Offset of the header type register (`UCHAR`)
+pub const HEADER_TYPE_OFFSET: ULONG = 0x0E;
+/// Offset of the built-in self test register (`UCHAR`)
+pub const BIST_OFFSET: ULONG = 0x0F;
+
+/// Offset of the base address registers of a type 0 header (`ULONG[6]`)
+pub const BASE_ADDRESSES_OFFSET: ULONG = 0x10;
+/// Offset of the card information structure pointer register of a type 0 header
+/// (`ULONG`)
+pub const CIS_OFFSET: ULONG = 0x28;
+/// Offset of the subsystem vendor ID register of a type 0 header (`USHORT`)
+pub const SUBSYSTEM_VENDOR_ID_OFFSET: ULONG = 0x2C;
+/// Offset of the subsystem ID register of a type 0 header (`USHORT`)
+pub const SUBSYSTEM_ID_OFFSET: ULONG = 0x2E;
+/// Offset of the expansion ROM base address register of a type 0 header
+/// (`ULONG`)
+pub const ROM_BASE_ADDRESS_OFFSET: ULONG = 0x30;
+/// Offset of the capabilities pointer register of type 0 and type 1 headers
+/// (`UCHAR`)
+pub const CAPABILITIES_PTR_OFFSET: ULONG = 0x34;
+/// Offset of the interrupt line register of a type 0 header (`UCHAR`)
+pub const INTERRUPT_LINE_OFFSET: ULONG = 0x3C;
+/// Offset of the interrupt pin register of a type 0 header (`UCHAR`)
+pub const INTERRUPT_PIN_OFFSET: ULONG = 0x3D;
+/// Offset of the minimum grant register of a type 0 header (`UCHAR`)
+pub const MINIMUM_GRANT_OFFSET: ULONG = 0x3E;
+/// Offset of the maximum latency register of a type 0 header (`UCHAR`)
+pub const MAXIMUM_LATENCY_OFFSET: ULONG = 0x3F;
+
+/// Length of the common configuration header, which is the
+/// [`PCI_COMMON_HEADER`]
+pub const COMMON_HEADER_LENGTH: ULONG = 0x40;
+/// Length of the configuration space of PCI devices, which is the
+/// [`PCI_COMMON_CONFIG`]
+pub const CONFIG_SPACE_LENGTH: ULONG = 0x100;
+/// Length of the configuration space of PCI Express devices, including the
+/// extended configuration space
+pub const EXTENDED_CONFIG_SPACE_LENGTH: ULONG = 0x1000;
+
+const _: () = {
+    const fn offset(offset: usize) -> ULONG {
+        assert!(offset <= ULONG::MAX as usize);
+        #[allow(clippy::cast_possible_truncation)]
+        {
+            offset as ULONG
+        }
+    }
+
+    assert!(offset(offset_of!(PCI_COMMON_HEADER, VendorID)) == VENDOR_ID_OFFSET);
+    assert!(offset(offset_of!(PCI_COMMON_HEADER, DeviceID)) == DEVICE_ID_OFFSET);
+    assert!(offset(offset_of!(PCI_COMMON_HEADER, Command)) == COMMAND_OFFSET);
+    assert!(offset(offset_of!(PCI_COMMON_HEADER, Status)) == STATUS_OFFSET);
+    assert!(offset(offset_of!(PCI_COMMON_HEADER, RevisionID)) == REVISION_ID_OFFSET);
+    assert!(offset(offset_of!(PCI_COMMON_HEADER, ProgIf)) == PROG_IF_OFFSET);
+    assert!(offset(offset_of!(PCI_COMMON_HEADER, SubClass)) == SUB_CLASS_OFFSET);
+    assert!(offset(offset_of!(PCI_COMMON_HEADER, BaseClass)) == BASE_CLASS_OFFSET);
+    assert!(offset(offset_of!(PCI_COMMON_HEADER, CacheLineSize)) == CACHE_LINE_SIZE_OFFSET);
+    assert!(offset(offset_of!(PCI_COMMON_HEADER, LatencyTimer)) == LATENCY_TIMER_OFFSET);
+    assert!(offset(offset_of!(PCI_COMMON_HEADER, HeaderType)) == HEADER_TYPE_OFFSET);
+    assert!(offset(offset_of!(PCI_COMMON_HEADER, BIST)) == BIST_OFFSET);
+
+    assert!(offset(offset_of!(PCI_COMMON_HEADER, u.type0.BaseAddresses)) == BASE_ADDRESSES_OFFSET);
+    assert!(offset(offset_of!(PCI_COMMON_HEADER, u.type0.CIS)) == CIS_OFFSET);
+    assert!(
+        offset(offset_of!(PCI_COMMON_HEADER, u.type0.SubVendorID)) == SUBSYSTEM_VENDOR_ID_OFFSET
+    );
+    assert!(offset(offset_of!(PCI_COMMON_HEADER, u.type0.SubSystemID)) == SUBSYSTEM_ID_OFFSET);
+    assert!(
+        offset(offset_of!(PCI_COMMON_HEADER, u.type0.ROMBaseAddress)) == ROM_BASE_ADDRESS_OFFSET
+    );
+    assert!(
+        offset(offset_of!(PCI_COMMON_HEADER, u.type0.CapabilitiesPtr)) == CAPABILITIES_PTR_OFFSET
+    );
+    assert!(offset(offset_of!(PCI_COMMON_HEADER, u.type0.InterruptLine)) == INTERRUPT_LINE_OFFSET);
+    assert!(offset(offset_of!(PCI_COMMON_HEADER, u.type0.InterruptPin)) == INTERRUPT_PIN_OFFSET);
+    assert!(offset(offset_of!(PCI_COMMON_HEADER, u.type0.MinimumGrant)) == MINIMUM_GRANT_OFFSET);
+    assert!(
+        offset(offset_of!(PCI_COMMON_HEADER, u.type0.MaximumLatency)) == MAXIMUM_LATENCY_OFFSET
+    );
+
+    assert!(offset(size_of::<PCI_COMMON_HEADER>()) == COMMON_HEADER_LENGTH);
+    assert!(offset(size_of::<PCI_COMMON_CONFIG>()) == CONFIG_SPACE_LENGTH);
+};
+
+/// Reads `buffer.len()` bytes of the configuration space of the device of
+/// `bus_interface`, starting at `offset`, and returns the number of bytes
+/// read.
+///
+/// Fewer bytes are read if the range extends past the configuration
+/// space of the device.
+///
+/// This must be called at `IRQL` <= `DISPATCH_LEVEL`.
+///
+/// # Safety
+///
+/// `bus_interface` must have been queried from the PCI bus driver for
+/// `GUID_BUS_INTERFACE_STANDARD`, and must still be referenced (i.e.
+/// `InterfaceDereference` must not have been called on it)
+#[must_use]
+pub unsafe fn read_config(
+    bus_interface: &BUS_INTERFACE_STANDARD,
+    offset: ULONG,
+    buffer: &mut [u8],
+) -> ULONG {
+    let Some(get_bus_data) = bus_interface.GetBusData else {
+        return 0;
+    };
+    let length = ULONG::try_from(buffer.len()).unwrap_or(ULONG::MAX);
+
+    // SAFETY: The caller guarantees that `bus_interface` is a referenced PCI bus
+    // interface, and `buffer` is valid for writes of `length` bytes
+    unsafe {
+        get_bus_data(
+            bus_interface.Context,
+            PCI_WHICHSPACE_CONFIG,
+            buffer.as_mut_ptr().cast(),
+            offset,
+            length,
+        )
+    }
+}
+
+/// Writes `buffer` to the configuration space of the device of
+/// `bus_interface`, starting at `offset`, and returns the number of bytes
+/// written.
+///
+/// Fewer bytes are written if the range extends past the
+/// configuration space of the device.
+///
+/// This must be called at `IRQL` <= `DISPATCH_LEVEL`.
+///
+/// # Safety
+///
+/// `bus_interface` must have been queried from the PCI bus driver for
+/// `GUID_BUS_INTERFACE_STANDARD`, and must still be referenced (i.e.
+/// `InterfaceDereference` must not have been called on it). The written
+/// registers must not change the resources of the device that are in use
+/// (ex. its base address registers while they are mapped).
+#[must_use]
+pub unsafe fn write_config(
+    bus_interface: &BUS_INTERFACE_STANDARD,
+    offset: ULONG,
+    buffer: &[u8],
+) -> ULONG {
+    let Some(set_bus_data) = bus_interface.SetBusData else {
+        return 0;
+    };
+    let length = ULONG::try_from(buffer.len()).unwrap_or(ULONG::MAX);
+
+    // SAFETY: The caller guarantees that `bus_interface` is a referenced PCI bus
+    // interface and that the write is sound, and `buffer` is valid for reads of
+    // `length` bytes. `SetBusData` does not write to the buffer.
+    unsafe {
+        set_bus_data(
+            bus_interface.Context,
+            PCI_WHICHSPACE_CONFIG,
+            buffer.as_ptr().cast_mut().cast(),
+            offset,
+            length,
+        )
+    }
+}
+
+/// Reads the common configuration header of the device of `bus_interface`, or
+/// returns `None` if it cannot be read entirely.
+///
+/// This must be called at `IRQL` <= `DISPATCH_LEVEL`.
+///
+/// # Safety
+///
+/// `bus_interface` must have been queried from the PCI bus driver for
+/// `GUID_BUS_INTERFACE_STANDARD`, and must still be referenced (i.e.
+/// `InterfaceDereference` must not have been called on it)
+#[must_use]
+pub unsafe fn read_common_header(
+    bus_interface: &BUS_INTERFACE_STANDARD,
+) -> Option<PCI_COMMON_HEADER> {
+    let mut header = MaybeUninit::<PCI_COMMON_HEADER>::zeroed();
+    // SAFETY: `header` is valid for writes of `size_of::<PCI_COMMON_HEADER>()`
+    // bytes, and was zero-initialized, which is a valid `u8` for each of them
+    let buffer = unsafe {
+        core::slice::from_raw_parts_mut(
+            header.as_mut_ptr().cast::<u8>(),
+            size_of::<PCI_COMMON_HEADER>(),
+        )
+    };
+
+    // SAFETY: The caller guarantees that `bus_interface` is a referenced PCI bus
+    // interface
+    if unsafe { read_config(bus_interface, 0, buffer) } != COMMON_HEADER_LENGTH {
+        return None;
+    }
+
+    // SAFETY: All fields of `PCI_COMMON_HEADER` are integers (or unions and arrays
+    // of integers), which were zero-initialized then read from the configuration
+    // space
+    Some(unsafe { header.assume_init() })
+}
+
+/// Returns the offset of the first capability of the device of `bus_interface`
+/// whose ID is `capability_id` (ex. `PCI_CAPABILITY_ID_MSIX`), or `None` if the
+/// device does not have this capability.
+///
+/// Only the capabilities in the configuration space of PCI devices are
+/// searched, not the extended capabilities of PCI Express devices.
+///
+/// This must be called at `IRQL` <= `DISPATCH_LEVEL`.
+///
+/// # Safety
+///
+/// `bus_interface` must have been queried from the PCI bus driver for
+/// `GUID_BUS_INTERFACE_STANDARD`, and must still be referenced (i.e.
+/// `InterfaceDereference` must not have been called on it)
+#[must_use]
+pub unsafe fn find_capability(
+    bus_interface: &BUS_INTERFACE_STANDARD,
+    capability_id: u8,
+) -> Option<ULONG> {
+    // SAFETY: The caller guarantees that `bus_interface` is a referenced PCI bus
+    // interface
+    let header = unsafe { read_common_header(bus_interface) }?;
+    if ULONG::from(header.Status) & PCI_STATUS_CAPABILITIES_LIST == 0
+        || ULONG::from(header.HeaderType) & !PCI_MULTIFUNCTION == PCI_CARDBUS_BRIDGE_TYPE
+    {
+        return None;
+    }
+
+    // SAFETY: The capabilities pointer is at the same offset in type 0 and type 1
+    // headers, and all members of the `u` union are integers
+    let mut offset = ULONG::from(unsafe { header.u.type0.CapabilitiesPtr });
+    // Each capability is at least 4 bytes long, which bounds the length of the
+    // list even if it is malformed (ex. circular)
+    for _ in 0..CONFIG_SPACE_LENGTH / 4 {
+        // The bottom 2 bits of capability pointers are reserved
+        offset &= !0b11;
+        if offset < COMMON_HEADER_LENGTH {
+            return None;
+        }
+
+        // The `CapabilityID` and `Next` fields of the `PCI_CAPABILITIES_HEADER`
+        let mut capability = [0u8; 2];
+        // SAFETY: The caller guarantees that `bus_interface` is a referenced PCI bus
+        // interface
+        if unsafe { read_config(bus_interface, offset, &mut capability) } != 2 {
+            return None;
+        }
+        let [id, next] = capability;
+        if id == capability_id {
+            return Some(offset);
+        }
+        offset = ULONG::from(next);
+    }
+    None
+}
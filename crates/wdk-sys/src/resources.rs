@@ -0,0 +1,310 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Accessors for the hardware resources described by resource descriptors
+//!
+//! The resources of a device are described by
+//! [`CM_PARTIAL_RESOURCE_DESCRIPTOR`]s
+//! (ex. from `WdfCmResourceListGetDescriptor` in `EvtDevicePrepareHardware`),
+//! and the resource requirements of a device by [`IO_RESOURCE_DESCRIPTOR`]s.
+//! The contents of both depend on their `Type`, which selects the active
+//! member of their `u` union. The functions in this module check the `Type`
+//! of a descriptor before reading the corresponding member of the union, and
+//! return `None` for descriptors of other types:
+//!
+//! ```rust, ignore
+//! use wdk_sys::resources;
+//!
+//! if let Some(memory) = resources::memory(&*descriptor) {
+//!     // Map `memory.length` bytes at `memory.start`
+//! }
+//! ```
+//!
+//! Large memory ranges (`CmResourceTypeMemoryLarge`) are returned with their
+//! lengths already decoded from the `CM_RESOURCE_MEMORY_LARGE_*` flags of the
+//! descriptor.
+
+#[cfg(any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"))]
+use crate::IO_RESOURCE_DESCRIPTOR;
+use crate::{
+    CmResourceTypeConnection,
+    CmResourceTypeInterrupt,
+    CmResourceTypeMemory,
+    CmResourceTypeMemoryLarge,
+    CmResourceTypePort,
+    CM_PARTIAL_RESOURCE_DESCRIPTOR,
+    CM_RESOURCE_INTERRUPT_LATCHED,
+    CM_RESOURCE_INTERRUPT_MESSAGE,
+    CM_RESOURCE_MEMORY_LARGE_40,
+    CM_RESOURCE_MEMORY_LARGE_48,
+    CM_RESOURCE_MEMORY_LARGE_64,
+    KAFFINITY,
+    PHYSICAL_ADDRESS,
+    ULONG,
+};
+
+/// A range of memory or I/O port addresses assigned to a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressRange {
+    /// Starting address of the range
+    pub start: i64,
+    /// Length of the range in bytes
+    pub length: u64,
+}
+
+/// An interrupt assigned to a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interrupt {
+    /// Interrupt level (IRQL for translated descriptors)
+    pub level: u32,
+    /// Interrupt vector
+    pub vector: u32,
+    /// Processors that the interrupt can be delivered to
+    pub affinity: KAFFINITY,
+    /// Whether the interrupt is latched (edge-triggered), rather than
+    /// level-triggered
+    pub is_latched: bool,
+    /// Whether the interrupt is message-signaled (MSI or MSI-X)
+    pub is_message_signaled: bool,
+}
+
+/// A connection to a device on another bus (ex. a GPIO pin or a peripheral on
+/// an I2C or SPI bus)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Connection {
+    /// Class of the connection (ex. `CM_RESOURCE_CONNECTION_CLASS_SERIAL`)
+    pub class: u8,
+    /// Type of the connection within its class (ex.
+    /// `CM_RESOURCE_CONNECTION_TYPE_SERIAL_I2C`)
+    pub connection_type: u8,
+    /// 64-bit connection ID, which is opened through the resource hub
+    pub id: u64,
+}
+
+/// Returns the memory range described by a `CmResourceTypeMemory` or
+/// `CmResourceTypeMemoryLarge` resource descriptor
+#[must_use]
+pub fn memory(descriptor: &CM_PARTIAL_RESOURCE_DESCRIPTOR) -> Option<AddressRange> {
+    match ULONG::from(descriptor.Type) {
+        CmResourceTypeMemory => {
+            // SAFETY: `Memory` is the active member of the `u` union of
+            // `CmResourceTypeMemory` descriptors
+            let memory = unsafe { descriptor.u.Memory };
+            Some(AddressRange {
+                start: physical_address(memory.Start),
+                length: u64::from(memory.Length),
+            })
+        }
+        CmResourceTypeMemoryLarge => {
+            let flags = ULONG::from(descriptor.Flags);
+            if flags & CM_RESOURCE_MEMORY_LARGE_40 != 0 {
+                // SAFETY: `Memory40` is the active member of the `u` union of
+                // `CmResourceTypeMemoryLarge` descriptors with `CM_RESOURCE_MEMORY_LARGE_40`
+                let memory = unsafe { descriptor.u.Memory40 };
+                Some(AddressRange {
+                    start: physical_address(memory.Start),
+                    length: u64::from(memory.Length40) << 8,
+                })
+            } else if flags & CM_RESOURCE_MEMORY_LARGE_48 != 0 {
+                // SAFETY: `Memory48` is the active member of the `u` union of
+                // `CmResourceTypeMemoryLarge` descriptors with `CM_RESOURCE_MEMORY_LARGE_48`
+                let memory = unsafe { descriptor.u.Memory48 };
+                Some(AddressRange {
+                    start: physical_address(memory.Start),
+                    length: u64::from(memory.Length48) << 16,
+                })
+            } else if flags & CM_RESOURCE_MEMORY_LARGE_64 != 0 {
+                // SAFETY: `Memory64` is the active member of the `u` union of
+                // `CmResourceTypeMemoryLarge` descriptors with `CM_RESOURCE_MEMORY_LARGE_64`
+                let memory = unsafe { descriptor.u.Memory64 };
+                Some(AddressRange {
+                    start: physical_address(memory.Start),
+                    length: u64::from(memory.Length64) << 32,
+                })
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Returns the I/O port range described by a `CmResourceTypePort` resource
+/// descriptor
+#[must_use]
+pub fn port(descriptor: &CM_PARTIAL_RESOURCE_DESCRIPTOR) -> Option<AddressRange> {
+    if ULONG::from(descriptor.Type) != CmResourceTypePort {
+        return None;
+    }
+
+    // SAFETY: `Port` is the active member of the `u` union of `CmResourceTypePort`
+    // descriptors
+    let port = unsafe { descriptor.u.Port };
+    Some(AddressRange {
+        start: physical_address(port.Start),
+        length: u64::from(port.Length),
+    })
+}
+
+/// Returns the interrupt described by a translated `CmResourceTypeInterrupt`
+/// resource descriptor.
+///
+/// In translated descriptors, the `Interrupt` member of the `u` union also
+/// describes message-signaled interrupts. In raw descriptors of
+/// message-signaled interrupts, `level` is not meaningful.
+#[must_use]
+pub fn interrupt(descriptor: &CM_PARTIAL_RESOURCE_DESCRIPTOR) -> Option<Interrupt> {
+    if ULONG::from(descriptor.Type) != CmResourceTypeInterrupt {
+        return None;
+    }
+
+    // SAFETY: `Interrupt` is the active member of the `u` union of translated
+    // `CmResourceTypeInterrupt` descriptors. For other descriptors, all of its
+    // fields are integers, so reading it is still sound.
+    let interrupt = unsafe { descriptor.u.Interrupt };
+    // `Level` is only a `USHORT` when the WDK is configured for processor groups
+    #[allow(clippy::useless_conversion)]
+    let level = u32::from(interrupt.Level);
+    let flags = ULONG::from(descriptor.Flags);
+    Some(Interrupt {
+        level,
+        vector: interrupt.Vector,
+        affinity: interrupt.Affinity,
+        is_latched: flags & CM_RESOURCE_INTERRUPT_LATCHED != 0,
+        is_message_signaled: flags & CM_RESOURCE_INTERRUPT_MESSAGE != 0,
+    })
+}
+
+/// Returns the connection described by a `CmResourceTypeConnection` resource
+/// descriptor
+#[must_use]
+pub fn connection(descriptor: &CM_PARTIAL_RESOURCE_DESCRIPTOR) -> Option<Connection> {
+    if ULONG::from(descriptor.Type) != CmResourceTypeConnection {
+        return None;
+    }
+
+    // SAFETY: `Connection` is the active member of the `u` union of
+    // `CmResourceTypeConnection` descriptors
+    let connection = unsafe { descriptor.u.Connection };
+    Some(Connection {
+        class: connection.Class,
+        connection_type: connection.Type,
+        id: (u64::from(connection.IdHighPart) << 32) | u64::from(connection.IdLowPart),
+    })
+}
+
+/// A range of memory or I/O port addresses that can satisfy a resource
+/// requirement of a device
+#[cfg(any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressRequirement {
+    /// Length of the range in bytes
+    pub length: u64,
+    /// Alignment of the start of the range in bytes
+    pub alignment: u64,
+    /// Lowest address that the range can start at
+    pub minimum_address: i64,
+    /// Highest address that the range can end at
+    pub maximum_address: i64,
+}
+
+/// Returns the memory range required by a `CmResourceTypeMemory` or
+/// `CmResourceTypeMemoryLarge` resource requirement descriptor
+#[cfg(any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"))]
+#[must_use]
+pub fn memory_requirement(descriptor: &IO_RESOURCE_DESCRIPTOR) -> Option<AddressRequirement> {
+    match ULONG::from(descriptor.Type) {
+        CmResourceTypeMemory => {
+            // SAFETY: `Memory` is the active member of the `u` union of
+            // `CmResourceTypeMemory` descriptors
+            let memory = unsafe { descriptor.u.Memory };
+            Some(AddressRequirement {
+                length: u64::from(memory.Length),
+                alignment: u64::from(memory.Alignment),
+                minimum_address: physical_address(memory.MinimumAddress),
+                maximum_address: physical_address(memory.MaximumAddress),
+            })
+        }
+        CmResourceTypeMemoryLarge => {
+            let flags = ULONG::from(descriptor.Flags);
+            if flags & CM_RESOURCE_MEMORY_LARGE_40 != 0 {
+                // SAFETY: `Memory40` is the active member of the `u` union of
+                // `CmResourceTypeMemoryLarge` descriptors with `CM_RESOURCE_MEMORY_LARGE_40`
+                let memory = unsafe { descriptor.u.Memory40 };
+                Some(AddressRequirement {
+                    length: u64::from(memory.Length40) << 8,
+                    alignment: u64::from(memory.Alignment40) << 8,
+                    minimum_address: physical_address(memory.MinimumAddress),
+                    maximum_address: physical_address(memory.MaximumAddress),
+                })
+            } else if flags & CM_RESOURCE_MEMORY_LARGE_48 != 0 {
+                // SAFETY: `Memory48` is the active member of the `u` union of
+                // `CmResourceTypeMemoryLarge` descriptors with `CM_RESOURCE_MEMORY_LARGE_48`
+                let memory = unsafe { descriptor.u.Memory48 };
+                Some(AddressRequirement {
+                    length: u64::from(memory.Length48) << 16,
+                    alignment: u64::from(memory.Alignment48) << 16,
+                    minimum_address: physical_address(memory.MinimumAddress),
+                    maximum_address: physical_address(memory.MaximumAddress),
+                })
+            } else if flags & CM_RESOURCE_MEMORY_LARGE_64 != 0 {
+                // SAFETY: `Memory64` is the active member of the `u` union of
+                // `CmResourceTypeMemoryLarge` descriptors with `CM_RESOURCE_MEMORY_LARGE_64`
+                let memory = unsafe { descriptor.u.Memory64 };
+                Some(AddressRequirement {
+                    length: u64::from(memory.Length64) << 32,
+                    alignment: u64::from(memory.Alignment64) << 32,
+                    minimum_address: physical_address(memory.MinimumAddress),
+                    maximum_address: physical_address(memory.MaximumAddress),
+                })
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Returns the I/O port range required by a `CmResourceTypePort` resource
+/// requirement descriptor
+#[cfg(any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"))]
+#[must_use]
+pub fn port_requirement(descriptor: &IO_RESOURCE_DESCRIPTOR) -> Option<AddressRequirement> {
+    if ULONG::from(descriptor.Type) != CmResourceTypePort {
+        return None;
+    }
+
+    // SAFETY: `Port` is the active member of the `u` union of `CmResourceTypePort`
+    // descriptors
+    let port = unsafe { descriptor.u.Port };
+    Some(AddressRequirement {
+        length: u64::from(port.Length),
+        alignment: u64::from(port.Alignment),
+        minimum_address: physical_address(port.MinimumAddress),
+        maximum_address: physical_address(port.MaximumAddress),
+    })
+}
+
+/// Returns the range of interrupt vectors that can satisfy a
+/// `CmResourceTypeInterrupt` resource requirement descriptor
+#[cfg(any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"))]
+#[must_use]
+pub fn interrupt_requirement(
+    descriptor: &IO_RESOURCE_DESCRIPTOR,
+) -> Option<core::ops::RangeInclusive<u32>> {
+    if ULONG::from(descriptor.Type) != CmResourceTypeInterrupt {
+        return None;
+    }
+
+    // SAFETY: `Interrupt` is the active member of the `u` union of
+    // `CmResourceTypeInterrupt` descriptors
+    let interrupt = unsafe { descriptor.u.Interrupt };
+    Some(interrupt.MinimumVector..=interrupt.MaximumVector)
+}
+
+/// Returns the value of a [`PHYSICAL_ADDRESS`]
+const fn physical_address(address: PHYSICAL_ADDRESS) -> i64 {
+    // SAFETY: All members of the `LARGE_INTEGER` union are integers covering the
+    // same 8 bytes, so `QuadPart` is always initialized
+    unsafe { address.QuadPart }
+}
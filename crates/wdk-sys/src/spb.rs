@@ -7,6 +7,11 @@
 //! constructors and destructors in the following headers: `spb.h`, `spbcx.h`,
 //! `reshub.h`, `pwmutil.h`. Types are not included in this module, but are
 //! available in the top-level `wdk_sys` module.
+//!
+//! In KMDF drivers, this includes the `FORCEINLINE` SpbCx functions (ex.
+//! `SpbDeviceInitConfig`), which are called through C wrappers compiled by the
+//! build script. `SpbCxStubs.lib`, which provides the SpbCx function table
+//! they dispatch through, is linked automatically.
 
 #[allow(
     missing_docs,
@@ -35,3 +40,21 @@ mod bindings {
               generated by bindgen, so the `bindings` module is empty"
 )]
 pub use bindings::*;
+
+/// Values of the SPB IOCTLs (ex. `IOCTL_SPB_EXECUTE_SEQUENCE`), which bindgen
+/// is unable to generate from the `CTL_CODE` macro
+#[allow(
+    missing_docs,
+    reason = "the IOCTLs are defined by macros in the WDK headers, which have no inline \
+              documentation"
+)]
+mod ioctls {
+    #[allow(
+        clippy::wildcard_imports,
+        reason = "the generated constants are typed with the type definitions of the WDK"
+    )]
+    use crate::types::*;
+
+    include!(concat!(env!("OUT_DIR"), "/spb_ioctls.rs"));
+}
+pub use ioctls::*;
@@ -0,0 +1,32 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Direct FFI bindings to Storport APIs from the Windows Driver Kit (WDK)
+//!
+//! This module contains all bindings to functions, constants, methods,
+//! constructors and destructors in the following headers: `storport.h`. Types
+//! are not included in this module, but are available in the top-level
+//! `wdk_sys` module.
+//!
+//! Enabling the `storport` feature also links `storport.lib`, so that the
+//! `StorPort*` functions can be called by Storport miniport drivers. Miniport
+//! drivers must still call `StorPortInitialize` from their `DriverEntry` to
+//! register their `HW_INITIALIZATION_DATA` with the Storport port driver.
+
+#[allow(
+    missing_docs,
+    reason = "most items in the WDK headers have no inline documentation, so bindgen is unable to \
+              generate documentation for their bindings"
+)]
+mod bindings {
+    #[allow(
+        clippy::wildcard_imports,
+        reason = "the underlying c code relies on all type definitions being in scope, which \
+                  results in the bindgen generated code relying on the generated types being in \
+                  scope as well"
+    )]
+    use crate::types::*;
+
+    include!(concat!(env!("OUT_DIR"), "/storport.rs"));
+}
+pub use bindings::*;
@@ -0,0 +1,46 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Direct FFI bindings to the Hyper-V `VMBus` Kernel Mode Client Library
+//! (KMCL) APIs from the Windows Driver Kit (WDK)
+//!
+//! This module contains all bindings to functions, constants, methods,
+//! constructors and destructors in the following headers:
+//! `vmbuskernelmodeclientlibapi.h`. Types are not included in this module, but
+//! are available in the top-level `wdk_sys` module.
+//!
+//! Enabling the `vmbus` feature also links `vmbkmcl.lib`, which implements the
+//! `Vmb*` functions. The KMCL is built on KMDF, so these bindings are only
+//! available to KMDF drivers. A para-virtualized device driver typically uses
+//! a channel as follows:
+//!
+//! 1. `VmbChannelAllocate` creates a `VMBCHANNEL` parented to the driver's
+//!    `WDFDEVICE`, typically from `EvtDevicePrepareHardware`.
+//! 1. The `VmbChannelInit*` functions configure the channel (ex. its ring
+//!    buffer sizes and the callbacks that process incoming packets), and
+//!    `VmbChannelEnable` opens it. Both must be called at `PASSIVE_LEVEL`.
+//! 1. Packets are sent with `VmbPacketSend` (and its variants) and completed
+//!    with `VmbChannelPacketComplete`, which can be called at up to
+//!    `DISPATCH_LEVEL`.
+//! 1. `VmbChannelDisable` closes the channel, waiting for outstanding packets
+//!    to be processed, and `VmbChannelCleanup` frees it.
+//!
+//! Full documentation is available in the [`VMBus` Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/vmbuskernelmodeclientlibapi/).
+
+#[allow(
+    missing_docs,
+    reason = "most items in the WDK headers have no inline documentation, so bindgen is unable to \
+              generate documentation for their bindings"
+)]
+mod bindings {
+    #[allow(
+        clippy::wildcard_imports,
+        reason = "the underlying c code relies on all type definitions being in scope, which \
+                  results in the bindgen generated code relying on the generated types being in \
+                  scope as well"
+    )]
+    use crate::types::*;
+
+    include!(concat!(env!("OUT_DIR"), "/vmbus.rs"));
+}
+pub use bindings::*;
@@ -0,0 +1,37 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Direct FFI bindings to WFP (Windows Filtering Platform) APIs from the
+//! Windows Driver Kit (WDK)
+//!
+//! This module contains all bindings to functions, constants, methods,
+//! constructors and destructors in the following headers: `ndis.h`,
+//! `fwpsk.h` and `fwpmk.h`. Types are not included in this module, but are
+//! available in the top-level `wdk_sys` module. The headers are processed with
+//! `NDIS630` defined.
+//!
+//! Enabling the `wfp` feature also links `fwpkclnt.lib`, which provides the
+//! `Fwps*` functions used by callout drivers (ex. `FwpsCalloutRegister3`) and
+//! the `Fwpm*` functions of the filter engine (ex. `FwpmEngineOpen0`), and
+//! `ndis.lib`, which provides the `Ndis*` functions used to access the
+//! `NET_BUFFER_LIST`s passed to a callout's `classifyFn`.
+//!
+//! Full documentation is available in the [Windows Filtering Platform Callout Drivers Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/network/introduction-to-windows-filtering-platform-callout-drivers).
+
+#[allow(
+    missing_docs,
+    reason = "most items in the WDK headers have no inline documentation, so bindgen is unable to \
+              generate documentation for their bindings"
+)]
+mod bindings {
+    #[allow(
+        clippy::wildcard_imports,
+        reason = "the underlying c code relies on all type definitions being in scope, which \
+                  results in the bindgen generated code relying on the generated types being in \
+                  scope as well"
+    )]
+    use crate::types::*;
+
+    include!(concat!(env!("OUT_DIR"), "/wfp.rs"));
+}
+pub use bindings::*;
@@ -6,8 +6,13 @@
 //! This module contains all bindings to functions, constants, methods,
 //! constructors and destructors in `windows.h`. Types are not included in this
 //! module, but are available in the top-level `wdk_sys` module.
+//!
+//! The `GUID`s defined with `DEFINE_GUID` in the headers of common device
+//! interface and setup classes are available as `GUID` constants (ex.
+//! `GUID_DEVINTERFACE_USB_DEVICE`).
 
 pub use bindings::*;
+pub use guids::*;
 
 #[allow(missing_docs)]
 mod bindings {
@@ -21,3 +26,11 @@ mod bindings {
 
     include!(concat!(env!("OUT_DIR"), "/windows.rs"));
 }
+
+#[allow(
+    clippy::unreadable_literal,
+    reason = "GUID values are generated in the same format as in the WDK headers"
+)]
+mod guids {
+    include!(concat!(env!("OUT_DIR"), "/guids.rs"));
+}
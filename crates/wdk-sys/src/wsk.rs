@@ -0,0 +1,51 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Direct FFI bindings to WSK (Winsock Kernel) APIs from the Windows Driver
+//! Kit (WDK)
+//!
+//! This module contains all bindings to functions, constants, methods,
+//! constructors and destructors in the following headers: `wsk.h`. Types are
+//! not included in this module, but are available in the top-level `wdk_sys`
+//! module.
+//!
+//! Enabling the `wsk` feature also links `netio.lib`, which provides the
+//! `WskRegister` family of functions. A WSK client goes through the following
+//! lifecycle:
+//!
+//! 1. `WskRegister` registers the client with the WSK subsystem, using a
+//!    `WSK_CLIENT_NPI` that points to the client's `WSK_CLIENT_DISPATCH`. This
+//!    is typically done in `DriverEntry`, and must be called at
+//!    `PASSIVE_LEVEL`.
+//! 1. `WskCaptureProviderNPI` waits for the WSK subsystem to become available,
+//!    and returns the `WSK_PROVIDER_NPI` whose dispatch table is used to create
+//!    sockets. It must be called at `PASSIVE_LEVEL`, unless it is called with
+//!    `WSK_NO_WAIT`, in which case it can be called at up to `DISPATCH_LEVEL`.
+//! 1. Sockets are created and used through the function pointers in the
+//!    provider and socket dispatch tables. These functions complete
+//!    asynchronously through the IRP passed to them, and can generally be
+//!    called at up to `DISPATCH_LEVEL`.
+//! 1. Every socket must be closed, and `WskReleaseProviderNPI` must be called
+//!    once for every successful `WskCaptureProviderNPI`, before `WskDeregister`
+//!    is called. `WskDeregister` waits for outstanding calls to complete, so it
+//!    must be called at `PASSIVE_LEVEL` (ex. from the driver's unload routine).
+//!
+//! Full documentation is available in the [Winsock Kernel Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/network/introduction-to-winsock-kernel).
+
+#[allow(
+    missing_docs,
+    reason = "most items in the WDK headers have no inline documentation, so bindgen is unable to \
+              generate documentation for their bindings"
+)]
+mod bindings {
+    #[allow(
+        clippy::wildcard_imports,
+        reason = "the underlying c code relies on all type definitions being in scope, which \
+                  results in the bindgen generated code relying on the generated types being in \
+                  scope as well"
+    )]
+    use crate::types::*;
+
+    include!(concat!(env!("OUT_DIR"), "/wsk.rs"));
+}
+pub use bindings::*;
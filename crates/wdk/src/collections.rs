@@ -0,0 +1,126 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Collections suitable for use in drivers.
+//!
+//! With the `collections` feature enabled, this module re-exports
+//! fixed-capacity collections from [`arrayvec`] and [`heapless`]. These
+//! collections store their elements inline and never allocate, so they can be
+//! used at any IRQL, and in drivers without a global allocator.
+//!
+//! With the `nightly` and `alloc` features enabled in kernel-mode drivers,
+//! this module also provides [`PoolAllocator`], and the [`PoolVec`] and
+//! [`PoolBox`] aliases, to allocate from a specific pool with a pool tag of the
+//! driver's choosing, instead of from the global allocator.
+
+#[cfg(feature = "collections")]
+pub use arrayvec::{ArrayString, ArrayVec, CapacityError};
+#[cfg(feature = "collections")]
+pub use heapless::{Deque, FnvIndexMap, FnvIndexSet, HistoryBuffer, IndexMap, IndexSet, LinearMap};
+#[cfg(all(
+    feature = "alloc",
+    feature = "nightly",
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF")
+))]
+pub use pool::*;
+
+#[cfg(all(
+    feature = "alloc",
+    feature = "nightly",
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF")
+))]
+mod pool {
+    extern crate alloc;
+
+    use core::{
+        alloc::{AllocError, Allocator, Layout},
+        ptr::NonNull,
+    };
+
+    use wdk_sys::{
+        ntddk::{ExAllocatePool2, ExFreePool},
+        POOL_FLAGS,
+        POOL_FLAG_NON_PAGED,
+        SIZE_T,
+        ULONG,
+    };
+
+    /// Alignment guaranteed for every pool allocation
+    /// (`MEMORY_ALLOCATION_ALIGNMENT`)
+    #[cfg(target_pointer_width = "64")]
+    const MEMORY_ALLOCATION_ALIGNMENT: usize = 16;
+    #[cfg(target_pointer_width = "32")]
+    const MEMORY_ALLOCATION_ALIGNMENT: usize = 8;
+
+    /// A [`Vec`](alloc::vec::Vec) whose buffer is allocated from non-paged pool
+    /// with the pool tag `TAG`
+    pub type PoolVec<T, const TAG: ULONG> = alloc::vec::Vec<T, PoolAllocator<TAG>>;
+
+    /// A [`Box`](alloc::boxed::Box) whose value is allocated from non-paged
+    /// pool with the pool tag `TAG`
+    pub type PoolBox<T, const TAG: ULONG> = alloc::boxed::Box<T, PoolAllocator<TAG>>;
+
+    /// Converts a four character pool tag (ex. `b"Drv1"`) into the value
+    /// expected by [`PoolAllocator`], so that it is displayed in the same order
+    /// in tooling (ie. Windbg)
+    #[must_use]
+    pub const fn pool_tag(tag: &[u8; 4]) -> ULONG {
+        u32::from_ne_bytes(*tag)
+    }
+
+    /// [`Allocator`] that allocates from the pool selected by `FLAGS`, with the
+    /// pool tag `TAG`.
+    ///
+    /// Allocations requiring an alignment greater than
+    /// `MEMORY_ALLOCATION_ALIGNMENT` are not supported and fail with
+    /// [`AllocError`].
+    ///
+    /// # Safety
+    /// This allocator is only safe to use for allocations happening at `IRQL`
+    /// <= `DISPATCH_LEVEL` when allocating from non-paged pool, and at `IRQL`
+    /// <= `APC_LEVEL` when `FLAGS` selects paged pool
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct PoolAllocator<const TAG: ULONG, const FLAGS: POOL_FLAGS = POOL_FLAG_NON_PAGED>;
+
+    // SAFETY: This is safe because the pool allocator:
+    //         1. returns memory blocks that stay valid until they are passed to
+    //            `deallocate`, and that are not affected by copies of the allocator
+    //         2. only returns memory blocks that fit `layout`, since pool
+    //            allocations are always aligned to `MEMORY_ALLOCATION_ALIGNMENT`
+    //            and larger alignments are rejected
+    unsafe impl<const TAG: ULONG, const FLAGS: POOL_FLAGS> Allocator for PoolAllocator<TAG, FLAGS> {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            if layout.align() > MEMORY_ALLOCATION_ALIGNMENT {
+                return Err(AllocError);
+            }
+
+            if layout.size() == 0 {
+                // Zero-sized allocations don't need any memory, so a dangling, but
+                // correctly aligned, pointer is returned instead
+                let dangling =
+                    NonNull::new(core::ptr::without_provenance_mut::<u8>(layout.align()))
+                        .ok_or(AllocError)?;
+                return Ok(NonNull::slice_from_raw_parts(dangling, 0));
+            }
+
+            let ptr =
+                // SAFETY: `ExAllocatePool2` is safe to call from any `IRQL` supported by the pool selected by `FLAGS`, which the user of this allocator is responsible for
+                unsafe { ExAllocatePool2(FLAGS, layout.size() as SIZE_T, TAG) };
+            NonNull::new(ptr.cast::<u8>())
+                .map(|ptr| NonNull::slice_from_raw_parts(ptr, layout.size()))
+                .ok_or(AllocError)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            if layout.size() == 0 {
+                return;
+            }
+
+            // SAFETY: The caller guarantees that `ptr` was returned by `allocate` for a
+            // non-zero-sized `layout`, so it was allocated by `ExAllocatePool2`
+            unsafe {
+                ExFreePool(ptr.as_ptr().cast());
+            }
+        }
+    }
+}
@@ -0,0 +1,362 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Device interface registration and device interface change notifications
+//!
+//! Drivers expose device interfaces so that applications and other drivers
+//! can find and open their devices. KMDF and UMDF drivers create a device
+//! interface with [`create_device_interface`], typically in
+//! `EvtDriverDeviceAdd`, optionally with a reference string to tell apart
+//! several interfaces of the same class on one device:
+//!
+//! ```rust, ignore
+//! use wdk::device_interface::create_device_interface;
+//!
+//! create_device_interface(device, &GUID_DEVINTERFACE_MY_DEVICE, None)?;
+//! ```
+//!
+//! WDM and KMDF drivers can be notified when device interfaces of a class
+//! arrive or are removed (ex. to open a device exposed by another driver) with
+//! [`DeviceInterfaceNotification::register`]:
+//!
+//! ```rust, ignore
+//! use wdk::device_interface::{DeviceInterfaceChange, DeviceInterfaceNotification};
+//!
+//! static NOTIFICATION: DriverCell<DeviceInterfaceNotification<F>> = DriverCell::new();
+//!
+//! // SAFETY: `driver` is the driver object passed to `DriverEntry`
+//! let notification = unsafe {
+//!     DeviceInterfaceNotification::register(driver, &GUID_DEVINTERFACE_MY_DEVICE, |change| {
+//!         match change {
+//!             DeviceInterfaceChange::Arrival(symbolic_link_name) => { /* ... */ }
+//!             DeviceInterfaceChange::Removal(symbolic_link_name) => { /* ... */ }
+//!         }
+//!     })
+//! }?;
+//! ```
+//!
+//! Dropping a [`DeviceInterfaceNotification`] unregisters it, and waits for
+//! any of its callbacks that are in progress to return.
+
+#[cfg(all(
+    feature = "alloc",
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF")
+))]
+extern crate alloc;
+
+#[cfg(all(
+    feature = "alloc",
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF")
+))]
+use alloc::boxed::Box;
+#[cfg(any(driver_model__driver_type = "KMDF", driver_model__driver_type = "UMDF"))]
+use core::mem::size_of_val;
+use core::ptr;
+#[cfg(all(
+    feature = "alloc",
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF")
+))]
+use core::{mem::size_of, slice};
+
+#[cfg(any(driver_model__driver_type = "KMDF", driver_model__driver_type = "UMDF"))]
+use wdk_sys::{
+    call_unsafe_wdf_function_binding,
+    BOOLEAN,
+    STATUS_INVALID_PARAMETER,
+    UNICODE_STRING,
+    WDFDEVICE,
+};
+#[cfg(all(
+    feature = "alloc",
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF")
+))]
+use wdk_sys::{
+    ntddk::{
+        IoRegisterPlugPlayNotification,
+        IoUnregisterPlugPlayNotificationEx,
+        GUID_DEVICE_INTERFACE_ARRIVAL,
+        GUID_DEVICE_INTERFACE_REMOVAL,
+    },
+    _IO_NOTIFICATION_EVENT_CATEGORY::EventCategoryDeviceInterfaceChange,
+    DEVICE_INTERFACE_CHANGE_NOTIFICATION,
+    PDRIVER_OBJECT,
+    PNPNOTIFY_DEVICE_INTERFACE_INCLUDE_EXISTING_INTERFACES,
+    PVOID,
+    STATUS_SUCCESS,
+};
+use wdk_sys::{GUID, NTSTATUS};
+
+use crate::nt_success;
+
+/// Creates a device interface of the class `interface_class_guid` for
+/// `device`, and returns once it is registered. The interface is enabled by
+/// WDF when `device` starts.
+///
+/// `reference_string` distinguishes several interfaces of the same class on
+/// `device`, and is appended to the symbolic link name of the interface. It
+/// does not need to be null terminated.
+///
+/// # Errors
+///
+/// This function will return `STATUS_INVALID_PARAMETER` if `reference_string` is too long. Otherwise, it will return an error if WDF fails to create the device interface. Full error documentation is available in the [WdfDeviceCreateDeviceInterface Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfdevice/nf-wdfdevice-wdfdevicecreatedeviceinterface#return-value)
+#[cfg(any(driver_model__driver_type = "KMDF", driver_model__driver_type = "UMDF"))]
+pub fn create_device_interface(
+    device: WDFDEVICE,
+    interface_class_guid: &GUID,
+    reference_string: Option<&[u16]>,
+) -> Result<(), NTSTATUS> {
+    let reference_string = reference_string.map(unicode_string).transpose()?;
+
+    let nt_status;
+    // SAFETY: `device` is a handle to a WDF device object, and
+    // `interface_class_guid` and `reference_string` are valid for the duration of
+    // the call
+    unsafe {
+        nt_status = call_unsafe_wdf_function_binding!(
+            WdfDeviceCreateDeviceInterface,
+            device,
+            interface_class_guid,
+            reference_string.as_ref().map_or(ptr::null(), ptr::from_ref),
+        );
+    }
+    nt_success(nt_status).then_some(()).ok_or(nt_status)
+}
+
+/// Enables or disables the device interface of the class
+/// `interface_class_guid` and `reference_string` that was created for `device`
+/// with [`create_device_interface`].
+///
+/// # Errors
+///
+/// This function will return `STATUS_INVALID_PARAMETER` if `reference_string`
+/// is too long.
+#[cfg(any(driver_model__driver_type = "KMDF", driver_model__driver_type = "UMDF"))]
+pub fn set_device_interface_state(
+    device: WDFDEVICE,
+    interface_class_guid: &GUID,
+    reference_string: Option<&[u16]>,
+    enabled: bool,
+) -> Result<(), NTSTATUS> {
+    let reference_string = reference_string.map(unicode_string).transpose()?;
+
+    // SAFETY: `device` is a handle to a WDF device object, and
+    // `interface_class_guid` and `reference_string` are valid for the duration of
+    // the call
+    unsafe {
+        call_unsafe_wdf_function_binding!(
+            WdfDeviceSetDeviceInterfaceState,
+            device,
+            interface_class_guid,
+            reference_string.as_ref().map_or(ptr::null(), ptr::from_ref),
+            BOOLEAN::from(enabled),
+        );
+    }
+    Ok(())
+}
+
+/// Returns a [`UNICODE_STRING`] that borrows `string`, without its null
+/// terminator if it has one
+#[cfg(any(driver_model__driver_type = "KMDF", driver_model__driver_type = "UMDF"))]
+fn unicode_string(string: &[u16]) -> Result<UNICODE_STRING, NTSTATUS> {
+    let string = string.strip_suffix(&[0]).unwrap_or(string);
+    let length = u16::try_from(size_of_val(string)).map_err(|_| STATUS_INVALID_PARAMETER)?;
+    Ok(UNICODE_STRING {
+        Length: length,
+        MaximumLength: length,
+        Buffer: string.as_ptr().cast_mut(),
+    })
+}
+
+/// A change to a device interface of the class that a
+/// [`DeviceInterfaceNotification`] is registered for
+#[cfg(all(
+    feature = "alloc",
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF")
+))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceInterfaceChange<'a> {
+    /// A device interface was enabled. Contains the symbolic link name of the
+    /// interface (not null terminated), which can be used to open it (ex. with
+    /// `IoGetDeviceObjectPointer`).
+    Arrival(&'a [u16]),
+    /// A device interface was disabled. Contains the symbolic link name of the
+    /// interface (not null terminated).
+    Removal(&'a [u16]),
+}
+
+/// A registration for notifications of device interface arrivals and removals
+/// through `IoRegisterPlugPlayNotification`, which is unregistered when
+/// dropped
+#[cfg(all(
+    feature = "alloc",
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF")
+))]
+pub struct DeviceInterfaceNotification<F>
+where
+    F: Fn(DeviceInterfaceChange<'_>) + Send + Sync + 'static,
+{
+    notification_entry: PVOID,
+    callback: *mut F,
+}
+
+#[cfg(all(
+    feature = "alloc",
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF")
+))]
+// SAFETY: The notification entry is only used to unregister, which can be done
+// from any thread, and the callback is `Send` and `Sync`
+unsafe impl<F> Send for DeviceInterfaceNotification<F> where
+    F: Fn(DeviceInterfaceChange<'_>) + Send + Sync + 'static
+{
+}
+
+#[cfg(all(
+    feature = "alloc",
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF")
+))]
+// SAFETY: `DeviceInterfaceNotification` has no methods that take `&self`
+unsafe impl<F> Sync for DeviceInterfaceNotification<F> where
+    F: Fn(DeviceInterfaceChange<'_>) + Send + Sync + 'static
+{
+}
+
+#[cfg(all(
+    feature = "alloc",
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF")
+))]
+impl<F> DeviceInterfaceNotification<F>
+where
+    F: Fn(DeviceInterfaceChange<'_>) + Send + Sync + 'static,
+{
+    /// Registers `callback` to be called when a device interface of the class
+    /// `interface_class_guid` arrives or is removed.
+    ///
+    /// `callback` is first called with an [`DeviceInterfaceChange::Arrival`]
+    /// for each interface of the class that is already enabled, possibly
+    /// before this function returns. It is called at `PASSIVE_LEVEL`, and must
+    /// not block waiting for other Plug and Play events. This function must be
+    /// called at `PASSIVE_LEVEL`.
+    ///
+    /// # Safety
+    ///
+    /// `driver` must point to the valid driver object of the calling driver
+    /// (for KMDF drivers, the one returned by `WdfDriverWdmGetDriverObject`).
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the notification fails to be registered. Full error documentation is available in the [IoRegisterPlugPlayNotification Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdm/nf-wdm-ioregisterplugplaynotification#return-value)
+    pub unsafe fn register(
+        driver: PDRIVER_OBJECT,
+        interface_class_guid: &GUID,
+        callback: F,
+    ) -> Result<Self, NTSTATUS> {
+        let callback = Box::into_raw(Box::new(callback));
+        let mut notification_entry: PVOID = ptr::null_mut();
+
+        // SAFETY: The caller guarantees that `driver` is a valid driver object,
+        // `interface_class_guid` is only read for the duration of the call, and
+        // `callback` stays valid until the notification is unregistered
+        let nt_status = unsafe {
+            IoRegisterPlugPlayNotification(
+                EventCategoryDeviceInterfaceChange,
+                PNPNOTIFY_DEVICE_INTERFACE_INCLUDE_EXISTING_INTERFACES,
+                ptr::from_ref(interface_class_guid).cast_mut().cast(),
+                driver,
+                Some(device_interface_change_callback::<F>),
+                callback.cast(),
+                ptr::addr_of_mut!(notification_entry),
+            )
+        };
+        if !nt_success(nt_status) {
+            // SAFETY: `callback` was allocated by `Box::into_raw` above, and is not used
+            // by the failed registration
+            drop(unsafe { Box::from_raw(callback) });
+            return Err(nt_status);
+        }
+
+        Ok(Self {
+            notification_entry,
+            callback,
+        })
+    }
+}
+
+#[cfg(all(
+    feature = "alloc",
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF")
+))]
+impl<F> Drop for DeviceInterfaceNotification<F>
+where
+    F: Fn(DeviceInterfaceChange<'_>) + Send + Sync + 'static,
+{
+    fn drop(&mut self) {
+        // SAFETY: `notification_entry` was returned by a successful
+        // `IoRegisterPlugPlayNotification`, and is only unregistered here
+        unsafe {
+            IoUnregisterPlugPlayNotificationEx(self.notification_entry);
+        }
+
+        // SAFETY: `callback` was allocated by `Box::into_raw` in `register`, and
+        // `IoUnregisterPlugPlayNotificationEx` guarantees that it is no longer
+        // called once it returns
+        drop(unsafe { Box::from_raw(self.callback) });
+    }
+}
+
+/// `DRIVER_NOTIFICATION_CALLBACK_ROUTINE` that forwards device interface
+/// changes to the callback of a [`DeviceInterfaceNotification`]
+#[cfg(all(
+    feature = "alloc",
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF")
+))]
+unsafe extern "C" fn device_interface_change_callback<F>(
+    notification_structure: PVOID,
+    context: PVOID,
+) -> NTSTATUS
+where
+    F: Fn(DeviceInterfaceChange<'_>) + Send + Sync + 'static,
+{
+    // SAFETY: Notifications of `EventCategoryDeviceInterfaceChange` are described
+    // by a `DEVICE_INTERFACE_CHANGE_NOTIFICATION`
+    let notification =
+        unsafe { &*notification_structure.cast::<DEVICE_INTERFACE_CHANGE_NOTIFICATION>() };
+    // SAFETY: `SymbolicLinkName` points to a valid `UNICODE_STRING` for the
+    // duration of the callback
+    let symbolic_link_name = unsafe { &*notification.SymbolicLinkName };
+    let symbolic_link_name = if symbolic_link_name.Buffer.is_null() {
+        &[]
+    } else {
+        // SAFETY: `Buffer` holds `Length` bytes of UTF-16 for the duration of the
+        // callback
+        unsafe {
+            slice::from_raw_parts(
+                symbolic_link_name.Buffer,
+                usize::from(symbolic_link_name.Length) / size_of::<u16>(),
+            )
+        }
+    };
+
+    let change = if is_same_guid(&notification.Event, &GUID_DEVICE_INTERFACE_ARRIVAL) {
+        DeviceInterfaceChange::Arrival(symbolic_link_name)
+    } else if is_same_guid(&notification.Event, &GUID_DEVICE_INTERFACE_REMOVAL) {
+        DeviceInterfaceChange::Removal(symbolic_link_name)
+    } else {
+        return STATUS_SUCCESS;
+    };
+
+    // SAFETY: `context` is the callback allocated in `register`, which is only
+    // freed after the notification is unregistered
+    let callback = unsafe { &*context.cast::<F>() };
+    callback(change);
+    STATUS_SUCCESS
+}
+
+/// Returns whether `a` and `b` are the same GUID, since bindgen does not derive
+/// `PartialEq` for [`GUID`]
+#[cfg(all(
+    feature = "alloc",
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF")
+))]
+fn is_same_guid(a: &GUID, b: &GUID) -> bool {
+    a.Data1 == b.Data1 && a.Data2 == b.Data2 && a.Data3 == b.Data3 && a.Data4 == b.Data4
+}
@@ -0,0 +1,475 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Typed access to the device property store
+//!
+//! Device properties are identified by a [`DEVPROPKEY`], and hold a typed
+//! [`DevicePropertyValue`]. [`set_device_property`] and [`get_device_property`]
+//! wrap `WdfDeviceAssignProperty`/`WdfDeviceQueryPropertyEx` for KMDF and UMDF
+//! drivers, and `IoSetDevicePropertyData`/`IoGetDevicePropertyData` for WDM
+//! drivers, so that properties can be managed without filling in the raw
+//! property structures or an INF `AddProperty` section. KMDF and UMDF drivers
+//! that do not know the size of a property in advance can use
+//! [`query_device_property`] instead, which retrieves it into memory allocated
+//! by WDF.
+//!
+//! ```rust, ignore
+//! use wdk::device_property::{get_device_property, set_device_property, DevicePropertyValue};
+//!
+//! set_device_property(device, &MY_PROPERTY_KEY, DevicePropertyValue::UInt32(42), true)?;
+//!
+//! let mut buffer = [0; 8];
+//! if let DevicePropertyValue::UInt32(value) =
+//!     get_device_property(device, &MY_PROPERTY_KEY, &mut buffer)?
+//! {
+//!     // ...
+//! }
+//!
+//! let friendly_name = query_device_property(device, &DEVPKEY_Device_FriendlyName)?;
+//! if let DevicePropertyValue::String(name) = friendly_name.value()? {
+//!     // ...
+//! }
+//! ```
+
+#[cfg(any(driver_model__driver_type = "KMDF", driver_model__driver_type = "UMDF"))]
+use core::slice;
+use core::{
+    mem::{align_of, size_of_val},
+    ptr,
+};
+
+#[cfg(any(driver_model__driver_type = "KMDF", driver_model__driver_type = "UMDF"))]
+use wdk_sys::{
+    call_unsafe_wdf_function_binding,
+    _POOL_TYPE,
+    WDFDEVICE,
+    WDFMEMORY,
+    WDF_DEVICE_PROPERTY_DATA,
+    WDF_NO_OBJECT_ATTRIBUTES,
+};
+#[cfg(driver_model__driver_type = "WDM")]
+use wdk_sys::{
+    ntddk::{IoGetDevicePropertyData, IoSetDevicePropertyData},
+    PDEVICE_OBJECT,
+};
+use wdk_sys::{
+    DEVPROPKEY,
+    DEVPROPTYPE,
+    DEVPROP_TYPE_BINARY,
+    DEVPROP_TYPE_BOOLEAN,
+    DEVPROP_TYPE_STRING,
+    DEVPROP_TYPE_UINT32,
+    LCID,
+    NTSTATUS,
+    PVOID,
+    STATUS_BUFFER_TOO_SMALL,
+    STATUS_INVALID_PARAMETER,
+    STATUS_NOT_SUPPORTED,
+    ULONG,
+};
+
+use crate::nt_success;
+
+/// `DEVPROP_TRUE` in `devpropdef.h`
+const DEVPROP_TRUE: u8 = 0xFF;
+/// `DEVPROP_FALSE` in `devpropdef.h`
+const DEVPROP_FALSE: u8 = 0x00;
+/// `PLUGPLAY_PROPERTY_PERSISTENT` in `wdm.h`
+const PLUGPLAY_PROPERTY_PERSISTENT: ULONG = 0x0000_0001;
+/// `LOCALE_NEUTRAL` in `winnt.h`. Device properties are always set and
+/// retrieved for the neutral locale
+const LOCALE_NEUTRAL: LCID = 0;
+
+/// A typed value of a device property
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DevicePropertyValue<'a> {
+    /// A `DEVPROP_TYPE_BOOLEAN` value
+    Boolean(bool),
+    /// A `DEVPROP_TYPE_UINT32` value
+    UInt32(u32),
+    /// A `DEVPROP_TYPE_STRING` value. The UTF-16 string must be null
+    /// terminated.
+    String(&'a [u16]),
+    /// A `DEVPROP_TYPE_BINARY` value
+    Binary(&'a [u8]),
+}
+
+impl<'a> DevicePropertyValue<'a> {
+    /// Calls `f` with the `DEVPROPTYPE`, size and pointer to the data of this
+    /// value, in the representation expected by the property store
+    fn with_raw_data<R>(
+        self,
+        f: impl FnOnce(DEVPROPTYPE, ULONG, PVOID) -> R,
+    ) -> Result<R, NTSTATUS> {
+        let boolean_data;
+        let (property_type, data, size) = match self {
+            Self::Boolean(value) => {
+                boolean_data = if value { DEVPROP_TRUE } else { DEVPROP_FALSE };
+                (
+                    DEVPROP_TYPE_BOOLEAN,
+                    ptr::from_ref(&boolean_data).cast(),
+                    size_of_val(&boolean_data),
+                )
+            }
+            Self::UInt32(ref value) => (
+                DEVPROP_TYPE_UINT32,
+                ptr::from_ref(value).cast(),
+                size_of_val(value),
+            ),
+            Self::String(value) => {
+                if value.last() != Some(&0) {
+                    return Err(STATUS_INVALID_PARAMETER);
+                }
+                (
+                    DEVPROP_TYPE_STRING,
+                    value.as_ptr().cast(),
+                    size_of_val(value),
+                )
+            }
+            Self::Binary(value) => (
+                DEVPROP_TYPE_BINARY,
+                value.as_ptr().cast(),
+                size_of_val(value),
+            ),
+        };
+        let size = ULONG::try_from(size).map_err(|_| STATUS_INVALID_PARAMETER)?;
+
+        // The property store only reads from `data`, so casting away its constness is
+        // sound
+        Ok(f(property_type, size, data.cast_mut()))
+    }
+
+    /// Interprets `data` retrieved from the property store as a value of type
+    /// `property_type`. `data` must be aligned to 2 bytes.
+    fn from_raw_data(property_type: DEVPROPTYPE, data: &'a [u8]) -> Result<Self, NTSTATUS> {
+        match property_type {
+            DEVPROP_TYPE_BOOLEAN => match data {
+                [value] => Ok(Self::Boolean(*value != DEVPROP_FALSE)),
+                _ => Err(STATUS_NOT_SUPPORTED),
+            },
+            DEVPROP_TYPE_UINT32 => data
+                .try_into()
+                .map(|value| Self::UInt32(u32::from_ne_bytes(value)))
+                .map_err(|_| STATUS_NOT_SUPPORTED),
+            DEVPROP_TYPE_STRING => {
+                // SAFETY: Every bit pattern is a valid `u16`
+                let (prefix, string, suffix) = unsafe { data.align_to::<u16>() };
+                if prefix.is_empty() && suffix.is_empty() {
+                    Ok(Self::String(string))
+                } else {
+                    Err(STATUS_NOT_SUPPORTED)
+                }
+            }
+            DEVPROP_TYPE_BINARY => Ok(Self::Binary(data)),
+            _ => Err(STATUS_NOT_SUPPORTED),
+        }
+    }
+}
+
+/// Returns the flags used to set a property, depending on whether it should
+/// persist across restarts of the device
+const fn property_flags(persistent: bool) -> ULONG {
+    if persistent {
+        PLUGPLAY_PROPERTY_PERSISTENT
+    } else {
+        0
+    }
+}
+
+/// Returns the part of `buffer` that is aligned to 2 bytes, so that retrieved
+/// strings can be reinterpreted as UTF-16
+fn aligned_property_buffer(buffer: &mut [u8]) -> &mut [u8] {
+    let offset = buffer.as_ptr().align_offset(align_of::<u16>());
+    let len = buffer.len();
+    &mut buffer[offset.min(len)..]
+}
+
+/// Sets the device property identified by `property_key` of `device` to
+/// `value`. If `persistent` is `true`, the value persists across restarts of
+/// the device.
+///
+/// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+///
+/// # Errors
+///
+/// This function will return `STATUS_INVALID_PARAMETER` if a [`DevicePropertyValue::String`] is not null terminated, or if `value` is too large. Otherwise, it will return an error if WDF fails to assign the property. Full error documentation is available in the [WdfDeviceAssignProperty Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfdevice/nf-wdfdevice-wdfdeviceassignproperty#return-value)
+#[cfg(any(driver_model__driver_type = "KMDF", driver_model__driver_type = "UMDF"))]
+pub fn set_device_property(
+    device: WDFDEVICE,
+    property_key: &DEVPROPKEY,
+    value: DevicePropertyValue<'_>,
+    persistent: bool,
+) -> Result<(), NTSTATUS> {
+    let mut property_data = device_property_data(property_key, property_flags(persistent));
+
+    let nt_status = value.with_raw_data(|property_type, size, data| {
+        // SAFETY: `device` is a handle to a WDF device object, and `property_data` and
+        // `data` are valid for the duration of the call
+        unsafe {
+            call_unsafe_wdf_function_binding!(
+                WdfDeviceAssignProperty,
+                device,
+                &mut property_data,
+                property_type,
+                size,
+                data,
+            )
+        }
+    })?;
+    nt_success(nt_status).then_some(()).ok_or(nt_status)
+}
+
+/// Retrieves the device property identified by `property_key` of `device` into
+/// `buffer`, and returns its typed value.
+///
+/// The value borrows from `buffer`. Since strings must be aligned, up to 1 byte
+/// at the start of `buffer` may be left unused.
+///
+/// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+///
+/// # Errors
+///
+/// This function will return `STATUS_NOT_SUPPORTED` if the property has a type that is not supported by [`DevicePropertyValue`], and `STATUS_BUFFER_TOO_SMALL` if `buffer` is too small to hold the value. Otherwise, it will return an error if WDF fails to query the property. Full error documentation is available in the [WdfDeviceQueryPropertyEx Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfdevice/nf-wdfdevice-wdfdevicequerypropertyex#return-value)
+#[cfg(any(driver_model__driver_type = "KMDF", driver_model__driver_type = "UMDF"))]
+pub fn get_device_property<'a>(
+    device: WDFDEVICE,
+    property_key: &DEVPROPKEY,
+    buffer: &'a mut [u8],
+) -> Result<DevicePropertyValue<'a>, NTSTATUS> {
+    let buffer = aligned_property_buffer(buffer);
+    let buffer_length = ULONG::try_from(buffer.len()).unwrap_or(ULONG::MAX);
+
+    let mut property_data = device_property_data(property_key, 0);
+    let mut result_length: ULONG = 0;
+    let mut property_type: DEVPROPTYPE = 0;
+
+    let nt_status;
+    // SAFETY: `device` is a handle to a WDF device object, and WDF writes at most
+    // `buffer_length` bytes to `buffer`
+    unsafe {
+        nt_status = call_unsafe_wdf_function_binding!(
+            WdfDeviceQueryPropertyEx,
+            device,
+            &mut property_data,
+            buffer_length,
+            buffer.as_mut_ptr().cast(),
+            &mut result_length,
+            &mut property_type,
+        );
+    }
+
+    if !nt_success(nt_status) {
+        return Err(nt_status);
+    }
+    let data = buffer
+        .get(..result_length as usize)
+        .ok_or(STATUS_BUFFER_TOO_SMALL)?;
+    DevicePropertyValue::from_raw_data(property_type, data)
+}
+
+/// A device property retrieved by [`query_device_property`]. Its value is held
+/// in memory allocated by WDF, which is freed when the `DeviceProperty` is
+/// dropped.
+#[cfg(any(driver_model__driver_type = "KMDF", driver_model__driver_type = "UMDF"))]
+#[derive(Debug)]
+pub struct DeviceProperty {
+    memory: WDFMEMORY,
+    property_type: DEVPROPTYPE,
+}
+
+#[cfg(any(driver_model__driver_type = "KMDF", driver_model__driver_type = "UMDF"))]
+impl DeviceProperty {
+    /// Returns the `DEVPROPTYPE` of the property
+    #[must_use]
+    pub const fn property_type(&self) -> DEVPROPTYPE {
+        self.property_type
+    }
+
+    /// Returns the typed value of the property
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_NOT_SUPPORTED` if the property has a
+    /// type that is not supported by [`DevicePropertyValue`]
+    pub fn value(&self) -> Result<DevicePropertyValue<'_>, NTSTATUS> {
+        let mut size = 0;
+
+        let buffer;
+        // SAFETY: `memory` is a private member of `DeviceProperty`, originally created
+        // by WDF, and is only deleted when the `DeviceProperty` is dropped
+        unsafe {
+            buffer = call_unsafe_wdf_function_binding!(WdfMemoryGetBuffer, self.memory, &mut size);
+        }
+
+        if size == 0 {
+            return DevicePropertyValue::from_raw_data(self.property_type, &[]);
+        }
+        // SAFETY: The buffer of `memory` is valid for reads of `size` bytes until
+        // `memory` is deleted, which cannot happen while `self` is borrowed. Pool
+        // allocations are aligned to more than 2 bytes.
+        let data = unsafe { slice::from_raw_parts(buffer.cast::<u8>(), size) };
+        DevicePropertyValue::from_raw_data(self.property_type, data)
+    }
+}
+
+#[cfg(any(driver_model__driver_type = "KMDF", driver_model__driver_type = "UMDF"))]
+impl Drop for DeviceProperty {
+    fn drop(&mut self) {
+        // SAFETY: `memory` is a private member of `DeviceProperty`, originally created
+        // by WDF, and is not used after it is deleted
+        unsafe {
+            call_unsafe_wdf_function_binding!(WdfObjectDelete, self.memory.cast());
+        }
+    }
+}
+
+/// Retrieves the device property identified by `property_key` of `device` into
+/// memory allocated by WDF, so that properties of any size can be retrieved
+/// without guessing the size of a buffer.
+///
+/// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+///
+/// # Errors
+///
+/// This function will return an error if WDF fails to query the property, or to allocate memory for it. Full error documentation is available in the [WdfDeviceAllocAndQueryPropertyEx Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfdevice/nf-wdfdevice-wdfdeviceallocandquerypropertyex#return-value)
+#[cfg(any(driver_model__driver_type = "KMDF", driver_model__driver_type = "UMDF"))]
+pub fn query_device_property(
+    device: WDFDEVICE,
+    property_key: &DEVPROPKEY,
+) -> Result<DeviceProperty, NTSTATUS> {
+    let mut property_data = device_property_data(property_key, 0);
+    let mut memory: WDFMEMORY = ptr::null_mut();
+    let mut property_type: DEVPROPTYPE = 0;
+
+    let nt_status;
+    // SAFETY: `device` is a handle to a WDF device object, `property_data` is valid
+    // for the duration of the call, and the argument receiving
+    // `WDF_NO_OBJECT_ATTRIBUTES` is allowed to be null
+    unsafe {
+        nt_status = call_unsafe_wdf_function_binding!(
+            WdfDeviceAllocAndQueryPropertyEx,
+            device,
+            &mut property_data,
+            _POOL_TYPE::PagedPool,
+            WDF_NO_OBJECT_ATTRIBUTES,
+            &mut memory,
+            &mut property_type,
+        );
+    }
+
+    if !nt_success(nt_status) {
+        return Err(nt_status);
+    }
+    Ok(DeviceProperty {
+        memory,
+        property_type,
+    })
+}
+
+/// Creates the `WDF_DEVICE_PROPERTY_DATA` for the property identified by
+/// `property_key`. This is the equivalent of `WDF_DEVICE_PROPERTY_DATA_INIT`,
+/// with `Lcid` and `Flags` set.
+#[cfg(any(driver_model__driver_type = "KMDF", driver_model__driver_type = "UMDF"))]
+fn device_property_data(property_key: &DEVPROPKEY, flags: ULONG) -> WDF_DEVICE_PROPERTY_DATA {
+    // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+    #[allow(clippy::cast_possible_truncation)]
+    const WDF_DEVICE_PROPERTY_DATA_SIZE: ULONG =
+        core::mem::size_of::<WDF_DEVICE_PROPERTY_DATA>() as ULONG;
+
+    WDF_DEVICE_PROPERTY_DATA {
+        Size: WDF_DEVICE_PROPERTY_DATA_SIZE,
+        PropertyKey: property_key,
+        Lcid: LOCALE_NEUTRAL,
+        Flags: flags,
+    }
+}
+
+/// Sets the device property identified by `property_key` of the physical
+/// device object `pdo` to `value`. If `persistent` is `true`, the value
+/// persists across restarts of the device.
+///
+/// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+///
+/// # Safety
+///
+/// `pdo` must point to a valid physical device object.
+///
+/// # Errors
+///
+/// This function will return `STATUS_INVALID_PARAMETER` if a [`DevicePropertyValue::String`] is not null terminated, or if `value` is too large. Otherwise, it will return an error if the property fails to be set. Full error documentation is available in the [IoSetDevicePropertyData Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdm/nf-wdm-iosetdevicepropertydata#return-value)
+#[cfg(driver_model__driver_type = "WDM")]
+pub unsafe fn set_device_property(
+    pdo: PDEVICE_OBJECT,
+    property_key: &DEVPROPKEY,
+    value: DevicePropertyValue<'_>,
+    persistent: bool,
+) -> Result<(), NTSTATUS> {
+    let nt_status = value.with_raw_data(|property_type, size, data| {
+        // SAFETY: The caller guarantees that `pdo` is a valid physical device object,
+        // and `property_key` and `data` are valid for the duration of the call
+        unsafe {
+            IoSetDevicePropertyData(
+                pdo,
+                property_key,
+                LOCALE_NEUTRAL,
+                property_flags(persistent),
+                property_type,
+                size,
+                data,
+            )
+        }
+    })?;
+    nt_success(nt_status).then_some(()).ok_or(nt_status)
+}
+
+/// Retrieves the device property identified by `property_key` of the physical
+/// device object `pdo` into `buffer`, and returns its typed value.
+///
+/// The value borrows from `buffer`. Since strings must be aligned, up to 1 byte
+/// at the start of `buffer` may be left unused.
+///
+/// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+///
+/// # Safety
+///
+/// `pdo` must point to a valid physical device object.
+///
+/// # Errors
+///
+/// This function will return `STATUS_NOT_SUPPORTED` if the property has a type that is not supported by [`DevicePropertyValue`], and `STATUS_BUFFER_TOO_SMALL` if `buffer` is too small to hold the value. Otherwise, it will return an error if the property fails to be retrieved. Full error documentation is available in the [IoGetDevicePropertyData Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdm/nf-wdm-iogetdevicepropertydata#return-value)
+#[cfg(driver_model__driver_type = "WDM")]
+pub unsafe fn get_device_property<'a>(
+    pdo: PDEVICE_OBJECT,
+    property_key: &DEVPROPKEY,
+    buffer: &'a mut [u8],
+) -> Result<DevicePropertyValue<'a>, NTSTATUS> {
+    let buffer = aligned_property_buffer(buffer);
+    let buffer_length = ULONG::try_from(buffer.len()).unwrap_or(ULONG::MAX);
+
+    let mut required_size: ULONG = 0;
+    let mut property_type: DEVPROPTYPE = 0;
+
+    // SAFETY: The caller guarantees that `pdo` is a valid physical device object,
+    // and `IoGetDevicePropertyData` writes at most `buffer_length` bytes to
+    // `buffer`
+    let nt_status = unsafe {
+        IoGetDevicePropertyData(
+            pdo,
+            property_key,
+            LOCALE_NEUTRAL,
+            0,
+            buffer_length,
+            buffer.as_mut_ptr().cast(),
+            &mut required_size,
+            &mut property_type,
+        )
+    };
+
+    if !nt_success(nt_status) {
+        return Err(nt_status);
+    }
+    let data = buffer
+        .get(..required_size as usize)
+        .ok_or(STATUS_BUFFER_TOO_SMALL)?;
+    DevicePropertyValue::from_raw_data(property_type, data)
+}
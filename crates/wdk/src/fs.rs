@@ -0,0 +1,378 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Synchronous file I/O from kernel mode
+//!
+//! [`KernelFile`] wraps a kernel handle to a file opened with
+//! [`ZwCreateFile`], so that drivers can read configuration blobs or write
+//! logs without calling the `Zw*` file APIs directly:
+//!
+//! ```rust, ignore
+//! use wdk::fs::{Access, KernelFile, Share};
+//!
+//! let path: Vec<u16> = r"\SystemRoot\my_driver.cfg".encode_utf16().collect();
+//! let file = KernelFile::open(&path, Access::Read, Share::READ)?;
+//!
+//! let mut config = [0; 512];
+//! let bytes_read = file.read_at(0, &mut config)?;
+//! ```
+//!
+//! Paths are object manager paths (ex. `\SystemRoot\my_driver.cfg` or
+//! `\??\C:\my_driver.log`), not Win32 paths. All I/O is synchronous, so every
+//! operation, including dropping the [`KernelFile`], must happen at `IRQL` =
+//! `PASSIVE_LEVEL`. This is asserted at runtime in debug builds.
+
+use core::{
+    mem::{size_of, size_of_val},
+    ops::BitOr,
+    ptr,
+};
+
+use wdk_sys::{
+    ntddk::{ZwClose, ZwCreateFile, ZwQueryInformationFile, ZwReadFile, ZwWriteFile},
+    _FILE_INFORMATION_CLASS::FileStandardInformation,
+    ACCESS_MASK,
+    FILE_ATTRIBUTE_NORMAL,
+    FILE_NON_DIRECTORY_FILE,
+    FILE_OPEN,
+    FILE_OVERWRITE_IF,
+    FILE_SHARE_DELETE,
+    FILE_SHARE_READ,
+    FILE_SHARE_WRITE,
+    FILE_STANDARD_INFORMATION,
+    FILE_SYNCHRONOUS_IO_NONALERT,
+    GENERIC_READ,
+    GENERIC_WRITE,
+    HANDLE,
+    IO_STATUS_BLOCK,
+    LARGE_INTEGER,
+    NTSTATUS,
+    OBJECT_ATTRIBUTES,
+    OBJ_CASE_INSENSITIVE,
+    OBJ_KERNEL_HANDLE,
+    PASSIVE_LEVEL,
+    STATUS_END_OF_FILE,
+    STATUS_INVALID_PARAMETER,
+    SYNCHRONIZE,
+    ULONG,
+    ULONG_PTR,
+    UNICODE_STRING,
+};
+
+use crate::{nt_success, time::current_irql};
+
+/// Access that a [`KernelFile`] is opened with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// The file can only be read
+    Read,
+    /// The file can only be written
+    Write,
+    /// The file can be both read and written
+    ReadWrite,
+}
+
+impl Access {
+    const fn access_mask(self) -> ACCESS_MASK {
+        // Synchronous I/O requires the handle to be opened with `SYNCHRONIZE` access
+        SYNCHRONIZE
+            | match self {
+                Self::Read => GENERIC_READ,
+                Self::Write => GENERIC_WRITE,
+                Self::ReadWrite => GENERIC_READ | GENERIC_WRITE,
+            }
+    }
+}
+
+/// Access that other handles to a file are allowed to have while a
+/// [`KernelFile`] is open. Values can be combined with `|`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Share(ULONG);
+
+impl Share {
+    /// Other handles to the file can be opened with delete access
+    pub const DELETE: Self = Self(FILE_SHARE_DELETE);
+    /// Other handles to the file cannot be opened
+    pub const NONE: Self = Self(0);
+    /// Other handles to the file can be opened with read access
+    pub const READ: Self = Self(FILE_SHARE_READ);
+    /// Other handles to the file can be opened with write access
+    pub const WRITE: Self = Self(FILE_SHARE_WRITE);
+}
+
+impl BitOr for Share {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// File opened from kernel mode with [`KernelFile::open`] or
+/// [`KernelFile::create`]
+///
+/// The file is opened with a kernel handle, so it cannot be accessed by the
+/// user-mode process that is current when it is opened. Dropping the
+/// [`KernelFile`] closes its handle.
+#[derive(Debug)]
+pub struct KernelFile {
+    handle: HANDLE,
+}
+
+// SAFETY: Kernel handles can be used from any thread
+unsafe impl Send for KernelFile {}
+
+// SAFETY: The `Zw*` file APIs can be called concurrently on a handle opened for
+// synchronous I/O, since the I/O manager serializes the operations on it
+unsafe impl Sync for KernelFile {}
+
+impl KernelFile {
+    /// Opens the existing file at `path`, a UTF-16 object manager path with or
+    /// without a null terminator.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file does not exist or cannot be opened with `access` and `share`. The error variant will contain a [`NTSTATUS`] of the failure. Full error documentation is available in the [ZwCreateFile Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdm/nf-wdm-zwcreatefile#return-value)
+    pub fn open(path: &[u16], access: Access, share: Share) -> Result<Self, NTSTATUS> {
+        Self::open_with_disposition(path, access, share, FILE_OPEN)
+    }
+
+    /// Creates the file at `path`, a UTF-16 object manager path with or
+    /// without a null terminator. If the file already exists, it is truncated.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file cannot be created or opened with `access` and `share`. The error variant will contain a [`NTSTATUS`] of the failure. Full error documentation is available in the [ZwCreateFile Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdm/nf-wdm-zwcreatefile#return-value)
+    pub fn create(path: &[u16], access: Access, share: Share) -> Result<Self, NTSTATUS> {
+        Self::open_with_disposition(path, access, share, FILE_OVERWRITE_IF)
+    }
+
+    fn open_with_disposition(
+        path: &[u16],
+        access: Access,
+        share: Share,
+        create_disposition: ULONG,
+    ) -> Result<Self, NTSTATUS> {
+        // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+        #[allow(clippy::cast_possible_truncation)]
+        const OBJECT_ATTRIBUTES_SIZE: ULONG = size_of::<OBJECT_ATTRIBUTES>() as ULONG;
+
+        debug_assert_passive_level();
+
+        let mut object_name = unicode_string(path)?;
+        // Equivalent of `InitializeObjectAttributes`, which is a macro that bindgen
+        // cannot generate
+        let mut object_attributes = OBJECT_ATTRIBUTES {
+            Length: OBJECT_ATTRIBUTES_SIZE,
+            RootDirectory: ptr::null_mut(),
+            ObjectName: ptr::addr_of_mut!(object_name),
+            Attributes: OBJ_KERNEL_HANDLE | OBJ_CASE_INSENSITIVE,
+            SecurityDescriptor: ptr::null_mut(),
+            SecurityQualityOfService: ptr::null_mut(),
+        };
+        let mut io_status_block = IO_STATUS_BLOCK::default();
+        let mut handle: HANDLE = ptr::null_mut();
+
+        // SAFETY: `object_attributes` points to `object_name`, which borrows `path`,
+        // and both outlive the call
+        let nt_status = unsafe {
+            ZwCreateFile(
+                ptr::addr_of_mut!(handle),
+                access.access_mask(),
+                ptr::addr_of_mut!(object_attributes),
+                ptr::addr_of_mut!(io_status_block),
+                ptr::null_mut(),
+                FILE_ATTRIBUTE_NORMAL,
+                share.0,
+                create_disposition,
+                FILE_NON_DIRECTORY_FILE | FILE_SYNCHRONOUS_IO_NONALERT,
+                ptr::null_mut(),
+                0,
+            )
+        };
+        if !nt_success(nt_status) {
+            return Err(nt_status);
+        }
+
+        Ok(Self { handle })
+    }
+
+    /// Reads from the file at byte `offset` into `buffer`, returning the number
+    /// of bytes read. Reading at or past the end of the file returns `Ok(0)`.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file cannot be read. The error variant will contain a [`NTSTATUS`] of the failure. Full error documentation is available in the [ZwReadFile Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdm/nf-wdm-zwreadfile#return-value)
+    pub fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize, NTSTATUS> {
+        debug_assert_passive_level();
+
+        let length = ULONG::try_from(buffer.len()).map_err(|_| STATUS_INVALID_PARAMETER)?;
+        let mut byte_offset = large_integer(offset)?;
+        let mut io_status_block = IO_STATUS_BLOCK::default();
+
+        // SAFETY: `buffer` is valid for writes of `length` bytes, and the read
+        // completes before the call returns since the handle was opened for
+        // synchronous I/O
+        let nt_status = unsafe {
+            ZwReadFile(
+                self.handle,
+                ptr::null_mut(),
+                None,
+                ptr::null_mut(),
+                ptr::addr_of_mut!(io_status_block),
+                buffer.as_mut_ptr().cast(),
+                length,
+                ptr::addr_of_mut!(byte_offset),
+                ptr::null_mut(),
+            )
+        };
+        if nt_status == STATUS_END_OF_FILE {
+            return Ok(0);
+        }
+        if !nt_success(nt_status) {
+            return Err(nt_status);
+        }
+
+        Ok(bytes_transferred(io_status_block.Information))
+    }
+
+    /// Writes `buffer` to the file at byte `offset`, returning the number of
+    /// bytes written. The file is extended if `offset` is past its end.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file cannot be written. The error variant will contain a [`NTSTATUS`] of the failure. Full error documentation is available in the [ZwWriteFile Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdm/nf-wdm-zwwritefile#return-value)
+    pub fn write_at(&self, offset: u64, buffer: &[u8]) -> Result<usize, NTSTATUS> {
+        debug_assert_passive_level();
+
+        let length = ULONG::try_from(buffer.len()).map_err(|_| STATUS_INVALID_PARAMETER)?;
+        let mut byte_offset = large_integer(offset)?;
+        let mut io_status_block = IO_STATUS_BLOCK::default();
+
+        // SAFETY: `buffer` is valid for reads of `length` bytes, and `ZwWriteFile` does
+        // not write to it despite its parameter not being `const`. The write completes
+        // before the call returns since the handle was opened for synchronous I/O
+        let nt_status = unsafe {
+            ZwWriteFile(
+                self.handle,
+                ptr::null_mut(),
+                None,
+                ptr::null_mut(),
+                ptr::addr_of_mut!(io_status_block),
+                buffer.as_ptr().cast_mut().cast(),
+                length,
+                ptr::addr_of_mut!(byte_offset),
+                ptr::null_mut(),
+            )
+        };
+        if !nt_success(nt_status) {
+            return Err(nt_status);
+        }
+
+        Ok(bytes_transferred(io_status_block.Information))
+    }
+
+    /// Returns the [`FILE_STANDARD_INFORMATION`] of the file (ex. its size and
+    /// whether it is pending deletion)
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the information cannot be queried. The error variant will contain a [`NTSTATUS`] of the failure. Full error documentation is available in the [ZwQueryInformationFile Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdm/nf-wdm-zwqueryinformationfile#return-value)
+    pub fn standard_information(&self) -> Result<FILE_STANDARD_INFORMATION, NTSTATUS> {
+        // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+        #[allow(clippy::cast_possible_truncation)]
+        const FILE_STANDARD_INFORMATION_SIZE: ULONG =
+            size_of::<FILE_STANDARD_INFORMATION>() as ULONG;
+
+        debug_assert_passive_level();
+
+        let mut file_information = FILE_STANDARD_INFORMATION::default();
+        let mut io_status_block = IO_STATUS_BLOCK::default();
+
+        // SAFETY: `file_information` is a valid location for the information of the
+        // requested class
+        let nt_status = unsafe {
+            ZwQueryInformationFile(
+                self.handle,
+                ptr::addr_of_mut!(io_status_block),
+                ptr::addr_of_mut!(file_information).cast(),
+                FILE_STANDARD_INFORMATION_SIZE,
+                FileStandardInformation,
+            )
+        };
+        if !nt_success(nt_status) {
+            return Err(nt_status);
+        }
+
+        Ok(file_information)
+    }
+
+    /// Returns the size of the file in bytes
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the size of the file cannot be
+    /// queried. See [`KernelFile::standard_information`].
+    pub fn size(&self) -> Result<u64, NTSTATUS> {
+        let file_information = self.standard_information()?;
+        // SAFETY: `QuadPart` is always a valid interpretation of a `LARGE_INTEGER`
+        let end_of_file = unsafe { file_information.EndOfFile.QuadPart };
+        u64::try_from(end_of_file).map_err(|_| STATUS_INVALID_PARAMETER)
+    }
+}
+
+impl Drop for KernelFile {
+    fn drop(&mut self) {
+        debug_assert_passive_level();
+
+        // SAFETY: `handle` was opened in `open_with_disposition`, and is not used after
+        // it is closed
+        let nt_status = unsafe { ZwClose(self.handle) };
+        debug_assert!(nt_success(nt_status));
+    }
+}
+
+/// Returns a [`UNICODE_STRING`] that borrows `string`, without its null
+/// terminator if it has one
+fn unicode_string(string: &[u16]) -> Result<UNICODE_STRING, NTSTATUS> {
+    let string = string.strip_suffix(&[0]).unwrap_or(string);
+    let length = u16::try_from(size_of_val(string)).map_err(|_| STATUS_INVALID_PARAMETER)?;
+    Ok(UNICODE_STRING {
+        Length: length,
+        MaximumLength: length,
+        Buffer: string.as_ptr().cast_mut(),
+    })
+}
+
+/// Converts the number of bytes transferred by an operation into a `usize`
+const fn bytes_transferred(bytes: ULONG_PTR) -> usize {
+    // `ULONG_PTR` is always the size of a pointer
+    #[allow(clippy::cast_possible_truncation)]
+    let bytes = bytes as usize;
+    bytes
+}
+
+fn large_integer(value: u64) -> Result<LARGE_INTEGER, NTSTATUS> {
+    Ok(LARGE_INTEGER {
+        QuadPart: i64::try_from(value).map_err(|_| STATUS_INVALID_PARAMETER)?,
+    })
+}
+
+fn debug_assert_passive_level() {
+    debug_assert!(
+        current_irql() == PASSIVE_LEVEL,
+        "KernelFile should only be used at IRQL = PASSIVE_LEVEL"
+    );
+}
@@ -6,15 +6,30 @@
 //! safe, idiomatic rust interface to the WDK.
 
 #![no_std]
+#![cfg_attr(feature = "nightly", feature(allocator_api))]
 
 #[cfg(any(
-    all(
-        feature = "alloc",
-        any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF")
-    ),
-    driver_model__driver_type = "UMDF",
+    driver_model__driver_type = "WDM",
+    driver_model__driver_type = "KMDF",
+    driver_model__driver_type = "UMDF"
 ))]
-pub use print::_print;
+pub use print::{
+    _dbg_print,
+    _print,
+    _print_ex,
+    Level,
+    DBG_PRINT_BUFFER_SIZE,
+    DEFAULT_COMPONENT_ID,
+};
+// Used by `driver_entry!` so that its expansion does not require a direct dependency on
+// `wdk-sys`
+#[cfg(any(driver_model__driver_type = "KMDF", driver_model__driver_type = "UMDF"))]
+#[doc(hidden)]
+pub use wdk_sys as __wdk_sys;
+#[cfg(any(driver_model__driver_type = "KMDF", driver_model__driver_type = "UMDF"))]
+pub use wdk_sys::__proc_macros::callback;
+#[cfg(any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"))]
+pub use wdk_sys::__proc_macros::{max_stack, trace_logging_write};
 #[cfg(any(
     driver_model__driver_type = "WDM",
     driver_model__driver_type = "KMDF",
@@ -25,16 +40,73 @@
 pub use wdk_sys::PAGED_CODE as paged_code;
 
 #[cfg(any(
-    all(
-        feature = "alloc",
-        any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF")
-    ),
-    driver_model__driver_type = "UMDF",
+    driver_model__driver_type = "WDM",
+    driver_model__driver_type = "KMDF",
+    driver_model__driver_type = "UMDF"
 ))]
 mod print;
 
+#[cfg(any(
+    driver_model__driver_type = "WDM",
+    driver_model__driver_type = "KMDF",
+    driver_model__driver_type = "UMDF"
+))]
+pub mod collections;
+#[cfg(any(
+    driver_model__driver_type = "KMDF",
+    driver_model__driver_type = "UMDF",
+    all(feature = "alloc", driver_model__driver_type = "WDM")
+))]
+pub mod device_interface;
+#[cfg(any(
+    driver_model__driver_type = "WDM",
+    driver_model__driver_type = "KMDF",
+    driver_model__driver_type = "UMDF"
+))]
+pub mod device_property;
+#[cfg(any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"))]
+pub mod fs;
+#[cfg(any(
+    driver_model__driver_type = "WDM",
+    driver_model__driver_type = "KMDF",
+    driver_model__driver_type = "UMDF"
+))]
+pub mod log;
+#[cfg(any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"))]
+pub mod mdl;
+#[cfg(all(
+    feature = "wsk",
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF")
+))]
+pub mod net;
+#[cfg(driver_model__driver_type = "KMDF")]
+pub mod pci;
+#[cfg(any(driver_model__driver_type = "KMDF", driver_model__driver_type = "UMDF"))]
+pub mod resources;
+#[cfg(all(feature = "spb", driver_model__driver_type = "KMDF"))]
+pub mod spb;
+#[cfg(any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"))]
+pub mod stack;
+#[cfg(driver_model__driver_type = "UMDF")]
+pub mod swdevice;
+#[cfg(any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"))]
+pub mod sync;
+#[cfg(any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"))]
+pub mod time;
+#[cfg(any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"))]
+pub mod trace_logging;
+#[cfg(all(
+    feature = "usb",
+    any(driver_model__driver_type = "KMDF", driver_model__driver_type = "UMDF")
+))]
+pub mod usb;
 #[cfg(any(driver_model__driver_type = "KMDF", driver_model__driver_type = "UMDF"))]
 pub mod wdf;
+#[cfg(all(
+    feature = "wfp",
+    any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF")
+))]
+pub mod wfp;
 
 /// Trigger a breakpoint in debugger via architecture-specific inline assembly.
 ///
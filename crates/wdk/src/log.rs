@@ -0,0 +1,60 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Leveled logging to the kernel debugger
+//!
+//! [`error!`], [`warn!`] and [`info!`] print a line via
+//! [`println_ex!`](crate::println_ex) with the corresponding [`Level`], and
+//! the [`DEFAULT_COMPONENT_ID`]. Which of them are printed is controlled with
+//! the kernel debugger's filter mask of the component ID (ex. `ed
+//! nt!Kd_IHVDRIVER_Mask 0xF` prints all of them), instead of always printing to
+//! `DPFLTR_DEFAULT_ID` like [`println!`](crate::println).
+//!
+//! ```rust, ignore
+//! wdk::log::error!("failed to start device: {nt_status:#010X}");
+//! wdk::log::info!("device started");
+//! ```
+//!
+//! [`Level`]: crate::Level
+//! [`DEFAULT_COMPONENT_ID`]: crate::DEFAULT_COMPONENT_ID
+
+pub use crate::{__log_error as error, __log_info as info, __log_warn as warn};
+
+/// print an error line to the kernel debugger. See [`crate::log`].
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_error {
+    () => {
+      ($crate::println_ex!($crate::Level::Error))
+    };
+
+    ($($arg:tt)+) => {
+      ($crate::println_ex!($crate::Level::Error, $($arg)+))
+    };
+}
+
+/// print a warning line to the kernel debugger. See [`crate::log`].
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_warn {
+    () => {
+      ($crate::println_ex!($crate::Level::Warning))
+    };
+
+    ($($arg:tt)+) => {
+      ($crate::println_ex!($crate::Level::Warning, $($arg)+))
+    };
+}
+
+/// print an informational line to the kernel debugger. See [`crate::log`].
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_info {
+    () => {
+      ($crate::println_ex!($crate::Level::Info))
+    };
+
+    ($($arg:tt)+) => {
+      ($crate::println_ex!($crate::Level::Info, $($arg)+))
+    };
+}
@@ -0,0 +1,406 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Memory descriptor lists (MDLs) and memory mapping
+//!
+//! An [`Mdl`] describes the physical pages of a virtually contiguous buffer.
+//! The pages must be locked with [`Mdl::probe_and_lock`] before they can be
+//! accessed through the MDL, which returns a [`LockedMdl`]. A [`LockedMdl`]
+//! can then be mapped into system space, either with [`LockedMdl::system_va`]
+//! or with [`LockedMdl::map_locked_pages`]:
+//!
+//! ```rust, ignore
+//! use wdk::mdl::{AccessMode, CacheType, LockOperation, Mdl};
+//!
+//! // SAFETY: `buffer` is a valid, resident buffer of `length` bytes that outlives the MDL
+//! let mdl = unsafe { Mdl::for_buffer(buffer, length)? };
+//! // SAFETY: `buffer` can be written, so probing it cannot fail
+//! let mut locked_mdl = unsafe { mdl.probe_and_lock(AccessMode::Kernel, LockOperation::Write) };
+//! let mapping = locked_mdl.map_locked_pages(CacheType::NonCached)?;
+//! ```
+//!
+//! Every step is undone when the returned value is dropped: mappings are
+//! unmapped, pages are unlocked, and the MDL is freed.
+//!
+//! Drivers that use direct I/O (`METHOD_IN_DIRECT` and `METHOD_OUT_DIRECT`
+//! IOCTLs) do not need to build MDLs themselves, since the I/O manager already
+//! locks the buffer of the request. In KMDF drivers,
+//! [`Request::direct_buffer`](crate::wdf::Request::direct_buffer) maps it into
+//! system space.
+
+use core::{ffi::c_void, ptr, ptr::NonNull};
+
+use wdk_sys::{
+    ntddk::{
+        IoAllocateMdl,
+        IoFreeMdl,
+        MmMapLockedPagesSpecifyCache,
+        MmProbeAndLockPages,
+        MmUnlockPages,
+        MmUnmapLockedPages,
+    },
+    MdlMappingNoExecute,
+    _LOCK_OPERATION::{IoModifyAccess, IoReadAccess, IoWriteAccess},
+    _MEMORY_CACHING_TYPE::{MmCached, MmNonCached, MmWriteCombined},
+    _MM_PAGE_PRIORITY::NormalPagePriority,
+    _MODE::UserMode,
+    CSHORT,
+    KPROCESSOR_MODE,
+    LOCK_OPERATION,
+    MDL_MAPPED_TO_SYSTEM_VA,
+    MDL_SOURCE_IS_NONPAGED_POOL,
+    MEMORY_CACHING_TYPE,
+    NTSTATUS,
+    PMDL,
+    STATUS_INSUFFICIENT_RESOURCES,
+    STATUS_INVALID_DEVICE_STATE,
+    STATUS_INVALID_PARAMETER,
+    ULONG,
+};
+
+use crate::time::KERNEL_MODE;
+
+// clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+#[allow(clippy::cast_possible_truncation)]
+const USER_MODE: KPROCESSOR_MODE = UserMode as KPROCESSOR_MODE;
+
+/// Flags of an MDL whose pages are already mapped into system space
+// clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+const MAPPED_TO_SYSTEM_VA_FLAGS: CSHORT =
+    (MDL_MAPPED_TO_SYSTEM_VA | MDL_SOURCE_IS_NONPAGED_POOL) as CSHORT;
+
+/// Priority of the system space mappings of MDLs. Mapped pages are never
+/// executable.
+// `NormalPagePriority` is a small positive enum constant, so it has no sign to lose
+#[allow(clippy::cast_sign_loss)]
+const MAPPING_PRIORITY: ULONG = NormalPagePriority as ULONG | MdlMappingNoExecute;
+
+/// Processor mode that the buffer of an [`Mdl`] is accessed from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessMode {
+    /// The buffer is a kernel-mode buffer
+    Kernel,
+    /// The buffer is a user-mode buffer, which must be valid for the current
+    /// process
+    User,
+}
+
+impl AccessMode {
+    const fn processor_mode(self) -> KPROCESSOR_MODE {
+        match self {
+            Self::Kernel => KERNEL_MODE,
+            Self::User => USER_MODE,
+        }
+    }
+}
+
+/// Access that the pages of an [`Mdl`] are locked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockOperation {
+    /// The pages are only read
+    Read,
+    /// The pages are only written
+    Write,
+    /// The pages are both read and written
+    Modify,
+}
+
+impl LockOperation {
+    const fn lock_operation(self) -> LOCK_OPERATION {
+        match self {
+            Self::Read => IoReadAccess,
+            Self::Write => IoWriteAccess,
+            Self::Modify => IoModifyAccess,
+        }
+    }
+}
+
+/// Caching of the mapping created by [`LockedMdl::map_locked_pages`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheType {
+    /// The pages are not cached (ex. device registers)
+    NonCached,
+    /// The pages are cached normally
+    Cached,
+    /// The pages are not cached, but writes to them are combined (ex. frame
+    /// buffers)
+    WriteCombined,
+}
+
+impl CacheType {
+    const fn memory_caching_type(self) -> MEMORY_CACHING_TYPE {
+        match self {
+            Self::NonCached => MmNonCached,
+            Self::Cached => MmCached,
+            Self::WriteCombined => MmWriteCombined,
+        }
+    }
+}
+
+/// MDL allocated with [`Mdl::for_buffer`], whose pages are not locked yet
+///
+/// Dropping the [`Mdl`] frees it.
+#[derive(Debug)]
+pub struct Mdl {
+    mdl: NonNull<wdk_sys::MDL>,
+}
+
+// SAFETY: MDLs can be used and freed from any thread
+unsafe impl Send for Mdl {}
+
+impl Mdl {
+    /// Allocates an MDL describing the `length` bytes at `buffer`
+    ///
+    /// This must be called at `IRQL` <= `DISPATCH_LEVEL`.
+    ///
+    /// # Safety
+    ///
+    /// `buffer` must point to a buffer of at least `length` bytes, which must
+    /// remain allocated until the returned [`Mdl`] is dropped.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `length` is zero or too large to
+    /// be described by an MDL (`STATUS_INVALID_PARAMETER`), or if the MDL
+    /// cannot be allocated (`STATUS_INSUFFICIENT_RESOURCES`)
+    pub unsafe fn for_buffer(buffer: *mut c_void, length: usize) -> Result<Self, NTSTATUS> {
+        let length = match ULONG::try_from(length) {
+            Ok(length) if length > 0 => length,
+            _ => return Err(STATUS_INVALID_PARAMETER),
+        };
+
+        // SAFETY: The caller guarantees that `buffer` is valid for `length` bytes for
+        // the lifetime of the MDL. The MDL is not associated with an IRP.
+        let mdl = unsafe { IoAllocateMdl(buffer, length, 0, 0, ptr::null_mut()) };
+        NonNull::new(mdl)
+            .map(|mdl| Self { mdl })
+            .ok_or(STATUS_INSUFFICIENT_RESOURCES)
+    }
+
+    /// Returns the raw MDL
+    #[must_use]
+    pub const fn as_raw(&self) -> PMDL {
+        self.mdl.as_ptr()
+    }
+
+    /// Returns the length in bytes of the buffer described by the MDL
+    #[must_use]
+    pub const fn byte_count(&self) -> usize {
+        // SAFETY: `mdl` is a valid MDL owned by this `Mdl`
+        byte_count(unsafe { self.mdl.as_ref() })
+    }
+
+    /// Probes the pages of the buffer for `operation` from `access_mode`, makes
+    /// them resident, and locks them in memory
+    ///
+    /// This must be called at `IRQL` <= `APC_LEVEL` for pageable buffers, or
+    /// at `IRQL` <= `DISPATCH_LEVEL` for buffers that are already resident.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must be valid for `operation` from `access_mode`. When
+    /// probing fails, [`MmProbeAndLockPages`] raises an exception, which cannot
+    /// be handled from Rust and bugchecks the system. User-mode buffers must
+    /// therefore be known to stay valid (ex. buffers already probed by the I/O
+    /// manager), and be accessed in the context of their process.
+    #[must_use = "the pages are unlocked when the LockedMdl is dropped"]
+    pub unsafe fn probe_and_lock(
+        self,
+        access_mode: AccessMode,
+        operation: LockOperation,
+    ) -> LockedMdl {
+        // SAFETY: `mdl` is a valid MDL owned by this `Mdl`, whose pages are not locked
+        // yet. The caller guarantees that probing the buffer does not fail.
+        unsafe {
+            MmProbeAndLockPages(
+                self.as_raw(),
+                access_mode.processor_mode(),
+                operation.lock_operation(),
+            );
+        }
+        LockedMdl { mdl: self }
+    }
+}
+
+impl Drop for Mdl {
+    fn drop(&mut self) {
+        // SAFETY: `mdl` was allocated by `IoAllocateMdl`, and is not used after it is
+        // freed
+        unsafe {
+            IoFreeMdl(self.as_raw());
+        }
+    }
+}
+
+/// MDL whose pages were locked by [`Mdl::probe_and_lock`]
+///
+/// Dropping the [`LockedMdl`] unlocks its pages, which also releases the
+/// mapping returned by [`LockedMdl::system_va`], and then frees the MDL.
+#[derive(Debug)]
+pub struct LockedMdl {
+    mdl: Mdl,
+}
+
+impl LockedMdl {
+    /// Returns the raw MDL
+    #[must_use]
+    pub const fn as_raw(&self) -> PMDL {
+        self.mdl.as_raw()
+    }
+
+    /// Returns the length in bytes of the buffer described by the MDL
+    #[must_use]
+    pub const fn byte_count(&self) -> usize {
+        self.mdl.byte_count()
+    }
+
+    /// Returns the address of the buffer in system space, mapping the pages
+    /// into system space first if they are not mapped yet
+    ///
+    /// This is the equivalent of `MmGetSystemAddressForMdlSafe`. The mapping
+    /// is cached, and remains valid until the [`LockedMdl`] is dropped.
+    ///
+    /// This must be called at `IRQL` <= `DISPATCH_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_INSUFFICIENT_RESOURCES` if there is
+    /// not enough system space to map the pages
+    pub fn system_va(&self) -> Result<NonNull<c_void>, NTSTATUS> {
+        // SAFETY: `mdl` is a valid MDL owned by this `LockedMdl`, whose pages are
+        // locked
+        unsafe { system_address(self.as_raw()) }.ok_or(STATUS_INSUFFICIENT_RESOURCES)
+    }
+
+    /// Maps the pages into system space with the caching of `cache_type`
+    ///
+    /// Unlike [`LockedMdl::system_va`], the caching of the mapping can be
+    /// chosen (ex. to access device memory), and the mapping is unmapped when
+    /// the returned [`MappedMdl`] is dropped.
+    ///
+    /// This must be called at `IRQL` <= `DISPATCH_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return:
+    /// - `STATUS_INVALID_DEVICE_STATE` if the pages are already mapped into
+    ///   system space (ex. by [`LockedMdl::system_va`])
+    /// - `STATUS_INSUFFICIENT_RESOURCES` if there is not enough system space to
+    ///   map the pages
+    pub fn map_locked_pages(&mut self, cache_type: CacheType) -> Result<MappedMdl<'_>, NTSTATUS> {
+        // SAFETY: `mdl` is a valid MDL owned by this `LockedMdl`
+        if unsafe { self.mdl.mdl.as_ref() }.MdlFlags & MAPPED_TO_SYSTEM_VA_FLAGS != 0 {
+            return Err(STATUS_INVALID_DEVICE_STATE);
+        }
+
+        // SAFETY: `mdl` is a valid MDL owned by this `LockedMdl`, whose pages are
+        // locked and not mapped into system space yet. Mappings into system space do
+        // not raise exceptions, since `BugCheckOnFailure` is `FALSE`.
+        let base_address = unsafe {
+            MmMapLockedPagesSpecifyCache(
+                self.as_raw(),
+                KERNEL_MODE,
+                cache_type.memory_caching_type(),
+                ptr::null_mut(),
+                0,
+                MAPPING_PRIORITY,
+            )
+        };
+        let base_address = NonNull::new(base_address).ok_or(STATUS_INSUFFICIENT_RESOURCES)?;
+        Ok(MappedMdl {
+            locked_mdl: self,
+            base_address,
+        })
+    }
+}
+
+impl Drop for LockedMdl {
+    fn drop(&mut self) {
+        // SAFETY: The pages of `mdl` were locked by `Mdl::probe_and_lock`, and are
+        // unlocked exactly once, before the MDL is freed
+        unsafe {
+            MmUnlockPages(self.as_raw());
+        }
+    }
+}
+
+/// Mapping of the pages of a [`LockedMdl`] into system space, created by
+/// [`LockedMdl::map_locked_pages`]
+///
+/// Dropping the [`MappedMdl`] unmaps the pages.
+#[derive(Debug)]
+pub struct MappedMdl<'a> {
+    locked_mdl: &'a mut LockedMdl,
+    base_address: NonNull<c_void>,
+}
+
+impl MappedMdl<'_> {
+    /// Returns the address of the buffer in system space
+    #[must_use]
+    pub const fn as_ptr(&self) -> *mut u8 {
+        self.base_address.as_ptr().cast()
+    }
+
+    /// Returns the length in bytes of the mapped buffer
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.locked_mdl.byte_count()
+    }
+
+    /// Returns `true` if the mapped buffer is empty
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Drop for MappedMdl<'_> {
+    fn drop(&mut self) {
+        // SAFETY: `base_address` was returned by `MmMapLockedPagesSpecifyCache` for
+        // the MDL, which is still locked, and is unmapped exactly once
+        unsafe {
+            MmUnmapLockedPages(self.base_address.as_ptr(), self.locked_mdl.as_raw());
+        }
+    }
+}
+
+/// Returns the length in bytes of the buffer described by `mdl`
+pub(crate) const fn byte_count(mdl: &wdk_sys::MDL) -> usize {
+    mdl.ByteCount as usize
+}
+
+/// Returns the address of the buffer described by `mdl` in system space,
+/// mapping its pages into system space first if they are not mapped yet, or
+/// `None` if there is not enough system space to map them
+///
+/// This is the equivalent of the `MmGetSystemAddressForMdlSafe` inline
+/// function of `wdm.h`, which has no bindings.
+///
+/// # Safety
+///
+/// `mdl` must be a valid MDL whose pages are locked (or that describes
+/// non-paged pool), and that is not accessed concurrently
+pub(crate) unsafe fn system_address(mdl: PMDL) -> Option<NonNull<c_void>> {
+    // SAFETY: The caller guarantees that `mdl` is a valid MDL
+    let mdl_ref = unsafe { &*mdl };
+    if mdl_ref.MdlFlags & MAPPED_TO_SYSTEM_VA_FLAGS != 0 {
+        return NonNull::new(mdl_ref.MappedSystemVa);
+    }
+
+    // SAFETY: The caller guarantees that the pages of `mdl` are locked, and
+    // mappings into system space do not raise exceptions, since
+    // `BugCheckOnFailure` is `FALSE`. The mapping is recorded in the MDL, and
+    // released when its pages are unlocked.
+    let base_address = unsafe {
+        MmMapLockedPagesSpecifyCache(
+            mdl,
+            KERNEL_MODE,
+            MmCached,
+            ptr::null_mut(),
+            0,
+            MAPPING_PRIORITY,
+        )
+    };
+    NonNull::new(base_address)
+}
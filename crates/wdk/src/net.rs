@@ -0,0 +1,722 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Kernel-mode TCP sockets through Winsock Kernel (WSK)
+//!
+//! A driver registers as a WSK client with [`WskClient::register`], typically
+//! in `DriverEntry`, and keeps the [`WskClient`] in driver-global state (ex. a
+//! [`DriverCell`](crate::sync::DriverCell)). Connections are then opened with
+//! [`WskSocket::connect`].
+//!
+//! All socket operations are asynchronous: instead of blocking, each operation
+//! takes a closure that is called when the operation completes. Completion
+//! closures are called at `IRQL` <= `DISPATCH_LEVEL`, possibly before the
+//! function that started the operation returns, and may start further
+//! operations (ex. the next receive).
+//!
+//! ```rust, ignore
+//! use wdk::net::{WskClient, WskSocket};
+//!
+//! static WSK_CLIENT: DriverCell<WskClient> = DriverCell::new();
+//!
+//! // In `DriverEntry`
+//! WSK_CLIENT.init(WskClient::register(Some(WAIT_TIMEOUT_MILLISECONDS))?)?;
+//!
+//! WskSocket::connect(WSK_CLIENT.get().unwrap(), remote_address, |socket| {
+//!     if let Ok(socket) = socket {
+//!         socket.send(b"ping".to_vec(), |_buffer, bytes_sent| {
+//!             println!("Sent {bytes_sent:?} bytes");
+//!         });
+//!     }
+//! });
+//! ```
+//!
+//! Dropping a [`WskSocket`] closes it, cancelling any of its pending
+//! operations. Each [`WskSocket`] keeps the registration of its [`WskClient`]
+//! alive, and the driver is deregistered as a WSK client once the
+//! [`WskClient`] and all of its sockets (including pending connections) have
+//! been dropped. Deregistering waits for the sockets to be closed, so whichever
+//! is dropped last must be dropped at `IRQL` = `PASSIVE_LEVEL`. Dropping every
+//! [`WskSocket`] before the [`WskClient`] (ex. in driver unload) ensures this.
+//!
+//! Buffers passed to [`WskSocket::send`] and [`WskSocket::receive`] are
+//! described to WSK with an MDL built for non-paged pool, so the global
+//! allocator must allocate from non-paged pool (ex. `wdk_alloc::WdkAllocator`).
+
+extern crate alloc;
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{
+    mem::size_of,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    ptr,
+};
+
+use wdk_sys::{
+    ntddk::{IoAllocateIrp, IoAllocateMdl, IoFreeIrp, IoFreeMdl, MmBuildMdlForNonPagedPool},
+    wsk::{WskCaptureProviderNPI, WskDeregister, WskRegister, WskReleaseProviderNPI},
+    NTSTATUS,
+    PDEVICE_OBJECT,
+    PFN_WSK_SEND,
+    PIRP,
+    PVOID,
+    PWSK_SOCKET,
+    SL_INVOKE_ON_CANCEL,
+    SL_INVOKE_ON_ERROR,
+    SL_INVOKE_ON_SUCCESS,
+    STATUS_INSUFFICIENT_RESOURCES,
+    STATUS_INVALID_PARAMETER,
+    STATUS_MORE_PROCESSING_REQUIRED,
+    STATUS_NOT_SUPPORTED,
+    UCHAR,
+    ULONG,
+    ULONG_PTR,
+    WSK_BUF,
+    WSK_CLIENT_DISPATCH,
+    WSK_CLIENT_NPI,
+    WSK_PROVIDER_CONNECTION_DISPATCH,
+    WSK_PROVIDER_DISPATCH,
+    WSK_PROVIDER_NPI,
+    WSK_REGISTRATION,
+};
+
+use crate::nt_success;
+
+/// `MAKE_WSK_VERSION(1, 0)` in `wsk.h`
+const WSK_VERSION_1_0: u16 = 0x0100;
+
+/// `WSK_INFINITE_WAIT` in `wsk.h`
+const WSK_INFINITE_WAIT: ULONG = 0xFFFF_FFFF;
+
+/// `AF_INET` in `ws2def.h`
+const AF_INET: u16 = 2;
+
+/// `AF_INET6` in `ws2def.h`
+const AF_INET6: u16 = 23;
+
+/// `SOCK_STREAM` in `ws2def.h`
+const SOCK_STREAM: u16 = 1;
+
+/// `IPPROTO_TCP` in `ws2def.h`
+const IPPROTO_TCP: ULONG = 6;
+
+/// Dispatch table of the WSK client. No client-level event callbacks are
+/// registered.
+static CLIENT_DISPATCH: WSK_CLIENT_DISPATCH = WSK_CLIENT_DISPATCH {
+    Version: WSK_VERSION_1_0,
+    Reserved: 0,
+    WskClientEvent: None,
+};
+
+/// Registration of the driver as a WSK client, along with the captured WSK
+/// provider NPI used to create sockets.
+///
+/// The registration is shared with every [`WskSocket`] created from the
+/// client, and is only released and deregistered once the [`WskClient`] and
+/// all of its sockets have been dropped. Deregistering waits for all sockets
+/// created from the client to be closed, so the last of them must be dropped at
+/// `IRQL` = `PASSIVE_LEVEL`.
+pub struct WskClient {
+    registration: Arc<Registration>,
+}
+
+/// WSK client registration shared by a [`WskClient`] and its sockets, which
+/// releases the provider NPI and deregisters the client when dropped
+struct Registration {
+    // `WskRegister` requires the registration to stay at the same address until
+    // `WskDeregister` is called
+    registration: Box<WSK_REGISTRATION>,
+    provider_npi: WSK_PROVIDER_NPI,
+}
+
+#[allow(
+    clippy::non_send_fields_in_send_ty,
+    reason = "the raw pointers in the provider NPI are owned by the WSK subsystem"
+)]
+// SAFETY: The WSK provider NPI and its dispatch table can be used from any
+// thread, and the registration is only mutated by WSK
+unsafe impl Send for Registration {}
+
+// SAFETY: All of the WSK provider functions used through a shared reference are
+// safe to call concurrently
+unsafe impl Sync for Registration {}
+
+impl WskClient {
+    /// Registers the driver as a WSK client, and waits for the WSK subsystem to
+    /// become available.
+    ///
+    /// `wait_timeout_milliseconds` is the maximum time to wait for the WSK
+    /// subsystem, or `None` to wait indefinitely. Drivers that register in
+    /// `DriverEntry` should not wait indefinitely, since the WSK subsystem may
+    /// not be loaded yet during boot.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if registration fails, or if the WSK subsystem does not become available before the timeout. The error variant will contain a [`NTSTATUS`] of the failure. Full error documentation is available in the [WskRegister Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wsk/nf-wsk-wskregister#return-value) and the [WskCaptureProviderNPI Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wsk/nf-wsk-wskcaptureprovidernpi#return-value)
+    pub fn register(wait_timeout_milliseconds: Option<u32>) -> Result<Self, NTSTATUS> {
+        let mut client_npi = WSK_CLIENT_NPI {
+            ClientContext: ptr::null_mut(),
+            Dispatch: ptr::addr_of!(CLIENT_DISPATCH),
+        };
+        let mut registration = Box::new(WSK_REGISTRATION::default());
+
+        // SAFETY: `client_npi` points to a dispatch table with a static lifetime, and
+        // `registration` is heap allocated, so it does not move until it is
+        // deregistered
+        let nt_status = unsafe {
+            WskRegister(
+                ptr::addr_of_mut!(client_npi),
+                ptr::addr_of_mut!(*registration),
+            )
+        };
+        if !nt_success(nt_status) {
+            return Err(nt_status);
+        }
+
+        let mut provider_npi = WSK_PROVIDER_NPI::default();
+        // SAFETY: `registration` was successfully registered above, and `provider_npi`
+        // is a valid location to store the captured provider NPI
+        let nt_status = unsafe {
+            WskCaptureProviderNPI(
+                ptr::addr_of_mut!(*registration),
+                wait_timeout_milliseconds.unwrap_or(WSK_INFINITE_WAIT),
+                ptr::addr_of_mut!(provider_npi),
+            )
+        };
+        if !nt_success(nt_status) {
+            // SAFETY: `registration` was successfully registered above, and no provider
+            // NPI was captured from it
+            unsafe {
+                WskDeregister(ptr::addr_of_mut!(*registration));
+            }
+            return Err(nt_status);
+        }
+
+        Ok(Self {
+            registration: Arc::new(Registration {
+                registration,
+                provider_npi,
+            }),
+        })
+    }
+}
+
+impl Registration {
+    /// Returns the dispatch table of the captured WSK provider
+    const fn dispatch(&self) -> &WSK_PROVIDER_DISPATCH {
+        // SAFETY: `provider_npi` was captured in `WskClient::register`, and its
+        // dispatch table remains valid until the provider NPI is released when `self`
+        // is dropped
+        unsafe { &*self.provider_npi.Dispatch }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        // SAFETY: `provider_npi` was captured from `registration` in
+        // `WskClient::register`, and is not used after it is released
+        unsafe {
+            WskReleaseProviderNPI(ptr::addr_of_mut!(*self.registration));
+        }
+
+        // SAFETY: `registration` was successfully registered in
+        // `WskClient::register`, and its provider NPI was released above
+        unsafe {
+            WskDeregister(ptr::addr_of_mut!(*self.registration));
+        }
+    }
+}
+
+/// Connected TCP socket created by [`WskSocket::connect`]
+///
+/// All operations on the socket can be started at `IRQL` <= `DISPATCH_LEVEL`,
+/// and dropping the socket closes it without blocking, unless it is the last
+/// owner of the registration of its [`WskClient`], which is then deregistered.
+pub struct WskSocket {
+    socket: PWSK_SOCKET,
+    // Allocated up front so that closing the socket when it is dropped cannot fail
+    close_irp: PIRP,
+    // Keeps the client registered until the socket is closed when it is dropped,
+    // since deregistering the client waits for all of its sockets to be closed
+    _registration: Arc<Registration>,
+}
+
+// SAFETY: WSK sockets can be used from any thread
+unsafe impl Send for WskSocket {}
+
+// SAFETY: WSK allows the functions of a socket to be called concurrently, and
+// closing the socket requires ownership of the `WskSocket`
+unsafe impl Sync for WskSocket {}
+
+impl WskSocket {
+    /// Creates a TCP socket bound to an ephemeral local port, and connects it
+    /// to `remote_address`. `completion` is called with the connected
+    /// socket, or with the [`NTSTATUS`] of the failure.
+    ///
+    /// Full error documentation is available in the [WskSocketConnect Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wsk/nc-wsk-pfn_wsk_socket_connect#return-value)
+    pub fn connect<F>(client: &WskClient, remote_address: SocketAddr, completion: F)
+    where
+        F: FnOnce(Result<Self, NTSTATUS>) + Send + 'static,
+    {
+        let Some(wsk_socket_connect) = client.registration.dispatch().WskSocketConnect else {
+            completion(Err(STATUS_NOT_SUPPORTED));
+            return;
+        };
+        let Some(close_irp) = allocate_irp() else {
+            completion(Err(STATUS_INSUFFICIENT_RESOURCES));
+            return;
+        };
+        let Some(irp) = allocate_irp() else {
+            // SAFETY: `close_irp` was allocated above, and is not used after it is freed
+            unsafe {
+                IoFreeIrp(close_irp);
+            }
+            completion(Err(STATUS_INSUFFICIENT_RESOURCES));
+            return;
+        };
+
+        let unspecified_address = match remote_address {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        let addresses = ConnectAddresses {
+            local: RawSocketAddress::from(SocketAddr::new(unspecified_address, 0)),
+            remote: RawSocketAddress::from(remote_address),
+        };
+        let wsk_client = client.registration.provider_npi.Client;
+        let registration = Arc::clone(&client.registration);
+
+        let complete = move |_addresses, nt_status, information| {
+            if nt_success(nt_status) {
+                completion(Ok(Self {
+                    // A successful `WskSocketConnect` returns the socket in the
+                    // `Information` of the IRP
+                    socket: information as PWSK_SOCKET,
+                    close_irp,
+                    _registration: registration,
+                }));
+            } else {
+                // SAFETY: `close_irp` was allocated above, and is not used after it is
+                // freed, since no socket was created
+                unsafe {
+                    IoFreeIrp(close_irp);
+                }
+                completion(Err(nt_status));
+            }
+        };
+        let issue = |addresses: *mut ConnectAddresses, irp| {
+            // SAFETY: `addresses` points to the addresses kept alive by `issue_request`
+            let local_address = unsafe { ptr::addr_of_mut!((*addresses).local) };
+            // SAFETY: `addresses` points to the addresses kept alive by `issue_request`
+            let remote_address = unsafe { ptr::addr_of_mut!((*addresses).remote) };
+
+            // SAFETY: The addresses remain valid until the IRP completes, and the socket
+            // does not have any event callbacks, so it does not need a context or
+            // dispatch table
+            unsafe {
+                wsk_socket_connect(
+                    wsk_client,
+                    SOCK_STREAM,
+                    IPPROTO_TCP,
+                    local_address.cast(),
+                    remote_address.cast(),
+                    0,
+                    ptr::null_mut(),
+                    ptr::null(),
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    irp,
+                )
+            }
+        };
+
+        // SAFETY: `irp` was allocated above and is passed to `WskSocketConnect`, which
+        // always completes it
+        unsafe {
+            issue_request(irp, addresses, complete, issue);
+        }
+    }
+
+    /// Sends the contents of `buffer` over the connection. `completion` is
+    /// called with `buffer`, and with the number of bytes sent or the
+    /// [`NTSTATUS`] of the failure.
+    ///
+    /// `buffer` must not be empty or larger than `ULONG::MAX` bytes, otherwise
+    /// the send fails with `STATUS_INVALID_PARAMETER`. Full error documentation is available in the [WskSend Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wsk/nc-wsk-pfn_wsk_send#return-value)
+    pub fn send<F>(&self, buffer: Vec<u8>, completion: F)
+    where
+        F: FnOnce(Vec<u8>, Result<usize, NTSTATUS>) + Send + 'static,
+    {
+        self.transfer(self.dispatch().WskSend, buffer, completion);
+    }
+
+    /// Receives data from the connection into `buffer`. `completion` is called
+    /// with `buffer`, and with the number of bytes received or the [`NTSTATUS`]
+    /// of the failure. Receiving 0 bytes means that the remote end of the
+    /// connection has gracefully disconnected.
+    ///
+    /// Up to `buffer.len()` bytes are received. `buffer` must not be empty or
+    /// larger than `ULONG::MAX` bytes, otherwise the receive fails with
+    /// `STATUS_INVALID_PARAMETER`. Full error documentation is available in the [WskReceive Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wsk/nc-wsk-pfn_wsk_receive#return-value)
+    pub fn receive<F>(&self, buffer: Vec<u8>, completion: F)
+    where
+        F: FnOnce(Vec<u8>, Result<usize, NTSTATUS>) + Send + 'static,
+    {
+        self.transfer(self.dispatch().WskReceive, buffer, completion);
+    }
+
+    /// Gracefully disconnects the socket, after any pending sends have
+    /// completed. `completion` is called with the result of the disconnect.
+    ///
+    /// Full error documentation is available in the [WskDisconnect Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wsk/nc-wsk-pfn_wsk_disconnect#return-value)
+    pub fn disconnect<F>(&self, completion: F)
+    where
+        F: FnOnce(Result<(), NTSTATUS>) + Send + 'static,
+    {
+        let Some(wsk_disconnect) = self.dispatch().WskDisconnect else {
+            completion(Err(STATUS_NOT_SUPPORTED));
+            return;
+        };
+        let Some(irp) = allocate_irp() else {
+            completion(Err(STATUS_INSUFFICIENT_RESOURCES));
+            return;
+        };
+        let socket = self.socket;
+
+        let complete = move |(), nt_status, _information| {
+            completion(nt_success(nt_status).then_some(()).ok_or(nt_status));
+        };
+        let issue = |_, irp| {
+            // SAFETY: `socket` is a connected socket that is not closed until the
+            // `WskSocket` is dropped, and a null buffer requests a graceful disconnect
+            // without sending any final data
+            unsafe { wsk_disconnect(socket, ptr::null_mut(), 0, irp) }
+        };
+
+        // SAFETY: `irp` was allocated above and is passed to `WskDisconnect`, which
+        // always completes it
+        unsafe {
+            issue_request(irp, (), complete, issue);
+        }
+    }
+
+    /// Starts a send or receive of `buffer` through `wsk_transfer`, which has
+    /// the same signature for both
+    fn transfer<F>(&self, wsk_transfer: PFN_WSK_SEND, mut buffer: Vec<u8>, completion: F)
+    where
+        F: FnOnce(Vec<u8>, Result<usize, NTSTATUS>) + Send + 'static,
+    {
+        let Some(wsk_transfer) = wsk_transfer else {
+            completion(buffer, Err(STATUS_NOT_SUPPORTED));
+            return;
+        };
+        let length = match ULONG::try_from(buffer.len()) {
+            Ok(length) if length > 0 => length,
+            _ => {
+                completion(buffer, Err(STATUS_INVALID_PARAMETER));
+                return;
+            }
+        };
+
+        // SAFETY: `buffer` is a non-empty allocation of `length` bytes, which is kept
+        // alive by `issue_request` until the MDL is freed
+        let mdl =
+            unsafe { IoAllocateMdl(buffer.as_mut_ptr().cast(), length, 0, 0, ptr::null_mut()) };
+        if mdl.is_null() {
+            completion(buffer, Err(STATUS_INSUFFICIENT_RESOURCES));
+            return;
+        }
+        // SAFETY: `mdl` describes `buffer`, which is allocated from non-paged pool by
+        // the global allocator
+        unsafe {
+            MmBuildMdlForNonPagedPool(mdl);
+        }
+
+        let Some(irp) = allocate_irp() else {
+            // SAFETY: `mdl` was allocated above, and is not used after it is freed
+            unsafe {
+                IoFreeMdl(mdl);
+            }
+            completion(buffer, Err(STATUS_INSUFFICIENT_RESOURCES));
+            return;
+        };
+
+        let transfer = Transfer {
+            wsk_buf: WSK_BUF {
+                Mdl: mdl,
+                Offset: 0,
+                Length: length.into(),
+            },
+            buffer,
+        };
+        let socket = self.socket;
+
+        let complete = move |transfer: Transfer, nt_status, information| {
+            // SAFETY: The IRP that used the MDL has completed, and the MDL is not used
+            // after it is freed
+            unsafe {
+                IoFreeMdl(transfer.wsk_buf.Mdl);
+            }
+            completion(
+                transfer.buffer,
+                nt_success(nt_status)
+                    .then(|| bytes_transferred(information))
+                    .ok_or(nt_status),
+            );
+        };
+        let issue = |transfer: *mut Transfer, irp| {
+            // SAFETY: `transfer` points to the transfer kept alive by `issue_request`
+            let wsk_buf = unsafe { ptr::addr_of_mut!((*transfer).wsk_buf) };
+
+            // SAFETY: `socket` is a connected socket that is not closed until the
+            // `WskSocket` is dropped, and `wsk_buf` remains valid until the IRP completes
+            unsafe { wsk_transfer(socket, wsk_buf, 0, irp) }
+        };
+
+        // SAFETY: `irp` was allocated above and is passed to `wsk_transfer`, which
+        // always completes it
+        unsafe {
+            issue_request(irp, transfer, complete, issue);
+        }
+    }
+
+    /// Returns the dispatch table of the connection-oriented socket
+    const fn dispatch(&self) -> &WSK_PROVIDER_CONNECTION_DISPATCH {
+        // SAFETY: `socket` was created with `WskSocketConnect`, so it is a valid socket
+        // until it is closed when `self` is dropped
+        let dispatch = unsafe { (*self.socket).Dispatch };
+        // SAFETY: The dispatch table of a connection-oriented socket is a
+        // `WSK_PROVIDER_CONNECTION_DISPATCH`
+        unsafe { &*dispatch.cast::<WSK_PROVIDER_CONNECTION_DISPATCH>() }
+    }
+}
+
+impl Drop for WskSocket {
+    fn drop(&mut self) {
+        let Some(wsk_close_socket) = self.dispatch().Basic.WskCloseSocket else {
+            // SAFETY: `close_irp` is only used to close the socket, and is not used after
+            // it is freed
+            unsafe {
+                IoFreeIrp(self.close_irp);
+            }
+            return;
+        };
+        let socket = self.socket;
+
+        let issue = |_, irp| {
+            // SAFETY: `socket` is a connected socket that is only closed here. Any pending
+            // operations on the socket are cancelled by WSK.
+            unsafe { wsk_close_socket(socket, irp) }
+        };
+
+        // SAFETY: `close_irp` was allocated in `connect` and has not been used, and is
+        // passed to `WskCloseSocket`, which always completes it
+        unsafe {
+            issue_request(self.close_irp, (), |(), _nt_status, _information| {}, issue);
+        }
+    }
+}
+
+/// Local and remote addresses passed to `WskSocketConnect`, which must remain
+/// valid until the request completes
+struct ConnectAddresses {
+    local: RawSocketAddress,
+    remote: RawSocketAddress,
+}
+
+/// Buffer of a send or receive request, along with the `WSK_BUF` describing
+/// it, which must remain valid until the request completes
+struct Transfer {
+    wsk_buf: WSK_BUF,
+    buffer: Vec<u8>,
+}
+
+/// Equivalent to `SOCKADDR_IN` in `ws2def.h`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RawSocketAddressV4 {
+    family: u16,
+    port: u16,
+    address: [u8; 4],
+    zero: [u8; 8],
+}
+
+/// Equivalent to `SOCKADDR_IN6` in `ws2ipdef.h`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RawSocketAddressV6 {
+    family: u16,
+    port: u16,
+    flow_info: u32,
+    address: [u8; 16],
+    scope_id: u32,
+}
+
+/// Socket address in the layout expected by WSK, which is determined by its
+/// address family
+#[repr(C)]
+union RawSocketAddress {
+    v4: RawSocketAddressV4,
+    v6: RawSocketAddressV6,
+}
+
+impl From<SocketAddr> for RawSocketAddress {
+    fn from(socket_address: SocketAddr) -> Self {
+        // Ports are stored in network byte order
+        match socket_address {
+            SocketAddr::V4(socket_address) => Self {
+                v4: RawSocketAddressV4 {
+                    family: AF_INET,
+                    port: socket_address.port().to_be(),
+                    address: socket_address.ip().octets(),
+                    zero: [0; 8],
+                },
+            },
+            SocketAddr::V6(socket_address) => Self {
+                v6: RawSocketAddressV6 {
+                    family: AF_INET6,
+                    port: socket_address.port().to_be(),
+                    flow_info: socket_address.flowinfo(),
+                    address: socket_address.ip().octets(),
+                    scope_id: socket_address.scope_id(),
+                },
+            },
+        }
+    }
+}
+
+const _: () = assert!(size_of::<RawSocketAddressV4>() == 16);
+const _: () = assert!(size_of::<RawSocketAddressV6>() == 28);
+
+/// State of an in-flight WSK request, owned by the completion routine of its
+/// IRP
+struct Request<T, F> {
+    data: T,
+    completion: F,
+}
+
+/// Allocates an IRP with a single stack location for a WSK request
+fn allocate_irp() -> Option<PIRP> {
+    // SAFETY: The IRP is only passed to WSK functions, which do not require the
+    // IRP to have a stack location for the caller
+    let irp = unsafe { IoAllocateIrp(1, 0) };
+    (!irp.is_null()).then_some(irp)
+}
+
+/// Sets the completion routine of `irp` to call `completion` with `data` and
+/// the final status and information of the IRP, and then calls `issue` to pass
+/// `irp` to a WSK function. `issue` receives a pointer to `data`, which remains
+/// valid until `irp` completes.
+///
+/// # Safety
+///
+/// `irp` must be an unused IRP allocated by [`allocate_irp`], and `issue` must
+/// pass it to a WSK function, which always completes it. `issue` must not
+/// access `data` after passing `irp` to WSK, since the request may have already
+/// completed.
+unsafe fn issue_request<T, F>(
+    irp: PIRP,
+    data: T,
+    completion: F,
+    issue: impl FnOnce(*mut T, PIRP) -> NTSTATUS,
+) where
+    F: FnOnce(T, NTSTATUS, ULONG_PTR),
+{
+    let request = Box::into_raw(Box::new(Request { data, completion }));
+
+    // SAFETY: `irp` is an unused IRP with a stack location for the completion
+    // routine, as guaranteed by the caller. `request` is freed by the completion
+    // routine.
+    unsafe {
+        set_completion_routine(irp, complete_request::<T, F>, request.cast());
+    }
+
+    // WSK always completes the IRP, and reports the result of the request through
+    // it, so the returned status is not needed
+    let _ = issue(
+        // SAFETY: `request` was allocated above, and is not freed until `irp` completes
+        unsafe { ptr::addr_of_mut!((*request).data) },
+        irp,
+    );
+}
+
+/// Equivalent to `IoSetCompletionRoutine` with `InvokeOnSuccess`,
+/// `InvokeOnError` and `InvokeOnCancel` all set
+///
+/// # Safety
+///
+/// `irp` must be a valid IRP whose next stack location is available to the
+/// caller
+unsafe fn set_completion_routine(
+    irp: PIRP,
+    completion_routine: unsafe extern "C" fn(PDEVICE_OBJECT, PIRP, PVOID) -> NTSTATUS,
+    context: PVOID,
+) {
+    // SAFETY: `irp` is a valid IRP, as guaranteed by the caller
+    let tail = unsafe { (*irp).Tail };
+    // SAFETY: `Overlay` is the active member of `Tail` for an IRP that is not
+    // queued
+    let overlay = unsafe { tail.Overlay };
+    // SAFETY: `CurrentStackLocation` is the active member of the union for an IRP
+    // that is not queued
+    let current_stack_location = unsafe {
+        overlay
+            .__bindgen_anon_2
+            .__bindgen_anon_1
+            .CurrentStackLocation
+    };
+    // SAFETY: The next stack location of `irp` is available, as guaranteed by the
+    // caller
+    let next_stack_location = unsafe { &mut *current_stack_location.wrapping_sub(1) };
+
+    next_stack_location.CompletionRoutine = Some(completion_routine);
+    next_stack_location.Context = context;
+    // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+    #[allow(clippy::cast_possible_truncation)]
+    {
+        next_stack_location.Control =
+            (SL_INVOKE_ON_SUCCESS | SL_INVOKE_ON_ERROR | SL_INVOKE_ON_CANCEL) as UCHAR;
+    }
+}
+
+/// Completion routine of the IRPs of WSK requests. This frees the IRP, and
+/// calls the completion closure of the request.
+unsafe extern "C" fn complete_request<T, F>(
+    _device_object: PDEVICE_OBJECT,
+    irp: PIRP,
+    context: PVOID,
+) -> NTSTATUS
+where
+    F: FnOnce(T, NTSTATUS, ULONG_PTR),
+{
+    // SAFETY: `context` was created from a `Box<Request<T, F>>` in `issue_request`,
+    // and the completion routine of an IRP is only called once
+    let Request { data, completion } = *unsafe { Box::from_raw(context.cast::<Request<T, F>>()) };
+
+    // SAFETY: `irp` is the completed IRP of the request
+    let io_status = unsafe { (*irp).IoStatus };
+    // SAFETY: `Status` is the active member of the union for a completed IRP
+    let nt_status = unsafe { io_status.__bindgen_anon_1.Status };
+
+    // SAFETY: `irp` was allocated by `allocate_irp`, and is not used after it is
+    // freed
+    unsafe {
+        IoFreeIrp(irp);
+    }
+
+    completion(data, nt_status, io_status.Information);
+
+    // The IRP was freed above, so the I/O manager must not process it any further
+    STATUS_MORE_PROCESSING_REQUIRED
+}
+
+/// Converts the number of bytes transferred by a request into a `usize`
+const fn bytes_transferred(bytes: ULONG_PTR) -> usize {
+    // `ULONG_PTR` is always the size of a pointer
+    #[allow(clippy::cast_possible_truncation)]
+    let bytes = bytes as usize;
+    bytes
+}
@@ -0,0 +1,443 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Access to the configuration space of PCI devices from KMDF function drivers
+//!
+//! [`PciDevice::query`] queries the `BUS_INTERFACE_STANDARD` of the PCI bus
+//! driver for a device, typically in `EvtDevicePrepareHardware`, and reads and
+//! writes the configuration space of the device through it:
+//!
+//! ```rust, ignore
+//! use wdk::pci::{BaseAddress, PciDevice};
+//! use wdk_sys::{pci, PCI_CAPABILITY_ID_MSIX};
+//!
+//! let pci_device = PciDevice::query(device)?;
+//! if pci_device.vendor_id()? != MY_VENDOR_ID {
+//!     return Err(STATUS_DEVICE_CONFIGURATION_ERROR);
+//! }
+//! let revision_id = pci_device.read_u8(pci::REVISION_ID_OFFSET)?;
+//! if let Some(BaseAddress::Memory { address, .. }) = pci_device.base_address(0)? {
+//!     // `address` is the bus-relative address of the registers of the device
+//! }
+//! let msix = pci_device
+//!     .capabilities()
+//!     .find(|capability| u32::from(capability.id) == PCI_CAPABILITY_ID_MSIX);
+//! ```
+//!
+//! The offsets of the registers of the common configuration header are in
+//! [`wdk_sys::pci`]. The bus interface is dereferenced when the [`PciDevice`]
+//! is dropped, which must happen before the device is removed (ex. in
+//! `EvtDeviceReleaseHardware`).
+
+use core::{mem::size_of, ptr};
+
+use wdk_sys::{
+    call_unsafe_wdf_function_binding,
+    ntddk::GUID_BUS_INTERFACE_STANDARD,
+    pci,
+    BUS_INTERFACE_STANDARD,
+    NTSTATUS,
+    PCI_CARDBUS_BRIDGE_TYPE,
+    PCI_MULTIFUNCTION,
+    PCI_STATUS_CAPABILITIES_LIST,
+    PCI_TYPE0_ADDRESSES,
+    STATUS_DEVICE_DATA_ERROR,
+    STATUS_INVALID_PARAMETER,
+    ULONG,
+    USHORT,
+    WDFDEVICE,
+};
+
+use crate::nt_success;
+
+/// Version of `BUS_INTERFACE_STANDARD` that is queried from the PCI bus driver
+const BUS_INTERFACE_STANDARD_VERSION: USHORT = 1;
+
+/// Size, in bytes, of `BUS_INTERFACE_STANDARD`
+// clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+#[allow(clippy::cast_possible_truncation)]
+const BUS_INTERFACE_STANDARD_SIZE: USHORT = size_of::<BUS_INTERFACE_STANDARD>() as USHORT;
+
+/// Bit of a base address register that is set for I/O space BARs
+const BASE_ADDRESS_IO_SPACE: u32 = 0b1;
+/// Bits of a memory base address register that contain its type
+const BASE_ADDRESS_MEMORY_TYPE_MASK: u32 = 0b110;
+/// Type of memory base address registers that contain 64-bit addresses
+const BASE_ADDRESS_MEMORY_TYPE_64_BIT: u32 = 0b100;
+/// Bit of a memory base address register that is set for prefetchable memory
+const BASE_ADDRESS_MEMORY_PREFETCHABLE: u32 = 0b1000;
+
+/// A PCI device, whose configuration space is accessed through the
+/// `BUS_INTERFACE_STANDARD` of the PCI bus driver
+pub struct PciDevice {
+    bus_interface: BUS_INTERFACE_STANDARD,
+}
+
+#[allow(
+    clippy::non_send_fields_in_send_ty,
+    reason = "the context of the bus interface is owned by the PCI bus driver"
+)]
+// SAFETY: The functions of `BUS_INTERFACE_STANDARD` can be called from any
+// thread, and the PCI bus driver synchronizes accesses to the configuration
+// space
+unsafe impl Send for PciDevice {}
+
+// SAFETY: The functions of `BUS_INTERFACE_STANDARD` can be called from any
+// thread, and the PCI bus driver synchronizes accesses to the configuration
+// space
+unsafe impl Sync for PciDevice {}
+
+impl PciDevice {
+    /// Try to query the `BUS_INTERFACE_STANDARD` of the PCI bus driver for
+    /// `device`.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`, typically from the
+    /// `EvtDevicePrepareHardware` callback.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the bus driver of `device` does not provide `BUS_INTERFACE_STANDARD`. Full error documentation is available in the [WdfFdoQueryForInterface Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdffdo/nf-wdffdo-wdffdoqueryforinterface#return-value)
+    pub fn query(device: WDFDEVICE) -> Result<Self, NTSTATUS> {
+        let mut bus_interface = BUS_INTERFACE_STANDARD::default();
+
+        let nt_status;
+        // SAFETY: `device` is a handle to a WDF device object, and `bus_interface` is
+        // valid for writes of the size passed to WDF
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfFdoQueryForInterface,
+                device,
+                &GUID_BUS_INTERFACE_STANDARD,
+                ptr::addr_of_mut!(bus_interface).cast(),
+                BUS_INTERFACE_STANDARD_SIZE,
+                BUS_INTERFACE_STANDARD_VERSION,
+                ptr::null_mut(),
+            );
+        }
+        if !nt_success(nt_status) {
+            return Err(nt_status);
+        }
+        Ok(Self { bus_interface })
+    }
+
+    /// Reads `buffer.len()` bytes of the configuration space, starting at
+    /// `offset`.
+    ///
+    /// This must be called at `IRQL` <= `DISPATCH_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_DEVICE_DATA_ERROR` if the range does
+    /// not fit in the configuration space of the device
+    pub fn read_bytes(&self, offset: ULONG, buffer: &mut [u8]) -> Result<(), NTSTATUS> {
+        // SAFETY: `bus_interface` is a private member of `PciDevice`, which was queried
+        // from the PCI bus driver and is dereferenced only when `self` is dropped
+        let bytes_read = unsafe { pci::read_config(&self.bus_interface, offset, buffer) };
+        if bytes_read as usize != buffer.len() {
+            return Err(STATUS_DEVICE_DATA_ERROR);
+        }
+        Ok(())
+    }
+
+    /// Writes `buffer` to the configuration space, starting at `offset`.
+    ///
+    /// This must be called at `IRQL` <= `DISPATCH_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_DEVICE_DATA_ERROR` if the range does
+    /// not fit in the configuration space of the device
+    ///
+    /// # Safety
+    ///
+    /// The written registers must not change the resources of the device that
+    /// are in use (ex. its base address registers while they are mapped)
+    pub unsafe fn write_bytes(&self, offset: ULONG, buffer: &[u8]) -> Result<(), NTSTATUS> {
+        // SAFETY: `bus_interface` is a private member of `PciDevice`, which was queried
+        // from the PCI bus driver and is dereferenced only when `self` is dropped. The
+        // caller guarantees that the write is sound.
+        let bytes_written = unsafe { pci::write_config(&self.bus_interface, offset, buffer) };
+        if bytes_written as usize != buffer.len() {
+            return Err(STATUS_DEVICE_DATA_ERROR);
+        }
+        Ok(())
+    }
+
+    /// Reads the 8-bit register at `offset` of the configuration space.
+    ///
+    /// This must be called at `IRQL` <= `DISPATCH_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_DEVICE_DATA_ERROR` if the register
+    /// is not in the configuration space of the device
+    pub fn read_u8(&self, offset: ULONG) -> Result<u8, NTSTATUS> {
+        let mut value = [0; 1];
+        self.read_bytes(offset, &mut value)?;
+        Ok(u8::from_le_bytes(value))
+    }
+
+    /// Reads the 16-bit register at `offset` of the configuration space.
+    ///
+    /// This must be called at `IRQL` <= `DISPATCH_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_DEVICE_DATA_ERROR` if the register
+    /// is not in the configuration space of the device
+    pub fn read_u16(&self, offset: ULONG) -> Result<u16, NTSTATUS> {
+        let mut value = [0; 2];
+        self.read_bytes(offset, &mut value)?;
+        Ok(u16::from_le_bytes(value))
+    }
+
+    /// Reads the 32-bit register at `offset` of the configuration space.
+    ///
+    /// This must be called at `IRQL` <= `DISPATCH_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_DEVICE_DATA_ERROR` if the register
+    /// is not in the configuration space of the device
+    pub fn read_u32(&self, offset: ULONG) -> Result<u32, NTSTATUS> {
+        let mut value = [0; 4];
+        self.read_bytes(offset, &mut value)?;
+        Ok(u32::from_le_bytes(value))
+    }
+
+    /// Writes `value` to the 8-bit register at `offset` of the configuration
+    /// space.
+    ///
+    /// This must be called at `IRQL` <= `DISPATCH_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_DEVICE_DATA_ERROR` if the register
+    /// is not in the configuration space of the device
+    ///
+    /// # Safety
+    ///
+    /// The written register must not change the resources of the device that
+    /// are in use (ex. its base address registers while they are mapped)
+    pub unsafe fn write_u8(&self, offset: ULONG, value: u8) -> Result<(), NTSTATUS> {
+        // SAFETY: The caller guarantees that the write is sound
+        unsafe { self.write_bytes(offset, &value.to_le_bytes()) }
+    }
+
+    /// Writes `value` to the 16-bit register at `offset` of the configuration
+    /// space.
+    ///
+    /// This must be called at `IRQL` <= `DISPATCH_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_DEVICE_DATA_ERROR` if the register
+    /// is not in the configuration space of the device
+    ///
+    /// # Safety
+    ///
+    /// The written register must not change the resources of the device that
+    /// are in use (ex. its base address registers while they are mapped)
+    pub unsafe fn write_u16(&self, offset: ULONG, value: u16) -> Result<(), NTSTATUS> {
+        // SAFETY: The caller guarantees that the write is sound
+        unsafe { self.write_bytes(offset, &value.to_le_bytes()) }
+    }
+
+    /// Writes `value` to the 32-bit register at `offset` of the configuration
+    /// space.
+    ///
+    /// This must be called at `IRQL` <= `DISPATCH_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_DEVICE_DATA_ERROR` if the register
+    /// is not in the configuration space of the device
+    ///
+    /// # Safety
+    ///
+    /// The written register must not change the resources of the device that
+    /// are in use (ex. its base address registers while they are mapped)
+    pub unsafe fn write_u32(&self, offset: ULONG, value: u32) -> Result<(), NTSTATUS> {
+        // SAFETY: The caller guarantees that the write is sound
+        unsafe { self.write_bytes(offset, &value.to_le_bytes()) }
+    }
+
+    /// Reads the vendor ID of the device.
+    ///
+    /// This must be called at `IRQL` <= `DISPATCH_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_DEVICE_DATA_ERROR` if the register
+    /// cannot be read
+    pub fn vendor_id(&self) -> Result<u16, NTSTATUS> {
+        self.read_u16(pci::VENDOR_ID_OFFSET)
+    }
+
+    /// Reads the device ID of the device.
+    ///
+    /// This must be called at `IRQL` <= `DISPATCH_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_DEVICE_DATA_ERROR` if the register
+    /// cannot be read
+    pub fn device_id(&self) -> Result<u16, NTSTATUS> {
+        self.read_u16(pci::DEVICE_ID_OFFSET)
+    }
+
+    /// Reads and decodes the base address register (BAR) at `index` of a
+    /// device with a type 0 header, or returns `None` if the BAR is not
+    /// implemented.
+    ///
+    /// A 64-bit memory BAR also uses the BAR at `index + 1` for the upper
+    /// 32 bits of its address. This must be called at `IRQL` <=
+    /// `DISPATCH_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_INVALID_PARAMETER` if `index` is not
+    /// the index of a BAR of a type 0 header (or is the last one, for a 64-bit
+    /// memory BAR), or `STATUS_DEVICE_DATA_ERROR` if the BAR cannot be read
+    pub fn base_address(&self, index: usize) -> Result<Option<BaseAddress>, NTSTATUS> {
+        let offset = base_address_offset(index)?;
+        let register = self.read_u32(offset)?;
+        if register == 0 {
+            return Ok(None);
+        }
+
+        if register & BASE_ADDRESS_IO_SPACE != 0 {
+            return Ok(Some(BaseAddress::Io {
+                address: register & !0b11,
+            }));
+        }
+
+        let is_64_bit = register & BASE_ADDRESS_MEMORY_TYPE_MASK == BASE_ADDRESS_MEMORY_TYPE_64_BIT;
+        let upper_address = if is_64_bit {
+            self.read_u32(base_address_offset(index + 1)?)?
+        } else {
+            0
+        };
+        Ok(Some(BaseAddress::Memory {
+            address: (u64::from(upper_address) << 32) | u64::from(register & !0b1111),
+            is_64_bit,
+            is_prefetchable: register & BASE_ADDRESS_MEMORY_PREFETCHABLE != 0,
+        }))
+    }
+
+    /// Returns an iterator over the capabilities in the configuration space
+    /// of the device.
+    ///
+    /// The extended capabilities of PCI Express devices are not included. The
+    /// iteration stops early if the configuration space cannot be read. This
+    /// must be called at `IRQL` <= `DISPATCH_LEVEL`.
+    #[must_use]
+    pub fn capabilities(&self) -> Capabilities<'_> {
+        let next_offset = self.first_capability_offset().unwrap_or(0);
+        Capabilities {
+            pci_device: self,
+            next_offset,
+            remaining: pci::CONFIG_SPACE_LENGTH / 4,
+        }
+    }
+
+    /// Returns the offset of the first capability of the device, or 0 if the
+    /// device has no capabilities
+    fn first_capability_offset(&self) -> Result<ULONG, NTSTATUS> {
+        let status = self.read_u16(pci::STATUS_OFFSET)?;
+        let header_type = self.read_u8(pci::HEADER_TYPE_OFFSET)?;
+        if ULONG::from(status) & PCI_STATUS_CAPABILITIES_LIST == 0
+            || ULONG::from(header_type) & !PCI_MULTIFUNCTION == PCI_CARDBUS_BRIDGE_TYPE
+        {
+            return Ok(0);
+        }
+        Ok(ULONG::from(self.read_u8(pci::CAPABILITIES_PTR_OFFSET)?))
+    }
+}
+
+impl Drop for PciDevice {
+    fn drop(&mut self) {
+        if let Some(interface_dereference) = self.bus_interface.InterfaceDereference {
+            // SAFETY: `bus_interface` was referenced by the PCI bus driver when it was
+            // queried, and is not used after it is dereferenced
+            unsafe {
+                interface_dereference(self.bus_interface.Context);
+            }
+        }
+    }
+}
+
+/// Decoded base address register (BAR) of a PCI device
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BaseAddress {
+    /// Range of memory space addresses
+    Memory {
+        /// Bus-relative address of the start of the range
+        address: u64,
+        /// Whether the BAR is a 64-bit BAR, which also uses the next BAR
+        is_64_bit: bool,
+        /// Whether reads of the range have no side effects
+        is_prefetchable: bool,
+    },
+    /// Range of I/O space addresses
+    Io {
+        /// Bus-relative address of the start of the range
+        address: u32,
+    },
+}
+
+/// Capability in the configuration space of a PCI device
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capability {
+    /// ID of the capability (ex. `PCI_CAPABILITY_ID_MSIX`)
+    pub id: u8,
+    /// Offset of the capability in the configuration space, where its
+    /// `PCI_CAPABILITIES_HEADER` starts
+    pub offset: ULONG,
+}
+
+/// Iterator over the capabilities of a [`PciDevice`], returned by
+/// [`PciDevice::capabilities`]
+pub struct Capabilities<'a> {
+    pci_device: &'a PciDevice,
+    next_offset: ULONG,
+    /// Each capability is at least 4 bytes long, which bounds the length of the
+    /// list even if it is malformed (ex. circular)
+    remaining: ULONG,
+}
+
+impl Iterator for Capabilities<'_> {
+    type Item = Capability;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The bottom 2 bits of capability pointers are reserved
+        let offset = self.next_offset & !0b11;
+        if offset < pci::COMMON_HEADER_LENGTH || self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        // The `CapabilityID` and `Next` fields of the `PCI_CAPABILITIES_HEADER`
+        let mut capability = [0; 2];
+        if self.pci_device.read_bytes(offset, &mut capability).is_err() {
+            self.next_offset = 0;
+            return None;
+        }
+        let [id, next] = capability;
+        self.next_offset = ULONG::from(next);
+        Some(Capability { id, offset })
+    }
+}
+
+/// Returns the offset of the base address register at `index` of a type 0
+/// header
+const fn base_address_offset(index: usize) -> Result<ULONG, NTSTATUS> {
+    if index >= PCI_TYPE0_ADDRESSES as usize {
+        return Err(STATUS_INVALID_PARAMETER);
+    }
+    // `index` is less than `PCI_TYPE0_ADDRESSES`, so it fits in a `ULONG`
+    #[allow(clippy::cast_possible_truncation)]
+    let index = index as ULONG;
+    // Each base address register is a `ULONG`
+    Ok(pci::BASE_ADDRESSES_OFFSET + index * 4)
+}
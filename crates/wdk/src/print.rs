@@ -1,9 +1,43 @@
 // Copyright (c) Microsoft Corporation
 // License: MIT OR Apache-2.0
 
+#[cfg(any(feature = "alloc", driver_model__driver_type = "UMDF"))]
 extern crate alloc;
 
+#[cfg(any(feature = "alloc", driver_model__driver_type = "UMDF"))]
 use alloc::ffi::CString;
+use core::fmt::{self, Write};
+
+/// Size, in bytes, of the stack buffer that [`dbg_print!`](crate::dbg_print)
+/// formats into, including the null terminator. This matches the maximum
+/// length of a single `DbgPrint` message.
+pub const DBG_PRINT_BUFFER_SIZE: usize = 512;
+
+/// Component ID that [`print_ex!`](crate::print_ex) messages are filtered by,
+/// unless another one is given. This is `DPFLTR_IHVDRIVER_ID` in `dpfilter.h`,
+/// the component ID reserved for third-party drivers, whose filter mask is set
+/// with `ed nt!Kd_IHVDRIVER_Mask <mask>` in the kernel debugger.
+pub const DEFAULT_COMPONENT_ID: u32 = 77;
+
+/// Importance of a [`print_ex!`](crate::print_ex) message, which the kernel
+/// debugger's filter mask of its component ID selects whether to print. Errors
+/// are always printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u32)]
+pub enum Level {
+    /// `DPFLTR_ERROR_LEVEL`
+    Error = 0,
+    /// `DPFLTR_WARNING_LEVEL`
+    Warning = 1,
+    /// `DPFLTR_TRACE_LEVEL`
+    Trace = 2,
+    /// `DPFLTR_INFO_LEVEL`
+    Info = 3,
+}
+
+/// Suffix written over the end of a [`dbg_print!`](crate::dbg_print) message
+/// that does not fit in [`DBG_PRINT_BUFFER_SIZE`]
+const TRUNCATION_MARKER: &[u8] = b"...\n";
 
 /// print to kernel debugger via [`wdk_sys::ntddk::DbgPrint`]
 #[macro_export]
@@ -25,28 +59,242 @@ macro_rules! println {
     };
 }
 
+/// print to kernel debugger via [`wdk_sys::ntddk::DbgPrint`], without
+/// allocating
+///
+/// The message is formatted into a fixed-size stack buffer of
+/// [`DBG_PRINT_BUFFER_SIZE`] bytes, and is truncated if it does not fit. This
+/// is safe to use in drivers without a global allocator, and in low-memory or
+/// bugcheck paths where allocating could fail.
+#[macro_export]
+macro_rules! dbg_print {
+    ($($arg:tt)*) => {
+      ($crate::_dbg_print(format_args!($($arg)*)))
+    };
+}
+
+/// print with newline to kernel debugger via [`wdk_sys::ntddk::DbgPrint`],
+/// without allocating. See [`dbg_print!`](crate::dbg_print).
+#[macro_export]
+macro_rules! dbg_println {
+    () => {
+      ($crate::dbg_print!("\n"));
+    };
+
+    ($($arg:tt)*) => {
+      ($crate::dbg_print!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+/// print to kernel debugger via [`wdk_sys::ntddk::DbgPrintEx`], if the filter
+/// mask of the component ID enables the [`Level`]
+///
+/// The component ID is [`DEFAULT_COMPONENT_ID`] unless it is given as the
+/// first argument. Like [`dbg_print!`](crate::dbg_print), the message is
+/// formatted without allocating, and is truncated if it does not fit in
+/// [`DBG_PRINT_BUFFER_SIZE`] bytes. User-mode drivers print every message via
+/// `OutputDebugStringA`.
+///
+/// ```rust, ignore
+/// use wdk::{print_ex, Level};
+///
+/// print_ex!(Level::Warning, "retrying request {request_id}\n");
+/// print_ex!(component_id = MY_COMPONENT_ID, Level::Info, "device started\n");
+/// ```
+#[macro_export]
+macro_rules! print_ex {
+    (component_id = $component_id:expr, $level:expr, $($arg:tt)*) => {
+      ($crate::_print_ex($component_id, $level, format_args!($($arg)*)))
+    };
+
+    ($level:expr, $($arg:tt)*) => {
+      ($crate::_print_ex($crate::DEFAULT_COMPONENT_ID, $level, format_args!($($arg)*)))
+    };
+}
+
+/// print with newline to kernel debugger via
+/// [`wdk_sys::ntddk::DbgPrintEx`]. See [`print_ex!`](crate::print_ex).
+#[macro_export]
+macro_rules! println_ex {
+    (component_id = $component_id:expr, $level:expr) => {
+      ($crate::print_ex!(component_id = $component_id, $level, "\n"))
+    };
+
+    (component_id = $component_id:expr, $level:expr, $($arg:tt)*) => {
+      ($crate::print_ex!(component_id = $component_id, $level, "{}\n", format_args!($($arg)*)))
+    };
+
+    ($level:expr) => {
+      ($crate::print_ex!($level, "\n"))
+    };
+
+    ($level:expr, $($arg:tt)*) => {
+      ($crate::print_ex!($level, "{}\n", format_args!($($arg)*)))
+    };
+}
+
 /// Internal implementation of print macros. This function is an implementation
 /// detail and should never be called directly, but must be public to be useable
 /// by the print! and println! macro
 ///
+/// When the `alloc` feature is disabled in kernel-mode drivers, this formats
+/// without allocating, like [`dbg_print!`](crate::dbg_print).
+///
 /// # Panics
 ///
 /// Panics if an internal null byte is passed in
 #[doc(hidden)]
-pub fn _print(args: core::fmt::Arguments) {
-    let formatted_string = CString::new(alloc::format!("{args}"))
-        .expect("CString should be able to be created from a String.");
+pub fn _print(args: fmt::Arguments) {
+    #[cfg(any(feature = "alloc", driver_model__driver_type = "UMDF"))]
+    {
+        let formatted_string = CString::new(alloc::format!("{args}"))
+            .expect("CString should be able to be created from a String.");
+
+        // SAFETY: `formatted_string` is a valid null terminated string
+        unsafe {
+            #[cfg(any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"))]
+            {
+                wdk_sys::ntddk::DbgPrint(formatted_string.as_ptr());
+            }
+
+            #[cfg(driver_model__driver_type = "UMDF")]
+            {
+                wdk_sys::windows::OutputDebugStringA(formatted_string.as_ptr());
+            }
+        }
+    }
+
+    #[cfg(not(any(feature = "alloc", driver_model__driver_type = "UMDF")))]
+    {
+        _dbg_print(args);
+    }
+}
+
+/// Internal implementation of dbg print macros. This function is an
+/// implementation detail and should never be called directly, but must be
+/// public to be useable by the dbg_print! and dbg_println! macro
+#[doc(hidden)]
+pub fn _dbg_print(args: fmt::Arguments) {
+    let mut buffer = TruncatingBuffer::new();
+    // `TruncatingBuffer` never fails to write, so this can only fail if a `Display`
+    // implementation returns an error. Whatever was formatted up to that point is
+    // still printed.
+    let _ = buffer.write_fmt(args);
+    let formatted_string = buffer.as_null_terminated();
 
-    // SAFETY: `formatted_string` is a valid null terminated string
+    // SAFETY: `formatted_string` is a valid null terminated string, and is passed
+    // as the argument of a `%s` format specifier so that it is never
+    // interpreted as a format string
     unsafe {
         #[cfg(any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"))]
         {
-            wdk_sys::ntddk::DbgPrint(formatted_string.as_ptr());
+            wdk_sys::ntddk::DbgPrint(c"%s".as_ptr(), formatted_string.as_ptr());
         }
 
         #[cfg(driver_model__driver_type = "UMDF")]
         {
-            wdk_sys::windows::OutputDebugStringA(formatted_string.as_ptr());
+            wdk_sys::windows::OutputDebugStringA(formatted_string.as_ptr().cast());
+        }
+    }
+}
+
+/// Internal implementation of leveled print macros. This function is an
+/// implementation detail and should never be called directly, but must be
+/// public to be useable by the print_ex! and println_ex! macros
+#[doc(hidden)]
+pub fn _print_ex(component_id: u32, level: Level, args: fmt::Arguments) {
+    let mut buffer = TruncatingBuffer::new();
+    // `TruncatingBuffer` never fails to write, so this can only fail if a `Display`
+    // implementation returns an error. Whatever was formatted up to that point is
+    // still printed.
+    let _ = buffer.write_fmt(args);
+    let formatted_string = buffer.as_null_terminated();
+
+    #[cfg(any(driver_model__driver_type = "WDM", driver_model__driver_type = "KMDF"))]
+    // SAFETY: `formatted_string` is a valid null terminated string, and is passed
+    // as the argument of a `%s` format specifier so that it is never
+    // interpreted as a format string
+    unsafe {
+        wdk_sys::ntddk::DbgPrintEx(
+            component_id,
+            level as u32,
+            c"%s".as_ptr(),
+            formatted_string.as_ptr(),
+        );
+    }
+
+    #[cfg(driver_model__driver_type = "UMDF")]
+    {
+        // User-mode debug output is not filtered by component ID or level
+        let _ = (component_id, level);
+
+        // SAFETY: `formatted_string` is a valid null terminated string
+        unsafe {
+            wdk_sys::windows::OutputDebugStringA(formatted_string.as_ptr().cast());
+        }
+    }
+}
+
+/// A fixed-size buffer that formatted output is written into, truncating any
+/// output that does not fit
+struct TruncatingBuffer {
+    buffer: [u8; DBG_PRINT_BUFFER_SIZE],
+    len: usize,
+    is_truncated: bool,
+}
+
+impl TruncatingBuffer {
+    const fn new() -> Self {
+        Self {
+            buffer: [0; DBG_PRINT_BUFFER_SIZE],
+            len: 0,
+            is_truncated: false,
+        }
+    }
+
+    /// Returns the contents of the buffer with a null terminator, ending with a
+    /// truncation marker if any output was truncated
+    fn as_null_terminated(&mut self) -> &[u8] {
+        if self.is_truncated {
+            let mut marker_start = self
+                .len
+                .min(DBG_PRINT_BUFFER_SIZE - 1 - TRUNCATION_MARKER.len());
+            // Don't overwrite part of a multi-byte character
+            while marker_start > 0 && (self.buffer[marker_start] & 0xC0) == 0x80 {
+                marker_start -= 1;
+            }
+            self.buffer[marker_start..marker_start + TRUNCATION_MARKER.len()]
+                .copy_from_slice(TRUNCATION_MARKER);
+            self.len = marker_start + TRUNCATION_MARKER.len();
+        }
+        self.buffer[self.len] = 0;
+        &self.buffer[..=self.len]
+    }
+}
+
+impl Write for TruncatingBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // Always leave room for the null terminator
+        let available = DBG_PRINT_BUFFER_SIZE - 1 - self.len;
+
+        let mut end = s.len();
+        if end > available {
+            // Only write complete characters
+            end = available;
+            while !s.is_char_boundary(end) {
+                end -= 1;
+            }
+            self.is_truncated = true;
+        }
+
+        // Interior null bytes would end the message early
+        for (destination, &byte) in self.buffer[self.len..self.len + end]
+            .iter_mut()
+            .zip(&s.as_bytes()[..end])
+        {
+            *destination = if byte == 0 { b' ' } else { byte };
         }
+        self.len += end;
+        Ok(())
     }
 }
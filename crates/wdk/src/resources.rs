@@ -0,0 +1,279 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Hardware resources assigned to a WDF device
+//!
+//! WDF passes the hardware resources of a device to its
+//! `EvtDevicePrepareHardware` callback as raw and translated resource lists.
+//! A [`ResourceList`] wraps one of these lists, and iterates over its
+//! resources as typed [`Resource`]s:
+//!
+//! ```rust, ignore
+//! use wdk::resources::{Connection, Resource, ResourceList};
+//!
+//! // SAFETY: `resources_translated` is the translated resource list passed to
+//! // `EvtDevicePrepareHardware`
+//! let resources = unsafe { ResourceList::from_raw(resources_translated) };
+//! for resource in resources.iter() {
+//!     match resource {
+//!         Resource::Memory { base, len } => map_registers(base, len)?,
+//!         Resource::Interrupt { vector, .. } => trace!("interrupt vector {vector}"),
+//!         Resource::Connection(Connection::I2c { id }) => open_i2c_target(id)?,
+//!         _ => {}
+//!     }
+//! }
+//! ```
+//!
+//! Functions annotated with [`callback`](crate::callback) can also take a
+//! [`ResourceList`] parameter in place of a `WDFCMRESLIST`.
+
+use wdk_sys::{
+    call_unsafe_wdf_function_binding,
+    resources,
+    CM_PARTIAL_RESOURCE_DESCRIPTOR,
+    CM_RESOURCE_CONNECTION_CLASS_GPIO,
+    CM_RESOURCE_CONNECTION_CLASS_SERIAL,
+    CM_RESOURCE_CONNECTION_TYPE_SERIAL_I2C,
+    CM_RESOURCE_CONNECTION_TYPE_SERIAL_SPI,
+    CM_RESOURCE_CONNECTION_TYPE_SERIAL_UART,
+    KAFFINITY,
+    ULONG,
+    WDFCMRESLIST,
+};
+
+use crate::wdf::FromRawCallbackArg;
+
+/// Resource list of a WDF device, such as the raw or translated resources
+/// passed to `EvtDevicePrepareHardware`
+#[derive(Clone, Copy, Debug)]
+pub struct ResourceList {
+    wdf_cm_res_list: WDFCMRESLIST,
+}
+
+impl ResourceList {
+    /// Creates a [`ResourceList`] from a raw WDF resource list handle
+    ///
+    /// # Safety
+    ///
+    /// `wdf_cm_res_list` must be a valid resource list (ex. one passed to
+    /// `EvtDevicePrepareHardware`), and the returned [`ResourceList`] must not
+    /// be used after WDF deletes it (ex. after `EvtDevicePrepareHardware`
+    /// returns)
+    #[must_use]
+    pub const unsafe fn from_raw(wdf_cm_res_list: WDFCMRESLIST) -> Self {
+        Self { wdf_cm_res_list }
+    }
+
+    /// Get the raw `WDFCMRESLIST` handle of the resource list
+    #[must_use]
+    pub const fn as_raw(&self) -> WDFCMRESLIST {
+        self.wdf_cm_res_list
+    }
+
+    /// Returns the number of resources in the list
+    #[must_use]
+    pub fn len(&self) -> usize {
+        let count: ULONG;
+        // SAFETY: `wdf_cm_res_list` is a private member of `ResourceList`, which is a
+        // valid resource list as guaranteed by the caller of `from_raw`
+        unsafe {
+            count =
+                call_unsafe_wdf_function_binding!(WdfCmResourceListGetCount, self.wdf_cm_res_list);
+        }
+        count as usize
+    }
+
+    /// Returns whether the list has no resources
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the descriptor of the resource at `index`, or `None` if there
+    /// is no such resource
+    #[must_use]
+    pub fn descriptor(&self, index: usize) -> Option<&CM_PARTIAL_RESOURCE_DESCRIPTOR> {
+        let index = ULONG::try_from(index).ok()?;
+
+        let descriptor;
+        // SAFETY: `wdf_cm_res_list` is a private member of `ResourceList`, which is a
+        // valid resource list as guaranteed by the caller of `from_raw`
+        unsafe {
+            descriptor = call_unsafe_wdf_function_binding!(
+                WdfCmResourceListGetDescriptor,
+                self.wdf_cm_res_list,
+                index,
+            );
+        }
+        // SAFETY: WDF returns either null or a pointer to a descriptor owned by the
+        // resource list, which outlives `self` as guaranteed by the caller of
+        // `from_raw`
+        unsafe { descriptor.as_ref() }
+    }
+
+    /// Returns the resource at `index`, or `None` if there is no such
+    /// resource
+    #[must_use]
+    pub fn get(&self, index: usize) -> Option<Resource<'_>> {
+        self.descriptor(index).map(Resource::from_descriptor)
+    }
+
+    /// Returns an iterator over the resources in the list
+    pub fn iter(&self) -> impl Iterator<Item = Resource<'_>> + '_ {
+        (0..self.len()).filter_map(|index| self.get(index))
+    }
+}
+
+impl FromRawCallbackArg<WDFCMRESLIST> for ResourceList {
+    unsafe fn from_raw_callback_arg(raw: WDFCMRESLIST) -> Self {
+        // SAFETY: The caller guarantees that the resource list is passed to the
+        // callback, so it is valid for the duration of the callback
+        unsafe { Self::from_raw(raw) }
+    }
+}
+
+/// Hardware resource of a WDF device
+#[derive(Clone, Copy)]
+pub enum Resource<'a> {
+    /// Range of memory-mapped addresses (`CmResourceTypeMemory` or
+    /// `CmResourceTypeMemoryLarge`)
+    Memory {
+        /// Starting physical address of the range
+        base: i64,
+        /// Length of the range in bytes
+        len: u64,
+    },
+    /// Range of I/O port addresses (`CmResourceTypePort`)
+    Port {
+        /// Starting address of the range
+        base: i64,
+        /// Length of the range in bytes
+        len: u64,
+    },
+    /// Interrupt (`CmResourceTypeInterrupt`). The level is only meaningful
+    /// for resources from the translated resource list.
+    Interrupt {
+        /// Interrupt level (IRQL for translated resources)
+        level: u32,
+        /// Interrupt vector
+        vector: u32,
+        /// Processors that the interrupt can be delivered to
+        affinity: KAFFINITY,
+        /// Whether the interrupt is latched (edge-triggered), rather than
+        /// level-triggered
+        is_latched: bool,
+        /// Whether the interrupt is message-signaled (MSI or MSI-X)
+        is_message_signaled: bool,
+    },
+    /// Connection to a device on another bus (`CmResourceTypeConnection`)
+    Connection(Connection),
+    /// Resource of any other type, whose descriptor can be read directly
+    Other(&'a CM_PARTIAL_RESOURCE_DESCRIPTOR),
+}
+
+impl<'a> Resource<'a> {
+    /// Creates a [`Resource`] from its resource descriptor
+    #[must_use]
+    pub fn from_descriptor(descriptor: &'a CM_PARTIAL_RESOURCE_DESCRIPTOR) -> Self {
+        resources::memory(descriptor)
+            .map(|memory| Self::Memory {
+                base: memory.start,
+                len: memory.length,
+            })
+            .or_else(|| {
+                resources::port(descriptor).map(|port| Self::Port {
+                    base: port.start,
+                    len: port.length,
+                })
+            })
+            .or_else(|| {
+                resources::interrupt(descriptor).map(|interrupt| Self::Interrupt {
+                    level: interrupt.level,
+                    vector: interrupt.vector,
+                    affinity: interrupt.affinity,
+                    is_latched: interrupt.is_latched,
+                    is_message_signaled: interrupt.is_message_signaled,
+                })
+            })
+            .or_else(|| {
+                resources::connection(descriptor)
+                    .map(|connection| Self::Connection(Connection::from(connection)))
+            })
+            .unwrap_or(Self::Other(descriptor))
+    }
+}
+
+/// Connection resource of a WDF device, whose connection ID is opened through
+/// the resource hub
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Connection {
+    /// Connection to a peripheral on an I2C bus
+    I2c {
+        /// Connection ID of the peripheral
+        id: u64,
+    },
+    /// Connection to a peripheral on an SPI bus
+    Spi {
+        /// Connection ID of the peripheral
+        id: u64,
+    },
+    /// Connection to a peripheral on a UART
+    Uart {
+        /// Connection ID of the peripheral
+        id: u64,
+    },
+    /// Connection to one or more GPIO pins
+    Gpio {
+        /// Connection ID of the GPIO pins
+        id: u64,
+    },
+    /// Connection of any other class or type
+    Other {
+        /// Class of the connection
+        class: u8,
+        /// Type of the connection within its class
+        connection_type: u8,
+        /// Connection ID
+        id: u64,
+    },
+}
+
+impl Connection {
+    /// Returns the raw 64-bit connection ID
+    #[must_use]
+    pub const fn id(&self) -> u64 {
+        match *self {
+            Self::I2c { id }
+            | Self::Spi { id }
+            | Self::Uart { id }
+            | Self::Gpio { id }
+            | Self::Other { id, .. } => id,
+        }
+    }
+}
+
+impl From<resources::Connection> for Connection {
+    fn from(connection: resources::Connection) -> Self {
+        let id = connection.id;
+        match (
+            ULONG::from(connection.class),
+            ULONG::from(connection.connection_type),
+        ) {
+            (CM_RESOURCE_CONNECTION_CLASS_SERIAL, CM_RESOURCE_CONNECTION_TYPE_SERIAL_I2C) => {
+                Self::I2c { id }
+            }
+            (CM_RESOURCE_CONNECTION_CLASS_SERIAL, CM_RESOURCE_CONNECTION_TYPE_SERIAL_SPI) => {
+                Self::Spi { id }
+            }
+            (CM_RESOURCE_CONNECTION_CLASS_SERIAL, CM_RESOURCE_CONNECTION_TYPE_SERIAL_UART) => {
+                Self::Uart { id }
+            }
+            (CM_RESOURCE_CONNECTION_CLASS_GPIO, _) => Self::Gpio { id },
+            _ => Self::Other {
+                class: connection.class,
+                connection_type: connection.connection_type,
+                id,
+            },
+        }
+    }
+}
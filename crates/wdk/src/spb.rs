@@ -0,0 +1,403 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Communication with peripherals on a simple peripheral bus (SPB), such as
+//! I2C and SPI
+//!
+//! A peripheral driver receives the connection ID of its SPB connection in a
+//! `CmResourceTypeConnection` resource of its translated resource list.
+//! [`ConnectionId::from_resource_descriptor`] extracts the connection ID and
+//! the type of bus from the resource descriptor, and [`SpbTarget::open`] opens
+//! an I/O target to the peripheral through the resource hub.
+//!
+//! ```rust, ignore
+//! use wdk::spb::{ConnectionId, SpbTarget};
+//!
+//! let connection_id = ConnectionId::from_resource_descriptor(&*descriptor)
+//!     .expect("descriptor should be a serial connection resource");
+//! let spb_target = SpbTarget::open(device, connection_id)?;
+//!
+//! let mut value = [0; 2];
+//! spb_target.write_read(&[REGISTER_ADDRESS], &mut value)?;
+//! ```
+
+use core::{mem::size_of, ptr};
+
+use wdk_sys::{
+    call_unsafe_wdf_function_binding,
+    resources,
+    spb::IOCTL_SPB_EXECUTE_SEQUENCE,
+    _WDF_IO_TARGET_OPEN_TYPE,
+    _WDF_MEMORY_DESCRIPTOR_TYPE,
+    CM_PARTIAL_RESOURCE_DESCRIPTOR,
+    CM_RESOURCE_CONNECTION_CLASS_SERIAL,
+    CM_RESOURCE_CONNECTION_TYPE_SERIAL_I2C,
+    CM_RESOURCE_CONNECTION_TYPE_SERIAL_SPI,
+    FILE_ATTRIBUTE_NORMAL,
+    FILE_NON_DIRECTORY_FILE,
+    FILE_OPEN,
+    GENERIC_READ,
+    GENERIC_WRITE,
+    NTSTATUS,
+    PVOID,
+    SPB_TRANSFER_BUFFER_FORMAT,
+    SPB_TRANSFER_BUFFER_LIST_ENTRY,
+    SPB_TRANSFER_DIRECTION,
+    SPB_TRANSFER_LIST,
+    SPB_TRANSFER_LIST_ENTRY,
+    STATUS_INVALID_PARAMETER,
+    ULONG,
+    ULONG_PTR,
+    UNICODE_STRING,
+    WDFDEVICE,
+    WDFIOTARGET,
+    WDF_IO_TARGET_OPEN_PARAMS,
+    WDF_MEMORY_DESCRIPTOR,
+};
+
+use crate::nt_success;
+
+/// Prefix of the path of a connection in the resource hub
+/// (`RESOURCE_HUB_DEVICE_NAME_NT` in `reshub.h`, followed by a separator)
+const RESOURCE_HUB_PATH_PREFIX: &str = "\\Device\\RESOURCE_HUB\\";
+
+/// Number of hexadecimal digits of a connection ID in a resource hub path
+const CONNECTION_ID_DIGITS: usize = 16;
+
+/// Length, in UTF-16 code units, of the path of a connection in the resource
+/// hub
+const RESOURCE_HUB_PATH_LENGTH: usize = RESOURCE_HUB_PATH_PREFIX.len() + CONNECTION_ID_DIGITS;
+
+/// Size, in bytes, of the path of a connection in the resource hub
+// clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+#[allow(clippy::cast_possible_truncation)]
+const RESOURCE_HUB_PATH_SIZE: u16 = (RESOURCE_HUB_PATH_LENGTH * size_of::<u16>()) as u16;
+
+/// Type of bus that a [`ConnectionId`] connects to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionType {
+    /// I2C bus
+    I2c,
+    /// SPI bus
+    Spi,
+}
+
+/// ID of a connection to a peripheral on a simple peripheral bus, assigned by
+/// the resource hub
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConnectionId {
+    id: u64,
+    connection_type: ConnectionType,
+}
+
+impl ConnectionId {
+    /// Extracts the [`ConnectionId`] from a translated
+    /// `CmResourceTypeConnection` resource descriptor.
+    ///
+    /// Returns `None` if `descriptor` is not a connection to an I2C or SPI bus.
+    #[must_use]
+    pub fn from_resource_descriptor(descriptor: &CM_PARTIAL_RESOURCE_DESCRIPTOR) -> Option<Self> {
+        let connection = resources::connection(descriptor)?;
+        if ULONG::from(connection.class) != CM_RESOURCE_CONNECTION_CLASS_SERIAL {
+            return None;
+        }
+
+        let connection_type = match ULONG::from(connection.connection_type) {
+            CM_RESOURCE_CONNECTION_TYPE_SERIAL_I2C => ConnectionType::I2c,
+            CM_RESOURCE_CONNECTION_TYPE_SERIAL_SPI => ConnectionType::Spi,
+            _ => return None,
+        };
+
+        Some(Self {
+            id: connection.id,
+            connection_type,
+        })
+    }
+
+    /// Returns the raw 64-bit connection ID
+    #[must_use]
+    pub const fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the type of bus that the connection is on
+    #[must_use]
+    pub const fn connection_type(&self) -> ConnectionType {
+        self.connection_type
+    }
+
+    /// Writes the path of the connection in the resource hub (ex.
+    /// `\Device\RESOURCE_HUB\0000000000000001`) to `buffer`, like
+    /// `RESOURCE_HUB_CREATE_PATH_FROM_ID`
+    fn write_resource_hub_path(&self, buffer: &mut [u16; RESOURCE_HUB_PATH_LENGTH]) {
+        let (prefix, digits) = buffer.split_at_mut(RESOURCE_HUB_PATH_PREFIX.len());
+        for (destination, byte) in prefix.iter_mut().zip(RESOURCE_HUB_PATH_PREFIX.bytes()) {
+            *destination = u16::from(byte);
+        }
+
+        for (index, destination) in digits.iter_mut().enumerate() {
+            let shift = (CONNECTION_ID_DIGITS - 1 - index) * 4;
+            // A hexadecimal digit always fits in a `usize`
+            #[allow(clippy::cast_possible_truncation)]
+            let digit = ((self.id >> shift) & 0xF) as usize;
+            *destination = u16::from(b"0123456789abcdef"[digit]);
+        }
+    }
+}
+
+/// WDF I/O target to a peripheral on a simple peripheral bus (SPB).
+///
+/// The I/O target is parented to the device it is opened for, and is deleted
+/// by WDF when the device is deleted.
+pub struct SpbTarget {
+    io_target: WDFIOTARGET,
+}
+
+impl SpbTarget {
+    /// Try to open an I/O target to the peripheral identified by
+    /// `connection_id`, for reading and writing.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`, typically from the
+    /// `EvtDevicePrepareHardware` callback.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if WDF fails to create or open the I/O target. The error variant will contain a [`NTSTATUS`] of the failure. Full error documentation is available in the [WdfIoTargetOpen Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfiotarget/nf-wdfiotarget-wdfiotargetopen#return-value)
+    pub fn open(device: WDFDEVICE, connection_id: ConnectionId) -> Result<Self, NTSTATUS> {
+        let mut spb_target = Self {
+            io_target: ptr::null_mut(),
+        };
+
+        let mut nt_status;
+        // SAFETY: `device` is a handle to a WDF device object. The resulting ffi object
+        // is stored in a private member and not accessible outside of this module.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfIoTargetCreate,
+                device,
+                ptr::null_mut(),
+                &mut spb_target.io_target,
+            );
+        }
+        if !nt_success(nt_status) {
+            return Err(nt_status);
+        }
+
+        let mut path = [0; RESOURCE_HUB_PATH_LENGTH];
+        connection_id.write_resource_hub_path(&mut path);
+
+        // Equivalent to `WDF_IO_TARGET_OPEN_PARAMS_INIT_OPEN_BY_NAME`
+        // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+        #[allow(clippy::cast_possible_truncation)]
+        let mut open_params = WDF_IO_TARGET_OPEN_PARAMS {
+            Size: size_of::<WDF_IO_TARGET_OPEN_PARAMS>() as ULONG,
+            Type: _WDF_IO_TARGET_OPEN_TYPE::WdfIoTargetOpenByName,
+            TargetDeviceName: UNICODE_STRING {
+                Length: RESOURCE_HUB_PATH_SIZE,
+                MaximumLength: RESOURCE_HUB_PATH_SIZE,
+                Buffer: path.as_mut_ptr(),
+            },
+            DesiredAccess: GENERIC_READ | GENERIC_WRITE,
+            CreateOptions: FILE_NON_DIRECTORY_FILE,
+            CreateDisposition: FILE_OPEN,
+            FileAttributes: FILE_ATTRIBUTE_NORMAL,
+            ..WDF_IO_TARGET_OPEN_PARAMS::default()
+        };
+
+        // SAFETY: `io_target` was successfully created above, and `path`, which
+        // `open_params` points to, outlives the call
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfIoTargetOpen,
+                spb_target.io_target,
+                &mut open_params,
+            );
+        }
+        if !nt_success(nt_status) {
+            // SAFETY: `io_target` was successfully created above, and is not used after
+            // it is deleted
+            unsafe {
+                call_unsafe_wdf_function_binding!(WdfObjectDelete, spb_target.io_target.cast());
+            }
+            return Err(nt_status);
+        }
+        Ok(spb_target)
+    }
+
+    /// Write `buffer` to the peripheral, and return the number of bytes
+    /// written.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_INVALID_PARAMETER` if `buffer` is larger than `ULONG::MAX` bytes. Otherwise, it will return an error if the write request fails. Full error documentation is available in the [WdfIoTargetSendWriteSynchronously Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfiotarget/nf-wdfiotarget-wdfiotargetsendwritesynchronously#return-value)
+    pub fn write(&self, buffer: &[u8]) -> Result<usize, NTSTATUS> {
+        let mut memory_descriptor =
+            memory_descriptor(buffer.as_ptr().cast_mut().cast(), buffer.len())?;
+        let mut bytes_written: ULONG_PTR = 0;
+
+        let nt_status;
+        // SAFETY: `io_target` is a private member of `SpbTarget`, which is always a
+        // valid, opened I/O target. The request is sent synchronously, so `buffer`
+        // outlives it, and is only read from.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfIoTargetSendWriteSynchronously,
+                self.io_target,
+                ptr::null_mut(),
+                &mut memory_descriptor,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut bytes_written,
+            );
+        }
+        nt_success(nt_status)
+            .then(|| bytes_transferred(bytes_written))
+            .ok_or(nt_status)
+    }
+
+    /// Read from the peripheral into `buffer`, and return the number of bytes
+    /// read.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_INVALID_PARAMETER` if `buffer` is larger than `ULONG::MAX` bytes. Otherwise, it will return an error if the read request fails. Full error documentation is available in the [WdfIoTargetSendReadSynchronously Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfiotarget/nf-wdfiotarget-wdfiotargetsendreadsynchronously#return-value)
+    pub fn read(&self, buffer: &mut [u8]) -> Result<usize, NTSTATUS> {
+        let mut memory_descriptor = memory_descriptor(buffer.as_mut_ptr().cast(), buffer.len())?;
+        let mut bytes_read: ULONG_PTR = 0;
+
+        let nt_status;
+        // SAFETY: `io_target` is a private member of `SpbTarget`, which is always a
+        // valid, opened I/O target. The request is sent synchronously, so `buffer`
+        // outlives it.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfIoTargetSendReadSynchronously,
+                self.io_target,
+                ptr::null_mut(),
+                &mut memory_descriptor,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut bytes_read,
+            );
+        }
+        nt_success(nt_status)
+            .then(|| bytes_transferred(bytes_read))
+            .ok_or(nt_status)
+    }
+
+    /// Write `write_buffer` to the peripheral, then read from the peripheral
+    /// into `read_buffer`, as a single sequence (ex. an I2C write of a register
+    /// address followed by a repeated start and a read of the register).
+    ///
+    /// Returns the total number of bytes transferred, including the bytes
+    /// written.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_INVALID_PARAMETER` if either buffer is larger than `ULONG::MAX` bytes. Otherwise, it will return an error if the `IOCTL_SPB_EXECUTE_SEQUENCE` request fails. Full error documentation is available in the [IOCTL_SPB_EXECUTE_SEQUENCE Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/spb/spb-ioctls#ioctl_spb_execute_sequence-control-code)
+    pub fn write_read(
+        &self,
+        write_buffer: &[u8],
+        read_buffer: &mut [u8],
+    ) -> Result<usize, NTSTATUS> {
+        // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+        #[allow(clippy::cast_possible_truncation)]
+        let mut sequence = WriteReadSequence {
+            list: SPB_TRANSFER_LIST {
+                Size: size_of::<SPB_TRANSFER_LIST>() as ULONG,
+                TransferCount: 2,
+                Transfers: [transfer_list_entry(
+                    SPB_TRANSFER_DIRECTION::SpbTransferDirectionToDevice,
+                    write_buffer.as_ptr().cast_mut().cast(),
+                    write_buffer.len(),
+                )?],
+                ..SPB_TRANSFER_LIST::default()
+            },
+            read_transfer: transfer_list_entry(
+                SPB_TRANSFER_DIRECTION::SpbTransferDirectionFromDevice,
+                read_buffer.as_mut_ptr().cast(),
+                read_buffer.len(),
+            )?,
+        };
+
+        let mut memory_descriptor = memory_descriptor(
+            ptr::addr_of_mut!(sequence).cast(),
+            size_of::<WriteReadSequence>(),
+        )?;
+        let mut bytes_transferred_count: ULONG_PTR = 0;
+
+        let nt_status;
+        // SAFETY: `io_target` is a private member of `SpbTarget`, which is always a
+        // valid, opened I/O target. The request is sent synchronously, so `sequence`,
+        // and the buffers it points to, outlive it. `write_buffer` is only read from.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfIoTargetSendIoctlSynchronously,
+                self.io_target,
+                ptr::null_mut(),
+                IOCTL_SPB_EXECUTE_SEQUENCE,
+                &mut memory_descriptor,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut bytes_transferred_count,
+            );
+        }
+        nt_success(nt_status)
+            .then(|| bytes_transferred(bytes_transferred_count))
+            .ok_or(nt_status)
+    }
+}
+
+/// An [`SPB_TRANSFER_LIST`] of a write transfer followed by a read transfer,
+/// like `SPB_TRANSFER_LIST_AND_ENTRIES(2)` in `spb.h`
+#[repr(C)]
+struct WriteReadSequence {
+    list: SPB_TRANSFER_LIST,
+    read_transfer: SPB_TRANSFER_LIST_ENTRY,
+}
+
+/// Creates an [`SPB_TRANSFER_LIST_ENTRY`] for a transfer of `length` bytes
+/// from or to `buffer`, like `SPB_TRANSFER_LIST_ENTRY_INIT_SIMPLE`
+fn transfer_list_entry(
+    direction: SPB_TRANSFER_DIRECTION::Type,
+    buffer: PVOID,
+    length: usize,
+) -> Result<SPB_TRANSFER_LIST_ENTRY, NTSTATUS> {
+    let mut entry = SPB_TRANSFER_LIST_ENTRY {
+        Direction: direction,
+        ..SPB_TRANSFER_LIST_ENTRY::default()
+    };
+    entry.Buffer.Format = SPB_TRANSFER_BUFFER_FORMAT::SpbTransferBufferFormatSimple;
+    entry.Buffer.__bindgen_anon_1.Simple = SPB_TRANSFER_BUFFER_LIST_ENTRY {
+        Buffer: buffer,
+        BufferCb: ULONG::try_from(length).map_err(|_| STATUS_INVALID_PARAMETER)?,
+    };
+    Ok(entry)
+}
+
+/// Creates a [`WDF_MEMORY_DESCRIPTOR`] describing `length` bytes at `buffer`,
+/// like `WDF_MEMORY_DESCRIPTOR_INIT_BUFFER`
+fn memory_descriptor(buffer: PVOID, length: usize) -> Result<WDF_MEMORY_DESCRIPTOR, NTSTATUS> {
+    let mut memory_descriptor = WDF_MEMORY_DESCRIPTOR {
+        Type: _WDF_MEMORY_DESCRIPTOR_TYPE::WdfMemoryDescriptorTypeBuffer,
+        ..WDF_MEMORY_DESCRIPTOR::default()
+    };
+    memory_descriptor.u.BufferType.Buffer = buffer;
+    memory_descriptor.u.BufferType.Length =
+        ULONG::try_from(length).map_err(|_| STATUS_INVALID_PARAMETER)?;
+    Ok(memory_descriptor)
+}
+
+/// Converts the number of bytes transferred by a request into a `usize`
+const fn bytes_transferred(bytes: ULONG_PTR) -> usize {
+    // `ULONG_PTR` is always the size of a pointer
+    #[allow(clippy::cast_possible_truncation)]
+    let bytes = bytes as usize;
+    bytes
+}
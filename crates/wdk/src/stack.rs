@@ -0,0 +1,120 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Kernel stack usage instrumentation
+//!
+//! Kernel stacks are small, so deep recursion or large stack frames can easily
+//! exhaust them and crash the system. Functions annotated with
+//! [`#[max_stack(bytes)]`](crate::max_stack) declare the maximum size of their
+//! stack frame:
+//!
+//! ```rust, ignore
+//! #[wdk::max_stack(512)]
+//! extern "C" fn dispatch_read(device: PDEVICE_OBJECT, irp: PIRP) -> NTSTATUS {
+//!     // ...
+//! }
+//! ```
+//!
+//! The declared limits are enforced after the driver is linked by the
+//! `validate-stack-frame-limits` task in `rust-driver-makefile.toml`, which
+//! fails the build if any annotated function's stack frame is larger than its
+//! limit. When the `stack-instrumentation` feature is enabled, the remaining
+//! stack is also checked every time an annotated function is entered, and a
+//! message is printed to the kernel debugger if less than
+//! [`LOW_STACK_THRESHOLD_BYTES`] would remain after its stack frame is
+//! allocated.
+
+use wdk_sys::ntddk::{DbgPrint, IoGetRemainingStackSize};
+
+/// The minimum amount of stack, in bytes, that should remain after the stack
+/// frame of a function annotated with [`#[max_stack(bytes)]`](crate::max_stack)
+/// is allocated. When the `stack-instrumentation` feature is enabled, entering
+/// an annotated function with less stack than this remaining is reported to
+/// the kernel debugger.
+pub const LOW_STACK_THRESHOLD_BYTES: usize = 4096;
+
+/// A stack frame limit recorded by [`#[max_stack(bytes)]`](crate::max_stack)
+/// in the driver binary.
+///
+/// This must stay in sync with the record format parsed by
+/// `wdk_build::stack_frame_limits`.
+#[repr(C)]
+#[derive(Debug)]
+#[allow(
+    dead_code,
+    reason = "the fields are only read from the driver binary after it is linked"
+)]
+pub struct StackFrameLimit {
+    function: *const (),
+    max_frame_size: usize,
+}
+
+// SAFETY: `StackFrameLimit` is only ever created in immutable statics, and its
+// function pointer is never dereferenced
+unsafe impl Sync for StackFrameLimit {}
+
+impl StackFrameLimit {
+    /// Create a [`StackFrameLimit`] declaring that the stack frame of
+    /// `function` is at most `max_frame_size` bytes
+    #[must_use]
+    pub const fn new(function: *const (), max_frame_size: usize) -> Self {
+        Self {
+            function,
+            max_frame_size,
+        }
+    }
+}
+
+/// Returns the amount of stack, in bytes, remaining for the current thread.
+///
+/// This is a wrapper around [`IoGetRemainingStackSize`](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/ntddk/nf-ntddk-iogetremainingstacksize)
+#[must_use]
+#[allow(
+    clippy::cast_possible_truncation,
+    reason = "ULONG_PTR is always the same size as usize"
+)]
+pub fn remaining_stack_size() -> usize {
+    // SAFETY: `IoGetRemainingStackSize` has no preconditions, and can be called at
+    // any IRQL
+    let remaining_stack_size = unsafe { IoGetRemainingStackSize() };
+
+    remaining_stack_size as usize
+}
+
+/// Checks that the current thread has enough stack remaining to enter the
+/// function named `function_name`, whose stack frame is at most
+/// `max_frame_size` bytes.
+///
+/// If the `stack-instrumentation` feature is enabled and less than
+/// [`LOW_STACK_THRESHOLD_BYTES`] would remain after the function's stack frame
+/// is allocated, a message is printed to the kernel debugger. Otherwise, this
+/// does nothing. This is called at the entry of every function annotated with
+/// [`#[max_stack(bytes)]`](crate::max_stack).
+#[inline]
+pub fn check_remaining_stack(function_name: &str, max_frame_size: usize) {
+    if !cfg!(feature = "stack-instrumentation") {
+        return;
+    }
+
+    let remaining_stack_size = remaining_stack_size();
+    if remaining_stack_size < max_frame_size.saturating_add(LOW_STACK_THRESHOLD_BYTES) {
+        // DbgPrint's `%.*s` takes the length as an `int`. Function names are always far
+        // shorter than `i32::MAX`
+        let function_name_length = i32::try_from(function_name.len()).unwrap_or(i32::MAX);
+
+        // SAFETY: The format string is a valid null-terminated string, and its
+        // arguments match its format specifiers: `%.*s` reads at most
+        // `function_name_length` bytes from `function_name`, and `%Iu` reads
+        // `usize`s
+        unsafe {
+            DbgPrint(
+                c"wdk: low stack entering %.*s: %Iu bytes remaining, stack frame is up to %Iu bytes\n"
+                    .as_ptr(),
+                function_name_length,
+                function_name.as_ptr(),
+                remaining_stack_size,
+                max_frame_size,
+            );
+        }
+    }
+}
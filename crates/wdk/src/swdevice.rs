@@ -0,0 +1,358 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Safe wrappers for creating software devices via [`SwDeviceCreate`]
+//!
+//! These are intended to be used from user-mode components of a driver package
+//! (ex. a companion service that enumerates a software device for the driver to
+//! load on). A [`SoftwareDevice`] is removed when it is dropped, unless its
+//! lifetime is extended via [`SoftwareDevice::set_lifetime`].
+//!
+//! ```rust, no_run
+//! use wdk::swdevice::{SoftwareDevice, SoftwareDeviceCreateInfo, SoftwareDeviceLifetime};
+//!
+//! fn to_wide(s: &str) -> Vec<u16> {
+//!     s.encode_utf16().chain(std::iter::once(0)).collect()
+//! }
+//!
+//! # fn create() -> Result<(), wdk_sys::HRESULT> {
+//! let enumerator_name = to_wide("SampleEnumerator");
+//! let parent_device_instance = to_wide(r"HTREE\ROOT\0");
+//! let instance_id = to_wide("SampleInstance");
+//! // Multi-strings are terminated by an additional null character
+//! let hardware_ids = to_wide("SampleHardwareId\0");
+//! let description = to_wide("Sample Software Device");
+//!
+//! let software_device = SoftwareDevice::create(
+//!     &enumerator_name,
+//!     &parent_device_instance,
+//!     &SoftwareDeviceCreateInfo {
+//!         description: Some(&description),
+//!         ..SoftwareDeviceCreateInfo::new(&instance_id, &hardware_ids)
+//!     },
+//! )?;
+//!
+//! // Keep the device present after the companion service exits
+//! software_device.set_lifetime(SoftwareDeviceLifetime::ParentPresent)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use core::{mem::size_of, ptr};
+
+use wdk_sys::{
+    windows::{
+        CloseHandle,
+        CreateEventW,
+        GetLastError,
+        SetEvent,
+        SwDeviceClose,
+        SwDeviceCreate,
+        SwDeviceSetLifetime,
+        WaitForSingleObject,
+    },
+    _SW_DEVICE_LIFETIME::{SWDeviceLifetimeHandle, SWDeviceLifetimeParentPresent},
+    GUID,
+    HANDLE,
+    HRESULT,
+    HSWDEVICE,
+    INFINITE,
+    PCWSTR,
+    PVOID,
+    SW_DEVICE_CREATE_INFO,
+    SW_DEVICE_LIFETIME,
+    ULONG,
+};
+
+/// Maximum length of a device instance ID, including the null terminator
+/// (`MAX_DEVICE_ID_LEN` in `cfgmgr32.h`)
+const MAX_DEVICE_ID_LEN: usize = 200;
+
+/// Return value of [`WaitForSingleObject`] when the object is signaled
+const WAIT_OBJECT_0: u32 = 0;
+
+// clippy::cast_possible_wrap cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+#[allow(clippy::cast_possible_wrap)]
+const E_INVALIDARG: HRESULT = 0x8007_0057_u32 as HRESULT;
+
+/// Lifetime of a [`SoftwareDevice`]. Full documentation is available in the
+/// [`SW_DEVICE_LIFETIME` documentation](https://learn.microsoft.com/en-us/windows/win32/api/swdevicedef/ne-swdevicedef-sw_device_lifetime)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoftwareDeviceLifetime {
+    /// The device is removed when its [`SoftwareDevice`] is dropped, or when
+    /// the process that created it exits
+    Handle,
+    /// The device remains present as long as its parent device is present
+    ParentPresent,
+}
+
+/// Information used to describe a software device when it is created. This is
+/// a safe equivalent of [`SW_DEVICE_CREATE_INFO`].
+///
+/// All strings are UTF-16 and must be null terminated. Multi-strings (ex.
+/// `hardware_ids`) must be terminated by an additional null character.
+#[derive(Debug, Clone, Copy)]
+pub struct SoftwareDeviceCreateInfo<'a> {
+    /// Instance ID of the device. This must be unique among all software
+    /// devices created by the same enumerator
+    pub instance_id: &'a [u16],
+    /// Multi-string of hardware IDs of the device
+    pub hardware_ids: &'a [u16],
+    /// Multi-string of compatible IDs of the device
+    pub compatible_ids: Option<&'a [u16]>,
+    /// Container ID of the device
+    pub container_id: Option<&'a GUID>,
+    /// Combination of `SW_DEVICE_CAPABILITIES` flags
+    pub capability_flags: ULONG,
+    /// Description of the device
+    pub description: Option<&'a [u16]>,
+    /// Location of the device
+    pub location: Option<&'a [u16]>,
+}
+
+/// A software device created via [`SwDeviceCreate`]
+///
+/// The device is closed (and removed, depending on its
+/// [`SoftwareDeviceLifetime`]) when this is dropped.
+#[derive(Debug)]
+pub struct SoftwareDevice {
+    handle: HSWDEVICE,
+    device_instance_id: [u16; MAX_DEVICE_ID_LEN],
+    device_instance_id_len: usize,
+}
+
+struct CreationContext {
+    completion_event: HANDLE,
+    create_result: HRESULT,
+    device_instance_id: [u16; MAX_DEVICE_ID_LEN],
+    device_instance_id_len: usize,
+}
+
+impl<'a> SoftwareDeviceCreateInfo<'a> {
+    /// Creates a [`SoftwareDeviceCreateInfo`] with the required fields, and
+    /// all other fields unset
+    #[must_use]
+    pub const fn new(instance_id: &'a [u16], hardware_ids: &'a [u16]) -> Self {
+        Self {
+            instance_id,
+            hardware_ids,
+            compatible_ids: None,
+            container_id: None,
+            capability_flags: 0,
+            description: None,
+            location: None,
+        }
+    }
+
+    fn to_raw(self) -> Result<SW_DEVICE_CREATE_INFO, HRESULT> {
+        // `SW_DEVICE_CREATE_INFO` is a small fixed-size struct, so its size always fits
+        // in a ULONG
+        #[allow(clippy::cast_possible_truncation)]
+        let create_info_size = size_of::<SW_DEVICE_CREATE_INFO>() as ULONG;
+
+        Ok(SW_DEVICE_CREATE_INFO {
+            cbSize: create_info_size,
+            pszInstanceId: as_wide_str_ptr(self.instance_id)?,
+            pszzHardwareIds: as_wide_multi_str_ptr(self.hardware_ids)?,
+            pszzCompatibleIds: self
+                .compatible_ids
+                .map_or(Ok(ptr::null()), as_wide_multi_str_ptr)?,
+            pContainerId: self.container_id.map_or(ptr::null(), ptr::from_ref),
+            CapabilityFlags: self.capability_flags,
+            pszDeviceDescription: self.description.map_or(Ok(ptr::null()), as_wide_str_ptr)?,
+            pszDeviceLocation: self.location.map_or(Ok(ptr::null()), as_wide_str_ptr)?,
+            pSecurityDescriptor: ptr::null(),
+        })
+    }
+}
+
+impl SoftwareDevice {
+    /// Create a software device enumerated by `enumerator_name`, as a child of
+    /// the device with the `parent_device_instance` instance ID. This blocks
+    /// until PnP has finished creating the device.
+    ///
+    /// All strings are UTF-16 and must be null terminated.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if any of the strings are not
+    /// null terminated, or if the device fails to be created. The error
+    /// variant will contain a [`HRESULT`] of the failure. Full error
+    /// documentation is available in the [`SwDeviceCreate` documentation](https://learn.microsoft.com/en-us/windows/win32/api/swdevice/nf-swdevice-swdevicecreate#return-value)
+    pub fn create(
+        enumerator_name: &[u16],
+        parent_device_instance: &[u16],
+        create_info: &SoftwareDeviceCreateInfo,
+    ) -> Result<Self, HRESULT> {
+        let enumerator_name = as_wide_str_ptr(enumerator_name)?;
+        let parent_device_instance = as_wide_str_ptr(parent_device_instance)?;
+        let create_info = create_info.to_raw()?;
+
+        // SAFETY: All parameters are either null or valid pointers, as required by
+        // `CreateEventW`
+        let completion_event = unsafe { CreateEventW(ptr::null_mut(), 0, 0, ptr::null()) };
+        if completion_event.is_null() {
+            return Err(last_error_hresult());
+        }
+
+        let mut context = CreationContext {
+            completion_event,
+            create_result: 0,
+            device_instance_id: [0; MAX_DEVICE_ID_LEN],
+            device_instance_id_len: 0,
+        };
+        let mut handle: HSWDEVICE = ptr::null_mut();
+
+        // SAFETY: All strings have been validated to be null terminated, and all
+        // pointers in `create_info` point to data that outlives this call. `context`
+        // outlives the creation callback, since this function waits for the callback
+        // to signal `completion_event` before returning.
+        let hresult = unsafe {
+            SwDeviceCreate(
+                enumerator_name,
+                parent_device_instance,
+                &create_info,
+                0,
+                ptr::null(),
+                Some(creation_callback),
+                ptr::from_mut(&mut context).cast(),
+                &mut handle,
+            )
+        };
+
+        if hresult >= 0 {
+            // SAFETY: `completion_event` is a valid event handle
+            let wait_result = unsafe { WaitForSingleObject(completion_event, INFINITE) };
+
+            // `context` must not be dropped while the creation callback could still access
+            // it
+            assert_eq!(
+                wait_result, WAIT_OBJECT_0,
+                "waiting for SwDeviceCreate completion should never fail"
+            );
+        }
+
+        // SAFETY: `completion_event` is a valid event handle that is no longer used
+        unsafe {
+            CloseHandle(completion_event);
+        }
+
+        if hresult < 0 {
+            return Err(hresult);
+        }
+
+        let software_device = Self {
+            handle,
+            device_instance_id: context.device_instance_id,
+            device_instance_id_len: context.device_instance_id_len,
+        };
+
+        // Dropping `software_device` closes `handle` if creation failed asynchronously
+        if context.create_result < 0 {
+            return Err(context.create_result);
+        }
+        Ok(software_device)
+    }
+
+    /// Returns the device instance ID assigned to the software device by PnP,
+    /// without a null terminator
+    #[must_use]
+    pub fn device_instance_id(&self) -> &[u16] {
+        &self.device_instance_id[..self.device_instance_id_len]
+    }
+
+    /// Set the lifetime of the software device
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the lifetime fails to be set. The
+    /// error variant will contain a [`HRESULT`] of the failure. Full error
+    /// documentation is available in the [`SwDeviceSetLifetime` documentation](https://learn.microsoft.com/en-us/windows/win32/api/swdevice/nf-swdevice-swdevicesetlifetime#return-value)
+    pub fn set_lifetime(&self, lifetime: SoftwareDeviceLifetime) -> Result<(), HRESULT> {
+        let lifetime: SW_DEVICE_LIFETIME = match lifetime {
+            SoftwareDeviceLifetime::Handle => SWDeviceLifetimeHandle,
+            SoftwareDeviceLifetime::ParentPresent => SWDeviceLifetimeParentPresent,
+        };
+
+        // SAFETY: `handle` is a private member of `SoftwareDevice`, originally created
+        // by `SwDeviceCreate`, and this module guarantees that it is always valid
+        let hresult = unsafe { SwDeviceSetLifetime(self.handle, lifetime) };
+        if hresult < 0 {
+            return Err(hresult);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SoftwareDevice {
+    fn drop(&mut self) {
+        // SAFETY: `handle` is a private member of `SoftwareDevice`, originally created
+        // by `SwDeviceCreate`, and is never used again after this call
+        unsafe {
+            SwDeviceClose(self.handle);
+        }
+    }
+}
+
+unsafe extern "C" fn creation_callback(
+    _handle: HSWDEVICE,
+    create_result: HRESULT,
+    context: PVOID,
+    device_instance_id: PCWSTR,
+) {
+    // SAFETY: `context` is the `CreationContext` passed to `SwDeviceCreate`, which
+    // is not accessed by `SoftwareDevice::create` until `completion_event` is
+    // signaled
+    let context = unsafe { &mut *context.cast::<CreationContext>() };
+    context.create_result = create_result;
+
+    if !device_instance_id.is_null() {
+        let mut len = 0;
+        // SAFETY: `device_instance_id` is a valid null terminated string provided by
+        // PnP, that is at most `MAX_DEVICE_ID_LEN` characters long (including the
+        // null terminator)
+        while len < MAX_DEVICE_ID_LEN - 1 && unsafe { *device_instance_id.add(len) } != 0 {
+            // SAFETY: `len` is within the bounds of `device_instance_id` (checked above)
+            context.device_instance_id[len] = unsafe { *device_instance_id.add(len) };
+            len += 1;
+        }
+        context.device_instance_id_len = len;
+    }
+
+    // SAFETY: `completion_event` is a valid event handle that outlives this
+    // callback
+    unsafe {
+        SetEvent(context.completion_event);
+    }
+}
+
+fn as_wide_str_ptr(wide_str: &[u16]) -> Result<PCWSTR, HRESULT> {
+    if wide_str.last() == Some(&0) {
+        Ok(wide_str.as_ptr())
+    } else {
+        Err(E_INVALIDARG)
+    }
+}
+
+fn as_wide_multi_str_ptr(wide_multi_str: &[u16]) -> Result<PCWSTR, HRESULT> {
+    if wide_multi_str.ends_with(&[0, 0]) {
+        Ok(wide_multi_str.as_ptr())
+    } else {
+        Err(E_INVALIDARG)
+    }
+}
+
+fn last_error_hresult() -> HRESULT {
+    const FACILITY_WIN32: u32 = 7;
+
+    // SAFETY: `GetLastError` has no safety requirements
+    let error = unsafe { GetLastError() };
+    if error == 0 {
+        return 0;
+    }
+
+    // Equivalent to the `HRESULT_FROM_WIN32` macro
+    #[allow(clippy::cast_possible_wrap)]
+    let hresult = ((error & 0x0000_FFFF) | (FACILITY_WIN32 << 16) | 0x8000_0000) as HRESULT;
+    hresult
+}
@@ -0,0 +1,1755 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Synchronization primitives for driver-global state
+//!
+//! Drivers commonly need state that is shared between `DriverEntry`, driver
+//! unload and the driver's callbacks. [`DriverCell`] and [`OnceLock`] provide
+//! a safe alternative to `static mut` for this state: the value is written
+//! exactly once, can then be read from any `IRQL` up to a configurable maximum,
+//! and (for [`DriverCell`]) is explicitly torn down in driver unload.
+//!
+//! For blocking synchronization between threads, [`KernelEvent`] and
+//! [`Semaphore`] wrap the kernel's dispatcher objects. Any
+//! [`DispatcherObject`] can be waited on with an optional timeout, and
+//! [`wait_any`] and [`wait_all`] wait on several of them at once.
+//! [`Resource`] is a reader-writer lock for state that is mostly read at
+//! `PASSIVE_LEVEL`, such as configuration. [`RemoveLock`] keeps a WDM device
+//! from being removed while I/O is in progress on it. [`Channel`] passes work
+//! from ISRs and DPCs to worker threads. WDF drivers can also use
+//! `wdf::WaitLock` for exclusive access at `PASSIVE_LEVEL`, which is managed by
+//! the framework.
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    marker::PhantomData,
+    mem::MaybeUninit,
+    ops::{Deref, DerefMut},
+    ptr,
+    sync::atomic::{AtomicU8, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use wdk_sys::{
+    ntddk::KeWaitForSingleObject,
+    _KWAIT_REASON,
+    APC_LEVEL,
+    DISPATCH_LEVEL,
+    HIGH_LEVEL,
+    PASSIVE_LEVEL,
+    PVOID,
+    STATUS_TIMEOUT,
+    ULONG,
+};
+#[cfg(feature = "alloc")]
+use wdk_sys::{
+    ntddk::{
+        ExAcquireResourceExclusiveLite,
+        ExAcquireResourceSharedLite,
+        ExDeleteResourceLite,
+        ExInitializeResourceLite,
+        ExIsResourceAcquiredExclusiveLite,
+        ExIsResourceAcquiredSharedLite,
+        ExReleaseResourceLite,
+        IoAcquireRemoveLockEx,
+        IoInitializeRemoveLockEx,
+        IoReleaseRemoveLockAndWaitEx,
+        IoReleaseRemoveLockEx,
+        KeEnterCriticalRegion,
+        KeInitializeEvent,
+        KeInitializeSemaphore,
+        KeLeaveCriticalRegion,
+        KeReadStateEvent,
+        KeReadStateSemaphore,
+        KeReleaseSemaphore,
+        KeResetEvent,
+        KeSetEvent,
+        KeWaitForMultipleObjects,
+    },
+    _EVENT_TYPE,
+    _WAIT_TYPE,
+    ERESOURCE,
+    IO_REMOVE_LOCK,
+    KEVENT,
+    KPRIORITY,
+    KSEMAPHORE,
+    KWAIT_BLOCK,
+    LONG,
+    MAXIMUM_WAIT_OBJECTS,
+    NTSTATUS,
+    STATUS_WAIT_0,
+    THREAD_WAIT_OBJECTS,
+    WAIT_TYPE,
+};
+
+use crate::time::{current_irql, relative_interval, KERNEL_MODE};
+
+const UNINITIALIZED: u8 = 0;
+const INITIALIZING: u8 = 1;
+const INITIALIZED: u8 = 2;
+
+// clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+#[cfg(feature = "alloc")]
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+const IO_NO_INCREMENT: KPRIORITY = wdk_sys::IO_NO_INCREMENT as KPRIORITY;
+
+/// Size of `IO_REMOVE_LOCK`, which the `Io*RemoveLockEx` routines are passed
+/// to check that the driver and the kernel agree on the layout of the lock
+#[cfg(feature = "alloc")]
+#[allow(clippy::cast_possible_truncation)]
+const REMOVE_LOCK_SIZE: ULONG = core::mem::size_of::<IO_REMOVE_LOCK>() as ULONG;
+
+/// A thread-safe cell which can be written to only once, and read from any
+/// `IRQL` up to a configurable maximum.
+///
+/// This is the `IRQL`-aware equivalent of `std::sync::OnceLock`. Initialization
+/// never blocks on a dispatcher object, so [`OnceLock::get`],
+/// [`OnceLock::set`] and [`OnceLock::get_or_init`] can be called at any `IRQL`
+/// <= the configured maximum.
+///
+/// If another thread is concurrently initializing the cell,
+/// [`OnceLock::get_or_init`] spins until initialization completes, so
+/// initialization closures should be short. It only spins at `IRQL` <=
+/// `APC_LEVEL`: at `DISPATCH_LEVEL` and above, the caller may have interrupted
+/// the initializing thread on the same processor, which would then never get
+/// to complete the initialization, so `None` is returned instead.
+pub struct OnceLock<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+    max_irql: ULONG,
+}
+
+// SAFETY: `OnceLock` only hands out shared references to its value after it has
+// been fully initialized, and initialization is guarded by `state`. Sharing a
+// `OnceLock` across threads can move a `T` between threads (via `set`), and
+// hand out `&T` to multiple threads, so `T` must be both `Send` and `Sync`.
+unsafe impl<T: Send + Sync> Sync for OnceLock<T> {}
+
+// SAFETY: Sending a `OnceLock` to another thread sends the contained `T` with
+// it
+unsafe impl<T: Send> Send for OnceLock<T> {}
+
+impl<T> OnceLock<T> {
+    /// Creates a new, uninitialized [`OnceLock`] that can be accessed at any
+    /// `IRQL`. [`OnceLock::get_or_init`] only waits for a concurrent
+    /// initialization at `IRQL` <= `APC_LEVEL`.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self::with_max_irql(HIGH_LEVEL)
+    }
+
+    /// Creates a new, uninitialized [`OnceLock`] that can only be accessed at
+    /// `IRQL` <= `max_irql`. In debug builds, this is asserted at runtime on
+    /// every access.
+    ///
+    /// This is useful when the contained value must only be used at a
+    /// restricted `IRQL` (ex. `max_irql` = `APC_LEVEL` for a value that
+    /// contains pageable memory).
+    #[must_use]
+    pub const fn with_max_irql(max_irql: ULONG) -> Self {
+        Self {
+            state: AtomicU8::new(UNINITIALIZED),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+            max_irql,
+        }
+    }
+
+    /// Returns the maximum `IRQL` this [`OnceLock`] can be accessed at
+    #[must_use]
+    pub const fn max_irql(&self) -> ULONG {
+        self.max_irql
+    }
+
+    /// Returns a reference to the contained value, or `None` if the
+    /// [`OnceLock`] has not been initialized yet.
+    ///
+    /// This never blocks, even if the [`OnceLock`] is currently being
+    /// initialized on another processor.
+    #[must_use]
+    pub fn get(&self) -> Option<&T> {
+        self.debug_assert_irql();
+
+        self.is_initialized().then(|| {
+            // SAFETY: The value is initialized, and is never mutated again while `self`
+            // is borrowed
+            unsafe { self.get_unchecked() }
+        })
+    }
+
+    /// Initializes the contents of the [`OnceLock`] to `value`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(value)` if the [`OnceLock`] was already initialized, or is
+    /// currently being initialized on another processor.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        self.debug_assert_irql();
+
+        if self
+            .state
+            .compare_exchange(
+                UNINITIALIZED,
+                INITIALIZING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            )
+            .is_err()
+        {
+            return Err(value);
+        }
+
+        // SAFETY: The successful transition to `INITIALIZING` grants exclusive access
+        // to the value
+        unsafe {
+            (*self.value.get()).write(value);
+        }
+        self.state.store(INITIALIZED, Ordering::Release);
+        Ok(())
+    }
+
+    /// Returns a reference to the contained value, initializing it with `f` if
+    /// the [`OnceLock`] has not been initialized yet.
+    ///
+    /// If the [`OnceLock`] is concurrently being initialized by another thread,
+    /// this spins until that initialization completes when called at `IRQL` <=
+    /// `APC_LEVEL`. At `DISPATCH_LEVEL` and above, this returns `None` instead
+    /// of spinning, since the initializing thread may have been interrupted by
+    /// the caller on the same processor. `None` is never returned at `IRQL` <=
+    /// `APC_LEVEL`.
+    ///
+    /// `f` must not attempt to initialize the same [`OnceLock`].
+    pub fn get_or_init<F>(&self, f: F) -> Option<&T>
+    where
+        F: FnOnce() -> T,
+    {
+        self.debug_assert_irql();
+
+        loop {
+            match self.state.compare_exchange_weak(
+                UNINITIALIZED,
+                INITIALIZING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    // SAFETY: The successful transition to `INITIALIZING` grants exclusive
+                    // access to the value
+                    unsafe {
+                        (*self.value.get()).write(f());
+                    }
+                    self.state.store(INITIALIZED, Ordering::Release);
+                    break;
+                }
+                Err(INITIALIZED) => break,
+                Err(INITIALIZING) if current_irql() >= DISPATCH_LEVEL => return None,
+                Err(_) => core::hint::spin_loop(),
+            }
+        }
+
+        // SAFETY: The loop above only exits once the value is initialized
+        Some(unsafe { self.get_unchecked() })
+    }
+
+    /// Takes the value out of the [`OnceLock`], leaving it uninitialized.
+    pub fn take(&mut self) -> Option<T> {
+        // SAFETY: `&mut self` guarantees there are no outstanding references to the
+        // value
+        unsafe { self.take_shared() }
+    }
+
+    /// Takes the value out of the [`OnceLock`] through a shared reference,
+    /// leaving it uninitialized.
+    ///
+    /// # Safety
+    ///
+    /// There must be no outstanding references to the contained value, and no
+    /// concurrent accesses to the [`OnceLock`] for the duration of this call.
+    unsafe fn take_shared(&self) -> Option<T> {
+        self.state
+            .compare_exchange(
+                INITIALIZED,
+                UNINITIALIZED,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+            .then(|| {
+                // SAFETY: The value was initialized, and the state is now `UNINITIALIZED`, so
+                // it will not be read or dropped again
+                unsafe { (*self.value.get()).assume_init_read() }
+            })
+    }
+
+    fn is_initialized(&self) -> bool {
+        self.state.load(Ordering::Acquire) == INITIALIZED
+    }
+
+    /// # Safety
+    ///
+    /// The value must be initialized
+    unsafe fn get_unchecked(&self) -> &T {
+        // SAFETY: The caller guarantees the value is initialized
+        unsafe { (*self.value.get()).assume_init_ref() }
+    }
+
+    fn debug_assert_irql(&self) {
+        debug_assert!(
+            current_irql() <= self.max_irql,
+            "OnceLock should only be accessed at IRQL <= {}",
+            self.max_irql
+        );
+    }
+}
+
+impl<T> Default for OnceLock<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OnceLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug_struct = f.debug_struct("OnceLock");
+        match self.get() {
+            Some(value) => debug_struct.field("value", value),
+            None => debug_struct.field("value", &format_args!("<uninit>")),
+        };
+        debug_struct
+            .field("max_irql", &self.max_irql)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Drop for OnceLock<T> {
+    fn drop(&mut self) {
+        drop(self.take());
+    }
+}
+
+/// A cell for driver-global state that is initialized in `DriverEntry` and
+/// torn down in driver unload.
+///
+/// [`DriverCell`] is intended to be used in a `static`, replacing `static mut`
+/// patterns:
+///
+/// ```rust, no_run
+/// use wdk::sync::DriverCell;
+///
+/// struct GlobalState {
+///     device_count: u32,
+/// }
+///
+/// static GLOBAL_STATE: DriverCell<GlobalState> = DriverCell::new();
+///
+/// fn driver_entry() {
+///     GLOBAL_STATE
+///         .init(GlobalState { device_count: 0 })
+///         .unwrap_or_else(|_| panic!("GLOBAL_STATE should only be initialized once"));
+/// }
+///
+/// fn callback() {
+///     if let Some(global_state) = GLOBAL_STATE.get() {
+///         let _ = global_state.device_count;
+///     }
+/// }
+///
+/// fn driver_unload() {
+///     // SAFETY: All callbacks that access `GLOBAL_STATE` have completed
+///     drop(unsafe { GLOBAL_STATE.teardown() });
+/// }
+/// ```
+pub struct DriverCell<T> {
+    inner: OnceLock<T>,
+}
+
+impl<T> DriverCell<T> {
+    /// Creates a new, uninitialized [`DriverCell`] that can be read at any
+    /// `IRQL`.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            inner: OnceLock::new(),
+        }
+    }
+
+    /// Creates a new, uninitialized [`DriverCell`] that can only be read at
+    /// `IRQL` <= `max_irql`. In debug builds, this is asserted at runtime on
+    /// every access.
+    #[must_use]
+    pub const fn with_max_irql(max_irql: ULONG) -> Self {
+        Self {
+            inner: OnceLock::with_max_irql(max_irql),
+        }
+    }
+
+    /// Initializes the [`DriverCell`] to `value`. This should be called from
+    /// `DriverEntry`.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`. In debug builds, this
+    /// is asserted at runtime.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(value)` if the [`DriverCell`] was already initialized.
+    pub fn init(&self, value: T) -> Result<(), T> {
+        debug_assert!(
+            current_irql() == PASSIVE_LEVEL,
+            "DriverCell should only be initialized at IRQL = PASSIVE_LEVEL"
+        );
+
+        self.inner.set(value)
+    }
+
+    /// Returns a reference to the contained value, or `None` if the
+    /// [`DriverCell`] has not been initialized, or has already been torn down.
+    #[must_use]
+    pub fn get(&self) -> Option<&T> {
+        self.inner.get()
+    }
+
+    /// Tears down the [`DriverCell`], returning its value so that it can be
+    /// cleaned up. This should be called from driver unload.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`. In debug builds, this
+    /// is asserted at runtime.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that no references previously returned by
+    /// [`DriverCell::get`] are still in use, and that no callbacks that may
+    /// access the [`DriverCell`] can run concurrently with, or after, this
+    /// call. This is typically guaranteed by the OS once driver unload is
+    /// invoked, provided that the driver has cancelled or flushed all of its
+    /// outstanding asynchronous work (ex. timers, DPCs and work items).
+    pub unsafe fn teardown(&self) -> Option<T> {
+        debug_assert!(
+            current_irql() == PASSIVE_LEVEL,
+            "DriverCell should only be torn down at IRQL = PASSIVE_LEVEL"
+        );
+
+        // SAFETY: The caller guarantees there are no outstanding references to, or
+        // concurrent accesses of, the value
+        unsafe { self.inner.take_shared() }
+    }
+}
+
+impl<T> Default for DriverCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for DriverCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DriverCell")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+/// Type of a [`KernelEvent`], which determines how many waiting threads are
+/// released when it is signaled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventType {
+    /// The event stays signaled, releasing all waiting threads, until it is
+    /// explicitly reset
+    Notification,
+    /// The event releases a single waiting thread, and is then automatically
+    /// reset
+    Synchronization,
+}
+
+/// A kernel event, which threads can wait on until it is signaled.
+///
+/// The underlying `KEVENT` is allocated from non-paged pool, so a
+/// [`KernelEvent`] can be moved freely (ex. into a device context or a
+/// [`DriverCell`]). It is waited on with the methods of [`DispatcherObject`],
+/// or together with other dispatcher objects with [`wait_any`] and
+/// [`wait_all`]:
+///
+/// ```rust, no_run
+/// use core::time::Duration;
+///
+/// use wdk::sync::{DispatcherObject, EventType, KernelEvent};
+///
+/// let event = KernelEvent::new(EventType::Notification, false);
+///
+/// // On another thread, or in a completion routine
+/// event.set();
+///
+/// if !event.wait_for(Duration::from_millis(100)) {
+///     // Timed out
+/// }
+/// ```
+#[cfg(feature = "alloc")]
+pub struct KernelEvent {
+    event: Box<UnsafeCell<KEVENT>>,
+}
+
+// SAFETY: `KEVENT` is only accessed through the `Ke*Event` and `KeWait*`
+// routines, which synchronize concurrent accesses
+#[cfg(feature = "alloc")]
+unsafe impl Send for KernelEvent {}
+
+// SAFETY: `KEVENT` is only accessed through the `Ke*Event` and `KeWait*`
+// routines, which synchronize concurrent accesses
+#[cfg(feature = "alloc")]
+unsafe impl Sync for KernelEvent {}
+
+#[cfg(feature = "alloc")]
+impl KernelEvent {
+    /// Creates a [`KernelEvent`] of `event_type`, which is initially signaled
+    /// if `signaled` is `true`
+    #[must_use]
+    pub fn new(event_type: EventType, signaled: bool) -> Self {
+        let event = Box::new(UnsafeCell::new(KEVENT::default()));
+        let event_type = match event_type {
+            EventType::Notification => _EVENT_TYPE::NotificationEvent,
+            EventType::Synchronization => _EVENT_TYPE::SynchronizationEvent,
+        };
+
+        // SAFETY: `event` points to a `KEVENT` allocated from non-paged pool, which is
+        // never moved for the lifetime of the `KernelEvent`
+        unsafe {
+            KeInitializeEvent(event.get(), event_type, u8::from(signaled));
+        }
+        Self { event }
+    }
+
+    /// Signals the event, releasing waiting threads.
+    ///
+    /// This must be called at `IRQL` <= `DISPATCH_LEVEL`. In debug builds, this
+    /// is asserted at runtime.
+    pub fn set(&self) {
+        debug_assert!(
+            current_irql() <= DISPATCH_LEVEL,
+            "KernelEvent should only be set at IRQL <= DISPATCH_LEVEL"
+        );
+
+        // SAFETY: `event` is a valid, initialized `KEVENT`, and the IRQL requirement of
+        // `KeSetEvent` is upheld by the caller
+        unsafe {
+            KeSetEvent(self.event.get(), IO_NO_INCREMENT, u8::from(false));
+        }
+    }
+
+    /// Resets the event to the not-signaled state.
+    ///
+    /// This must be called at `IRQL` <= `DISPATCH_LEVEL`. In debug builds, this
+    /// is asserted at runtime.
+    pub fn reset(&self) {
+        debug_assert!(
+            current_irql() <= DISPATCH_LEVEL,
+            "KernelEvent should only be reset at IRQL <= DISPATCH_LEVEL"
+        );
+
+        // SAFETY: `event` is a valid, initialized `KEVENT`, and the IRQL requirement of
+        // `KeResetEvent` is upheld by the caller
+        unsafe {
+            KeResetEvent(self.event.get());
+        }
+    }
+
+    /// Returns whether the event is currently signaled
+    #[must_use]
+    pub fn is_signaled(&self) -> bool {
+        // SAFETY: `event` is a valid, initialized `KEVENT`. `KeReadStateEvent` can be
+        // called at any IRQL.
+        unsafe { KeReadStateEvent(self.event.get()) != 0 }
+    }
+}
+
+// SAFETY: `as_dispatcher_object` returns a pointer to the `KEVENT` of the
+// event, which lives as long as `self`
+#[cfg(feature = "alloc")]
+unsafe impl DispatcherObject for KernelEvent {
+    fn as_dispatcher_object(&self) -> PVOID {
+        self.event.get().cast()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Debug for KernelEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KernelEvent")
+            .field("is_signaled", &self.is_signaled())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A kernel semaphore, which limits the number of threads that can
+/// concurrently hold one of its permits.
+///
+/// Waiting on a [`Semaphore`] (with the methods of [`DispatcherObject`],
+/// [`wait_any`] or [`wait_all`]) acquires a permit, and [`Semaphore::release`]
+/// returns permits. Like [`KernelEvent`], the underlying `KSEMAPHORE` is
+/// allocated from non-paged pool, so a [`Semaphore`] can be moved freely.
+#[cfg(feature = "alloc")]
+pub struct Semaphore {
+    semaphore: Box<UnsafeCell<KSEMAPHORE>>,
+    limit: u32,
+}
+
+// SAFETY: `KSEMAPHORE` is only accessed through the `Ke*Semaphore` and
+// `KeWait*` routines, which synchronize concurrent accesses
+#[cfg(feature = "alloc")]
+unsafe impl Send for Semaphore {}
+
+// SAFETY: `KSEMAPHORE` is only accessed through the `Ke*Semaphore` and
+// `KeWait*` routines, which synchronize concurrent accesses
+#[cfg(feature = "alloc")]
+unsafe impl Sync for Semaphore {}
+
+#[cfg(feature = "alloc")]
+impl Semaphore {
+    /// Creates a [`Semaphore`] with `initial_count` available permits, which
+    /// can never have more than `limit` available permits
+    ///
+    /// # Panics
+    ///
+    /// Panics if `initial_count` is larger than `limit`, or if `limit` is
+    /// larger than `i32::MAX`
+    #[must_use]
+    pub fn new(initial_count: u32, limit: u32) -> Self {
+        assert!(
+            initial_count <= limit,
+            "initial count of Semaphore should not be larger than its limit"
+        );
+        let limit = LONG::try_from(limit).expect("limit of Semaphore should fit in a LONG");
+        let initial_count =
+            LONG::try_from(initial_count).expect("initial count of Semaphore should fit in a LONG");
+        let semaphore = Box::new(UnsafeCell::new(KSEMAPHORE::default()));
+
+        // SAFETY: `semaphore` points to a `KSEMAPHORE` allocated from non-paged pool,
+        // which is never moved for the lifetime of the `Semaphore`, and `initial_count`
+        // is <= `limit`.
+        unsafe {
+            KeInitializeSemaphore(semaphore.get(), initial_count, limit);
+        }
+        Self {
+            semaphore,
+            limit: limit.unsigned_abs(),
+        }
+    }
+
+    /// Returns `count` permits to the semaphore, releasing waiting threads.
+    ///
+    /// This must be called at `IRQL` <= `DISPATCH_LEVEL`. In debug builds, this
+    /// is asserted at runtime.
+    ///
+    /// # Safety
+    ///
+    /// Returning `count` permits must not exceed the limit of the semaphore,
+    /// taking concurrent releases into account: the kernel raises
+    /// `STATUS_SEMAPHORE_LIMIT_EXCEEDED` in this case. This is typically
+    /// guaranteed by only returning permits that were previously acquired by
+    /// waiting on the semaphore, or by serializing the calls to this function.
+    /// In debug builds, the number of available permits is checked against the
+    /// limit before the permits are returned, which does not detect concurrent
+    /// releases that exceed the limit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is larger than `i32::MAX`
+    pub unsafe fn release(&self, count: u32) {
+        debug_assert!(
+            current_irql() <= DISPATCH_LEVEL,
+            "Semaphore should only be released at IRQL <= DISPATCH_LEVEL"
+        );
+        debug_assert!(
+            count <= self.limit - self.available_permits().min(self.limit),
+            "Semaphore should not be released beyond its limit"
+        );
+        let count = LONG::try_from(count).expect("count should fit in a LONG");
+
+        // SAFETY: `semaphore` is a valid, initialized `KSEMAPHORE`, the caller
+        // guarantees that releasing `count` permits does not exceed its limit, and the
+        // IRQL requirement of `KeReleaseSemaphore` is upheld by the caller
+        unsafe {
+            KeReleaseSemaphore(
+                self.semaphore.get(),
+                IO_NO_INCREMENT,
+                count,
+                u8::from(false),
+            );
+        }
+    }
+
+    /// Returns the number of permits that are currently available
+    #[must_use]
+    pub fn available_permits(&self) -> u32 {
+        // SAFETY: `semaphore` is a valid, initialized `KSEMAPHORE`.
+        // `KeReadStateSemaphore` can be called at any IRQL.
+        unsafe { KeReadStateSemaphore(self.semaphore.get()) }.unsigned_abs()
+    }
+
+    /// Returns the maximum number of permits that can be available
+    #[must_use]
+    pub const fn limit(&self) -> u32 {
+        self.limit
+    }
+}
+
+// SAFETY: `as_dispatcher_object` returns a pointer to the `KSEMAPHORE` of the
+// semaphore, which lives as long as `self`
+#[cfg(feature = "alloc")]
+unsafe impl DispatcherObject for Semaphore {
+    fn as_dispatcher_object(&self) -> PVOID {
+        self.semaphore.get().cast()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Semaphore")
+            .field("available_permits", &self.available_permits())
+            .field("limit", &self.limit)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A reader-writer lock backed by an executive resource (`ERESOURCE`), which
+/// protects a value that can be read by several threads at once, or written by
+/// one thread at a time.
+///
+/// [`Resource::read`] and [`Resource::write`] block until the resource is
+/// acquired for shared or exclusive access, and return an RAII guard that
+/// releases it when dropped. Normal kernel APCs are disabled while the
+/// resource is held, as required by `ExAcquireResourceSharedLite` and
+/// `ExAcquireResourceExclusiveLite`, so that the thread cannot be suspended
+/// while holding it. Executive resources are owned by the thread that acquired
+/// them, so the guards cannot be sent to other threads.
+///
+/// An `ERESOURCE` can be acquired again by the thread that holds it, which
+/// would hand out aliasing references to the value. A thread that holds the
+/// resource for exclusive access therefore cannot acquire it again, and a
+/// thread that holds it for shared access cannot acquire it for exclusive
+/// access: [`Resource::read`] and [`Resource::write`] panic, and
+/// [`Resource::try_read`] and [`Resource::try_write`] return `None`. Acquiring
+/// shared access again while holding shared access is allowed.
+///
+/// The resource can only be acquired at `IRQL` <= `APC_LEVEL`. In debug
+/// builds, this is asserted at runtime.
+///
+/// ```rust, no_run
+/// use wdk::sync::Resource;
+///
+/// let configuration = Resource::new([0u8; 16]);
+///
+/// configuration.write()[0] = 1;
+/// assert_eq!(configuration.read()[0], 1);
+/// ```
+#[cfg(feature = "alloc")]
+pub struct Resource<T> {
+    resource: Box<UnsafeCell<ERESOURCE>>,
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: The value is only accessed through guards, which hold the resource
+// for shared access (only allowing `&T`) or exclusive access (allowing `&mut
+// T`), so `T` must be `Send` to be written from and `Sync` to be read from
+// several threads
+#[cfg(feature = "alloc")]
+unsafe impl<T: Send + Sync> Sync for Resource<T> {}
+
+// SAFETY: `ERESOURCE` is allocated from non-paged pool and is not tied to the
+// thread that initialized it, so the `Resource` can be sent to another thread
+// along with the value
+#[cfg(feature = "alloc")]
+unsafe impl<T: Send> Send for Resource<T> {}
+
+#[cfg(feature = "alloc")]
+impl<T> Resource<T> {
+    /// Creates a [`Resource`] protecting `value`.
+    ///
+    /// This must be called at `IRQL` <= `DISPATCH_LEVEL`. In debug builds, this
+    /// is asserted at runtime.
+    #[must_use]
+    pub fn new(value: T) -> Self {
+        debug_assert!(
+            current_irql() <= DISPATCH_LEVEL,
+            "Resource should only be created at IRQL <= DISPATCH_LEVEL"
+        );
+        let resource = Box::new(UnsafeCell::new(ERESOURCE::default()));
+
+        // SAFETY: `resource` points to an `ERESOURCE` allocated from non-paged pool,
+        // which is never moved for the lifetime of the `Resource`
+        let nt_status = unsafe { ExInitializeResourceLite(resource.get()) };
+        debug_assert!(
+            crate::nt_success(nt_status),
+            "ExInitializeResourceLite should always succeed"
+        );
+
+        Self {
+            resource,
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Acquires the resource for shared access, blocking until no thread holds
+    /// it for exclusive access
+    ///
+    /// # Panics
+    ///
+    /// Panics if the current thread holds the resource for exclusive access
+    pub fn read(&self) -> ResourceSharedGuard<'_, T> {
+        assert!(
+            self.is_acquirable_by_current_thread(ResourceAccess::Shared),
+            "Resource should not be acquired for shared access by the thread that holds it for \
+             exclusive access"
+        );
+        Self::enter_critical_region();
+
+        // SAFETY: `resource` is a valid, initialized `ERESOURCE`, and normal kernel
+        // APCs are disabled by `enter_critical_region`
+        unsafe {
+            ExAcquireResourceSharedLite(self.resource.get(), u8::from(true));
+        }
+        ResourceSharedGuard {
+            resource: self,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Acquires the resource for shared access if no thread holds it for
+    /// exclusive access, without blocking. Returns `None` if the resource is
+    /// held for exclusive access, including by the current thread.
+    pub fn try_read(&self) -> Option<ResourceSharedGuard<'_, T>> {
+        if !self.is_acquirable_by_current_thread(ResourceAccess::Shared) {
+            return None;
+        }
+        Self::enter_critical_region();
+
+        // SAFETY: `resource` is a valid, initialized `ERESOURCE`, and normal kernel
+        // APCs are disabled by `enter_critical_region`
+        let is_acquired =
+            unsafe { ExAcquireResourceSharedLite(self.resource.get(), u8::from(false)) } != 0;
+        if !is_acquired {
+            Self::leave_critical_region();
+            return None;
+        }
+        Some(ResourceSharedGuard {
+            resource: self,
+            _not_send: PhantomData,
+        })
+    }
+
+    /// Acquires the resource for exclusive access, blocking until no other
+    /// thread holds it
+    ///
+    /// # Panics
+    ///
+    /// Panics if the current thread already holds the resource
+    pub fn write(&self) -> ResourceExclusiveGuard<'_, T> {
+        assert!(
+            self.is_acquirable_by_current_thread(ResourceAccess::Exclusive),
+            "Resource should not be acquired for exclusive access by a thread that already holds \
+             it"
+        );
+        Self::enter_critical_region();
+
+        // SAFETY: `resource` is a valid, initialized `ERESOURCE`, and normal kernel
+        // APCs are disabled by `enter_critical_region`
+        unsafe {
+            ExAcquireResourceExclusiveLite(self.resource.get(), u8::from(true));
+        }
+        ResourceExclusiveGuard {
+            resource: self,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Acquires the resource for exclusive access if no other thread holds
+    /// it, without blocking. Returns `None` if the resource is held, including
+    /// by the current thread.
+    pub fn try_write(&self) -> Option<ResourceExclusiveGuard<'_, T>> {
+        if !self.is_acquirable_by_current_thread(ResourceAccess::Exclusive) {
+            return None;
+        }
+        Self::enter_critical_region();
+
+        // SAFETY: `resource` is a valid, initialized `ERESOURCE`, and normal kernel
+        // APCs are disabled by `enter_critical_region`
+        let is_acquired =
+            unsafe { ExAcquireResourceExclusiveLite(self.resource.get(), u8::from(false)) } != 0;
+        if !is_acquired {
+            Self::leave_critical_region();
+            return None;
+        }
+        Some(ResourceExclusiveGuard {
+            resource: self,
+            _not_send: PhantomData,
+        })
+    }
+
+    /// Returns a mutable reference to the protected value. No locking is
+    /// needed, since the mutable borrow guarantees exclusive access.
+    pub const fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    /// Returns whether the current thread can acquire the resource for
+    /// `access` without aliasing a guard it already holds
+    fn is_acquirable_by_current_thread(&self, access: ResourceAccess) -> bool {
+        // SAFETY: `resource` is a valid, initialized `ERESOURCE`, and
+        // `ExIsResourceAcquiredExclusiveLite` can be called at IRQL <= DISPATCH_LEVEL
+        let is_held_exclusive =
+            unsafe { ExIsResourceAcquiredExclusiveLite(self.resource.get()) } != 0;
+        // SAFETY: `resource` is a valid, initialized `ERESOURCE`, and
+        // `ExIsResourceAcquiredSharedLite` can be called at IRQL <= DISPATCH_LEVEL
+        let held_count = unsafe { ExIsResourceAcquiredSharedLite(self.resource.get()) };
+        access.is_allowed_while_holding(is_held_exclusive, held_count)
+    }
+
+    fn enter_critical_region() {
+        debug_assert!(
+            current_irql() <= APC_LEVEL,
+            "Resource should only be acquired at IRQL <= APC_LEVEL"
+        );
+
+        // SAFETY: `KeEnterCriticalRegion` can be called at any IRQL <= APC_LEVEL, and
+        // every call is paired with `leave_critical_region` when the resource is
+        // released or fails to be acquired
+        unsafe {
+            KeEnterCriticalRegion();
+        }
+    }
+
+    fn leave_critical_region() {
+        // SAFETY: Every call is paired with a previous call to `enter_critical_region`
+        // on the same thread
+        unsafe {
+            KeLeaveCriticalRegion();
+        }
+    }
+
+    /// Releases the resource, which must be held by the current thread
+    fn release(&self) {
+        // SAFETY: `resource` is a valid, initialized `ERESOURCE`, which is held by the
+        // current thread since guards cannot be sent to other threads
+        unsafe {
+            ExReleaseResourceLite(self.resource.get());
+        }
+        Self::leave_critical_region();
+    }
+}
+
+/// Access to a [`Resource`] that a thread acquires
+#[cfg(feature = "alloc")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ResourceAccess {
+    Shared,
+    Exclusive,
+}
+
+#[cfg(feature = "alloc")]
+impl ResourceAccess {
+    /// Returns whether a thread can acquire this access to a resource, given
+    /// whether it holds the resource for exclusive access, and how many times
+    /// it holds the resource for either access
+    const fn is_allowed_while_holding(self, is_held_exclusive: bool, held_count: ULONG) -> bool {
+        match self {
+            // Shared guards only hand out `&T`, so they can alias each other
+            Self::Shared => !is_held_exclusive,
+            Self::Exclusive => !is_held_exclusive && held_count == 0,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Default> Default for Resource<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: fmt::Debug> fmt::Debug for Resource<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug_struct = f.debug_struct("Resource");
+        match self.try_read() {
+            Some(guard) => debug_struct.field("value", &*guard),
+            None => debug_struct.field("value", &format_args!("<locked>")),
+        };
+        debug_struct.finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> Drop for Resource<T> {
+    fn drop(&mut self) {
+        debug_assert!(
+            current_irql() <= DISPATCH_LEVEL,
+            "Resource should only be dropped at IRQL <= DISPATCH_LEVEL"
+        );
+
+        // SAFETY: `resource` is a valid, initialized `ERESOURCE`, which is not held by
+        // any thread since no guards can outlive the `Resource`
+        let nt_status = unsafe { ExDeleteResourceLite(self.resource.get()) };
+        debug_assert!(
+            crate::nt_success(nt_status),
+            "ExDeleteResourceLite should always succeed"
+        );
+    }
+}
+
+/// RAII guard of a [`Resource`] acquired for shared access with
+/// [`Resource::read`] or [`Resource::try_read`], which releases it when
+/// dropped
+#[cfg(feature = "alloc")]
+#[must_use = "the resource is released as soon as the guard is dropped"]
+pub struct ResourceSharedGuard<'a, T> {
+    resource: &'a Resource<T>,
+    // Executive resources must be released by the thread that acquired them
+    _not_send: PhantomData<*const ()>,
+}
+
+// SAFETY: The guard only allows `&T` to be accessed, so sharing it between
+// threads is safe if `T` is `Sync`
+#[cfg(feature = "alloc")]
+unsafe impl<T: Sync> Sync for ResourceSharedGuard<'_, T> {}
+
+#[cfg(feature = "alloc")]
+impl<T> Deref for ResourceSharedGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: The resource is held for shared access, so no thread can access the
+        // value mutably for the lifetime of the guard
+        unsafe { &*self.resource.value.get() }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> Drop for ResourceSharedGuard<'_, T> {
+    fn drop(&mut self) {
+        self.resource.release();
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: fmt::Debug> fmt::Debug for ResourceSharedGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+/// RAII guard of a [`Resource`] acquired for exclusive access with
+/// [`Resource::write`] or [`Resource::try_write`], which releases it when
+/// dropped
+#[cfg(feature = "alloc")]
+#[must_use = "the resource is released as soon as the guard is dropped"]
+pub struct ResourceExclusiveGuard<'a, T> {
+    resource: &'a Resource<T>,
+    // Executive resources must be released by the thread that acquired them
+    _not_send: PhantomData<*const ()>,
+}
+
+// SAFETY: Sharing the guard between threads only allows `&T` to be accessed,
+// which is safe if `T` is `Sync`
+#[cfg(feature = "alloc")]
+unsafe impl<T: Sync> Sync for ResourceExclusiveGuard<'_, T> {}
+
+#[cfg(feature = "alloc")]
+impl<T> Deref for ResourceExclusiveGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: The resource is held for exclusive access, so no other thread can
+        // access the value for the lifetime of the guard
+        unsafe { &*self.resource.value.get() }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> DerefMut for ResourceExclusiveGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: The resource is held for exclusive access, so no other thread can
+        // access the value for the lifetime of the guard
+        unsafe { &mut *self.resource.value.get() }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> Drop for ResourceExclusiveGuard<'_, T> {
+    fn drop(&mut self) {
+        self.resource.release();
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: fmt::Debug> fmt::Debug for ResourceExclusiveGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+/// A remove lock (`IO_REMOVE_LOCK`), which keeps a WDM device from being
+/// removed while I/O is in progress on it.
+///
+/// Dispatch routines call [`RemoveLock::acquire`] before starting to process an
+/// IRP, and hold the returned [`RemoveLockGuard`] until the IRP is completed
+/// or passed down the device stack. Once removal of the device has started,
+/// acquiring fails with `STATUS_DELETE_PENDING`, so new I/O can be failed
+/// instead of racing with the removal. The `IRP_MN_REMOVE_DEVICE` handler
+/// acquires the lock like any other dispatch routine, then calls
+/// [`RemoveLockGuard::release_and_wait`] to wait for all other outstanding
+/// acquisitions to be released before detaching and deleting the device.
+///
+/// Each acquisition is released exactly once, when its guard is dropped or
+/// consumed by [`RemoveLockGuard::release_and_wait`]. Like [`Resource`], the
+/// underlying `IO_REMOVE_LOCK` is allocated from non-paged pool, so a
+/// [`RemoveLock`] can be moved freely (ex. into a device extension).
+///
+/// ```rust, no_run
+/// use wdk::sync::RemoveLock;
+/// use wdk_sys::NTSTATUS;
+///
+/// fn dispatch_remove_device(remove_lock: &RemoveLock) -> Result<(), NTSTATUS> {
+///     let guard = remove_lock.acquire()?;
+///     // Pass the IRP down the device stack
+///     guard.release_and_wait();
+///     // Detach and delete the device
+///     Ok(())
+/// }
+/// ```
+#[cfg(feature = "alloc")]
+pub struct RemoveLock {
+    remove_lock: Box<UnsafeCell<IO_REMOVE_LOCK>>,
+}
+
+// SAFETY: `IO_REMOVE_LOCK` is only accessed through the `Io*RemoveLockEx`
+// routines, which synchronize concurrent accesses
+#[cfg(feature = "alloc")]
+unsafe impl Send for RemoveLock {}
+
+// SAFETY: `IO_REMOVE_LOCK` is only accessed through the `Io*RemoveLockEx`
+// routines, which synchronize concurrent accesses
+#[cfg(feature = "alloc")]
+unsafe impl Sync for RemoveLock {}
+
+#[cfg(feature = "alloc")]
+impl RemoveLock {
+    /// Creates a [`RemoveLock`], whose outstanding acquisitions are tracked
+    /// in allocations tagged with `tag` in checked builds of Windows.
+    ///
+    /// This must be called at `PASSIVE_LEVEL`. In debug builds, this is
+    /// asserted at runtime.
+    #[must_use]
+    pub fn new(tag: ULONG) -> Self {
+        debug_assert!(
+            current_irql() == PASSIVE_LEVEL,
+            "RemoveLock should only be created at PASSIVE_LEVEL"
+        );
+        let remove_lock = Box::new(UnsafeCell::new(IO_REMOVE_LOCK::default()));
+
+        // SAFETY: `remove_lock` points to an `IO_REMOVE_LOCK` allocated from non-paged
+        // pool, which is never moved for the lifetime of the `RemoveLock`. A maximum of
+        // 0 locked minutes and a high watermark of 0 disable the checks of checked
+        // builds of Windows, as `IoInitializeRemoveLock` callers commonly do.
+        unsafe {
+            IoInitializeRemoveLockEx(remove_lock.get(), tag, 0, 0, REMOVE_LOCK_SIZE);
+        }
+        Self { remove_lock }
+    }
+
+    /// Acquires the remove lock, which keeps the device from being removed
+    /// until the returned guard is dropped.
+    ///
+    /// This must be called at `IRQL` <= `DISPATCH_LEVEL`. In debug builds, this
+    /// is asserted at runtime.
+    ///
+    /// # Errors
+    ///
+    /// Returns `STATUS_DELETE_PENDING` if removal of the device has started
+    /// (i.e. [`RemoveLockGuard::release_and_wait`] was called), in which case
+    /// the I/O should be failed with that status
+    pub fn acquire(&self) -> Result<RemoveLockGuard<'_>, NTSTATUS> {
+        debug_assert!(
+            current_irql() <= DISPATCH_LEVEL,
+            "RemoveLock should only be acquired at IRQL <= DISPATCH_LEVEL"
+        );
+
+        // SAFETY: `remove_lock` is a valid, initialized `IO_REMOVE_LOCK`, and the IRQL
+        // requirement of `IoAcquireRemoveLockEx` is upheld by the caller. The file name
+        // is a NUL-terminated string with a static lifetime.
+        let nt_status = unsafe {
+            IoAcquireRemoveLockEx(
+                self.remove_lock.get(),
+                ptr::null_mut(),
+                concat!(file!(), "\0").as_ptr().cast(),
+                line!(),
+                REMOVE_LOCK_SIZE,
+            )
+        };
+        if !crate::nt_success(nt_status) {
+            return Err(nt_status);
+        }
+        Ok(RemoveLockGuard { remove_lock: self })
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Debug for RemoveLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoveLock").finish_non_exhaustive()
+    }
+}
+
+/// RAII guard of a [`RemoveLock`] acquired with [`RemoveLock::acquire`], which
+/// releases it when dropped
+#[cfg(feature = "alloc")]
+#[must_use = "the remove lock is released as soon as the guard is dropped"]
+pub struct RemoveLockGuard<'a> {
+    remove_lock: &'a RemoveLock,
+}
+
+#[cfg(feature = "alloc")]
+impl RemoveLockGuard<'_> {
+    /// Releases this acquisition of the remove lock, and waits until all other
+    /// acquisitions are released. Once this is called, every further
+    /// [`RemoveLock::acquire`] fails with `STATUS_DELETE_PENDING`.
+    ///
+    /// This is called by the `IRP_MN_REMOVE_DEVICE` handler of a driver, with
+    /// the acquisition it made for the remove IRP, before it detaches and
+    /// deletes the device. It must be called at `PASSIVE_LEVEL`. In debug
+    /// builds, this is asserted at runtime.
+    pub fn release_and_wait(self) {
+        debug_assert!(
+            current_irql() == PASSIVE_LEVEL,
+            "RemoveLock should only be released and waited on at PASSIVE_LEVEL"
+        );
+        let remove_lock = self.remove_lock.remove_lock.get();
+        // The acquisition is released by `IoReleaseRemoveLockAndWaitEx`, so it must
+        // not be released again when the guard is dropped
+        core::mem::forget(self);
+
+        // SAFETY: `remove_lock` is a valid, initialized `IO_REMOVE_LOCK`, which was
+        // acquired by the consumed guard, and the IRQL requirement of
+        // `IoReleaseRemoveLockAndWaitEx` is upheld by the caller
+        unsafe {
+            IoReleaseRemoveLockAndWaitEx(remove_lock, ptr::null_mut(), REMOVE_LOCK_SIZE);
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Drop for RemoveLockGuard<'_> {
+    fn drop(&mut self) {
+        // SAFETY: `remove_lock` is a valid, initialized `IO_REMOVE_LOCK`, which was
+        // acquired by this guard with the same (null) tag. `IoReleaseRemoveLockEx`
+        // can be called at IRQL <= DISPATCH_LEVEL, which is the highest IRQL the lock
+        // can be acquired at.
+        unsafe {
+            IoReleaseRemoveLockEx(
+                self.remove_lock.remove_lock.get(),
+                ptr::null_mut(),
+                REMOVE_LOCK_SIZE,
+            );
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Debug for RemoveLockGuard<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoveLockGuard").finish_non_exhaustive()
+    }
+}
+
+/// A fixed-capacity queue of `N` values, which passes work from interrupt
+/// service routines, DPCs or other threads to worker threads.
+///
+/// [`Channel::send`] and [`Channel::try_recv`] never block and never allocate,
+/// so they can be called at any `IRQL`, including from an ISR, by any number
+/// of senders and receivers at once. The queue is a lock-free ring of `N`
+/// slots, so `N` must be a power of two. A [`Channel`] can be placed in a
+/// `static`, since [`Channel::new`] is a `const fn`.
+///
+/// After each value is sent, the channel calls the [`Notify`] hook it was
+/// created with, which can wake up the receivers: a [`KernelEvent`] is
+/// signaled, and a closure is called (ex. to queue a work item). Channels
+/// notified through a [`KernelEvent`] can also be received from with
+/// [`Channel::recv`], which blocks until a value is available.
+///
+/// ```rust, no_run
+/// use wdk::sync::{Channel, EventType, KernelEvent};
+///
+/// let channel =
+///     Channel::<u32, 16, _>::with_notifier(KernelEvent::new(EventType::Synchronization, false));
+///
+/// // In a DPC
+/// if channel.send(42).is_err() {
+///     // The channel is full, so the value is dropped
+/// }
+///
+/// // In a worker thread, at PASSIVE_LEVEL
+/// let value = channel.recv();
+/// ```
+pub struct Channel<T, const N: usize, S = ()> {
+    slots: [ChannelSlot<T>; N],
+    send_position: AtomicUsize,
+    receive_position: AtomicUsize,
+    notifier: S,
+}
+
+/// Slot of a [`Channel`], whose sequence number tells whether it is ready to
+/// be written to (when it equals the position of the next send into the slot)
+/// or read from (when it equals that position plus one).
+///
+/// The sequence number is stored relative to the index of the slot, so that
+/// every slot of an empty channel starts at 0 and the slots can be created in
+/// a `const fn`.
+struct ChannelSlot<T> {
+    relative_sequence: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> ChannelSlot<T> {
+    const fn new() -> Self {
+        Self {
+            relative_sequence: AtomicUsize::new(0),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+}
+
+// SAFETY: Each value is moved into the channel by exactly one sender and out of
+// it by exactly one receiver, as synchronized by the sequence number of its
+// slot, so `T` only needs to be `Send`. The notifier is shared by all senders,
+// so it must be `Sync`.
+unsafe impl<T: Send, const N: usize, S: Sync> Sync for Channel<T, N, S> {}
+
+// SAFETY: Sending a `Channel` to another thread sends the values it contains
+// and its notifier with it
+unsafe impl<T: Send, const N: usize, S: Send> Send for Channel<T, N, S> {}
+
+impl<T, const N: usize> Channel<T, N> {
+    /// Creates an empty [`Channel`], which does not notify its receivers when
+    /// a value is sent
+    #[must_use]
+    pub const fn new() -> Self {
+        Self::with_notifier(())
+    }
+}
+
+impl<T, const N: usize, S: Notify> Channel<T, N, S> {
+    /// Creates an empty [`Channel`], which calls `notifier` after each value
+    /// is sent
+    #[must_use]
+    pub const fn with_notifier(notifier: S) -> Self {
+        const {
+            assert!(
+                N.is_power_of_two(),
+                "capacity of Channel should be a power of two"
+            );
+        }
+        Self {
+            slots: [const { ChannelSlot::new() }; N],
+            send_position: AtomicUsize::new(0),
+            receive_position: AtomicUsize::new(0),
+            notifier,
+        }
+    }
+
+    /// Sends `value` to the receivers of the channel, then calls the notifier
+    /// of the channel.
+    ///
+    /// This can be called at any `IRQL`, but the notifier may restrict it
+    /// further (ex. a [`KernelEvent`] can only be signaled at `IRQL` <=
+    /// `DISPATCH_LEVEL`).
+    ///
+    /// # Errors
+    ///
+    /// Returns `value` back if the channel is full
+    pub fn send(&self, value: T) -> Result<(), T> {
+        let mut position = self.send_position.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[position % N];
+            let sequence = slot
+                .relative_sequence
+                .load(Ordering::Acquire)
+                .wrapping_add(position % N);
+            // Positions wrap around, so they are compared through the sign of their
+            // difference
+            #[allow(clippy::cast_possible_wrap)]
+            let lag = sequence.wrapping_sub(position) as isize;
+            if lag < 0 {
+                // The slot still holds the value sent `N` positions earlier
+                return Err(value);
+            }
+            if lag > 0 {
+                // Another sender claimed `position`
+                position = self.send_position.load(Ordering::Relaxed);
+                continue;
+            }
+            match self.send_position.compare_exchange_weak(
+                position,
+                position.wrapping_add(1),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    // SAFETY: Claiming `position` gives this sender exclusive access to the
+                    // slot, which no receiver reads until its sequence number is updated
+                    // below
+                    unsafe {
+                        (*slot.value.get()).write(value);
+                    }
+                    slot.relative_sequence.store(
+                        position.wrapping_add(1).wrapping_sub(position % N),
+                        Ordering::Release,
+                    );
+                    self.notifier.notify();
+                    return Ok(());
+                }
+                Err(current_position) => position = current_position,
+            }
+        }
+    }
+
+    /// Receives the oldest value in the channel, or returns `None` if the
+    /// channel is empty.
+    ///
+    /// This can be called at any `IRQL`.
+    pub fn try_recv(&self) -> Option<T> {
+        let mut position = self.receive_position.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[position % N];
+            let sequence = slot
+                .relative_sequence
+                .load(Ordering::Acquire)
+                .wrapping_add(position % N);
+            // Positions wrap around, so they are compared through the sign of their
+            // difference
+            #[allow(clippy::cast_possible_wrap)]
+            let lag = sequence.wrapping_sub(position.wrapping_add(1)) as isize;
+            if lag < 0 {
+                // No value has been sent to the slot yet
+                return None;
+            }
+            if lag > 0 {
+                // Another receiver claimed `position`
+                position = self.receive_position.load(Ordering::Relaxed);
+                continue;
+            }
+            match self.receive_position.compare_exchange_weak(
+                position,
+                position.wrapping_add(1),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    // SAFETY: The sequence number of the slot shows that a value was written
+                    // to it, and claiming `position` gives this receiver exclusive access to
+                    // the slot until its sequence number is updated below
+                    let value = unsafe { (*slot.value.get()).assume_init_read() };
+                    slot.relative_sequence.store(
+                        position.wrapping_add(N).wrapping_sub(position % N),
+                        Ordering::Release,
+                    );
+                    return Some(value);
+                }
+                Err(current_position) => position = current_position,
+            }
+        }
+    }
+
+    /// Returns the number of values in the channel. Since values can be sent
+    /// and received concurrently, this is only a snapshot.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        let receive_position = self.receive_position.load(Ordering::Relaxed);
+        let send_position = self.send_position.load(Ordering::Relaxed);
+        send_position.wrapping_sub(receive_position).min(N)
+    }
+
+    /// Returns `true` if the channel has no values. Since values can be sent
+    /// and received concurrently, this is only a snapshot.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the maximum number of values that the channel can hold
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the notifier that the channel calls after each value is sent
+    #[must_use]
+    pub const fn notifier(&self) -> &S {
+        &self.notifier
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T, const N: usize> Channel<T, N, KernelEvent> {
+    /// Receives the oldest value in the channel, waiting until a value is sent
+    /// if the channel is empty.
+    ///
+    /// This must be called at `IRQL` <= `APC_LEVEL`. In debug builds, this is
+    /// asserted at runtime.
+    pub fn recv(&self) -> T {
+        loop {
+            // The event is reset before checking for values, so that a value sent after
+            // the check signals the event again
+            self.notifier.reset();
+            if let Some(value) = self.try_recv() {
+                return value;
+            }
+            self.notifier.wait();
+        }
+    }
+
+    /// Receives the oldest value in the channel, waiting until a value is sent
+    /// or until `timeout` elapses if the channel is empty. Returns `None` if
+    /// no value was received, which can also happen before `timeout` elapses
+    /// if another receiver received the value that was sent.
+    ///
+    /// This must be called at `IRQL` <= `APC_LEVEL`, or at `IRQL` <=
+    /// `DISPATCH_LEVEL` with a `timeout` of zero. In debug builds, this is
+    /// asserted at runtime.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<T> {
+        self.notifier.reset();
+        if let Some(value) = self.try_recv() {
+            return Some(value);
+        }
+        self.notifier.wait_for(timeout);
+        self.try_recv()
+    }
+}
+
+impl<T, const N: usize> Default for Channel<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize, S> fmt::Debug for Channel<T, N, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let receive_position = self.receive_position.load(Ordering::Relaxed);
+        let send_position = self.send_position.load(Ordering::Relaxed);
+        f.debug_struct("Channel")
+            .field("len", &send_position.wrapping_sub(receive_position).min(N))
+            .field("capacity", &N)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, const N: usize, S> Drop for Channel<T, N, S> {
+    fn drop(&mut self) {
+        let send_position = *self.send_position.get_mut();
+        let mut position = *self.receive_position.get_mut();
+        while position != send_position {
+            // SAFETY: The channel is exclusively borrowed, so no sends or receives are in
+            // progress, and every slot between the receive and send positions holds a
+            // value that was sent and not received
+            unsafe {
+                self.slots[position % N].value.get_mut().assume_init_drop();
+            }
+            position = position.wrapping_add(1);
+        }
+    }
+}
+
+/// A hook that a [`Channel`] calls after each value is sent, to wake up its
+/// receivers.
+///
+/// The hook is called at the `IRQL` of the sender, and should be short.
+pub trait Notify {
+    /// Notifies the receivers of the channel that a value was sent
+    fn notify(&self);
+}
+
+/// Does not notify the receivers, which are expected to poll the channel
+impl Notify for () {
+    fn notify(&self) {}
+}
+
+/// Calls the closure (ex. to queue a work item that drains the channel)
+impl<F: Fn()> Notify for F {
+    fn notify(&self) {
+        self();
+    }
+}
+
+/// Signals the event. Values can then only be sent at `IRQL` <=
+/// `DISPATCH_LEVEL`.
+#[cfg(feature = "alloc")]
+impl Notify for KernelEvent {
+    fn notify(&self) {
+        self.set();
+    }
+}
+
+/// A kernel dispatcher object (ex. a [`KernelEvent`] or a [`Semaphore`]),
+/// which threads can wait on until it is signaled.
+///
+/// Waits are non-alertable. Waiting with a timeout of zero only checks
+/// whether the object is signaled, and can be done at `IRQL` <=
+/// `DISPATCH_LEVEL`. All other waits must be done at `IRQL` <= `APC_LEVEL`.
+/// In debug builds, this is asserted at runtime.
+///
+/// # Safety
+///
+/// [`DispatcherObject::as_dispatcher_object`] must return a pointer to an
+/// initialized dispatcher object (ex. a `KEVENT`, `KSEMAPHORE`, `KMUTEX` or
+/// `KTIMER`) in non-paged memory, which stays valid for as long as `self` is
+/// borrowed.
+pub unsafe trait DispatcherObject {
+    /// Returns a pointer to the underlying dispatcher object
+    fn as_dispatcher_object(&self) -> PVOID;
+
+    /// Waits until the object is signaled
+    fn wait(&self) {
+        let signaled = wait_for_single_object(self.as_dispatcher_object(), None);
+        debug_assert!(signaled, "wait without a timeout should not time out");
+    }
+
+    /// Waits until the object is signaled, or until `timeout` elapses, and
+    /// returns whether the object was signaled
+    fn wait_for(&self, timeout: Duration) -> bool {
+        wait_for_single_object(self.as_dispatcher_object(), Some(timeout))
+    }
+}
+
+/// Waits until any of `objects` is signaled, or until `timeout` elapses, and
+/// returns the index of the object that was signaled, or `None` if the wait
+/// timed out. A `timeout` of `None` waits indefinitely.
+///
+/// # Panics
+///
+/// Panics if `objects` is empty, or has more than `MAXIMUM_WAIT_OBJECTS`
+/// objects.
+#[cfg(feature = "alloc")]
+pub fn wait_any(objects: &[&dyn DispatcherObject], timeout: Option<Duration>) -> Option<usize> {
+    let status = wait_for_multiple_objects(objects, _WAIT_TYPE::WaitAny, timeout);
+    if status == STATUS_TIMEOUT {
+        return None;
+    }
+    // `KeWaitForMultipleObjects` returns `STATUS_WAIT_0` + the index of the
+    // signaled object
+    usize::try_from(status - STATUS_WAIT_0).ok()
+}
+
+/// Waits until all of `objects` are signaled, or until `timeout` elapses, and
+/// returns whether the objects were signaled. A `timeout` of `None` waits
+/// indefinitely.
+///
+/// # Panics
+///
+/// Panics if `objects` is empty, or has more than `MAXIMUM_WAIT_OBJECTS`
+/// objects.
+#[cfg(feature = "alloc")]
+pub fn wait_all(objects: &[&dyn DispatcherObject], timeout: Option<Duration>) -> bool {
+    wait_for_multiple_objects(objects, _WAIT_TYPE::WaitAll, timeout) != STATUS_TIMEOUT
+}
+
+fn wait_for_single_object(object: PVOID, timeout: Option<Duration>) -> bool {
+    debug_assert_wait_irql(timeout);
+
+    let mut interval = timeout.map(relative_interval);
+    // SAFETY: `object` is a valid dispatcher object, as guaranteed by the
+    // implementation of `DispatcherObject`, `interval` lives for the duration of
+    // the call, and the IRQL requirement of `KeWaitForSingleObject` is asserted
+    // above
+    let status = unsafe {
+        KeWaitForSingleObject(
+            object,
+            _KWAIT_REASON::Executive,
+            KERNEL_MODE,
+            u8::from(false),
+            interval.as_mut().map_or(ptr::null_mut(), ptr::from_mut),
+        )
+    };
+    status != STATUS_TIMEOUT
+}
+
+#[cfg(feature = "alloc")]
+fn wait_for_multiple_objects(
+    objects: &[&dyn DispatcherObject],
+    wait_type: WAIT_TYPE,
+    timeout: Option<Duration>,
+) -> NTSTATUS {
+    assert!(
+        !objects.is_empty() && objects.len() <= MAXIMUM_WAIT_OBJECTS as usize,
+        "between 1 and MAXIMUM_WAIT_OBJECTS objects should be waited on"
+    );
+    debug_assert_wait_irql(timeout);
+
+    let mut dispatcher_objects = objects
+        .iter()
+        .map(|object| object.as_dispatcher_object())
+        .collect::<Vec<_>>();
+    // Waits on more than `THREAD_WAIT_OBJECTS` objects require a wait block for
+    // each object, which must be in non-paged memory
+    let mut wait_blocks = (objects.len() > THREAD_WAIT_OBJECTS as usize).then(|| {
+        (0..objects.len())
+            .map(|_| KWAIT_BLOCK::default())
+            .collect::<Vec<_>>()
+    });
+    let mut interval = timeout.map(relative_interval);
+    // clippy::cast_possible_truncation cannot check the bound asserted above
+    #[allow(clippy::cast_possible_truncation)]
+    let count = objects.len() as ULONG;
+
+    // SAFETY: Every pointer in `dispatcher_objects` is a valid dispatcher object,
+    // as guaranteed by the implementations of `DispatcherObject`. `wait_blocks`
+    // has a wait block for each object when more than `THREAD_WAIT_OBJECTS`
+    // objects are waited on, and `dispatcher_objects`, `wait_blocks` and
+    // `interval` all live for the duration of the call. The IRQL requirement of
+    // `KeWaitForMultipleObjects` is asserted above.
+    unsafe {
+        KeWaitForMultipleObjects(
+            count,
+            dispatcher_objects.as_mut_ptr(),
+            wait_type,
+            _KWAIT_REASON::Executive,
+            KERNEL_MODE,
+            u8::from(false),
+            interval.as_mut().map_or(ptr::null_mut(), ptr::from_mut),
+            wait_blocks
+                .as_mut()
+                .map_or(ptr::null_mut(), Vec::as_mut_ptr),
+        )
+    }
+}
+
+fn debug_assert_wait_irql(timeout: Option<Duration>) {
+    debug_assert!(
+        current_irql() <= APC_LEVEL
+            || (current_irql() <= DISPATCH_LEVEL
+                && timeout.is_some_and(|timeout| timeout.is_zero())),
+        "dispatcher objects should only be waited on at IRQL <= APC_LEVEL, or at IRQL <= \
+         DISPATCH_LEVEL with a timeout of zero"
+    );
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reentrant_resource_acquisition_is_rejected() {
+        // Not held by the current thread
+        assert!(ResourceAccess::Shared.is_allowed_while_holding(false, 0));
+        assert!(ResourceAccess::Exclusive.is_allowed_while_holding(false, 0));
+
+        // Held for shared access
+        assert!(ResourceAccess::Shared.is_allowed_while_holding(false, 1));
+        assert!(!ResourceAccess::Exclusive.is_allowed_while_holding(false, 1));
+
+        // Held for exclusive access
+        assert!(!ResourceAccess::Shared.is_allowed_while_holding(true, 1));
+        assert!(!ResourceAccess::Exclusive.is_allowed_while_holding(true, 1));
+    }
+}
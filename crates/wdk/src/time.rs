@@ -0,0 +1,387 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! IRQL-aware timestamps, sleep and delay utilities
+//!
+//! [`Instant`] is a monotonic, high-resolution timestamp read from the
+//! performance counter via [`KeQueryPerformanceCounter`], and
+//! [`interrupt_time`] returns the time since boot as tracked by the system
+//! interrupt time. Both can be used at any `IRQL`, so they are suitable for
+//! latency measurements in DPCs and ISRs.
+//!
+//! [`sleep`] and [`sleep_alertable`] yield the current thread to the scheduler
+//! via [`KeDelayExecutionThread`], and can only be used at `IRQL` <=
+//! `APC_LEVEL`. [`spin_wait`] and [`spin_wait_until`] busy-wait the current
+//! processor via [`KeStallExecutionProcessor`], and can be used at any `IRQL`,
+//! but should only ever be used for very short delays.
+
+use core::{
+    ops::{Add, AddAssign, Sub, SubAssign},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use wdk_sys::{
+    ntddk::{
+        KeDelayExecutionThread,
+        KeGetCurrentIrql,
+        KeQueryInterruptTimePrecise,
+        KeQueryPerformanceCounter,
+        KeStallExecutionProcessor,
+    },
+    _MODE::KernelMode,
+    APC_LEVEL,
+    DISPATCH_LEVEL,
+    KPROCESSOR_MODE,
+    LARGE_INTEGER,
+    NTSTATUS,
+    ULONG,
+};
+
+/// The longest duration that [`spin_wait`] should be used for when called at
+/// `IRQL` >= `DISPATCH_LEVEL`.
+///
+/// Stalling a processor at raised `IRQL` prevents any other work from being
+/// scheduled on it, so the [`KeStallExecutionProcessor` documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdm/nf-wdm-kestallexecutionprocessor#remarks)
+/// recommends never stalling for longer than 50 microseconds.
+pub const MAX_SPIN_WAIT_AT_DISPATCH_LEVEL: Duration = Duration::from_micros(50);
+
+// clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) const KERNEL_MODE: KPROCESSOR_MODE = KernelMode as KPROCESSOR_MODE;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// Frequency of the performance counter, in ticks per second. The frequency
+/// is fixed at boot, so it is only queried once.
+static PERFORMANCE_FREQUENCY: AtomicU64 = AtomicU64::new(0);
+
+/// A monotonic, high-resolution timestamp, read from the performance counter.
+///
+/// This is the kernel-mode equivalent of [`std::time::Instant`]: it is only
+/// useful to measure the [`Duration`] between two timestamps (ex. the latency
+/// of a hardware operation), and never goes backwards. Timestamps can be taken
+/// at any `IRQL`.
+///
+/// # Example
+///
+/// ```rust, no_run
+/// use wdk::time::Instant;
+///
+/// let start = Instant::now();
+/// // ... program the hardware, and wait for it to complete ...
+/// let latency = start.elapsed();
+/// ```
+///
+/// [`std::time::Instant`]: https://doc.rust-lang.org/std/time/struct.Instant.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant {
+    ticks: u64,
+}
+
+impl Instant {
+    /// Returns the current value of the performance counter.
+    ///
+    /// This can be called at any `IRQL`.
+    #[must_use]
+    pub fn now() -> Self {
+        // SAFETY: `KeQueryPerformanceCounter` is safe to call at any IRQL, and accepts
+        // a null pointer when the caller does not need the frequency
+        let counter = unsafe { KeQueryPerformanceCounter(core::ptr::null_mut()) };
+
+        // SAFETY: `QuadPart` covers the whole union, so it is always initialized
+        let ticks = unsafe { counter.QuadPart };
+        Self {
+            ticks: ticks.try_into().unwrap_or_default(),
+        }
+    }
+
+    /// Returns the raw value of the performance counter at this timestamp, in
+    /// ticks of [`Instant::frequency`]
+    #[must_use]
+    pub const fn ticks(self) -> u64 {
+        self.ticks
+    }
+
+    /// Returns the frequency of the performance counter, in ticks per second
+    #[must_use]
+    pub fn frequency() -> u64 {
+        let frequency = PERFORMANCE_FREQUENCY.load(Ordering::Relaxed);
+        if frequency != 0 {
+            return frequency;
+        }
+
+        let mut frequency = LARGE_INTEGER::default();
+        // SAFETY: `KeQueryPerformanceCounter` is safe to call at any IRQL, and
+        // `frequency` is a valid `LARGE_INTEGER` that lives for the duration of the
+        // call
+        unsafe {
+            KeQueryPerformanceCounter(&mut frequency);
+        }
+
+        // SAFETY: `QuadPart` covers the whole union, so it is always initialized
+        let frequency = unsafe { frequency.QuadPart }.try_into().unwrap_or_default();
+        // The frequency is fixed at boot, so racing writers always store the same value
+        PERFORMANCE_FREQUENCY.store(frequency, Ordering::Relaxed);
+        frequency
+    }
+
+    /// Returns the time elapsed since this timestamp.
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        Self::now().saturating_duration_since(*self)
+    }
+
+    /// Returns the time elapsed from `earlier` to this timestamp, or `None` if
+    /// `earlier` is later than this timestamp
+    #[must_use]
+    pub fn checked_duration_since(&self, earlier: Self) -> Option<Duration> {
+        self.ticks.checked_sub(earlier.ticks).map(ticks_to_duration)
+    }
+
+    /// Returns the time elapsed from `earlier` to this timestamp, or
+    /// [`Duration::ZERO`] if `earlier` is later than this timestamp
+    #[must_use]
+    pub fn saturating_duration_since(&self, earlier: Self) -> Duration {
+        self.checked_duration_since(earlier).unwrap_or_default()
+    }
+
+    /// Returns the time elapsed from `earlier` to this timestamp.
+    ///
+    /// This saturates to [`Duration::ZERO`] if `earlier` is later than this
+    /// timestamp, like [`std::time::Instant::duration_since`].
+    ///
+    /// [`std::time::Instant::duration_since`]: https://doc.rust-lang.org/std/time/struct.Instant.html#method.duration_since
+    #[must_use]
+    pub fn duration_since(&self, earlier: Self) -> Duration {
+        self.saturating_duration_since(earlier)
+    }
+
+    /// Returns the timestamp `duration` after this one, or `None` if it cannot
+    /// be represented
+    #[must_use]
+    pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+        self.ticks
+            .checked_add(duration_to_ticks(duration)?)
+            .map(|ticks| Self { ticks })
+    }
+
+    /// Returns the timestamp `duration` before this one, or `None` if it
+    /// cannot be represented
+    #[must_use]
+    pub fn checked_sub(&self, duration: Duration) -> Option<Self> {
+        self.ticks
+            .checked_sub(duration_to_ticks(duration)?)
+            .map(|ticks| Self { ticks })
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Self;
+
+    /// # Panics
+    ///
+    /// This function will panic if the resulting timestamp cannot be
+    /// represented. See [`Instant::checked_add`] for a version without panic.
+    fn add(self, duration: Duration) -> Self {
+        self.checked_add(duration)
+            .expect("overflow when adding duration to instant")
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Self;
+
+    /// # Panics
+    ///
+    /// This function will panic if the resulting timestamp cannot be
+    /// represented. See [`Instant::checked_sub`] for a version without panic.
+    fn sub(self, duration: Duration) -> Self {
+        self.checked_sub(duration)
+            .expect("overflow when subtracting duration from instant")
+    }
+}
+
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, duration: Duration) {
+        *self = *self - duration;
+    }
+}
+
+impl Sub<Self> for Instant {
+    type Output = Duration;
+
+    /// Returns the time elapsed from `earlier` to this timestamp, saturating to
+    /// [`Duration::ZERO`]
+    fn sub(self, earlier: Self) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+/// Returns the time elapsed since the system booted, as tracked by the system
+/// interrupt time.
+///
+/// Unlike [`Instant`], the interrupt time includes the time that the system
+/// spent in sleep or hibernation, and is in units of 100 nanoseconds. This can
+/// be called at any `IRQL`.
+#[must_use]
+pub fn interrupt_time() -> Duration {
+    // SAFETY: `KeQueryInterruptTimePrecise` is safe to call at any IRQL, and
+    // accepts a null pointer when the caller does not need the performance counter
+    // value
+    let hundred_nanosecond_intervals =
+        unsafe { KeQueryInterruptTimePrecise(core::ptr::null_mut()) };
+    Duration::from_nanos(hundred_nanosecond_intervals.saturating_mul(100))
+}
+
+/// Put the current thread into a non-alertable wait for at least `duration`.
+///
+/// The actual wait time is rounded up to the granularity of the system clock,
+/// so the thread may sleep for longer than `duration`.
+///
+/// This must be called at `IRQL` <= `APC_LEVEL`. In debug builds, this is
+/// asserted at runtime.
+pub fn sleep(duration: Duration) {
+    let nt_status = delay_execution_thread(duration, false);
+
+    // A non-alertable wait in kernel mode can only return `STATUS_SUCCESS`
+    debug_assert!(crate::nt_success(nt_status));
+}
+
+/// Put the current thread into an alertable wait for at least `duration`.
+///
+/// Returns `STATUS_SUCCESS` if the full duration elapsed, or `STATUS_ALERTED`
+/// if the wait was interrupted by an alert. Full documentation on the possible
+/// return values is available in the [`KeDelayExecutionThread` documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdm/nf-wdm-kedelayexecutionthread#return-value)
+///
+/// This must be called at `IRQL` <= `APC_LEVEL`. In debug builds, this is
+/// asserted at runtime.
+#[must_use]
+pub fn sleep_alertable(duration: Duration) -> NTSTATUS {
+    delay_execution_thread(duration, true)
+}
+
+/// Busy-wait the current processor for at least `duration`.
+///
+/// This does not yield the processor, and is intended for very short delays
+/// where the caller cannot wait on a dispatcher object (ex. waiting on a
+/// hardware register from a DPC). Prefer [`sleep`] whenever the caller is
+/// running at `IRQL` <= `APC_LEVEL`.
+///
+/// When called at `IRQL` >= `DISPATCH_LEVEL`, `duration` should never exceed
+/// [`MAX_SPIN_WAIT_AT_DISPATCH_LEVEL`]. In debug builds, this is asserted at
+/// runtime.
+pub fn spin_wait(duration: Duration) {
+    debug_assert!(
+        duration <= MAX_SPIN_WAIT_AT_DISPATCH_LEVEL || current_irql() < DISPATCH_LEVEL,
+        "spin_wait should not stall for longer than {MAX_SPIN_WAIT_AT_DISPATCH_LEVEL:?} at IRQL \
+         >= DISPATCH_LEVEL"
+    );
+
+    // Round up so that the processor is never stalled for less than `duration`
+    let microseconds = duration
+        .as_nanos()
+        .div_ceil(1_000)
+        .try_into()
+        .unwrap_or(ULONG::MAX);
+
+    // SAFETY: `KeStallExecutionProcessor` is safe to call at any IRQL
+    unsafe {
+        KeStallExecutionProcessor(microseconds);
+    }
+}
+
+/// Busy-wait the current processor until `condition` returns `true`, for at
+/// most `timeout`.
+///
+/// Returns `true` if `condition` returned `true`, or `false` if `timeout`
+/// elapsed first. `condition` is polled about once per microsecond, which is
+/// suitable for polling a hardware register (ex. waiting for a device to
+/// acknowledge a command from a DPC).
+///
+/// Like [`spin_wait`], when called at `IRQL` >= `DISPATCH_LEVEL`, `timeout`
+/// should never exceed [`MAX_SPIN_WAIT_AT_DISPATCH_LEVEL`]. In debug builds,
+/// this is asserted at runtime.
+pub fn spin_wait_until<F: FnMut() -> bool>(timeout: Duration, mut condition: F) -> bool {
+    debug_assert!(
+        timeout <= MAX_SPIN_WAIT_AT_DISPATCH_LEVEL || current_irql() < DISPATCH_LEVEL,
+        "spin_wait_until should not stall for longer than {MAX_SPIN_WAIT_AT_DISPATCH_LEVEL:?} at \
+         IRQL >= DISPATCH_LEVEL"
+    );
+
+    let start = Instant::now();
+    loop {
+        if condition() {
+            return true;
+        }
+        if start.elapsed() >= timeout {
+            return false;
+        }
+
+        // SAFETY: `KeStallExecutionProcessor` is safe to call at any IRQL
+        unsafe {
+            KeStallExecutionProcessor(1);
+        }
+    }
+}
+
+fn delay_execution_thread(duration: Duration, alertable: bool) -> NTSTATUS {
+    debug_assert!(
+        current_irql() <= APC_LEVEL,
+        "sleep should only be called at IRQL <= APC_LEVEL"
+    );
+
+    let mut interval = relative_interval(duration);
+
+    // SAFETY: `interval` is a valid `LARGE_INTEGER` that lives for the duration of
+    // the call, and the IRQL requirement of `KeDelayExecutionThread` is upheld by
+    // the callers of this function
+    unsafe { KeDelayExecutionThread(KERNEL_MODE, u8::from(alertable), &mut interval) }
+}
+
+/// Converts `duration` to the relative interval expected by kernel wait
+/// routines, rounded up so that the thread never waits for less than
+/// `duration`
+pub(crate) fn relative_interval(duration: Duration) -> LARGE_INTEGER {
+    let hundred_nanosecond_intervals: i64 = duration
+        .as_nanos()
+        .div_ceil(100)
+        .try_into()
+        .unwrap_or(i64::MAX);
+
+    // Negative values represent a relative interval, in units of 100 nanoseconds
+    LARGE_INTEGER {
+        QuadPart: -hundred_nanosecond_intervals,
+    }
+}
+
+pub(crate) fn current_irql() -> ULONG {
+    // SAFETY: `KeGetCurrentIrql` is safe to call at any IRQL
+    ULONG::from(unsafe { KeGetCurrentIrql() })
+}
+
+/// Converts a number of performance counter ticks to a [`Duration`], rounded
+/// down
+fn ticks_to_duration(ticks: u64) -> Duration {
+    let frequency = u128::from(Instant::frequency().max(1));
+    let nanoseconds = u128::from(ticks) * NANOS_PER_SEC / frequency;
+    Duration::from_nanos(nanoseconds.try_into().unwrap_or(u64::MAX))
+}
+
+/// Converts a [`Duration`] to a number of performance counter ticks, rounded
+/// up, or `None` if it does not fit in a `u64`
+fn duration_to_ticks(duration: Duration) -> Option<u64> {
+    let frequency = u128::from(Instant::frequency());
+    duration
+        .as_nanos()
+        .checked_mul(frequency)?
+        .div_ceil(NANOS_PER_SEC)
+        .try_into()
+        .ok()
+}
@@ -0,0 +1,563 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Manifest-free ETW events in the `TraceLogging` format
+//!
+//! `TraceLogging` events are self-describing: the names and types of an event's
+//! fields are sent to ETW alongside the event, so no instrumentation manifest
+//! needs to be compiled or registered. A driver defines a
+//! [`TraceLoggingProvider`] with
+//! [`trace_logging_provider!`](crate::trace_logging_provider), registers it in
+//! `DriverEntry`, and writes events with
+//! [`trace_logging_write!`](crate::trace_logging_write):
+//!
+//! ```rust, ignore
+//! static PROVIDER: TraceLoggingProvider = wdk::trace_logging_provider!(
+//!     "Contoso.SampleDriver",
+//!     // {a3f0b2c4-5d6e-4f70-8192-a3b4c5d6e7f8}
+//!     GUID {
+//!         Data1: 0xa3f0_b2c4,
+//!         Data2: 0x5d6e,
+//!         Data3: 0x4f70,
+//!         Data4: [0x81, 0x92, 0xa3, 0xb4, 0xc5, 0xd6, 0xe7, 0xf8],
+//!     }
+//! );
+//!
+//! PROVIDER.register()?;
+//! wdk::trace_logging_write!(
+//!     PROVIDER,
+//!     "DeviceAdded",
+//!     level: Level::Information,
+//!     device_index = 3_u32,
+//!     hardware_id = "ROOT\\SAMPLE",
+//! );
+//! PROVIDER.unregister();
+//! ```
+//!
+//! Providers that should only be registered while a device is present (ex.
+//! one per device, in KMDF drivers) are registered with
+//! [`DeviceTraceLoggingProvider`] instead, which unregisters the provider when
+//! it is dropped.
+//!
+//! The event metadata is generated at compile time by `trace_logging_write!`,
+//! and the field values are only evaluated when a session has enabled the
+//! provider for the event's level and keyword.
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use core::ops::Deref;
+use core::{
+    ptr,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use wdk_sys::{
+    ntddk::{EtwProviderEnabled, EtwRegister, EtwSetInformation, EtwUnregister, EtwWriteTransfer},
+    _EVENT_INFO_CLASS,
+    EVENT_DESCRIPTOR,
+    GUID,
+    NTSTATUS,
+    REGHANDLE,
+    ULONG,
+};
+
+use crate::nt_success;
+
+/// Channel of `TraceLogging` events, which lets decoders on older versions of
+/// Windows recognize them as self-describing
+const WINEVENT_CHANNEL_TRACELOGGING: u8 = 11;
+
+/// `EVENT_DATA_DESCRIPTOR_TYPE_PROVIDER_METADATA`
+const EVENT_DATA_DESCRIPTOR_TYPE_PROVIDER_METADATA: u8 = 1;
+
+/// `EVENT_DATA_DESCRIPTOR_TYPE_EVENT_METADATA`
+const EVENT_DATA_DESCRIPTOR_TYPE_EVENT_METADATA: u8 = 2;
+
+/// Severity of an event. Sessions enable a provider up to a maximum level, and
+/// only receive events at or below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    /// Abnormal exit or termination
+    Critical = 1,
+    /// Severe error
+    Error = 2,
+    /// Warning, such as an allocation failure that was recovered from
+    Warning = 3,
+    /// Non-error informational event
+    Information = 4,
+    /// Detailed trace event
+    Verbose = 5,
+}
+
+/// An ETW provider that writes `TraceLogging` events.
+///
+/// Providers are defined in `static`s with
+/// [`trace_logging_provider!`](crate::trace_logging_provider), and must be
+/// [registered](TraceLoggingProvider::register) before events are written
+/// with [`trace_logging_write!`](crate::trace_logging_write). Events written
+/// while the provider is not registered are discarded.
+pub struct TraceLoggingProvider {
+    id: GUID,
+    metadata: &'static [u8],
+    reg_handle: AtomicU64,
+}
+
+impl TraceLoggingProvider {
+    /// Construct a [`TraceLoggingProvider`] from its provider metadata, which
+    /// is generated by
+    /// [`trace_logging_provider!`](crate::trace_logging_provider)
+    #[doc(hidden)]
+    #[must_use]
+    pub const fn __new(metadata: &'static [u8], id: GUID) -> Self {
+        Self {
+            id,
+            metadata,
+            reg_handle: AtomicU64::new(0),
+        }
+    }
+
+    /// Register the provider with ETW
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if ETW fails to register the provider. The error variant will contain a [`NTSTATUS`] of the failure. Full error documentation is available in the [EtwRegister Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdm/nf-wdm-etwregister#return-value)
+    pub fn register(&'static self) -> Result<(), NTSTATUS> {
+        // SAFETY: `self` is a `'static` reference, so it outlives the registration
+        unsafe { self.register_unchecked() }
+    }
+
+    /// Register the provider with ETW, without requiring it to be `'static`
+    ///
+    /// # Safety
+    ///
+    /// The provider must not be moved or freed until it is unregistered.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if ETW fails to register the provider. The error variant will contain a [`NTSTATUS`] of the failure. Full error documentation is available in the [EtwRegister Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdm/nf-wdm-etwregister#return-value)
+    unsafe fn register_unchecked(&self) -> Result<(), NTSTATUS> {
+        let mut reg_handle: REGHANDLE = 0;
+
+        let nt_status;
+        // SAFETY: `self.id` is a valid `GUID` that the caller guarantees outlives the
+        // registration, no enable callback is registered, and `reg_handle` is a valid
+        // location to store the registration handle
+        unsafe {
+            nt_status = EtwRegister(
+                ptr::addr_of!(self.id),
+                None,
+                ptr::null_mut(),
+                ptr::addr_of_mut!(reg_handle),
+            );
+        }
+        if !nt_success(nt_status) {
+            return Err(nt_status);
+        }
+
+        // The provider metadata starts with its total size as a `u16`
+        let metadata_length = ULONG::from(u16::from_le_bytes([self.metadata[0], self.metadata[1]]));
+        // Like `TraceLoggingRegister`, failing to set the provider traits is not fatal:
+        // the provider metadata is also sent with every event
+        //
+        // SAFETY: `reg_handle` was just registered, and `self.metadata` is valid for
+        // reads of `metadata_length` bytes
+        let _ = unsafe {
+            EtwSetInformation(
+                reg_handle,
+                _EVENT_INFO_CLASS::EventProviderSetTraits,
+                self.metadata.as_ptr().cast_mut().cast(),
+                metadata_length,
+            )
+        };
+
+        self.reg_handle.store(reg_handle, Ordering::Release);
+        Ok(())
+    }
+
+    /// Unregister the provider from ETW. This does nothing if the provider is
+    /// not registered.
+    pub fn unregister(&self) {
+        let reg_handle = self.reg_handle.swap(0, Ordering::AcqRel);
+        if reg_handle != 0 {
+            // SAFETY: `reg_handle` was registered by `register`, and is no longer
+            // reachable through `self.reg_handle`
+            let _ = unsafe { EtwUnregister(reg_handle) };
+        }
+    }
+
+    /// Returns whether any session has enabled the provider for events with
+    /// `level` and `keyword`
+    #[must_use]
+    pub fn is_enabled(&self, level: Level, keyword: u64) -> bool {
+        let reg_handle = self.reg_handle.load(Ordering::Acquire);
+        // SAFETY: `reg_handle` is either 0, which ETW reports as not enabled, or a
+        // registered handle
+        reg_handle != 0 && unsafe { EtwProviderEnabled(reg_handle, level as u8, keyword) } != 0
+    }
+
+    /// Describes the provider metadata of the provider, for the first data
+    /// descriptor of an event
+    #[doc(hidden)]
+    #[must_use]
+    pub fn __metadata_descriptor(&self) -> EventDataDescriptor {
+        EventDataDescriptor::new(self.metadata, EVENT_DATA_DESCRIPTOR_TYPE_PROVIDER_METADATA)
+    }
+
+    /// Write an event whose data descriptors are generated by
+    /// [`trace_logging_write!`](crate::trace_logging_write)
+    #[doc(hidden)]
+    pub fn __write(
+        &self,
+        level: Level,
+        opcode: u8,
+        keyword: u64,
+        data_descriptors: &[EventDataDescriptor],
+    ) {
+        let reg_handle = self.reg_handle.load(Ordering::Acquire);
+        if reg_handle == 0 {
+            return;
+        }
+
+        let event_descriptor = EVENT_DESCRIPTOR {
+            Channel: WINEVENT_CHANNEL_TRACELOGGING,
+            Level: level as u8,
+            Opcode: opcode,
+            Keyword: keyword,
+            ..EVENT_DESCRIPTOR::default()
+        };
+        let data_descriptor_count = ULONG::try_from(data_descriptors.len())
+            .expect("event data descriptor count should be limited by the number of fields");
+
+        // SAFETY: This is safe because:
+        //         1. `reg_handle` is a registered handle
+        //         2. `event_descriptor` is a valid `EVENT_DESCRIPTOR`
+        //         3. activity IDs are allowed to be null
+        //         4. `data_descriptors` points to `data_descriptor_count` descriptors
+        //            that are layout compatible with `EVENT_DATA_DESCRIPTOR`, and that
+        //            describe memory that is valid for the duration of this call
+        let _ = unsafe {
+            EtwWriteTransfer(
+                reg_handle,
+                ptr::addr_of!(event_descriptor),
+                ptr::null(),
+                ptr::null(),
+                data_descriptor_count,
+                data_descriptors.as_ptr().cast_mut().cast(),
+            )
+        };
+    }
+}
+
+/// A [`TraceLoggingProvider`] that is registered for the lifetime of a single
+/// device, instead of the lifetime of the driver.
+///
+/// The provider is registered in `EvtDriverDeviceAdd` (or `AddDevice`) with
+/// [`DeviceTraceLoggingProvider::register`], and is unregistered when the
+/// [`DeviceTraceLoggingProvider`] is dropped, so it should be stored in the
+/// device's context and dropped when the device is removed (ex. in the
+/// `EvtCleanupCallback` of the device). Several devices can each register a
+/// provider with the same name and provider ID.
+///
+/// Events are written with [`trace_logging_write!`](crate::trace_logging_write)
+/// through the [`TraceLoggingProvider`] that a [`DeviceTraceLoggingProvider`]
+/// dereferences to:
+///
+/// ```rust, ignore
+/// let provider = DeviceTraceLoggingProvider::register(wdk::trace_logging_provider!(
+///     "Contoso.SampleDriver.Device",
+///     PROVIDER_ID
+/// ))?;
+///
+/// wdk::trace_logging_write!(provider, "ReadCompleted", level: Level::Verbose, bytes_read = 512_u32);
+/// ```
+#[cfg(feature = "alloc")]
+pub struct DeviceTraceLoggingProvider {
+    provider: Box<TraceLoggingProvider>,
+}
+
+#[cfg(feature = "alloc")]
+impl DeviceTraceLoggingProvider {
+    /// Register `provider` with ETW
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if ETW fails to register the provider. The error variant will contain a [`NTSTATUS`] of the failure. Full error documentation is available in the [EtwRegister Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdm/nf-wdm-etwregister#return-value)
+    pub fn register(provider: TraceLoggingProvider) -> Result<Self, NTSTATUS> {
+        let provider = Box::new(provider);
+        // SAFETY: `provider` is heap allocated, so it is not moved while it is
+        // registered, and it is unregistered in `drop` before it is freed
+        unsafe {
+            provider.register_unchecked()?;
+        }
+        Ok(Self { provider })
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Deref for DeviceTraceLoggingProvider {
+    type Target = TraceLoggingProvider;
+
+    fn deref(&self) -> &Self::Target {
+        &self.provider
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Drop for DeviceTraceLoggingProvider {
+    fn drop(&mut self) {
+        self.provider.unregister();
+    }
+}
+
+/// Layout compatible equivalent of `EVENT_DATA_DESCRIPTOR`, describing a
+/// block of memory that is sent to ETW as part of an event
+#[doc(hidden)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct EventDataDescriptor {
+    ptr: u64,
+    size: u32,
+    descriptor_type: u8,
+    reserved1: u8,
+    reserved2: u16,
+}
+
+impl EventDataDescriptor {
+    fn new(data: &[u8], descriptor_type: u8) -> Self {
+        Self {
+            ptr: data.as_ptr() as u64,
+            // Event data is limited to 64KB, so saturating only affects events that ETW
+            // rejects anyways
+            size: u32::try_from(data.len()).unwrap_or(u32::MAX),
+            descriptor_type,
+            reserved1: 0,
+            reserved2: 0,
+        }
+    }
+
+    /// Describes the event metadata generated by
+    /// [`trace_logging_write!`](crate::trace_logging_write)
+    #[must_use]
+    pub fn event_metadata(metadata: &[u8]) -> Self {
+        Self::new(metadata, EVENT_DATA_DESCRIPTOR_TYPE_EVENT_METADATA)
+    }
+}
+
+/// Data of a single field of an event, as prepared by
+/// [`TraceLoggingField::field_data`]
+pub struct FieldData<'a> {
+    length_prefix: Option<u16>,
+    bytes: &'a [u8],
+}
+
+impl<'a> FieldData<'a> {
+    /// Data of a fixed-size field, sent as is
+    #[must_use]
+    pub const fn fixed(bytes: &'a [u8]) -> Self {
+        Self {
+            length_prefix: None,
+            bytes,
+        }
+    }
+
+    /// Data of a variable-size field, sent after its length in bytes as a
+    /// `u16`. Data longer than `u16::MAX` bytes is truncated.
+    #[must_use]
+    pub fn counted(bytes: &'a [u8]) -> Self {
+        let length = u16::try_from(bytes.len()).unwrap_or(u16::MAX);
+        Self {
+            length_prefix: Some(length),
+            bytes: &bytes[..usize::from(length)],
+        }
+    }
+
+    /// Describes the length prefix of the field, which is empty for
+    /// fixed-size fields
+    #[doc(hidden)]
+    #[must_use]
+    pub fn __length_prefix_descriptor(&self) -> EventDataDescriptor {
+        self.length_prefix.as_ref().map_or_else(
+            || EventDataDescriptor::new(&[], 0),
+            |length_prefix| EventDataDescriptor::new(plain_bytes(length_prefix), 0),
+        )
+    }
+
+    /// Describes the data of the field
+    #[doc(hidden)]
+    #[must_use]
+    pub fn __data_descriptor(&self) -> EventDataDescriptor {
+        EventDataDescriptor::new(self.bytes, 0)
+    }
+}
+
+/// A type that can be written as a field of a `TraceLogging` event with
+/// [`trace_logging_write!`](crate::trace_logging_write)
+pub trait TraceLoggingField {
+    /// `TraceLogging` input type of the field, which describes how its data is
+    /// encoded (ex. `TlgInUINT32`)
+    const IN_TYPE: u8;
+
+    /// `TraceLogging` output type of the field, which describes how decoders
+    /// should format it (ex. `TlgOutBOOLEAN`). `0` uses the default format of
+    /// [`IN_TYPE`](TraceLoggingField::IN_TYPE).
+    const OUT_TYPE: u8 = 0;
+
+    /// Prepare the data of the field for an event
+    fn field_data(&self) -> FieldData<'_>;
+}
+
+/// Returns the bytes of a value of a type without padding bytes (ex. integers,
+/// floating point types, `bool` and `GUID`)
+const fn plain_bytes<T: Copy>(value: &T) -> &[u8] {
+    // SAFETY: Only used for types without padding bytes, so all of the bytes of
+    // `value` are initialized
+    unsafe {
+        core::slice::from_raw_parts(ptr::from_ref(value).cast::<u8>(), core::mem::size_of::<T>())
+    }
+}
+
+macro_rules! impl_trace_logging_field_for_plain_types {
+    ($($t:ty => $in_type:expr),* $(,)?) => {
+        $(
+            impl TraceLoggingField for $t {
+                const IN_TYPE: u8 = $in_type;
+
+                fn field_data(&self) -> FieldData<'_> {
+                    FieldData::fixed(plain_bytes(self))
+                }
+            }
+        )*
+    };
+}
+
+impl_trace_logging_field_for_plain_types! {
+    i8 => 3,
+    u8 => 4,
+    i16 => 5,
+    u16 => 6,
+    i32 => 7,
+    u32 => 8,
+    i64 => 9,
+    u64 => 10,
+    f32 => 11,
+    f64 => 12,
+}
+
+#[cfg(target_pointer_width = "64")]
+impl_trace_logging_field_for_plain_types! {
+    isize => 9,
+    usize => 10,
+}
+
+#[cfg(target_pointer_width = "32")]
+impl_trace_logging_field_for_plain_types! {
+    isize => 7,
+    usize => 8,
+}
+
+impl TraceLoggingField for bool {
+    // `TlgInUINT8`
+    const IN_TYPE: u8 = 4;
+    // `TlgOutBOOLEAN`
+    const OUT_TYPE: u8 = 3;
+
+    fn field_data(&self) -> FieldData<'_> {
+        FieldData::fixed(plain_bytes(self))
+    }
+}
+
+impl TraceLoggingField for str {
+    // `TlgInCOUNTEDANSISTRING`
+    const IN_TYPE: u8 = 23;
+    // `TlgOutUTF8`
+    const OUT_TYPE: u8 = 35;
+
+    fn field_data(&self) -> FieldData<'_> {
+        FieldData::counted(self.as_bytes())
+    }
+}
+
+impl TraceLoggingField for [u8] {
+    // `TlgInBINARY`
+    const IN_TYPE: u8 = 14;
+
+    fn field_data(&self) -> FieldData<'_> {
+        FieldData::counted(self)
+    }
+}
+
+impl TraceLoggingField for GUID {
+    // `TlgInGUID`
+    const IN_TYPE: u8 = 15;
+
+    fn field_data(&self) -> FieldData<'_> {
+        FieldData::fixed(plain_bytes(self))
+    }
+}
+
+impl<T: TraceLoggingField + ?Sized> TraceLoggingField for &T {
+    const IN_TYPE: u8 = T::IN_TYPE;
+    const OUT_TYPE: u8 = T::OUT_TYPE;
+
+    fn field_data(&self) -> FieldData<'_> {
+        T::field_data(self)
+    }
+}
+
+/// Generate the `TraceLogging` provider metadata for a provider named `name`:
+/// its total size as a little-endian `u16`, followed by its nul-terminated
+/// name
+#[doc(hidden)]
+#[must_use]
+pub const fn __provider_metadata<const N: usize>(name: &str) -> [u8; N] {
+    assert!(
+        N == name.len() + 3 && N <= u16::MAX as usize,
+        "provider metadata size should match the provider name"
+    );
+
+    let mut metadata = [0; N];
+    // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+    #[allow(clippy::cast_possible_truncation)]
+    let size = (N as u16).to_le_bytes();
+    metadata[0] = size[0];
+    metadata[1] = size[1];
+
+    let name = name.as_bytes();
+    let mut i = 0;
+    while i < name.len() {
+        assert!(
+            name[i] != 0,
+            "provider name should not contain nul characters"
+        );
+        metadata[i + 2] = name[i];
+        i += 1;
+    }
+    metadata
+}
+
+/// Define a [`TraceLoggingProvider`] with the given name and provider ID
+/// (`GUID`). The provider metadata is generated at compile time.
+///
+/// ```rust, ignore
+/// static PROVIDER: TraceLoggingProvider =
+///     wdk::trace_logging_provider!("Contoso.SampleDriver", PROVIDER_ID);
+/// ```
+#[macro_export]
+macro_rules! trace_logging_provider {
+    ($name:literal, $id:expr $(,)?) => {
+        $crate::trace_logging::TraceLoggingProvider::__new(
+            {
+                const NAME: &str = $name;
+                const METADATA: [u8; NAME.len() + 3] =
+                    $crate::trace_logging::__provider_metadata::<{ NAME.len() + 3 }>(NAME);
+                &METADATA
+            },
+            $id,
+        )
+    };
+}
@@ -0,0 +1,1060 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Communication with USB devices from WDF USB client drivers
+//!
+//! A USB client driver creates a [`UsbDevice`] for its WDF device, typically
+//! from its `EvtDevicePrepareHardware` callback, and then selects a
+//! configuration to get the [`UsbInterface`]s of the device and their
+//! configured [`UsbPipe`]s:
+//!
+//! ```rust, ignore
+//! use wdk::usb::{PipeType, UsbDevice};
+//!
+//! let usb_device = UsbDevice::create(device)?;
+//! usb_device.select_single_interface()?;
+//!
+//! let interface = usb_device.interface(0).expect("interface 0 should be configured");
+//! let bulk_in_pipe = interface
+//!     .pipes()
+//!     .find(|pipe| pipe.pipe_type() == Some(PipeType::Bulk) && pipe.is_in())
+//!     .expect("interface should have a bulk IN pipe");
+//!
+//! let mut buffer = [0; 64];
+//! let bytes_read = bulk_in_pipe.read(&mut buffer)?;
+//! ```
+//!
+//! The synchronous transfers must be called at `IRQL` = `PASSIVE_LEVEL`. I/O
+//! queue callbacks can instead forward their [`Request`]s to a pipe with
+//! [`UsbPipe::send_read`] and [`UsbPipe::send_write`], whose
+//! [`TransferCompletion`] is called when the transfer completes.
+
+use core::{
+    mem::{self, size_of},
+    ptr,
+};
+
+use wdk_sys::{
+    _WdfUsbTargetDeviceSelectConfigType,
+    call_unsafe_wdf_function_binding,
+    _WDF_MEMORY_DESCRIPTOR_TYPE,
+    _WDF_USB_PIPE_TYPE,
+    _WDF_USB_REQUEST_TYPE,
+    NTSTATUS,
+    PVOID,
+    PWDF_REQUEST_COMPLETION_PARAMS,
+    STATUS_BUFFER_TOO_SMALL,
+    STATUS_INVALID_DEVICE_REQUEST,
+    STATUS_INVALID_PARAMETER,
+    ULONG,
+    USB_CONFIGURATION_DESCRIPTOR,
+    USB_DEVICE_DESCRIPTOR,
+    USB_ENDPOINT_DESCRIPTOR,
+    USB_ENDPOINT_DESCRIPTOR_TYPE,
+    USB_INTERFACE_DESCRIPTOR,
+    USB_INTERFACE_DESCRIPTOR_TYPE,
+    USHORT,
+    WDFCONTEXT,
+    WDFDEVICE,
+    WDFIOTARGET,
+    WDFMEMORY,
+    WDFREQUEST,
+    WDFUSBDEVICE,
+    WDFUSBINTERFACE,
+    WDFUSBPIPE,
+    WDF_MEMORY_DESCRIPTOR,
+    WDF_USB_CONTROL_SETUP_PACKET,
+    WDF_USB_DEVICE_CREATE_CONFIG,
+    WDF_USB_DEVICE_SELECT_CONFIG_PARAMS,
+    WDF_USB_PIPE_INFORMATION,
+};
+
+use crate::{nt_success, wdf::Request};
+
+/// Version of the USBD client contract that [`UsbDevice::create`] registers
+/// the driver with (`USBD_CLIENT_CONTRACT_VERSION_602` in `usbdlib.h`)
+const USBD_CLIENT_CONTRACT_VERSION_602: ULONG = 0x602;
+
+/// Bit of `bmRequestType` that is set for control transfers from the device
+/// to the host (`BMREQUEST_DEVICE_TO_HOST` in `usbspec.h`)
+const DEVICE_TO_HOST_REQUEST_TYPE: u8 = 0x80;
+
+/// Bit of `bEndpointAddress` that is set for IN endpoints
+/// (`USB_ENDPOINT_DIRECTION_MASK` in `usbspec.h`)
+const ENDPOINT_DIRECTION_IN: u8 = 0x80;
+
+/// Callback of an asynchronous USB transfer
+///
+/// It is called when a transfer sent with [`UsbPipe::send_read`],
+/// [`UsbPipe::send_write`] or [`UsbDevice::send_control_transfer`] completes,
+/// with the sent request and the number of bytes transferred, or the
+/// [`NTSTATUS`] of the failure. The callback receives ownership of the request
+/// back, and is responsible for completing it. It is also called, before the
+/// send function returns, if the request cannot be sent.
+pub type TransferCompletion = fn(request: Request, result: Result<usize, NTSTATUS>);
+
+/// WDF USB target device of a WDF device
+///
+/// The USB target device is parented to the WDF device it is created for, and
+/// is deleted by WDF when the device is deleted.
+pub struct UsbDevice {
+    usb_device: WDFUSBDEVICE,
+}
+
+impl UsbDevice {
+    /// Try to create the USB target device of `device`.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`, typically from the
+    /// `EvtDevicePrepareHardware` callback.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if WDF fails to create the USB target device. Full error documentation is available in the [WdfUsbTargetDeviceCreateWithParameters Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfusb/nf-wdfusb-wdfusbtargetdevicecreatewithparameters#return-value)
+    pub fn create(device: WDFDEVICE) -> Result<Self, NTSTATUS> {
+        // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+        #[allow(clippy::cast_possible_truncation)]
+        const WDF_USB_DEVICE_CREATE_CONFIG_SIZE: ULONG =
+            size_of::<WDF_USB_DEVICE_CREATE_CONFIG>() as ULONG;
+
+        let mut usb_device = Self {
+            usb_device: ptr::null_mut(),
+        };
+
+        // Equivalent to `WDF_USB_DEVICE_CREATE_CONFIG_INIT`
+        let mut create_config = WDF_USB_DEVICE_CREATE_CONFIG {
+            Size: WDF_USB_DEVICE_CREATE_CONFIG_SIZE,
+            USBDClientContractVersion: USBD_CLIENT_CONTRACT_VERSION_602,
+        };
+
+        let nt_status;
+        // SAFETY: `device` is a handle to a WDF device object. The resulting ffi object
+        // is stored in a private member and not accessible outside of this module.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfUsbTargetDeviceCreateWithParameters,
+                device,
+                &mut create_config,
+                ptr::null_mut(),
+                &mut usb_device.usb_device,
+            );
+        }
+        nt_success(nt_status).then_some(usb_device).ok_or(nt_status)
+    }
+
+    /// Returns the raw WDF USB target device handle
+    #[must_use]
+    pub const fn as_raw(&self) -> WDFUSBDEVICE {
+        self.usb_device
+    }
+
+    /// Returns the USB device descriptor of the device
+    #[must_use]
+    pub fn device_descriptor(&self) -> USB_DEVICE_DESCRIPTOR {
+        let mut device_descriptor = USB_DEVICE_DESCRIPTOR::default();
+        // SAFETY: `usb_device` is a private member of `UsbDevice`, originally created
+        // by WDF, and this module guarantees that it is always in a valid state.
+        unsafe {
+            call_unsafe_wdf_function_binding!(
+                WdfUsbTargetDeviceGetDeviceDescriptor,
+                self.usb_device,
+                &mut device_descriptor,
+            );
+        }
+        device_descriptor
+    }
+
+    /// Returns the size, in bytes, of the configuration descriptor of the
+    /// device, including its interface and endpoint descriptors. A buffer of
+    /// this size can hold the [`ConfigurationDescriptor`] returned by
+    /// [`UsbDevice::configuration_descriptor`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if WDF fails to retrieve the configuration descriptor. Full error documentation is available in the [WdfUsbTargetDeviceRetrieveConfigDescriptor Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfusb/nf-wdfusb-wdfusbtargetdeviceretrieveconfigdescriptor#return-value)
+    pub fn configuration_descriptor_size(&self) -> Result<usize, NTSTATUS> {
+        let mut length: USHORT = 0;
+
+        let nt_status;
+        // SAFETY: `usb_device` is a private member of `UsbDevice`, originally created
+        // by WDF, and this module guarantees that it is always in a valid state. A
+        // null buffer only queries the size of the descriptor.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfUsbTargetDeviceRetrieveConfigDescriptor,
+                self.usb_device,
+                ptr::null_mut(),
+                &mut length,
+            );
+        }
+        if nt_status == STATUS_BUFFER_TOO_SMALL || nt_success(nt_status) {
+            return Ok(usize::from(length));
+        }
+        Err(nt_status)
+    }
+
+    /// Retrieve the configuration descriptor of the device, including its
+    /// interface and endpoint descriptors, into `buffer`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_BUFFER_TOO_SMALL` if `buffer` is smaller than [`UsbDevice::configuration_descriptor_size`], and `STATUS_INVALID_DEVICE_REQUEST` if the retrieved descriptor is malformed. Otherwise, it will return an error if WDF fails to retrieve the configuration descriptor. Full error documentation is available in the [WdfUsbTargetDeviceRetrieveConfigDescriptor Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfusb/nf-wdfusb-wdfusbtargetdeviceretrieveconfigdescriptor#return-value)
+    pub fn configuration_descriptor<'a>(
+        &self,
+        buffer: &'a mut [u8],
+    ) -> Result<ConfigurationDescriptor<'a>, NTSTATUS> {
+        // A configuration descriptor is never larger than `USHORT::MAX` bytes, since
+        // that is the range of its `wTotalLength`
+        let mut length = USHORT::try_from(buffer.len()).unwrap_or(USHORT::MAX);
+
+        let nt_status;
+        // SAFETY: `usb_device` is a private member of `UsbDevice`, originally created
+        // by WDF, and this module guarantees that it is always in a valid state.
+        // `buffer` is at least `length` bytes long, and outlives the call.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfUsbTargetDeviceRetrieveConfigDescriptor,
+                self.usb_device,
+                buffer.as_mut_ptr().cast(),
+                &mut length,
+            );
+        }
+        if !nt_success(nt_status) {
+            return Err(nt_status);
+        }
+
+        buffer
+            .get(..usize::from(length))
+            .and_then(ConfigurationDescriptor::new)
+            .ok_or(STATUS_INVALID_DEVICE_REQUEST)
+    }
+
+    /// Selects the first configuration of the device, with the first
+    /// alternate setting of its only interface.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the device has more than one interface, or if WDF fails to select the configuration. Full error documentation is available in the [WdfUsbTargetDeviceSelectConfig Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfusb/nf-wdfusb-wdfusbtargetdeviceselectconfig#return-value)
+    pub fn select_single_interface(&self) -> Result<(), NTSTATUS> {
+        self.select_config(
+            _WdfUsbTargetDeviceSelectConfigType::WdfUsbTargetDeviceSelectConfigTypeSingleInterface,
+        )
+    }
+
+    /// Selects the first configuration of the device, with the first
+    /// alternate setting of each of its interfaces.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if WDF fails to select the configuration. Full error documentation is available in the [WdfUsbTargetDeviceSelectConfig Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfusb/nf-wdfusb-wdfusbtargetdeviceselectconfig#return-value)
+    pub fn select_multiple_interfaces(&self) -> Result<(), NTSTATUS> {
+        self.select_config(
+            _WdfUsbTargetDeviceSelectConfigType::WdfUsbTargetDeviceSelectConfigTypeMultiInterface,
+        )
+    }
+
+    /// Selects the first configuration of the device, like
+    /// `WDF_USB_DEVICE_SELECT_CONFIG_PARAMS_INIT_SINGLE_INTERFACE` and
+    /// `WDF_USB_DEVICE_SELECT_CONFIG_PARAMS_INIT_MULTIPLE_INTERFACES` (without
+    /// interface setting pairs)
+    fn select_config(
+        &self,
+        select_config_type: _WdfUsbTargetDeviceSelectConfigType::Type,
+    ) -> Result<(), NTSTATUS> {
+        // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+        #[allow(clippy::cast_possible_truncation)]
+        const WDF_USB_DEVICE_SELECT_CONFIG_PARAMS_SIZE: ULONG =
+            size_of::<WDF_USB_DEVICE_SELECT_CONFIG_PARAMS>() as ULONG;
+
+        let mut select_config_params = WDF_USB_DEVICE_SELECT_CONFIG_PARAMS {
+            Size: WDF_USB_DEVICE_SELECT_CONFIG_PARAMS_SIZE,
+            Type: select_config_type,
+            ..WDF_USB_DEVICE_SELECT_CONFIG_PARAMS::default()
+        };
+
+        let nt_status;
+        // SAFETY: `usb_device` is a private member of `UsbDevice`, originally created
+        // by WDF, and this module guarantees that it is always in a valid state.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfUsbTargetDeviceSelectConfig,
+                self.usb_device,
+                ptr::null_mut(),
+                &mut select_config_params,
+            );
+        }
+        nt_success(nt_status).then_some(()).ok_or(nt_status)
+    }
+
+    /// Returns the number of interfaces of the device
+    #[must_use]
+    pub fn interface_count(&self) -> u8 {
+        let interface_count;
+        // SAFETY: `usb_device` is a private member of `UsbDevice`, originally created
+        // by WDF, and this module guarantees that it is always in a valid state.
+        unsafe {
+            interface_count = call_unsafe_wdf_function_binding!(
+                WdfUsbTargetDeviceGetNumInterfaces,
+                self.usb_device
+            );
+        }
+        interface_count
+    }
+
+    /// Returns the interface of the device at `index`, or `None` if there is no
+    /// such interface
+    #[must_use]
+    pub fn interface(&self, index: u8) -> Option<UsbInterface> {
+        let usb_interface;
+        // SAFETY: `usb_device` is a private member of `UsbDevice`, originally created
+        // by WDF, and this module guarantees that it is always in a valid state.
+        unsafe {
+            usb_interface = call_unsafe_wdf_function_binding!(
+                WdfUsbTargetDeviceGetInterface,
+                self.usb_device,
+                index,
+            );
+        }
+        (!usb_interface.is_null()).then_some(UsbInterface { usb_interface })
+    }
+
+    /// Returns an iterator over the interfaces of the device
+    pub fn interfaces(&self) -> impl Iterator<Item = UsbInterface> + '_ {
+        (0..self.interface_count()).filter_map(|index| self.interface(index))
+    }
+
+    /// Sends a control transfer from the device to the host, which reads
+    /// into `buffer`, and returns the number of bytes read. The direction bit
+    /// of `setup_packet` is set accordingly.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_INVALID_PARAMETER` if `buffer` is larger than `ULONG::MAX` bytes. Otherwise, it will return an error if the control transfer fails. Full error documentation is available in the [WdfUsbTargetDeviceSendControlTransferSynchronously Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfusb/nf-wdfusb-wdfusbtargetdevicesendcontroltransfersynchronously#return-value)
+    pub fn control_transfer_in(
+        &self,
+        setup_packet: ControlSetupPacket,
+        buffer: &mut [u8],
+    ) -> Result<usize, NTSTATUS> {
+        let setup_packet = ControlSetupPacket {
+            request_type: setup_packet.request_type | DEVICE_TO_HOST_REQUEST_TYPE,
+            ..setup_packet
+        };
+        // SAFETY: `buffer` is exclusively borrowed, and outlives the synchronous
+        // transfer
+        unsafe { self.control_transfer(setup_packet, buffer.as_mut_ptr().cast(), buffer.len()) }
+    }
+
+    /// Sends a control transfer from the host to the device, which writes
+    /// `buffer`, and returns the number of bytes written. The direction bit of
+    /// `setup_packet` is cleared accordingly.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_INVALID_PARAMETER` if `buffer` is larger than `ULONG::MAX` bytes. Otherwise, it will return an error if the control transfer fails. Full error documentation is available in the [WdfUsbTargetDeviceSendControlTransferSynchronously Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfusb/nf-wdfusb-wdfusbtargetdevicesendcontroltransfersynchronously#return-value)
+    pub fn control_transfer_out(
+        &self,
+        setup_packet: ControlSetupPacket,
+        buffer: &[u8],
+    ) -> Result<usize, NTSTATUS> {
+        let setup_packet = ControlSetupPacket {
+            request_type: setup_packet.request_type & !DEVICE_TO_HOST_REQUEST_TYPE,
+            ..setup_packet
+        };
+        // SAFETY: `buffer` outlives the synchronous transfer, and is only read from
+        // since the direction of the transfer is from the host to the device
+        unsafe {
+            self.control_transfer(
+                setup_packet,
+                buffer.as_ptr().cast_mut().cast(),
+                buffer.len(),
+            )
+        }
+    }
+
+    /// Sends a control transfer with a data stage of `length` bytes at
+    /// `buffer`, and returns the number of bytes transferred
+    ///
+    /// # Safety
+    ///
+    /// `buffer` must be valid for `length` bytes for the duration of the call,
+    /// and must be writable if `setup_packet` is from the device to the host
+    unsafe fn control_transfer(
+        &self,
+        setup_packet: ControlSetupPacket,
+        buffer: PVOID,
+        length: usize,
+    ) -> Result<usize, NTSTATUS> {
+        let mut wdf_setup_packet = setup_packet.to_wdf();
+        let mut memory_descriptor = memory_descriptor(buffer, length)?;
+        let memory_descriptor = if length == 0 {
+            ptr::null_mut()
+        } else {
+            ptr::addr_of_mut!(memory_descriptor)
+        };
+        let mut bytes_transferred_count: ULONG = 0;
+
+        let nt_status;
+        // SAFETY: `usb_device` is a private member of `UsbDevice`, originally created
+        // by WDF, and this module guarantees that it is always in a valid state. The
+        // caller guarantees that `buffer` is valid for the synchronous transfer.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfUsbTargetDeviceSendControlTransferSynchronously,
+                self.usb_device,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut wdf_setup_packet,
+                memory_descriptor,
+                &mut bytes_transferred_count,
+            );
+        }
+        nt_success(nt_status)
+            .then(|| bytes_transferred(bytes_transferred_count))
+            .ok_or(nt_status)
+    }
+
+    /// Forwards `request` to the device as a control transfer described by
+    /// `setup_packet`, and calls `completion` when the transfer completes.
+    ///
+    /// The data stage of the transfer uses the output buffer of `request` if
+    /// `setup_packet` is from the device to the host, and its input buffer
+    /// otherwise.
+    pub fn send_control_transfer(
+        &self,
+        request: Request,
+        setup_packet: ControlSetupPacket,
+        completion: TransferCompletion,
+    ) {
+        let memory = if setup_packet.is_device_to_host() {
+            request_output_memory(&request)
+        } else {
+            request_input_memory(&request)
+        };
+        let memory = match memory {
+            Ok(memory) => memory,
+            Err(nt_status) => return completion(request, Err(nt_status)),
+        };
+        let mut wdf_setup_packet = setup_packet.to_wdf();
+
+        let nt_status;
+        // SAFETY: `usb_device` is a private member of `UsbDevice`, originally created
+        // by WDF, and this module guarantees that it is always in a valid state.
+        // `request` is owned by the driver, and `memory` is one of its buffers.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfUsbTargetDeviceFormatRequestForControlTransfer,
+                self.usb_device,
+                request.as_raw(),
+                &mut wdf_setup_packet,
+                memory,
+                ptr::null_mut(),
+            );
+        }
+        if !nt_success(nt_status) {
+            return completion(request, Err(nt_status));
+        }
+
+        let io_target;
+        // SAFETY: `usb_device` is a private member of `UsbDevice`, originally created
+        // by WDF, and this module guarantees that it is always in a valid state.
+        unsafe {
+            io_target =
+                call_unsafe_wdf_function_binding!(WdfUsbTargetDeviceGetIoTarget, self.usb_device);
+        }
+        send(request, io_target, completion);
+    }
+}
+
+/// Setup packet of a USB control transfer.
+///
+/// The `wLength` field of the setup packet is set by WDF from the size of the
+/// buffer of the transfer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ControlSetupPacket {
+    /// Type, recipient and direction of the request (`bmRequestType`)
+    pub request_type: u8,
+    /// Request code (`bRequest`)
+    pub request: u8,
+    /// Request-specific value (`wValue`)
+    pub value: u16,
+    /// Request-specific index (`wIndex`)
+    pub index: u16,
+}
+
+impl ControlSetupPacket {
+    /// Returns whether the data stage of the transfer is from the device to
+    /// the host
+    const fn is_device_to_host(self) -> bool {
+        self.request_type & DEVICE_TO_HOST_REQUEST_TYPE != 0
+    }
+
+    /// Converts the setup packet to a [`WDF_USB_CONTROL_SETUP_PACKET`], like
+    /// `WDF_USB_CONTROL_SETUP_PACKET_INIT`
+    fn to_wdf(self) -> WDF_USB_CONTROL_SETUP_PACKET {
+        let [value_low, value_high] = self.value.to_le_bytes();
+        let [index_low, index_high] = self.index.to_le_bytes();
+
+        let mut wdf_setup_packet = WDF_USB_CONTROL_SETUP_PACKET::default();
+        wdf_setup_packet.Generic.Bytes = [
+            self.request_type,
+            self.request,
+            value_low,
+            value_high,
+            index_low,
+            index_high,
+            0,
+            0,
+        ];
+        wdf_setup_packet
+    }
+}
+
+/// Interface of a [`UsbDevice`], in its selected configuration
+#[derive(Clone, Copy, Debug)]
+pub struct UsbInterface {
+    usb_interface: WDFUSBINTERFACE,
+}
+
+impl UsbInterface {
+    /// Returns the raw WDF USB interface handle
+    #[must_use]
+    pub const fn as_raw(&self) -> WDFUSBINTERFACE {
+        self.usb_interface
+    }
+
+    /// Returns the interface number (`bInterfaceNumber`) of the interface
+    #[must_use]
+    pub fn number(&self) -> u8 {
+        let interface_number;
+        // SAFETY: `usb_interface` is a private member of `UsbInterface`, originally
+        // returned by WDF for a valid `UsbDevice`
+        unsafe {
+            interface_number = call_unsafe_wdf_function_binding!(
+                WdfUsbInterfaceGetInterfaceNumber,
+                self.usb_interface,
+            );
+        }
+        interface_number
+    }
+
+    /// Returns the number of pipes configured for the selected alternate
+    /// setting of the interface
+    #[must_use]
+    pub fn pipe_count(&self) -> u8 {
+        let pipe_count;
+        // SAFETY: `usb_interface` is a private member of `UsbInterface`, originally
+        // returned by WDF for a valid `UsbDevice`
+        unsafe {
+            pipe_count = call_unsafe_wdf_function_binding!(
+                WdfUsbInterfaceGetNumConfiguredPipes,
+                self.usb_interface,
+            );
+        }
+        pipe_count
+    }
+
+    /// Returns the configured pipe of the interface at `index`, or `None` if
+    /// there is no such pipe
+    #[must_use]
+    pub fn pipe(&self, index: u8) -> Option<UsbPipe> {
+        // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+        #[allow(clippy::cast_possible_truncation)]
+        const WDF_USB_PIPE_INFORMATION_SIZE: ULONG = size_of::<WDF_USB_PIPE_INFORMATION>() as ULONG;
+
+        // Equivalent to `WDF_USB_PIPE_INFORMATION_INIT`
+        let mut information = WDF_USB_PIPE_INFORMATION {
+            Size: WDF_USB_PIPE_INFORMATION_SIZE,
+            ..WDF_USB_PIPE_INFORMATION::default()
+        };
+
+        let usb_pipe;
+        // SAFETY: `usb_interface` is a private member of `UsbInterface`, originally
+        // returned by WDF for a valid `UsbDevice`
+        unsafe {
+            usb_pipe = call_unsafe_wdf_function_binding!(
+                WdfUsbInterfaceGetConfiguredPipe,
+                self.usb_interface,
+                index,
+                &mut information,
+            );
+        }
+        (!usb_pipe.is_null()).then_some(UsbPipe {
+            usb_pipe,
+            information,
+        })
+    }
+
+    /// Returns an iterator over the configured pipes of the interface
+    pub fn pipes(&self) -> impl Iterator<Item = UsbPipe> + '_ {
+        (0..self.pipe_count()).filter_map(|index| self.pipe(index))
+    }
+}
+
+/// Type of the transfers of a [`UsbPipe`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PipeType {
+    /// Control transfers
+    Control,
+    /// Isochronous transfers
+    Isochronous,
+    /// Bulk transfers
+    Bulk,
+    /// Interrupt transfers
+    Interrupt,
+}
+
+/// Configured pipe of a [`UsbInterface`]
+#[derive(Clone, Copy, Debug)]
+pub struct UsbPipe {
+    usb_pipe: WDFUSBPIPE,
+    information: WDF_USB_PIPE_INFORMATION,
+}
+
+impl UsbPipe {
+    /// Returns the raw WDF USB pipe handle
+    #[must_use]
+    pub const fn as_raw(&self) -> WDFUSBPIPE {
+        self.usb_pipe
+    }
+
+    /// Returns the type of the transfers of the pipe, or `None` if WDF reports
+    /// an invalid pipe type
+    #[must_use]
+    pub const fn pipe_type(&self) -> Option<PipeType> {
+        match self.information.PipeType {
+            _WDF_USB_PIPE_TYPE::WdfUsbPipeTypeControl => Some(PipeType::Control),
+            _WDF_USB_PIPE_TYPE::WdfUsbPipeTypeIsochronous => Some(PipeType::Isochronous),
+            _WDF_USB_PIPE_TYPE::WdfUsbPipeTypeBulk => Some(PipeType::Bulk),
+            _WDF_USB_PIPE_TYPE::WdfUsbPipeTypeInterrupt => Some(PipeType::Interrupt),
+            _ => None,
+        }
+    }
+
+    /// Returns the address of the endpoint of the pipe (`bEndpointAddress`)
+    #[must_use]
+    pub const fn endpoint_address(&self) -> u8 {
+        self.information.EndpointAddress
+    }
+
+    /// Returns whether the pipe transfers data from the device to the host
+    #[must_use]
+    pub const fn is_in(&self) -> bool {
+        self.information.EndpointAddress & ENDPOINT_DIRECTION_IN != 0
+    }
+
+    /// Returns the maximum packet size of the pipe, in bytes
+    #[must_use]
+    pub const fn maximum_packet_size(&self) -> ULONG {
+        self.information.MaximumPacketSize
+    }
+
+    /// Returns the maximum size of a single transfer on the pipe, in bytes
+    #[must_use]
+    pub const fn maximum_transfer_size(&self) -> ULONG {
+        self.information.MaximumTransferSize
+    }
+
+    /// Read from the pipe into `buffer`, and return the number of bytes read.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_INVALID_PARAMETER` if `buffer` is larger than `ULONG::MAX` bytes. Otherwise, it will return an error if the read fails. Full error documentation is available in the [WdfUsbTargetPipeReadSynchronously Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfusb/nf-wdfusb-wdfusbtargetpipereadsynchronously#return-value)
+    pub fn read(&self, buffer: &mut [u8]) -> Result<usize, NTSTATUS> {
+        let mut memory_descriptor = memory_descriptor(buffer.as_mut_ptr().cast(), buffer.len())?;
+        let mut bytes_read: ULONG = 0;
+
+        let nt_status;
+        // SAFETY: `usb_pipe` is a private member of `UsbPipe`, originally returned by
+        // WDF for a valid `UsbInterface`. The read is synchronous, so `buffer`
+        // outlives it.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfUsbTargetPipeReadSynchronously,
+                self.usb_pipe,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut memory_descriptor,
+                &mut bytes_read,
+            );
+        }
+        nt_success(nt_status)
+            .then(|| bytes_transferred(bytes_read))
+            .ok_or(nt_status)
+    }
+
+    /// Write `buffer` to the pipe, and return the number of bytes written.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `STATUS_INVALID_PARAMETER` if `buffer` is larger than `ULONG::MAX` bytes. Otherwise, it will return an error if the write fails. Full error documentation is available in the [WdfUsbTargetPipeWriteSynchronously Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfusb/nf-wdfusb-wdfusbtargetpipewritesynchronously#return-value)
+    pub fn write(&self, buffer: &[u8]) -> Result<usize, NTSTATUS> {
+        let mut memory_descriptor =
+            memory_descriptor(buffer.as_ptr().cast_mut().cast(), buffer.len())?;
+        let mut bytes_written: ULONG = 0;
+
+        let nt_status;
+        // SAFETY: `usb_pipe` is a private member of `UsbPipe`, originally returned by
+        // WDF for a valid `UsbInterface`. The write is synchronous, so `buffer`
+        // outlives it, and is only read from.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfUsbTargetPipeWriteSynchronously,
+                self.usb_pipe,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut memory_descriptor,
+                &mut bytes_written,
+            );
+        }
+        nt_success(nt_status)
+            .then(|| bytes_transferred(bytes_written))
+            .ok_or(nt_status)
+    }
+
+    /// Forwards `request` to the pipe as a read into the output buffer of
+    /// `request`, and calls `completion` when the read completes.
+    pub fn send_read(&self, request: Request, completion: TransferCompletion) {
+        let memory = match request_output_memory(&request) {
+            Ok(memory) => memory,
+            Err(nt_status) => return completion(request, Err(nt_status)),
+        };
+
+        let nt_status;
+        // SAFETY: `usb_pipe` is a private member of `UsbPipe`, originally returned by
+        // WDF for a valid `UsbInterface`. `request` is owned by the driver, and
+        // `memory` is its output buffer.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfUsbTargetPipeFormatRequestForRead,
+                self.usb_pipe,
+                request.as_raw(),
+                memory,
+                ptr::null_mut(),
+            );
+        }
+        if !nt_success(nt_status) {
+            return completion(request, Err(nt_status));
+        }
+        send(request, self.io_target(), completion);
+    }
+
+    /// Forwards `request` to the pipe as a write of the input buffer of
+    /// `request`, and calls `completion` when the write completes.
+    pub fn send_write(&self, request: Request, completion: TransferCompletion) {
+        let memory = match request_input_memory(&request) {
+            Ok(memory) => memory,
+            Err(nt_status) => return completion(request, Err(nt_status)),
+        };
+
+        let nt_status;
+        // SAFETY: `usb_pipe` is a private member of `UsbPipe`, originally returned by
+        // WDF for a valid `UsbInterface`. `request` is owned by the driver, and
+        // `memory` is its input buffer.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfUsbTargetPipeFormatRequestForWrite,
+                self.usb_pipe,
+                request.as_raw(),
+                memory,
+                ptr::null_mut(),
+            );
+        }
+        if !nt_success(nt_status) {
+            return completion(request, Err(nt_status));
+        }
+        send(request, self.io_target(), completion);
+    }
+
+    /// Returns the I/O target of the pipe
+    fn io_target(&self) -> WDFIOTARGET {
+        let io_target;
+        // SAFETY: `usb_pipe` is a private member of `UsbPipe`, originally returned by
+        // WDF for a valid `UsbInterface`
+        unsafe {
+            io_target =
+                call_unsafe_wdf_function_binding!(WdfUsbTargetPipeGetIoTarget, self.usb_pipe);
+        }
+        io_target
+    }
+}
+
+/// Configuration descriptor of a USB device, followed by the interface,
+/// endpoint and class-specific descriptors of the configuration
+#[derive(Clone, Copy, Debug)]
+pub struct ConfigurationDescriptor<'a> {
+    header: USB_CONFIGURATION_DESCRIPTOR,
+    bytes: &'a [u8],
+}
+
+impl<'a> ConfigurationDescriptor<'a> {
+    /// Creates a [`ConfigurationDescriptor`] from the bytes retrieved from the
+    /// device, truncated to its `wTotalLength`. Returns `None` if `bytes` is
+    /// too short to hold a configuration descriptor.
+    fn new(bytes: &'a [u8]) -> Option<Self> {
+        let header = read_descriptor::<USB_CONFIGURATION_DESCRIPTOR>(bytes)?;
+        let total_length = usize::from(header.wTotalLength).min(bytes.len());
+        Some(Self {
+            header,
+            bytes: &bytes[..total_length],
+        })
+    }
+
+    /// Returns the fixed-size header of the configuration descriptor
+    #[must_use]
+    pub const fn header(&self) -> USB_CONFIGURATION_DESCRIPTOR {
+        self.header
+    }
+
+    /// Returns the raw bytes of the configuration descriptor and of the
+    /// descriptors that follow it
+    #[must_use]
+    pub const fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Returns an iterator over every descriptor of the configuration,
+    /// starting with the configuration descriptor itself
+    #[must_use]
+    pub const fn descriptors(&self) -> Descriptors<'a> {
+        Descriptors {
+            remaining: self.bytes,
+        }
+    }
+
+    /// Returns an iterator over the interface descriptors of the
+    /// configuration, including every alternate setting of each interface
+    pub fn interfaces(&self) -> impl Iterator<Item = USB_INTERFACE_DESCRIPTOR> + 'a {
+        self.descriptors()
+            .filter(|descriptor| {
+                u32::from(descriptor.descriptor_type()) == USB_INTERFACE_DESCRIPTOR_TYPE
+            })
+            .filter_map(|descriptor| read_descriptor(descriptor.as_bytes()))
+    }
+
+    /// Returns an iterator over the endpoint descriptors of the
+    /// configuration, in the order of the interfaces they belong to
+    pub fn endpoints(&self) -> impl Iterator<Item = USB_ENDPOINT_DESCRIPTOR> + 'a {
+        self.descriptors()
+            .filter(|descriptor| {
+                u32::from(descriptor.descriptor_type()) == USB_ENDPOINT_DESCRIPTOR_TYPE
+            })
+            .filter_map(|descriptor| read_descriptor(descriptor.as_bytes()))
+    }
+}
+
+/// A single descriptor of a [`ConfigurationDescriptor`]
+#[derive(Clone, Copy, Debug)]
+pub struct Descriptor<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Descriptor<'a> {
+    /// Returns the type of the descriptor (`bDescriptorType`)
+    #[must_use]
+    pub const fn descriptor_type(&self) -> u8 {
+        self.bytes[1]
+    }
+
+    /// Returns the raw bytes of the descriptor, including its `bLength` and
+    /// `bDescriptorType` fields
+    #[must_use]
+    pub const fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+}
+
+/// Iterator over the descriptors of a [`ConfigurationDescriptor`]. Iteration
+/// stops at the first malformed descriptor.
+#[derive(Clone, Debug)]
+pub struct Descriptors<'a> {
+    remaining: &'a [u8],
+}
+
+impl<'a> Iterator for Descriptors<'a> {
+    type Item = Descriptor<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let length = usize::from(*self.remaining.first()?);
+        // Every descriptor starts with its `bLength` and `bDescriptorType` fields
+        if length < 2 || length > self.remaining.len() {
+            self.remaining = &[];
+            return None;
+        }
+
+        let (bytes, remaining) = self.remaining.split_at(length);
+        self.remaining = remaining;
+        Some(Descriptor { bytes })
+    }
+}
+
+/// Reads a descriptor of type `T` from the start of `bytes`, or returns
+/// `None` if `bytes` is too short
+const fn read_descriptor<T: Copy>(bytes: &[u8]) -> Option<T> {
+    if bytes.len() < size_of::<T>() {
+        return None;
+    }
+    // SAFETY: `bytes` holds at least `size_of::<T>()` bytes, and the USB descriptor
+    // types this is used with are plain data, valid for any bit pattern
+    Some(unsafe { ptr::read_unaligned(bytes.as_ptr().cast::<T>()) })
+}
+
+/// Sends `request`, which was formatted for `io_target`, and calls
+/// `completion` when it completes, or immediately if it cannot be sent
+fn send(request: Request, io_target: WDFIOTARGET, completion: TransferCompletion) {
+    // SAFETY: `request` is owned by the driver. The completion context is only
+    // converted back to a `TransferCompletion` by `evt_transfer_completion`.
+    unsafe {
+        call_unsafe_wdf_function_binding!(
+            WdfRequestSetCompletionRoutine,
+            request.as_raw(),
+            Some(evt_transfer_completion),
+            completion as WDFCONTEXT,
+        );
+    }
+
+    let sent;
+    // SAFETY: `request` is owned by the driver, and was formatted for `io_target`
+    unsafe {
+        sent = call_unsafe_wdf_function_binding!(
+            WdfRequestSend,
+            request.as_raw(),
+            io_target,
+            ptr::null_mut(),
+        );
+    }
+    if sent != 0 {
+        // The request is owned by the I/O target until `evt_transfer_completion`
+        // receives it back
+        return;
+    }
+
+    let nt_status;
+    // SAFETY: `request` is still owned by the driver, since it was not sent
+    unsafe {
+        nt_status = call_unsafe_wdf_function_binding!(WdfRequestGetStatus, request.as_raw());
+    }
+    completion(request, Err(nt_status));
+}
+
+/// `EvtRequestCompletionRoutine` of the requests sent by [`send`], which
+/// dispatches to their [`TransferCompletion`]
+unsafe extern "C" fn evt_transfer_completion(
+    wdf_request: WDFREQUEST,
+    _io_target: WDFIOTARGET,
+    params: PWDF_REQUEST_COMPLETION_PARAMS,
+    context: WDFCONTEXT,
+) {
+    // SAFETY: The completion context is always set from a `TransferCompletion` by
+    // `send`
+    let completion = unsafe { mem::transmute::<WDFCONTEXT, TransferCompletion>(context) };
+    // SAFETY: WDF passes ownership of the completed request back to its completion
+    // routine, and it is only completed through the returned `Request`
+    let request = unsafe { Request::from_raw(wdf_request) };
+
+    let nt_status;
+    // SAFETY: `wdf_request` is a valid request that has completed
+    unsafe {
+        nt_status = call_unsafe_wdf_function_binding!(WdfRequestGetStatus, wdf_request);
+    }
+    if !nt_success(nt_status) {
+        return completion(request, Err(nt_status));
+    }
+
+    // SAFETY: WDF passes valid completion parameters to the completion routine
+    let parameters = unsafe { (*params).Parameters };
+    // SAFETY: Requests sent by `send` are always formatted for a USB target, so
+    // `Usb` is the active member of the `Parameters` union
+    let usb_completion_params = unsafe { parameters.Usb.Completion };
+    // SAFETY: WDF sets the USB completion parameters of requests formatted for a
+    // USB target
+    let usb_completion_params = unsafe { &*usb_completion_params };
+
+    let length = match usb_completion_params.Type {
+        _WDF_USB_REQUEST_TYPE::WdfUsbRequestTypePipeRead => {
+            // SAFETY: `PipeRead` is the active member of the `Parameters` union of pipe
+            // reads
+            unsafe { usb_completion_params.Parameters.PipeRead.Length }
+        }
+        _WDF_USB_REQUEST_TYPE::WdfUsbRequestTypePipeWrite => {
+            // SAFETY: `PipeWrite` is the active member of the `Parameters` union of pipe
+            // writes
+            unsafe { usb_completion_params.Parameters.PipeWrite.Length }
+        }
+        _WDF_USB_REQUEST_TYPE::WdfUsbRequestTypeDeviceControlTransfer => {
+            let control_transfer_parameters;
+            // SAFETY: `DeviceControlTransfer` is the active member of the `Parameters`
+            // union of control transfers
+            unsafe {
+                control_transfer_parameters =
+                    usb_completion_params.Parameters.DeviceControlTransfer;
+            }
+            bytes_transferred(control_transfer_parameters.Length)
+        }
+        _ => 0,
+    };
+    completion(request, Ok(length));
+}
+
+/// Returns the output buffer of `request`
+fn request_output_memory(request: &Request) -> Result<WDFMEMORY, NTSTATUS> {
+    let mut memory: WDFMEMORY = ptr::null_mut();
+
+    let nt_status;
+    // SAFETY: `request` is owned by the driver
+    unsafe {
+        nt_status = call_unsafe_wdf_function_binding!(
+            WdfRequestRetrieveOutputMemory,
+            request.as_raw(),
+            &mut memory,
+        );
+    }
+    nt_success(nt_status).then_some(memory).ok_or(nt_status)
+}
+
+/// Returns the input buffer of `request`
+fn request_input_memory(request: &Request) -> Result<WDFMEMORY, NTSTATUS> {
+    let mut memory: WDFMEMORY = ptr::null_mut();
+
+    let nt_status;
+    // SAFETY: `request` is owned by the driver
+    unsafe {
+        nt_status = call_unsafe_wdf_function_binding!(
+            WdfRequestRetrieveInputMemory,
+            request.as_raw(),
+            &mut memory,
+        );
+    }
+    nt_success(nt_status).then_some(memory).ok_or(nt_status)
+}
+
+/// Creates a [`WDF_MEMORY_DESCRIPTOR`] describing `length` bytes at `buffer`,
+/// like `WDF_MEMORY_DESCRIPTOR_INIT_BUFFER`
+fn memory_descriptor(buffer: PVOID, length: usize) -> Result<WDF_MEMORY_DESCRIPTOR, NTSTATUS> {
+    let mut memory_descriptor = WDF_MEMORY_DESCRIPTOR {
+        Type: _WDF_MEMORY_DESCRIPTOR_TYPE::WdfMemoryDescriptorTypeBuffer,
+        ..WDF_MEMORY_DESCRIPTOR::default()
+    };
+    memory_descriptor.u.BufferType.Buffer = buffer;
+    memory_descriptor.u.BufferType.Length =
+        ULONG::try_from(length).map_err(|_| STATUS_INVALID_PARAMETER)?;
+    Ok(memory_descriptor)
+}
+
+/// Converts the number of bytes transferred by a request into a `usize`
+const fn bytes_transferred(bytes: ULONG) -> usize {
+    // `ULONG` always fits in a `usize` on the architectures supported by the WDK
+    #[allow(clippy::cast_possible_truncation)]
+    let bytes = bytes as usize;
+    bytes
+}
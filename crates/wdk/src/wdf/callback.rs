@@ -0,0 +1,103 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+#[doc(hidden)]
+pub use wdk_sys::__wdf_callback;
+use wdk_sys::{BOOLEAN, NTSTATUS, PVOID, STATUS_SUCCESS, UNICODE_STRING, WDFREQUEST};
+
+use super::{Request, UnicodeString};
+
+/// Conversion of a raw argument passed by WDF to a callback into the type of
+/// the corresponding parameter of a [`callback`](crate::callback) function.
+///
+/// Every type can be received as-is, and the following conversions are
+/// provided for safe wrappers:
+///
+/// * `WDFREQUEST` to [`Request`], which takes over the responsibility of
+///   completing the request
+/// * `PCUNICODE_STRING` to [`UnicodeString`]
+/// * `BOOLEAN` to [`bool`]
+/// * `PVOID` (ex. the `WDFCONTEXT` of completion routines and work items) to
+///   `&T`, recovering the context that the driver registered the callback with
+pub trait FromRawCallbackArg<Raw>: Sized {
+    /// Converts the raw argument of a callback
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be an argument passed by WDF to a callback, and must satisfy
+    /// the requirements of the conversion: a `WDFREQUEST` must be owned by the
+    /// callback, a `PCUNICODE_STRING` must be valid for the duration of the
+    /// callback, and a `PVOID` must point to a valid `T` that outlives the
+    /// returned reference.
+    unsafe fn from_raw_callback_arg(raw: Raw) -> Self;
+}
+
+impl<T> FromRawCallbackArg<T> for T {
+    unsafe fn from_raw_callback_arg(raw: T) -> Self {
+        raw
+    }
+}
+
+impl FromRawCallbackArg<WDFREQUEST> for Request {
+    unsafe fn from_raw_callback_arg(raw: WDFREQUEST) -> Self {
+        // SAFETY: The caller guarantees that the request is owned by the callback
+        unsafe { Self::from_raw(raw) }
+    }
+}
+
+impl FromRawCallbackArg<*const UNICODE_STRING> for UnicodeString<'_> {
+    unsafe fn from_raw_callback_arg(raw: *const UNICODE_STRING) -> Self {
+        // SAFETY: The caller guarantees that the string is valid for the duration of
+        // the callback
+        unsafe { Self::from_raw(raw) }
+    }
+}
+
+impl FromRawCallbackArg<BOOLEAN> for bool {
+    unsafe fn from_raw_callback_arg(raw: BOOLEAN) -> Self {
+        raw != 0
+    }
+}
+
+impl<'a, T: 'a> FromRawCallbackArg<PVOID> for &'a T {
+    unsafe fn from_raw_callback_arg(raw: PVOID) -> Self {
+        // SAFETY: The caller guarantees that `raw` points to a valid `T` that
+        // outlives `'a`
+        unsafe { &*raw.cast::<T>() }
+    }
+}
+
+/// Conversion of the return value of a [`callback`](crate::callback) function
+/// into the raw return value expected by WDF.
+///
+/// Every type can be returned as-is, and the following conversions are
+/// provided:
+///
+/// * `Result<(), NTSTATUS>` (and `Result<(), Error>`) to [`NTSTATUS`], where
+///   `Ok(())` is [`STATUS_SUCCESS`]
+/// * [`bool`] to `BOOLEAN`
+pub trait IntoRawCallbackReturn<Raw> {
+    /// Converts the return value of a callback
+    fn into_raw_callback_return(self) -> Raw;
+}
+
+impl<T> IntoRawCallbackReturn<T> for T {
+    fn into_raw_callback_return(self) -> T {
+        self
+    }
+}
+
+impl<E: Into<NTSTATUS>> IntoRawCallbackReturn<NTSTATUS> for Result<(), E> {
+    fn into_raw_callback_return(self) -> NTSTATUS {
+        match self {
+            Ok(()) => STATUS_SUCCESS,
+            Err(error) => error.into(),
+        }
+    }
+}
+
+impl IntoRawCallbackReturn<BOOLEAN> for bool {
+    fn into_raw_callback_return(self) -> BOOLEAN {
+        BOOLEAN::from(self)
+    }
+}
@@ -0,0 +1,311 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+use core::{marker::PhantomData, mem::size_of, ptr};
+
+use wdk_sys::{
+    call_unsafe_wdf_function_binding,
+    _WDF_CHILD_LIST_RETRIEVE_DEVICE_STATUS::WdfChildListRetrieveDeviceSuccess,
+    BOOLEAN,
+    PFN_WDF_CHILD_LIST_CREATE_DEVICE,
+    PWDF_CHILD_IDENTIFICATION_DESCRIPTION_HEADER,
+    ULONG,
+    WDFCHILDLIST,
+    WDFDEVICE,
+    WDF_CHILD_IDENTIFICATION_DESCRIPTION_HEADER,
+    WDF_CHILD_LIST_CONFIG,
+    WDF_CHILD_RETRIEVE_INFO,
+    WDF_OBJECT_ATTRIBUTES,
+};
+
+use super::Error;
+
+/// A WDF child identification description, identifying a child device by a
+/// driver-defined identifier of type `T`.
+///
+/// This is the identification description used by a [`ChildList<T>`]. The
+/// identifier should uniquely identify a child device on the bus (ex. a port
+/// number or serial number).
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ChildIdentification<T> {
+    header: WDF_CHILD_IDENTIFICATION_DESCRIPTION_HEADER,
+    id: T,
+}
+
+impl<T: Copy + PartialEq> ChildIdentification<T> {
+    // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+    #[allow(clippy::cast_possible_truncation)]
+    const SIZE: ULONG = {
+        // Manually assert there is not truncation since clippy doesn't work for
+        // compile-time constants
+        assert!(size_of::<Self>() <= ULONG::MAX as usize);
+
+        size_of::<Self>() as ULONG
+    };
+
+    /// Creates a new [`ChildIdentification`] for the child identified by `id`
+    #[must_use]
+    pub fn new(id: T) -> Self {
+        Self {
+            header: WDF_CHILD_IDENTIFICATION_DESCRIPTION_HEADER {
+                IdentificationDescriptionSize: Self::SIZE,
+            },
+            id,
+        }
+    }
+
+    /// Returns the identifier of the child
+    #[must_use]
+    pub const fn id(&self) -> &T {
+        &self.id
+    }
+
+    /// Returns a reference to the [`ChildIdentification`] that contains
+    /// `header`. This is used to retrieve the identifier of the child in WDF
+    /// callbacks, such as `EvtChildListCreateDevice`.
+    ///
+    /// # Safety
+    ///
+    /// `header` must point to the header of a valid [`ChildIdentification<T>`]
+    /// (ex. the identification description passed by WDF to the callbacks of a
+    /// [`ChildList<T>`]), which must remain valid for `'a`.
+    #[must_use]
+    pub unsafe fn from_header<'a>(
+        header: PWDF_CHILD_IDENTIFICATION_DESCRIPTION_HEADER,
+    ) -> &'a Self {
+        // SAFETY: `ChildIdentification` is `repr(C)` with the header as its first
+        // field, and the caller guarantees that `header` points to the header
+        // of a valid `ChildIdentification<T>`
+        unsafe { &*header.cast::<Self>() }
+    }
+}
+
+/// WDF Child List.
+///
+/// A child list is used by a bus driver to dynamically enumerate the child
+/// devices on its bus. The driver reports which children are present (ex. in
+/// response to a hotplug event, or after a bus scan), and WDF calls the
+/// `EvtChildListCreateDevice` callback to create a PDO for each newly
+/// reported child. Children are identified by a driver-defined identifier of
+/// type `T`, which is compared with [`PartialEq`] to detect whether a reported
+/// child is already present.
+pub struct ChildList<T> {
+    wdf_child_list: WDFCHILDLIST,
+    _identification: PhantomData<ChildIdentification<T>>,
+}
+
+impl<T: Copy + PartialEq> ChildList<T> {
+    /// Try to construct a WDF Child List object for the bus device `device`.
+    ///
+    /// `evt_child_list_create_device` is called to create the PDO of each
+    /// child reported as present. [`ChildIdentification::from_header`] can be
+    /// used in the callback to retrieve the identifier of the child, and
+    /// [`PdoInit::from_child_init`](super::PdoInit::from_child_init) to
+    /// configure the PDO.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if WDF fails to construct a child list. The error contains the [`NTSTATUS`](wdk_sys::NTSTATUS) of the failure. Full error documentation is available in the [WdfChildListCreate Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfchildlist/nf-wdfchildlist-wdfchildlistcreate#return-value)
+    pub fn try_new(
+        device: WDFDEVICE,
+        evt_child_list_create_device: PFN_WDF_CHILD_LIST_CREATE_DEVICE,
+        attributes: &mut WDF_OBJECT_ATTRIBUTES,
+    ) -> Result<Self, Error> {
+        // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+        #[allow(clippy::cast_possible_truncation)]
+        const WDF_CHILD_LIST_CONFIG_SIZE: ULONG = size_of::<WDF_CHILD_LIST_CONFIG>() as ULONG;
+
+        let mut child_list_config = WDF_CHILD_LIST_CONFIG {
+            Size: WDF_CHILD_LIST_CONFIG_SIZE,
+            IdentificationDescriptionSize: ChildIdentification::<T>::SIZE,
+            EvtChildListCreateDevice: evt_child_list_create_device,
+            // Compare identifiers with `PartialEq` instead of WDF's default `memcmp`, which
+            // would also compare padding bytes
+            EvtChildListIdentificationDescriptionCompare: Some(
+                compare_identification_descriptions::<T>,
+            ),
+            ..WDF_CHILD_LIST_CONFIG::default()
+        };
+
+        let mut child_list = Self {
+            wdf_child_list: core::ptr::null_mut(),
+            _identification: PhantomData,
+        };
+
+        let nt_status;
+        // SAFETY: The resulting ffi object is stored in a private member and not
+        // accessible outside of this module, and this module guarantees that it is
+        // always in a valid state.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfChildListCreate,
+                device,
+                &mut child_list_config,
+                attributes,
+                &mut child_list.wdf_child_list,
+            );
+        }
+        Error::check("WdfChildListCreate", nt_status).map(|()| child_list)
+    }
+
+    /// Try to construct a WDF Child List object. This is an alias for
+    /// [`ChildList::try_new()`]
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if WDF fails to construct a child list. The error contains the [`NTSTATUS`](wdk_sys::NTSTATUS) of the failure. Full error documentation is available in the [WdfChildListCreate Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfchildlist/nf-wdfchildlist-wdfchildlistcreate#return-value)
+    pub fn create(
+        device: WDFDEVICE,
+        evt_child_list_create_device: PFN_WDF_CHILD_LIST_CREATE_DEVICE,
+        attributes: &mut WDF_OBJECT_ATTRIBUTES,
+    ) -> Result<Self, Error> {
+        Self::try_new(device, evt_child_list_create_device, attributes)
+    }
+
+    /// Report the child identified by `id` as present. If the child is not
+    /// already present, WDF will create a PDO for it.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if WDF fails to add the child. The error contains the [`NTSTATUS`](wdk_sys::NTSTATUS) of the failure. Full error documentation is available in the [WdfChildListAddOrUpdateChildDescriptionAsPresent Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfchildlist/nf-wdfchildlist-wdfchildlistaddorupdatechilddescriptionaspresent#return-value)
+    pub fn add_or_update_child_as_present(&self, id: T) -> Result<(), Error> {
+        let mut identification = ChildIdentification::new(id);
+
+        let nt_status;
+        // SAFETY: `wdf_child_list` is a private member of `ChildList`, originally
+        // created by WDF, and this module guarantees that it is always in a valid
+        // state. WDF copies the identification description, so it only needs to be
+        // valid for the duration of the call.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfChildListAddOrUpdateChildDescriptionAsPresent,
+                self.wdf_child_list,
+                &mut identification.header,
+                ptr::null_mut(),
+            );
+        }
+        Error::check(
+            "WdfChildListAddOrUpdateChildDescriptionAsPresent",
+            nt_status,
+        )
+    }
+
+    /// Report the child identified by `id` as missing. If the child is present,
+    /// WDF will report its PDO as missing to the PnP manager.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the child is not present in the child list. The error contains the [`NTSTATUS`](wdk_sys::NTSTATUS) of the failure. Full error documentation is available in the [WdfChildListUpdateChildDescriptionAsMissing Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfchildlist/nf-wdfchildlist-wdfchildlistupdatechilddescriptionasmissing#return-value)
+    pub fn update_child_as_missing(&self, id: T) -> Result<(), Error> {
+        let mut identification = ChildIdentification::new(id);
+
+        let nt_status;
+        // SAFETY: `wdf_child_list` is a private member of `ChildList`, originally
+        // created by WDF, and this module guarantees that it is always in a valid
+        // state. `identification` is only read for the duration of the call.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfChildListUpdateChildDescriptionAsMissing,
+                self.wdf_child_list,
+                &mut identification.header,
+            );
+        }
+        Error::check("WdfChildListUpdateChildDescriptionAsMissing", nt_status)
+    }
+
+    /// Report the complete set of children present on the bus. All children
+    /// that are not reported as present by `report_children` are reported as
+    /// missing.
+    ///
+    /// This wraps `report_children` in calls to `WdfChildListBeginScan` and
+    /// `WdfChildListEndScan`.
+    pub fn scan<F>(&self, report_children: F)
+    where
+        F: FnOnce(&Self),
+    {
+        // SAFETY: `wdf_child_list` is a private member of `ChildList`, originally
+        // created by WDF, and this module guarantees that it is always in a valid
+        // state.
+        unsafe {
+            call_unsafe_wdf_function_binding!(WdfChildListBeginScan, self.wdf_child_list);
+        }
+
+        report_children(self);
+
+        // SAFETY: `wdf_child_list` is a private member of `ChildList`, originally
+        // created by WDF, and this module guarantees that it is always in a valid
+        // state. `WdfChildListBeginScan` was called above.
+        unsafe {
+            call_unsafe_wdf_function_binding!(WdfChildListEndScan, self.wdf_child_list);
+        }
+    }
+
+    /// Returns the PDO of the child identified by `id`, or `None` if the child
+    /// is not present or its PDO has not been created yet.
+    #[must_use]
+    pub fn retrieve_pdo(&self, id: T) -> Option<WDFDEVICE> {
+        // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+        #[allow(clippy::cast_possible_truncation)]
+        const WDF_CHILD_RETRIEVE_INFO_SIZE: ULONG = size_of::<WDF_CHILD_RETRIEVE_INFO>() as ULONG;
+
+        let mut identification = ChildIdentification::new(id);
+        let mut retrieve_info = WDF_CHILD_RETRIEVE_INFO {
+            Size: WDF_CHILD_RETRIEVE_INFO_SIZE,
+            IdentificationDescription: &mut identification.header,
+            ..WDF_CHILD_RETRIEVE_INFO::default()
+        };
+
+        let pdo;
+        // SAFETY: `wdf_child_list` is a private member of `ChildList`, originally
+        // created by WDF, and this module guarantees that it is always in a valid
+        // state. `retrieve_info` and `identification` outlive the call.
+        unsafe {
+            pdo = call_unsafe_wdf_function_binding!(
+                WdfChildListRetrievePdo,
+                self.wdf_child_list,
+                &mut retrieve_info,
+            );
+        }
+        (retrieve_info.Status == WdfChildListRetrieveDeviceSuccess && !pdo.is_null()).then_some(pdo)
+    }
+
+    /// Request that the child identified by `id` be ejected. Returns `true` if
+    /// the child was found in the child list.
+    #[must_use]
+    pub fn request_child_eject(&self, id: T) -> bool {
+        let mut identification = ChildIdentification::new(id);
+
+        let result;
+        // SAFETY: `wdf_child_list` is a private member of `ChildList`, originally
+        // created by WDF, and this module guarantees that it is always in a valid
+        // state. `identification` is only read for the duration of the call.
+        unsafe {
+            result = call_unsafe_wdf_function_binding!(
+                WdfChildListRequestChildEject,
+                self.wdf_child_list,
+                &mut identification.header,
+            );
+        }
+        result != 0
+    }
+}
+
+/// `EvtChildListIdentificationDescriptionCompare` callback that compares the
+/// identifiers of two [`ChildIdentification<T>`] with [`PartialEq`]
+unsafe extern "C" fn compare_identification_descriptions<T: Copy + PartialEq>(
+    _child_list: WDFCHILDLIST,
+    first_identification_description: PWDF_CHILD_IDENTIFICATION_DESCRIPTION_HEADER,
+    second_identification_description: PWDF_CHILD_IDENTIFICATION_DESCRIPTION_HEADER,
+) -> BOOLEAN {
+    // SAFETY: This callback is only registered for child lists created by
+    // `ChildList<T>::try_new`, whose identification descriptions are always
+    // `ChildIdentification<T>`
+    let (first, second) = unsafe {
+        (
+            ChildIdentification::<T>::from_header(first_identification_description),
+            ChildIdentification::<T>::from_header(second_identification_description),
+        )
+    };
+    BOOLEAN::from(first.id == second.id)
+}
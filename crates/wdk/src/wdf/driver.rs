@@ -0,0 +1,287 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+use core::{char::decode_utf16, fmt, slice};
+
+use wdk_sys::{
+    call_unsafe_wdf_function_binding,
+    NTSTATUS,
+    PCUNICODE_STRING,
+    PDRIVER_OBJECT,
+    PFN_WDF_DRIVER_DEVICE_ADD,
+    PFN_WDF_DRIVER_UNLOAD,
+    STATUS_SUCCESS,
+    ULONG,
+    UNICODE_STRING,
+    WDFDEVICE_INIT,
+    WDFDRIVER,
+    WDF_DRIVER_CONFIG,
+    WDF_NO_OBJECT_ATTRIBUTES,
+};
+
+use super::Error;
+
+/// Builder for the WDF driver object created in `DriverEntry`.
+///
+/// The builder is normally returned from the initialization function passed to
+/// [`driver_entry!`](crate::driver_entry), which generates the `DriverEntry`
+/// symbol and creates the driver from the returned builder:
+///
+/// ```rust, ignore
+/// wdk::driver_entry!(driver_init);
+///
+/// fn driver_init(registry_path: UnicodeString) -> DriverBuilder {
+///     println!("Driver Registry Parameter Key: {registry_path}");
+///
+///     DriverBuilder::new()
+///         .evt_device_add(evt_driver_device_add)
+///         .evt_driver_unload(evt_driver_unload)
+/// }
+/// ```
+pub struct DriverBuilder {
+    evt_device_add: PFN_WDF_DRIVER_DEVICE_ADD,
+    evt_driver_unload: PFN_WDF_DRIVER_UNLOAD,
+}
+
+impl DriverBuilder {
+    /// Construct a [`DriverBuilder`] with no event callbacks registered
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            evt_device_add: None,
+            evt_driver_unload: None,
+        }
+    }
+
+    /// Register the `EvtDriverDeviceAdd` callback, which is called by the PnP
+    /// manager for every device the driver is loaded for
+    #[must_use]
+    pub const fn evt_device_add(
+        mut self,
+        evt_device_add: unsafe extern "C" fn(WDFDRIVER, *mut WDFDEVICE_INIT) -> NTSTATUS,
+    ) -> Self {
+        self.evt_device_add = Some(evt_device_add);
+        self
+    }
+
+    /// Register the `EvtDriverUnload` callback, which is called before the
+    /// driver is unloaded
+    #[must_use]
+    pub const fn evt_driver_unload(
+        mut self,
+        evt_driver_unload: unsafe extern "C" fn(WDFDRIVER),
+    ) -> Self {
+        self.evt_driver_unload = Some(evt_driver_unload);
+        self
+    }
+
+    /// Try to create the WDF driver object for the driver being initialized in
+    /// `DriverEntry`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if WDF fails to create the driver object. The error contains the [`NTSTATUS`] of the failure. Full error documentation is available in the [WdfDriverCreate Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfdriver/nf-wdfdriver-wdfdrivercreate#return-value)
+    ///
+    /// # Safety
+    ///
+    /// `driver` and `registry_path` must be the arguments passed by the
+    /// operating system to `DriverEntry`, and this function must only be
+    /// called once, from `DriverEntry`.
+    pub unsafe fn create(
+        self,
+        driver: PDRIVER_OBJECT,
+        registry_path: PCUNICODE_STRING,
+    ) -> Result<Driver, Error> {
+        let mut driver_config = {
+            let wdf_driver_config_size: ULONG;
+
+            // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+            #[allow(clippy::cast_possible_truncation)]
+            {
+                const WDF_DRIVER_CONFIG_SIZE: usize = core::mem::size_of::<WDF_DRIVER_CONFIG>();
+
+                // Manually assert there is not truncation since clippy doesn't work for
+                // compile-time constants
+                const { assert!(WDF_DRIVER_CONFIG_SIZE <= ULONG::MAX as usize) }
+
+                wdf_driver_config_size = WDF_DRIVER_CONFIG_SIZE as ULONG;
+            }
+
+            WDF_DRIVER_CONFIG {
+                Size: wdf_driver_config_size,
+                EvtDriverDeviceAdd: self.evt_device_add,
+                EvtDriverUnload: self.evt_driver_unload,
+                ..WDF_DRIVER_CONFIG::default()
+            }
+        };
+
+        let mut driver_handle = Driver {
+            wdf_driver: core::ptr::null_mut(),
+        };
+
+        let nt_status;
+        // SAFETY: This is safe because:
+        //         1. `driver` and `registry_path` are the arguments of `DriverEntry`,
+        //            as guaranteed by the caller
+        //         2. the argument receiving `WDF_NO_OBJECT_ATTRIBUTES` is allowed to be
+        //            null
+        //         3. `driver_config` is a valid pointer to a valid `WDF_DRIVER_CONFIG`
+        //         4. `driver_handle.wdf_driver` is a valid location to store the
+        //            created `WDFDRIVER`
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfDriverCreate,
+                driver,
+                registry_path,
+                WDF_NO_OBJECT_ATTRIBUTES,
+                &mut driver_config,
+                &mut driver_handle.wdf_driver,
+            );
+        }
+        Error::check("WdfDriverCreate", nt_status).map(|()| driver_handle)
+    }
+
+    /// Implementation of the `DriverEntry` generated by
+    /// [`driver_entry!`](crate::driver_entry)
+    ///
+    /// # Safety
+    ///
+    /// `driver` and `registry_path` must be the arguments passed by the
+    /// operating system to `DriverEntry`
+    #[doc(hidden)]
+    pub unsafe fn __driver_entry(
+        driver: PDRIVER_OBJECT,
+        registry_path: PCUNICODE_STRING,
+        driver_init: fn(UnicodeString) -> Self,
+    ) -> NTSTATUS {
+        // SAFETY: `registry_path` is provided by `DriverEntry`, so it is a valid
+        // pointer to a `UNICODE_STRING` that outlives this function
+        let driver_builder = driver_init(unsafe { UnicodeString::from_raw(registry_path) });
+
+        // SAFETY: `driver` and `registry_path` are the arguments of `DriverEntry`, as
+        // guaranteed by the caller
+        match unsafe { driver_builder.create(driver, registry_path) } {
+            Ok(_) => STATUS_SUCCESS,
+            Err(error) => error.into(),
+        }
+    }
+}
+
+impl Default for DriverBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// WDF driver object created by [`DriverBuilder::create`]
+pub struct Driver {
+    wdf_driver: WDFDRIVER,
+}
+
+impl Driver {
+    /// Get the raw `WDFDRIVER` handle of the driver object
+    #[must_use]
+    pub const fn as_raw(&self) -> WDFDRIVER {
+        self.wdf_driver
+    }
+}
+
+/// Borrowed view of a [`UNICODE_STRING`], such as the registry path passed to
+/// `DriverEntry`. Its [`Display`](fmt::Display) implementation decodes the
+/// UTF-16 contents without allocating, replacing invalid code units with
+/// [`char::REPLACEMENT_CHARACTER`].
+#[derive(Clone, Copy)]
+pub struct UnicodeString<'a> {
+    buffer: &'a [u16],
+}
+
+impl<'a> UnicodeString<'a> {
+    /// Construct a [`UnicodeString`] from a raw pointer to a [`UNICODE_STRING`]
+    ///
+    /// # Safety
+    ///
+    /// `unicode_string` must be a valid pointer to a [`UNICODE_STRING`] whose
+    /// `Buffer` is valid for reads of `Length` bytes (or null when `Length` is
+    /// 0), and neither may be mutated for the lifetime `'a`
+    #[must_use]
+    pub unsafe fn from_raw(unicode_string: *const UNICODE_STRING) -> Self {
+        // SAFETY: `unicode_string` is a valid pointer to a `UNICODE_STRING`, as
+        // guaranteed by the caller
+        let unicode_string = unsafe { &*unicode_string };
+        let number_of_slice_elements =
+            usize::from(unicode_string.Length) / core::mem::size_of::<u16>();
+
+        if unicode_string.Buffer.is_null() || number_of_slice_elements == 0 {
+            return Self { buffer: &[] };
+        }
+
+        Self {
+            // SAFETY: This is safe because:
+            //         1. `Buffer` is non-null and valid for reads of `Length` bytes, as guaranteed
+            //            by the caller. `WCHAR` buffers are always aligned.
+            //         2. the buffer is not mutated for `'a`, as guaranteed by the caller
+            //         3. `Length` is a `u16`, so the slice is never larger than `isize::MAX`
+            buffer: unsafe {
+                slice::from_raw_parts(unicode_string.Buffer, number_of_slice_elements)
+            },
+        }
+    }
+
+    /// Get the UTF-16 code units of the string, excluding any null terminator
+    #[must_use]
+    pub const fn as_slice(&self) -> &'a [u16] {
+        self.buffer
+    }
+}
+
+impl fmt::Display for UnicodeString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use fmt::Write;
+
+        decode_utf16(self.buffer.iter().copied())
+            .try_for_each(|c| f.write_char(c.unwrap_or(char::REPLACEMENT_CHARACTER)))
+    }
+}
+
+impl fmt::Debug for UnicodeString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{self}\"")
+    }
+}
+
+/// Generate the `DriverEntry` symbol required by WDF.
+///
+/// The argument is the path of a `fn(UnicodeString) -> DriverBuilder`, which
+/// receives the driver's registry path and returns a configured
+/// [`DriverBuilder`](crate::wdf::DriverBuilder). The generated `DriverEntry`
+/// then creates the WDF driver object and returns the resulting [`NTSTATUS`]
+/// to the operating system.
+///
+/// ```rust, ignore
+/// wdk::driver_entry!(driver_init);
+///
+/// fn driver_init(registry_path: UnicodeString) -> DriverBuilder {
+///     DriverBuilder::new().evt_device_add(evt_driver_device_add)
+/// }
+/// ```
+#[macro_export]
+macro_rules! driver_entry {
+    ($driver_init:path) => {
+        /// `DriverEntry` function required by WDF, generated by
+        /// `wdk::driver_entry!`
+        ///
+        /// # Safety
+        /// Function is unsafe since it dereferences raw pointers passed to it from WDF
+        #[export_name = "DriverEntry"] // WDF expects a symbol with the name DriverEntry
+        pub unsafe extern "system" fn __wdk_driver_entry(
+            driver: $crate::__wdk_sys::PDRIVER_OBJECT,
+            registry_path: $crate::__wdk_sys::PCUNICODE_STRING,
+        ) -> $crate::__wdk_sys::NTSTATUS {
+            // SAFETY: `driver` and `registry_path` are the arguments passed by the
+            // operating system to `DriverEntry`
+            unsafe {
+                $crate::wdf::DriverBuilder::__driver_entry(driver, registry_path, $driver_init)
+            }
+        }
+    };
+}
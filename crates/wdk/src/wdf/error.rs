@@ -0,0 +1,186 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+use core::fmt;
+
+use wdk_sys::{
+    ntstatus::NtStatus,
+    NTSTATUS,
+    STATUS_BUFFER_TOO_SMALL,
+    STATUS_CANCELLED,
+    STATUS_INSUFFICIENT_RESOURCES,
+    STATUS_INVALID_DEVICE_REQUEST,
+    STATUS_INVALID_DEVICE_STATE,
+    STATUS_INVALID_PARAMETER,
+    STATUS_NOT_FOUND,
+    STATUS_NOT_SUPPORTED,
+    STATUS_NO_MEMORY,
+    STATUS_NO_SUCH_DEVICE,
+    STATUS_OBJECT_NAME_COLLISION,
+};
+
+use crate::nt_success;
+
+/// Error returned by the safe abstractions in [`wdf`](crate::wdf) when a WDF
+/// API fails.
+///
+/// Each variant groups the [`NTSTATUS`] values of a family of failures, and
+/// records the name of the WDF API that failed along with the exact
+/// [`NTSTATUS`] it returned. [`Error`] converts into the original
+/// [`NTSTATUS`], so that callbacks returning [`NTSTATUS`] to the framework can
+/// propagate it with `?`:
+///
+/// ```rust, ignore
+/// fn evt_device_d0_entry(device: WDFDEVICE) -> Result<(), NTSTATUS> {
+///     let timer = Timer::try_new(&mut timer_config, &mut attributes)?;
+///     ...
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The system ran out of memory or other resources (ex.
+    /// `STATUS_INSUFFICIENT_RESOURCES`)
+    InsufficientResources {
+        /// Name of the WDF API that failed
+        api: &'static str,
+        /// [`NTSTATUS`] returned by the WDF API
+        status: NTSTATUS,
+    },
+    /// An argument passed to the WDF API was invalid (ex.
+    /// `STATUS_INVALID_PARAMETER`)
+    InvalidParameter {
+        /// Name of the WDF API that failed
+        api: &'static str,
+        /// [`NTSTATUS`] returned by the WDF API
+        status: NTSTATUS,
+    },
+    /// The WDF object or device is not in a state that allows the operation
+    /// (ex. `STATUS_INVALID_DEVICE_STATE`)
+    InvalidState {
+        /// Name of the WDF API that failed
+        api: &'static str,
+        /// [`NTSTATUS`] returned by the WDF API
+        status: NTSTATUS,
+    },
+    /// The operation was canceled (`STATUS_CANCELLED`)
+    Cancelled {
+        /// Name of the WDF API that failed
+        api: &'static str,
+        /// [`NTSTATUS`] returned by the WDF API
+        status: NTSTATUS,
+    },
+    /// The object or device does not exist, or already exists (ex.
+    /// `STATUS_NOT_FOUND` or `STATUS_OBJECT_NAME_COLLISION`)
+    NotFoundOrCollision {
+        /// Name of the WDF API that failed
+        api: &'static str,
+        /// [`NTSTATUS`] returned by the WDF API
+        status: NTSTATUS,
+    },
+    /// The operation is not supported (ex. `STATUS_NOT_SUPPORTED`)
+    NotSupported {
+        /// Name of the WDF API that failed
+        api: &'static str,
+        /// [`NTSTATUS`] returned by the WDF API
+        status: NTSTATUS,
+    },
+    /// Any other failure
+    Other {
+        /// Name of the WDF API that failed
+        api: &'static str,
+        /// [`NTSTATUS`] returned by the WDF API
+        status: NTSTATUS,
+    },
+}
+
+impl Error {
+    /// Constructs the [`Error`] for the failure `status` returned by the WDF
+    /// API named `api`
+    #[must_use]
+    pub const fn new(api: &'static str, status: NTSTATUS) -> Self {
+        match status {
+            STATUS_INSUFFICIENT_RESOURCES | STATUS_NO_MEMORY => {
+                Self::InsufficientResources { api, status }
+            }
+            STATUS_INVALID_PARAMETER | STATUS_BUFFER_TOO_SMALL => {
+                Self::InvalidParameter { api, status }
+            }
+            STATUS_INVALID_DEVICE_STATE | STATUS_INVALID_DEVICE_REQUEST => {
+                Self::InvalidState { api, status }
+            }
+            STATUS_CANCELLED => Self::Cancelled { api, status },
+            STATUS_NOT_FOUND | STATUS_NO_SUCH_DEVICE | STATUS_OBJECT_NAME_COLLISION => {
+                Self::NotFoundOrCollision { api, status }
+            }
+            STATUS_NOT_SUPPORTED => Self::NotSupported { api, status },
+            _ => Self::Other { api, status },
+        }
+    }
+
+    /// Returns the name of the WDF API that failed
+    #[must_use]
+    pub const fn api(&self) -> &'static str {
+        match *self {
+            Self::InsufficientResources { api, .. }
+            | Self::InvalidParameter { api, .. }
+            | Self::InvalidState { api, .. }
+            | Self::Cancelled { api, .. }
+            | Self::NotFoundOrCollision { api, .. }
+            | Self::NotSupported { api, .. }
+            | Self::Other { api, .. } => api,
+        }
+    }
+
+    /// Returns the [`NTSTATUS`] returned by the WDF API that failed
+    #[must_use]
+    pub const fn status(&self) -> NTSTATUS {
+        match *self {
+            Self::InsufficientResources { status, .. }
+            | Self::InvalidParameter { status, .. }
+            | Self::InvalidState { status, .. }
+            | Self::Cancelled { status, .. }
+            | Self::NotFoundOrCollision { status, .. }
+            | Self::NotSupported { status, .. }
+            | Self::Other { status, .. } => status,
+        }
+    }
+
+    /// Returns `Ok(())` if `status` is a success status, or the [`Error`] for
+    /// the failure of the WDF API named `api` otherwise
+    pub(crate) const fn check(api: &'static str, status: NTSTATUS) -> Result<(), Self> {
+        if nt_success(status) {
+            Ok(())
+        } else {
+            Err(Self::new(api, status))
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Self::InsufficientResources { .. } => "insufficient resources",
+            Self::InvalidParameter { .. } => "an invalid parameter",
+            Self::InvalidState { .. } => "an invalid state",
+            Self::Cancelled { .. } => "cancellation",
+            Self::NotFoundOrCollision { .. } => "a missing or existing object",
+            Self::NotSupported { .. } => "an unsupported operation",
+            Self::Other { .. } => "an error",
+        };
+        // The status is displayed by its symbolic name (ex.
+        // STATUS_INSUFFICIENT_RESOURCES), or in hexadecimal if it is not defined in
+        // ntstatus.h
+        write!(
+            f,
+            "{} failed with {description} ({})",
+            self.api(),
+            NtStatus(self.status())
+        )
+    }
+}
+
+impl From<Error> for NTSTATUS {
+    fn from(error: Error) -> Self {
+        error.status()
+    }
+}
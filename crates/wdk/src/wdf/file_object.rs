@@ -0,0 +1,355 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+use core::{
+    marker::PhantomData,
+    mem::{align_of, size_of, MaybeUninit},
+    ptr,
+};
+
+use wdk_sys::{
+    call_unsafe_wdf_function_binding,
+    _WDF_EXECUTION_LEVEL::WdfExecutionLevelInheritFromParent,
+    _WDF_FILEOBJECT_CLASS::WdfFileObjectWdfCannotUseFsContexts,
+    _WDF_SYNCHRONIZATION_SCOPE::WdfSynchronizationScopeInheritFromParent,
+    _WDF_TRI_STATE::{WdfFalse, WdfTrue, WdfUseDefault},
+    NTSTATUS,
+    PVOID,
+    PWDFDEVICE_INIT,
+    STATUS_SUCCESS,
+    ULONG,
+    WDFDEVICE,
+    WDFFILEOBJECT,
+    WDFOBJECT,
+    WDFREQUEST,
+    WDF_FILEOBJECT_CLASS,
+    WDF_FILEOBJECT_CONFIG,
+    WDF_OBJECT_ATTRIBUTES,
+    WDF_OBJECT_CONTEXT_TYPE_INFO,
+};
+
+use super::Request;
+
+/// Alignment guaranteed for every WDF object context
+/// (`MEMORY_ALLOCATION_ALIGNMENT`)
+#[cfg(target_pointer_width = "64")]
+const MEMORY_ALLOCATION_ALIGNMENT: usize = 16;
+#[cfg(target_pointer_width = "32")]
+const MEMORY_ALLOCATION_ALIGNMENT: usize = 8;
+
+/// Context of a file object, holding the [`FileObjectCallbacks::Context`]
+/// returned by [`FileObjectCallbacks::create`].
+///
+/// WDF zero-initializes contexts, so `is_initialized` is `false` until the
+/// file object is successfully created.
+#[repr(C)]
+struct FileObjectContext<T> {
+    value: MaybeUninit<T>,
+    is_initialized: bool,
+}
+
+/// [`WDF_OBJECT_CONTEXT_TYPE_INFO`] of the [`FileObjectContext`] of file
+/// objects configured by a [`FileObjectConfig`].
+///
+/// The same type info is used for every [`FileObjectCallbacks::Context`] type,
+/// since statics cannot be generic. The actual size of the context is set via
+/// `ContextSizeOverride` when the file object configuration is registered.
+struct FileObjectContextTypeInfo(WDF_OBJECT_CONTEXT_TYPE_INFO);
+
+// SAFETY: The context type info is immutable, and only points to itself and to
+// a static string
+unsafe impl Sync for FileObjectContextTypeInfo {}
+
+// clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+#[allow(clippy::cast_possible_truncation)]
+static FILE_OBJECT_CONTEXT_TYPE_INFO: FileObjectContextTypeInfo =
+    FileObjectContextTypeInfo(WDF_OBJECT_CONTEXT_TYPE_INFO {
+        Size: size_of::<WDF_OBJECT_CONTEXT_TYPE_INFO>() as ULONG,
+        ContextName: c"wdk::wdf::FileObjectContext".as_ptr().cast_mut(),
+        ContextSize: size_of::<FileObjectContext<()>>(),
+        UniqueType: ptr::addr_of!(FILE_OBJECT_CONTEXT_TYPE_INFO.0),
+        EvtDriverGetUniqueContextType: None,
+    });
+
+/// File object callbacks of a WDF device, with a typed context for each file
+/// object.
+///
+/// A driver implements [`FileObjectCallbacks`] for a type of its choosing and
+/// registers it on the `WDFDEVICE_INIT` of a device with
+/// [`FileObjectConfig::register`], before the device is created. WDF then
+/// calls [`FileObjectCallbacks::create`] whenever an application or another
+/// driver opens a handle to the device (ex. through one of its device
+/// interfaces), and the returned [`FileObjectCallbacks::Context`] tracks the
+/// state of that handle until its file object is destroyed.
+///
+/// ```rust, ignore
+/// struct Handles;
+///
+/// impl FileObjectCallbacks for Handles {
+///     type Context = HandleState;
+///
+///     fn create(
+///         _device: WDFDEVICE,
+///         _request: &Request,
+///         _file_object: WDFFILEOBJECT,
+///     ) -> Result<HandleState, NTSTATUS> {
+///         Ok(HandleState::default())
+///     }
+/// }
+///
+/// // In `EvtDriverDeviceAdd`
+/// // SAFETY: `device_init` is the `DeviceInit` argument of `EvtDriverDeviceAdd`
+/// unsafe { FileObjectConfig::<Handles>::new().register(device_init) };
+/// ```
+///
+/// The context of the file object of a request (ex. from
+/// `WdfRequestGetFileObject`) can be retrieved in I/O callbacks with
+/// [`file_object_context`].
+pub trait FileObjectCallbacks {
+    /// State tracked for each file object
+    type Context: Send + Sync;
+
+    /// Called when a file object is created for `device`, with the create
+    /// `request`. The returned context is attached to `file_object`, and the
+    /// request is then completed successfully.
+    ///
+    /// This is the `EvtDeviceFileCreate` callback.
+    ///
+    /// # Errors
+    ///
+    /// An error returned by this callback completes the create request with the returned [`NTSTATUS`], which fails the creation of the file object (ex. `STATUS_ACCESS_DENIED` to reject the handle). Full documentation is available in the [EvtWdfDeviceFileCreate Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfdevice/nc-wdfdevice-evt_wdf_device_file_create)
+    fn create(
+        device: WDFDEVICE,
+        request: &Request,
+        file_object: WDFFILEOBJECT,
+    ) -> Result<Self::Context, NTSTATUS>;
+
+    /// Called when the last handle to `file_object` is closed, which may
+    /// happen while I/O requests for it are still pending.
+    ///
+    /// This is the `EvtFileCleanup` callback.
+    fn cleanup(_file_object: WDFFILEOBJECT, _context: &Self::Context) {}
+
+    /// Called when all references to `file_object` are released, after all of
+    /// its I/O requests have completed. `context` is dropped after this
+    /// callback returns.
+    ///
+    /// This is the `EvtFileClose` callback.
+    fn close(_file_object: WDFFILEOBJECT, _context: &Self::Context) {}
+}
+
+/// Configuration of the file objects of a WDF device, dispatching to the
+/// callbacks of `C`.
+///
+/// This is the equivalent of `WDF_FILEOBJECT_CONFIG_INIT`, with the
+/// `EvtDeviceFileCreate`, `EvtFileClose` and `EvtFileCleanup` callbacks set.
+/// The configuration is applied to a device with
+/// [`FileObjectConfig::register`].
+pub struct FileObjectConfig<C> {
+    file_object_config: WDF_FILEOBJECT_CONFIG,
+    callbacks: PhantomData<fn() -> C>,
+}
+
+impl<C: FileObjectCallbacks> FileObjectConfig<C> {
+    /// Creates a [`FileObjectConfig`] with the callbacks of `C`, and the
+    /// default WDF file object settings
+    #[must_use]
+    pub fn new() -> Self {
+        // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+        #[allow(clippy::cast_possible_truncation)]
+        const WDF_FILEOBJECT_CONFIG_SIZE: ULONG = size_of::<WDF_FILEOBJECT_CONFIG>() as ULONG;
+
+        Self {
+            file_object_config: WDF_FILEOBJECT_CONFIG {
+                Size: WDF_FILEOBJECT_CONFIG_SIZE,
+                EvtDeviceFileCreate: Some(evt_device_file_create::<C>),
+                EvtFileClose: Some(evt_file_close::<C>),
+                EvtFileCleanup: Some(evt_file_cleanup::<C>),
+                AutoForwardCleanupClose: WdfUseDefault,
+                FileObjectClass: WdfFileObjectWdfCannotUseFsContexts,
+            },
+            callbacks: PhantomData,
+        }
+    }
+
+    /// Sets whether WDF forwards cleanup and close requests to the next lower
+    /// driver (ex. in filter drivers). By default, WDF decides based on
+    /// whether the device is a filter device.
+    #[must_use]
+    pub const fn auto_forward_cleanup_close(mut self, auto_forward: bool) -> Self {
+        self.file_object_config.AutoForwardCleanupClose =
+            if auto_forward { WdfTrue } else { WdfFalse };
+        self
+    }
+
+    /// Sets where WDF stores its file object handles, which determines
+    /// whether the driver can use the `FsContext` fields of file objects. The
+    /// default is `WdfFileObjectWdfCannotUseFsContexts`.
+    #[must_use]
+    pub const fn file_object_class(mut self, file_object_class: WDF_FILEOBJECT_CLASS) -> Self {
+        self.file_object_config.FileObjectClass = file_object_class;
+        self
+    }
+
+    /// Registers this configuration for the file objects of the device that
+    /// will be created from `device_init`.
+    ///
+    /// # Safety
+    ///
+    /// `device_init` must be a valid `PWDFDEVICE_INIT` that has not yet been
+    /// used to create a device (ex. the `DeviceInit` argument of
+    /// `EvtDriverDeviceAdd`).
+    pub unsafe fn register(mut self, device_init: PWDFDEVICE_INIT) {
+        // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+        #[allow(clippy::cast_possible_truncation)]
+        const WDF_OBJECT_ATTRIBUTES_SIZE: ULONG = size_of::<WDF_OBJECT_ATTRIBUTES>() as ULONG;
+
+        const {
+            assert!(
+                align_of::<FileObjectContext<C::Context>>() <= MEMORY_ALLOCATION_ALIGNMENT,
+                "file object contexts with an alignment greater than \
+                 `MEMORY_ALLOCATION_ALIGNMENT` are not supported"
+            );
+        }
+
+        let mut file_object_attributes = WDF_OBJECT_ATTRIBUTES {
+            Size: WDF_OBJECT_ATTRIBUTES_SIZE,
+            EvtCleanupCallback: None,
+            EvtDestroyCallback: Some(evt_file_object_destroy::<C>),
+            ExecutionLevel: WdfExecutionLevelInheritFromParent,
+            SynchronizationScope: WdfSynchronizationScopeInheritFromParent,
+            ParentObject: ptr::null_mut(),
+            ContextSizeOverride: size_of::<FileObjectContext<C::Context>>(),
+            ContextTypeInfo: ptr::addr_of!(FILE_OBJECT_CONTEXT_TYPE_INFO.0),
+        };
+
+        // SAFETY: The caller guarantees that `device_init` is valid and has not been
+        // used to create a device yet. WDF copies the configuration and attributes,
+        // so they are only read for the duration of the call.
+        unsafe {
+            call_unsafe_wdf_function_binding!(
+                WdfDeviceInitSetFileObjectConfig,
+                device_init,
+                ptr::addr_of_mut!(self.file_object_config),
+                ptr::addr_of_mut!(file_object_attributes),
+            );
+        }
+    }
+}
+
+impl<C: FileObjectCallbacks> Default for FileObjectConfig<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the [`FileObjectCallbacks::Context`] of `file_object`, or `None` if
+/// the file object has not been successfully created yet
+///
+/// # Safety
+///
+/// `file_object` must be a valid file object of a device whose file objects
+/// were configured with a [`FileObjectConfig<C>`], and the returned reference
+/// must not outlive the file object.
+#[must_use]
+pub unsafe fn file_object_context<'a, C: FileObjectCallbacks>(
+    file_object: WDFFILEOBJECT,
+) -> Option<&'a C::Context> {
+    // SAFETY: The caller guarantees that `file_object` is a valid file object of
+    // a device configured with `FileObjectConfig<C>`
+    let context = unsafe { get_context::<C>(file_object) };
+    // SAFETY: The context is only mutated before the file object is accessible and
+    // while it is being destroyed, and the caller guarantees that the returned
+    // reference does not outlive the file object
+    let context = unsafe { &*context };
+    if !context.is_initialized {
+        return None;
+    }
+    // SAFETY: `value` is initialized when `is_initialized` is set
+    Some(unsafe { context.value.assume_init_ref() })
+}
+
+/// Returns a pointer to the [`FileObjectContext`] of `file_object`
+///
+/// # Safety
+///
+/// `file_object` must be a valid file object of a device whose file objects
+/// were configured with a [`FileObjectConfig<C>`]
+unsafe fn get_context<C: FileObjectCallbacks>(
+    file_object: WDFFILEOBJECT,
+) -> *mut FileObjectContext<C::Context> {
+    let context: PVOID;
+    // SAFETY: The caller guarantees that `file_object` is a valid file object,
+    // whose context was allocated with `FILE_OBJECT_CONTEXT_TYPE_INFO`
+    unsafe {
+        context = call_unsafe_wdf_function_binding!(
+            WdfObjectGetTypedContextWorker,
+            file_object.cast(),
+            ptr::addr_of!(FILE_OBJECT_CONTEXT_TYPE_INFO.0),
+        );
+    }
+    context.cast()
+}
+
+/// `EvtDeviceFileCreate` callback that dispatches to
+/// [`FileObjectCallbacks::create`]
+unsafe extern "C" fn evt_device_file_create<C: FileObjectCallbacks>(
+    device: WDFDEVICE,
+    wdf_request: WDFREQUEST,
+    file_object: WDFFILEOBJECT,
+) {
+    // SAFETY: WDF passes ownership of the create request to this callback, and it
+    // is only completed through the returned `Request`
+    let request = unsafe { Request::from_raw(wdf_request) };
+
+    match C::create(device, &request, file_object) {
+        Ok(value) => {
+            // SAFETY: WDF only calls this callback for file objects of devices
+            // configured with `FileObjectConfig<C>`
+            let context = unsafe { get_context::<C>(file_object) };
+            // SAFETY: The file object cannot be accessed by anything else before its
+            // create request is completed
+            let context = unsafe { &mut *context };
+            context.value.write(value);
+            context.is_initialized = true;
+            request.complete(STATUS_SUCCESS);
+        }
+        Err(nt_status) => request.complete(nt_status),
+    }
+}
+
+/// `EvtFileCleanup` callback that dispatches to
+/// [`FileObjectCallbacks::cleanup`]
+unsafe extern "C" fn evt_file_cleanup<C: FileObjectCallbacks>(file_object: WDFFILEOBJECT) {
+    // SAFETY: WDF only calls this callback for file objects of devices configured
+    // with `FileObjectConfig<C>`, and the context outlives the callback
+    if let Some(context) = unsafe { file_object_context::<C>(file_object) } {
+        C::cleanup(file_object, context);
+    }
+}
+
+/// `EvtFileClose` callback that dispatches to [`FileObjectCallbacks::close`]
+unsafe extern "C" fn evt_file_close<C: FileObjectCallbacks>(file_object: WDFFILEOBJECT) {
+    // SAFETY: WDF only calls this callback for file objects of devices configured
+    // with `FileObjectConfig<C>`, and the context outlives the callback
+    if let Some(context) = unsafe { file_object_context::<C>(file_object) } {
+        C::close(file_object, context);
+    }
+}
+
+/// `EvtDestroyCallback` of file objects, which drops their
+/// [`FileObjectCallbacks::Context`]
+unsafe extern "C" fn evt_file_object_destroy<C: FileObjectCallbacks>(object: WDFOBJECT) {
+    // SAFETY: WDF only calls this callback for file objects of devices configured
+    // with `FileObjectConfig<C>`
+    let context = unsafe { get_context::<C>(object.cast()) };
+    // SAFETY: Nothing else can access the context of a file object that is being
+    // destroyed
+    let context = unsafe { &mut *context };
+    if context.is_initialized {
+        context.is_initialized = false;
+        // SAFETY: `value` is initialized when `is_initialized` is set, and is not
+        // accessed after it is dropped
+        unsafe { context.value.assume_init_drop() };
+    }
+}
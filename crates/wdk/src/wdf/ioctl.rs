@@ -0,0 +1,510 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+extern crate alloc;
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{ffi::c_void, mem::size_of, ptr};
+
+use wdk_sys::{
+    call_unsafe_wdf_function_binding,
+    _WDF_EXECUTION_LEVEL::WdfExecutionLevelInheritFromParent,
+    _WDF_IO_QUEUE_DISPATCH_TYPE::WdfIoQueueDispatchParallel,
+    _WDF_REQUEST_TYPE::WdfRequestTypeDeviceControl,
+    _WDF_SYNCHRONIZATION_SCOPE::WdfSynchronizationScopeInheritFromParent,
+    _WDF_TRI_STATE::WdfUseDefault,
+    NTSTATUS,
+    PVOID,
+    STATUS_INVALID_DEVICE_REQUEST,
+    STATUS_INVALID_DEVICE_STATE,
+    STATUS_SUCCESS,
+    ULONG,
+    ULONG_PTR,
+    WDFDEVICE,
+    WDFOBJECT,
+    WDFQUEUE,
+    WDFREQUEST,
+    WDF_IO_QUEUE_CONFIG,
+    WDF_IO_QUEUE_DISPATCH_TYPE,
+    WDF_OBJECT_ATTRIBUTES,
+    WDF_OBJECT_CONTEXT_TYPE_INFO,
+};
+
+use super::{Error, Request};
+
+/// Transfer type of IOCTLs whose input and output buffers are copied through
+/// a system buffer
+const METHOD_BUFFERED: ULONG = 0;
+
+/// Returns the IOCTL code of a device control operation, like the `CTL_CODE`
+/// macro of the WDK
+///
+/// `method` is one of `METHOD_BUFFERED`, `METHOD_IN_DIRECT`,
+/// `METHOD_OUT_DIRECT` or `METHOD_NEITHER`, and `access` is a combination of
+/// `FILE_ANY_ACCESS`, `FILE_READ_ACCESS` and `FILE_WRITE_ACCESS`.
+#[must_use]
+pub const fn ctl_code(device_type: ULONG, function: ULONG, method: ULONG, access: ULONG) -> ULONG {
+    (device_type << 16) | (access << 14) | (function << 2) | method
+}
+
+/// Returns the transfer type of `io_control_code`, like the
+/// `METHOD_FROM_CTL_CODE` macro of the WDK
+#[must_use]
+pub const fn method_from_ctl_code(io_control_code: ULONG) -> ULONG {
+    io_control_code & 0b11
+}
+
+/// A type that can be copied to and from the buffers of a `METHOD_BUFFERED`
+/// IOCTL by an [`IoctlRouter`].
+///
+/// The input buffer is filled by the caller of the IOCTL (ex. a user-mode
+/// application), and the output buffer is returned to it as-is, so the type is
+/// read from, and written to, raw bytes.
+///
+/// # Safety
+///
+/// Every bit pattern must be a valid value of the type (ex. integers, and
+/// `#[repr(C)]` structs and arrays of them, but not `bool`, `char`, enums or
+/// references), and the type must not contain padding bytes, which would leak
+/// uninitialized kernel memory to the caller of the IOCTL.
+pub unsafe trait IoctlBuffer: Copy + Send + 'static {}
+
+macro_rules! impl_ioctl_buffer {
+    ($($type:ty),* $(,)?) => {
+        $(
+            // SAFETY: Every bit pattern is a valid value of the type, which has no
+            // padding bytes
+            unsafe impl IoctlBuffer for $type {}
+        )*
+    };
+}
+
+impl_ioctl_buffer!(
+    (),
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize
+);
+
+// SAFETY: An array has no padding between its elements, and every bit pattern
+// is valid for it if every bit pattern is valid for its elements
+unsafe impl<T: IoctlBuffer, const N: usize> IoctlBuffer for [T; N] {}
+
+/// Parameters of a device control request dispatched by an [`IoctlRouter`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoctlParameters {
+    /// The IOCTL code of the request
+    pub io_control_code: ULONG,
+    /// The length of the input buffer of the request, in bytes
+    pub input_buffer_length: usize,
+    /// The length of the output buffer of the request, in bytes
+    pub output_buffer_length: usize,
+}
+
+/// Handler of an IOCTL, which receives the device of the queue and takes over
+/// the responsibility of completing the request
+type IoctlHandler = Box<dyn Fn(WDFDEVICE, Request, &IoctlParameters) + Send + Sync>;
+
+/// Handler of a single IOCTL code
+struct Route {
+    io_control_code: ULONG,
+    handler: IoctlHandler,
+}
+
+/// Routes of an [`IoctlRouter`], stored in the context of its queue
+type Routes = Box<[Route]>;
+
+/// [`WDF_OBJECT_CONTEXT_TYPE_INFO`] of the context holding the routes of the
+/// queue created by [`IoctlRouter::create_queue`]
+struct IoctlRouterContextTypeInfo(WDF_OBJECT_CONTEXT_TYPE_INFO);
+
+// SAFETY: The context type info is immutable, and only points to itself and to
+// a static string
+unsafe impl Sync for IoctlRouterContextTypeInfo {}
+
+// clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+#[allow(clippy::cast_possible_truncation)]
+static IOCTL_ROUTER_CONTEXT_TYPE_INFO: IoctlRouterContextTypeInfo =
+    IoctlRouterContextTypeInfo(WDF_OBJECT_CONTEXT_TYPE_INFO {
+        Size: size_of::<WDF_OBJECT_CONTEXT_TYPE_INFO>() as ULONG,
+        ContextName: c"wdk::wdf::IoctlRouterContext".as_ptr().cast_mut(),
+        ContextSize: size_of::<Option<Routes>>(),
+        UniqueType: ptr::addr_of!(IOCTL_ROUTER_CONTEXT_TYPE_INFO.0),
+        EvtDriverGetUniqueContextType: None,
+    });
+
+/// A table of IOCTL handlers, which dispatches the device control requests of
+/// an I/O queue by their IOCTL code.
+///
+/// Typed handlers, registered with [`IoctlRouter::route`], receive a copy of
+/// the input buffer of a `METHOD_BUFFERED` IOCTL, and return the value copied
+/// to its output buffer, so they never touch the raw buffers of the request:
+///
+/// ```rust, ignore
+/// const IOCTL_ECHO: ULONG = ctl_code(FILE_DEVICE_UNKNOWN, 0x800, METHOD_BUFFERED, FILE_ANY_ACCESS);
+///
+/// IoctlRouter::new()
+///     .route(IOCTL_ECHO, |_device, input: &u32| Ok::<u32, NTSTATUS>(*input))
+///     .create_queue(device, WdfIoQueueDispatchParallel)?;
+/// ```
+///
+/// Requests with an IOCTL code that has no handler are completed with
+/// `STATUS_INVALID_DEVICE_REQUEST`.
+#[derive(Default)]
+pub struct IoctlRouter {
+    routes: Vec<Route>,
+}
+
+impl IoctlRouter {
+    /// Creates an [`IoctlRouter`] without any handler
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { routes: Vec::new() }
+    }
+
+    /// Registers `handler` for the `METHOD_BUFFERED` IOCTL `io_control_code`.
+    ///
+    /// `handler` receives the device of the queue and a copy of the input
+    /// buffer, and returns the value written to the output buffer. The request
+    /// is completed with `STATUS_SUCCESS` and the size of `O` as its
+    /// information, or with the error returned by `handler`. Requests whose
+    /// input buffer is shorter than `I`, or whose output buffer is shorter than
+    /// `O`, are completed with `STATUS_BUFFER_TOO_SMALL` without calling
+    /// `handler`.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `io_control_code` is not a `METHOD_BUFFERED`
+    /// IOCTL, or if it already has a handler.
+    #[must_use]
+    pub fn route<I, O, E, F>(self, io_control_code: ULONG, handler: F) -> Self
+    where
+        I: IoctlBuffer,
+        O: IoctlBuffer,
+        E: Into<NTSTATUS>,
+        F: Fn(WDFDEVICE, &I) -> Result<O, E> + Send + Sync + 'static,
+    {
+        assert_eq!(
+            method_from_ctl_code(io_control_code),
+            METHOD_BUFFERED,
+            "IOCTL {io_control_code:#x} is not a METHOD_BUFFERED IOCTL"
+        );
+
+        self.route_request(io_control_code, move |device, request, _parameters| {
+            match dispatch_buffered(device, &request, &handler) {
+                Ok(()) => {
+                    request.complete_with_information(STATUS_SUCCESS, size_of::<O>() as ULONG_PTR);
+                }
+                Err(nt_status) => request.complete(nt_status),
+            }
+        })
+    }
+
+    /// Registers `handler` for the IOCTL `io_control_code`, with any transfer
+    /// type.
+    ///
+    /// `handler` receives the device of the queue, the request and its
+    /// parameters, and is responsible for retrieving the buffers of the request
+    /// (ex. with [`Request::direct_io_buffers`]) and completing it.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `io_control_code` already has a handler.
+    #[must_use]
+    pub fn route_request<F>(mut self, io_control_code: ULONG, handler: F) -> Self
+    where
+        F: Fn(WDFDEVICE, Request, &IoctlParameters) + Send + Sync + 'static,
+    {
+        assert!(
+            self.routes
+                .iter()
+                .all(|route| route.io_control_code != io_control_code),
+            "IOCTL {io_control_code:#x} already has a handler"
+        );
+
+        self.routes.push(Route {
+            io_control_code,
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// Creates an I/O queue for `device` that dispatches its device control
+    /// requests to the handlers of this router, with `dispatch_type`.
+    ///
+    /// The queue receives every device control request of the device, and owns
+    /// the handlers until it is deleted along with the device. Handlers of a
+    /// queue created with `WdfIoQueueDispatchParallel` can be called
+    /// concurrently.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if WDF fails to create the queue, or to configure it to receive the device control requests of `device`. Full error documentation is available in the [WdfIoQueueCreate Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfio/nf-wdfio-wdfioqueuecreate#return-value)
+    pub fn create_queue(
+        self,
+        device: WDFDEVICE,
+        dispatch_type: WDF_IO_QUEUE_DISPATCH_TYPE,
+    ) -> Result<WDFQUEUE, Error> {
+        // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+        #[allow(clippy::cast_possible_truncation)]
+        const WDF_IO_QUEUE_CONFIG_SIZE: ULONG = size_of::<WDF_IO_QUEUE_CONFIG>() as ULONG;
+        // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+        #[allow(clippy::cast_possible_truncation)]
+        const WDF_OBJECT_ATTRIBUTES_SIZE: ULONG = size_of::<WDF_OBJECT_ATTRIBUTES>() as ULONG;
+
+        let mut queue_config = WDF_IO_QUEUE_CONFIG {
+            Size: WDF_IO_QUEUE_CONFIG_SIZE,
+            DispatchType: dispatch_type,
+            PowerManaged: WdfUseDefault,
+            EvtIoDeviceControl: Some(evt_io_device_control),
+            ..WDF_IO_QUEUE_CONFIG::default()
+        };
+        if dispatch_type == WdfIoQueueDispatchParallel {
+            queue_config.Settings.Parallel.NumberOfPresentedRequests = ULONG::MAX;
+        }
+
+        let mut queue_attributes = WDF_OBJECT_ATTRIBUTES {
+            Size: WDF_OBJECT_ATTRIBUTES_SIZE,
+            EvtCleanupCallback: None,
+            EvtDestroyCallback: Some(evt_ioctl_router_context_destroy),
+            ExecutionLevel: WdfExecutionLevelInheritFromParent,
+            SynchronizationScope: WdfSynchronizationScopeInheritFromParent,
+            ParentObject: ptr::null_mut(),
+            ContextSizeOverride: 0,
+            ContextTypeInfo: ptr::addr_of!(IOCTL_ROUTER_CONTEXT_TYPE_INFO.0),
+        };
+        let mut queue: WDFQUEUE = ptr::null_mut();
+
+        let nt_status;
+        // SAFETY: `device` is a valid WDF device, and the queue is not the default
+        // queue of the device, so it receives no request before it is configured
+        // below
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfIoQueueCreate,
+                device,
+                &mut queue_config,
+                &mut queue_attributes,
+                &mut queue,
+            );
+        }
+        Error::check("WdfIoQueueCreate", nt_status)?;
+
+        // SAFETY: `queue` was created above with the context of `IoctlRouter`
+        let context = unsafe { ioctl_router_context(queue) };
+        // A newly allocated context is zero-initialized, so there are no previous
+        // routes to drop
+        // SAFETY: The context was allocated by WDF with the size and alignment of
+        // `Option<Routes>`, and no callback of the queue accesses it until the queue
+        // receives requests
+        unsafe {
+            ptr::write(context, Some(self.routes.into_boxed_slice()));
+        }
+
+        let nt_status;
+        // SAFETY: `queue` was created above for `device`
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfDeviceConfigureRequestDispatching,
+                device,
+                queue,
+                WdfRequestTypeDeviceControl,
+            );
+        }
+        Error::check("WdfDeviceConfigureRequestDispatching", nt_status).map(|()| queue)
+    }
+}
+
+impl core::fmt::Debug for IoctlRouter {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list()
+            .entries(self.routes.iter().map(|route| route.io_control_code))
+            .finish()
+    }
+}
+
+/// Copies the input buffer of a `METHOD_BUFFERED` request, checks that its
+/// output buffer can hold an `O`, calls `handler` with the input, and copies
+/// its result to the output buffer of the request
+fn dispatch_buffered<I, O, E, F>(
+    device: WDFDEVICE,
+    request: &Request,
+    handler: &F,
+) -> Result<(), NTSTATUS>
+where
+    I: IoctlBuffer,
+    O: IoctlBuffer,
+    E: Into<NTSTATUS>,
+    F: Fn(WDFDEVICE, &I) -> Result<O, E>,
+{
+    // The input and output buffers of a `METHOD_BUFFERED` IOCTL share the same
+    // system buffer, so the input is copied out before the output is written
+    let input: I = if size_of::<I>() == 0 {
+        // SAFETY: Every bit pattern, including the empty one, is a valid `I`
+        unsafe { core::mem::zeroed() }
+    } else {
+        let buffer = retrieve_buffer(request, size_of::<I>(), BufferKind::Input)?;
+        // SAFETY: WDF guarantees that the input buffer holds at least `size_of::<I>()`
+        // bytes, and every bit pattern is a valid `I`. The buffer is only aligned to
+        // `MEMORY_ALLOCATION_ALIGNMENT`, so it is read unaligned.
+        unsafe { ptr::read_unaligned(buffer.cast::<I>()) }
+    };
+
+    // The output buffer is retrieved before calling `handler`, so that requests
+    // with a short output buffer are completed without calling it
+    let output_buffer = if size_of::<O>() == 0 {
+        None
+    } else {
+        Some(retrieve_buffer(
+            request,
+            size_of::<O>(),
+            BufferKind::Output,
+        )?)
+    };
+
+    let output = handler(device, &input).map_err(Into::into)?;
+
+    if let Some(buffer) = output_buffer {
+        // SAFETY: WDF guarantees that the output buffer holds at least
+        // `size_of::<O>()` bytes, and nothing else accesses it until the request is
+        // completed
+        unsafe { ptr::write_unaligned(buffer.cast::<O>(), output) };
+    }
+    Ok(())
+}
+
+/// The buffer of a request retrieved by [`retrieve_buffer`]
+#[derive(Clone, Copy)]
+enum BufferKind {
+    Input,
+    Output,
+}
+
+/// Retrieves the input or output buffer of `request`, which must hold at
+/// least `minimum_length` bytes
+fn retrieve_buffer(
+    request: &Request,
+    minimum_length: usize,
+    buffer_kind: BufferKind,
+) -> Result<*mut c_void, NTSTATUS> {
+    let mut buffer: PVOID = ptr::null_mut();
+
+    let nt_status;
+    match buffer_kind {
+        BufferKind::Input => {
+            // SAFETY: The request is a valid WDF request owned by `request`
+            unsafe {
+                nt_status = call_unsafe_wdf_function_binding!(
+                    WdfRequestRetrieveInputBuffer,
+                    request.as_raw(),
+                    minimum_length,
+                    &mut buffer,
+                    ptr::null_mut(),
+                );
+            }
+        }
+        BufferKind::Output => {
+            // SAFETY: The request is a valid WDF request owned by `request`
+            unsafe {
+                nt_status = call_unsafe_wdf_function_binding!(
+                    WdfRequestRetrieveOutputBuffer,
+                    request.as_raw(),
+                    minimum_length,
+                    &mut buffer,
+                    ptr::null_mut(),
+                );
+            }
+        }
+    }
+
+    if crate::nt_success(nt_status) {
+        Ok(buffer)
+    } else {
+        Err(nt_status)
+    }
+}
+
+/// Returns a pointer to the context holding the routes of `queue`
+///
+/// # Safety
+///
+/// `queue` must be a valid WDF queue created by [`IoctlRouter::create_queue`]
+unsafe fn ioctl_router_context(queue: WDFQUEUE) -> *mut Option<Routes> {
+    let context: PVOID;
+    // SAFETY: The caller guarantees that `queue` is a valid WDF queue
+    unsafe {
+        context = call_unsafe_wdf_function_binding!(
+            WdfObjectGetTypedContextWorker,
+            queue.cast(),
+            ptr::addr_of!(IOCTL_ROUTER_CONTEXT_TYPE_INFO.0),
+        );
+    }
+    context.cast()
+}
+
+/// `EvtIoDeviceControl` callback of the queues created by
+/// [`IoctlRouter::create_queue`], which dispatches requests to the handler of
+/// their IOCTL code
+unsafe extern "C" fn evt_io_device_control(
+    queue: WDFQUEUE,
+    wdf_request: WDFREQUEST,
+    output_buffer_length: usize,
+    input_buffer_length: usize,
+    io_control_code: ULONG,
+) {
+    // SAFETY: WDF passes the ownership of the request to this callback
+    let request = unsafe { Request::from_raw(wdf_request) };
+
+    // SAFETY: WDF only calls this callback for queues created by
+    // `IoctlRouter::create_queue`
+    let context = unsafe { ioctl_router_context(queue) };
+    // SAFETY: The context is only mutated before the queue receives requests, and
+    // after it is deleted
+    let Some(routes) = (unsafe { &*context }) else {
+        request.complete(STATUS_INVALID_DEVICE_STATE);
+        return;
+    };
+    let Some(route) = routes
+        .iter()
+        .find(|route| route.io_control_code == io_control_code)
+    else {
+        request.complete(STATUS_INVALID_DEVICE_REQUEST);
+        return;
+    };
+
+    let device: WDFDEVICE;
+    // SAFETY: `queue` is a valid WDF queue for the duration of the callback
+    unsafe {
+        device = call_unsafe_wdf_function_binding!(WdfIoQueueGetDevice, queue);
+    }
+
+    (route.handler)(
+        device,
+        request,
+        &IoctlParameters {
+            io_control_code,
+            input_buffer_length,
+            output_buffer_length,
+        },
+    );
+}
+
+/// `EvtDestroyCallback` of the queues created by
+/// [`IoctlRouter::create_queue`], which drops their routes
+unsafe extern "C" fn evt_ioctl_router_context_destroy(object: WDFOBJECT) {
+    // SAFETY: WDF only calls this callback for queues created by
+    // `IoctlRouter::create_queue`
+    let context = unsafe { ioctl_router_context(object.cast()) };
+    // SAFETY: Nothing else can access the context of a queue that is being
+    // destroyed
+    drop(unsafe { (*context).take() });
+}
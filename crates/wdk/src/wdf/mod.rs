@@ -3,8 +3,34 @@
 
 //! Safe abstractions over WDF APIs
 
+pub use callback::*;
+#[cfg(driver_model__driver_type = "KMDF")]
+pub use child_list::*;
+pub use driver::*;
+pub use error::*;
+pub use file_object::*;
+#[cfg(feature = "alloc")]
+pub use ioctl::*;
+#[cfg(driver_model__driver_type = "KMDF")]
+pub use pdo::*;
+pub use power::*;
+pub use request::*;
 pub use spinlock::*;
 pub use timer::*;
+pub use wait_lock::*;
 
+mod callback;
+#[cfg(driver_model__driver_type = "KMDF")]
+mod child_list;
+mod driver;
+mod error;
+mod file_object;
+#[cfg(feature = "alloc")]
+mod ioctl;
+#[cfg(driver_model__driver_type = "KMDF")]
+mod pdo;
+mod power;
+mod request;
 mod spinlock;
 mod timer;
+mod wait_lock;
@@ -0,0 +1,308 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+use wdk_sys::{
+    call_unsafe_wdf_function_binding,
+    GUID,
+    LCID,
+    PWDFDEVICE_INIT,
+    STATUS_INSUFFICIENT_RESOURCES,
+    UNICODE_STRING,
+    WDFDEVICE,
+    WDF_OBJECT_ATTRIBUTES,
+};
+
+use super::Error;
+
+/// Initialization data for a PDO (physical device object) created by a bus
+/// driver.
+///
+/// A [`PdoInit`] is either provided by WDF to the `EvtChildListCreateDevice`
+/// callback of a [`ChildList`](super::ChildList) (see
+/// [`PdoInit::from_child_init`]), or allocated by the bus driver to statically
+/// enumerate a child device (see [`PdoInit::allocate`]). Once the identifiers
+/// of the child device are assigned, the PDO is created with
+/// [`PdoInit::create_device`].
+pub struct PdoInit {
+    device_init: PWDFDEVICE_INIT,
+    // Whether the driver allocated `device_init`, and must free it if the PDO is never created
+    is_allocated_by_driver: bool,
+}
+
+impl PdoInit {
+    /// Try to allocate the initialization data for a PDO that is a child of
+    /// `parent_device`. This is used for static enumeration of child devices,
+    /// via [`PdoInit::create_static_child`].
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an [`Error::InsufficientResources`] if WDF fails to allocate the initialization data. Full documentation is available in the [WdfPdoInitAllocate Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfpdo/nf-wdfpdo-wdfpdoinitallocate#return-value)
+    pub fn allocate(parent_device: WDFDEVICE) -> Result<Self, Error> {
+        let device_init;
+        // SAFETY: `parent_device` is a handle to a WDF device object, and the returned
+        // `PWDFDEVICE_INIT` is owned by the resulting `PdoInit`, which frees it on drop
+        // if the PDO is never created
+        unsafe {
+            device_init = call_unsafe_wdf_function_binding!(WdfPdoInitAllocate, parent_device);
+        }
+
+        if device_init.is_null() {
+            return Err(Error::new(
+                "WdfPdoInitAllocate",
+                STATUS_INSUFFICIENT_RESOURCES,
+            ));
+        }
+
+        Ok(Self {
+            device_init,
+            is_allocated_by_driver: true,
+        })
+    }
+
+    /// Wraps the initialization data passed by WDF to the
+    /// `EvtChildListCreateDevice` callback of a [`ChildList`](super::ChildList)
+    ///
+    /// # Safety
+    ///
+    /// `child_init` must be the `ChildInit` argument passed to the
+    /// `EvtChildListCreateDevice` callback currently executing, and the
+    /// returned [`PdoInit`] must not outlive the callback.
+    #[must_use]
+    pub const unsafe fn from_child_init(child_init: PWDFDEVICE_INIT) -> Self {
+        Self {
+            device_init: child_init,
+            is_allocated_by_driver: false,
+        }
+    }
+
+    /// Assign the device ID of the PDO (ex. `MyBus\MyDevice`)
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if WDF fails to assign the device ID. The error contains the [`NTSTATUS`](wdk_sys::NTSTATUS) of the failure. Full error documentation is available in the [WdfPdoInitAssignDeviceID Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfpdo/nf-wdfpdo-wdfpdoinitassigndeviceid#return-value)
+    pub fn assign_device_id(&mut self, device_id: &UNICODE_STRING) -> Result<(), Error> {
+        let nt_status;
+        // SAFETY: `device_init` is a private member of `PdoInit`, which is always valid
+        // until the PDO is created. WDF copies `device_id`.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfPdoInitAssignDeviceID,
+                self.device_init,
+                device_id,
+            );
+        }
+        Error::check("WdfPdoInitAssignDeviceID", nt_status)
+    }
+
+    /// Assign the instance ID of the PDO. The instance ID must be unique among
+    /// the children of the bus device with the same device ID.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if WDF fails to assign the instance ID. The error contains the [`NTSTATUS`](wdk_sys::NTSTATUS) of the failure. Full error documentation is available in the [WdfPdoInitAssignInstanceID Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfpdo/nf-wdfpdo-wdfpdoinitassigninstanceid#return-value)
+    pub fn assign_instance_id(&mut self, instance_id: &UNICODE_STRING) -> Result<(), Error> {
+        let nt_status;
+        // SAFETY: `device_init` is a private member of `PdoInit`, which is always valid
+        // until the PDO is created. WDF copies `instance_id`.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfPdoInitAssignInstanceID,
+                self.device_init,
+                instance_id,
+            );
+        }
+        Error::check("WdfPdoInitAssignInstanceID", nt_status)
+    }
+
+    /// Add a hardware ID to the PDO. Hardware IDs should be added in order of
+    /// decreasing specificity.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if WDF fails to add the hardware ID. The error contains the [`NTSTATUS`](wdk_sys::NTSTATUS) of the failure. Full error documentation is available in the [WdfPdoInitAddHardwareID Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfpdo/nf-wdfpdo-wdfpdoinitaddhardwareid#return-value)
+    pub fn add_hardware_id(&mut self, hardware_id: &UNICODE_STRING) -> Result<(), Error> {
+        let nt_status;
+        // SAFETY: `device_init` is a private member of `PdoInit`, which is always valid
+        // until the PDO is created. WDF copies `hardware_id`.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfPdoInitAddHardwareID,
+                self.device_init,
+                hardware_id,
+            );
+        }
+        Error::check("WdfPdoInitAddHardwareID", nt_status)
+    }
+
+    /// Add a compatible ID to the PDO. Compatible IDs should be added in order
+    /// of decreasing specificity.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if WDF fails to add the compatible ID. The error contains the [`NTSTATUS`](wdk_sys::NTSTATUS) of the failure. Full error documentation is available in the [WdfPdoInitAddCompatibleID Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfpdo/nf-wdfpdo-wdfpdoinitaddcompatibleid#return-value)
+    pub fn add_compatible_id(&mut self, compatible_id: &UNICODE_STRING) -> Result<(), Error> {
+        let nt_status;
+        // SAFETY: `device_init` is a private member of `PdoInit`, which is always valid
+        // until the PDO is created. WDF copies `compatible_id`.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfPdoInitAddCompatibleID,
+                self.device_init,
+                compatible_id,
+            );
+        }
+        Error::check("WdfPdoInitAddCompatibleID", nt_status)
+    }
+
+    /// Add a device description and location for the locale `locale_id` (ex.
+    /// `0x409` for en-US), which are displayed in Device Manager
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if WDF fails to add the device text. The error contains the [`NTSTATUS`](wdk_sys::NTSTATUS) of the failure. Full error documentation is available in the [WdfPdoInitAddDeviceText Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfpdo/nf-wdfpdo-wdfpdoinitadddevicetext#return-value)
+    pub fn add_device_text(
+        &mut self,
+        device_description: &UNICODE_STRING,
+        device_location: &UNICODE_STRING,
+        locale_id: LCID,
+    ) -> Result<(), Error> {
+        let nt_status;
+        // SAFETY: `device_init` is a private member of `PdoInit`, which is always valid
+        // until the PDO is created. WDF copies `device_description` and
+        // `device_location`.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfPdoInitAddDeviceText,
+                self.device_init,
+                device_description,
+                device_location,
+                locale_id,
+            );
+        }
+        Error::check("WdfPdoInitAddDeviceText", nt_status)
+    }
+
+    /// Set the locale whose device text is used when the system locale has no
+    /// device text added via [`PdoInit::add_device_text`]
+    pub fn set_default_locale(&mut self, locale_id: LCID) {
+        // SAFETY: `device_init` is a private member of `PdoInit`, which is always valid
+        // until the PDO is created
+        unsafe {
+            call_unsafe_wdf_function_binding!(
+                WdfPdoInitSetDefaultLocale,
+                self.device_init,
+                locale_id,
+            );
+        }
+    }
+
+    /// Allow the PDO to be used in raw mode (ie. without a function driver),
+    /// with `device_class_guid` as its device setup class
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if WDF fails to assign raw mode. The error contains the [`NTSTATUS`](wdk_sys::NTSTATUS) of the failure. Full error documentation is available in the [WdfPdoInitAssignRawDevice Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfpdo/nf-wdfpdo-wdfpdoinitassignrawdevice#return-value)
+    pub fn assign_raw_device(&mut self, device_class_guid: &GUID) -> Result<(), Error> {
+        let nt_status;
+        // SAFETY: `device_init` is a private member of `PdoInit`, which is always valid
+        // until the PDO is created. WDF copies `device_class_guid`.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfPdoInitAssignRawDevice,
+                self.device_init,
+                device_class_guid,
+            );
+        }
+        Error::check("WdfPdoInitAssignRawDevice", nt_status)
+    }
+
+    /// Create the PDO. Any other `WdfDeviceInit*` or `WdfPdoInit*`
+    /// configuration must be applied to [`PdoInit::as_raw`] before calling
+    /// this.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if WDF fails to create the PDO. The error contains the [`NTSTATUS`](wdk_sys::NTSTATUS) of the failure. Full error documentation is available in the [WdfDeviceCreate Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfdevice/nf-wdfdevice-wdfdevicecreate#return-value)
+    pub fn create_device(
+        mut self,
+        attributes: &mut WDF_OBJECT_ATTRIBUTES,
+    ) -> Result<WDFDEVICE, Error> {
+        let mut device: WDFDEVICE = core::ptr::null_mut();
+
+        let nt_status;
+        // SAFETY: `device_init` is a private member of `PdoInit`, which is always valid
+        // until the PDO is created
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfDeviceCreate,
+                &mut self.device_init,
+                attributes,
+                &mut device,
+            );
+        }
+
+        // On failure, `self` is dropped when returning, freeing `device_init` if it was
+        // allocated by the driver
+        Error::check("WdfDeviceCreate", nt_status)?;
+
+        // WDF takes ownership of `device_init` once the PDO is created
+        self.is_allocated_by_driver = false;
+        Ok(device)
+    }
+
+    /// Create the PDO and statically enumerate it as a child of
+    /// `parent_device`. This should be used with a [`PdoInit`] from
+    /// [`PdoInit::allocate`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if WDF fails to create the PDO, or fails to add it as a child of `parent_device`. The error contains the [`NTSTATUS`](wdk_sys::NTSTATUS) of the failure. Full error documentation is available in the [WdfFdoAddStaticChild Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdffdo/nf-wdffdo-wdffdoaddstaticchild#return-value)
+    pub fn create_static_child(
+        self,
+        parent_device: WDFDEVICE,
+        attributes: &mut WDF_OBJECT_ATTRIBUTES,
+    ) -> Result<WDFDEVICE, Error> {
+        let device = self.create_device(attributes)?;
+
+        let nt_status;
+        // SAFETY: `device` is the PDO that was just created, and `parent_device` is a
+        // handle to a WDF device object
+        unsafe {
+            nt_status =
+                call_unsafe_wdf_function_binding!(WdfFdoAddStaticChild, parent_device, device);
+        }
+
+        if let Err(error) = Error::check("WdfFdoAddStaticChild", nt_status) {
+            // SAFETY: `device` was created above, and has not been reported to the PnP
+            // manager, so it must be deleted by the driver
+            unsafe {
+                call_unsafe_wdf_function_binding!(WdfObjectDelete, device.cast());
+            }
+            return Err(error);
+        }
+
+        Ok(device)
+    }
+
+    /// Returns the underlying `PWDFDEVICE_INIT`, so that configuration without
+    /// a safe wrapper (ex. `WdfDeviceInitSetPnpPowerEventCallbacks`) can be
+    /// applied to the PDO before it is created.
+    #[must_use]
+    pub const fn as_raw(&self) -> PWDFDEVICE_INIT {
+        self.device_init
+    }
+}
+
+impl Drop for PdoInit {
+    fn drop(&mut self) {
+        if self.is_allocated_by_driver && !self.device_init.is_null() {
+            // SAFETY: `device_init` was allocated by `WdfPdoInitAllocate`, and was never
+            // used to successfully create a PDO
+            unsafe {
+                call_unsafe_wdf_function_binding!(WdfDeviceInitFree, self.device_init);
+            }
+        }
+    }
+}
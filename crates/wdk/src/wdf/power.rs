@@ -0,0 +1,185 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+use core::mem::size_of;
+
+use wdk_sys::{
+    call_unsafe_wdf_function_binding,
+    NTSTATUS,
+    PWDFDEVICE_INIT,
+    STATUS_SUCCESS,
+    ULONG,
+    WDFCMRESLIST,
+    WDFDEVICE,
+    WDF_PNPPOWER_EVENT_CALLBACKS,
+    WDF_POWER_DEVICE_STATE,
+};
+
+/// Power management callbacks of a WDF device.
+///
+/// A driver implements [`PowerPolicy`] for a type of its choosing and
+/// registers it on the `WDFDEVICE_INIT` of a device with
+/// [`register_power_policy`], before the device is created. WDF then calls the
+/// methods of the implementation as the device moves through its PnP and power
+/// states. Every method has a default implementation that succeeds without
+/// doing anything, so a driver only needs to implement the callbacks it cares
+/// about.
+///
+/// Returning an error from any of the callbacks causes WDF to treat the
+/// transition as failed. Full documentation is available in the [WdfDeviceInitSetPnpPowerEventCallbacks Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfdevice/nf-wdfdevice-wdfdeviceinitsetpnppowereventcallbacks)
+pub trait PowerPolicy {
+    /// Called when `device` enters the working (D0) state, after being in
+    /// `previous_state`.
+    ///
+    /// This is the `EvtDeviceD0Entry` callback.
+    ///
+    /// # Errors
+    ///
+    /// An error returned by this callback fails the power transition. Full documentation is available in the [EvtDeviceD0Entry Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfdevice/nc-wdfdevice-evt_wdf_device_d0_entry#return-value)
+    fn d0_entry(
+        _device: WDFDEVICE,
+        _previous_state: WDF_POWER_DEVICE_STATE,
+    ) -> Result<(), NTSTATUS> {
+        Ok(())
+    }
+
+    /// Called when `device` leaves the working (D0) state, to enter
+    /// `target_state`.
+    ///
+    /// This is the `EvtDeviceD0Exit` callback.
+    ///
+    /// # Errors
+    ///
+    /// An error returned by this callback causes WDF to report the device as failed. Full documentation is available in the [EvtDeviceD0Exit Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfdevice/nc-wdfdevice-evt_wdf_device_d0_exit#return-value)
+    fn d0_exit(_device: WDFDEVICE, _target_state: WDF_POWER_DEVICE_STATE) -> Result<(), NTSTATUS> {
+        Ok(())
+    }
+
+    /// Called when the hardware resources of `device` are assigned, to make
+    /// the device accessible to the driver.
+    ///
+    /// The resources can be iterated by wrapping the resource lists in a
+    /// [`ResourceList`](crate::resources::ResourceList).
+    ///
+    /// This is the `EvtDevicePrepareHardware` callback.
+    ///
+    /// # Errors
+    ///
+    /// An error returned by this callback causes WDF to stop the device. Full documentation is available in the [EvtDevicePrepareHardware Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfdevice/nc-wdfdevice-evt_wdf_device_prepare_hardware#return-value)
+    fn prepare_hardware(
+        _device: WDFDEVICE,
+        _resources_raw: WDFCMRESLIST,
+        _resources_translated: WDFCMRESLIST,
+    ) -> Result<(), NTSTATUS> {
+        Ok(())
+    }
+
+    /// Called when the hardware resources of `device` are removed, so that
+    /// the device is no longer accessible to the driver.
+    ///
+    /// This is the `EvtDeviceReleaseHardware` callback.
+    ///
+    /// # Errors
+    ///
+    /// An error returned by this callback causes WDF to report the device as failed. Full documentation is available in the [EvtDeviceReleaseHardware Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfdevice/nc-wdfdevice-evt_wdf_device_release_hardware#return-value)
+    fn release_hardware(
+        _device: WDFDEVICE,
+        _resources_translated: WDFCMRESLIST,
+    ) -> Result<(), NTSTATUS> {
+        Ok(())
+    }
+}
+
+/// Creates the [`WDF_PNPPOWER_EVENT_CALLBACKS`] dispatching to the callbacks
+/// of `P`.
+///
+/// This is the equivalent of `WDF_PNPPOWER_EVENT_CALLBACKS_INIT`, with the
+/// `EvtDeviceD0Entry`, `EvtDeviceD0Exit`, `EvtDevicePrepareHardware` and
+/// `EvtDeviceReleaseHardware` callbacks set. The remaining callbacks can be set
+/// on the returned structure before it is registered.
+#[must_use]
+pub fn pnp_power_event_callbacks<P: PowerPolicy>() -> WDF_PNPPOWER_EVENT_CALLBACKS {
+    // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+    #[allow(clippy::cast_possible_truncation)]
+    const WDF_PNPPOWER_EVENT_CALLBACKS_SIZE: ULONG =
+        size_of::<WDF_PNPPOWER_EVENT_CALLBACKS>() as ULONG;
+
+    WDF_PNPPOWER_EVENT_CALLBACKS {
+        Size: WDF_PNPPOWER_EVENT_CALLBACKS_SIZE,
+        EvtDeviceD0Entry: Some(evt_device_d0_entry::<P>),
+        EvtDeviceD0Exit: Some(evt_device_d0_exit::<P>),
+        EvtDevicePrepareHardware: Some(evt_device_prepare_hardware::<P>),
+        EvtDeviceReleaseHardware: Some(evt_device_release_hardware::<P>),
+        ..WDF_PNPPOWER_EVENT_CALLBACKS::default()
+    }
+}
+
+/// Registers the callbacks of `P` as the PnP and power event callbacks of the
+/// device that will be created from `device_init`.
+///
+/// # Safety
+///
+/// `device_init` must be a valid `PWDFDEVICE_INIT` that has not yet been used
+/// to create a device (ex. the `DeviceInit` argument of `EvtDriverDeviceAdd`).
+pub unsafe fn register_power_policy<P: PowerPolicy>(device_init: PWDFDEVICE_INIT) {
+    let mut pnp_power_event_callbacks = pnp_power_event_callbacks::<P>();
+
+    // SAFETY: The caller guarantees that `device_init` is valid and has not been
+    // used to create a device yet. WDF copies the callbacks, so
+    // `pnp_power_event_callbacks` is only read for the duration of the call.
+    unsafe {
+        call_unsafe_wdf_function_binding!(
+            WdfDeviceInitSetPnpPowerEventCallbacks,
+            device_init,
+            &mut pnp_power_event_callbacks,
+        );
+    }
+}
+
+/// Converts the result of a [`PowerPolicy`] callback into the [`NTSTATUS`]
+/// returned to WDF
+fn to_nt_status(result: Result<(), NTSTATUS>) -> NTSTATUS {
+    match result {
+        Ok(()) => STATUS_SUCCESS,
+        Err(nt_status) => nt_status,
+    }
+}
+
+/// `EvtDeviceD0Entry` callback that dispatches to [`PowerPolicy::d0_entry`]
+unsafe extern "C" fn evt_device_d0_entry<P: PowerPolicy>(
+    device: WDFDEVICE,
+    previous_state: WDF_POWER_DEVICE_STATE,
+) -> NTSTATUS {
+    to_nt_status(P::d0_entry(device, previous_state))
+}
+
+/// `EvtDeviceD0Exit` callback that dispatches to [`PowerPolicy::d0_exit`]
+unsafe extern "C" fn evt_device_d0_exit<P: PowerPolicy>(
+    device: WDFDEVICE,
+    target_state: WDF_POWER_DEVICE_STATE,
+) -> NTSTATUS {
+    to_nt_status(P::d0_exit(device, target_state))
+}
+
+/// `EvtDevicePrepareHardware` callback that dispatches to
+/// [`PowerPolicy::prepare_hardware`]
+unsafe extern "C" fn evt_device_prepare_hardware<P: PowerPolicy>(
+    device: WDFDEVICE,
+    resources_raw: WDFCMRESLIST,
+    resources_translated: WDFCMRESLIST,
+) -> NTSTATUS {
+    to_nt_status(P::prepare_hardware(
+        device,
+        resources_raw,
+        resources_translated,
+    ))
+}
+
+/// `EvtDeviceReleaseHardware` callback that dispatches to
+/// [`PowerPolicy::release_hardware`]
+unsafe extern "C" fn evt_device_release_hardware<P: PowerPolicy>(
+    device: WDFDEVICE,
+    resources_translated: WDFCMRESLIST,
+) -> NTSTATUS {
+    to_nt_status(P::release_hardware(device, resources_translated))
+}
@@ -0,0 +1,580 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(any(feature = "alloc", driver_model__driver_type = "KMDF"))]
+use core::ptr;
+#[cfg(feature = "alloc")]
+use core::{
+    cell::UnsafeCell,
+    mem::size_of,
+    sync::atomic::{AtomicU8, Ordering},
+};
+#[cfg(driver_model__driver_type = "KMDF")]
+use core::{ffi::c_void, slice};
+
+use wdk_sys::{call_unsafe_wdf_function_binding, NTSTATUS, ULONG_PTR, WDFREQUEST};
+#[cfg(feature = "alloc")]
+use wdk_sys::{
+    _WDF_EXECUTION_LEVEL::WdfExecutionLevelInheritFromParent,
+    _WDF_SYNCHRONIZATION_SCOPE::WdfSynchronizationScopeInheritFromParent,
+    PVOID,
+    STATUS_CANCELLED,
+    ULONG,
+    WDFOBJECT,
+    WDF_OBJECT_ATTRIBUTES,
+    WDF_OBJECT_CONTEXT_TYPE_INFO,
+};
+#[cfg(driver_model__driver_type = "KMDF")]
+use wdk_sys::{PMDL, STATUS_INSUFFICIENT_RESOURCES};
+
+#[cfg(any(feature = "alloc", driver_model__driver_type = "KMDF"))]
+use super::Error;
+
+/// Cancel callback of a [`CancelableRequest`]
+#[cfg(feature = "alloc")]
+type CancelCallback = Box<dyn FnOnce(Request) + Send>;
+
+/// The request is cancelable, and neither its cancel callback nor
+/// [`CancelableRequest::unmark_cancelable`] has claimed it
+#[cfg(feature = "alloc")]
+const CANCEL_STATE_MARKED: u8 = 0;
+/// [`CancelableRequest::unmark_cancelable`] is calling
+/// `WdfRequestUnmarkCancelable`
+#[cfg(feature = "alloc")]
+const CANCEL_STATE_UNMARKING: u8 = 1;
+/// The cancel callback owns the request
+#[cfg(feature = "alloc")]
+const CANCEL_STATE_CANCELED: u8 = 2;
+/// The request was canceled while [`CancelableRequest::unmark_cancelable`] was
+/// unmarking it, so the cancel callback is called by
+/// [`CancelableRequest::unmark_cancelable`] once `WdfRequestUnmarkCancelable`
+/// returns
+#[cfg(feature = "alloc")]
+const CANCEL_STATE_DEFERRED: u8 = 3;
+
+/// Context of a request made cancelable by [`Request::mark_cancelable`]
+#[cfg(feature = "alloc")]
+struct CancelContext {
+    /// Which of the cancel callback or
+    /// [`CancelableRequest::unmark_cancelable`] owns the request, as one of
+    /// the `CANCEL_STATE_*` constants
+    state: AtomicU8,
+    cancel_callback: UnsafeCell<Option<CancelCallback>>,
+}
+
+#[cfg(feature = "alloc")]
+impl CancelContext {
+    /// Takes the cancel callback out of the context
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may access the cancel callback concurrently
+    unsafe fn take_cancel_callback(&self) -> Option<CancelCallback> {
+        // SAFETY: The caller guarantees that nothing else accesses the cancel callback
+        // concurrently
+        unsafe { (*self.cancel_callback.get()).take() }
+    }
+}
+
+/// [`WDF_OBJECT_CONTEXT_TYPE_INFO`] of the [`CancelContext`] of a
+/// [`CancelableRequest`]
+#[cfg(feature = "alloc")]
+struct CancelContextTypeInfo(WDF_OBJECT_CONTEXT_TYPE_INFO);
+
+#[cfg(feature = "alloc")]
+// SAFETY: The context type info is immutable, and only points to itself and to
+// a static string
+unsafe impl Sync for CancelContextTypeInfo {}
+
+#[cfg(feature = "alloc")]
+// clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+#[allow(clippy::cast_possible_truncation)]
+static CANCEL_CONTEXT_TYPE_INFO: CancelContextTypeInfo =
+    CancelContextTypeInfo(WDF_OBJECT_CONTEXT_TYPE_INFO {
+        Size: size_of::<WDF_OBJECT_CONTEXT_TYPE_INFO>() as ULONG,
+        ContextName: c"wdk::wdf::CancelContext".as_ptr().cast_mut(),
+        ContextSize: size_of::<CancelContext>(),
+        UniqueType: ptr::addr_of!(CANCEL_CONTEXT_TYPE_INFO.0),
+        EvtDriverGetUniqueContextType: None,
+    });
+
+/// A WDF request owned by the driver, which must be completed.
+///
+/// While a driver holds a request for a long time (ex. until a hardware event
+/// occurs), it should make the request cancelable with
+/// [`Request::mark_cancelable`], and reclaim it with
+/// [`CancelableRequest::unmark_cancelable`] before completing it. Exactly one
+/// of the cancel callback or a successful
+/// [`CancelableRequest::unmark_cancelable`] receives ownership of the request,
+/// so the request is never completed twice, and
+/// [`CancelableRequest::unmark_cancelable`] never unmarks a request that its
+/// cancel callback has completed.
+///
+/// Dropping a [`Request`] without completing it leaves the request pending
+/// forever, which prevents the I/O operation from finishing.
+#[must_use = "requests must be completed"]
+#[derive(Debug)]
+pub struct Request {
+    wdf_request: WDFREQUEST,
+}
+
+// SAFETY: WDF request handles can be used from any thread, and `Request`
+// represents exclusive ownership of the request
+unsafe impl Send for Request {}
+
+impl Request {
+    /// Creates a [`Request`] from a raw WDF request handle
+    ///
+    /// # Safety
+    ///
+    /// `wdf_request` must be a valid WDF request that is owned by the driver
+    /// (ex. delivered to an I/O queue callback), and that is not used by
+    /// anything else after this call, since the returned [`Request`] takes over
+    /// the responsibility of completing it.
+    pub const unsafe fn from_raw(wdf_request: WDFREQUEST) -> Self {
+        Self { wdf_request }
+    }
+
+    /// Returns the raw WDF request handle
+    #[must_use]
+    pub const fn as_raw(&self) -> WDFREQUEST {
+        self.wdf_request
+    }
+
+    /// Completes the request with `status`
+    pub fn complete(self, status: NTSTATUS) {
+        // SAFETY: `wdf_request` is owned by this `Request`, which is consumed, so the
+        // request is completed exactly once
+        unsafe {
+            call_unsafe_wdf_function_binding!(WdfRequestComplete, self.wdf_request, status);
+        }
+    }
+
+    /// Completes the request with `status`, and `information` (ex. the number
+    /// of bytes transferred)
+    pub fn complete_with_information(self, status: NTSTATUS, information: ULONG_PTR) {
+        // SAFETY: `wdf_request` is owned by this `Request`, which is consumed, so the
+        // request is completed exactly once
+        unsafe {
+            call_unsafe_wdf_function_binding!(
+                WdfRequestCompleteWithInformation,
+                self.wdf_request,
+                status,
+                information,
+            );
+        }
+    }
+
+    /// Returns the buffer of a direct I/O request (ex. the buffer of a
+    /// `METHOD_IN_DIRECT` or `METHOD_OUT_DIRECT` IOCTL), mapped into system
+    /// space.
+    ///
+    /// The I/O manager already locked the pages of the buffer, and they stay
+    /// locked, and mapped, until the request is completed.
+    ///
+    /// This must be called at `IRQL` <= `DISPATCH_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the request has no direct I/O buffer, or if the buffer cannot be mapped into system space, in which case the error is an [`Error::InsufficientResources`]. Full error documentation is available in the [WdfRequestRetrieveOutputWdmMdl Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfrequest/nf-wdfrequest-wdfrequestretrieveoutputwdmmdl#return-value)
+    #[cfg(driver_model__driver_type = "KMDF")]
+    pub fn direct_buffer(&mut self) -> Result<&mut [u8], Error> {
+        let mut mdl: PMDL = ptr::null_mut();
+
+        let nt_status;
+        // SAFETY: `wdf_request` is a valid WDF request owned by this `Request`
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfRequestRetrieveOutputWdmMdl,
+                self.wdf_request,
+                &mut mdl,
+            );
+        }
+        Error::check("WdfRequestRetrieveOutputWdmMdl", nt_status)?;
+
+        // SAFETY: The MDL of a direct I/O request is locked by the I/O manager until
+        // the request is completed, and is only accessed through this `Request`
+        let Some(buffer) = (unsafe { crate::mdl::system_address(mdl) }) else {
+            return Err(Error::new(
+                "MmGetSystemAddressForMdlSafe",
+                STATUS_INSUFFICIENT_RESOURCES,
+            ));
+        };
+        // SAFETY: The MDL is valid until the request is completed
+        let length = crate::mdl::byte_count(unsafe { &*mdl });
+
+        // SAFETY: `buffer` maps the `length` bytes described by the MDL, which stay
+        // mapped until the request is completed. The mutable borrow of this `Request`
+        // prevents the request from being completed, or the buffer from being
+        // retrieved again, while the slice is alive.
+        Ok(unsafe { slice::from_raw_parts_mut(buffer.as_ptr().cast(), length) })
+    }
+
+    /// Returns both buffers of a `METHOD_IN_DIRECT` or `METHOD_OUT_DIRECT`
+    /// IOCTL: the input buffer, which the I/O manager copied into system
+    /// memory, and the direct I/O buffer, mapped into system space (see
+    /// [`Request::direct_buffer`]). IOCTLs without an input buffer should use
+    /// [`Request::direct_buffer`] instead.
+    ///
+    /// This must be called at `IRQL` <= `DISPATCH_LEVEL`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the input buffer is shorter than `minimum_input_length` bytes (an [`Error::InvalidParameter`]), or if the direct I/O buffer cannot be retrieved (see [`Request::direct_buffer`]). Full error documentation is available in the [WdfRequestRetrieveInputBuffer Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfrequest/nf-wdfrequest-wdfrequestretrieveinputbuffer#return-value)
+    #[cfg(driver_model__driver_type = "KMDF")]
+    pub fn direct_io_buffers(
+        &mut self,
+        minimum_input_length: usize,
+    ) -> Result<(&[u8], &mut [u8]), Error> {
+        let mut input_buffer: *mut c_void = ptr::null_mut();
+        let mut input_length: usize = 0;
+
+        let nt_status;
+        // SAFETY: `wdf_request` is a valid WDF request owned by this `Request`
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfRequestRetrieveInputBuffer,
+                self.wdf_request,
+                minimum_input_length,
+                &mut input_buffer,
+                &mut input_length,
+            );
+        }
+        Error::check("WdfRequestRetrieveInputBuffer", nt_status)?;
+
+        // SAFETY: The input buffer of a direct I/O IOCTL is the system buffer of the
+        // request, which holds `input_length` bytes until the request is completed.
+        // It does not overlap the direct I/O buffer, which is mapped from the pages
+        // of a separate user-mode buffer.
+        let input_buffer =
+            unsafe { slice::from_raw_parts(input_buffer.cast::<u8>().cast_const(), input_length) };
+        Ok((input_buffer, self.direct_buffer()?))
+    }
+
+    /// Makes the request cancelable, with `cancel` as its cancel callback.
+    ///
+    /// If the request is canceled, `cancel` is called with the request, and
+    /// must complete it (typically with `STATUS_CANCELLED`). Otherwise, the
+    /// driver must reclaim the request with
+    /// [`CancelableRequest::unmark_cancelable`] before completing it. If the
+    /// request is canceled while it is being reclaimed, `cancel` is called by
+    /// [`CancelableRequest::unmark_cancelable`] instead of the framework's
+    /// cancel callback.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the request cannot be made cancelable, along with the request, which the driver must complete. The error is an [`Error::Cancelled`] if the request was already canceled, in which case `cancel` is not called. Full error documentation is available in the [WdfRequestMarkCancelableEx Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfrequest/nf-wdfrequest-wdfrequestmarkcancelableex#return-value)
+    #[cfg(feature = "alloc")]
+    pub fn mark_cancelable<F>(self, cancel: F) -> Result<CancelableRequest, (Self, Error)>
+    where
+        F: FnOnce(Self) + Send + 'static,
+    {
+        // SAFETY: `wdf_request` is owned by this `Request`, so it is not cancelable,
+        // and no cancel callback can access its context
+        if let Err(error) = unsafe { set_cancel_callback(self.wdf_request, Box::new(cancel)) } {
+            return Err((self, error));
+        }
+
+        // The reference keeps the request handle valid for the `CancelableRequest`,
+        // even after the cancel callback completes the request
+        // SAFETY: `wdf_request` is a valid WDF request owned by this `Request`
+        unsafe {
+            call_unsafe_wdf_function_binding!(
+                WdfObjectReferenceActual,
+                self.wdf_request.cast(),
+                ptr::null_mut(),
+                0,
+                ptr::null(),
+            );
+        }
+        let cancelable_request = CancelableRequest {
+            wdf_request: self.wdf_request,
+        };
+
+        let nt_status;
+        // SAFETY: `wdf_request` is owned by this `Request`, and
+        // `evt_request_cancel` takes ownership of the request if it is canceled
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfRequestMarkCancelableEx,
+                self.wdf_request,
+                Some(evt_request_cancel),
+            );
+        }
+
+        if let Err(error) = Error::check("WdfRequestMarkCancelableEx", nt_status) {
+            // The cancel callback is never called when marking the request fails
+            // SAFETY: `set_cancel_callback` initialized the cancel context of the request,
+            // which is owned by this `Request`
+            let cancel_context = unsafe { cancel_context(self.wdf_request) };
+            // SAFETY: The request is not cancelable, so no cancel callback can access its
+            // context
+            drop(unsafe { cancel_context.take_cancel_callback() });
+            return Err((self, error));
+        }
+        Ok(cancelable_request)
+    }
+}
+
+/// A WDF request that was made cancelable by [`Request::mark_cancelable`].
+///
+/// The request must be reclaimed with
+/// [`CancelableRequest::unmark_cancelable`] before it can be completed by the
+/// driver. Dropping a [`CancelableRequest`] leaves the request pending until
+/// it is canceled, at which point its cancel callback completes it.
+#[cfg(feature = "alloc")]
+#[must_use = "cancelable requests must be unmarked before they can be completed"]
+#[derive(Debug)]
+pub struct CancelableRequest {
+    wdf_request: WDFREQUEST,
+}
+
+#[cfg(feature = "alloc")]
+// SAFETY: WDF request handles can be used from any thread, and the cancel
+// callback of the request is `Send`
+unsafe impl Send for CancelableRequest {}
+
+#[cfg(feature = "alloc")]
+impl CancelableRequest {
+    /// Returns the raw WDF request handle
+    #[must_use]
+    pub const fn as_raw(&self) -> WDFREQUEST {
+        self.wdf_request
+    }
+
+    /// Makes the request no longer cancelable, returning ownership of it to
+    /// the driver. The cancel callback of the request is dropped without being
+    /// called.
+    ///
+    /// If the request is canceled while it is being unmarked, its cancel
+    /// callback is called with the request by this function, before the error
+    /// is returned.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the request can no longer be reclaimed. The error is an [`Error::Cancelled`] if the request was canceled, in which case its cancel callback has been, or will be, called with the request, and is responsible for completing it. `WdfRequestUnmarkCancelable` is not called if the cancel callback has already been called. Full error documentation is available in the [WdfRequestUnmarkCancelable Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfrequest/nf-wdfrequest-wdfrequestunmarkcancelable#return-value)
+    pub fn unmark_cancelable(self) -> Result<Request, Error> {
+        // SAFETY: `Request::mark_cancelable` initialized the cancel context of the
+        // request, and the reference held by this `CancelableRequest` keeps it valid
+        let cancel_context = unsafe { cancel_context(self.wdf_request) };
+
+        // Once the cancel callback has claimed the request, it may already have
+        // completed it, so it must not be unmarked anymore
+        if cancel_context
+            .state
+            .compare_exchange(
+                CANCEL_STATE_MARKED,
+                CANCEL_STATE_UNMARKING,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_err()
+        {
+            return Err(Error::Cancelled {
+                api: "WdfRequestUnmarkCancelable",
+                status: STATUS_CANCELLED,
+            });
+        }
+
+        let nt_status;
+        // SAFETY: The request is not completed while the state is
+        // `CANCEL_STATE_UNMARKING`, since a concurrent cancel callback defers to this
+        // function
+        unsafe {
+            nt_status =
+                call_unsafe_wdf_function_binding!(WdfRequestUnmarkCancelable, self.wdf_request);
+        }
+
+        if let Err(error) = Error::check("WdfRequestUnmarkCancelable", nt_status) {
+            // If the cancel callback was called while unmarking the request, it deferred
+            // to this function. Otherwise, it claims the request once it is called.
+            if cancel_context
+                .state
+                .compare_exchange(
+                    CANCEL_STATE_UNMARKING,
+                    CANCEL_STATE_MARKED,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                )
+                .is_err()
+            {
+                // SAFETY: The cancel callback deferred to this function, so nothing else
+                // accesses the cancel callback
+                let cancel_callback = unsafe { cancel_context.take_cancel_callback() };
+                if let Some(cancel_callback) = cancel_callback {
+                    cancel_callback(Request {
+                        wdf_request: self.wdf_request,
+                    });
+                }
+            }
+            return Err(error);
+        }
+
+        // SAFETY: The request is no longer cancelable, so the cancel callback will not
+        // be called, and no longer accesses the request's context
+        drop(unsafe { cancel_context.take_cancel_callback() });
+        Ok(Request {
+            wdf_request: self.wdf_request,
+        })
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Drop for CancelableRequest {
+    fn drop(&mut self) {
+        // SAFETY: This releases the reference taken by `Request::mark_cancelable`
+        unsafe {
+            call_unsafe_wdf_function_binding!(
+                WdfObjectDereferenceActual,
+                self.wdf_request.cast(),
+                ptr::null_mut(),
+                0,
+                ptr::null(),
+            );
+        }
+    }
+}
+
+/// Stores `cancel_callback` in the cancel context of `wdf_request`, allocating
+/// the context if the request does not have one yet
+///
+/// # Safety
+///
+/// `wdf_request` must be a valid WDF request owned by the driver, which is not
+/// cancelable
+#[cfg(feature = "alloc")]
+unsafe fn set_cancel_callback(
+    wdf_request: WDFREQUEST,
+    cancel_callback: CancelCallback,
+) -> Result<(), Error> {
+    // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
+    #[allow(clippy::cast_possible_truncation)]
+    const WDF_OBJECT_ATTRIBUTES_SIZE: ULONG = {
+        // Manually assert there is not truncation since clippy doesn't work for
+        // compile-time constants
+        assert!(size_of::<WDF_OBJECT_ATTRIBUTES>() <= ULONG::MAX as usize);
+
+        size_of::<WDF_OBJECT_ATTRIBUTES>() as ULONG
+    };
+
+    let mut context_attributes = WDF_OBJECT_ATTRIBUTES {
+        Size: WDF_OBJECT_ATTRIBUTES_SIZE,
+        EvtCleanupCallback: None,
+        EvtDestroyCallback: Some(evt_cancel_context_destroy),
+        ExecutionLevel: WdfExecutionLevelInheritFromParent,
+        SynchronizationScope: WdfSynchronizationScopeInheritFromParent,
+        ParentObject: ptr::null_mut(),
+        ContextSizeOverride: 0,
+        ContextTypeInfo: ptr::addr_of!(CANCEL_CONTEXT_TYPE_INFO.0),
+    };
+    let mut context: PVOID = ptr::null_mut();
+
+    let nt_status;
+    // SAFETY: The caller guarantees that `wdf_request` is a valid WDF request. If
+    // the request already has a cancel context, it is returned with
+    // `STATUS_OBJECT_NAME_EXISTS`, which is a success status.
+    unsafe {
+        nt_status = call_unsafe_wdf_function_binding!(
+            WdfObjectAllocateContext,
+            wdf_request.cast(),
+            ptr::addr_of_mut!(context_attributes),
+            ptr::addr_of_mut!(context),
+        );
+    }
+    Error::check("WdfObjectAllocateContext", nt_status)?;
+
+    // A newly allocated context is zero-initialized, and an existing context never
+    // holds a callback while the request is not cancelable, so there is no
+    // previous callback to drop
+    // SAFETY: `context` was allocated by WDF with the size and alignment of
+    // `CancelContext`, and the caller guarantees that nothing else accesses it
+    unsafe {
+        ptr::write(
+            context.cast::<CancelContext>(),
+            CancelContext {
+                state: AtomicU8::new(CANCEL_STATE_MARKED),
+                cancel_callback: UnsafeCell::new(Some(cancel_callback)),
+            },
+        );
+    }
+    Ok(())
+}
+
+/// Returns the cancel context of `wdf_request`
+///
+/// # Safety
+///
+/// `wdf_request` must be a valid WDF request whose cancel context was
+/// initialized by `set_cancel_callback`, and must stay valid for `'a`
+#[cfg(feature = "alloc")]
+unsafe fn cancel_context<'a>(wdf_request: WDFREQUEST) -> &'a CancelContext {
+    let context: PVOID;
+    // SAFETY: The caller guarantees that `wdf_request` is a valid WDF request
+    unsafe {
+        context = call_unsafe_wdf_function_binding!(
+            WdfObjectGetTypedContextWorker,
+            wdf_request.cast(),
+            ptr::addr_of!(CANCEL_CONTEXT_TYPE_INFO.0),
+        );
+    }
+
+    // SAFETY: The caller guarantees that the context was initialized by
+    // `set_cancel_callback`, and that the request, which owns the context, stays
+    // valid for `'a`
+    unsafe { &*context.cast::<CancelContext>() }
+}
+
+/// `EvtRequestCancel` callback of requests made cancelable by
+/// [`Request::mark_cancelable`]
+#[cfg(feature = "alloc")]
+unsafe extern "C" fn evt_request_cancel(wdf_request: WDFREQUEST) {
+    // SAFETY: WDF only calls this callback for requests marked cancelable by
+    // `Request::mark_cancelable`, which are valid until they are completed
+    let cancel_context = unsafe { cancel_context(wdf_request) };
+
+    // The cancel callback is called by `CancelableRequest::unmark_cancelable`
+    // instead if it is concurrently unmarking the request, since the request must
+    // not be completed before `WdfRequestUnmarkCancelable` returns
+    let mut state = cancel_context.state.load(Ordering::Acquire);
+    loop {
+        let new_state = if state == CANCEL_STATE_UNMARKING {
+            CANCEL_STATE_DEFERRED
+        } else {
+            CANCEL_STATE_CANCELED
+        };
+        match cancel_context.state.compare_exchange_weak(
+            state,
+            new_state,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) if new_state == CANCEL_STATE_DEFERRED => return,
+            Ok(_) => break,
+            Err(current_state) => state = current_state,
+        }
+    }
+
+    // SAFETY: The request was claimed by this callback, so
+    // `CancelableRequest::unmark_cancelable` no longer accesses the cancel callback
+    let cancel_callback = unsafe { cancel_context.take_cancel_callback() };
+    if let Some(cancel_callback) = cancel_callback {
+        cancel_callback(Request { wdf_request });
+    }
+}
+
+/// `EvtDestroyCallback` of the cancel context, which drops the cancel
+/// callback if the request is destroyed while still holding one
+#[cfg(feature = "alloc")]
+unsafe extern "C" fn evt_cancel_context_destroy(object: WDFOBJECT) {
+    // SAFETY: WDF only calls this callback for requests whose cancel context was
+    // initialized by `set_cancel_callback`, while the request is still valid
+    let cancel_context = unsafe { cancel_context(object.cast()) };
+    // SAFETY: Nothing else can access the context of a request that is being
+    // destroyed
+    drop(unsafe { cancel_context.take_cancel_callback() });
+}
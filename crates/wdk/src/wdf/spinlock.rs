@@ -1,9 +1,9 @@
 // Copyright (c) Microsoft Corporation
 // License: MIT OR Apache-2.0
 
-use wdk_sys::{call_unsafe_wdf_function_binding, NTSTATUS, WDFSPINLOCK, WDF_OBJECT_ATTRIBUTES};
+use wdk_sys::{call_unsafe_wdf_function_binding, WDFSPINLOCK, WDF_OBJECT_ATTRIBUTES};
 
-use crate::nt_success;
+use super::Error;
 
 /// WDF Spin Lock.
 ///
@@ -26,8 +26,8 @@ impl SpinLock {
     ///
     /// # Errors
     ///
-    /// This function will return an error if WDF fails to contruct a timer. The error variant will contain a [`NTSTATUS`] of the failure. Full error documentation is available in the [WDFSpinLock Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfsync/nf-wdfsync-wdfspinlockcreate#return-value)
-    pub fn try_new(attributes: &mut WDF_OBJECT_ATTRIBUTES) -> Result<Self, NTSTATUS> {
+    /// This function will return an error if WDF fails to contruct a timer. The error contains the [`NTSTATUS`](wdk_sys::NTSTATUS) of the failure. Full error documentation is available in the [WDFSpinLock Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfsync/nf-wdfsync-wdfspinlockcreate#return-value)
+    pub fn try_new(attributes: &mut WDF_OBJECT_ATTRIBUTES) -> Result<Self, Error> {
         let mut spin_lock = Self {
             wdf_spin_lock: core::ptr::null_mut(),
         };
@@ -43,7 +43,7 @@ pub fn try_new(attributes: &mut WDF_OBJECT_ATTRIBUTES) -> Result<Self, NTSTATUS>
                 &mut spin_lock.wdf_spin_lock,
             );
         }
-        nt_success(nt_status).then_some(spin_lock).ok_or(nt_status)
+        Error::check("WdfSpinLockCreate", nt_status).map(|()| spin_lock)
     }
 
     /// Try to construct a WDF Spin Lock object. This is an alias for
@@ -51,8 +51,8 @@ pub fn try_new(attributes: &mut WDF_OBJECT_ATTRIBUTES) -> Result<Self, NTSTATUS>
     ///
     /// # Errors
     ///
-    /// This function will return an error if WDF fails to contruct a timer. The error variant will contain a [`NTSTATUS`] of the failure. Full error documentation is available in the [WDFSpinLock Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfsync/nf-wdfsync-wdfspinlockcreate#return-value)
-    pub fn create(attributes: &mut WDF_OBJECT_ATTRIBUTES) -> Result<Self, NTSTATUS> {
+    /// This function will return an error if WDF fails to contruct a timer. The error contains the [`NTSTATUS`](wdk_sys::NTSTATUS) of the failure. Full error documentation is available in the [WDFSpinLock Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfsync/nf-wdfsync-wdfspinlockcreate#return-value)
+    pub fn create(attributes: &mut WDF_OBJECT_ATTRIBUTES) -> Result<Self, Error> {
         Self::try_new(attributes)
     }
 
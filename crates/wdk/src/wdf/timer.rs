@@ -3,13 +3,12 @@
 
 use wdk_sys::{
     call_unsafe_wdf_function_binding,
-    NTSTATUS,
     WDFTIMER,
     WDF_OBJECT_ATTRIBUTES,
     WDF_TIMER_CONFIG,
 };
 
-use crate::nt_success;
+use super::Error;
 
 /// WDF Timer.
 pub struct Timer {
@@ -20,11 +19,11 @@ impl Timer {
     ///
     /// # Errors
     ///
-    /// This function will return an error if WDF fails to contruct a timer. The error variant will contain a [`NTSTATUS`] of the failure. Full error documentation is available in the [WDFTimer Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdftimer/nf-wdftimer-wdftimercreate#return-value)
+    /// This function will return an error if WDF fails to contruct a timer. The error contains the [`NTSTATUS`](wdk_sys::NTSTATUS) of the failure. Full error documentation is available in the [WDFTimer Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdftimer/nf-wdftimer-wdftimercreate#return-value)
     pub fn try_new(
         timer_config: &mut WDF_TIMER_CONFIG,
         attributes: &mut WDF_OBJECT_ATTRIBUTES,
-    ) -> Result<Self, NTSTATUS> {
+    ) -> Result<Self, Error> {
         let mut timer = Self {
             wdf_timer: core::ptr::null_mut(),
         };
@@ -41,18 +40,18 @@ pub fn try_new(
                 &mut timer.wdf_timer,
             );
         }
-        nt_success(nt_status).then_some(timer).ok_or(nt_status)
+        Error::check("WdfTimerCreate", nt_status).map(|()| timer)
     }
 
     /// Try to construct a WDF Timer object
     ///
     /// # Errors
     ///
-    /// This function will return an error if WDF fails to contruct a timer. The error variant will contain a [`NTSTATUS`] of the failure. Full error documentation is available in the [WDFTimer Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdftimer/nf-wdftimer-wdftimercreate#return-value)
+    /// This function will return an error if WDF fails to contruct a timer. The error contains the [`NTSTATUS`](wdk_sys::NTSTATUS) of the failure. Full error documentation is available in the [WDFTimer Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdftimer/nf-wdftimer-wdftimercreate#return-value)
     pub fn create(
         timer_config: &mut WDF_TIMER_CONFIG,
         attributes: &mut WDF_OBJECT_ATTRIBUTES,
-    ) -> Result<Self, NTSTATUS> {
+    ) -> Result<Self, Error> {
         Self::try_new(timer_config, attributes)
     }
 
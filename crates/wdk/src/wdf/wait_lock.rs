@@ -0,0 +1,131 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+use core::{marker::PhantomData, ptr};
+
+use wdk_sys::{
+    call_unsafe_wdf_function_binding,
+    LONGLONG,
+    STATUS_TIMEOUT,
+    WDFWAITLOCK,
+    WDF_OBJECT_ATTRIBUTES,
+};
+
+use super::Error;
+
+/// WDF Wait Lock.
+///
+/// Use framework wait locks to synchronize access to driver data from code that
+/// runs at `IRQL` = `PASSIVE_LEVEL`. Unlike a [`SpinLock`](super::SpinLock), a
+/// wait lock does not raise the `IRQL`, so the thread holding it can be
+/// preempted and can call functions that must run at `PASSIVE_LEVEL`, and
+/// threads waiting for it are put to sleep instead of spinning. Normal kernel
+/// APCs are disabled while the lock is held. Before a driver can use a
+/// framework wait lock it must call [`WaitLock::try_new()`] to create a
+/// [`WaitLock`]. The driver can then call [`WaitLock::acquire`] to acquire the
+/// lock, which is released when the returned [`WaitLockGuard`] is dropped.
+pub struct WaitLock {
+    wdf_wait_lock: WDFWAITLOCK,
+}
+
+// SAFETY: WDF wait lock handles can be used from any thread
+unsafe impl Send for WaitLock {}
+
+// SAFETY: WDF synchronizes concurrent calls to acquire and release the wait
+// lock
+unsafe impl Sync for WaitLock {}
+
+impl WaitLock {
+    /// Try to construct a WDF Wait Lock object
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if WDF fails to contruct a wait lock. The error contains the [`NTSTATUS`](wdk_sys::NTSTATUS) of the failure. Full error documentation is available in the [WDFWaitLock Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdfsync/nf-wdfsync-wdfwaitlockcreate#return-value)
+    pub fn try_new(attributes: &mut WDF_OBJECT_ATTRIBUTES) -> Result<Self, Error> {
+        let mut wait_lock = Self {
+            wdf_wait_lock: ptr::null_mut(),
+        };
+
+        let nt_status;
+        // SAFETY: The resulting ffi object is stored in a private member and not
+        // accessible outside of this module, and this module guarantees that it is
+        // always in a valid state.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfWaitLockCreate,
+                attributes,
+                &mut wait_lock.wdf_wait_lock,
+            );
+        }
+        Error::check("WdfWaitLockCreate", nt_status).map(|()| wait_lock)
+    }
+
+    /// Acquire the wait lock, blocking until it is available. The lock is
+    /// released when the returned [`WaitLockGuard`] is dropped.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`.
+    pub fn acquire(&self) -> WaitLockGuard<'_> {
+        // SAFETY: `wdf_wait_lock` is a private member of `WaitLock`, originally created
+        // by WDF, and this module guarantees that it is always in a valid state. A null
+        // timeout waits indefinitely, so the lock is always acquired.
+        unsafe {
+            call_unsafe_wdf_function_binding!(
+                WdfWaitLockAcquire,
+                self.wdf_wait_lock,
+                ptr::null_mut(),
+            );
+        }
+        WaitLockGuard {
+            wait_lock: self,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Acquire the wait lock if it is available, without blocking. Returns
+    /// `None` if another thread holds the lock.
+    ///
+    /// Unlike [`WaitLock::acquire`], this can be called at `IRQL` <=
+    /// `DISPATCH_LEVEL`.
+    pub fn try_acquire(&self) -> Option<WaitLockGuard<'_>> {
+        let mut timeout: LONGLONG = 0;
+
+        let nt_status;
+        // SAFETY: `wdf_wait_lock` is a private member of `WaitLock`, originally created
+        // by WDF, and this module guarantees that it is always in a valid state.
+        unsafe {
+            nt_status = call_unsafe_wdf_function_binding!(
+                WdfWaitLockAcquire,
+                self.wdf_wait_lock,
+                &mut timeout,
+            );
+        }
+        if nt_status == STATUS_TIMEOUT {
+            return None;
+        }
+        Some(WaitLockGuard {
+            wait_lock: self,
+            _not_send: PhantomData,
+        })
+    }
+}
+
+/// RAII guard of a [`WaitLock`] acquired with [`WaitLock::acquire`] or
+/// [`WaitLock::try_acquire`], which releases the lock when dropped
+#[must_use = "the wait lock is released as soon as the guard is dropped"]
+pub struct WaitLockGuard<'a> {
+    wait_lock: &'a WaitLock,
+    // Wait locks must be released by the thread that acquired them
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for WaitLockGuard<'_> {
+    fn drop(&mut self) {
+        // SAFETY: `wdf_wait_lock` is a private member of `WaitLock`, originally created
+        // by WDF, and this module guarantees that it is always in a valid state. The
+        // lock is held by the current thread, since guards cannot be sent to other
+        // threads.
+        unsafe {
+            call_unsafe_wdf_function_binding!(WdfWaitLockRelease, self.wait_lock.wdf_wait_lock);
+        }
+    }
+}
@@ -0,0 +1,761 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Windows Filtering Platform (WFP) callout drivers
+//!
+//! A callout driver implements [`CalloutHandler`] for each of its callouts,
+//! registers them with the filter engine with [`Callout::register`], and then
+//! adds the management objects that route traffic to them (the callouts
+//! themselves, a sublayer and filters) through a [`FilterEngine`]:
+//!
+//! ```rust, ignore
+//! use wdk::wfp::{Action, CalloutHandler, ClassifyOut, ClassifyParameters};
+//!
+//! struct BlockAll;
+//!
+//! impl CalloutHandler for BlockAll {
+//!     fn classify(_parameters: &ClassifyParameters<'_>, classify_out: &mut ClassifyOut<'_>) {
+//!         classify_out.set_action(Action::Block);
+//!     }
+//! }
+//!
+//! static CALLOUT: DriverCell<Callout> = DriverCell::new();
+//! static ENGINE: DriverCell<FilterEngine> = DriverCell::new();
+//!
+//! // In `DriverEntry`
+//! CALLOUT.init(Callout::register::<BlockAll>(device_object, CALLOUT_KEY)?)?;
+//!
+//! let engine = FilterEngine::open()?;
+//! engine.add_callout(CALLOUT_KEY, FWPM_LAYER_ALE_AUTH_CONNECT_V4, "Block all")?;
+//! engine.add_sublayer(SUBLAYER_KEY, "Sample sublayer", 0x100)?;
+//! engine.add_filter(&FilterDefinition {
+//!     name: "Block all outbound IPv4 connections",
+//!     layer: FWPM_LAYER_ALE_AUTH_CONNECT_V4,
+//!     sublayer: SUBLAYER_KEY,
+//!     callout: CALLOUT_KEY,
+//!     action: CalloutAction::Terminating,
+//!     weight: None,
+//!     conditions: &[],
+//!     context: 0,
+//! })?;
+//! ENGINE.init(engine)?;
+//! ```
+//!
+//! The [`FilterEngine`] opens a dynamic session, so every object added through
+//! it is removed from the filter engine when it is dropped. Dropping a
+//! [`Callout`] unregisters it. In driver unload, the [`FilterEngine`] must be
+//! dropped before the [`Callout`]s, so that no filters still reference them.
+//!
+//! Callouts have no per-registration context: [`CalloutHandler`]s are
+//! implemented with associated functions, and any state they need is kept in
+//! driver-global state (ex. a [`DriverCell`](crate::sync::DriverCell)).
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::{ptr, slice};
+
+use wdk_sys::{
+    wfp::{
+        FwpmCalloutAdd0,
+        FwpmEngineClose0,
+        FwpmEngineOpen0,
+        FwpmFilterAdd0,
+        FwpmSubLayerAdd0,
+        FwpsCalloutRegister0,
+        FwpsCalloutUnregisterById0,
+    },
+    FWPM_CALLOUT0,
+    FWPM_DISPLAY_DATA0,
+    FWPM_FILTER0,
+    FWPM_FILTER_CONDITION0,
+    FWPM_SESSION0,
+    FWPM_SUBLAYER0,
+    FWPS_CALLOUT0,
+    FWPS_CALLOUT_NOTIFY_TYPE,
+    FWPS_CALLOUT_NOTIFY_TYPE_::{
+        FWPS_CALLOUT_NOTIFY_ADD_FILTER,
+        FWPS_CALLOUT_NOTIFY_DELETE_FILTER,
+    },
+    FWPS_CLASSIFY_OUT0,
+    FWPS_FILTER0,
+    FWPS_INCOMING_METADATA_VALUES0,
+    FWPS_INCOMING_VALUES0,
+    FWP_ACTION_TYPE,
+    FWP_DATA_TYPE_::{
+        FWP_BYTE_ARRAY16_TYPE,
+        FWP_BYTE_BLOB_TYPE,
+        FWP_EMPTY,
+        FWP_UINT16,
+        FWP_UINT32,
+        FWP_UINT64,
+        FWP_UINT8,
+    },
+    FWP_VALUE0,
+    GUID,
+    HANDLE,
+    NTSTATUS,
+    PASSIVE_LEVEL,
+    PDEVICE_OBJECT,
+    PVOID,
+    STATUS_INVALID_PARAMETER,
+    STATUS_SUCCESS,
+    UINT32,
+    UINT64,
+};
+
+use crate::{nt_success, time::current_irql};
+
+/// `FWP_ACTION_FLAG_TERMINATING` in `fwptypes.h`
+const FWP_ACTION_FLAG_TERMINATING: FWP_ACTION_TYPE = 0x0000_1000;
+
+/// `FWP_ACTION_FLAG_NON_TERMINATING` in `fwptypes.h`
+const FWP_ACTION_FLAG_NON_TERMINATING: FWP_ACTION_TYPE = 0x0000_2000;
+
+/// `FWP_ACTION_FLAG_CALLOUT` in `fwptypes.h`
+const FWP_ACTION_FLAG_CALLOUT: FWP_ACTION_TYPE = 0x0000_4000;
+
+/// `FWPS_RIGHT_ACTION_WRITE` in `fwpstypes.h`
+const FWPS_RIGHT_ACTION_WRITE: UINT32 = 0x0000_0001;
+
+/// `FWPS_CLASSIFY_OUT_FLAG_ABSORB` in `fwpstypes.h`
+const FWPS_CLASSIFY_OUT_FLAG_ABSORB: UINT32 = 0x0000_0001;
+
+/// `FWPM_SESSION_FLAG_DYNAMIC` in `fwpmtypes.h`
+const FWPM_SESSION_FLAG_DYNAMIC: UINT32 = 0x0000_0001;
+
+/// `RPC_C_AUTHN_WINNT` in `rpcdce.h`
+const RPC_C_AUTHN_WINNT: UINT32 = 10;
+
+/// Callbacks of a WFP callout, called by the filter engine for the traffic
+/// matched by the filters that reference the callout
+pub trait CalloutHandler {
+    /// Processes the data matched by a filter, and decides what happens to it
+    /// by setting an [`Action`] through `classify_out`.
+    ///
+    /// This is called at `IRQL` <= `DISPATCH_LEVEL`, depending on the layer
+    /// that the data is classified at.
+    fn classify(parameters: &ClassifyParameters<'_>, classify_out: &mut ClassifyOut<'_>);
+
+    /// Called when a filter that references the callout is added to, or
+    /// deleted from, the filter engine. Returning an error when a filter is
+    /// added rejects the filter.
+    ///
+    /// This is called at `IRQL` = `PASSIVE_LEVEL`. The default implementation
+    /// accepts every filter.
+    ///
+    /// # Errors
+    ///
+    /// The [`NTSTATUS`] returned when a filter is added is reported as the
+    /// failure of adding it
+    fn notify(notify_type: NotifyType, filter: &FWPS_FILTER0) -> Result<(), NTSTATUS> {
+        let _ = (notify_type, filter);
+        Ok(())
+    }
+}
+
+/// Kind of change that [`CalloutHandler::notify`] is called for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyType {
+    /// A filter that references the callout is being added
+    AddFilter,
+    /// A filter that references the callout is being deleted
+    DeleteFilter,
+}
+
+/// Action that a callout decides for classified data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// The data is permitted, unless a filter with a higher priority blocks it
+    Permit,
+    /// The data is blocked. Filters with a lower priority cannot override
+    /// this.
+    Block,
+    /// The decision is left to filters with a lower priority
+    Continue,
+}
+
+impl Action {
+    const fn action_type(self) -> FWP_ACTION_TYPE {
+        match self {
+            // `FWP_ACTION_PERMIT`
+            Self::Permit => 0x0000_0002 | FWP_ACTION_FLAG_TERMINATING,
+            // `FWP_ACTION_BLOCK`
+            Self::Block => 0x0000_0001 | FWP_ACTION_FLAG_TERMINATING,
+            // `FWP_ACTION_CONTINUE`
+            Self::Continue => 0x0000_0006 | FWP_ACTION_FLAG_NON_TERMINATING,
+        }
+    }
+}
+
+/// Data passed to [`CalloutHandler::classify`]
+pub struct ClassifyParameters<'a> {
+    incoming_values: &'a FWPS_INCOMING_VALUES0,
+    metadata: &'a FWPS_INCOMING_METADATA_VALUES0,
+    layer_data: PVOID,
+    filter: &'a FWPS_FILTER0,
+    flow_context: UINT64,
+}
+
+impl<'a> ClassifyParameters<'a> {
+    /// Returns the run-time identifier of the layer that the data is
+    /// classified at (ex. `FWPS_LAYER_ALE_AUTH_CONNECT_V4`)
+    #[must_use]
+    pub const fn layer_id(&self) -> u16 {
+        self.incoming_values.layerId
+    }
+
+    /// Returns the value of the data field at `index`, or `None` if the layer
+    /// has no such field. Indices are defined per layer by the
+    /// `FWPS_FIELDS_*` enumerations (ex.
+    /// `FWPS_FIELD_ALE_AUTH_CONNECT_V4_IP_REMOTE_PORT`).
+    #[must_use]
+    pub fn value(&self, index: usize) -> Option<Value<'a>> {
+        let value_count = usize::try_from(self.incoming_values.valueCount).ok()?;
+        if index >= value_count {
+            return None;
+        }
+
+        // SAFETY: `incomingValue` points to `valueCount` values, and `index` was
+        // checked to be in bounds
+        let incoming_value = unsafe { self.incoming_values.incomingValue.add(index) };
+        // SAFETY: The incoming values remain valid for the duration of the
+        // classification
+        let incoming_value = unsafe { &*incoming_value };
+        Some(Value::from_raw(&incoming_value.value))
+    }
+
+    /// Returns the metadata of the classified data (ex. the process ID, or the
+    /// size of the transport header). Which fields are present depends on the
+    /// layer.
+    #[must_use]
+    pub const fn metadata(&self) -> &'a FWPS_INCOMING_METADATA_VALUES0 {
+        self.metadata
+    }
+
+    /// Returns the layer-specific data being classified (ex. a
+    /// `NET_BUFFER_LIST` at network and transport layers), or a null pointer
+    /// if the layer has none
+    #[must_use]
+    pub const fn layer_data(&self) -> PVOID {
+        self.layer_data
+    }
+
+    /// Returns the run-time identifier of the filter that matched the data
+    #[must_use]
+    pub const fn filter_id(&self) -> u64 {
+        self.filter.filterId
+    }
+
+    /// Returns the context of the filter that matched the data, which is the
+    /// [`FilterDefinition::context`] of filters added with
+    /// [`FilterEngine::add_filter`]
+    #[must_use]
+    pub const fn filter_context(&self) -> u64 {
+        self.filter.context
+    }
+
+    /// Returns the context associated with the data flow, or 0 if none has
+    /// been associated with it
+    #[must_use]
+    pub const fn flow_context(&self) -> u64 {
+        self.flow_context
+    }
+}
+
+/// Value of a data field passed to [`CalloutHandler::classify`]
+#[derive(Clone, Copy)]
+pub enum Value<'a> {
+    /// The field has no value
+    Empty,
+    /// `FWP_UINT8`
+    U8(u8),
+    /// `FWP_UINT16` (ex. ports)
+    U16(u16),
+    /// `FWP_UINT32` (ex. IPv4 addresses, in host byte order)
+    U32(u32),
+    /// `FWP_UINT64`
+    U64(u64),
+    /// `FWP_BYTE_ARRAY16_TYPE` (ex. IPv6 addresses)
+    ByteArray16(&'a [u8; 16]),
+    /// `FWP_BYTE_BLOB_TYPE` (ex. application IDs)
+    ByteBlob(&'a [u8]),
+    /// Any other type of value, which must be read from the raw `FWP_VALUE0`
+    Other(&'a FWP_VALUE0),
+}
+
+impl<'a> Value<'a> {
+    fn from_raw(raw: &'a FWP_VALUE0) -> Self {
+        let value = raw.__bindgen_anon_1;
+        match raw.type_ {
+            FWP_EMPTY => Self::Empty,
+            // SAFETY: `uint8` is the active member of the union for `FWP_UINT8` values
+            FWP_UINT8 => Self::U8(unsafe { value.uint8 }),
+            // SAFETY: `uint16` is the active member of the union for `FWP_UINT16` values
+            FWP_UINT16 => Self::U16(unsafe { value.uint16 }),
+            // SAFETY: `uint32` is the active member of the union for `FWP_UINT32` values
+            FWP_UINT32 => Self::U32(unsafe { value.uint32 }),
+            FWP_UINT64 => {
+                // SAFETY: `uint64` is the active member of the union for `FWP_UINT64` values
+                let uint64 = unsafe { value.uint64 };
+                // SAFETY: The filter engine passes 64-bit values by pointer, which remains
+                // valid for the duration of the classification
+                Self::U64(unsafe { *uint64 })
+            }
+            FWP_BYTE_ARRAY16_TYPE => {
+                // SAFETY: `byteArray16` is the active member of the union for
+                // `FWP_BYTE_ARRAY16_TYPE` values
+                let byte_array16 = unsafe { value.byteArray16 };
+                // SAFETY: The filter engine passes byte arrays by pointer, which remains
+                // valid for the duration of the classification
+                Self::ByteArray16(unsafe { &(*byte_array16).byteArray16 })
+            }
+            FWP_BYTE_BLOB_TYPE => {
+                // SAFETY: `byteBlob` is the active member of the union for
+                // `FWP_BYTE_BLOB_TYPE` values
+                let byte_blob = unsafe { value.byteBlob };
+                // SAFETY: The filter engine passes byte blobs by pointer, which remains valid
+                // for the duration of the classification
+                let byte_blob = unsafe { &*byte_blob };
+                let Ok(size) = usize::try_from(byte_blob.size) else {
+                    return Self::Other(raw);
+                };
+                if size == 0 {
+                    return Self::ByteBlob(&[]);
+                }
+                // SAFETY: `data` points to `size` bytes, which remain valid for the duration
+                // of the classification
+                Self::ByteBlob(unsafe { slice::from_raw_parts(byte_blob.data, size) })
+            }
+            _ => Self::Other(raw),
+        }
+    }
+}
+
+/// Result of a classification, written by [`CalloutHandler::classify`]
+pub struct ClassifyOut<'a> {
+    raw: &'a mut FWPS_CLASSIFY_OUT0,
+}
+
+impl ClassifyOut<'_> {
+    /// Returns whether the callout is allowed to set the action. This is not
+    /// the case when a filter with a higher priority has already decided the
+    /// action, and did not allow it to be overridden.
+    #[must_use]
+    pub const fn can_set_action(&self) -> bool {
+        self.raw.rights & FWPS_RIGHT_ACTION_WRITE != 0
+    }
+
+    /// Sets the action for the classified data. This does nothing if
+    /// [`ClassifyOut::can_set_action`] is `false`.
+    ///
+    /// Blocking the data also prevents filters with a lower priority from
+    /// overriding the action.
+    pub fn set_action(&mut self, action: Action) {
+        if !self.can_set_action() {
+            return;
+        }
+
+        self.raw.actionType = action.action_type();
+        if action == Action::Block {
+            self.raw.rights &= !FWPS_RIGHT_ACTION_WRITE;
+        }
+    }
+
+    /// Silently drops the classified data, without reporting the block to the
+    /// rest of the network stack. This is typically combined with
+    /// [`Action::Block`] by callouts that reinject modified data.
+    pub const fn absorb(&mut self) {
+        self.raw.flags |= FWPS_CLASSIFY_OUT_FLAG_ABSORB;
+    }
+}
+
+/// Callout registered with the filter engine by [`Callout::register`]
+///
+/// Dropping the [`Callout`] unregisters it. This must happen at `IRQL` =
+/// `PASSIVE_LEVEL`, after all filters that reference the callout have been
+/// deleted (ex. by dropping the [`FilterEngine`] that added them).
+pub struct Callout {
+    callout_id: UINT32,
+}
+
+impl Callout {
+    /// Registers a callout identified by `callout_key`, whose callbacks are
+    /// implemented by `H`. `device_object` is the device object of the
+    /// callout driver.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`. In debug builds, this
+    /// is asserted at runtime.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if registration fails (ex. if a callout is already registered with `callout_key`). The error variant will contain a [`NTSTATUS`] of the failure. Full error documentation is available in the [FwpsCalloutRegister0 Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/fwpsk/nf-fwpsk-fwpscalloutregister0#return-value)
+    pub fn register<H: CalloutHandler>(
+        device_object: PDEVICE_OBJECT,
+        callout_key: GUID,
+    ) -> Result<Self, NTSTATUS> {
+        debug_assert_passive_level();
+
+        let callout = FWPS_CALLOUT0 {
+            calloutKey: callout_key,
+            flags: 0,
+            classifyFn: Some(classify_fn::<H>),
+            notifyFn: Some(notify_fn::<H>),
+            flowDeleteFn: None,
+        };
+        let mut callout_id = 0;
+
+        // SAFETY: `device_object` is the device object of the driver, and `callout`
+        // points to callbacks that remain valid until the callout is unregistered
+        let nt_status = unsafe {
+            FwpsCalloutRegister0(
+                device_object.cast(),
+                ptr::addr_of!(callout),
+                ptr::addr_of_mut!(callout_id),
+            )
+        };
+        if !nt_success(nt_status) {
+            return Err(nt_status);
+        }
+
+        Ok(Self { callout_id })
+    }
+
+    /// Returns the run-time identifier assigned to the callout by the filter
+    /// engine
+    #[must_use]
+    pub const fn id(&self) -> u32 {
+        self.callout_id
+    }
+}
+
+impl Drop for Callout {
+    fn drop(&mut self) {
+        debug_assert_passive_level();
+
+        // Unregistering only fails if the callout is still in use, in which case there
+        // is nothing left to do but leave it registered
+        // SAFETY: `callout_id` was registered in `register`, and is only unregistered
+        // here
+        let _ = unsafe { FwpsCalloutUnregisterById0(self.callout_id) };
+    }
+}
+
+/// Type of the action of a filter added with [`FilterEngine::add_filter`],
+/// which determines how the decision of its callout is used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalloutAction {
+    /// The callout always returns [`Action::Permit`] or [`Action::Block`]
+    Terminating,
+    /// The callout only inspects data, and always returns
+    /// [`Action::Continue`]
+    Inspection,
+    /// The callout can return any [`Action`]
+    Unknown,
+}
+
+impl CalloutAction {
+    const fn action_type(self) -> FWP_ACTION_TYPE {
+        match self {
+            // `FWP_ACTION_CALLOUT_TERMINATING`
+            Self::Terminating => {
+                0x0000_0003 | FWP_ACTION_FLAG_CALLOUT | FWP_ACTION_FLAG_TERMINATING
+            }
+            // `FWP_ACTION_CALLOUT_INSPECTION`
+            Self::Inspection => {
+                0x0000_0004 | FWP_ACTION_FLAG_CALLOUT | FWP_ACTION_FLAG_NON_TERMINATING
+            }
+            // `FWP_ACTION_CALLOUT_UNKNOWN`
+            Self::Unknown => 0x0000_0005 | FWP_ACTION_FLAG_CALLOUT,
+        }
+    }
+}
+
+/// Filter added with [`FilterEngine::add_filter`], which invokes a callout for
+/// the data that matches its conditions
+pub struct FilterDefinition<'a> {
+    /// Name of the filter, shown by WFP diagnostic tools
+    pub name: &'a str,
+    /// Key of the layer that the filter is added to (ex.
+    /// `FWPM_LAYER_ALE_AUTH_CONNECT_V4`)
+    pub layer: GUID,
+    /// Key of the sublayer that the filter is added to
+    pub sublayer: GUID,
+    /// Key of the callout that the filter invokes, which must have been added
+    /// with [`FilterEngine::add_callout`]
+    pub callout: GUID,
+    /// Type of the filter's action
+    pub action: CalloutAction,
+    /// Weight of the filter within its sublayer, from 0 to 15, or `None` to
+    /// let the filter engine assign a weight from its conditions
+    pub weight: Option<u8>,
+    /// Conditions that data must match to invoke the callout. An empty slice
+    /// matches all data at the layer.
+    pub conditions: &'a [FWPM_FILTER_CONDITION0],
+    /// Context passed to the callout as
+    /// [`ClassifyParameters::filter_context`]
+    pub context: u64,
+}
+
+/// Session with the filter engine, used to add the management objects that
+/// route traffic to callouts
+///
+/// The session is dynamic: dropping the [`FilterEngine`] closes the session,
+/// which removes every callout, sublayer and filter added through it. This
+/// must happen at `IRQL` = `PASSIVE_LEVEL`.
+pub struct FilterEngine {
+    handle: HANDLE,
+}
+
+// SAFETY: Filter engine handles can be used from any thread
+unsafe impl Send for FilterEngine {}
+
+// SAFETY: The filter engine serializes concurrent calls made with the same
+// handle
+unsafe impl Sync for FilterEngine {}
+
+impl FilterEngine {
+    /// Opens a dynamic session with the filter engine
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`. In debug builds, this
+    /// is asserted at runtime.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the session fails to be opened (ex. if the Base Filtering Engine service is not running yet). The error variant will contain a [`NTSTATUS`] of the failure. Full error documentation is available in the [FwpmEngineOpen0 Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/fwpmk/nf-fwpmk-fwpmengineopen0#return-value)
+    pub fn open() -> Result<Self, NTSTATUS> {
+        debug_assert_passive_level();
+
+        let session = FWPM_SESSION0 {
+            flags: FWPM_SESSION_FLAG_DYNAMIC,
+            ..Default::default()
+        };
+        let mut handle = ptr::null_mut();
+
+        // SAFETY: A null server name opens a session with the local filter engine, and
+        // `session` and `handle` are valid for the duration of the call
+        let nt_status = unsafe {
+            FwpmEngineOpen0(
+                ptr::null(),
+                RPC_C_AUTHN_WINNT,
+                ptr::null_mut(),
+                ptr::addr_of!(session),
+                ptr::addr_of_mut!(handle),
+            )
+        };
+        if !nt_success(nt_status) {
+            return Err(nt_status);
+        }
+
+        Ok(Self { handle })
+    }
+
+    /// Adds the management object of the callout identified by `callout_key`,
+    /// so that filters at `applicable_layer` can invoke it. The callout does
+    /// not need to be registered with [`Callout::register`] yet.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`. In debug builds, this
+    /// is asserted at runtime.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the callout fails to be added. The error variant will contain a [`NTSTATUS`] of the failure. Full error documentation is available in the [FwpmCalloutAdd0 Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/fwpmk/nf-fwpmk-fwpmcalloutadd0#return-value)
+    pub fn add_callout(
+        &self,
+        callout_key: GUID,
+        applicable_layer: GUID,
+        name: &str,
+    ) -> Result<(), NTSTATUS> {
+        debug_assert_passive_level();
+
+        let mut name = wide_string(name);
+        let callout = FWPM_CALLOUT0 {
+            calloutKey: callout_key,
+            displayData: display_data(&mut name),
+            applicableLayer: applicable_layer,
+            ..Default::default()
+        };
+
+        // SAFETY: `handle` is an open session, and `callout` and the name it points to
+        // are valid for the duration of the call
+        let nt_status = unsafe {
+            FwpmCalloutAdd0(
+                self.handle,
+                ptr::addr_of!(callout),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        nt_success(nt_status).then_some(()).ok_or(nt_status)
+    }
+
+    /// Adds a sublayer identified by `sublayer_key`, which groups the filters
+    /// of the driver. Sublayers with a higher `weight` are evaluated first.
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`. In debug builds, this
+    /// is asserted at runtime.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the sublayer fails to be added. The error variant will contain a [`NTSTATUS`] of the failure. Full error documentation is available in the [FwpmSubLayerAdd0 Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/fwpmk/nf-fwpmk-fwpmsublayeradd0#return-value)
+    pub fn add_sublayer(
+        &self,
+        sublayer_key: GUID,
+        name: &str,
+        weight: u16,
+    ) -> Result<(), NTSTATUS> {
+        debug_assert_passive_level();
+
+        let mut name = wide_string(name);
+        let sublayer = FWPM_SUBLAYER0 {
+            subLayerKey: sublayer_key,
+            displayData: display_data(&mut name),
+            weight,
+            ..Default::default()
+        };
+
+        // SAFETY: `handle` is an open session, and `sublayer` and the name it points to
+        // are valid for the duration of the call
+        let nt_status =
+            unsafe { FwpmSubLayerAdd0(self.handle, ptr::addr_of!(sublayer), ptr::null_mut()) };
+        nt_success(nt_status).then_some(()).ok_or(nt_status)
+    }
+
+    /// Adds a filter that invokes a callout, and returns its run-time
+    /// identifier
+    ///
+    /// This must be called at `IRQL` = `PASSIVE_LEVEL`. In debug builds, this
+    /// is asserted at runtime.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the filter fails to be added (ex. if its callout rejects it in [`CalloutHandler::notify`]), or `STATUS_INVALID_PARAMETER` if its weight is greater than 15 or it has more than `u32::MAX` conditions. The error variant will contain a [`NTSTATUS`] of the failure. Full error documentation is available in the [FwpmFilterAdd0 Documentation](https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/fwpmk/nf-fwpmk-fwpmfilteradd0#return-value)
+    pub fn add_filter(&self, definition: &FilterDefinition<'_>) -> Result<u64, NTSTATUS> {
+        debug_assert_passive_level();
+
+        let num_filter_conditions =
+            UINT32::try_from(definition.conditions.len()).map_err(|_| STATUS_INVALID_PARAMETER)?;
+        let mut name = wide_string(definition.name);
+        let mut filter = FWPM_FILTER0 {
+            displayData: display_data(&mut name),
+            layerKey: definition.layer,
+            subLayerKey: definition.sublayer,
+            numFilterConditions: num_filter_conditions,
+            filterCondition: definition.conditions.as_ptr().cast_mut(),
+            ..Default::default()
+        };
+
+        match definition.weight {
+            Some(weight) if weight > 15 => return Err(STATUS_INVALID_PARAMETER),
+            Some(weight) => {
+                filter.weight.type_ = FWP_UINT8;
+                filter.weight.__bindgen_anon_1.uint8 = weight;
+            }
+            None => filter.weight.type_ = FWP_EMPTY,
+        }
+        filter.action.type_ = definition.action.action_type();
+        filter.action.__bindgen_anon_1.calloutKey = definition.callout;
+        filter.__bindgen_anon_1.rawContext = definition.context;
+
+        let mut filter_id = 0;
+        // SAFETY: `handle` is an open session, and `filter`, along with the name and
+        // conditions it points to, is valid for the duration of the call. The filter
+        // engine does not modify the conditions.
+        let nt_status = unsafe {
+            FwpmFilterAdd0(
+                self.handle,
+                ptr::addr_of!(filter),
+                ptr::null_mut(),
+                ptr::addr_of_mut!(filter_id),
+            )
+        };
+        if !nt_success(nt_status) {
+            return Err(nt_status);
+        }
+
+        Ok(filter_id)
+    }
+}
+
+impl Drop for FilterEngine {
+    fn drop(&mut self) {
+        debug_assert_passive_level();
+
+        // SAFETY: `handle` was opened in `open`, and is not used after it is closed
+        let _ = unsafe { FwpmEngineClose0(self.handle) };
+    }
+}
+
+/// `classifyFn` of callouts registered by [`Callout::register`]
+unsafe extern "C" fn classify_fn<H: CalloutHandler>(
+    in_fixed_values: *const FWPS_INCOMING_VALUES0,
+    in_meta_values: *const FWPS_INCOMING_METADATA_VALUES0,
+    layer_data: PVOID,
+    filter: *const FWPS_FILTER0,
+    flow_context: UINT64,
+    classify_out: *mut FWPS_CLASSIFY_OUT0,
+) {
+    let parameters = ClassifyParameters {
+        // SAFETY: The filter engine passes valid incoming values, which remain valid
+        // for the duration of the classification
+        incoming_values: unsafe { &*in_fixed_values },
+        // SAFETY: The filter engine passes valid metadata, which remains valid for the
+        // duration of the classification
+        metadata: unsafe { &*in_meta_values },
+        layer_data,
+        // SAFETY: The filter engine passes the matching filter, which remains valid
+        // for the duration of the classification
+        filter: unsafe { &*filter },
+        flow_context,
+    };
+    let mut classify_out = ClassifyOut {
+        // SAFETY: The filter engine passes a valid classification result, which is
+        // exclusively owned by the callout for the duration of the classification
+        raw: unsafe { &mut *classify_out },
+    };
+
+    H::classify(&parameters, &mut classify_out);
+}
+
+/// `notifyFn` of callouts registered by [`Callout::register`]
+unsafe extern "C" fn notify_fn<H: CalloutHandler>(
+    notify_type: FWPS_CALLOUT_NOTIFY_TYPE,
+    _filter_key: *const GUID,
+    filter: *mut FWPS_FILTER0,
+) -> NTSTATUS {
+    let notify_type = match notify_type {
+        FWPS_CALLOUT_NOTIFY_ADD_FILTER => NotifyType::AddFilter,
+        FWPS_CALLOUT_NOTIFY_DELETE_FILTER => NotifyType::DeleteFilter,
+        _ => return STATUS_SUCCESS,
+    };
+
+    // SAFETY: The filter engine passes the added or deleted filter, which remains
+    // valid for the duration of the notification
+    let filter = unsafe { &*filter };
+
+    match H::notify(notify_type, filter) {
+        Ok(()) => STATUS_SUCCESS,
+        Err(nt_status) => nt_status,
+    }
+}
+
+/// Converts `string` into a null-terminated UTF-16 string
+fn wide_string(string: &str) -> Vec<u16> {
+    string.encode_utf16().chain(core::iter::once(0)).collect()
+}
+
+/// Creates display data with `name` and no description. `name` must remain
+/// valid while the display data is used.
+const fn display_data(name: &mut [u16]) -> FWPM_DISPLAY_DATA0 {
+    FWPM_DISPLAY_DATA0 {
+        name: name.as_mut_ptr(),
+        description: ptr::null_mut(),
+    }
+}
+
+fn debug_assert_passive_level() {
+    debug_assert!(
+        current_irql() == PASSIVE_LEVEL,
+        "WFP callouts and filter engine sessions should only be managed at IRQL = PASSIVE_LEVEL"
+    );
+}
@@ -6,38 +6,31 @@
 //! This is a sample UMDF driver that demonstrates how to use the crates in
 //! windows-driver-rs to create a skeleton of a UMDF driver.
 
-use std::{ffi::CString, slice, string::String};
+use std::ffi::CString;
 
-use wdk::println;
+use wdk::{
+    println,
+    wdf::{DriverBuilder, UnicodeString},
+};
 use wdk_sys::{
     call_unsafe_wdf_function_binding,
     windows::OutputDebugStringA,
     NTSTATUS,
-    PCUNICODE_STRING,
-    PDRIVER_OBJECT,
-    ULONG,
-    UNICODE_STRING,
-    WCHAR,
     WDFDEVICE,
     WDFDEVICE_INIT,
     WDFDRIVER,
-    WDF_DRIVER_CONFIG,
     WDF_NO_HANDLE,
     WDF_NO_OBJECT_ATTRIBUTES,
 };
 
-/// `DriverEntry` function required by WDF
+wdk::driver_entry!(driver_init);
+
+/// Initialization of the driver, called from the `DriverEntry` generated by
+/// [`wdk::driver_entry!`]
 ///
 /// # Panics
 /// Can panic from unwraps of `CStrings` used internally
-///
-/// # Safety
-/// Function is unsafe since it dereferences raw pointers passed to it from WDF
-#[export_name = "DriverEntry"] // WDF expects a symbol with the name DriverEntry
-pub unsafe extern "system" fn driver_entry(
-    driver: PDRIVER_OBJECT,
-    registry_path: PCUNICODE_STRING,
-) -> NTSTATUS {
+fn driver_init(registry_path: UnicodeString) -> DriverBuilder {
     // This is an example of directly using OutputDebugStringA binding to print
     let string = CString::new("Hello World!\n").unwrap();
 
@@ -47,94 +40,15 @@
         OutputDebugStringA(string.as_ptr());
     }
 
-    let mut driver_config = {
-        let wdf_driver_config_size: ULONG;
-
-        // clippy::cast_possible_truncation cannot currently check compile-time constants: https://github.com/rust-lang/rust-clippy/issues/9613
-        #[allow(clippy::cast_possible_truncation)]
-        {
-            const WDF_DRIVER_CONFIG_SIZE: usize = core::mem::size_of::<WDF_DRIVER_CONFIG>();
-
-            // Manually assert there is not truncation since clippy doesn't work for
-            // compile-time constants
-            const { assert!(WDF_DRIVER_CONFIG_SIZE <= ULONG::MAX as usize) }
-
-            wdf_driver_config_size = WDF_DRIVER_CONFIG_SIZE as ULONG;
-        }
-
-        WDF_DRIVER_CONFIG {
-            Size: wdf_driver_config_size,
-            EvtDriverDeviceAdd: Some(evt_driver_device_add),
-            EvtDriverUnload: Some(evt_driver_unload),
-            ..WDF_DRIVER_CONFIG::default()
-        }
-    };
-
-    let driver_attributes = WDF_NO_OBJECT_ATTRIBUTES;
-    let driver_handle_output = WDF_NO_HANDLE.cast::<WDFDRIVER>();
-
-    let wdf_driver_create_ntstatus;
-    // SAFETY: This is safe because:
-    //         1. `driver` is provided by `DriverEntry` and is never null
-    //         2. `registry_path` is provided by `DriverEntry` and is never null
-    //         3. `driver_attributes` is allowed to be null
-    //         4. `driver_config` is a valid pointer to a valid `WDF_DRIVER_CONFIG`
-    //         5. `driver_handle_output` is expected to be null
-    unsafe {
-        wdf_driver_create_ntstatus = call_unsafe_wdf_function_binding!(
-            WdfDriverCreate,
-            driver,
-            registry_path,
-            driver_attributes,
-            &mut driver_config,
-            driver_handle_output,
-        );
-    }
-
-    // Translate UTF16 string to rust string
-    let registry_path: UNICODE_STRING =
-        // SAFETY: This dereference is safe since `registry_path` is:
-        //         * provided by `DriverEntry` and is never null
-        //         * a valid pointer to a `UNICODE_STRING`
-        unsafe { *registry_path };
-    let number_of_slice_elements = {
-        registry_path.Length as usize
-            / core::mem::size_of_val(
-                // SAFETY: This dereference is safe since `Buffer` is:
-                //         * provided by `DriverEntry` and is never null
-                //         * a valid pointer to `Buffer`'s type
-                &unsafe { *registry_path.Buffer },
-            )
-    };
-
-    let registry_path = String::from_utf16_lossy(
-        // SAFETY: This is safe because:
-        //         1. `registry_path.Buffer` is valid for reads for `number_of_slice_elements` *
-        //            `core::mem::size_of::<WCHAR>()` bytes, and is guaranteed to be aligned and it
-        //            must be properly aligned.
-        //         2. `registry_path.Buffer` points to `number_of_slice_elements` consecutive
-        //            properly initialized values of type `WCHAR`.
-        //         3. Windows does not mutate the memory referenced by the returned slice for for
-        //            its entire lifetime.
-        //         4. The total size, `number_of_slice_elements` * `core::mem::size_of::<WCHAR>()`,
-        //            of the slice must be no larger than `isize::MAX`. This is proven by the below
-        //            `debug_assert!`.
-        unsafe {
-            debug_assert!(isize::try_from(
-                number_of_slice_elements * core::mem::size_of::<WCHAR>()
-            )
-            .is_ok());
-            slice::from_raw_parts(registry_path.Buffer, number_of_slice_elements)
-        },
-    );
-
     // It is much better to use the println macro that has an implementation in
     // wdk::print.rs to call OutputDebugStringA. The println! implementation in
     // wdk::print.rs has the same features as the one in std (ex. format args
     // support).
     println!("UMDF Driver Entry Complete! Driver Registry Parameter Key: {registry_path}");
 
-    wdf_driver_create_ntstatus
+    DriverBuilder::new()
+        .evt_device_add(evt_driver_device_add)
+        .evt_driver_unload(evt_driver_unload)
 }
 
 extern "C" fn evt_driver_device_add(
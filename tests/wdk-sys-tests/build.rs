@@ -0,0 +1,257 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Build script for the `wdk-sys-tests` crate.
+//!
+//! When the `msvc-layout` feature is enabled, this compiles a C program that
+//! prints the size, alignment and field offsets of [`MSVC_LAYOUT_STRUCTS`], as
+//! computed by MSVC from the WDK headers, and generates `msvc_layout.rs` in
+//! `OUT_DIR`, which pairs each of them with the layout of the Rust bindings.
+
+#[cfg(feature = "msvc-layout")]
+mod msvc_layout {
+    use std::{
+        env,
+        fmt::Write,
+        path::{Path, PathBuf},
+        process::Command,
+    };
+
+    use wdk_build::{ApiSubset, Config};
+
+    /// Structs whose layout in the bindings is compared against the layout
+    /// computed by MSVC, and the fields whose offsets are compared
+    pub const MSVC_LAYOUT_STRUCTS: &[(&str, &[&str])] = &[
+        ("GUID", &["Data1", "Data2", "Data3", "Data4"]),
+        ("LIST_ENTRY", &["Flink", "Blink"]),
+        ("UNICODE_STRING", &["Length", "MaximumLength", "Buffer"]),
+        (
+            "MDL",
+            &[
+                "Next",
+                "Size",
+                "MdlFlags",
+                "MappedSystemVa",
+                "StartVa",
+                "ByteCount",
+                "ByteOffset",
+            ],
+        ),
+        ("KEVENT", &["Header"]),
+        (
+            "IRP",
+            &[
+                "MdlAddress",
+                "Flags",
+                "AssociatedIrp",
+                "IoStatus",
+                "RequestorMode",
+                "Cancel",
+                "Tail",
+            ],
+        ),
+        (
+            "IO_STACK_LOCATION",
+            &[
+                "MajorFunction",
+                "MinorFunction",
+                "Flags",
+                "Control",
+                "Parameters",
+                "DeviceObject",
+                "FileObject",
+                "CompletionRoutine",
+                "Context",
+            ],
+        ),
+        (
+            "DEVICE_OBJECT",
+            &[
+                "DriverObject",
+                "NextDevice",
+                "CurrentIrp",
+                "Flags",
+                "DeviceExtension",
+                "DeviceType",
+                "StackSize",
+            ],
+        ),
+        (
+            "DRIVER_OBJECT",
+            &[
+                "DeviceObject",
+                "DriverExtension",
+                "DriverInit",
+                "DriverUnload",
+                "MajorFunction",
+            ],
+        ),
+        (
+            "CM_PARTIAL_RESOURCE_DESCRIPTOR",
+            &["Type", "ShareDisposition", "Flags", "u"],
+        ),
+        (
+            "IO_RESOURCE_DESCRIPTOR",
+            &["Option", "Type", "ShareDisposition", "Flags", "u"],
+        ),
+    ];
+
+    /// A layout property of a struct, as a Rust expression evaluated against
+    /// the bindings, and a C expression evaluated by MSVC
+    struct LayoutProperty {
+        description: String,
+        rust_expression: String,
+        c_expression: String,
+    }
+
+    fn layout_properties() -> Vec<LayoutProperty> {
+        MSVC_LAYOUT_STRUCTS
+            .iter()
+            .flat_map(|(type_name, field_names)| {
+                [
+                    LayoutProperty {
+                        description: format!("size of {type_name}"),
+                        rust_expression: format!("core::mem::size_of::<wdk_sys::{type_name}>()"),
+                        c_expression: format!("sizeof({type_name})"),
+                    },
+                    LayoutProperty {
+                        description: format!("alignment of {type_name}"),
+                        rust_expression: format!("core::mem::align_of::<wdk_sys::{type_name}>()"),
+                        c_expression: format!("__alignof({type_name})"),
+                    },
+                ]
+                .into_iter()
+                .chain(field_names.iter().map(move |field_name| LayoutProperty {
+                    description: format!("offset of {type_name}::{field_name}"),
+                    rust_expression: format!(
+                        "core::mem::offset_of!(wdk_sys::{type_name}, {field_name})"
+                    ),
+                    c_expression: format!("FIELD_OFFSET({type_name}, {field_name})"),
+                }))
+            })
+            .collect()
+    }
+
+    /// Compiles and runs a C program that prints the value of each of the C
+    /// expressions of `layout_properties`, and returns the printed values
+    fn compute_msvc_layout(
+        config: &Config,
+        layout_properties: &[LayoutProperty],
+        out_path: &Path,
+    ) -> Result<Vec<usize>, Box<dyn std::error::Error>> {
+        let target = env::var("TARGET")?;
+        if target != env::var("HOST")? {
+            return Err(format!(
+                "the msvc-layout feature requires the target ({target}) to match the host, since \
+                 the layout is computed by running a program built for the target"
+            )
+            .into());
+        }
+
+        // Writing to a `String` cannot fail
+        let mut c_source = config.bindgen_header_contents([ApiSubset::Base]);
+        let _ = writeln!(c_source, "int __cdecl printf(const char *format, ...);");
+        let _ = writeln!(c_source, "int __cdecl main(void) {{");
+        for LayoutProperty { c_expression, .. } in layout_properties {
+            let _ = writeln!(
+                c_source,
+                "    printf(\"%llu\\n\", (unsigned long long)({c_expression}));"
+            );
+        }
+        let _ = writeln!(c_source, "    return 0;\n}}");
+
+        let c_source_path = out_path.join("msvc-layout.c");
+        std::fs::write(&c_source_path, c_source)?;
+
+        let mut cc_builder = cc::Build::new();
+        for (key, value) in config.preprocessor_definitions() {
+            cc_builder.define(&key, value.as_deref());
+        }
+        let compiler = cc_builder.includes(config.include_paths()?).get_compiler();
+        if !compiler.is_like_msvc() {
+            return Err("the msvc-layout feature requires the MSVC compiler".into());
+        }
+
+        let executable_path = out_path.join("msvc-layout.exe");
+        let output = compiler
+            .to_command()
+            .arg(&c_source_path)
+            .arg(format!("/Fo{}\\", out_path.display()))
+            .arg(format!("/Fe{}", executable_path.display()))
+            .output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "failed to compile {}:\n{}",
+                c_source_path.display(),
+                String::from_utf8_lossy(&output.stdout)
+            )
+            .into());
+        }
+
+        let output = Command::new(&executable_path).output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "{} failed with {}",
+                executable_path.display(),
+                output.status
+            )
+            .into());
+        }
+        let values = String::from_utf8(output.stdout)?
+            .lines()
+            .map(str::parse)
+            .collect::<Result<Vec<usize>, _>>()?;
+        if values.len() != layout_properties.len() {
+            return Err(format!(
+                "{} printed {} values instead of {}",
+                executable_path.display(),
+                values.len(),
+                layout_properties.len()
+            )
+            .into());
+        }
+        Ok(values)
+    }
+
+    /// Generates `msvc_layout.rs` in `OUT_DIR`, which defines `MSVC_LAYOUT`:
+    /// the description of each layout property, its value in the bindings, and
+    /// its value computed by MSVC
+    pub fn generate() -> Result<(), Box<dyn std::error::Error>> {
+        let out_path = PathBuf::from(env::var("OUT_DIR")?);
+        let config = Config::from_env_auto()?;
+
+        let layout_properties = layout_properties();
+        let msvc_values = compute_msvc_layout(&config, &layout_properties, &out_path)?;
+
+        // Writing to a `String` cannot fail
+        let mut msvc_layout_rs =
+            String::from("pub const MSVC_LAYOUT: &[(&str, usize, usize)] = &[\n");
+        for (
+            LayoutProperty {
+                description,
+                rust_expression,
+                ..
+            },
+            msvc_value,
+        ) in layout_properties.iter().zip(msvc_values)
+        {
+            let _ = writeln!(
+                msvc_layout_rs,
+                "    (\"{description}\", {rust_expression}, {msvc_value}),"
+            );
+        }
+        msvc_layout_rs.push_str("];\n");
+
+        std::fs::write(out_path.join("msvc_layout.rs"), msvc_layout_rs)?;
+        Ok(())
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo::rerun-if-changed=build.rs");
+
+    #[cfg(feature = "msvc-layout")]
+    msvc_layout::generate()?;
+
+    Ok(())
+}
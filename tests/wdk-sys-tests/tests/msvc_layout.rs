@@ -0,0 +1,28 @@
+// Copyright (c) Microsoft Corporation
+// License: MIT OR Apache-2.0
+
+//! Compares the layout of structs in the bindings generated by `wdk-sys`
+//! against the layout computed by MSVC from the WDK headers, to catch ABI
+//! drift across WDK and `bindgen` releases. This requires the `msvc-layout`
+//! feature, since computing the layout requires the full WDK and MSVC.
+
+#![cfg(feature = "msvc-layout")]
+
+include!(concat!(env!("OUT_DIR"), "/msvc_layout.rs"));
+
+#[test]
+fn bindings_layout_matches_msvc() {
+    let mismatches = MSVC_LAYOUT
+        .iter()
+        .filter(|(_, rust_value, msvc_value)| rust_value != msvc_value)
+        .map(|(description, rust_value, msvc_value)| {
+            format!("{description}: {rust_value} in the bindings, {msvc_value} with MSVC")
+        })
+        .collect::<Vec<_>>();
+
+    assert!(
+        mismatches.is_empty(),
+        "the layout of the bindings does not match MSVC:\n{}",
+        mismatches.join("\n")
+    );
+}